@@ -0,0 +1,52 @@
+//! Payment request spec that the merchant and payer examples exchange as json
+
+// every example uses only a part of the spec
+#![allow(dead_code)]
+
+use moksha_core::primitives::CurrencyUnit;
+use moksha_wallet::{localstore::sqlite::SqliteLocalStore, wallet::Wallet};
+use serde::{Deserialize, Serialize};
+use tempfile::TempDir;
+
+/// Issued by the merchant, fulfilled by posting a [`Payment`] to `pay_url`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    pub id: String,
+    pub amount: u64,
+    pub unit: CurrencyUnit,
+    pub description: Option<String>,
+    /// mints the merchant accepts tokens from
+    pub mints: Vec<String>,
+    pub pay_url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewPaymentRequest {
+    pub amount: u64,
+    pub description: Option<String>,
+}
+
+/// Body of a payment, `token` is a serialized `cashuA` or `cashuB` token
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payment {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentStatus {
+    pub id: String,
+    pub fulfilled: bool,
+    /// amount the merchant redeemed at the mint
+    pub amount_received: u64,
+}
+
+/// Opens a wallet with a fresh seed in a temporary directory, which is deleted on drop
+pub async fn temp_wallet() -> anyhow::Result<(Wallet<SqliteLocalStore>, TempDir)> {
+    let dir = tempfile::tempdir()?;
+    let db_path = dir.path().join("wallet.db").to_string_lossy().to_string();
+    let wallet = Wallet::builder()
+        .with_localstore(SqliteLocalStore::with_path(db_path).await?)
+        .build()
+        .await?;
+    Ok((wallet, dir))
+}
@@ -0,0 +1,100 @@
+//! Payer side of a payment: reads a payment request of `merchant.rs`, funds a new wallet with a
+//! mint quote at the mint of the request and posts a token for the requested amount.
+//!
+//! The invoice of the mint quote is paid by the fake lightning backend of the merchant example:
+//!
+//! ```bash
+//! cargo run -p integrationtests --example payer -- --request http://127.0.0.1:7171/requests/<id>
+//! ```
+
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use moksha_core::primitives::{CurrencyUnit, PaymentMethod};
+use url::Url;
+
+use crate::common::{Payment, PaymentRequest, PaymentStatus};
+
+mod common;
+
+#[derive(Debug, Parser)]
+struct Opts {
+    /// Url of the payment request
+    #[clap(long)]
+    request: Url,
+
+    /// Seconds to wait until the invoice of the mint quote is paid
+    #[clap(long, default_value_t = 30)]
+    timeout: u64,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opts = Opts::parse();
+    let http = reqwest::Client::new();
+
+    let request: PaymentRequest = http
+        .get(opts.request.clone())
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if request.unit != CurrencyUnit::Sat {
+        bail!("only sat payment requests are supported");
+    }
+    let mint_url = Url::parse(
+        request
+            .mints
+            .first()
+            .context("the payment request accepts no mint")?,
+    )?;
+    println!(
+        "paying request {} for {} sats at {mint_url}",
+        request.id, request.amount
+    );
+
+    let (wallet, _dir) = common::temp_wallet().await?;
+    wallet.add_mint_keysets(&mint_url).await?;
+    let keyset = wallet.get_active_keyset(&mint_url).await?;
+
+    let quote = wallet
+        .create_quote_bolt11(&mint_url, request.amount)
+        .await?;
+    println!("waiting for invoice {}", quote.payment_request);
+    wallet
+        .mint_tokens_when_paid(
+            &keyset,
+            &PaymentMethod::Bolt11,
+            request.amount.into(),
+            quote.quote,
+            Duration::from_millis(500),
+            Duration::from_secs(opts.timeout),
+        )
+        .await?;
+
+    let token = wallet.send_tokens(&keyset, request.amount).await?;
+    let response = http
+        .post(&request.pay_url)
+        .json(&Payment {
+            token: token.serialize()?,
+        })
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        bail!(
+            "the merchant rejected the payment: {}",
+            response.text().await?
+        );
+    }
+    let status: PaymentStatus = response.json().await?;
+    if !status.fulfilled {
+        bail!("payment request {} is not fulfilled", status.id);
+    }
+    println!(
+        "fulfilled payment request {}, the merchant received {} sats",
+        status.id, status.amount_received
+    );
+    Ok(())
+}
@@ -30,7 +30,10 @@ pub async fn test_nutshell_compatibility() -> anyhow::Result<()> {
     let wallet_keyset = wallet_keysets.first().unwrap(); // FIXME
 
     // check if mint info is correct
-    let mint_info = wallet.get_mint_info(&mint_url).await?;
+    let mint_info = wallet
+        .get_mint_info(&mint_url)
+        .await?
+        .expect("mint info not found");
     assert_eq!(Some("nutshell".to_owned()), mint_info.name);
 
     // get initial balance
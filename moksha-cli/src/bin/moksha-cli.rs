@@ -1,23 +1,23 @@
 use clap::{Parser, Subcommand};
 use console::{style, Term};
-use dialoguer::{theme::ColorfulTheme, Confirm, Select};
+use dialoguer::{theme::ColorfulTheme, Confirm, Password, Select};
+use moksha_core::inspect::TokenReport;
+use moksha_core::page::PageCursor;
 use moksha_core::primitives::{
     CurrencyUnit, PaymentMethod, PostMeltBtcOnchainResponse, PostMintQuoteBolt11Response,
     PostMintQuoteBtcOnchainResponse,
 };
-use moksha_core::token::TokenV3;
 use moksha_wallet::client::CashuClient;
 
 use moksha_wallet::http::CrossPlatformHttpClient;
 
-use moksha_wallet::localstore::WalletKeysetFilter;
+use moksha_wallet::localstore::{HistoryFilter, WalletKeysetFilter};
 use mokshacli::cli::{self, choose_mint, get_mints_with_balance};
 use num_format::{Locale, ToFormattedString};
 use qrcode::render::unicode;
 use qrcode::QrCode;
 
 use std::path::PathBuf;
-use std::str::FromStr;
 
 use url::Url;
 
@@ -27,6 +27,16 @@ struct Opts {
     #[clap(short, long)]
     db_dir: Option<PathBuf>,
 
+    /// Open the wallet read-only, e.g. to show the balance of a wallet that was migrated by a
+    /// newer version. Only the balance command is supported.
+    #[clap(long)]
+    force_readonly: bool,
+
+    /// Allow mints with plain http urls on public hosts. The tokens of such mints can be stolen
+    /// by anyone on the network path.
+    #[clap(long)]
+    allow_insecure_mints: bool,
+
     #[clap(subcommand)]
     command: Command,
 }
@@ -43,7 +53,12 @@ enum Command {
     PayOnchain { address: String, amount: u64 },
 
     /// Send tokens
-    Send { amount: u64 },
+    Send {
+        amount: u64,
+        /// Note for the receiver that is added to the token
+        #[clap(long)]
+        memo: Option<String>,
+    },
 
     /// Receive tokens
     Receive { token: String },
@@ -56,6 +71,40 @@ enum Command {
 
     /// Add a new mint to the wallet
     AddMint { mint_url: Url },
+
+    /// Check the connection to the mints and show the response times of their endpoints
+    Doctor,
+
+    /// Show the contents of a token without redeeming it. Doesn't contact the mint.
+    Decode { token: String },
+
+    /// Compare the local wallet state with the state of the mints
+    Audit {
+        /// Remove spent proofs and deactivate unknown keysets
+        #[clap(long)]
+        fix: bool,
+    },
+
+    /// Show the mints, sends, receives and payments, the newest first
+    History {
+        /// Number of entries to show
+        #[clap(long, default_value_t = 20)]
+        limit: usize,
+        /// Show the entries after this cursor, it is printed below the previous entries
+        #[clap(long)]
+        before: Option<PageCursor>,
+    },
+
+    /// Write the proofs of the wallet to a password protected backup file
+    ExportBackup { file: PathBuf },
+
+    /// Add the proofs of a backup file to the wallet
+    ImportBackup {
+        file: PathBuf,
+        /// Import the backup even if it contains proofs of mints that are not in the wallet
+        #[clap(long)]
+        force: bool,
+    },
 }
 
 #[tokio::main]
@@ -63,6 +112,12 @@ async fn main() -> anyhow::Result<()> {
     use moksha_wallet::localstore::sqlite::SqliteLocalStore;
 
     let cli = Opts::parse();
+    let term = Term::stdout();
+
+    if let Command::Decode { token } = &cli.command {
+        term.write_str(&TokenReport::parse(token).to_string())?;
+        return Ok(());
+    }
 
     let db_path = match cli.db_dir {
         Some(dir) => {
@@ -73,12 +128,33 @@ async fn main() -> anyhow::Result<()> {
         None => moksha_wallet::config_path::db_path(),
     };
 
-    let term = Term::stdout();
-    let localstore = SqliteLocalStore::with_path(db_path.clone()).await?;
+    if cli.force_readonly && !matches!(cli.command, Command::Balance) {
+        term.write_line("Error: only the balance can be shown with --force-readonly")?;
+        std::process::exit(1);
+    }
+    let localstore = match cli.force_readonly {
+        true => SqliteLocalStore::with_path_readonly(db_path.clone()).await?,
+        false => SqliteLocalStore::with_path(db_path.clone())
+            .await
+            .map_err(|e| {
+                if matches!(
+                    e,
+                    moksha_wallet::error::MokshaWalletError::StoreRequiresNewerVersion { .. }
+                ) {
+                    term.write_line(&format!(
+                        "Error: {e}. Update moksha-cli or use --force-readonly to show the balance"
+                    ))
+                    .expect("write_line failed");
+                    std::process::exit(1);
+                }
+                e
+            })?,
+    };
     let client = CrossPlatformHttpClient::new();
     let wallet = moksha_wallet::wallet::WalletBuilder::default()
         .with_client(client)
         .with_localstore(localstore)
+        .with_allow_insecure_mints(cli.allow_insecure_mints)
         .build()
         .await
         .map_err(|e| {
@@ -92,12 +168,122 @@ async fn main() -> anyhow::Result<()> {
             }
             e
         })?;
+    if !cli.force_readonly {
+        wallet.take_scheduled_snapshot().await?;
+    }
 
     match cli.command {
         Command::AddMint { mint_url } => {
+            if !cli::warn_insecure_mint(&term, &mint_url, cli.allow_insecure_mints)? {
+                std::process::exit(1);
+            }
             wallet.add_mint_keysets(&mint_url).await?;
             term.write_line("Mint added successfully ")?;
         }
+        Command::Doctor => {
+            let mint_urls = wallet.get_mint_urls().await?;
+            if mint_urls.is_empty() {
+                term.write_line("No mints found.")?;
+            }
+            for mint_url in mint_urls {
+                term.write_line(&format!("Mint: {}", style(&mint_url).cyan()))?;
+                let start = std::time::Instant::now();
+                match wallet.get_mint_info(&mint_url).await {
+                    Ok(Some(info)) => term.write_line(&format!(
+                        " - reachable in {} ms ({} {})",
+                        start.elapsed().as_millis(),
+                        info.name.unwrap_or_default(),
+                        info.version.unwrap_or_default()
+                    ))?,
+                    Ok(None) => term.write_line(&format!(
+                        " - reachable in {} ms (no mint info)",
+                        start.elapsed().as_millis()
+                    ))?,
+                    Err(e) => {
+                        term.write_line(&format!(" - {}: {e}", style("unreachable").red()))?
+                    }
+                }
+                cli::show_endpoint_stats(&term, &wallet.endpoint_stats(&mint_url).await?)?;
+            }
+        }
+        // handled before the wallet is opened
+        Command::Decode { .. } => {}
+        Command::Audit { fix } => {
+            for mint_url in wallet.get_mint_urls().await? {
+                let report = wallet.audit_against_mint(&mint_url, fix).await?;
+                term.write_line(&format!("Mint: {}", style(&report.mint_url).cyan()))?;
+                term.write_line(&format!(
+                    " - confirmed balance: {} (sat)",
+                    style(report.confirmed_balance.to_formatted_string(&Locale::en)).cyan()
+                ))?;
+                if report.is_consistent() {
+                    term.write_line(" - no discrepancies found")?;
+                    continue;
+                }
+                term.write_line(&format!(
+                    " - spent proofs: {} ({} sat)",
+                    report.spent_proofs.len(),
+                    report.spent_proofs.total_amount()
+                ))?;
+                term.write_line(&format!(
+                    " - pending proofs: {} ({} sat)",
+                    report.pending_proofs.len(),
+                    report.pending_proofs.total_amount()
+                ))?;
+                for keyset in report.unknown_keysets {
+                    term.write_line(&format!(
+                        " - keyset unknown to mint: {}",
+                        keyset.keyset_id.to_string()
+                    ))?;
+                }
+                if fix {
+                    term.write_line(" - removed spent proofs and deactivated unknown keysets")?;
+                }
+            }
+        }
+        Command::History { limit, before } => {
+            let page = wallet
+                .get_history_page(&HistoryFilter::default(), before.as_ref(), limit)
+                .await?;
+            if page.entries.is_empty() {
+                term.write_line("No history found.")?;
+            }
+            for entry in page.entries {
+                term.write_line(&format!(
+                    " - {} {} {} (sat) {}{}",
+                    entry.created_at,
+                    entry.kind,
+                    style(entry.amount.to_formatted_string(&Locale::en)).cyan(),
+                    entry.mint_url,
+                    entry
+                        .memo
+                        .map(|memo| format!(" \"{memo}\""))
+                        .unwrap_or_default()
+                ))?;
+            }
+            if let Some(next) = page.next {
+                term.write_line(&format!("More entries: --before {next}"))?;
+            }
+        }
+        Command::ExportBackup { file } => {
+            let password = Password::with_theme(&ColorfulTheme::default())
+                .with_prompt("Backup password")
+                .with_confirmation("Repeat the password", "The passwords don't match")
+                .interact()?;
+            std::fs::write(&file, wallet.export_backup(&password).await?)?;
+            term.write_line(&format!("Backup written to {}", file.display()))?;
+        }
+        Command::ImportBackup { file, force } => {
+            let data = std::fs::read(&file)?;
+            let password = Password::with_theme(&ColorfulTheme::default())
+                .with_prompt("Backup password")
+                .interact()?;
+            let amount = wallet.import_backup(&data, &password, force).await?;
+            term.write_line(&format!(
+                "Imported {} (sat)",
+                amount.to_formatted_string(&Locale::en)
+            ))?;
+        }
         Command::Info => {
             let wallet_version = style(env!("CARGO_PKG_VERSION")).cyan();
             let mint_urls = wallet.get_mint_urls().await?;
@@ -111,12 +297,22 @@ async fn main() -> anyhow::Result<()> {
                 term.write_line("Mints:")?;
                 for mint in mint_urls {
                     term.write_line(&format!(" - {}", mint))?;
+                    match wallet.get_mint_info(&mint).await {
+                        Ok(Some(info)) => cli::show_mint_info(&term, &info)?,
+                        Ok(None) => term.write_line("   no mint info")?,
+                        Err(e) => {
+                            term.write_line(&format!("   {}: {e}", style("unreachable").red()))?
+                        }
+                    }
                 }
             }
         }
         // checks if the mints keyset is already in the wallet, if not it adds it and then imports the tokens
         Command::Receive { token } => {
-            let token: TokenV3 = TokenV3::from_str(&token)?;
+            let token = wallet.decode_token(&token)?;
+            if let Some(memo) = &token.memo {
+                term.write_line(&format!("Memo: {memo}"))?;
+            }
             let mint_urls = wallet.get_mint_urls().await?;
             let currency = match &token.currency_unit {
                 Some(currency) => currency,
@@ -131,6 +327,10 @@ async fn main() -> anyhow::Result<()> {
                 }
             };
 
+            if !cli::warn_insecure_mint(&term, &token_mint_url, cli.allow_insecure_mints)? {
+                std::process::exit(1);
+            }
+
             if !mint_urls.contains(&token_mint_url) {
                 let add_mint = Confirm::new()
                     .with_prompt(format!(
@@ -162,7 +362,7 @@ async fn main() -> anyhow::Result<()> {
             wallet.receive_tokens(wallet_keyset, &token).await?;
             cli::show_total_balance(&wallet).await?;
         }
-        Command::Send { amount } => {
+        Command::Send { amount, memo } => {
             let currency_unit = CurrencyUnit::Sat;
             let mint_url = choose_mint(&wallet, &currency_unit).await?;
 
@@ -179,7 +379,9 @@ async fn main() -> anyhow::Result<()> {
                 .expect("no active keyset found");
 
             term.write_line(&format!("Using tokens from mint: {mint_url}"))?;
-            let result = wallet.send_tokens(wallet_keyset, amount).await?;
+            let result = wallet
+                .send_tokens_with_memo(wallet_keyset, amount, memo)
+                .await?;
             let tokens: String = result.try_into()?;
 
             term.write_line(&format!("Result {amount} (sat):\n{tokens}"))?;
@@ -264,7 +466,10 @@ async fn main() -> anyhow::Result<()> {
 
             let info = wallet.get_mint_info(&mint_url).await?;
 
-            if info.nuts.nut18.map_or(true, |nut18| !nut18.supported) {
+            if info
+                .and_then(|info| info.nuts.nut18)
+                .map_or(true, |nut18| !nut18.supported)
+            {
                 term.write_line("Error: onchain-payments are not supported by this mint")?;
                 return Ok(());
             }
@@ -318,9 +523,12 @@ async fn main() -> anyhow::Result<()> {
             let currency = CurrencyUnit::Sat;
             let mint_url = choose_mint(&wallet, &currency).await?.0;
 
-            let info = wallet.get_mint_info(&mint_url).await?;
+            let nut17 = wallet
+                .get_mint_info(&mint_url)
+                .await?
+                .and_then(|info| info.nuts.nut17);
 
-            let payment_method = info.nuts.nut17.as_ref().map_or_else(
+            let payment_method = nut17.as_ref().map_or_else(
                 || {
                     term.write_line("Only bolt11 minting is supported")
                         .expect("write_line failed");
@@ -347,7 +555,7 @@ async fn main() -> anyhow::Result<()> {
 
             let quote = match payment_method {
                 PaymentMethod::BtcOnchain => {
-                    let nut17 = info.nuts.nut17.expect("nut17 is None");
+                    let nut17 = nut17.expect("nut17 is None");
                     let payment_method = nut17.payment_methods.first().expect("no payment methods");
 
                     if amount < payment_method.min_amount {
@@ -445,5 +653,6 @@ async fn main() -> anyhow::Result<()> {
             }
         }
     }
+    wallet.flush_endpoint_stats().await?;
     Ok(())
 }
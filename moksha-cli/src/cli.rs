@@ -4,10 +4,13 @@ use console::{style, Term};
 use dialoguer::{theme::ColorfulTheme, Select};
 use indicatif::{ProgressBar, ProgressStyle};
 
-use moksha_core::primitives::CurrencyUnit;
+use moksha_core::primitives::{CurrencyUnit, MintInfoResponse};
 use moksha_wallet::{
-    error::MokshaWalletError, http::CrossPlatformHttpClient, localstore::sqlite::SqliteLocalStore,
-    wallet::Wallet,
+    error::MokshaWalletError,
+    http::CrossPlatformHttpClient,
+    localstore::sqlite::SqliteLocalStore,
+    stats::EndpointSummary,
+    wallet::{is_secure_mint_url, Wallet},
 };
 use num_format::Locale;
 use num_format::ToFormattedString;
@@ -20,6 +23,30 @@ pub fn progress_bar() -> anyhow::Result<ProgressBar> {
     Ok(pb)
 }
 
+/// Shows a prominent warning for a mint that is reached over plain http on a public host. Returns
+/// whether the mint may be used.
+pub fn warn_insecure_mint(
+    term: &Term,
+    mint_url: &Url,
+    allow_insecure_mints: bool,
+) -> anyhow::Result<bool> {
+    if is_secure_mint_url(mint_url) {
+        return Ok(true);
+    }
+    term.write_line(&format!(
+        "{}",
+        style(format!(
+            "WARNING: {mint_url} uses plain http on a public host. Anyone on the network path can steal the tokens of this mint."
+        ))
+        .red()
+        .bold()
+    ))?;
+    if !allow_insecure_mints {
+        term.write_line("Use --allow-insecure-mints to use this mint anyway")?;
+    }
+    Ok(allow_insecure_mints)
+}
+
 pub async fn choose_mint(
     wallet: &Wallet<SqliteLocalStore, CrossPlatformHttpClient>,
     currency_unit: &CurrencyUnit,
@@ -88,3 +115,61 @@ pub async fn show_total_balance(
     ))?;
     Ok(())
 }
+
+/// Prints the response times and error rates of the endpoints of a mint as a table
+pub fn show_endpoint_stats(term: &Term, stats: &[EndpointSummary]) -> anyhow::Result<()> {
+    if stats.is_empty() {
+        term.write_line(" - no requests recorded yet")?;
+        return Ok(());
+    }
+
+    let ms = |value: Option<u64>| value.map_or_else(|| "-".to_owned(), |v| v.to_string());
+    term.write_line(&format!(
+        "   {:<12} {:>8} {:>8} {:>8} {:>8} {:>8}",
+        "endpoint", "requests", "errors", "p50 ms", "p90 ms", "p99 ms"
+    ))?;
+    for stat in stats {
+        let errors = format!("{:.0}%", stat.error_rate() * 100.0);
+        let errors = if stat.errors > 0 {
+            style(errors).red().to_string()
+        } else {
+            errors
+        };
+        term.write_line(&format!(
+            "   {:<12} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            stat.endpoint.to_string(),
+            stat.requests,
+            errors,
+            ms(stat.p50_ms),
+            ms(stat.p90_ms),
+            ms(stat.p99_ms)
+        ))?;
+    }
+    Ok(())
+}
+
+/// Prints the info a mint announces (NUT-06)
+pub fn show_mint_info(term: &Term, info: &MintInfoResponse) -> anyhow::Result<()> {
+    let fields = [
+        ("name", info.name.clone()),
+        ("version", info.version.clone()),
+        ("description", info.description.clone()),
+        ("motd", info.motd.clone()),
+    ];
+    for (label, value) in fields {
+        if let Some(value) = value {
+            term.write_line(&format!("   {label}: {}", style(value).cyan()))?;
+        }
+    }
+    for contact in info.contact.iter().flatten() {
+        term.write_line(&format!("   contact: {}", contact.join(" ")))?;
+    }
+    let nuts = info
+        .nuts
+        .supported()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    term.write_line(&format!("   supported nuts: {}", nuts.join(", ")))?;
+    Ok(())
+}
@@ -6,13 +6,29 @@
 //!
 //! Both the `Amount` and `SplitAmount` structs are serializable and deserializable using serde.
 
+use crate::error::MokshaCoreError;
+
 #[derive(Debug, Clone)]
 pub struct Amount(pub u64);
 
 impl Amount {
+    /// Splits the amount into powers of 2. A zero amount has no denominations and is split
+    /// into nothing.
     pub fn split(&self) -> SplitAmount {
         split_amount(self.0).into()
     }
+
+    /// Divides `total` into the rest and this amount, e.g. the change and the amount that is sent
+    /// in a swap. Sending nothing keeps the whole total, sending the total leaves no rest.
+    pub fn split_from(&self, total: u64) -> Result<(Amount, Amount), MokshaCoreError> {
+        let rest = total
+            .checked_sub(self.0)
+            .ok_or(MokshaCoreError::NotEnoughTokens {
+                needed: self.0,
+                available: total,
+            })?;
+        Ok((rest.into(), self.clone()))
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -67,6 +83,10 @@ fn split_amount(amount: u64) -> Vec<u64> {
 #[cfg(test)]
 mod tests {
     use pretty_assertions::assert_eq;
+    use rand::Rng;
+
+    use super::Amount;
+    use crate::error::MokshaCoreError;
 
     #[test]
     fn test_split_amount() -> anyhow::Result<()> {
@@ -78,6 +98,51 @@ mod tests {
 
         let bits = super::split_amount(64);
         assert_eq!(bits, vec![64]);
+
+        assert!(super::split_amount(0).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_from_edges() -> anyhow::Result<()> {
+        let (rest, amount) = Amount(0).split_from(64)?;
+        assert_eq!(vec![64], rest.split().into_iter().collect::<Vec<_>>());
+        assert!(amount.split().is_empty());
+
+        let (rest, amount) = Amount(64).split_from(64)?;
+        assert!(rest.split().is_empty());
+        assert_eq!(vec![64], amount.split().into_iter().collect::<Vec<_>>());
+
+        let (rest, amount) = Amount(0).split_from(0)?;
+        assert!(rest.split().is_empty());
+        assert!(amount.split().is_empty());
+
+        assert!(matches!(
+            Amount(100).split_from(64),
+            Err(MokshaCoreError::NotEnoughTokens {
+                needed: 100,
+                available: 64
+            })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_from_random() -> anyhow::Result<()> {
+        let mut rng = rand::thread_rng();
+        for _ in 0..1_000 {
+            let total = rng.gen_range(0..=u64::MAX / 2);
+            let amount = rng.gen_range(0..=total);
+
+            let (rest, sent) = Amount(amount).split_from(total)?;
+            let rest = rest.split().into_iter().collect::<Vec<_>>();
+            let sent = sent.split().into_iter().collect::<Vec<_>>();
+            assert_eq!(total - amount, rest.iter().sum::<u64>());
+            assert_eq!(amount, sent.iter().sum::<u64>());
+            assert!(rest.iter().chain(&sent).all(|a| a.is_power_of_two()));
+
+            assert!(Amount(total + 1).split_from(total).is_err());
+        }
         Ok(())
     }
 }
@@ -4,6 +4,8 @@
 //!
 //! The `BlindedSignature` struct represents a blinded signature, with an `amount` field for the amount in satoshis, a `c_` field for the public key of the blinding factor, and an optional `id` field for the ID of the signature.
 //!
+//! The optional `dleq` field of a `BlindedSignature` contains a [`DleqProof`] that the signature was created with the published key of the mint as described in [Nut-12](https://github.com/cashubtc/nuts/blob/main/12.md).
+//!
 //! Both the `BlindedMessage` and `BlindedSignature` structs are serializable and deserializable using serde.
 //!
 //! The `TotalAmount` trait is also defined in this module, which provides a `total_amount` method for calculating the total amount of a vector of `BlindedMessage` or `BlindedSignature` structs. The trait is implemented for both `Vec<BlindedMessage>` and `Vec<BlindedSignature>`.
@@ -21,6 +23,18 @@ pub struct BlindedSignature {
     #[schema(value_type=String)]
     pub c_: PublicKey,
     pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dleq: Option<DleqProof>,
+}
+
+/// Proof that the mint used the private key of its public key `A` to create the signature `C_`
+/// of a blinded message `B_` (NUT-12)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DleqProof {
+    #[schema(value_type=String)]
+    pub e: SecretKey,
+    #[schema(value_type=String)]
+    pub s: SecretKey,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
@@ -0,0 +1,327 @@
+//! A minimal CBOR ([RFC 8949](https://www.rfc-editor.org/rfc/rfc8949)) encoder and decoder for the v4 token format.
+//!
+//! Supports unsigned integers, byte and text strings, arrays, maps, booleans and null. Arrays and maps with indefinite length are decoded, but always encoded with a definite length.
+
+use crate::error::MokshaCoreError;
+
+/// Maximum nesting of arrays and maps, so malicious input can't exhaust the stack
+const MAX_DEPTH: usize = 16;
+
+const MAJOR_UNSIGNED: u8 = 0;
+const MAJOR_BYTES: u8 = 2;
+const MAJOR_TEXT: u8 = 3;
+const MAJOR_ARRAY: u8 = 4;
+const MAJOR_MAP: u8 = 5;
+const MAJOR_SIMPLE: u8 = 7;
+
+const INDEFINITE: u8 = 31;
+const BREAK: u8 = 0xff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Unsigned(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Value>),
+    /// Entries in the order of the encoding
+    Map(Vec<(Value, Value)>),
+    Bool(bool),
+    Null,
+}
+
+impl Value {
+    /// Creates a map with text keys
+    pub fn map(entries: Vec<(&str, Self)>) -> Self {
+        Self::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (Self::Text(key.to_owned()), value))
+                .collect(),
+        )
+    }
+
+    /// Returns the value of a text key in a map
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        match self {
+            Self::Map(entries) => entries
+                .iter()
+                .find(|(k, _)| matches!(k, Self::Text(text) if text == key))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_unsigned(&self) -> Option<u64> {
+        match self {
+            Self::Unsigned(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Bytes(bytes) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Self]> {
+        match self {
+            Self::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.encode_into(&mut out);
+        out
+    }
+
+    fn encode_into(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Unsigned(value) => encode_head(out, MAJOR_UNSIGNED, *value),
+            Self::Bytes(bytes) => {
+                encode_head(out, MAJOR_BYTES, bytes.len() as u64);
+                out.extend_from_slice(bytes);
+            }
+            Self::Text(text) => {
+                encode_head(out, MAJOR_TEXT, text.len() as u64);
+                out.extend_from_slice(text.as_bytes());
+            }
+            Self::Array(values) => {
+                encode_head(out, MAJOR_ARRAY, values.len() as u64);
+                values.iter().for_each(|value| value.encode_into(out));
+            }
+            Self::Map(entries) => {
+                encode_head(out, MAJOR_MAP, entries.len() as u64);
+                for (key, value) in entries {
+                    key.encode_into(out);
+                    value.encode_into(out);
+                }
+            }
+            Self::Bool(false) => out.push(MAJOR_SIMPLE << 5 | 20),
+            Self::Bool(true) => out.push(MAJOR_SIMPLE << 5 | 21),
+            Self::Null => out.push(MAJOR_SIMPLE << 5 | 22),
+        }
+    }
+
+    /// Decodes a single value that must span all of `bytes`
+    pub fn decode(bytes: &[u8]) -> Result<Self, MokshaCoreError> {
+        let mut decoder = Decoder { bytes, pos: 0 };
+        let value = decoder.value(0)?;
+        if decoder.pos != bytes.len() {
+            return Err(invalid("trailing bytes"));
+        }
+        Ok(value)
+    }
+}
+
+fn encode_head(out: &mut Vec<u8>, major: u8, value: u64) {
+    let major = major << 5;
+    match value {
+        0..=23 => out.push(major | value as u8),
+        24..=0xff => out.extend([major | 24, value as u8]),
+        0x100..=0xffff => {
+            out.push(major | 25);
+            out.extend((value as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(major | 26);
+            out.extend((value as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(major | 27);
+            out.extend(value.to_be_bytes());
+        }
+    }
+}
+
+fn invalid(reason: &str) -> MokshaCoreError {
+    MokshaCoreError::InvalidCbor(reason.to_owned())
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], MokshaCoreError> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or_else(|| invalid("unexpected end of input"))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn peek_break(&self) -> bool {
+        self.bytes.get(self.pos) == Some(&BREAK)
+    }
+
+    /// Reads the argument of a head, `None` for an indefinite length
+    fn argument(&mut self, info: u8) -> Result<Option<u64>, MokshaCoreError> {
+        Ok(Some(match info {
+            0..=23 => info as u64,
+            24 => self.take(1)?[0] as u64,
+            25 => u16::from_be_bytes(self.take(2)?.try_into()?) as u64,
+            26 => u32::from_be_bytes(self.take(4)?.try_into()?) as u64,
+            27 => u64::from_be_bytes(self.take(8)?.try_into()?),
+            INDEFINITE => return Ok(None),
+            _ => return Err(invalid("reserved additional information")),
+        }))
+    }
+
+    fn length(&mut self, info: u8) -> Result<Option<usize>, MokshaCoreError> {
+        self.argument(info)?
+            .map(|len| usize::try_from(len).map_err(|_| invalid("length too large")))
+            .transpose()
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value, MokshaCoreError> {
+        if depth > MAX_DEPTH {
+            return Err(invalid("nested too deep"));
+        }
+        let head = self.take(1)?[0];
+        let (major, info) = (head >> 5, head & 0x1f);
+        match major {
+            MAJOR_UNSIGNED => self
+                .argument(info)?
+                .map(Value::Unsigned)
+                .ok_or_else(|| invalid("indefinite integer")),
+            MAJOR_BYTES => Ok(Value::Bytes(self.string(major, info)?)),
+            MAJOR_TEXT => String::from_utf8(self.string(major, info)?)
+                .map(Value::Text)
+                .map_err(|_| invalid("text is not utf-8")),
+            MAJOR_ARRAY => {
+                let mut values = vec![];
+                match self.length(info)? {
+                    Some(len) => {
+                        for _ in 0..len {
+                            values.push(self.value(depth + 1)?);
+                        }
+                    }
+                    None => {
+                        while !self.peek_break() {
+                            values.push(self.value(depth + 1)?);
+                        }
+                        self.pos += 1;
+                    }
+                }
+                Ok(Value::Array(values))
+            }
+            MAJOR_MAP => {
+                let mut entries = vec![];
+                match self.length(info)? {
+                    Some(len) => {
+                        for _ in 0..len {
+                            entries.push((self.value(depth + 1)?, self.value(depth + 1)?));
+                        }
+                    }
+                    None => {
+                        while !self.peek_break() {
+                            entries.push((self.value(depth + 1)?, self.value(depth + 1)?));
+                        }
+                        self.pos += 1;
+                    }
+                }
+                Ok(Value::Map(entries))
+            }
+            MAJOR_SIMPLE => match info {
+                20 => Ok(Value::Bool(false)),
+                21 => Ok(Value::Bool(true)),
+                22 => Ok(Value::Null),
+                _ => Err(invalid("unsupported simple value")),
+            },
+            _ => Err(invalid("unsupported major type")),
+        }
+    }
+
+    /// Reads a byte or text string, joining the chunks of an indefinite length string
+    fn string(&mut self, major: u8, info: u8) -> Result<Vec<u8>, MokshaCoreError> {
+        if let Some(len) = self.length(info)? {
+            return Ok(self.take(len)?.to_vec());
+        }
+        let mut bytes = vec![];
+        while !self.peek_break() {
+            let head = self.take(1)?[0];
+            if head >> 5 != major || head & 0x1f == INDEFINITE {
+                return Err(invalid("invalid chunk of indefinite string"));
+            }
+            let len = self
+                .length(head & 0x1f)?
+                .ok_or_else(|| invalid("invalid chunk of indefinite string"))?;
+            bytes.extend_from_slice(self.take(len)?);
+        }
+        self.pos += 1;
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Value;
+
+    #[test]
+    fn test_roundtrip() -> anyhow::Result<()> {
+        let value = Value::map(vec![
+            ("a", Value::Unsigned(0)),
+            ("b", Value::Unsigned(24)),
+            ("c", Value::Unsigned(1_000)),
+            ("d", Value::Unsigned(100_000)),
+            ("e", Value::Unsigned(u64::MAX)),
+            ("f", Value::Bytes(vec![1, 2, 3])),
+            ("g", Value::Text("Thank you".to_owned())),
+            ("h", Value::Array(vec![Value::Bool(true), Value::Null])),
+        ]);
+        let encoded = value.encode();
+        assert_eq!(value, Value::decode(&encoded)?);
+        assert_eq!(Some(1_000), value.get("c").and_then(Value::as_unsigned));
+        assert_eq!(None, value.get("x"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rfc_examples() -> anyhow::Result<()> {
+        assert_eq!(vec![0x17], Value::Unsigned(23).encode());
+        assert_eq!(vec![0x18, 0x18], Value::Unsigned(24).encode());
+        assert_eq!(vec![0x19, 0x03, 0xe8], Value::Unsigned(1_000).encode());
+        assert_eq!(
+            vec![0x64, 0x49, 0x45, 0x54, 0x46],
+            Value::Text("IETF".into()).encode()
+        );
+
+        // indefinite length array [1, [2, 3]] and byte string (_ h'0102', h'030405')
+        let array = Value::decode(&[0x9f, 0x01, 0x82, 0x02, 0x03, 0xff])?;
+        assert_eq!(
+            Value::Array(vec![
+                Value::Unsigned(1),
+                Value::Array(vec![Value::Unsigned(2), Value::Unsigned(3)])
+            ]),
+            array
+        );
+        let bytes = Value::decode(&[0x5f, 0x42, 0x01, 0x02, 0x43, 0x03, 0x04, 0x05, 0xff])?;
+        assert_eq!(Value::Bytes(vec![1, 2, 3, 4, 5]), bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        // truncated text, trailing bytes, negative integer, nesting
+        assert!(Value::decode(&[0x64, 0x49]).is_err());
+        assert!(Value::decode(&[0x01, 0x02]).is_err());
+        assert!(Value::decode(&[0x20]).is_err());
+        assert!(Value::decode(&[0x81; 64]).is_err());
+        assert!(Value::decode(&[0x5b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]).is_err());
+    }
+}
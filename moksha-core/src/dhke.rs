@@ -40,8 +40,28 @@
 //!```
 //! If true, C must have originated from Bob
 //!
+//! Bob proves that he used the private key `a` of `A` to create `C'` with a DLEQ proof
+//! ([NUT-12](https://github.com/cashubtc/nuts/blob/main/12.md)):
+//!```python
+//! r = random nonce
+//! R1 = r*G
+//! R2 = r*B'
+//! e = hash(R1, R2, A, C')
+//! s = r + e*a
+//! return e, s
+//!```
+//!
+//! Alice:
+//!```python
+//! R1 = s*G - e*A
+//! R2 = s*B' - e*C'
+//! e == hash(R1, R2, A, C')
+//!```
 //!
-use crate::{blind::BlindingFactor, error::MokshaCoreError};
+use crate::{
+    blind::{BlindingFactor, DleqProof},
+    error::MokshaCoreError,
+};
 use bitcoin_hashes::{sha256, Hash};
 use secp256k1::{All, PublicKey, Scalar, Secp256k1, SecretKey};
 use std::iter::once;
@@ -74,7 +94,7 @@ impl Dhke {
 
     /// The domain separator is b"Secp256k1_HashToCurve_Cashu_" or
     /// bytes.fromhex("536563703235366b315f48617368546f43757276655f43617368755f").
-    fn hash_to_curve(message: &[u8]) -> Result<PublicKey, MokshaCoreError> {
+    pub fn hash_to_curve(message: &[u8]) -> Result<PublicKey, MokshaCoreError> {
         let msg_to_hash = sha256::Hash::hash(&[b"Secp256k1_HashToCurve_Cashu_", message].concat());
         (0..2u32.pow(16))
             .map(|counter| sha256::Hash::hash(&[&msg_to_hash[..], &counter.to_le_bytes()].concat()))
@@ -131,6 +151,54 @@ impl Dhke {
             MokshaCoreError::Secp256k1Error(secp256k1::Error::InvalidPublicKey),
         )
     }
+
+    /// Hashes the uncompressed hex encoding of the points as defined in NUT-12
+    pub fn hash_e(points: &[PublicKey]) -> Result<SecretKey, MokshaCoreError> {
+        let e = points
+            .iter()
+            .map(|point| hex::encode(point.serialize_uncompressed()))
+            .collect::<String>();
+        Ok(SecretKey::from_slice(
+            sha256::Hash::hash(e.as_bytes()).as_byte_array(),
+        )?)
+    }
+
+    /// Creates the DLEQ proof that `c_` is the signature of `b_` with the private key `a`
+    pub fn create_dleq(
+        &self,
+        b_: PublicKey,
+        c_: PublicKey,
+        a: &SecretKey,
+    ) -> Result<DleqProof, MokshaCoreError> {
+        let r = SecretKey::new(&mut rand::thread_rng());
+        let r1 = PublicKey::from_secret_key(&self.secp, &r);
+        let r2 = b_.mul_tweak(&self.secp, &Scalar::from(r))?;
+        let e = Self::hash_e(&[r1, r2, a.public_key(&self.secp), c_])?;
+        let s = a.mul_tweak(&Scalar::from(e))?.add_tweak(&Scalar::from(r))?;
+        Ok(DleqProof { e, s })
+    }
+
+    /// Verifies that `c_` is the signature of `b_` with the private key of the public key `a`
+    pub fn verify_dleq(
+        &self,
+        dleq: &DleqProof,
+        b_: PublicKey,
+        c_: PublicKey,
+        a: PublicKey,
+    ) -> Result<bool, MokshaCoreError> {
+        let e = Scalar::from(dleq.e);
+        let s = Scalar::from(dleq.s);
+        // the points are infinity for some invalid proofs, which cannot be combined
+        let (Ok(r1), Ok(r2)) = (
+            PublicKey::from_secret_key(&self.secp, &dleq.s)
+                .combine(&a.mul_tweak(&self.secp, &e)?.negate(&self.secp)),
+            b_.mul_tweak(&self.secp, &s)?
+                .combine(&c_.mul_tweak(&self.secp, &e)?.negate(&self.secp)),
+        ) else {
+            return Ok(false);
+        };
+        Ok(Self::hash_e(&[r1, r2, a, c_])? == dleq.e)
+    }
 }
 
 pub fn public_key_from_hex(hex: &str) -> secp256k1::PublicKey {
@@ -144,8 +212,9 @@ mod tests {
     use std::str::FromStr;
 
     use crate::{
-        blind::BlindingFactor,
+        blind::{BlindedSignature, BlindingFactor, DleqProof},
         dhke::{public_key_from_hex, Dhke},
+        fixture::read_fixture_as,
     };
     use anyhow::Ok;
     use pretty_assertions::assert_eq;
@@ -287,4 +356,67 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_hash_e() -> anyhow::Result<()> {
+        let k = public_key_from_hex(
+            "020000000000000000000000000000000000000000000000000000000000000001",
+        );
+        let c_ = public_key_from_hex(
+            "02a9acc1e48c25eeeb9289b5031cc57da9fe72f3fe2861d264bdc074209b107ba2",
+        );
+
+        let e = Dhke::hash_e(&[k, k, k, c_])?;
+        assert_eq!(
+            "a4dc034b74338c28c6bc3ea49731f2a24440fc7c4affc08b31a93fc9fbe6401e",
+            hex::encode(e.secret_bytes())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_dleq() -> anyhow::Result<()> {
+        let dhke = Dhke::new();
+        let a = pk_from_hex("0000000000000000000000000000000000000000000000000000000000000003");
+        let bf: BlindingFactor =
+            "0000000000000000000000000000000000000000000000000000000000000002".try_into()?;
+        let b_ = dhke.step1_alice("test_message", &bf)?;
+        let c_ = dhke.step2_bob(b_, &a)?;
+
+        let dleq = dhke.create_dleq(b_, c_, &a)?;
+        assert!(dhke.verify_dleq(&dleq, b_, c_, a.public_key(&dhke.secp))?);
+        // a signature of another key
+        let other = pk_from_hex("0000000000000000000000000000000000000000000000000000000000000004");
+        assert!(!dhke.verify_dleq(
+            &dleq,
+            b_,
+            dhke.step2_bob(b_, &other)?,
+            a.public_key(&dhke.secp)
+        )?);
+        Ok(())
+    }
+
+    #[test]
+    #[allow(non_snake_case)]
+    fn test_verify_dleq_fixture() -> anyhow::Result<()> {
+        #[derive(serde::Deserialize)]
+        struct Fixture {
+            A: secp256k1::PublicKey,
+            B_: secp256k1::PublicKey,
+            signature: BlindedSignature,
+        }
+        let dhke = Dhke::new();
+        let Fixture { A, B_, signature } = read_fixture_as::<Fixture>("dleq.json")?;
+        let dleq = signature.dleq.expect("dleq is missing");
+        assert!(dhke.verify_dleq(&dleq, B_, signature.c_, A)?);
+
+        let tampered = DleqProof {
+            s: dleq.s.add_tweak(&secp256k1::Scalar::ONE)?,
+            ..dleq
+        };
+        assert!(!dhke.verify_dleq(&tampered, B_, signature.c_, A)?);
+        // the proof is only valid for the blinded message it was created for
+        assert!(!dhke.verify_dleq(&dleq, signature.c_, signature.c_, A)?);
+        Ok(())
+    }
 }
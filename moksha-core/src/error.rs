@@ -23,12 +23,18 @@ pub enum MokshaCoreError {
     #[error("Invalid Keysetid")]
     InvalidKeysetid,
 
-    #[error("Not enough tokens")]
-    NotEnoughTokens,
+    #[error("Not enough tokens: need {needed}, have {available}")]
+    NotEnoughTokens { needed: u64, available: u64 },
 
     #[error("Invalid token")]
     InvalidToken,
 
+    #[error("Token has no proofs")]
+    EmptyToken,
+
+    #[error("Invalid cbor: {0}")]
+    InvalidCbor(String),
+
     #[error("No valid point on curve secp256k1 found")]
     NoValidPointFound,
 
@@ -37,4 +43,7 @@ pub enum MokshaCoreError {
 
     #[error("Invalid Keyset-ID")]
     Slice(#[from] std::array::TryFromSliceError),
+
+    #[error("Invalid page cursor: {0}")]
+    InvalidPageCursor(String),
 }
@@ -0,0 +1,388 @@
+//! This module defines the `TokenReport`, a summary of a token for support and debugging, see [`TokenV3::inspect`] and [`TokenReport::parse`].
+//!
+//! The report contains the mints, amounts, denominations and keysets of a token and the format of its secrets. Secrets are only shown as fingerprints (the first 8 bytes of their sha256 hash), so a report can be shared without giving away the token. Creating a report never contacts a mint.
+//!
+//! A token that can't be decoded completely still produces a report with everything that could be read and a warning that explains what is wrong.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Display,
+    str::FromStr,
+};
+
+use base64::{engine::general_purpose, Engine as _};
+use bitcoin_hashes::{sha256, Hash};
+use url::Url;
+
+use crate::{
+    primitives::CurrencyUnit,
+    secret::{SecretKind, WellKnownSecret},
+    token::{TokenV3, TokenV4, TOKEN_PREFIX_V3, TOKEN_PREFIX_V4},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenVersion {
+    /// json based `cashuA` token
+    V3,
+    /// cbor based `cashuB` token
+    V4,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecretFormat {
+    /// 64 hex characters like the secrets that are derived from a seed (NUT-13). Random secrets of
+    /// current wallets have the same format.
+    Deterministic,
+    /// locked to a public key (NUT-11)
+    P2pk {
+        locktime: Option<u64>,
+    },
+    Other,
+}
+
+impl SecretFormat {
+    fn of(secret: &str) -> Self {
+        if secret.len() == 64 && secret.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Self::Deterministic;
+        }
+        match WellKnownSecret::from_str(secret) {
+            Ok(secret) => match secret.kind() {
+                SecretKind::P2PK => Self::P2pk {
+                    locktime: secret.locktime(),
+                },
+            },
+            Err(_) => Self::Other,
+        }
+    }
+}
+
+impl Display for SecretFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Deterministic => write!(f, "deterministic"),
+            Self::P2pk {
+                locktime: Some(locktime),
+            } => write!(f, "p2pk (locktime {locktime})"),
+            Self::P2pk { locktime: None } => write!(f, "p2pk"),
+            Self::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// A proof of a token without its secret and signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofSummary {
+    pub amount: u64,
+    pub keyset_id: String,
+    /// hex encoded first 8 bytes of the sha256 hash of the secret
+    pub fingerprint: String,
+    pub format: SecretFormat,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenWarning {
+    /// the token doesn't start with `cashuA` or `cashuB`
+    UnknownPrefix(String),
+    /// the token could not be decoded, the report only contains what was read before the error
+    Malformed(String),
+    /// the token contains data after its end
+    TrailingData(usize),
+    /// fingerprint of a secret that is used by more than one proof
+    DuplicateSecret(String),
+    /// the proofs belong to more than one mint
+    MixedMints,
+    /// a part of the token has no mint
+    MissingMint,
+}
+
+impl Display for TokenWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownPrefix(prefix) => write!(f, "unknown token prefix {prefix}"),
+            Self::Malformed(reason) => write!(f, "malformed token: {reason}"),
+            Self::TrailingData(bytes) => write!(f, "{bytes} bytes after the end of the token"),
+            Self::DuplicateSecret(fingerprint) => {
+                write!(f, "secret {fingerprint} is used more than once")
+            }
+            Self::MixedMints => write!(f, "the proofs belong to more than one mint"),
+            Self::MissingMint => write!(f, "the token has proofs without a mint"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenReport {
+    pub version: Option<TokenVersion>,
+    /// length of the serialized token in bytes
+    pub size: usize,
+    pub mints: Vec<Url>,
+    pub unit: Option<CurrencyUnit>,
+    pub memo: Option<String>,
+    pub total_amount: u64,
+    pub proofs: Vec<ProofSummary>,
+    /// number of proofs per amount
+    pub denominations: BTreeMap<u64, usize>,
+    pub keyset_ids: Vec<String>,
+    pub warnings: Vec<TokenWarning>,
+}
+
+impl TokenReport {
+    fn new(version: Option<TokenVersion>, size: usize) -> Self {
+        Self {
+            version,
+            size,
+            mints: vec![],
+            unit: None,
+            memo: None,
+            total_amount: 0,
+            proofs: vec![],
+            denominations: BTreeMap::new(),
+            keyset_ids: vec![],
+            warnings: vec![],
+        }
+    }
+
+    /// Creates the report of a serialized token. Never fails, a token that can't be decoded
+    /// produces a partial report with a warning.
+    pub fn parse(token: &str) -> Self {
+        let token = token.trim();
+        if token.starts_with(TOKEN_PREFIX_V4) {
+            let mut report = Self::new(Some(TokenVersion::V4), token.len());
+            match TokenV4::deserialize(token) {
+                Ok(token) => report.add_token(&token.into()),
+                Err(e) => report.warnings.push(TokenWarning::Malformed(e.to_string())),
+            }
+            return report;
+        }
+
+        let Some(data) = token.strip_prefix(TOKEN_PREFIX_V3) else {
+            let mut report = Self::new(None, token.len());
+            let prefix = token.chars().take(TOKEN_PREFIX_V3.len()).collect();
+            report.warnings.push(TokenWarning::UnknownPrefix(prefix));
+            return report;
+        };
+        let mut report = Self::new(Some(TokenVersion::V3), token.len());
+        let Ok(json) = general_purpose::URL_SAFE_NO_PAD
+            .decode(data.as_bytes())
+            .or_else(|_| general_purpose::URL_SAFE.decode(data.as_bytes()))
+        else {
+            report
+                .warnings
+                .push(TokenWarning::Malformed("invalid base64".to_owned()));
+            return report;
+        };
+
+        // reads the token in front of trailing garbage
+        let mut tokens = serde_json::Deserializer::from_slice(&json).into_iter::<TokenV3>();
+        match tokens.next() {
+            Some(Ok(token)) => {
+                report.add_token(&token);
+                let trailing = json.len() - tokens.byte_offset();
+                if trailing > 0 {
+                    report.warnings.push(TokenWarning::TrailingData(trailing));
+                }
+            }
+            Some(Err(e)) => report.warnings.push(TokenWarning::Malformed(e.to_string())),
+            None => report
+                .warnings
+                .push(TokenWarning::Malformed("empty token".to_owned())),
+        }
+        report
+    }
+
+    /// Reports the proofs in the canonical order, like the token is serialized
+    pub(crate) fn from_token(token: &TokenV3) -> Self {
+        let size = token.serialize().map(|token| token.len()).unwrap_or(0);
+        let mut report = Self::new(Some(TokenVersion::V3), size);
+        report.add_token(&token.clone().canonicalize());
+        report
+    }
+
+    fn add_token(&mut self, token: &TokenV3) {
+        self.unit = token.currency_unit.clone();
+        self.memo = token.memo.clone();
+
+        let mut secrets = HashSet::new();
+        for part in &token.tokens {
+            match &part.mint {
+                Some(mint) if !self.mints.contains(mint) => self.mints.push(mint.clone()),
+                Some(_) => {}
+                None if !self.warnings.contains(&TokenWarning::MissingMint) => {
+                    self.warnings.push(TokenWarning::MissingMint)
+                }
+                None => {}
+            }
+            for proof in part.proofs.proofs() {
+                let fingerprint = fingerprint(&proof.secret);
+                if !secrets.insert(proof.secret.clone()) {
+                    self.warnings
+                        .push(TokenWarning::DuplicateSecret(fingerprint.clone()));
+                }
+                if !self.keyset_ids.contains(&proof.keyset_id) {
+                    self.keyset_ids.push(proof.keyset_id.clone());
+                }
+                self.total_amount += proof.amount;
+                *self.denominations.entry(proof.amount).or_default() += 1;
+                self.proofs.push(ProofSummary {
+                    amount: proof.amount,
+                    keyset_id: proof.keyset_id,
+                    format: SecretFormat::of(&proof.secret),
+                    fingerprint,
+                });
+            }
+        }
+        if self.mints.len() > 1 {
+            self.warnings.push(TokenWarning::MixedMints);
+        }
+    }
+}
+
+fn fingerprint(secret: &str) -> String {
+    hex::encode(&sha256::Hash::hash(secret.as_bytes()).as_byte_array()[..8])
+}
+
+impl Display for TokenReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let version = match self.version {
+            Some(TokenVersion::V3) => "V3 (cashuA)",
+            Some(TokenVersion::V4) => "V4 (cashuB)",
+            None => "unknown",
+        };
+        writeln!(f, "version:  {version}")?;
+        writeln!(f, "size:     {} bytes", self.size)?;
+        for mint in &self.mints {
+            writeln!(f, "mint:     {mint}")?;
+        }
+        if let Some(unit) = &self.unit {
+            writeln!(f, "unit:     {unit}")?;
+        }
+        if let Some(memo) = &self.memo {
+            writeln!(f, "memo:     {memo}")?;
+        }
+        writeln!(
+            f,
+            "amount:   {} in {} proofs",
+            self.total_amount,
+            self.proofs.len()
+        )?;
+        for (amount, count) in &self.denominations {
+            writeln!(f, "          {count} x {amount}")?;
+        }
+        writeln!(f, "keysets:  {}", self.keyset_ids.join(", "))?;
+        for proof in &self.proofs {
+            writeln!(
+                f,
+                "proof:    {} {} {} {}",
+                proof.amount, proof.keyset_id, proof.fingerprint, proof.format
+            )?;
+        }
+        for warning in &self.warnings {
+            writeln!(f, "warning:  {warning}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::{SecretFormat, TokenReport, TokenWarning};
+    use crate::{
+        fixture::read_fixture,
+        secret::WellKnownSecret,
+        token::{Token, TokenV3},
+    };
+
+    #[test]
+    fn test_inspect_fixtures() -> anyhow::Result<()> {
+        for fixture in [
+            "token_60.cashu",
+            "token_nut_example.cashu",
+            "token_v4_nut_example.cashu",
+            "token_invalid.cashu",
+        ] {
+            let report = TokenReport::parse(&read_fixture(fixture)?);
+            assert_eq!(
+                read_fixture(&fixture.replace(".cashu", ".inspect"))?,
+                report.to_string().trim(),
+                "{fixture}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspect_does_not_show_secrets() -> anyhow::Result<()> {
+        let token = TokenV3::deserialize(read_fixture("token_60.cashu")?)?;
+        let report = token.inspect();
+        assert_eq!(
+            TokenReport::parse(&token.serialize()?).to_string(),
+            report.to_string()
+        );
+        for proof in token.proofs().proofs() {
+            assert!(!report.to_string().contains(&proof.secret));
+            assert!(!report.to_string().contains(&proof.c.to_string()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspect_anomalies() -> anyhow::Result<()> {
+        // the amounts of the fixture differ, so the proofs keep the canonical order
+        let mut token = TokenV3::deserialize(read_fixture("token_60.cashu")?)?.canonicalize();
+        let mut proofs = token.proofs().proofs();
+        let pubkey = proofs[0].c;
+        proofs[1].secret = proofs[0].secret.clone();
+        proofs[2].secret = WellKnownSecret::p2pk_with_locktime(&pubkey, 1_700_000_000).to_string();
+        proofs[3].secret = "a".repeat(64);
+        token.tokens = vec![
+            Token {
+                mint: Some("http://127.0.0.1:3338".parse()?),
+                proofs: proofs[..2].to_vec().into(),
+            },
+            Token {
+                mint: Some("http://127.0.0.1:3339".parse()?),
+                proofs: proofs[2..].to_vec().into(),
+            },
+        ];
+
+        let report = token.inspect();
+        assert_eq!(2, report.mints.len());
+        assert_eq!(
+            vec![
+                SecretFormat::Other,
+                SecretFormat::Other,
+                SecretFormat::P2pk {
+                    locktime: Some(1_700_000_000)
+                },
+                SecretFormat::Deterministic
+            ],
+            report.proofs.iter().map(|p| p.format).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![
+                TokenWarning::DuplicateSecret(report.proofs[0].fingerprint.clone()),
+                TokenWarning::MixedMints
+            ],
+            report.warnings
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspect_unknown_prefix() {
+        let report = TokenReport::parse("cashuCabc");
+        assert_eq!(None, report.version);
+        assert_eq!(
+            vec![TokenWarning::UnknownPrefix("cashuC".to_owned())],
+            report.warnings
+        );
+
+        let report = TokenReport::parse("cashuA!!!");
+        assert_eq!(
+            vec![TokenWarning::Malformed("invalid base64".to_owned())],
+            report.warnings
+        );
+    }
+}
@@ -57,12 +57,24 @@ pub struct Keyset {
     pub id: String, // FIXME use KeysetId
     pub unit: CurrencyUnit,
     pub active: bool,
+    /// fee in parts per thousand per proof that is used as input (NUT-02)
+    #[serde(default)]
+    pub input_fee_ppk: u64,
+    /// unix timestamp (seconds) after which the mint doesn't accept proofs of the keyset anymore
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_expiry: Option<u64>,
 }
 
 impl Keysets {
     pub fn new(id: String, unit: CurrencyUnit, active: bool) -> Self {
         Self {
-            keysets: vec![Keyset { id, unit, active }],
+            keysets: vec![Keyset {
+                id,
+                unit,
+                active,
+                input_fee_ppk: 0,
+                final_expiry: None,
+            }],
         }
     }
 
@@ -1,9 +1,13 @@
 pub mod amount;
 pub mod blind;
+mod cbor;
 pub mod dhke;
 pub mod error;
 pub mod fixture;
+pub mod inspect;
 pub mod keyset;
+pub mod page;
 pub mod primitives;
 pub mod proof;
+pub mod secret;
 pub mod token;
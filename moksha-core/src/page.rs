@@ -0,0 +1,103 @@
+//! Cursors for paging through records, the newest first.
+//!
+//! A `PageCursor` points after the last record of a page. It encodes the creation time and the id
+//! of that record, so a page continues with the records that are ordered by `(created_at, id)`
+//! below it. Records that are added while paging sort above the cursor and are not returned, so
+//! the pages neither repeat nor skip records. The cursor is opaque to clients.
+
+use std::{fmt::Display, str::FromStr};
+
+use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
+
+use crate::error::MokshaCoreError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PageCursor {
+    /// unix timestamp (seconds) of the last record of the page
+    pub created_at: u64,
+    /// id of the last record of the page
+    pub id: u64,
+}
+
+impl PageCursor {
+    pub fn new(created_at: u64, id: u64) -> Self {
+        Self { created_at, id }
+    }
+
+    /// Whether a record comes after the cursor, i.e. belongs to the following pages
+    pub fn is_before(&self, created_at: u64, id: u64) -> bool {
+        (created_at, id) < (self.created_at, self.id)
+    }
+}
+
+impl Display for PageCursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut bytes = [0u8; 16];
+        bytes[..8].copy_from_slice(&self.created_at.to_be_bytes());
+        bytes[8..].copy_from_slice(&self.id.to_be_bytes());
+        write!(f, "{}", general_purpose::URL_SAFE_NO_PAD.encode(bytes))
+    }
+}
+
+impl FromStr for PageCursor {
+    type Err = MokshaCoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes: [u8; 16] = general_purpose::URL_SAFE_NO_PAD
+            .decode(s)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| MokshaCoreError::InvalidPageCursor(s.to_owned()))?;
+        let (created_at, id) = bytes.split_at(8);
+        Ok(Self {
+            created_at: u64::from_be_bytes(created_at.try_into()?),
+            id: u64::from_be_bytes(id.try_into()?),
+        })
+    }
+}
+
+impl TryFrom<String> for PageCursor {
+    type Error = MokshaCoreError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<PageCursor> for String {
+    fn from(cursor: PageCursor) -> Self {
+        cursor.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PageCursor;
+    use crate::error::MokshaCoreError;
+
+    #[test]
+    fn test_page_cursor() -> anyhow::Result<()> {
+        let cursor = PageCursor::new(1_730_000_000, 10_000);
+        let encoded = cursor.to_string();
+        assert_eq!(cursor, encoded.parse()?);
+        assert_eq!(
+            cursor,
+            serde_json::from_str(&serde_json::to_string(&cursor)?)?
+        );
+
+        assert!(cursor.is_before(1_730_000_000, 9_999));
+        assert!(cursor.is_before(1_729_999_999, 10_001));
+        assert!(!cursor.is_before(1_730_000_000, 10_000));
+        assert!(!cursor.is_before(1_730_000_001, 1));
+
+        for invalid in ["", "abc", "not a cursor", &format!("{encoded}AA")] {
+            assert!(matches!(
+                invalid.parse::<PageCursor>(),
+                Err(MokshaCoreError::InvalidPageCursor(_))
+            ));
+        }
+        Ok(())
+    }
+}
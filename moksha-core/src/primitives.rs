@@ -40,12 +40,152 @@ pub struct PostSwapRequest {
 #[derive(Clone, Debug, Serialize, Deserialize, Default, ToSchema)]
 pub struct PostSwapResponse {
     pub signatures: Vec<BlindedSignature>,
+    /// input fee in satoshis that was deducted from the inputs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_fee: Option<u64>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct PostCheckStateRequest {
+    #[serde(rename = "Ys")]
+    #[schema(value_type = Vec<String>)]
+    pub ys: Vec<PublicKey>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Default, ToSchema)]
+pub struct PostCheckStateResponse {
+    pub states: Vec<ProofState>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, ToSchema)]
+pub struct PostRestoreRequest {
+    pub outputs: Vec<BlindedMessage>,
+}
+
+/// The outputs the mint has signatures for and the signatures in the same order (NUT-09)
+#[derive(Clone, Debug, Serialize, Deserialize, Default, ToSchema)]
+pub struct PostRestoreResponse {
+    pub outputs: Vec<BlindedMessage>,
+    pub signatures: Vec<BlindedSignature>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct ProofState {
+    #[serde(rename = "Y")]
+    #[schema(value_type = String)]
+    pub y: PublicKey,
+    pub state: State,
+    pub witness: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum State {
+    Unspent,
+    Pending,
+    Spent,
+}
+
+/// Error body of a failed request to the mint
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct CashuErrorResponse {
+    /// one of the [`CashuErrorCode`]s, or 0 for other errors
     pub code: u64,
-    pub detail: String,
+    /// older mints send the message as `detail`
+    #[serde(alias = "detail")]
+    pub error: String,
+}
+
+impl CashuErrorResponse {
+    pub fn new(code: Option<CashuErrorCode>, error: impl Into<String>) -> Self {
+        Self {
+            code: code.map_or(0, CashuErrorCode::code),
+            error: error.into(),
+        }
+    }
+
+    /// The known error code of the response, [`None`] for generic errors and codes of newer mints
+    pub fn error_code(&self) -> Option<CashuErrorCode> {
+        CashuErrorCode::try_from(self.code).ok()
+    }
+}
+
+/// Error codes of the mint as defined in NUT-00. Wallets must not match on the error messages,
+/// they are for humans and may change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CashuErrorCode {
+    /// the outputs have already been signed
+    OutputsAlreadySigned = 10002,
+    /// the proofs could not be verified
+    ProofVerificationFailed = 10003,
+    /// the proofs have already been spent
+    ProofAlreadyUsed = 11001,
+    /// the inputs don't cover the outputs and the fees
+    InsufficientFee = 11002,
+    UnitNotSupported = 11005,
+    /// the amount is below the minimum or above the maximum of the mint
+    AmountOutsideLimit = 11006,
+    KeysetNotFound = 12001,
+    KeysetInactive = 12002,
+    /// the invoice of the quote has not been paid yet
+    InvoiceNotPaid = 20001,
+    /// the tokens of the quote have already been issued
+    QuoteAlreadyIssued = 20002,
+    QuotePending = 20005,
+    InvoiceAlreadyPaid = 20006,
+    QuoteExpired = 20007,
+}
+
+impl CashuErrorCode {
+    pub const ALL: [Self; 13] = [
+        Self::OutputsAlreadySigned,
+        Self::ProofVerificationFailed,
+        Self::ProofAlreadyUsed,
+        Self::InsufficientFee,
+        Self::UnitNotSupported,
+        Self::AmountOutsideLimit,
+        Self::KeysetNotFound,
+        Self::KeysetInactive,
+        Self::InvoiceNotPaid,
+        Self::QuoteAlreadyIssued,
+        Self::QuotePending,
+        Self::InvoiceAlreadyPaid,
+        Self::QuoteExpired,
+    ];
+
+    pub const fn code(self) -> u64 {
+        self as u64
+    }
+
+    /// The message of the code in the NUT-00 table, for responses without a message
+    pub const fn message(self) -> &'static str {
+        match self {
+            Self::OutputsAlreadySigned => "Blinded message of output already signed",
+            Self::ProofVerificationFailed => "Token could not be verified",
+            Self::ProofAlreadyUsed => "Token is already spent",
+            Self::InsufficientFee => "Transaction is not balanced (inputs != outputs)",
+            Self::UnitNotSupported => "Unit in request is not supported",
+            Self::AmountOutsideLimit => "Amount outside of limit range",
+            Self::KeysetNotFound => "Keyset is not known",
+            Self::KeysetInactive => "Keyset is inactive, cannot sign messages",
+            Self::InvoiceNotPaid => "Quote request is not paid",
+            Self::QuoteAlreadyIssued => "Tokens have already been issued for quote",
+            Self::QuotePending => "Quote is pending",
+            Self::InvoiceAlreadyPaid => "Invoice already paid",
+            Self::QuoteExpired => "Quote is expired",
+        }
+    }
+}
+
+impl TryFrom<u64> for CashuErrorCode {
+    type Error = u64;
+
+    fn try_from(code: u64) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|error_code| error_code.code() == code)
+            .ok_or(code)
+    }
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default, ToSchema)]
@@ -193,6 +333,9 @@ pub struct PostMeltQuoteBolt11Request {
     /// payment request
     pub request: String,
     pub unit: CurrencyUnit,
+    /// amount in sats to pay for an amountless invoice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone, ToSchema)]
@@ -296,6 +439,12 @@ pub struct PostMeltBolt11Response {
     pub paid: bool,
     pub payment_preimage: Option<String>,
     pub change: Vec<BlindedSignature>,
+    /// input fee in satoshis that was deducted from the inputs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input_fee: Option<u64>,
+    /// lightning routing fee in satoshis the mint paid for the invoice
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fee_paid: Option<u64>,
 }
 
 #[skip_serializing_none]
@@ -309,6 +458,8 @@ pub struct MintInfoResponse {
     pub description_long: Option<String>,
     pub contact: Option<Vec<Vec<String>>>,
     pub motd: Option<String>,
+    /// older mints may not announce their nuts
+    #[serde(default = "Nuts::unannounced")]
     pub nuts: Nuts,
 }
 
@@ -421,11 +572,11 @@ pub struct GetMeltBtcOnchainResponse {
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 pub struct Nuts {
     /// Minting tokens
-    #[serde(rename = "4")]
+    #[serde(rename = "4", default = "Nut4::unannounced")]
     pub nut4: Nut4,
 
     /// Melting tokens
-    #[serde(rename = "5")]
+    #[serde(rename = "5", default = "Nut5::unannounced")]
     pub nut5: Nut5,
 
     /// Token state check
@@ -461,6 +612,44 @@ pub struct Nuts {
     pub nut18: Option<Nut18>,
 }
 
+impl Nuts {
+    /// The nuts of a mint that doesn't announce them: no payment methods or limits and no
+    /// optional features
+    pub fn unannounced() -> Self {
+        Self {
+            nut4: Nut4::unannounced(),
+            nut5: Nut5::unannounced(),
+            nut7: None,
+            nut8: None,
+            nut9: None,
+            nut10: None,
+            nut11: None,
+            nut12: None,
+            nut17: None,
+            nut18: None,
+        }
+    }
+
+    /// Returns the numbers of the supported nuts
+    pub fn supported(&self) -> Vec<u8> {
+        [
+            (4, !self.nut4.disabled),
+            (5, !self.nut5.disabled),
+            (7, self.nut7.as_ref().is_some_and(|nut| nut.supported)),
+            (8, self.nut8.as_ref().is_some_and(|nut| nut.supported)),
+            (9, self.nut9.as_ref().is_some_and(|nut| nut.supported)),
+            (10, self.nut10.as_ref().is_some_and(|nut| nut.supported)),
+            (11, self.nut11.as_ref().is_some_and(|nut| nut.supported)),
+            (12, self.nut12.as_ref().is_some_and(|nut| nut.supported)),
+            (17, self.nut17.as_ref().is_some_and(|nut| nut.supported)),
+            (18, self.nut18.as_ref().is_some_and(|nut| nut.supported)),
+        ]
+        .into_iter()
+        .filter_map(|(nut, supported)| supported.then_some(nut))
+        .collect()
+    }
+}
+
 impl Default for Nuts {
     fn default() -> Self {
         Self {
@@ -471,7 +660,7 @@ impl Default for Nuts {
             nut9: Some(Nut9 { supported: false }),
             nut10: Some(Nut10 { supported: false }),
             nut11: Some(Nut11 { supported: false }),
-            nut12: Some(Nut12 { supported: false }),
+            nut12: Some(Nut12 { supported: true }),
             nut17: Some(Nut17::default()),
             nut18: Some(Nut18::default()),
         }
@@ -499,6 +688,15 @@ impl Default for Nut4 {
     }
 }
 
+impl Nut4 {
+    fn unannounced() -> Self {
+        Self {
+            payment_methods: vec![],
+            disabled: false,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, ToSchema)]
 pub struct Nut5 {
     #[serde(rename = "methods")]
@@ -520,6 +718,15 @@ impl Default for Nut5 {
     }
 }
 
+impl Nut5 {
+    fn unannounced() -> Self {
+        Self {
+            payment_methods: vec![],
+            disabled: false,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq, Default, ToSchema)]
 pub struct Nut7 {
     pub supported: bool,
@@ -623,7 +830,10 @@ mod tests {
     use crate::{
         dhke::public_key_from_hex,
         fixture::read_fixture,
-        primitives::{KeyResponse, MintInfoResponse, Nuts, PostSwapResponse},
+        primitives::{
+            CashuErrorCode, CashuErrorResponse, KeyResponse, MintInfoResponse, Nuts,
+            PostCheckStateResponse, PostSwapResponse, ProofState, State,
+        },
     };
 
     #[test]
@@ -634,6 +844,26 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_serialize_check_state_response() -> anyhow::Result<()> {
+        let response = PostCheckStateResponse {
+            states: vec![ProofState {
+                y: public_key_from_hex(
+                    "02599b9ea0a1ad4143706c2a5a4a568ce442dd4313e1cf1f7f0b58a317c1a355ee",
+                ),
+                state: State::Spent,
+                witness: None,
+            }],
+        };
+        let serialized = serde_json::to_string(&response)?;
+        assert_eq!(
+            serialized,
+            "{\"states\":[{\"Y\":\"02599b9ea0a1ad4143706c2a5a4a568ce442dd4313e1cf1f7f0b58a317c1a355ee\",\"state\":\"SPENT\",\"witness\":null}]}"
+        );
+        assert_eq!(response, serde_json::from_str(&serialized)?);
+        Ok(())
+    }
+
     #[test]
     fn test_serialize_keyresponse() -> anyhow::Result<()> {
         let response = KeyResponse {
@@ -695,4 +925,50 @@ mod tests {
         assert!(info.nuts.nut12.is_none());
         Ok(())
     }
+
+    #[test]
+    fn test_deserialize_mint_info_without_nuts() -> anyhow::Result<()> {
+        let mint_info = read_fixture("mint_info_without_nuts.json")?;
+        let info = serde_json::from_str::<MintInfoResponse>(&mint_info)?;
+        assert_eq!(Some("OldMint".to_owned()), info.name);
+        assert_eq!(None, info.contact);
+        assert_eq!(Nuts::unannounced(), info.nuts);
+        assert_eq!(vec![4, 5], info.nuts.supported());
+        assert_eq!(vec![4, 5, 8, 12, 17, 18], Nuts::default().supported());
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_codes() -> anyhow::Result<()> {
+        for code in CashuErrorCode::ALL {
+            assert_eq!(Ok(code), CashuErrorCode::try_from(code.code()));
+        }
+        assert_eq!(20001, CashuErrorCode::InvoiceNotPaid.code());
+        assert_eq!(Err(0), CashuErrorCode::try_from(0));
+        assert_eq!(Err(99999), CashuErrorCode::try_from(99999));
+        assert_eq!(
+            "Token is already spent",
+            CashuErrorCode::ProofAlreadyUsed.message()
+        );
+        let messages = CashuErrorCode::ALL
+            .map(CashuErrorCode::message)
+            .into_iter()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(CashuErrorCode::ALL.len(), messages.len());
+
+        let response = CashuErrorResponse::new(Some(CashuErrorCode::ProofAlreadyUsed), "spent");
+        assert_eq!(
+            r#"{"code":11001,"error":"spent"}"#,
+            serde_json::to_string(&response)?
+        );
+        assert_eq!(
+            Some(CashuErrorCode::ProofAlreadyUsed),
+            response.error_code()
+        );
+
+        let legacy: CashuErrorResponse = serde_json::from_str(r#"{"code":0,"detail":"failed"}"#)?;
+        assert_eq!(CashuErrorResponse::new(None, "failed"), legacy);
+        assert_eq!(None, legacy.error_code());
+        Ok(())
+    }
 }
@@ -10,12 +10,14 @@
 //!
 //! Both the `Proof` and `Proofs` structs are serializable and deserializable using serde.
 
+use std::collections::BTreeMap;
+
 use secp256k1::PublicKey;
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
 use utoipa::ToSchema;
 
-use crate::{error::MokshaCoreError, keyset::KeysetId};
+use crate::{dhke::Dhke, error::MokshaCoreError, keyset::KeysetId, secret::WellKnownSecret};
 
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
@@ -40,6 +42,16 @@ impl Proof {
             script: None,
         }
     }
+
+    /// Returns Y = hash_to_curve(secret), which identifies the proof towards the mint without revealing the secret
+    pub fn y(&self) -> Result<PublicKey, MokshaCoreError> {
+        Dhke::hash_to_curve(self.secret.as_bytes())
+    }
+
+    /// Returns the spending condition if the secret is a well-known secret (Nut-10)
+    pub fn well_known_secret(&self) -> Option<WellKnownSecret> {
+        self.secret.parse().ok()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
@@ -69,6 +81,10 @@ impl Proofs {
         self.0.clone()
     }
 
+    pub fn secrets(&self) -> Vec<String> {
+        self.0.iter().map(|proof| proof.secret.clone()).collect()
+    }
+
     pub fn len(&self) -> usize {
         self.0.len()
     }
@@ -77,6 +93,16 @@ impl Proofs {
         self.0.is_empty()
     }
 
+    /// Sorts the proofs by amount descending, then by secret. The order doesn't depend on how the
+    /// proofs were selected, see [`crate::token::TokenV3::canonicalize`].
+    pub fn sort_canonical(&mut self) {
+        self.0.sort_by(|a, b| {
+            b.amount
+                .cmp(&a.amount)
+                .then_with(|| a.secret.cmp(&b.secret))
+        });
+    }
+
     pub fn proofs_by_keyset(&self, keyset_id: &KeysetId) -> Self {
         self.0
             .iter()
@@ -86,31 +112,147 @@ impl Proofs {
             .into()
     }
 
+    /// Selects proofs that cover the given amount with the smallest possible overshoot, using as
+    /// few proofs as possible.
+    ///
+    /// The proofs are grouped by denomination and taken greedily from the largest denomination
+    /// down. If this doesn't match the amount exactly, the best combination is the greedy
+    /// selection of the larger denominations plus one additional proof of the denomination where
+    /// the greedy selection had to stop. This is optimal for power-of-two denominations.
     pub fn proofs_for_amount(&self, amount: u64) -> Result<Self, MokshaCoreError> {
-        let mut all_proofs = self.0.clone();
-        if amount > self.total_amount() {
-            return Err(MokshaCoreError::NotEnoughTokens);
+        let available = self.total_amount();
+        if amount > available {
+            return Err(MokshaCoreError::NotEnoughTokens {
+                needed: amount,
+                available,
+            });
         }
 
-        all_proofs.sort_by(|a, b| a.amount.cmp(&b.amount));
+        let mut denominations: BTreeMap<u64, Vec<Proof>> = BTreeMap::new();
+        for proof in &self.0 {
+            denominations
+                .entry(proof.amount)
+                .or_default()
+                .push(proof.clone());
+        }
 
         let mut selected_proofs = vec![];
-        let mut selected_amount = 0;
+        let mut remaining = amount;
+        // (total amount, number of proofs, proofs)
+        let mut best: Option<(u64, usize, Vec<Proof>)> = None;
+
+        for (denomination, proofs) in denominations.into_iter().rev() {
+            let mut proofs = proofs.into_iter();
+            while remaining >= denomination {
+                match proofs.next() {
+                    Some(proof) => {
+                        remaining -= denomination;
+                        selected_proofs.push(proof);
+                    }
+                    None => break,
+                }
+            }
 
-        while selected_amount < amount {
-            if all_proofs.is_empty() {
-                break;
+            if remaining == 0 {
+                return Ok(selected_proofs.into());
             }
 
-            let proof = all_proofs.pop().expect("proofs is empty");
-            selected_amount += proof.amount;
-            selected_proofs.push(proof);
+            if let Some(proof) = proofs.next() {
+                let total = amount - remaining + denomination;
+                let count = selected_proofs.len() + 1;
+                if best.as_ref().map_or(true, |(best_total, best_count, _)| {
+                    (total, count) < (*best_total, *best_count)
+                }) {
+                    let mut candidate = selected_proofs.clone();
+                    candidate.push(proof);
+                    best = Some((total, count, candidate));
+                }
+            }
         }
 
-        Ok(selected_proofs.into())
+        best.map(|(_, _, proofs)| proofs.into())
+            .ok_or(MokshaCoreError::NotEnoughTokens {
+                needed: amount,
+                available,
+            })
+    }
+
+    /// Selects proofs that sum up to exactly the given amount, so they can be sent without a
+    /// swap. Returns `None` if there is no such combination.
+    ///
+    /// The selection of [`Self::proofs_for_amount`] is exact whenever possible for power-of-two
+    /// denominations. For other denominations the combinations of the proofs are searched, up to
+    /// [`EXACT_SEARCH_LIMIT`] steps.
+    pub fn exact_proofs_for_amount(&self, amount: u64) -> Option<Self> {
+        if let Ok(proofs) = self.proofs_for_amount(amount) {
+            if proofs.total_amount() == amount {
+                return Some(proofs);
+            }
+        }
+
+        let mut denominations: BTreeMap<u64, Vec<Proof>> = BTreeMap::new();
+        for proof in &self.0 {
+            denominations
+                .entry(proof.amount)
+                .or_default()
+                .push(proof.clone());
+        }
+        let denominations = denominations.into_iter().rev().collect::<Vec<_>>();
+        // total amount of the denominations from the index on
+        let mut rest = vec![0; denominations.len() + 1];
+        for (index, (denomination, proofs)) in denominations.iter().enumerate().rev() {
+            rest[index] = rest[index + 1] + denomination * proofs.len() as u64;
+        }
+
+        let mut counts = vec![0; denominations.len()];
+        let mut steps = 0;
+        if !search_exact(&denominations, &rest, &mut counts, 0, amount, &mut steps) {
+            return None;
+        }
+        Some(
+            denominations
+                .into_iter()
+                .zip(counts)
+                .flat_map(|((_, proofs), count)| proofs.into_iter().take(count))
+                .collect::<Vec<_>>()
+                .into(),
+        )
     }
 }
 
+/// Maximum number of steps [`Proofs::exact_proofs_for_amount`] searches for a combination
+pub const EXACT_SEARCH_LIMIT: usize = 100_000;
+
+/// Chooses the number of proofs of every denomination, the largest counts first
+fn search_exact(
+    denominations: &[(u64, Vec<Proof>)],
+    rest: &[u64],
+    counts: &mut [usize],
+    index: usize,
+    remaining: u64,
+    steps: &mut usize,
+) -> bool {
+    if remaining == 0 {
+        return true;
+    }
+    *steps += 1;
+    if index == denominations.len() || rest[index] < remaining || *steps > EXACT_SEARCH_LIMIT {
+        return false;
+    }
+
+    let (denomination, proofs) = &denominations[index];
+    let max = proofs.len().min((remaining / denomination) as usize);
+    for count in (0..=max).rev() {
+        counts[index] = count;
+        let remaining = remaining - denomination * count as u64;
+        if search_exact(denominations, rest, counts, index + 1, remaining, steps) {
+            return true;
+        }
+    }
+    counts[index] = 0;
+    false
+}
+
 impl From<Vec<Proof>> for Proofs {
     fn from(from: Vec<Proof>) -> Self {
         Self(from)
@@ -128,6 +270,7 @@ mod tests {
     use serde_json::json;
 
     use crate::{
+        error::MokshaCoreError,
         fixture::read_fixture,
         proof::{Proof, Proofs},
         token::TokenV3,
@@ -141,25 +284,141 @@ mod tests {
         let result = proofs.proofs_for_amount(10);
 
         assert!(result.is_err());
-        assert!(result
-            .err()
-            .unwrap()
-            .to_string()
-            .contains("Not enough tokens"));
+        assert_eq!(
+            "Not enough tokens: need 10, have 0",
+            result.err().unwrap().to_string()
+        );
         Ok(())
     }
 
+    #[test]
+    fn test_proofs_for_amount_shortfall() {
+        let proofs = proofs_with_amounts(&[4, 8]);
+
+        let result = proofs.proofs_for_amount(21);
+
+        assert!(matches!(
+            result,
+            Err(MokshaCoreError::NotEnoughTokens {
+                needed: 21,
+                available: 12
+            })
+        ));
+        assert_eq!(
+            "Not enough tokens: need 21, have 12",
+            result.err().unwrap().to_string()
+        );
+    }
+
     #[test]
     fn test_proofs_for_amount_valid() -> anyhow::Result<()> {
         let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
         let token: TokenV3 = fixture.try_into()?;
 
         let result = token.proofs().proofs_for_amount(10)?;
+        assert_eq!(12, result.total_amount());
+        assert_eq!(2, result.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_proofs_for_amount_exact_match() -> anyhow::Result<()> {
+        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
+        let token: TokenV3 = fixture.try_into()?;
+
+        let result = token.proofs().proofs_for_amount(28)?;
+        assert_eq!(28, result.total_amount());
+        let mut amounts = result.proofs().iter().map(|p| p.amount).collect::<Vec<_>>();
+        amounts.sort();
+        assert_eq!(vec![4, 8, 16], amounts);
+        Ok(())
+    }
+
+    #[test]
+    fn test_proofs_for_amount_minimal_overshoot() -> anyhow::Result<()> {
+        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
+        let token: TokenV3 = fixture.try_into()?;
+
+        let result = token.proofs().proofs_for_amount(30)?;
         assert_eq!(32, result.total_amount());
         assert_eq!(1, result.len());
+
+        let result = token.proofs().proofs_for_amount(33)?;
+        assert_eq!(36, result.total_amount());
+        assert_eq!(2, result.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_proofs_for_amount_prefers_smaller_denominations() -> anyhow::Result<()> {
+        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
+        let token: TokenV3 = fixture.try_into()?;
+
+        let result = token.proofs().proofs_for_amount(10)?;
+        assert_eq!(vec![8, 4], amounts(&result));
+
+        let proofs = proofs_with_amounts(&[4, 4, 1]);
+        let result = proofs.proofs_for_amount(6)?;
+        assert_eq!(vec![4, 4], amounts(&result));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_proofs_for_amount() -> anyhow::Result<()> {
+        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
+        let token: TokenV3 = fixture.try_into()?;
+        let result = token.proofs().exact_proofs_for_amount(28);
+        assert_eq!(Some(vec![16, 8, 4]), result.as_ref().map(amounts));
+        assert_eq!(None, token.proofs().exact_proofs_for_amount(30));
+        assert_eq!(None, token.proofs().exact_proofs_for_amount(61));
+
+        // the greedy selection takes the 5 and misses the combination
+        let proofs = proofs_with_amounts(&[5, 3, 3]);
+        let result = proofs.exact_proofs_for_amount(6);
+        assert_eq!(Some(vec![3, 3]), result.as_ref().map(amounts));
+        assert_eq!(
+            Some(vec![]),
+            proofs.exact_proofs_for_amount(0).as_ref().map(amounts)
+        );
         Ok(())
     }
 
+    #[test]
+    fn test_proofs_for_amount_single_larger_proof() -> anyhow::Result<()> {
+        let proofs = proofs_with_amounts(&[1, 2, 64]);
+
+        let result = proofs.proofs_for_amount(10)?;
+        assert_eq!(vec![64], amounts(&result));
+
+        let proofs = proofs_with_amounts(&[8, 8, 16]);
+        let result = proofs.proofs_for_amount(9)?;
+        assert_eq!(vec![16], amounts(&result));
+        Ok(())
+    }
+
+    fn amounts(proofs: &Proofs) -> Vec<u64> {
+        proofs.proofs().iter().map(|p| p.amount).collect()
+    }
+
+    fn proofs_with_amounts(amounts: &[u64]) -> Proofs {
+        let c = "02c020067db727d586bc3183aecf97fcb800c3f4cc4759f69c626c9db5d8f5b5d4"
+            .parse()
+            .expect("invalid pubkey");
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(i, amount)| {
+                Proof::new(
+                    *amount,
+                    format!("secret{i}"),
+                    c,
+                    "00ffd48b8f5ecf80".to_owned(),
+                )
+            })
+            .collect::<Vec<Proof>>()
+            .into()
+    }
+
     #[test]
     fn test_proof() -> anyhow::Result<()> {
         let js = json!(
@@ -0,0 +1,110 @@
+//! This module defines well-known secrets as described in [Nut-10](https://github.com/cashubtc/nuts/blob/main/10.md)
+//!
+//! A well-known secret is serialized as a JSON array `[kind, {"nonce", "data", "tags"}]` and stored
+//! in the `secret` field of a proof. Currently only the `P2PK` kind from [Nut-11](https://github.com/cashubtc/nuts/blob/main/11.md) is supported.
+
+use std::{fmt::Display, str::FromStr};
+
+use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::error::MokshaCoreError;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SecretKind {
+    P2PK,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SecretData {
+    pub nonce: String,
+    /// for P2PK the hex-encoded public key the proof is locked to
+    pub data: String,
+    #[serde(default)]
+    pub tags: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WellKnownSecret(pub SecretKind, pub SecretData);
+
+impl WellKnownSecret {
+    /// Creates a P2PK secret that can be spent by anyone after the given unix timestamp
+    pub fn p2pk_with_locktime(pubkey: &PublicKey, locktime: u64) -> Self {
+        Self(
+            SecretKind::P2PK,
+            SecretData {
+                nonce: Uuid::new_v4().simple().to_string(),
+                data: pubkey.to_string(),
+                tags: vec![vec!["locktime".to_owned(), locktime.to_string()]],
+            },
+        )
+    }
+
+    pub fn kind(&self) -> &SecretKind {
+        &self.0
+    }
+
+    /// Returns the value of the `locktime` tag
+    pub fn locktime(&self) -> Option<u64> {
+        self.1
+            .tags
+            .iter()
+            .find(|tag| tag.first().map(String::as_str) == Some("locktime"))
+            .and_then(|tag| tag.get(1))
+            .and_then(|locktime| locktime.parse().ok())
+    }
+}
+
+impl FromStr for WellKnownSecret {
+    type Err = MokshaCoreError;
+
+    fn from_str(secret: &str) -> Result<Self, Self::Err> {
+        Ok(serde_json::from_str(secret)?)
+    }
+}
+
+impl Display for WellKnownSecret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| std::fmt::Error)?;
+        write!(f, "{json}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use secp256k1::PublicKey;
+
+    use crate::secret::{SecretKind, WellKnownSecret};
+
+    #[test]
+    fn test_deserialize_p2pk_secret() -> anyhow::Result<()> {
+        let secret = r#"["P2PK",{"nonce":"da62796403af76c80cd6ce9153ed3746","data":"033281c37677ea273eb7183b783067f5244933ef78d8c3f15b1a77cb246099c26e","tags":[["sigflag","SIG_ALL"],["locktime","1689418329"]]}]"#;
+
+        let secret = WellKnownSecret::from_str(secret)?;
+        assert_eq!(&SecretKind::P2PK, secret.kind());
+        assert_eq!(Some(1689418329), secret.locktime());
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize_p2pk_with_locktime() -> anyhow::Result<()> {
+        let pubkey = PublicKey::from_str(
+            "02c020067db727d586bc3183aecf97fcb800c3f4cc4759f69c626c9db5d8f5b5d4",
+        )?;
+        let secret = WellKnownSecret::p2pk_with_locktime(&pubkey, 1_700_000_000);
+
+        let serialized = secret.to_string();
+        assert!(serialized.starts_with(r#"["P2PK",{"nonce":""#));
+        assert_eq!(secret, WellKnownSecret::from_str(&serialized)?);
+        assert_eq!(Some(1_700_000_000), secret.locktime());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plain_secret_is_not_well_known() {
+        assert!(WellKnownSecret::from_str("EhpennC9qB3iFlW8FZ_pZw").is_err());
+    }
+}
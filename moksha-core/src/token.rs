@@ -1,22 +1,35 @@
 //! This module defines the `Token` struct, which is used for representing tokens in Cashu as described in [Nut-00](https://github.com/cashubtc/nuts/blob/main/00.md)
 //!
 //! The `Token` struct represents a token, with an optional `mint` field for the URL of the Mint and a `proofs` field for the proofs associated with the token.
+//!
+//! `TokenV3` is the json based `cashuA` format. `TokenV4` is the compact cbor based `cashuB` format, which is converted to a `TokenV3` when it is received.
 
-use std::str::FromStr;
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
 
 use base64::{engine::general_purpose, Engine as _};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_with::skip_serializing_none;
 use url::Url;
 
-use crate::{error::MokshaCoreError, primitives::CurrencyUnit, proof::Proofs};
+use crate::{
+    cbor::Value,
+    error::MokshaCoreError,
+    inspect::TokenReport,
+    primitives::CurrencyUnit,
+    proof::{Proof, Proofs},
+};
 
-const TOKEN_PREFIX_V3: &str = "cashuA";
+pub const TOKEN_PREFIX_V3: &str = "cashuA";
+pub const TOKEN_PREFIX_V4: &str = "cashuB";
 
 #[skip_serializing_none]
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Token {
-    #[serde(serialize_with = "serialize_url", deserialize_with = "deserialize_url")]
+    #[serde(
+        default,
+        serialize_with = "serialize_url",
+        deserialize_with = "deserialize_url"
+    )]
     pub mint: Option<Url>,
     pub proofs: Proofs,
 }
@@ -67,6 +80,15 @@ impl TokenV3 {
         }
     }
 
+    /// Creates a token of a single mint. Fails if there are no proofs, use [`TokenV3::empty`]
+    /// for a token without value.
+    pub fn from_proofs(mint_url: &Url, proofs: Proofs) -> Result<Self, MokshaCoreError> {
+        if proofs.is_empty() {
+            return Err(MokshaCoreError::EmptyToken);
+        }
+        Ok((mint_url.to_owned(), proofs).into())
+    }
+
     pub const fn empty() -> Self {
         Self {
             tokens: vec![],
@@ -98,8 +120,19 @@ impl TokenV3 {
         )
     }
 
+    /// Sorts the proofs of every entry in the canonical order, see [`Proofs::sort_canonical`].
+    /// The order of the entries is kept.
+    pub fn canonicalize(mut self) -> Self {
+        for token in self.tokens.iter_mut() {
+            token.proofs.sort_canonical();
+        }
+        self
+    }
+
+    /// Serializes the token with the proofs in the canonical order, so the same proofs always
+    /// result in the same string. [`TokenV3::deserialize`] keeps the order of the string.
     pub fn serialize(&self) -> Result<String, MokshaCoreError> {
-        let json = serde_json::to_string(&self)?;
+        let json = serde_json::to_string(&self.clone().canonicalize())?;
         Ok(format!(
             "{}{}",
             TOKEN_PREFIX_V3,
@@ -107,8 +140,12 @@ impl TokenV3 {
         ))
     }
 
+    /// Deserializes a `cashuA` token. `cashuB` tokens are converted from a [`TokenV4`].
     pub fn deserialize(data: impl Into<String>) -> Result<Self, MokshaCoreError> {
         let data = data.into();
+        if data.starts_with(TOKEN_PREFIX_V4) {
+            return TokenV4::deserialize(data).map(Into::into);
+        }
         let token = data
             .strip_prefix(TOKEN_PREFIX_V3)
             .ok_or(MokshaCoreError::InvalidTokenPrefix)?;
@@ -127,6 +164,11 @@ impl TokenV3 {
             .and_then(|token| token.mint.as_ref())
             .map(|url| url.to_owned())
     }
+
+    /// Summarizes the token without contacting the mint, see [`TokenReport`]
+    pub fn inspect(&self) -> TokenReport {
+        TokenReport::from_token(self)
+    }
 }
 
 impl TryFrom<TokenV3> for String {
@@ -179,22 +221,218 @@ impl From<(Url, CurrencyUnit, Proofs)> for TokenV3 {
     }
 }
 
+/// A token of a single mint and unit in the compact `cashuB` format
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenV4 {
+    pub mint: Url,
+    pub unit: CurrencyUnit,
+    pub memo: Option<String>,
+    pub proofs: Proofs,
+}
+
+impl TokenV4 {
+    pub fn total_amount(&self) -> u64 {
+        self.proofs.total_amount()
+    }
+
+    /// Fails if a keyset id of the proofs is not hex encoded
+    pub fn serialize(&self) -> Result<String, MokshaCoreError> {
+        let mut keysets = BTreeMap::<&str, Vec<&Proof>>::new();
+        for proof in self.proofs.0.iter() {
+            keysets.entry(&proof.keyset_id).or_default().push(proof);
+        }
+        let keysets = keysets
+            .into_iter()
+            .map(|(keyset_id, proofs)| {
+                let proofs = proofs
+                    .into_iter()
+                    .map(|proof| {
+                        Value::map(vec![
+                            ("a", Value::Unsigned(proof.amount)),
+                            ("s", Value::Text(proof.secret.clone())),
+                            ("c", Value::Bytes(proof.c.serialize().to_vec())),
+                        ])
+                    })
+                    .collect();
+                Ok(Value::map(vec![
+                    ("i", Value::Bytes(hex::decode(keyset_id)?)),
+                    ("p", Value::Array(proofs)),
+                ]))
+            })
+            .collect::<Result<Vec<_>, MokshaCoreError>>()?;
+
+        let mut mint = self.mint.as_str().to_owned();
+        if mint.ends_with('/') {
+            mint.pop();
+        }
+        let mut token = vec![("t", Value::Array(keysets))];
+        if let Some(ref memo) = self.memo {
+            token.push(("d", Value::Text(memo.clone())));
+        }
+        token.push(("m", Value::Text(mint)));
+        token.push(("u", Value::Text(self.unit.to_string())));
+
+        Ok(format!(
+            "{}{}",
+            TOKEN_PREFIX_V4,
+            general_purpose::URL_SAFE.encode(Value::map(token).encode())
+        ))
+    }
+
+    pub fn deserialize(data: impl Into<String>) -> Result<Self, MokshaCoreError> {
+        let data = data.into();
+        let token = data
+            .strip_prefix(TOKEN_PREFIX_V4)
+            .ok_or(MokshaCoreError::InvalidTokenPrefix)?;
+        let bytes = general_purpose::URL_SAFE_NO_PAD
+            .decode(token.as_bytes())
+            .or_else(|_| general_purpose::URL_SAFE.decode(token.as_bytes()))
+            .map_err(|_| MokshaCoreError::InvalidToken)?;
+        let token = Value::decode(&bytes)?;
+
+        let text = |value: &Value, key: &str| {
+            value
+                .get(key)
+                .and_then(Value::as_text)
+                .map(ToOwned::to_owned)
+                .ok_or(MokshaCoreError::InvalidToken)
+        };
+        let mint = Url::parse(&text(&token, "m")?).map_err(|_| MokshaCoreError::InvalidToken)?;
+        let unit = serde_json::from_value(serde_json::Value::String(text(&token, "u")?))
+            .map_err(|_| MokshaCoreError::InvalidToken)?;
+        let memo = token.get("d").map(|_| text(&token, "d")).transpose()?;
+
+        let mut proofs = vec![];
+        for keyset in token
+            .get("t")
+            .and_then(Value::as_array)
+            .ok_or(MokshaCoreError::InvalidToken)?
+        {
+            let keyset_id = keyset
+                .get("i")
+                .and_then(Value::as_bytes)
+                .map(hex::encode)
+                .ok_or(MokshaCoreError::InvalidToken)?;
+            for proof in keyset
+                .get("p")
+                .and_then(Value::as_array)
+                .ok_or(MokshaCoreError::InvalidToken)?
+            {
+                let amount = proof
+                    .get("a")
+                    .and_then(Value::as_unsigned)
+                    .ok_or(MokshaCoreError::InvalidToken)?;
+                let c = proof
+                    .get("c")
+                    .and_then(Value::as_bytes)
+                    .ok_or(MokshaCoreError::InvalidToken)?;
+                proofs.push(Proof::new(
+                    amount,
+                    text(proof, "s")?,
+                    secp256k1::PublicKey::from_slice(c)?,
+                    keyset_id.clone(),
+                ));
+            }
+        }
+
+        Ok(Self {
+            mint,
+            unit,
+            memo,
+            proofs: proofs.into(),
+        })
+    }
+}
+
+impl Display for TokenV4 {
+    /// Fails if a keyset id of the proofs is not hex encoded, see [`TokenV4::serialize`]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.serialize().map_err(|_| std::fmt::Error)?)
+    }
+}
+
+impl TryFrom<String> for TokenV4 {
+    type Error = MokshaCoreError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::deserialize(value)
+    }
+}
+
+impl FromStr for TokenV4 {
+    type Err = MokshaCoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::deserialize(s)
+    }
+}
+
+impl From<TokenV4> for TokenV3 {
+    fn from(token: TokenV4) -> Self {
+        Self {
+            tokens: vec![Token {
+                mint: Some(token.mint),
+                proofs: token.proofs,
+            }],
+            currency_unit: Some(token.unit),
+            memo: token.memo,
+        }
+    }
+}
+
+impl TryFrom<TokenV3> for TokenV4 {
+    type Error = MokshaCoreError;
+
+    /// Fails if the proofs don't belong to exactly one mint
+    fn try_from(token: TokenV3) -> Result<Self, Self::Error> {
+        let mint = token.mint().ok_or(MokshaCoreError::InvalidToken)?;
+        if token.tokens.iter().any(|t| t.mint.as_ref() != Some(&mint)) {
+            return Err(MokshaCoreError::InvalidToken);
+        }
+        Ok(Self {
+            proofs: token.proofs(),
+            mint,
+            unit: token.currency_unit.unwrap_or(CurrencyUnit::Sat),
+            memo: token.memo,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
 
+    use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
     use serde_json::{json, Value};
     use url::Url;
 
     use crate::{
         dhke,
+        error::MokshaCoreError,
         fixture::read_fixture,
         primitives::CurrencyUnit,
-        proof::Proof,
-        token::{Token, TokenV3},
+        proof::{Proof, Proofs},
+        token::{Token, TokenV3, TokenV4},
     };
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn test_token_from_proofs() -> anyhow::Result<()> {
+        let mint_url = Url::parse("http://localhost:3338")?;
+        let proofs = TokenV3::deserialize(read_fixture("token_60.cashu")?)?.proofs();
+
+        let token = TokenV3::from_proofs(&mint_url, proofs.clone())?;
+        assert_eq!(TokenV3::from((mint_url.clone(), proofs)), token);
+        assert_eq!(60, token.total_amount());
+        assert_eq!(Some(mint_url.clone()), token.mint());
+
+        assert!(matches!(
+            TokenV3::from_proofs(&mint_url, Proofs::empty()),
+            Err(MokshaCoreError::EmptyToken)
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_token_v3() -> anyhow::Result<()> {
         let js = json!(
@@ -232,9 +470,11 @@ mod tests {
         assert_eq!(token.memo, Some("Thank you.".to_string()));
         assert_eq!(token.total_amount(), 10);
 
+        // the proofs are serialized by amount descending
         let token_serialized = token.serialize()?;
-        let fixture = read_fixture("token_nut_example.cashu")?;
+        let fixture = read_fixture("token_nut_example_canonical.cashu")?;
         assert_eq!(token_serialized, fixture);
+        assert_eq!(token.canonicalize(), TokenV3::deserialize(fixture)?);
         Ok(())
     }
 
@@ -302,12 +542,106 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_token_memo_roundtrip() -> anyhow::Result<()> {
+        use base64::{engine::general_purpose, Engine as _};
+        let token = TokenV3 {
+            memo: Some("for the pizza".to_owned()),
+            ..read_fixture("token_60.cashu")?.try_into()?
+        };
+        let decoded = TokenV3::deserialize(token.serialize()?)?;
+        assert_eq!(Some("for the pizza".to_owned()), decoded.memo);
+        assert_eq!(token.clone().canonicalize(), decoded);
+
+        // tokens without a memo are serialized like before
+        let token = TokenV3 {
+            memo: None,
+            ..token
+        };
+        let json =
+            general_purpose::URL_SAFE.decode(token.serialize()?.strip_prefix("cashuA").unwrap())?;
+        assert!(serde_json::from_slice::<Value>(&json)?
+            .get("memo")
+            .is_none());
+        assert_eq!(
+            token.clone().canonicalize(),
+            TokenV3::deserialize(token.serialize()?)?
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_tokens_deserialize() -> anyhow::Result<()> {
         let input = read_fixture("token_nut_example.cashu")?;
         let tokens = TokenV3::from_str(&input)?;
         assert_eq!(tokens.memo, Some("Thank you.".to_string()),);
         assert_eq!(tokens.tokens.len(), 1);
+        // the order of the proofs in the string is kept
+        assert_eq!(
+            vec![2, 8],
+            tokens.tokens[0]
+                .proofs
+                .proofs()
+                .iter()
+                .map(|proof| proof.amount)
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_serialize_independent_of_proof_order() -> anyhow::Result<()> {
+        let token = TokenV3::deserialize(read_fixture("token_60.cashu")?)?;
+        let mut reversed = token.clone();
+        let mut proofs = token.proofs().proofs();
+        proofs.reverse();
+        reversed.tokens[0].proofs = proofs.into();
+
+        assert_ne!(token, reversed);
+        assert_eq!(token.serialize()?, reversed.serialize()?);
+        assert_eq!(
+            vec![32, 16, 8, 4],
+            TokenV3::deserialize(token.serialize()?)?
+                .proofs()
+                .proofs()
+                .iter()
+                .map(|proof| proof.amount)
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonicalize_idempotent_and_order_insensitive() -> anyhow::Result<()> {
+        let mut rng = StdRng::seed_from_u64(273);
+        let c = dhke::public_key_from_hex(
+            "02c020067db727d586bc3183aecf97fcb800c3f4cc4759f69c626c9db5d8f5b5d4",
+        );
+        for _ in 0..100 {
+            // few distinct amounts, so many proofs are ordered by their secret
+            let mut proofs = (0..rng.gen_range(0..12))
+                .map(|_| {
+                    Proof::new(
+                        1 << rng.gen_range(0..3u32),
+                        format!("{:x}", rng.gen::<u32>()),
+                        c,
+                        "009a1f293253e41e".to_owned(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            let token = TokenV3::from((
+                Url::parse("https://8333.space:3338")?,
+                Proofs::new(proofs.clone()),
+            ));
+            let canonical = token.clone().canonicalize();
+            assert_eq!(canonical, canonical.clone().canonicalize());
+
+            proofs.shuffle(&mut rng);
+            let shuffled =
+                TokenV3::from((Url::parse("https://8333.space:3338")?, Proofs::new(proofs)));
+            assert_eq!(canonical, shuffled.clone().canonicalize());
+            assert_eq!(token.serialize()?, shuffled.serialize()?);
+        }
         Ok(())
     }
 
@@ -343,4 +677,64 @@ mod tests {
         assert!(tokens.memo.is_none());
         Ok(())
     }
+
+    #[test]
+    fn test_token_v4_deserialize() -> anyhow::Result<()> {
+        let token = TokenV4::from_str(&read_fixture("token_v4_nut_example.cashu")?)?;
+        assert_eq!(token.mint, Url::parse("http://localhost:3338")?);
+        assert_eq!(token.unit, CurrencyUnit::Sat);
+        assert_eq!(token.memo, Some("Thank you".to_string()));
+        assert_eq!(token.total_amount(), 1);
+
+        let proof = token.proofs.proofs()[0].clone();
+        assert_eq!(proof.keyset_id, "00ad268c4d1f5826");
+        assert_eq!(
+            proof.secret,
+            "9a6dbb847bd232ba76db0df197216b29d3b8cc14553cd27827fc1cc942fedb4e"
+        );
+        assert_eq!(
+            proof.c.to_string(),
+            "038618543ffb6b8695df4ad4babcde92a34a96bdcd97dcee0d7ccf98d472126792"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_v4_roundtrip() -> anyhow::Result<()> {
+        let input = read_fixture("token_v4_nut_example.cashu")?;
+        let token = TokenV4::try_from(input.clone())?;
+        assert_eq!(token.to_string(), input);
+        assert_eq!(TokenV4::deserialize(token.serialize()?)?, token);
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_v3_from_v4() -> anyhow::Result<()> {
+        let input = read_fixture("token_v4_nut_example.cashu")?;
+        let token = TokenV3::deserialize(input.clone())?;
+        assert_eq!(token.memo, Some("Thank you".to_string()));
+        assert_eq!(token.currency_unit, Some(CurrencyUnit::Sat));
+        assert_eq!(token.mint(), Some(Url::parse("http://localhost:3338")?));
+        assert_eq!(token.total_amount(), 1);
+
+        assert_eq!(TokenV4::try_from(token)?.to_string(), input);
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_v4_from_multiple_mints() -> anyhow::Result<()> {
+        let mut token = TokenV3::deserialize(read_fixture("token_v4_nut_example.cashu")?)?;
+        token.tokens.push(Token {
+            mint: Some(Url::parse("https://8333.space:3338")?),
+            proofs: token.proofs(),
+        });
+        assert!(TokenV4::try_from(token).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_v4_deserialize_invalid() {
+        assert!(TokenV4::deserialize("cashuAeyJ0b2tlbiI6W119").is_err());
+        assert!(TokenV4::deserialize("cashuBoA").is_err());
+    }
 }
@@ -0,0 +1,111 @@
+//! Micro-benchmarks of the hot paths of a swap: signing the outputs and looking up the used
+//! proofs.
+//!
+//! Needs a postgres database: `MINT_DB_URL=postgres://... cargo bench -p moksha-mint`. The used
+//! proofs are added in a transaction that is rolled back, so the database stays unchanged.
+
+use std::time::{Duration, Instant};
+
+use moksha_core::{
+    amount::Amount, blind::BlindedMessage, blind::BlindingFactor, dhke::Dhke, proof::Proof,
+    proof::Proofs,
+};
+use mokshamint::{
+    config::DatabaseConfig,
+    database::Database,
+    lightning::{lnbits::LnbitsLightningSettings, LightningType},
+    mint::MintBuilder,
+};
+use secp256k1::SecretKey;
+
+/// Number of used proofs in the database during the lookup benchmark
+const USED_PROOFS: usize = 10_000;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let Ok(db_url) = std::env::var("MINT_DB_URL") else {
+        eprintln!("MINT_DB_URL is not set, skipping the benchmarks");
+        return Ok(());
+    };
+    let mint = MintBuilder::new()
+        .with_private_key("moksha-bench".to_owned())
+        .with_db(Some(DatabaseConfig {
+            db_url,
+            ..Default::default()
+        }))
+        .with_lightning(LightningType::Lnbits(LnbitsLightningSettings::new(
+            "bench",
+            "http://127.0.0.1:6100",
+        )))
+        .with_fee(Some((0.0, 0).into()))
+        .build()
+        .await?;
+    let keyset = mint.keyset();
+    let dhke = Dhke::new();
+    let blinding_factor: BlindingFactor = SecretKey::from_slice(&[1; 32])?.into();
+
+    let outputs = Amount(1_000)
+        .split()
+        .into_iter()
+        .enumerate()
+        .map(|(i, amount)| {
+            Ok(BlindedMessage {
+                amount,
+                b_: dhke.step1_alice(format!("output-{i}"), &blinding_factor)?,
+                id: keyset.keyset_id.clone(),
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    bench(
+        &format!("create_blinded_signatures ({} outputs)", outputs.len()),
+        1_000,
+        || {
+            mint.create_blinded_signatures(&outputs, &keyset)
+                .expect("signing failed");
+        },
+    );
+
+    let c = Dhke::hash_to_curve(b"used-proof")?;
+    let create_proofs = |prefix: &str, count: usize| -> Proofs {
+        (0..count)
+            .map(|i| Proof::new(1, format!("{prefix}-{i}"), c, keyset.keyset_id.clone()))
+            .collect::<Vec<_>>()
+            .into()
+    };
+    let mut tx = mint.db.begin_tx().await?;
+    mint.db
+        .add_used_proofs_if_unused(&mut tx, &create_proofs("used", USED_PROOFS))
+        .await?;
+    let inputs = create_proofs("input", 8);
+    let iterations = 500;
+    let start = Instant::now();
+    for _ in 0..iterations {
+        mint.check_used_proofs(&mut tx, &inputs).await?;
+    }
+    report(
+        &format!("check_used_proofs (8 inputs, {USED_PROOFS} used)"),
+        iterations,
+        start.elapsed(),
+    );
+    tx.rollback().await?;
+    Ok(())
+}
+
+fn bench(name: &str, iterations: u32, f: impl Fn()) {
+    // warm up
+    for _ in 0..iterations / 10 {
+        f();
+    }
+    let start = Instant::now();
+    for _ in 0..iterations {
+        f();
+    }
+    report(name, iterations, start.elapsed());
+}
+
+fn report(name: &str, iterations: u32, elapsed: Duration) {
+    println!(
+        "{name:<50} {:>10.1} µs/iter ({iterations} iterations)",
+        elapsed.as_secs_f64() * 1_000_000.0 / iterations as f64
+    );
+}
@@ -0,0 +1,7 @@
+-- signatures issued for blinded messages, so wallets can restore their proofs (NUT-09)
+CREATE TABLE blind_signatures (
+    b_ TEXT NOT NULL PRIMARY KEY,
+    amount BIGINT NOT NULL,
+    c_ TEXT NOT NULL,
+    keyset_id TEXT NOT NULL
+);
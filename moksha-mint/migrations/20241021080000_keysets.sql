@@ -0,0 +1,6 @@
+-- keysets of the mint. New outputs are signed with the active keyset, proofs of all keysets are accepted.
+CREATE TABLE keysets (
+    keyset_id TEXT NOT NULL PRIMARY KEY,
+    derivation_path TEXT NOT NULL,
+    active BOOLEAN NOT NULL
+);
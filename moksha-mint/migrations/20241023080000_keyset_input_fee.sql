@@ -0,0 +1,2 @@
+-- fee in parts per thousand per input proof of the keyset (NUT-02)
+ALTER TABLE keysets ADD COLUMN input_fee_ppk BIGINT NOT NULL DEFAULT 0;
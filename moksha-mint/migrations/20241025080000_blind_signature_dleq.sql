@@ -0,0 +1,3 @@
+-- DLEQ proofs of the signatures (NUT-12), missing for signatures issued before they were added
+ALTER TABLE blind_signatures ADD COLUMN dleq_e TEXT;
+ALTER TABLE blind_signatures ADD COLUMN dleq_s TEXT;
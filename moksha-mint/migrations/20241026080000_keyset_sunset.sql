@@ -0,0 +1,2 @@
+-- unix timestamp (seconds) after which proofs of a deactivated keyset are rejected
+ALTER TABLE keysets ADD COLUMN sunset_at BIGINT;
@@ -0,0 +1,8 @@
+-- unix timestamps (seconds), existing invoices expire one day after the migration
+ALTER TABLE pending_invoices
+    ADD COLUMN created_at BIGINT NOT NULL DEFAULT extract(epoch FROM now())::BIGINT,
+    ADD COLUMN expires_at BIGINT NOT NULL DEFAULT extract(epoch FROM now())::BIGINT + 86400;
+ALTER TABLE pending_invoices
+    ALTER COLUMN created_at DROP DEFAULT,
+    ALTER COLUMN expires_at DROP DEFAULT;
+CREATE INDEX pending_invoices_expires_at ON pending_invoices (expires_at);
@@ -0,0 +1,4 @@
+-- the admin api pages through the redeemed proofs by the time they were redeemed, the newest first
+ALTER TABLE used_proofs ADD COLUMN id BIGSERIAL NOT NULL;
+ALTER TABLE used_proofs ADD COLUMN created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM NOW())::BIGINT;
+CREATE UNIQUE INDEX used_proofs_created_at_id ON used_proofs (created_at, id);
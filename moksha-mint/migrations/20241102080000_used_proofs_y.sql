@@ -0,0 +1,3 @@
+-- the state of proofs is checked by their Y, the column is filled for existing rows at startup
+ALTER TABLE used_proofs ADD COLUMN y TEXT;
+CREATE INDEX used_proofs_y ON used_proofs (y);
@@ -28,8 +28,11 @@ pub async fn main() -> anyhow::Result<()> {
     let MintConfig {
         privatekey,
         derivation_path,
+        input_fee_ppk,
+        max_outstanding,
         info,
         lightning_fee,
+        pending_invoice,
         server,
         btconchain_backend,
         lightning_backend,
@@ -44,10 +47,13 @@ pub async fn main() -> anyhow::Result<()> {
         .with_server(Some(server))
         .with_private_key(privatekey)
         .with_derivation_path(derivation_path)
+        .with_input_fee_ppk(input_fee_ppk)
+        .with_max_outstanding(max_outstanding)
         .with_db(Some(database))
         .with_lightning(lightning_backend.expect("lightning not set"))
         .with_btc_onchain(btconchain_backend)
         .with_fee(Some(lightning_fee))
+        .with_pending_invoice(Some(pending_invoice))
         .with_tracing(tracing)
         .build()
         .await;
@@ -0,0 +1,23 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[cfg(test)]
+use mockall::automock;
+
+/// Source of the current time, so time dependent checks can be tested
+#[cfg_attr(test, automock)]
+pub trait Clock: Send + Sync {
+    /// Returns the current unix timestamp in seconds
+    fn now(&self) -> u64;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time is before unix epoch")
+            .as_secs()
+    }
+}
@@ -18,11 +18,19 @@ pub struct Opts {
     pub privatekey: String,
     #[clap(long, env = "MINT_DERIVATION_PATH")]
     pub derivation_path: Option<String>,
+    /// fee in parts per thousand per input proof of the active keyset (NUT-02)
+    #[clap(long, default_value_t = 0, env = "MINT_INPUT_FEE_PPK")]
+    pub input_fee_ppk: u64,
+    /// maximum amount of ecash in sats that may be outstanding, unlimited if not set
+    #[clap(long, env = "MINT_MAX_OUTSTANDING")]
+    pub max_outstanding: Option<u64>,
     #[clap(flatten)]
     pub info: MintInfoConfig,
     #[clap(flatten)]
     pub lightning_fee: LightningFeeConfig,
     #[clap(flatten)]
+    pub pending_invoice: PendingInvoiceConfig,
+    #[clap(flatten)]
     pub server: ServerConfig,
     #[clap(flatten)]
     pub database: DatabaseConfig,
@@ -71,8 +79,11 @@ pub struct TracingConfig {
 pub struct MintConfig {
     pub privatekey: String,
     pub derivation_path: Option<String>,
+    pub input_fee_ppk: u64,
+    pub max_outstanding: Option<u64>,
     pub info: MintInfoConfig,
     pub lightning_fee: LightningFeeConfig,
+    pub pending_invoice: PendingInvoiceConfig,
     pub server: ServerConfig,
     pub btconchain_backend: Option<BtcOnchainConfig>,
     pub lightning_backend: Option<LightningType>,
@@ -85,8 +96,11 @@ impl From<(Opts, LightningType, Option<BtcOnchainConfig>)> for MintConfig {
         Self {
             privatekey: opts.privatekey,
             derivation_path: opts.derivation_path,
+            input_fee_ppk: opts.input_fee_ppk,
+            max_outstanding: opts.max_outstanding,
             info: opts.info,
             lightning_fee: opts.lightning_fee,
+            pending_invoice: opts.pending_invoice,
             server: opts.server,
             btconchain_backend: btc,
             lightning_backend: Some(ln),
@@ -128,8 +142,11 @@ impl MintConfig {
     pub const fn new(
         private_key: String,
         derivation_path: Option<String>,
+        input_fee_ppk: u64,
+        max_outstanding: Option<u64>,
         info: MintInfoConfig,
         lightning_fee: LightningFeeConfig,
+        pending_invoice: PendingInvoiceConfig,
         server: ServerConfig,
         database: DatabaseConfig,
         btconchain_backend: Option<BtcOnchainConfig>,
@@ -139,8 +156,11 @@ impl MintConfig {
         Self {
             privatekey: private_key,
             derivation_path,
+            input_fee_ppk,
+            max_outstanding,
             info,
             lightning_fee,
+            pending_invoice,
             server,
             btconchain_backend,
             lightning_backend,
@@ -263,6 +283,9 @@ pub struct ServerConfig {
     pub serve_wallet_path: Option<PathBuf>,
     #[clap(long, env = "MINT_API_PREFIX")]
     pub api_prefix: Option<String>,
+    /// bearer token of the admin routes, they are disabled without a token
+    #[clap(long, env = "MINT_ADMIN_TOKEN")]
+    pub admin_token: Option<String>,
 }
 
 impl Default for ServerConfig {
@@ -271,6 +294,7 @@ impl Default for ServerConfig {
             host_port: "[::]:3338".to_string().parse().expect("invalid host port"),
             serve_wallet_path: None,
             api_prefix: None,
+            admin_token: None,
         }
     }
 }
@@ -336,6 +360,12 @@ pub struct LightningFeeConfig {
     #[clap(long, default_value_t = 4_000, env = "MINT_LIGHTNING_FEE_RESERVE_MIN")]
     pub fee_reserve_min: u64,
     // TODO check if fee_percent is in range
+    /// smallest invoice amount in sats the mint pays
+    #[clap(long, default_value_t = 1, env = "MINT_LIGHTNING_MIN_MELT")]
+    pub min_melt: u64,
+    /// largest invoice amount in sats the mint pays
+    #[clap(long, default_value_t = 10_000_000, env = "MINT_LIGHTNING_MAX_MELT")]
+    pub max_melt: u64,
 }
 
 impl LightningFeeConfig {
@@ -343,24 +373,54 @@ impl LightningFeeConfig {
         Self {
             fee_percent,
             fee_reserve_min,
+            min_melt: 1,
+            max_melt: 10_000_000,
+        }
+    }
+
+    pub const fn with_melt_limits(self, min_melt: u64, max_melt: u64) -> Self {
+        Self {
+            min_melt,
+            max_melt,
+            ..self
         }
     }
 }
 
 impl From<(f32, u64)> for LightningFeeConfig {
     fn from(tuple: (f32, u64)) -> Self {
-        Self {
-            fee_percent: tuple.0,
-            fee_reserve_min: tuple.1,
-        }
+        Self::new(tuple.0, tuple.1)
     }
 }
 
 impl Default for LightningFeeConfig {
+    fn default() -> Self {
+        Self::new(1.0, 4000)
+    }
+}
+
+/// Expiry of the invoices of mint quotes. The expiry should not be shorter than the expiry of the
+/// invoices of the lightning backend, an invoice that is paid after it expired in the mint can't be
+/// minted anymore.
+#[derive(Debug, Clone, Parser)]
+pub struct PendingInvoiceConfig {
+    /// seconds after which a pending invoice can't be minted anymore
+    #[clap(long, default_value_t = 86_400, env = "MINT_PENDING_INVOICE_EXPIRY")]
+    pub expiry: u64,
+    /// seconds between the runs of the task that removes expired pending invoices
+    #[clap(
+        long,
+        default_value_t = 600,
+        env = "MINT_PENDING_INVOICE_PRUNE_INTERVAL"
+    )]
+    pub prune_interval: u64,
+}
+
+impl Default for PendingInvoiceConfig {
     fn default() -> Self {
         Self {
-            fee_percent: 1.0,
-            fee_reserve_min: 4000,
+            expiry: 86_400,
+            prune_interval: 600,
         }
     }
 }
@@ -1,29 +1,120 @@
 use async_trait::async_trait;
 use moksha_core::primitives::{BitcreditMintQuote, BitcreditRequestToMint};
 use moksha_core::{
+    blind::{BlindedMessage, BlindedSignature},
+    page::PageCursor,
     primitives::{Bolt11MeltQuote, Bolt11MintQuote, BtcOnchainMeltQuote, BtcOnchainMintQuote},
     proof::Proofs,
 };
+use secp256k1::PublicKey;
 use uuid::Uuid;
 
-use crate::{error::MokshaMintError, model::Invoice};
+use crate::{
+    error::MokshaMintError,
+    model::{Invoice, KeysetInfo, KeysetLiability, RedeemedProof},
+};
 
 pub mod postgres;
 
 #[async_trait]
 pub trait Database {
     type DB: sqlx::Database;
+
+    /// Starts the transaction of an operation. The changes of an operation are only committed
+    /// once it has fully succeeded, e.g. the inputs of a melt are only marked as used after the
+    /// lightning payment succeeded, so a failed operation leaves the proofs spendable.
     async fn begin_tx(&self) -> Result<sqlx::Transaction<Self::DB>, sqlx::Error>;
     async fn get_used_proofs(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
     ) -> Result<Proofs, MokshaMintError>;
-    async fn add_used_proofs(
+
+    /// Returns the used proofs with the given secrets
+    async fn get_used_proofs_by_secrets(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        secrets: &[String],
+    ) -> Result<Proofs, MokshaMintError>;
+
+    /// Returns the used proofs with the given Ys, see [`moksha_core::proof::Proof::y`]
+    async fn get_used_proofs_by_ys(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        ys: &[PublicKey],
+    ) -> Result<Proofs, MokshaMintError>;
+    /// Marks the proofs as used in one step with the check that they are unused, fails with
+    /// [`MokshaMintError::ProofAlreadyUsed`] otherwise. A concurrent transaction with the same
+    /// proofs waits until this transaction is finished.
+    async fn add_used_proofs_if_unused(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
         proofs: &Proofs,
     ) -> Result<(), MokshaMintError>;
 
+    /// Returns up to `limit` redeemed proofs ordered by `(redeemed_at, id)` with the newest first,
+    /// starting after the cursor `before`
+    async fn get_used_proofs_page(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        before: Option<&PageCursor>,
+        limit: u64,
+    ) -> Result<Vec<RedeemedProof>, MokshaMintError>;
+
+    async fn get_keysets(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+    ) -> Result<Vec<KeysetInfo>, MokshaMintError>;
+
+    /// Adds the keyset if it is not known yet
+    async fn add_keyset(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        keyset: &KeysetInfo,
+    ) -> Result<(), MokshaMintError>;
+
+    /// Adds or updates the keyset as the only active keyset
+    async fn activate_keyset(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        keyset: &KeysetInfo,
+    ) -> Result<(), MokshaMintError>;
+
+    /// Sets the time after which proofs of the keyset are rejected, `None` removes the sunset
+    async fn set_keyset_sunset(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        keyset_id: &str,
+        sunset_at: Option<u64>,
+    ) -> Result<(), MokshaMintError>;
+
+    /// Waits until no other transaction issues ecash and keeps them waiting until this
+    /// transaction ends, so the outstanding amount can't change between checking and signing
+    async fn lock_issuance(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+    ) -> Result<(), MokshaMintError>;
+
+    /// Returns the signed and redeemed amounts of the keysets that have any
+    async fn get_keyset_liabilities(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+    ) -> Result<Vec<KeysetLiability>, MokshaMintError>;
+
+    /// Stores the signatures of the outputs, so they can be restored (NUT-09)
+    async fn add_blind_signatures(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        outputs: &[BlindedMessage],
+        signatures: &[BlindedSignature],
+    ) -> Result<(), MokshaMintError>;
+
+    /// Returns the signatures that were issued for the blinded messages `b_`
+    async fn get_blind_signatures(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        b_: &[PublicKey],
+    ) -> Result<Vec<(PublicKey, BlindedSignature)>, MokshaMintError>;
+
     async fn get_pending_invoice(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
@@ -35,11 +126,25 @@ pub trait Database {
         key: String,
         invoice: &Invoice,
     ) -> Result<(), MokshaMintError>;
+    /// Removes the pending invoice. Fails with `InvoiceAlreadyMinted` if it was already removed,
+    /// so only one of several concurrent mints for the same invoice can succeed.
     async fn delete_pending_invoice(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
         key: String,
     ) -> Result<(), MokshaMintError>;
+    /// Returns the keys and pending invoices that expired at or before `before`
+    async fn get_expired_invoices(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        before: u64,
+    ) -> Result<Vec<(String, Invoice)>, MokshaMintError>;
+    /// Removes the pending invoices with the given keys and returns how many were removed
+    async fn delete_invoices(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        keys: &[String],
+    ) -> Result<u64, MokshaMintError>;
 
     async fn get_bolt11_mint_quote(
         &self,
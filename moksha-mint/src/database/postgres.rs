@@ -1,7 +1,10 @@
 #![allow(clippy::blocks_in_conditions)]
 use async_trait::async_trait;
 use moksha_core::{
-    dhke,
+    blind::{BlindedMessage, BlindedSignature, DleqProof},
+    dhke::{self, Dhke},
+    error::MokshaCoreError,
+    page::PageCursor,
     primitives::{
         Bolt11MeltQuote, Bolt11MintQuote, BtcOnchainMeltQuote, BtcOnchainMintQuote, CurrencyUnit,
     },
@@ -9,14 +12,23 @@ use moksha_core::{
 };
 
 use moksha_core::primitives::{BitcreditMintQuote, BitcreditRequestToMint};
+use secp256k1::{PublicKey, SecretKey};
 use sqlx::postgres::PgPoolOptions;
+use std::str::FromStr;
 use tracing::instrument;
 use uuid::Uuid;
 
-use crate::{config::DatabaseConfig, error::MokshaMintError, model::Invoice};
+use crate::{
+    config::DatabaseConfig,
+    error::MokshaMintError,
+    model::{Invoice, KeysetInfo, KeysetLiability, RedeemedProof},
+};
 
 use super::Database;
 
+/// Key of the advisory lock that is held by the transactions that issue ecash
+const ISSUANCE_LOCK: i64 = 0x6d6f6b736861;
+
 #[derive(Clone)]
 pub struct PostgresDB {
     pool: sqlx::Pool<sqlx::Postgres>,
@@ -37,6 +49,29 @@ impl PostgresDB {
             .run(&self.pool)
             .await
             .expect("Could not run migrations");
+        self.add_missing_used_proof_ys()
+            .await
+            .expect("Could not add the Y of the used proofs");
+    }
+
+    /// Stores the Y of the proofs that were used before the column was added
+    async fn add_missing_used_proof_ys(&self) -> Result<(), MokshaMintError> {
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query!("SELECT secret FROM used_proofs WHERE y IS NULL")
+            .fetch_all(&mut *tx)
+            .await?;
+        for row in rows {
+            let y = Dhke::hash_to_curve(row.secret.as_bytes())?;
+            sqlx::query!(
+                "UPDATE used_proofs SET y = $1 WHERE secret = $2",
+                y.to_string(),
+                row.secret
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
     }
 }
 
@@ -70,26 +105,280 @@ impl Database for PostgresDB {
     }
 
     #[instrument(level = "debug", skip(self), err)]
-    async fn add_used_proofs(
+    async fn get_used_proofs_by_secrets(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        secrets: &[String],
+    ) -> Result<Proofs, MokshaMintError> {
+        let proofs = sqlx::query!(
+            "SELECT amount, secret, c, keyset_id FROM used_proofs WHERE secret = ANY($1)",
+            secrets
+        )
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|row| Proof {
+            amount: row.amount as u64,
+            secret: row.secret,
+            c: dhke::public_key_from_hex(&row.c).to_owned(),
+            keyset_id: row.keyset_id,
+            script: None,
+        })
+        .collect::<Vec<Proof>>();
+
+        Ok(proofs.into())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_used_proofs_by_ys(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        ys: &[PublicKey],
+    ) -> Result<Proofs, MokshaMintError> {
+        let ys = ys.iter().map(ToString::to_string).collect::<Vec<_>>();
+        let proofs = sqlx::query!(
+            "SELECT amount, secret, c, keyset_id FROM used_proofs WHERE y = ANY($1)",
+            &ys
+        )
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|row| Proof {
+            amount: row.amount as u64,
+            secret: row.secret,
+            c: dhke::public_key_from_hex(&row.c).to_owned(),
+            keyset_id: row.keyset_id,
+            script: None,
+        })
+        .collect::<Vec<Proof>>();
+
+        Ok(proofs.into())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn add_used_proofs_if_unused(
         &self,
         tx: &mut sqlx::Transaction<Self::DB>,
         proofs: &Proofs,
     ) -> Result<(), MokshaMintError> {
         for proof in proofs.proofs() {
-            sqlx::query!(
-                "INSERT INTO used_proofs (amount, secret, c, keyset_id) VALUES ($1, $2, $3, $4)",
+            let inserted = sqlx::query!(
+                "INSERT INTO used_proofs (amount, secret, c, keyset_id, y) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (secret) DO NOTHING",
                 proof.amount as i64,
                 proof.secret,
                 proof.c.to_string(),
-                proof.keyset_id.to_string()
+                proof.keyset_id.to_string(),
+                proof.y()?.to_string()
             )
             .execute(&mut **tx)
-            .await?;
+            .await?
+            .rows_affected();
+            if inserted == 0 {
+                return Err(MokshaMintError::ProofAlreadyUsed(format!("{proof:?}")));
+            }
         }
 
         Ok(())
     }
 
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_used_proofs_page(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        before: Option<&PageCursor>,
+        limit: u64,
+    ) -> Result<Vec<RedeemedProof>, MokshaMintError> {
+        let proofs = sqlx::query!(
+            "SELECT id, amount, secret, keyset_id, created_at FROM used_proofs WHERE $1::BIGINT IS NULL OR (created_at, id) < ($1, $2) ORDER BY created_at DESC, id DESC LIMIT $3",
+            before.map(|cursor| cursor.created_at as i64),
+            before.map(|cursor| cursor.id as i64),
+            limit.min(i64::MAX as u64) as i64
+        )
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|row| RedeemedProof {
+            id: row.id as u64,
+            amount: row.amount as u64,
+            secret: row.secret,
+            keyset_id: row.keyset_id,
+            redeemed_at: row.created_at as u64,
+        })
+        .collect();
+        Ok(proofs)
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_keysets(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+    ) -> Result<Vec<KeysetInfo>, MokshaMintError> {
+        let keysets = sqlx::query!(
+            "SELECT keyset_id, derivation_path, active, input_fee_ppk, sunset_at FROM keysets"
+        )
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|row| KeysetInfo {
+            keyset_id: row.keyset_id,
+            derivation_path: row.derivation_path,
+            active: row.active,
+            input_fee_ppk: row.input_fee_ppk as u64,
+            sunset_at: row.sunset_at.map(|sunset_at| sunset_at as u64),
+        })
+        .collect();
+        Ok(keysets)
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn add_keyset(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        keyset: &KeysetInfo,
+    ) -> Result<(), MokshaMintError> {
+        sqlx::query!(
+            "INSERT INTO keysets (keyset_id, derivation_path, active, input_fee_ppk) VALUES ($1, $2, $3, $4) ON CONFLICT (keyset_id) DO NOTHING",
+            keyset.keyset_id,
+            keyset.derivation_path,
+            keyset.active,
+            keyset.input_fee_ppk as i64
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn activate_keyset(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        keyset: &KeysetInfo,
+    ) -> Result<(), MokshaMintError> {
+        sqlx::query!("UPDATE keysets SET active = false")
+            .execute(&mut **tx)
+            .await?;
+        sqlx::query!(
+            "INSERT INTO keysets (keyset_id, derivation_path, active, input_fee_ppk) VALUES ($1, $2, true, $3) ON CONFLICT (keyset_id) DO UPDATE SET active = true, sunset_at = NULL",
+            keyset.keyset_id,
+            keyset.derivation_path,
+            keyset.input_fee_ppk as i64
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn set_keyset_sunset(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        keyset_id: &str,
+        sunset_at: Option<u64>,
+    ) -> Result<(), MokshaMintError> {
+        sqlx::query!(
+            "UPDATE keysets SET sunset_at = $1 WHERE keyset_id = $2",
+            sunset_at.map(|sunset_at| sunset_at as i64),
+            keyset_id
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn lock_issuance(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+    ) -> Result<(), MokshaMintError> {
+        sqlx::query!("SELECT pg_advisory_xact_lock($1)", ISSUANCE_LOCK)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_keyset_liabilities(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+    ) -> Result<Vec<KeysetLiability>, MokshaMintError> {
+        let liabilities = sqlx::query!(
+            r#"SELECT keyset_id as "keyset_id!", SUM(issued)::BIGINT as "issued!", SUM(redeemed)::BIGINT as "redeemed!" FROM (
+                SELECT keyset_id, amount as issued, 0::BIGINT as redeemed FROM blind_signatures
+                UNION ALL
+                SELECT keyset_id, 0::BIGINT, amount FROM used_proofs
+            ) amounts GROUP BY keyset_id ORDER BY keyset_id"#
+        )
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|row| KeysetLiability {
+            keyset_id: row.keyset_id,
+            issued: row.issued as u64,
+            redeemed: row.redeemed as u64,
+        })
+        .collect();
+        Ok(liabilities)
+    }
+
+    #[instrument(level = "debug", skip(self, outputs, signatures), err)]
+    async fn add_blind_signatures(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        outputs: &[BlindedMessage],
+        signatures: &[BlindedSignature],
+    ) -> Result<(), MokshaMintError> {
+        for (output, signature) in outputs.iter().zip(signatures) {
+            sqlx::query!(
+                "INSERT INTO blind_signatures (b_, amount, c_, keyset_id, dleq_e, dleq_s) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (b_) DO NOTHING",
+                output.b_.to_string(),
+                signature.amount as i64,
+                signature.c_.to_string(),
+                signature.id,
+                signature.dleq.map(|dleq| dleq.e.display_secret().to_string()),
+                signature.dleq.map(|dleq| dleq.s.display_secret().to_string())
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, b_), err)]
+    async fn get_blind_signatures(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        b_: &[PublicKey],
+    ) -> Result<Vec<(PublicKey, BlindedSignature)>, MokshaMintError> {
+        let b_ = b_.iter().map(|b| b.to_string()).collect::<Vec<_>>();
+        let signatures = sqlx::query!(
+            "SELECT b_, amount, c_, keyset_id, dleq_e, dleq_s FROM blind_signatures WHERE b_ = ANY($1)",
+            &b_
+        )
+        .fetch_all(&mut **tx)
+        .await?
+        .into_iter()
+        .map(|row| {
+            let dleq = match (row.dleq_e, row.dleq_s) {
+                (Some(e), Some(s)) => Some(DleqProof {
+                    e: SecretKey::from_str(&e)?,
+                    s: SecretKey::from_str(&s)?,
+                }),
+                _ => None,
+            };
+            Ok((
+                dhke::public_key_from_hex(&row.b_),
+                BlindedSignature {
+                    amount: row.amount as u64,
+                    c_: dhke::public_key_from_hex(&row.c_),
+                    id: row.keyset_id,
+                    dleq,
+                },
+            ))
+        })
+        .collect::<Result<Vec<_>, MokshaCoreError>>()?;
+        Ok(signatures)
+    }
+
     #[instrument(level = "debug", skip(self))]
     async fn get_pending_invoice(
         &self,
@@ -97,12 +386,14 @@ impl Database for PostgresDB {
         key: String,
     ) -> Result<Invoice, MokshaMintError> {
         let invoice: Invoice = sqlx::query!(
-            "SELECT amount, payment_request FROM pending_invoices WHERE key = $1",
+            "SELECT amount, payment_request, created_at, expires_at FROM pending_invoices WHERE key = $1",
             key
         )
         .map(|row| Invoice {
             amount: row.amount as u64,
             payment_request: row.payment_request,
+            created_at: row.created_at as u64,
+            expires_at: row.expires_at as u64,
         })
         .fetch_one(&mut **tx)
         .await?;
@@ -118,10 +409,12 @@ impl Database for PostgresDB {
         invoice: &Invoice,
     ) -> Result<(), MokshaMintError> {
         sqlx::query!(
-            "INSERT INTO pending_invoices (key, amount, payment_request) VALUES ($1, $2, $3)",
+            "INSERT INTO pending_invoices (key, amount, payment_request, created_at, expires_at) VALUES ($1, $2, $3, $4, $5)",
             key,
             invoice.amount as i64,
-            invoice.payment_request
+            invoice.payment_request,
+            invoice.created_at as i64,
+            invoice.expires_at as i64
         )
         .execute(&mut **tx)
         .await?;
@@ -135,12 +428,54 @@ impl Database for PostgresDB {
         tx: &mut sqlx::Transaction<Self::DB>,
         key: String,
     ) -> Result<(), MokshaMintError> {
-        sqlx::query!("DELETE FROM pending_invoices WHERE key = $1", key)
+        let result = sqlx::query!("DELETE FROM pending_invoices WHERE key = $1", key)
             .execute(&mut **tx)
             .await?;
+        if result.rows_affected() == 0 {
+            return Err(MokshaMintError::InvoiceAlreadyMinted(key));
+        }
         Ok(())
     }
 
+    #[instrument(level = "debug", skip(self), err)]
+    async fn get_expired_invoices(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        before: u64,
+    ) -> Result<Vec<(String, Invoice)>, MokshaMintError> {
+        let invoices = sqlx::query!(
+            "SELECT key, amount, payment_request, created_at, expires_at FROM pending_invoices WHERE expires_at <= $1",
+            before as i64
+        )
+        .map(|row| {
+            (
+                row.key,
+                Invoice {
+                    amount: row.amount as u64,
+                    payment_request: row.payment_request,
+                    created_at: row.created_at as u64,
+                    expires_at: row.expires_at as u64,
+                },
+            )
+        })
+        .fetch_all(&mut **tx)
+        .await?;
+
+        Ok(invoices)
+    }
+
+    #[instrument(level = "debug", skip(self), err)]
+    async fn delete_invoices(
+        &self,
+        tx: &mut sqlx::Transaction<Self::DB>,
+        keys: &[String],
+    ) -> Result<u64, MokshaMintError> {
+        let result = sqlx::query!("DELETE FROM pending_invoices WHERE key = ANY($1)", keys)
+            .execute(&mut **tx)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
     #[instrument(level = "debug", skip(self), err)]
     async fn get_bolt11_mint_quote(
         &self,
@@ -8,8 +8,7 @@ use axum::{
 use fedimint_tonic_lnd::{tonic::Status, ConnectError};
 
 use lightning_invoice::ParseOrSemanticError;
-use moksha_core::primitives::CurrencyUnit;
-use serde_json::json;
+use moksha_core::primitives::{CashuErrorCode, CashuErrorResponse, CurrencyUnit};
 use thiserror::Error;
 use tracing::{event, Level};
 
@@ -29,6 +28,12 @@ pub enum MokshaMintError {
     #[error("Failed to pay invoice {0} - Error {1}")]
     PayInvoice(String, LightningError),
 
+    #[error("Payment of invoice {0} is still in flight")]
+    PaymentInFlight(String),
+
+    #[error("No route found to pay invoice {0}")]
+    NoRoute(String),
+
     #[error("DB Error {0}")]
     Db(#[from] sqlx::Error),
 
@@ -47,9 +52,18 @@ pub enum MokshaMintError {
     #[error("Lightning invoice not paid yet.")]
     InvoiceNotPaidYet,
 
+    #[error("Invoice already minted {0}")]
+    InvoiceAlreadyMinted(String),
+
+    #[error("Invoice expired {0}")]
+    InvoiceExpired(String),
+
     #[error("BTC-Onchain not paid yet.")]
     BtcOnchainNotPaidYet,
 
+    #[error("Invalid proof {0}")]
+    InvalidProof(String),
+
     #[error("Proof already used {0}")]
     ProofAlreadyUsed(String),
 
@@ -59,6 +73,12 @@ pub enum MokshaMintError {
     #[error("duplicate promises.")]
     SwapHasDuplicatePromises,
 
+    #[error("Swap outputs of {outputs} exceed the inputs of {inputs}")]
+    SwapAmountTooHigh { inputs: u64, outputs: u64 },
+
+    #[error("Output amount {0} is not a denomination of the keyset")]
+    InvalidOutputDecomposition(u64),
+
     #[error("Invalid amount: {0}")]
     InvalidAmount(String),
 
@@ -74,15 +94,46 @@ pub enum MokshaMintError {
     #[error("Keyset not found {0}")]
     KeysetNotFound(String),
 
+    #[error("Unknown keyset {0}")]
+    UnknownKeyset(String),
+
+    #[error("Keyset {0} is inactive")]
+    KeysetInactive(String),
+
+    #[error("Keyset {0} is active, rotate the keyset first")]
+    KeysetActive(String),
+
+    #[error("Keyset {0} expired, contact mint")]
+    KeysetExpired(String),
+
     #[error("Currency not supported {0}")]
     CurrencyNotSupported(CurrencyUnit),
 
     #[error("Not Enough tokens. Required amount {0}")]
     NotEnoughTokens(u64),
 
+    #[error("Minting {amount} sats exceeds the issuance cap of {max} sats, {outstanding} sats are outstanding")]
+    IssuanceCapReached {
+        amount: u64,
+        outstanding: u64,
+        max: u64,
+    },
+
+    #[error("Melt amount {amount} is below the minimum of {min} sats")]
+    MeltAmountTooLow { amount: u64, min: u64 },
+
+    #[error("Melt amount {amount} is above the maximum of {max} sats")]
+    MeltAmountTooHigh { amount: u64, max: u64 },
+
     #[error("Lnd error: {0}")]
     Lnd(#[from] Status),
 
+    #[error("Proof is locked until {0}")]
+    ProofLocked(u64),
+
+    #[error("Unsupported spending condition: {0}")]
+    UnsupportedSpendingCondition(String),
+
     #[error("PrivateKey in keyset not found")]
     PrivateKeyNotFound,
 
@@ -90,15 +141,182 @@ pub enum MokshaMintError {
     MokshaCore(#[from] moksha_core::error::MokshaCoreError),
 }
 
+impl MokshaMintError {
+    /// The NUT-00 error code that is sent to the wallet, [`None`] for errors without a code
+    pub fn code(&self) -> Option<CashuErrorCode> {
+        match self {
+            Self::InvoiceNotPaidYet | Self::BtcOnchainNotPaidYet => {
+                Some(CashuErrorCode::InvoiceNotPaid)
+            }
+            Self::InvoiceAlreadyMinted(_) => Some(CashuErrorCode::QuoteAlreadyIssued),
+            Self::PaymentInFlight(_) => Some(CashuErrorCode::QuotePending),
+            Self::InvoiceExpired(_) => Some(CashuErrorCode::QuoteExpired),
+            Self::InvalidProof(_) => Some(CashuErrorCode::ProofVerificationFailed),
+            Self::ProofAlreadyUsed(_) => Some(CashuErrorCode::ProofAlreadyUsed),
+            Self::SwapAmountMismatch(_)
+            | Self::SwapAmountTooHigh { .. }
+            | Self::NotEnoughTokens(_) => Some(CashuErrorCode::InsufficientFee),
+            Self::KeysetNotFound(_) | Self::UnknownKeyset(_) => {
+                Some(CashuErrorCode::KeysetNotFound)
+            }
+            Self::KeysetInactive(_) | Self::KeysetExpired(_) => {
+                Some(CashuErrorCode::KeysetInactive)
+            }
+            Self::CurrencyNotSupported(_) => Some(CashuErrorCode::UnitNotSupported),
+            Self::InvoiceAmountTooLow(_)
+            | Self::IssuanceCapReached { .. }
+            | Self::MeltAmountTooLow { .. }
+            | Self::MeltAmountTooHigh { .. } => Some(CashuErrorCode::AmountOutsideLimit),
+            // no match arm with a wildcard, so every new variant has to be assigned a code
+            Self::ConnectError(_)
+            | Self::ClnConnectError(_)
+            | Self::DecodeInvoice(..)
+            | Self::PayInvoice(..)
+            | Self::NoRoute(_)
+            | Self::Db(_)
+            | Self::Utf8(_)
+            | Self::Serialization(_)
+            | Self::InvoiceNotFound(_)
+            | Self::SwapHasDuplicatePromises
+            | Self::InvalidOutputDecomposition(_)
+            | Self::InvalidAmount(_)
+            | Self::Lightning(_)
+            | Self::InvalidQuote(_)
+            | Self::InvalidUuid(_)
+            | Self::KeysetActive(_)
+            | Self::Lnd(_)
+            | Self::ProofLocked(_)
+            | Self::UnsupportedSpendingCondition(_)
+            | Self::PrivateKeyNotFound
+            | Self::MokshaCore(_) => None,
+        }
+    }
+}
+
 impl IntoResponse for MokshaMintError {
     fn into_response(self) -> Response {
         event!(Level::ERROR, "error in mint: {:?}", self);
 
-        let body = Json(json!({
-            "code": 0,
-            "detail": self.to_string(),
-        }));
+        let body = Json(CashuErrorResponse::new(self.code(), self.to_string()));
 
         (StatusCode::BAD_REQUEST, body).into_response()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use axum::response::IntoResponse;
+    use http_body_util::BodyExt;
+    use moksha_core::primitives::{CashuErrorCode, CashuErrorResponse, CurrencyUnit};
+
+    use super::MokshaMintError;
+
+    #[tokio::test]
+    async fn test_error_codes() -> anyhow::Result<()> {
+        let errors = [
+            (
+                MokshaMintError::InvoiceNotPaidYet,
+                Some(CashuErrorCode::InvoiceNotPaid),
+            ),
+            (
+                MokshaMintError::BtcOnchainNotPaidYet,
+                Some(CashuErrorCode::InvoiceNotPaid),
+            ),
+            (
+                MokshaMintError::InvoiceAlreadyMinted("hash".to_owned()),
+                Some(CashuErrorCode::QuoteAlreadyIssued),
+            ),
+            (
+                MokshaMintError::PaymentInFlight("lnbc".to_owned()),
+                Some(CashuErrorCode::QuotePending),
+            ),
+            (
+                MokshaMintError::InvoiceExpired("quote".to_owned()),
+                Some(CashuErrorCode::QuoteExpired),
+            ),
+            (
+                MokshaMintError::InvalidProof("signature".to_owned()),
+                Some(CashuErrorCode::ProofVerificationFailed),
+            ),
+            (
+                MokshaMintError::ProofAlreadyUsed("secret".to_owned()),
+                Some(CashuErrorCode::ProofAlreadyUsed),
+            ),
+            (
+                MokshaMintError::SwapAmountMismatch("amount".to_owned()),
+                Some(CashuErrorCode::InsufficientFee),
+            ),
+            (
+                MokshaMintError::SwapAmountTooHigh {
+                    inputs: 1,
+                    outputs: 2,
+                },
+                Some(CashuErrorCode::InsufficientFee),
+            ),
+            (
+                MokshaMintError::NotEnoughTokens(2),
+                Some(CashuErrorCode::InsufficientFee),
+            ),
+            (
+                MokshaMintError::KeysetNotFound("id".to_owned()),
+                Some(CashuErrorCode::KeysetNotFound),
+            ),
+            (
+                MokshaMintError::UnknownKeyset("id".to_owned()),
+                Some(CashuErrorCode::KeysetNotFound),
+            ),
+            (
+                MokshaMintError::KeysetInactive("id".to_owned()),
+                Some(CashuErrorCode::KeysetInactive),
+            ),
+            (
+                MokshaMintError::KeysetExpired("id".to_owned()),
+                Some(CashuErrorCode::KeysetInactive),
+            ),
+            (
+                MokshaMintError::CurrencyNotSupported(CurrencyUnit::Usd),
+                Some(CashuErrorCode::UnitNotSupported),
+            ),
+            (
+                MokshaMintError::InvoiceAmountTooLow("0".to_owned()),
+                Some(CashuErrorCode::AmountOutsideLimit),
+            ),
+            (
+                MokshaMintError::IssuanceCapReached {
+                    amount: 2,
+                    outstanding: 1,
+                    max: 2,
+                },
+                Some(CashuErrorCode::AmountOutsideLimit),
+            ),
+            (
+                MokshaMintError::MeltAmountTooLow { amount: 1, min: 2 },
+                Some(CashuErrorCode::AmountOutsideLimit),
+            ),
+            (
+                MokshaMintError::MeltAmountTooHigh { amount: 2, max: 1 },
+                Some(CashuErrorCode::AmountOutsideLimit),
+            ),
+            (MokshaMintError::InvoiceNotFound("hash".to_owned()), None),
+            (MokshaMintError::SwapHasDuplicatePromises, None),
+            (MokshaMintError::PrivateKeyNotFound, None),
+        ];
+
+        for (error, code) in errors {
+            assert_eq!(code, error.code(), "unexpected code for {error:?}");
+
+            let message = error.to_string();
+            let body = error
+                .into_response()
+                .into_body()
+                .collect()
+                .await?
+                .to_bytes();
+            let response: CashuErrorResponse = serde_json::from_slice(&body)?;
+            assert_eq!(code, response.error_code());
+            assert_eq!(code.map_or(0, CashuErrorCode::code), response.code);
+            assert_eq!(message, response.error);
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 pub mod btconchain;
+pub mod clock;
 pub mod config;
 pub mod database;
 pub mod error;
@@ -7,15 +7,19 @@ use std::{
 
 use crate::{
     error::MokshaMintError,
+    lightning::error::LightningError,
     model::{CreateInvoiceResult, PayInvoiceResult},
     url_serialize::{deserialize_url, serialize_url},
 };
 use async_trait::async_trait;
 use clap::Parser;
-use fedimint_tonic_lnd::Client;
+use fedimint_tonic_lnd::{
+    lnrpc::{invoice::InvoiceState, SendResponse},
+    Client,
+};
 use serde::{Deserialize, Serialize};
 use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
-use tracing::{debug, instrument};
+use tracing::{debug, instrument, warn};
 use url::Url;
 
 use super::Lightning;
@@ -106,7 +110,7 @@ impl Lightning for LndLightning {
             .await?
             .into_inner();
 
-        Ok(invoice.state == fedimint_tonic_lnd::lnrpc::invoice::InvoiceState::Settled as i32)
+        Ok(is_settled(invoice.state))
     }
 
     #[instrument(skip(self), err)]
@@ -135,25 +139,130 @@ impl Lightning for LndLightning {
         payment_request: String,
     ) -> Result<PayInvoiceResult, MokshaMintError> {
         let pay_req = fedimint_tonic_lnd::lnrpc::SendRequest {
-            payment_request,
+            payment_request: payment_request.clone(),
             ..Default::default()
         };
         let payment_response = self
             .client_lock()
             .await?
             .send_payment_sync(fedimint_tonic_lnd::tonic::Request::new(pay_req))
-            .await?
+            .await
+            .map_err(|status| {
+                if status.message().contains(PAYMENT_IN_FLIGHT) {
+                    MokshaMintError::PaymentInFlight(payment_request.clone())
+                } else {
+                    MokshaMintError::Lnd(status)
+                }
+            })?
             .into_inner();
 
-        let total_fees = payment_response
-            .payment_route
-            .map_or(0, |route| route.total_fees_msat / 1_000) as u64;
+        pay_invoice_result(&payment_request, payment_response)
+    }
+}
 
-        debug!("lnd total_fees: {}", total_fees);
+// messages of ErrPaymentInFlight and FailureReasonNoRoute in lnd
+const PAYMENT_IN_FLIGHT: &str = "payment is in transition";
+const NO_ROUTE: &str = "unable to find a path to destination";
 
-        Ok(PayInvoiceResult {
-            payment_hash: hex::encode(payment_response.payment_hash),
-            total_fees,
-        })
+fn is_settled(state: i32) -> bool {
+    state == InvoiceState::Settled as i32
+}
+
+/// lnd reports a failed payment in the `payment_error` of the response instead of a status
+fn pay_invoice_result(
+    payment_request: &str,
+    response: SendResponse,
+) -> Result<PayInvoiceResult, MokshaMintError> {
+    if !response.payment_error.is_empty() {
+        return Err(payment_error(payment_request, &response.payment_error));
+    }
+
+    let total_fees = response
+        .payment_route
+        .map_or(0, |route| route.total_fees_msat / 1_000) as u64;
+
+    debug!("lnd total_fees: {}", total_fees);
+
+    Ok(PayInvoiceResult {
+        payment_hash: hex::encode(response.payment_hash),
+        total_fees,
+    })
+}
+
+/// Maps the error messages of lnd, which has no error codes for failed payments
+fn payment_error(payment_request: &str, message: &str) -> MokshaMintError {
+    if message.contains(PAYMENT_IN_FLIGHT) {
+        MokshaMintError::PaymentInFlight(payment_request.to_owned())
+    } else if message.contains(NO_ROUTE) {
+        MokshaMintError::NoRoute(payment_request.to_owned())
+    } else {
+        warn!("lnd payment failed: {message}");
+        MokshaMintError::PayInvoice(payment_request.to_owned(), LightningError::PaymentFailed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_tonic_lnd::lnrpc::{invoice::InvoiceState, Route, SendResponse};
+
+    use crate::{error::MokshaMintError, lightning::error::LightningError};
+
+    const INVOICE: &str = "lnbcrt1u1invoice";
+
+    #[test]
+    fn test_is_settled() {
+        assert!(super::is_settled(InvoiceState::Settled as i32));
+        for state in [
+            InvoiceState::Open,
+            InvoiceState::Accepted,
+            InvoiceState::Canceled,
+        ] {
+            assert!(!super::is_settled(state as i32));
+        }
+    }
+
+    #[test]
+    fn test_pay_invoice_result_paid() -> anyhow::Result<()> {
+        let response = SendResponse {
+            payment_hash: vec![1, 2, 3],
+            payment_route: Some(Route {
+                total_fees_msat: 2_500,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let result = super::pay_invoice_result(INVOICE, response)?;
+        assert_eq!("010203", result.payment_hash);
+        assert_eq!(2, result.total_fees);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pay_invoice_result_failed() {
+        let failed = |payment_error: &str| {
+            super::pay_invoice_result(
+                INVOICE,
+                SendResponse {
+                    payment_error: payment_error.to_owned(),
+                    ..Default::default()
+                },
+            )
+        };
+
+        assert!(matches!(
+            failed("payment is in transition"),
+            Err(MokshaMintError::PaymentInFlight(ref invoice)) if invoice == INVOICE
+        ));
+        assert!(matches!(
+            failed("unable to find a path to destination"),
+            Err(MokshaMintError::NoRoute(ref invoice)) if invoice == INVOICE
+        ));
+        assert!(matches!(
+            failed("insufficient local balance"),
+            Err(MokshaMintError::PayInvoice(
+                _,
+                LightningError::PaymentFailed
+            ))
+        ));
     }
 }
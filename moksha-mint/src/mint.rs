@@ -1,21 +1,31 @@
-use std::{collections::HashSet, sync::Arc, vec};
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::Duration,
+    vec,
+};
 
 use moksha_core::{
     amount::Amount,
     blind::{BlindedMessage, BlindedSignature, TotalAmount},
     dhke::Dhke,
-    keyset::MintKeyset,
-    primitives::{BtcOnchainMeltQuote, PaymentMethod},
+    keyset::{Keyset, Keysets, MintKeyset},
+    page::PageCursor,
+    primitives::{BtcOnchainMeltQuote, CurrencyUnit, PaymentMethod, ProofState, State},
     proof::Proofs,
 };
-use sqlx::Transaction;
-use tracing::instrument;
+use secp256k1::PublicKey;
+use sqlx::{Acquire, Transaction};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
 
 use crate::{
     btconchain::{lnd::LndBtcOnchain, BtcOnchain},
+    clock::{Clock, SystemClock},
     config::{
         BtcOnchainConfig, BtcOnchainType, BuildParams, DatabaseConfig, LightningFeeConfig,
-        MintConfig, MintInfoConfig, ServerConfig, TracingConfig,
+        MintConfig, MintInfoConfig, PendingInvoiceConfig, ServerConfig, TracingConfig,
     },
     database::{postgres::PostgresDB, Database},
     error::MokshaMintError,
@@ -23,7 +33,7 @@ use crate::{
         alby::AlbyLightning, lnbits::LnbitsLightning, lnd::LndLightning, strike::StrikeLightning,
         Lightning, LightningType,
     },
-    model::Invoice,
+    model::{AdminRedeemedProofs, Invoice, KeysetInfo, KeysetLiability, MeltResult},
 };
 
 use crate::lightning::cln::ClnLightning;
@@ -32,12 +42,61 @@ use crate::lightning::cln::ClnLightning;
 pub struct Mint<DB: Database = PostgresDB> {
     pub lightning: Arc<dyn Lightning + Send + Sync>,
     pub lightning_type: LightningType,
-    pub keyset: MintKeyset,
+    keysets: Arc<RwLock<MintKeysets>>,
     pub db: DB,
     pub dhke: Dhke,
     pub onchain: Option<Arc<dyn BtcOnchain + Send + Sync>>,
     pub config: MintConfig,
     pub build_params: BuildParams,
+    pub clock: Arc<dyn Clock>,
+}
+
+/// The keysets that are derived from the private key of the mint. New outputs are signed with the
+/// active keyset, proofs of all known keysets are accepted.
+#[derive(Debug, Clone)]
+struct MintKeysets {
+    active: String,
+    keysets: HashMap<String, MintKeysetEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct MintKeysetEntry {
+    derivation_path: String,
+    keyset: MintKeyset,
+    /// fee in parts per thousand per input proof (NUT-02)
+    input_fee_ppk: u64,
+    /// unix timestamp (seconds) after which proofs of the keyset are rejected
+    sunset_at: Option<u64>,
+}
+
+impl MintKeysets {
+    fn new(derivation_path: &str, keyset: MintKeyset, input_fee_ppk: u64) -> Self {
+        let active = keyset.keyset_id.clone();
+        let mut keysets = Self {
+            keysets: HashMap::new(),
+            active,
+        };
+        keysets.insert(derivation_path, keyset, input_fee_ppk, None);
+        keysets
+    }
+
+    fn insert(
+        &mut self,
+        derivation_path: &str,
+        keyset: MintKeyset,
+        input_fee_ppk: u64,
+        sunset_at: Option<u64>,
+    ) {
+        self.keysets.insert(
+            keyset.keyset_id.clone(),
+            MintKeysetEntry {
+                derivation_path: derivation_path.to_owned(),
+                keyset,
+                input_fee_ppk,
+                sunset_at,
+            },
+        );
+    }
 }
 
 impl<DB> Mint<DB>
@@ -55,16 +114,300 @@ where
         Self {
             lightning,
             lightning_type,
-            keyset: MintKeyset::new(
-                &config.privatekey.clone(),
+            keysets: Arc::new(RwLock::new(MintKeysets::new(
                 &config.derivation_path.clone().unwrap_or_default(),
-            ),
+                MintKeyset::new(
+                    &config.privatekey.clone(),
+                    &config.derivation_path.clone().unwrap_or_default(),
+                ),
+                config.input_fee_ppk,
+            ))),
             db,
             dhke: Dhke::new(),
             config,
             onchain,
             build_params,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Returns the active keyset that signs new outputs
+    pub fn keyset(&self) -> MintKeyset {
+        let keysets = self.keysets.read().expect("keysets lock is poisoned");
+        keysets.keysets[&keysets.active].keyset.clone()
+    }
+
+    /// Returns an active or inactive keyset of the mint
+    pub fn get_keyset(&self, keyset_id: &str) -> Option<MintKeyset> {
+        self.keysets
+            .read()
+            .expect("keysets lock is poisoned")
+            .keysets
+            .get(keyset_id)
+            .map(|entry| entry.keyset.clone())
+    }
+
+    /// Returns the ids of all known keysets
+    pub fn keysets(&self) -> Keysets {
+        let keysets = self.keysets.read().expect("keysets lock is poisoned");
+        let mut ids = keysets.keysets.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        Keysets {
+            keysets: ids
+                .into_iter()
+                .map(|id| Keyset {
+                    active: id == keysets.active,
+                    input_fee_ppk: keysets.keysets[&id].input_fee_ppk,
+                    final_expiry: keysets.keysets[&id].sunset_at,
+                    id,
+                    unit: CurrencyUnit::Sat,
+                })
+                .collect(),
+        }
+    }
+
+    /// Loads the keysets that are stored in the database. If there are none, the keyset from the
+    /// config is stored as the active keyset.
+    pub async fn load_keysets(&self) -> Result<(), MokshaMintError> {
+        let mut tx = self.db.begin_tx().await?;
+        let stored = self.db.get_keysets(&mut tx).await?;
+        if stored.is_empty() {
+            let entry = self.active_keyset_entry();
+            self.db
+                .activate_keyset(
+                    &mut tx,
+                    &KeysetInfo {
+                        keyset_id: entry.keyset.keyset_id,
+                        derivation_path: entry.derivation_path,
+                        active: true,
+                        input_fee_ppk: entry.input_fee_ppk,
+                        sunset_at: None,
+                    },
+                )
+                .await?;
+        }
+        tx.commit().await?;
+
+        let mut keysets = self.keysets.write().expect("keysets lock is poisoned");
+        for info in stored {
+            let keyset = MintKeyset::new(&self.config.privatekey, &info.derivation_path);
+            if info.active {
+                keysets.active = keyset.keyset_id.clone();
+            }
+            keysets.insert(
+                &info.derivation_path,
+                keyset,
+                info.input_fee_ppk,
+                info.sunset_at,
+            );
+        }
+        Ok(())
+    }
+
+    /// Derives a new keyset that signs all new outputs. Proofs of the previous keysets are still
+    /// accepted.
+    ///
+    /// The input fee is fixed once a keyset exists, so `input_fee_ppk` is ignored if the keyset
+    /// was active before.
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn rotate_keyset(
+        &self,
+        derivation_path: &str,
+        input_fee_ppk: u64,
+    ) -> Result<MintKeyset, MokshaMintError> {
+        let keyset = MintKeyset::new(&self.config.privatekey, derivation_path);
+        let known = self
+            .keysets
+            .read()
+            .expect("keysets lock is poisoned")
+            .keysets
+            .iter()
+            .map(|(keyset_id, entry)| KeysetInfo {
+                keyset_id: keyset_id.clone(),
+                derivation_path: entry.derivation_path.clone(),
+                active: false,
+                input_fee_ppk: entry.input_fee_ppk,
+                sunset_at: entry.sunset_at,
+            })
+            .collect::<Vec<_>>();
+        let input_fee_ppk = known
+            .iter()
+            .find(|info| info.keyset_id == keyset.keyset_id)
+            .map_or(input_fee_ppk, |info| info.input_fee_ppk);
+
+        let mut tx = self.db.begin_tx().await?;
+        for info in known {
+            self.db.add_keyset(&mut tx, &info).await?;
+        }
+        self.db
+            .activate_keyset(
+                &mut tx,
+                &KeysetInfo {
+                    keyset_id: keyset.keyset_id.clone(),
+                    derivation_path: derivation_path.to_owned(),
+                    active: true,
+                    input_fee_ppk,
+                    sunset_at: None,
+                },
+            )
+            .await?;
+        tx.commit().await?;
+
+        // an active keyset has no sunset
+        let mut keysets = self.keysets.write().expect("keysets lock is poisoned");
+        keysets.insert(derivation_path, keyset.clone(), input_fee_ppk, None);
+        keysets.active = keyset.keyset_id.clone();
+        Ok(keyset)
+    }
+
+    /// Schedules the sunset of an inactive keyset: its proofs are rejected from `sunset_at` (unix
+    /// timestamp in seconds) on. The sunset is announced as `final_expiry` of the keyset (NUT-02),
+    /// so wallets can swap their proofs before. `None` cancels the sunset.
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn sunset_keyset(
+        &self,
+        keyset_id: &str,
+        sunset_at: Option<u64>,
+    ) -> Result<(), MokshaMintError> {
+        {
+            let keysets = self.keysets.read().expect("keysets lock is poisoned");
+            if !keysets.keysets.contains_key(keyset_id) {
+                return Err(MokshaMintError::UnknownKeyset(keyset_id.to_owned()));
+            }
+            if keysets.active == keyset_id {
+                return Err(MokshaMintError::KeysetActive(keyset_id.to_owned()));
+            }
+        }
+
+        let mut tx = self.db.begin_tx().await?;
+        self.db
+            .set_keyset_sunset(&mut tx, keyset_id, sunset_at)
+            .await?;
+        tx.commit().await?;
+
+        let mut keysets = self.keysets.write().expect("keysets lock is poisoned");
+        if let Some(entry) = keysets.keysets.get_mut(keyset_id) {
+            entry.sunset_at = sunset_at;
         }
+        Ok(())
+    }
+
+    /// Returns the signed and redeemed amounts of every keyset of the mint
+    pub async fn keyset_liabilities(&self) -> Result<Vec<KeysetLiability>, MokshaMintError> {
+        let mut tx = self.db.begin_tx().await?;
+        let stored = self.db.get_keyset_liabilities(&mut tx).await?;
+        tx.commit().await?;
+
+        let mut ids = self
+            .keysets()
+            .keysets
+            .into_iter()
+            .map(|k| k.id)
+            .collect::<Vec<_>>();
+        ids.sort();
+        Ok(ids
+            .into_iter()
+            .map(|keyset_id| {
+                stored
+                    .iter()
+                    .find(|liability| liability.keyset_id == keyset_id)
+                    .cloned()
+                    .unwrap_or(KeysetLiability {
+                        keyset_id,
+                        ..Default::default()
+                    })
+            })
+            .collect())
+    }
+
+    /// Returns up to `limit` redeemed proofs, the newest first. Starts after the cursor `before`,
+    /// proofs that are redeemed while paging are not returned by the following pages.
+    pub async fn redeemed_proofs(
+        &self,
+        before: Option<&PageCursor>,
+        limit: u64,
+    ) -> Result<AdminRedeemedProofs, MokshaMintError> {
+        let mut tx = self.db.begin_tx().await?;
+        // one more proof shows whether there is a following page
+        let mut proofs = self
+            .db
+            .get_used_proofs_page(&mut tx, before, limit.saturating_add(1))
+            .await?;
+        tx.commit().await?;
+
+        let next = match proofs.len() as u64 > limit {
+            true => {
+                proofs.truncate(limit as usize);
+                proofs
+                    .last()
+                    .map(|proof| PageCursor::new(proof.redeemed_at, proof.id))
+            }
+            false => None,
+        };
+        Ok(AdminRedeemedProofs { proofs, next })
+    }
+
+    fn active_keyset_entry(&self) -> MintKeysetEntry {
+        let keysets = self.keysets.read().expect("keysets lock is poisoned");
+        keysets.keysets[&keysets.active].clone()
+    }
+
+    /// Returns the fee for spending the proofs (NUT-02): the sum of the input fees of their
+    /// keysets, rounded up to a full sat
+    pub fn input_fee(&self, proofs: &Proofs) -> Result<u64, MokshaMintError> {
+        let keysets = self.keysets.read().expect("keysets lock is poisoned");
+        let fee_ppk = proofs
+            .proofs()
+            .into_iter()
+            .map(|proof| {
+                keysets
+                    .keysets
+                    .get(&proof.keyset_id)
+                    .map(|entry| entry.input_fee_ppk)
+                    .ok_or(MokshaMintError::UnknownKeyset(proof.keyset_id))
+            })
+            .sum::<Result<u64, _>>()?;
+        Ok((fee_ppk + 999) / 1000)
+    }
+
+    /// Returns the amount in sats of a melt: the amount of the invoice, or the amount of the caller
+    /// for an amountless invoice. Fails if the amount is outside the melt limits of the mint.
+    pub fn melt_amount(
+        &self,
+        invoice_amount_msat: Option<u64>,
+        requested_amount: Option<u64>,
+    ) -> Result<u64, MokshaMintError> {
+        let amount = match (invoice_amount_msat, requested_amount) {
+            (Some(amount_msat), None) => amount_msat / 1_000,
+            (Some(amount_msat), Some(requested)) if requested == amount_msat / 1_000 => requested,
+            (Some(amount_msat), Some(requested)) => {
+                return Err(MokshaMintError::InvalidAmount(format!(
+                    "amount {requested} doesn't match the invoice amount {}",
+                    amount_msat / 1_000
+                )))
+            }
+            (None, Some(requested)) => requested,
+            (None, None) => {
+                return Err(MokshaMintError::InvalidAmount(
+                    "amountless invoice needs an amount".to_owned(),
+                ))
+            }
+        };
+
+        let fee_config = &self.config.lightning_fee;
+        if amount < fee_config.min_melt {
+            return Err(MokshaMintError::MeltAmountTooLow {
+                amount,
+                min: fee_config.min_melt,
+            });
+        }
+        if amount > fee_config.max_melt {
+            return Err(MokshaMintError::MeltAmountTooHigh {
+                amount,
+                max: fee_config.max_melt,
+            });
+        }
+        Ok(amount)
     }
 
     pub fn fee_reserve(&self, amount_msat: u64) -> u64 {
@@ -73,10 +416,9 @@ where
         std::cmp::max(fee_reserve, self.config.lightning_fee.fee_reserve_min)
     }
 
+    /// Fee reserve in sats for paying an invoice of `amount_sat`, rounded up to whole sats
     pub fn fee_reserve_sat(&self, amount_sat: u64) -> u64 {
-        let fee_percent = self.config.lightning_fee.fee_percent as f64 / 100.0;
-        let fee_reserve = (amount_sat as f64 * fee_percent) as u64;
-        std::cmp::max(fee_reserve, self.config.lightning_fee.fee_reserve_min)
+        self.fee_reserve(amount_sat * 1_000).div_ceil(1_000)
     }
 
     pub fn create_blinded_signatures(
@@ -92,10 +434,14 @@ where
                     .get(&blinded_msg.amount)
                     .ok_or(MokshaMintError::PrivateKeyNotFound)?;
                 let blinded_sig = self.dhke.step2_bob(blinded_msg.b_, private_key)?;
+                let dleq = self
+                    .dhke
+                    .create_dleq(blinded_msg.b_, blinded_sig, private_key)?;
                 Ok(BlindedSignature {
                     id: keyset.keyset_id.clone(),
                     amount: blinded_msg.amount,
                     c_: blinded_sig,
+                    dleq: Some(dleq),
                 })
             })
             .collect::<Result<Vec<_>, _>>()
@@ -108,9 +454,18 @@ where
         amount: u64,
     ) -> Result<(String, String), MokshaMintError> {
         let mut tx = self.db.begin_tx().await?;
+        // reject the quote before the invoice is paid, minting checks the cap again
+        self.check_issuance_cap(&mut tx, amount).await?;
         let pr = self.lightning.create_invoice(amount).await?.payment_request;
+        let now = self.clock.now();
+        let invoice = Invoice::new(
+            amount,
+            pr.clone(),
+            now,
+            now.saturating_add(self.config.pending_invoice.expiry),
+        );
         self.db
-            .add_pending_invoice(&mut tx, key.clone(), &Invoice::new(amount, pr.clone()))
+            .add_pending_invoice(&mut tx, key.clone(), &invoice)
             .await?;
         tx.commit().await?;
         Ok((pr, key))
@@ -124,27 +479,177 @@ where
         key: String,
         outputs: &[BlindedMessage],
         keyset: &MintKeyset,
-        return_error: bool,
     ) -> Result<Vec<BlindedSignature>, MokshaMintError> {
         // FIXME refactor (split up in multiple functions)
         if payment_method == PaymentMethod::Bolt11 {
-            let mut tx = self.db.begin_tx().await?;
-            let invoice = self.db.get_pending_invoice(&mut tx, key.clone()).await?;
+            let invoice = match self.db.get_pending_invoice(tx, key.clone()).await {
+                Err(MokshaMintError::Db(sqlx::Error::RowNotFound)) => {
+                    return Err(self.missing_invoice_error(tx, key).await);
+                }
+                result => result?,
+            };
+            // the invoice may not be pruned yet
+            if invoice.is_expired(self.clock.now()) {
+                return Err(MokshaMintError::InvoiceExpired(key));
+            }
 
             let is_paid = self
                 .lightning
                 .is_invoice_paid(invoice.payment_request.clone())
                 .await?;
-
-            // FIXME remove after legacy api is removed
-            if return_error && !is_paid {
+            if !is_paid {
                 return Err(MokshaMintError::InvoiceNotPaidYet);
             }
 
-            self.db.delete_pending_invoice(&mut tx, key).await?;
-            tx.commit().await?;
+            // the amounts are chosen by the wallet, so the sum must not overflow
+            let outputs_amount = outputs
+                .iter()
+                .try_fold(0u64, |sum, o| sum.checked_add(o.amount))
+                .ok_or_else(|| MokshaMintError::InvalidAmount("outputs overflow".to_owned()))?;
+            if outputs_amount != invoice.amount {
+                return Err(MokshaMintError::InvalidAmount(format!(
+                    "outputs {outputs_amount} != invoice {}",
+                    invoice.amount
+                )));
+            }
+
+            self.db.delete_pending_invoice(tx, key).await?;
+        }
+        let amount = outputs
+            .iter()
+            .fold(0u64, |sum, o| sum.saturating_add(o.amount));
+        if self.config.max_outstanding.is_some() {
+            // concurrent mints would both pass the cap with the signatures of the other one
+            // uncommitted
+            self.db.lock_issuance(tx).await?;
+        }
+        self.check_issuance_cap(tx, amount).await?;
+        self.sign_outputs(tx, outputs, keyset).await
+    }
+
+    /// Removes the pending invoices that expired and returns how many were removed
+    #[instrument(level = "debug", skip(self), err)]
+    pub async fn prune_expired_invoices(&self) -> Result<u64, MokshaMintError> {
+        let mut tx = self.db.begin_tx().await?;
+        let keys = self
+            .db
+            .get_expired_invoices(&mut tx, self.clock.now())
+            .await?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect::<Vec<_>>();
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        let removed = self.db.delete_invoices(&mut tx, &keys).await?;
+        tx.commit().await?;
+        info!("removed {removed} expired pending invoices");
+        Ok(removed)
+    }
+
+    /// Fails if issuing `amount` would exceed the configured maximum of outstanding ecash. The
+    /// outstanding amount is everything that was signed and not yet redeemed.
+    async fn check_issuance_cap(
+        &self,
+        tx: &mut Transaction<'_, <DB as Database>::DB>,
+        amount: u64,
+    ) -> Result<(), MokshaMintError> {
+        let max = match self.config.max_outstanding {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        let liabilities = self.db.get_keyset_liabilities(tx).await?;
+        let issued = liabilities.iter().map(|l| l.issued).sum::<u64>();
+        let redeemed = liabilities.iter().map(|l| l.redeemed).sum::<u64>();
+        let outstanding = issued.saturating_sub(redeemed);
+        if outstanding.saturating_add(amount) > max {
+            return Err(MokshaMintError::IssuanceCapReached {
+                amount,
+                outstanding,
+                max,
+            });
+        }
+        Ok(())
+    }
+
+    /// Signs the outputs and stores the signatures for restoring them (NUT-09)
+    async fn sign_outputs(
+        &self,
+        tx: &mut Transaction<'_, <DB as Database>::DB>,
+        outputs: &[BlindedMessage],
+        keyset: &MintKeyset,
+    ) -> Result<Vec<BlindedSignature>, MokshaMintError> {
+        self.check_inactive_keysets(outputs)?;
+        let signatures = self.create_blinded_signatures(outputs, keyset)?;
+        self.db
+            .add_blind_signatures(tx, outputs, &signatures)
+            .await?;
+        Ok(signatures)
+    }
+
+    /// Signs the change of a paid melt with the keyset, whatever keyset the outputs ask for. The
+    /// outputs were checked before the payment. The savepoint keeps a failed insert from aborting
+    /// the transaction that marks the inputs as used.
+    async fn sign_change(
+        &self,
+        tx: &mut Transaction<'_, <DB as Database>::DB>,
+        outputs: &[BlindedMessage],
+        keyset: &MintKeyset,
+    ) -> Result<Vec<BlindedSignature>, MokshaMintError> {
+        let mut savepoint = tx.begin().await?;
+        let signatures = self.create_blinded_signatures(outputs, keyset)?;
+        self.db
+            .add_blind_signatures(&mut savepoint, outputs, &signatures)
+            .await?;
+        savepoint.commit().await?;
+        Ok(signatures)
+    }
+
+    /// Returns the outputs that were already signed together with their signatures (NUT-09)
+    #[instrument(level = "debug", skip_all, err)]
+    pub async fn restore(
+        &self,
+        outputs: &[BlindedMessage],
+    ) -> Result<(Vec<BlindedMessage>, Vec<BlindedSignature>), MokshaMintError> {
+        let mut tx = self.db.begin_tx().await?;
+        let b_ = outputs.iter().map(|o| o.b_).collect::<Vec<_>>();
+        let signatures = self
+            .db
+            .get_blind_signatures(&mut tx, &b_)
+            .await?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        tx.commit().await?;
+
+        Ok(outputs
+            .iter()
+            .filter_map(|output| {
+                signatures
+                    .get(&output.b_)
+                    .map(|signature| (output.clone(), signature.clone()))
+            })
+            .unzip())
+    }
+
+    /// A pending invoice that can't be found was either never created or has already been minted
+    async fn missing_invoice_error(
+        &self,
+        tx: &mut Transaction<'_, <DB as Database>::DB>,
+        key: String,
+    ) -> MokshaMintError {
+        let minted = match Uuid::from_str(&key) {
+            Ok(quote_id) => matches!(
+                self.db.get_bolt11_mint_quote(tx, &quote_id).await,
+                Ok(quote) if quote.paid
+            ),
+            Err(_) => false,
+        };
+
+        if minted {
+            MokshaMintError::InvoiceAlreadyMinted(key)
+        } else {
+            MokshaMintError::InvoiceNotFound(key)
         }
-        self.create_blinded_signatures(outputs, keyset)
     }
 
     fn has_duplicate_pubkeys(outputs: &[BlindedMessage]) -> bool {
@@ -152,6 +657,35 @@ where
         !outputs.iter().all(move |x| uniq.insert(x.b_))
     }
 
+    /// Checks the outputs of a swap before anything is signed: they must be unique, every amount
+    /// must be a denomination of the keyset and they must not exceed the inputs
+    fn verify_swap_outputs(
+        proofs: &Proofs,
+        blinded_messages: &[BlindedMessage],
+        keyset: &MintKeyset,
+    ) -> Result<(), MokshaMintError> {
+        if Self::has_duplicate_pubkeys(blinded_messages) {
+            return Err(MokshaMintError::SwapHasDuplicatePromises);
+        }
+
+        if let Some(output) = blinded_messages
+            .iter()
+            .find(|o| !keyset.private_keys.contains_key(&o.amount))
+        {
+            return Err(MokshaMintError::InvalidOutputDecomposition(output.amount));
+        }
+
+        // the amounts are chosen by the wallet, so the sum must not overflow
+        let inputs = proofs.total_amount();
+        let outputs = blinded_messages
+            .iter()
+            .fold(0u64, |sum, o| sum.saturating_add(o.amount));
+        if outputs > inputs {
+            return Err(MokshaMintError::SwapAmountTooHigh { inputs, outputs });
+        }
+        Ok(())
+    }
+
     #[instrument(level = "debug", skip_all, err)]
     pub async fn swap(
         &self,
@@ -159,38 +693,49 @@ where
         blinded_messages: &[BlindedMessage],
         keyset: &MintKeyset,
     ) -> Result<Vec<BlindedSignature>, MokshaMintError> {
+        self.verify_proofs(proofs)?;
+        Self::verify_swap_outputs(proofs, blinded_messages, keyset)?;
         let mut tx = self.db.begin_tx().await?;
-        self.check_used_proofs(&mut tx, proofs).await?;
-
-        if Self::has_duplicate_pubkeys(blinded_messages) {
-            return Err(MokshaMintError::SwapHasDuplicatePromises);
-        }
+        self.db.add_used_proofs_if_unused(&mut tx, proofs).await?;
+        self.verify_spending_conditions(proofs)?;
 
         let sum_proofs = proofs.total_amount();
+        let input_fee = self.input_fee(proofs)?;
 
-        let promises = self.create_blinded_signatures(blinded_messages, keyset)?;
+        let promises = self.sign_outputs(&mut tx, blinded_messages, keyset).await?;
         let amount_promises = promises.total_amount();
-        if sum_proofs != amount_promises {
+        if amount_promises.checked_add(input_fee) != Some(sum_proofs) {
             return Err(MokshaMintError::SwapAmountMismatch(format!(
-                "Swap amount mismatch: {sum_proofs} != {amount_promises}"
+                "Swap amount mismatch: {sum_proofs} != {amount_promises} + fee {input_fee}"
             )));
         }
 
-        self.db.add_used_proofs(&mut tx, proofs).await?;
         tx.commit().await?;
         Ok(promises)
     }
 
+    /// Pays the invoice of a melt quote. `amount` is the amount of the quote in sats. The proofs
+    /// must cover the amount, the `fee_reserve` of the quote and the input fee, everything that is
+    /// not spent on the invoice and the routing fee is returned as change.
+    #[allow(clippy::too_many_arguments)]
     #[instrument(level = "debug", skip(self, proofs, blinded_messages, keyset), err)]
     pub async fn melt_bolt11(
         &self,
         tx: &mut Transaction<'_, <DB as Database>::DB>,
         payment_request: String,
+        amount: u64,
         fee_reserve: u64,
         proofs: &Proofs,
         blinded_messages: &[BlindedMessage],
         keyset: &MintKeyset,
-    ) -> Result<(bool, String, Vec<BlindedSignature>), MokshaMintError> {
+    ) -> Result<MeltResult, MokshaMintError> {
+        self.verify_proofs(proofs)?;
+        // the change outputs are checked before the payment, once the invoice is paid the melt
+        // must not fail anymore
+        if Self::has_duplicate_pubkeys(blinded_messages) {
+            return Err(MokshaMintError::SwapHasDuplicatePromises);
+        }
+        self.check_inactive_keysets(blinded_messages)?;
         let invoice = self
             .lightning
             .decode_invoice(payment_request.clone())
@@ -198,48 +743,78 @@ where
 
         let proofs_amount = proofs.total_amount();
 
-        // TODO verify proofs
-
-        self.check_used_proofs(tx, proofs).await?;
+        // the proofs are marked as used before the payment, so a concurrent melt of the same
+        // proofs waits for this one. The savepoint drops them again if the melt fails, even if the
+        // caller commits the transaction.
+        let mut savepoint = tx.begin().await?;
+        self.db
+            .add_used_proofs_if_unused(&mut savepoint, proofs)
+            .await?;
+        self.verify_spending_conditions(proofs)?;
 
-        // TODO check for fees
-        let amount_msat = invoice
-            .amount_milli_satoshis()
-            .expect("Invoice amount is missing");
+        // the limits may have changed since the quote was created
+        let amount_sat = self.melt_amount(invoice.amount_milli_satoshis(), Some(amount))?;
 
-        if amount_msat < (proofs_amount / 1_000) {
-            return Err(MokshaMintError::InvoiceAmountTooLow(format!(
-                "Invoice amount is too low: {amount_msat}",
-            )));
+        // the invoice may have expired since the quote was created
+        if invoice.would_expire(Duration::from_secs(self.clock.now())) {
+            return Err(MokshaMintError::InvoiceExpired(payment_request));
         }
 
-        // TODO check invoice
+        let input_fee = self.input_fee(proofs)?;
+        if proofs_amount < amount_sat + fee_reserve + input_fee {
+            return Err(MokshaMintError::NotEnoughTokens(
+                amount_sat + fee_reserve + input_fee,
+            ));
+        }
 
         let result = self.lightning.pay_invoice(payment_request).await?;
-        self.db.add_used_proofs(tx, proofs).await?;
-
-        let change = if fee_reserve > 0 {
-            let return_fees = Amount(fee_reserve - result.total_fees).split();
-
-            if (return_fees.len()) > blinded_messages.len() {
-                // FIXME better handle case when there are more fees than blinded messages
-                vec![]
-            } else {
-                let out: Vec<_> = blinded_messages[0..return_fees.len()]
-                    .iter()
-                    .zip(return_fees.into_iter())
-                    .map(|(message, fee)| BlindedMessage {
-                        amount: fee,
-                        ..message.clone()
-                    })
-                    .collect();
-
-                self.create_blinded_signatures(&out, keyset)?
-            }
-        } else {
+        savepoint.commit().await?;
+
+        // everything that was not spent on the invoice, the routing fees and the input fee is
+        // returned as change
+        let change_amount =
+            proofs_amount.saturating_sub(amount_sat + result.total_fees + input_fee);
+        // with fewer outputs than denominations of the change the largest denominations are
+        // returned, the rest is kept by the mint
+        let change_amounts = Amount(change_amount)
+            .split()
+            .into_iter()
+            .rev()
+            .take(blinded_messages.len())
+            .collect::<Vec<_>>();
+        let kept = change_amount - change_amounts.iter().sum::<u64>();
+        if kept > 0 {
+            warn!(
+                "{kept} sats of change exceed the {} outputs of the melt",
+                blinded_messages.len()
+            );
+        }
+        let change = if change_amounts.is_empty() {
             vec![]
+        } else {
+            let out: Vec<_> = blinded_messages
+                .iter()
+                .zip(change_amounts)
+                .map(|(message, amount)| BlindedMessage {
+                    amount,
+                    ..message.clone()
+                })
+                .collect();
+            // a failure here would roll back the used proofs of a paid invoice, so the change
+            // is kept by the mint instead
+            self.sign_change(tx, &out, keyset)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("failed to sign the change of a paid melt: {e}");
+                    vec![]
+                })
         };
-        Ok((true, result.payment_hash, change))
+        Ok(MeltResult {
+            paid: true,
+            payment_hash: result.payment_hash,
+            fee_paid: result.total_fees,
+            change,
+        })
     }
 
     pub async fn check_used_proofs(
@@ -247,21 +822,139 @@ where
         tx: &mut Transaction<'_, <DB as Database>::DB>,
         proofs: &Proofs,
     ) -> Result<(), MokshaMintError> {
-        let used_proofs = self.db.get_used_proofs(tx).await?.proofs();
-        for used_proof in used_proofs {
-            if proofs.proofs().contains(&used_proof) {
-                return Err(MokshaMintError::ProofAlreadyUsed(format!("{used_proof:?}")));
+        let secrets = proofs
+            .proofs()
+            .into_iter()
+            .map(|proof| proof.secret)
+            .collect::<Vec<_>>();
+        let used_proofs = self.db.get_used_proofs_by_secrets(tx, &secrets).await?;
+        match used_proofs.proofs().into_iter().next() {
+            Some(used_proof) => Err(MokshaMintError::ProofAlreadyUsed(format!("{used_proof:?}"))),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that the proofs belong to a keyset of the mint and that their signatures are valid
+    pub fn verify_proofs(&self, proofs: &Proofs) -> Result<(), MokshaMintError> {
+        self.check_known_keysets(proofs)?;
+        self.check_keyset_sunsets(proofs)?;
+        for proof in proofs.proofs() {
+            let keyset = self
+                .get_keyset(&proof.keyset_id)
+                .ok_or_else(|| MokshaMintError::UnknownKeyset(proof.keyset_id.clone()))?;
+            let private_key = keyset
+                .private_keys
+                .get(&proof.amount)
+                .ok_or_else(|| MokshaMintError::InvalidProof(proof.secret.clone()))?;
+            if !self
+                .dhke
+                .verify(*private_key, proof.c, proof.secret.clone())?
+            {
+                return Err(MokshaMintError::InvalidProof(proof.secret));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the mint has the keys of every proof
+    fn check_known_keysets(&self, proofs: &Proofs) -> Result<(), MokshaMintError> {
+        let keysets = self.keysets.read().expect("keysets lock is poisoned");
+        match proofs
+            .proofs()
+            .into_iter()
+            .find(|proof| !keysets.keysets.contains_key(&proof.keyset_id))
+        {
+            Some(proof) => Err(MokshaMintError::UnknownKeyset(proof.keyset_id)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that no proof is from a keyset after its sunset
+    fn check_keyset_sunsets(&self, proofs: &Proofs) -> Result<(), MokshaMintError> {
+        let now = self.clock.now();
+        let keysets = self.keysets.read().expect("keysets lock is poisoned");
+        match proofs.proofs().into_iter().find(|proof| {
+            keysets
+                .keysets
+                .get(&proof.keyset_id)
+                .and_then(|entry| entry.sunset_at)
+                .is_some_and(|sunset_at| sunset_at <= now)
+        }) {
+            Some(proof) => Err(MokshaMintError::KeysetExpired(proof.keyset_id)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that no output asks for a signature of an inactive keyset of the mint
+    fn check_inactive_keysets(&self, outputs: &[BlindedMessage]) -> Result<(), MokshaMintError> {
+        let keysets = self.keysets.read().expect("keysets lock is poisoned");
+        match outputs
+            .iter()
+            .find(|output| output.id != keysets.active && keysets.keysets.contains_key(&output.id))
+        {
+            Some(output) => Err(MokshaMintError::KeysetInactive(output.id.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks the spending conditions of proofs with well-known secrets (NUT-10).
+    ///
+    /// P2PK proofs (NUT-11) are only accepted after their locktime, signatures are not supported yet.
+    pub fn verify_spending_conditions(&self, proofs: &Proofs) -> Result<(), MokshaMintError> {
+        let now = self.clock.now();
+        for proof in proofs.proofs() {
+            let secret = match proof.well_known_secret() {
+                Some(secret) => secret,
+                None => continue,
+            };
+
+            match secret.locktime() {
+                Some(locktime) if locktime <= now => {}
+                Some(locktime) => return Err(MokshaMintError::ProofLocked(locktime)),
+                None => {
+                    return Err(MokshaMintError::UnsupportedSpendingCondition(
+                        "P2PK without locktime".to_owned(),
+                    ))
+                }
             }
         }
         Ok(())
     }
 
+    /// Returns the state of the proofs identified by their Y values (NUT-07)
+    #[instrument(level = "debug", skip_all, err)]
+    pub async fn check_state(&self, ys: &[PublicKey]) -> Result<Vec<ProofState>, MokshaMintError> {
+        let mut tx = self.db.begin_tx().await?;
+        let used_proofs = self.db.get_used_proofs_by_ys(&mut tx, ys).await?;
+        tx.commit().await?;
+
+        let spent = used_proofs
+            .proofs()
+            .iter()
+            .map(|proof| proof.y())
+            .collect::<Result<HashSet<PublicKey>, _>>()?;
+
+        Ok(ys
+            .iter()
+            .map(|y| ProofState {
+                y: *y,
+                state: if spent.contains(y) {
+                    State::Spent
+                } else {
+                    State::Unspent
+                },
+                witness: None,
+            })
+            .collect())
+    }
+
     #[instrument(level = "debug", skip(self, proofs), err)]
     pub async fn melt_onchain(
         &self,
         quote: &BtcOnchainMeltQuote,
         proofs: &Proofs,
     ) -> Result<String, MokshaMintError> {
+        self.verify_proofs(proofs)?;
         let proofs_amount = proofs.total_amount();
 
         if proofs_amount < quote.amount {
@@ -269,7 +962,8 @@ where
         }
 
         let mut tx = self.db.begin_tx().await?;
-        self.check_used_proofs(&mut tx, proofs).await?;
+        self.db.add_used_proofs_if_unused(&mut tx, proofs).await?;
+        self.verify_spending_conditions(proofs)?;
 
         let send_response = self
             .onchain
@@ -277,22 +971,44 @@ where
             .expect("onchain backend not set")
             .send_coins(&quote.address, quote.amount, quote.fee_sat_per_vbyte)
             .await?;
-
-        self.db.add_used_proofs(&mut tx, proofs).await?;
         tx.commit().await?;
 
         Ok(send_response.txid)
     }
 }
 
+impl<DB> Mint<DB>
+where
+    DB: Database + Clone + Send + Sync + 'static,
+{
+    /// Spawns the task that removes expired pending invoices, it runs right away and then every
+    /// `prune_interval` seconds
+    pub fn spawn_invoice_pruning(&self) -> tokio::task::JoinHandle<()> {
+        let mint = self.clone();
+        let period = Duration::from_secs(self.config.pending_invoice.prune_interval.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = mint.prune_expired_invoices().await {
+                    warn!("failed to prune expired invoices: {e}");
+                }
+            }
+        })
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct MintBuilder {
     private_key: Option<String>,
     derivation_path: Option<String>,
+    input_fee_ppk: u64,
+    max_outstanding: Option<u64>,
     lightning_type: Option<LightningType>,
 
     db_config: Option<DatabaseConfig>,
     fee_config: Option<LightningFeeConfig>,
+    pending_invoice_config: Option<PendingInvoiceConfig>,
     mint_info_settings: Option<MintInfoConfig>,
     server_config: Option<ServerConfig>,
     btc_onchain_config: Option<BtcOnchainConfig>,
@@ -304,9 +1020,12 @@ impl MintBuilder {
         MintBuilder {
             private_key: None,
             derivation_path: None,
+            input_fee_ppk: 0,
+            max_outstanding: None,
             lightning_type: None,
             db_config: None,
             fee_config: None,
+            pending_invoice_config: None,
             mint_info_settings: None,
             server_config: None,
             btc_onchain_config: None,
@@ -339,6 +1058,18 @@ impl MintBuilder {
         self
     }
 
+    /// Sets the input fee of the active keyset in parts per thousand per proof
+    pub const fn with_input_fee_ppk(mut self, input_fee_ppk: u64) -> Self {
+        self.input_fee_ppk = input_fee_ppk;
+        self
+    }
+
+    /// Caps the amount of ecash that may be outstanding, `None` doesn't limit minting
+    pub const fn with_max_outstanding(mut self, max_outstanding: Option<u64>) -> Self {
+        self.max_outstanding = max_outstanding;
+        self
+    }
+
     pub fn with_lightning(mut self, lightning: LightningType) -> Self {
         self.lightning_type = Some(lightning);
         self
@@ -349,6 +1080,15 @@ impl MintBuilder {
         self
     }
 
+    /// Sets the expiry of pending invoices, `None` uses the default
+    pub const fn with_pending_invoice(
+        mut self,
+        pending_invoice_config: Option<PendingInvoiceConfig>,
+    ) -> Self {
+        self.pending_invoice_config = pending_invoice_config;
+        self
+    }
+
     pub fn with_btc_onchain(mut self, btc_onchain_config: Option<BtcOnchainConfig>) -> Self {
         self.btc_onchain_config = btc_onchain_config;
         self
@@ -414,7 +1154,7 @@ impl MintBuilder {
         let db = PostgresDB::new(&db_config).await?;
         db.migrate().await;
 
-        Ok(Mint::new(
+        let mint = Mint::new(
             ln,
             self.lightning_type
                 .clone()
@@ -424,8 +1164,11 @@ impl MintBuilder {
             MintConfig::new(
                 self.private_key.expect("private-key not set"),
                 self.derivation_path,
+                self.input_fee_ppk,
+                self.max_outstanding,
                 self.mint_info_settings.unwrap_or_default(),
                 self.fee_config.expect("fee-config not set"),
+                self.pending_invoice_config.unwrap_or_default(),
                 self.server_config.unwrap_or_default(),
                 db_config,
                 self.btc_onchain_config,
@@ -434,33 +1177,48 @@ impl MintBuilder {
             ),
             BuildParams::from_env(),
             lnd_onchain,
-        ))
+        );
+        mint.load_keysets().await?;
+        Ok(mint)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::btconchain::MockBtcOnchain;
-    use crate::config::{DatabaseConfig, MintConfig};
+    use crate::clock::MockClock;
+    use crate::config::{DatabaseConfig, LightningFeeConfig, MintConfig, PendingInvoiceConfig};
     use crate::database::postgres::PostgresDB;
     use crate::database::Database;
     use crate::error::MokshaMintError;
     use crate::lightning::error::LightningError;
     use crate::lightning::{LightningType, MockLightning};
     use crate::mint::Mint;
-    use crate::model::{Invoice, PayInvoiceResult};
-    use moksha_core::blind::{BlindedMessage, TotalAmount};
-    use moksha_core::dhke;
+    use crate::model::{CreateInvoiceResult, Invoice, MeltResult, PayInvoiceResult};
+    use moksha_core::blind::{BlindedMessage, BlindingFactor, TotalAmount};
+    use moksha_core::dhke::{self, Dhke};
     use moksha_core::fixture::read_fixture_as;
-    use moksha_core::primitives::PostSwapRequest;
-    use moksha_core::proof::Proofs;
+    use moksha_core::keyset::MintKeyset;
+    use moksha_core::primitives::{Bolt11MintQuote, PostSwapRequest, State};
+    use moksha_core::proof::{Proof, Proofs};
+    use moksha_core::secret::WellKnownSecret;
     use moksha_core::token::TokenV3;
     use pretty_assertions::assert_eq;
+    use secp256k1::SecretKey;
+    use std::collections::HashMap;
     use std::str::FromStr;
+    use std::sync::atomic::{AtomicU64, Ordering};
     use std::sync::Arc;
     use testcontainers::clients::Cli;
     use testcontainers::RunnableImage;
     use testcontainers_modules::postgres::Postgres;
+    use uuid::Uuid;
+
+    /// expiry of pending invoices in tests that don't depend on it, the largest value postgres
+    /// can store
+    const NO_EXPIRY: u64 = i64::MAX as u64;
+    /// creation time of the 20 sat invoice of the melt tests, which expires 600 seconds later
+    const FIXTURE_INVOICE_TIME: u64 = 1_683_813_990;
 
     #[tokio::test]
     async fn test_fee_reserve() -> anyhow::Result<()> {
@@ -475,21 +1233,170 @@ mod tests {
         .await?;
         let fee = mint.fee_reserve(10000);
         assert_eq!(4000, fee);
+        // the minimum of 4000 msat is rounded to 4 sats
+        assert_eq!(4, mint.fee_reserve_sat(10));
+        // 1% of 1000 sats
+        assert_eq!(10, mint.fee_reserve_sat(1_000));
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_create_blindsignatures() -> anyhow::Result<()> {
+    async fn test_melt_amount_limits() -> anyhow::Result<()> {
         let docker = Cli::default();
         let image = create_postgres_image();
         let node = docker.run(image);
 
-        let mint = create_mint_from_mocks(
+        let mut mint = create_mint_from_mocks(
             create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
             None,
         )
         .await?;
-
+        mint.config.lightning_fee = LightningFeeConfig::default().with_melt_limits(10, 100);
+
+        assert_eq!(10, mint.melt_amount(Some(10_000), None)?);
+        assert_eq!(100, mint.melt_amount(Some(100_000), None)?);
+        assert!(matches!(
+            mint.melt_amount(Some(9_999), None),
+            Err(MokshaMintError::MeltAmountTooLow { amount: 9, min: 10 })
+        ));
+        assert!(matches!(
+            mint.melt_amount(Some(101_000), None),
+            Err(MokshaMintError::MeltAmountTooHigh {
+                amount: 101,
+                max: 100
+            })
+        ));
+
+        // amountless invoices are bounded by the amount of the caller
+        assert_eq!(10, mint.melt_amount(None, Some(10))?);
+        assert_eq!(100, mint.melt_amount(None, Some(100))?);
+        assert!(matches!(
+            mint.melt_amount(None, Some(9)),
+            Err(MokshaMintError::MeltAmountTooLow { amount: 9, min: 10 })
+        ));
+        assert!(matches!(
+            mint.melt_amount(None, Some(101)),
+            Err(MokshaMintError::MeltAmountTooHigh {
+                amount: 101,
+                max: 100
+            })
+        ));
+        assert!(matches!(
+            mint.melt_amount(None, None),
+            Err(MokshaMintError::InvalidAmount(_))
+        ));
+        assert!(matches!(
+            mint.melt_amount(Some(20_000), Some(21)),
+            Err(MokshaMintError::InvalidAmount(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_melt_below_min_melt() -> anyhow::Result<()> {
+        use lightning_invoice::Bolt11Invoice as LNInvoice;
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(
+                // 20 sat
+                LNInvoice::from_str("lnbc200n1pj9eanxsp5agdl4rd0twdljpcgmg67dwj9mseu5m4lwfhslkws4uh4m5f5pcrqpp5lvspx676rykr64l02s97wjztcxe355qck0naydrsvvkqw42cc35sdq2f38xy6t5wvxqzjccqpjrzjq027t9tsc6jn5ve2k6gnn689unn8h239juuf9s3ce09aty6ed73t5z7nqsqqsygqqyqqqqqqqqqqqqgq9q9qyysgqs5msn4j9v53fq000zhw0gulkcx2dlnfdt953v2ur7z765jj3m0fx6cppkpjwntq5nsqm273u4eevva508pvepg8mh27sqcd29sfjr4cq255a40").expect("invalid invoice")
+            )
+        });
+        lightning.expect_pay_invoice().never();
+        let mut mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+        // the limit was raised after the quote was created
+        mint.config.lightning_fee = LightningFeeConfig::default().with_melt_limits(21, 100);
+
+        let tokens = create_token_from_fixture("token_60.cashu")?;
+        let proofs = sign_proofs(&mint.keyset(), &tokens.proofs())?;
+        let change = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_blank_4000.json")?;
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &proofs,
+                &change,
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::MeltAmountTooLow {
+                amount: 20,
+                min: 21
+            })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_melt_expired_invoice() -> anyhow::Result<()> {
+        use lightning_invoice::Bolt11Invoice as LNInvoice;
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(
+                // 20 sat
+                LNInvoice::from_str("lnbc200n1pj9eanxsp5agdl4rd0twdljpcgmg67dwj9mseu5m4lwfhslkws4uh4m5f5pcrqpp5lvspx676rykr64l02s97wjztcxe355qck0naydrsvvkqw42cc35sdq2f38xy6t5wvxqzjccqpjrzjq027t9tsc6jn5ve2k6gnn689unn8h239juuf9s3ce09aty6ed73t5z7nqsqqsygqqyqqqqqqqqqqqqgq9q9qyysgqs5msn4j9v53fq000zhw0gulkcx2dlnfdt953v2ur7z765jj3m0fx6cppkpjwntq5nsqm273u4eevva508pvepg8mh27sqcd29sfjr4cq255a40").expect("invalid invoice")
+            )
+        });
+        lightning.expect_pay_invoice().never();
+        let mut mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+        let mut clock = MockClock::new();
+        clock.expect_now().returning(|| FIXTURE_INVOICE_TIME + 601);
+        mint.clock = Arc::new(clock);
+
+        let tokens = create_token_from_fixture("token_60.cashu")?;
+        let proofs = sign_proofs(&mint.keyset(), &tokens.proofs())?;
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &proofs,
+                &[],
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::InvoiceExpired(_))));
+        tx.commit().await?;
+        let mut tx = mint.db.begin_tx().await?;
+        assert!(mint.db.get_used_proofs(&mut tx).await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_create_blindsignatures() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+
         let blinded_messages = vec![BlindedMessage {
             amount: 8,
             b_: dhke::public_key_from_hex(
@@ -498,7 +1405,7 @@ mod tests {
             id: "00ffd48b8f5ecf80".to_owned(),
         }];
 
-        let result = mint.create_blinded_signatures(&blinded_messages, &mint.keyset)?;
+        let result = mint.create_blinded_signatures(&blinded_messages, &mint.keyset())?;
 
         assert_eq!(1, result.len());
         assert_eq!(8, result[0].amount);
@@ -508,6 +1415,13 @@ mod tests {
             ),
             result[0].c_
         );
+        let dleq = result[0].dleq.expect("dleq is missing");
+        assert!(mint.dhke.verify_dleq(
+            &dleq,
+            blinded_messages[0].b_,
+            result[0].c_,
+            mint.keyset().public_keys[&8]
+        )?);
         Ok(())
     }
 
@@ -520,7 +1434,7 @@ mod tests {
         let mut lightning = MockLightning::new();
         lightning.expect_is_invoice_paid().returning(|_| Ok(true));
         let mint = create_mint_from_mocks(
-            create_mock_db_pending_invoice(node.get_host_port_ipv4(5432)).await?,
+            create_mock_db_pending_invoice(node.get_host_port_ipv4(5432), 100).await?,
             Some(lightning),
         )
         .await?;
@@ -533,16 +1447,15 @@ mod tests {
                 moksha_core::primitives::PaymentMethod::Bolt11,
                 "somehash".to_string(),
                 &outputs,
-                &mint.keyset,
-                true,
+                &mint.keyset(),
             )
-            .await?;
-        assert!(result.is_empty());
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::InvalidAmount(_))));
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_mint_valid() -> anyhow::Result<()> {
+    async fn test_mint_less_than_invoice() -> anyhow::Result<()> {
         let docker = Cli::default();
         let image = create_postgres_image();
         let node = docker.run(image);
@@ -550,7 +1463,7 @@ mod tests {
         let mut lightning = MockLightning::new();
         lightning.expect_is_invoice_paid().returning(|_| Ok(true));
         let mint = create_mint_from_mocks(
-            create_mock_db_pending_invoice(node.get_host_port_ipv4(5432)).await?,
+            create_mock_db_pending_invoice(node.get_host_port_ipv4(5432), 100).await?,
             Some(lightning),
         )
         .await?;
@@ -563,89 +1476,158 @@ mod tests {
                 moksha_core::primitives::PaymentMethod::Bolt11,
                 "somehash".to_string(),
                 &outputs,
-                &mint.keyset,
-                true,
+                &mint.keyset(),
             )
-            .await?;
-        assert_eq!(40, result.total_amount());
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::InvalidAmount(_))));
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_swap_zero() -> anyhow::Result<()> {
+    async fn test_mint_outputs_overflow() -> anyhow::Result<()> {
         let docker = Cli::default();
         let image = create_postgres_image();
         let node = docker.run(image);
 
-        let blinded_messages = vec![];
+        let mut lightning = MockLightning::new();
+        lightning.expect_is_invoice_paid().returning(|_| Ok(true));
         let mint = create_mint_from_mocks(
-            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
-            None,
+            create_mock_db_pending_invoice(node.get_host_port_ipv4(5432), 100).await?,
+            Some(lightning),
         )
         .await?;
 
-        let proofs = Proofs::empty();
-        let result = mint.swap(&proofs, &blinded_messages, &mint.keyset).await?;
-
-        assert!(result.is_empty());
+        // the amounts wrap around to the amount of the invoice
+        let output = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_40.json")?[0].clone();
+        let outputs = [1 << 63, 1 << 63, 64, 32, 4]
+            .into_iter()
+            .map(|amount| BlindedMessage {
+                amount,
+                ..output.clone()
+            })
+            .collect::<Vec<_>>();
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .mint_tokens(
+                &mut tx,
+                moksha_core::primitives::PaymentMethod::Bolt11,
+                "somehash".to_string(),
+                &outputs,
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::InvalidAmount(_))));
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_swap_64_in_20() -> anyhow::Result<()> {
+    async fn test_mint_more_than_invoice() -> anyhow::Result<()> {
         let docker = Cli::default();
         let image = create_postgres_image();
         let node = docker.run(image);
 
+        let mut lightning = MockLightning::new();
+        lightning.expect_is_invoice_paid().returning(|_| Ok(true));
         let mint = create_mint_from_mocks(
-            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
-            None,
+            create_mock_db_pending_invoice(node.get_host_port_ipv4(5432), 8).await?,
+            Some(lightning),
         )
         .await?;
-        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
 
+        let outputs = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_40.json")?;
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .mint_tokens(
+                &mut tx,
+                moksha_core::primitives::PaymentMethod::Bolt11,
+                "somehash".to_string(),
+                &outputs,
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::InvalidAmount(_))));
+
+        // the invoice can still be minted with the correct amount
         let result = mint
-            .swap(&request.inputs, &request.outputs, &mint.keyset)
+            .mint_tokens(
+                &mut tx,
+                moksha_core::primitives::PaymentMethod::Bolt11,
+                "somehash".to_string(),
+                &outputs[0..1],
+                &mint.keyset(),
+            )
             .await?;
-        assert_eq!(result.total_amount(), 64);
+        assert_eq!(8, result.total_amount());
+        Ok(())
+    }
 
-        let prv_last = result.get(result.len() - 2).expect("element not found");
-        let last = result.last().expect("element not found");
+    #[tokio::test]
+    async fn test_mint_not_paid() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
 
-        assert_eq!(prv_last.amount, 4);
-        assert_eq!(last.amount, 16);
+        let mut lightning = MockLightning::new();
+        lightning.expect_is_invoice_paid().returning(|_| Ok(false));
+        let mint = create_mint_from_mocks(
+            create_mock_db_pending_invoice(node.get_host_port_ipv4(5432), 40).await?,
+            Some(lightning),
+        )
+        .await?;
+
+        let outputs = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_40.json")?;
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .mint_tokens(
+                &mut tx,
+                moksha_core::primitives::PaymentMethod::Bolt11,
+                "somehash".to_string(),
+                &outputs,
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::InvoiceNotPaidYet)));
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_swap_duplicate_key() -> anyhow::Result<()> {
+    async fn test_mint_valid() -> anyhow::Result<()> {
         let docker = Cli::default();
         let image = create_postgres_image();
         let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_is_invoice_paid().returning(|_| Ok(true));
         let mint = create_mint_from_mocks(
-            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
-            None,
+            create_mock_db_pending_invoice(node.get_host_port_ipv4(5432), 40).await?,
+            Some(lightning),
         )
         .await?;
-        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_duplicate_key.json")?;
 
+        let outputs = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_40.json")?;
+        let mut tx = mint.db.begin_tx().await?;
         let result = mint
-            .swap(&request.inputs, &request.outputs, &mint.keyset)
-            .await;
-        assert!(result.is_err());
+            .mint_tokens(
+                &mut tx,
+                moksha_core::primitives::PaymentMethod::Bolt11,
+                "somehash".to_string(),
+                &outputs,
+                &mint.keyset(),
+            )
+            .await?;
+        assert_eq!(40, result.total_amount());
         Ok(())
     }
 
     #[tokio::test]
-    /// melt 20 sats with 60 tokens and receive 40 tokens as change
-    async fn test_melt_overpay() -> anyhow::Result<()> {
+    async fn test_mint_issuance_cap() -> anyhow::Result<()> {
         use lightning_invoice::Bolt11Invoice as LNInvoice;
         let docker = Cli::default();
         let image = create_postgres_image();
         let node = docker.run(image);
 
         let mut lightning = MockLightning::new();
-
+        lightning.expect_is_invoice_paid().returning(|_| Ok(true));
         lightning.expect_decode_invoice().returning(|_| {
             Ok(
                 // 20 sat
@@ -657,63 +1639,1712 @@ mod tests {
                 payment_hash: "hash".to_string(),
                 total_fees: 2,
             })
-            .map_err(|_err: LightningError| MokshaMintError::InvoiceNotFound("".to_string()))
         });
-
         let db = create_mock_db_empty(node.get_host_port_ipv4(5432)).await?;
+        let mut mint = create_mint_from_mocks(db, Some(lightning)).await?;
+        mint.config.max_outstanding = Some(80);
+
+        let outputs = |amounts: &[u64], prefix: &str| {
+            let blinding_factor: BlindingFactor = SecretKey::from_slice(&[1; 32])?.into();
+            amounts
+                .iter()
+                .enumerate()
+                .map(|(i, amount)| {
+                    Ok(BlindedMessage {
+                        amount: *amount,
+                        b_: mint
+                            .dhke
+                            .step1_alice(format!("{prefix}{i}"), &blinding_factor)?,
+                        id: mint.keyset().keyset_id,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        };
+        let mint_invoice = |key: &'static str, outputs: Vec<BlindedMessage>| {
+            let mint = &mint;
+            async move {
+                let mut tx = mint.db.begin_tx().await?;
+                let invoice =
+                    Invoice::new(outputs.total_amount(), "invoice".to_string(), 0, NO_EXPIRY);
+                mint.db
+                    .add_pending_invoice(&mut tx, key.to_string(), &invoice)
+                    .await?;
+                let result = mint
+                    .mint_tokens(
+                        &mut tx,
+                        moksha_core::primitives::PaymentMethod::Bolt11,
+                        key.to_string(),
+                        &outputs,
+                        &mint.keyset(),
+                    )
+                    .await;
+                if result.is_ok() {
+                    tx.commit().await?;
+                }
+                anyhow::Ok(result)
+            }
+        };
 
-        let mint = Mint::new(
-            // "TEST_PRIVATE_KEY".to_string(),
-            // "0/0/0/0".to_string(),
-            Arc::new(lightning),
-            LightningType::Lnbits(Default::default()),
-            db,
-            Default::default(),
-            Default::default(),
-            Some(Arc::new(MockBtcOnchain::default())),
-        );
+        // minting up to the cap
+        let first = outputs(&[8, 32], "first")?;
+        assert_eq!(40, mint_invoice("first", first).await??.total_amount());
+        let second = outputs(&[8, 32], "second")?;
+        assert_eq!(40, mint_invoice("second", second).await??.total_amount());
+
+        // past the cap, no new quotes are created either
+        let third = outputs(&[8, 16], "third")?;
+        let result = mint_invoice("third", third.clone()).await?;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::IssuanceCapReached {
+                amount: 24,
+                outstanding: 80,
+                max: 80
+            })
+        ));
+        let result = mint.create_invoice("fourth".to_string(), 1).await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::IssuanceCapReached { .. })
+        ));
+
+        // melting 60 sats without change frees headroom
+        let tokens = create_token_from_fixture("token_60.cashu")?;
+        let proofs = sign_proofs(&mint.keyset(), &tokens.proofs())?;
+        let mut tx = mint.db.begin_tx().await?;
+        mint.melt_bolt11(
+            &mut tx,
+            "some invoice".to_string(),
+            20,
+            4,
+            &proofs,
+            &[],
+            &mint.keyset(),
+        )
+        .await?;
+        tx.commit().await?;
+        assert_eq!(24, mint_invoice("third", third).await??.total_amount());
+        Ok(())
+    }
 
-        let tokens = create_token_from_fixture("token_60.cashu").expect("can not read fixture");
-        let invoice = "some invoice".to_string();
-        let change = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_blank_4000.json")?;
+    #[tokio::test]
+    async fn test_mint_issuance_cap_concurrent() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
 
-        let mut tx = mint.db.begin_tx().await?;
-        let (paid, _payment_hash, change) = mint
-            .melt_bolt11(&mut tx, invoice, 4, &tokens.proofs(), &change, &mint.keyset)
-            .await?;
+        let mut lightning = MockLightning::new();
+        lightning.expect_is_invoice_paid().returning(|_| Ok(true));
+        let db = create_mock_db_empty(node.get_host_port_ipv4(5432)).await?;
+        let mut mint = create_mint_from_mocks(db, Some(lightning)).await?;
+        mint.config.max_outstanding = Some(60);
+
+        let blinding_factor: BlindingFactor = SecretKey::from_slice(&[1; 32])?.into();
+        let mint_invoice = |key: &'static str| {
+            let mint = &mint;
+            let blinding_factor = blinding_factor.clone();
+            async move {
+                let outputs = vec![BlindedMessage {
+                    amount: 32,
+                    b_: mint.dhke.step1_alice(key.to_string(), &blinding_factor)?,
+                    id: mint.keyset().keyset_id,
+                }];
+                let mut tx = mint.db.begin_tx().await?;
+                let invoice = Invoice::new(32, "invoice".to_string(), 0, NO_EXPIRY);
+                mint.db
+                    .add_pending_invoice(&mut tx, key.to_string(), &invoice)
+                    .await?;
+                tx.commit().await?;
+
+                let mut tx = mint.db.begin_tx().await?;
+                let result = mint
+                    .mint_tokens(
+                        &mut tx,
+                        moksha_core::primitives::PaymentMethod::Bolt11,
+                        key.to_string(),
+                        &outputs,
+                        &mint.keyset(),
+                    )
+                    .await;
+                if result.is_ok() {
+                    tx.commit().await?;
+                }
+                anyhow::Ok(result)
+            }
+        };
 
-        assert!(paid);
-        assert!(change.total_amount() == 2);
+        // each mint fits below the cap, both together don't
+        let (first, second) = tokio::join!(mint_invoice("first"), mint_invoice("second"));
+        let results = [first?, second?];
+        assert_eq!(1, results.iter().filter(|r| r.is_ok()).count());
+        assert!(results.iter().any(|r| matches!(
+            r,
+            Err(MokshaMintError::IssuanceCapReached {
+                amount: 32,
+                outstanding: 32,
+                max: 60
+            })
+        )));
         Ok(())
     }
 
-    fn create_token_from_fixture(fixture: &str) -> Result<TokenV3, anyhow::Error> {
-        let base_dir = std::env::var("CARGO_MANIFEST_DIR")?;
-        let raw_token = std::fs::read_to_string(format!("{base_dir}/src/fixtures/{fixture}"))?;
-        Ok(raw_token.trim().to_string().try_into()?)
-    }
+    #[tokio::test]
+    async fn test_redeemed_proofs_pages() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
 
-    async fn create_mint_from_mocks(
-        mock_db: PostgresDB,
-        mock_ln: Option<MockLightning>,
-    ) -> anyhow::Result<Mint> {
-        let lightning = match mock_ln {
-            Some(ln) => Arc::new(ln),
-            None => Arc::new(MockLightning::new()),
+        let proof = create_token_from_fixture("token_60.cashu")?
+            .proofs()
+            .proofs()[0]
+            .clone();
+        let redeem = |prefix: &'static str, count: u64| {
+            let mint = &mint;
+            let proof = proof.clone();
+            async move {
+                let proofs = (0..count)
+                    .map(|i| Proof {
+                        secret: format!("{prefix}{i}"),
+                        ..proof.clone()
+                    })
+                    .collect::<Vec<_>>();
+                let mut tx = mint.db.begin_tx().await?;
+                mint.db
+                    .add_used_proofs_if_unused(&mut tx, &proofs.into())
+                    .await?;
+                tx.commit().await?;
+                anyhow::Ok(())
+            }
         };
+        redeem("proof", 10_000).await?;
+
+        let collect_pages = |limit: u64| {
+            let mint = &mint;
+            async move {
+                let mut secrets = vec![];
+                let mut before = None;
+                loop {
+                    let page = mint.redeemed_proofs(before.as_ref(), limit).await?;
+                    secrets.extend(page.proofs.iter().map(|proof| proof.secret.clone()));
+                    match page.next {
+                        Some(_) => assert_eq!(limit as usize, page.proofs.len()),
+                        None => return anyhow::Ok(secrets),
+                    }
+                    before = page.next;
+                }
+            }
+        };
+        // the newest first, without duplicates or omissions
+        let expected = (0..10_000)
+            .rev()
+            .map(|i| format!("proof{i}"))
+            .collect::<Vec<_>>();
+        for limit in [333, 1_000, 10_000] {
+            assert_eq!(expected, collect_pages(limit).await?);
+        }
 
-        Ok(Mint::new(
-            lightning,
-            LightningType::Lnbits(Default::default()),
-            mock_db,
-            MintConfig {
-                privatekey: "TEST_PRIVATE_KEY".to_string(),
-                derivation_path: Some("0/0/0/0".to_string()),
-                ..Default::default()
-            },
-            Default::default(),
-            Some(Arc::new(MockBtcOnchain::default())),
-        ))
+        // proofs that are redeemed while paging are not returned
+        let first = mint.redeemed_proofs(None, 500).await?;
+        redeem("new", 100).await?;
+        let mut secrets = first
+            .proofs
+            .iter()
+            .map(|proof| proof.secret.clone())
+            .collect::<Vec<_>>();
+        let mut before = first.next;
+        while let Some(cursor) = before {
+            let page = mint.redeemed_proofs(Some(&cursor), 500).await?;
+            secrets.extend(page.proofs.iter().map(|proof| proof.secret.clone()));
+            before = page.next;
+        }
+        assert_eq!(expected, secrets);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_concurrent_same_invoice() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_is_invoice_paid().returning(|_| Ok(true));
+        let db = create_mock_db_empty(node.get_host_port_ipv4(5432)).await?;
+        let quote = Bolt11MintQuote {
+            quote_id: Uuid::new_v4(),
+            payment_request: "lnbcrt1u1pjgamjepp5cr2dzhcuy9tjwl7u45kxa9h02khvsd2a7f2x9yjxgst8trduld4sdqqcqzzsxqyz5vqsp5kaclwkq79ylef295qj7x6c9kvhaq6272ge4tgz7stlzv46csrzks9qyyssq9szxlvhh0uen2jmh07hp242nj5529wje3x5e434kepjzeqaq5hnsje8rzrl97s0j8cxxt3kgz5gfswrrchr45u8fq3twz2jjc029klqpd6jmgv".to_string(),
+            expiry: 0,
+            paid: false,
+        };
+        let key = quote.quote_id.to_string();
+        let mut tx = db.begin_tx().await?;
+        db.add_pending_invoice(
+            &mut tx,
+            key.clone(),
+            &Invoice::new(40, quote.payment_request.clone(), 0, NO_EXPIRY),
+        )
+        .await?;
+        db.add_bolt11_mint_quote(&mut tx, &quote).await?;
+        tx.commit().await?;
+        let mint = create_mint_from_mocks(db, Some(lightning)).await?;
+
+        let outputs = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_40.json")?;
+        let mint_once = || async {
+            let mut tx = mint.db.begin_tx().await?;
+            let result = mint
+                .mint_tokens(
+                    &mut tx,
+                    moksha_core::primitives::PaymentMethod::Bolt11,
+                    key.clone(),
+                    &outputs,
+                    &mint.keyset(),
+                )
+                .await?;
+            mint.db
+                .update_bolt11_mint_quote(
+                    &mut tx,
+                    &Bolt11MintQuote {
+                        paid: true,
+                        ..quote.clone()
+                    },
+                )
+                .await?;
+            tx.commit().await?;
+            Ok::<_, MokshaMintError>(result)
+        };
+
+        let (first, second) = tokio::join!(mint_once(), mint_once());
+        let results = [first, second];
+        assert_eq!(1, results.iter().filter(|r| r.is_ok()).count());
+        assert!(results
+            .iter()
+            .any(|r| matches!(r, Err(MokshaMintError::InvoiceAlreadyMinted(k)) if *k == key)));
+
+        let result = mint_once().await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::InvoiceAlreadyMinted(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_unknown_invoice() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .mint_tokens(
+                &mut tx,
+                moksha_core::primitives::PaymentMethod::Bolt11,
+                "unknownhash".to_string(),
+                &[],
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::InvoiceNotFound(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_zero() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let blinded_messages = vec![];
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+
+        let proofs = Proofs::empty();
+        let result = mint
+            .swap(&proofs, &blinded_messages, &mint.keyset())
+            .await?;
+
+        assert!(result.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_64_in_20() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let inputs = sign_proofs(&mint.keyset(), &request.inputs)?;
+
+        let result = mint.swap(&inputs, &request.outputs, &mint.keyset()).await?;
+        assert_eq!(result.total_amount(), 64);
+
+        let prv_last = result.get(result.len() - 2).expect("element not found");
+        let last = result.last().expect("element not found");
+
+        assert_eq!(prv_last.amount, 4);
+        assert_eq!(last.amount, 16);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_used_proofs() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let inputs = sign_proofs(&mint.keyset(), &request.inputs)?;
+        mint.swap(&inputs, &request.outputs, &mint.keyset()).await?;
+
+        let result = mint.swap(&inputs, &request.outputs, &mint.keyset()).await;
+        assert!(matches!(result, Err(MokshaMintError::ProofAlreadyUsed(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_state() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let inputs = sign_proofs(&mint.keyset(), &request.inputs)?;
+        let unspent = create_token_from_fixture("token_60.cashu")?.proofs();
+        mint.swap(&inputs, &request.outputs, &mint.keyset()).await?;
+
+        let ys = inputs
+            .proofs()
+            .iter()
+            .chain(unspent.proofs().iter())
+            .map(|p| p.y())
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = mint.check_state(&ys).await?;
+
+        assert_eq!(ys.len(), result.len());
+        let (spent, unspent_states) = result.split_at(inputs.len());
+        assert!(spent.iter().all(|s| s.state == State::Spent));
+        assert!(unspent_states.iter().all(|s| s.state == State::Unspent));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_check_state_of_proofs_used_before_y() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let proofs = create_token_from_fixture("token_60.cashu")?.proofs();
+
+        // rows as they were written before the Y was stored
+        let mut tx = mint.db.begin_tx().await?;
+        for proof in proofs.proofs() {
+            sqlx::query(
+                "INSERT INTO used_proofs (amount, secret, c, keyset_id) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(proof.amount as i64)
+            .bind(&proof.secret)
+            .bind(proof.c.to_string())
+            .bind(proof.keyset_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        let ys = proofs
+            .proofs()
+            .iter()
+            .map(|p| p.y())
+            .collect::<Result<Vec<_>, _>>()?;
+        let result = mint.check_state(&ys).await?;
+        assert!(result.iter().all(|s| s.state == State::Unspent));
+
+        mint.db.migrate().await;
+        let result = mint.check_state(&ys).await?;
+        assert_eq!(ys.len(), result.len());
+        assert!(result.iter().all(|s| s.state == State::Spent));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let inputs = sign_proofs(&mint.keyset(), &request.inputs)?;
+        let signatures = mint.swap(&inputs, &request.outputs, &mint.keyset()).await?;
+
+        let unknown = BlindedMessage {
+            amount: 8,
+            b_: dhke::public_key_from_hex(
+                "02634a2c2b34bec9e8a4aba4361f6bf202d7fa2365379b0840afe249a7a9d71239",
+            ),
+            id: mint.keyset().keyset_id.clone(),
+        };
+        let mut outputs = vec![unknown];
+        outputs.extend(request.outputs.iter().rev().cloned());
+
+        let (restored_outputs, restored_signatures) = mint.restore(&outputs).await?;
+        assert_eq!(request.outputs.len(), restored_outputs.len());
+        assert_eq!(
+            request
+                .outputs
+                .iter()
+                .rev()
+                .map(|o| o.b_)
+                .collect::<Vec<_>>(),
+            restored_outputs.iter().map(|o| o.b_).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            signatures.iter().rev().map(|s| s.c_).collect::<Vec<_>>(),
+            restored_signatures.iter().map(|s| s.c_).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            signatures.iter().rev().map(|s| s.dleq).collect::<Vec<_>>(),
+            restored_signatures
+                .iter()
+                .map(|s| s.dleq)
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_expired_invoice() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_create_invoice().returning(|_| {
+            Ok(CreateInvoiceResult {
+                payment_hash: vec![],
+                payment_request: "invoice".to_string(),
+            })
+        });
+        lightning.expect_is_invoice_paid().never();
+        let mut mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+        mint.config.pending_invoice = PendingInvoiceConfig {
+            expiry: 60,
+            prune_interval: 600,
+        };
+        let now = Arc::new(AtomicU64::new(1_000));
+        let mut clock = MockClock::new();
+        let clock_now = now.clone();
+        clock
+            .expect_now()
+            .returning(move || clock_now.load(Ordering::SeqCst));
+        mint.clock = Arc::new(clock);
+
+        mint.create_invoice("key".to_string(), 40).await?;
+        let mut tx = mint.db.begin_tx().await?;
+        assert_eq!(
+            Invoice::new(40, "invoice".to_string(), 1_000, 1_060),
+            mint.db
+                .get_pending_invoice(&mut tx, "key".to_string())
+                .await?
+        );
+        tx.commit().await?;
+
+        // the payment status isn't checked for an expired invoice
+        now.store(1_060, Ordering::SeqCst);
+        let outputs = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_40.json")?;
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .mint_tokens(
+                &mut tx,
+                moksha_core::primitives::PaymentMethod::Bolt11,
+                "key".to_string(),
+                &outputs,
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::InvoiceExpired(ref key)) if key == "key"));
+        assert_eq!(
+            Some(moksha_core::primitives::CashuErrorCode::QuoteExpired),
+            result.err().and_then(|e| e.code())
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired_invoices() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let now = Arc::new(AtomicU64::new(1_000));
+        let mut clock = MockClock::new();
+        let clock_now = now.clone();
+        clock
+            .expect_now()
+            .returning(move || clock_now.load(Ordering::SeqCst));
+        mint.clock = Arc::new(clock);
+
+        let mut tx = mint.db.begin_tx().await?;
+        for (key, expires_at) in [("first", 1_000), ("second", 1_500), ("third", 2_000)] {
+            mint.db
+                .add_pending_invoice(
+                    &mut tx,
+                    key.to_string(),
+                    &Invoice::new(8, format!("invoice {key}"), 0, expires_at),
+                )
+                .await?;
+        }
+        let expired = mint.db.get_expired_invoices(&mut tx, 1_500).await?;
+        assert_eq!(
+            vec!["first", "second"],
+            expired
+                .iter()
+                .map(|(key, _)| key.as_str())
+                .collect::<Vec<_>>()
+        );
+        tx.commit().await?;
+
+        // an invoice expires at its expiry
+        assert_eq!(1, mint.prune_expired_invoices().await?);
+        assert_eq!(0, mint.prune_expired_invoices().await?);
+        let mut tx = mint.db.begin_tx().await?;
+        assert!(matches!(
+            mint.db
+                .get_pending_invoice(&mut tx, "first".to_string())
+                .await,
+            Err(MokshaMintError::Db(sqlx::Error::RowNotFound))
+        ));
+        assert!(mint
+            .db
+            .get_pending_invoice(&mut tx, "second".to_string())
+            .await
+            .is_ok());
+        tx.commit().await?;
+
+        // the background task prunes right after it was spawned
+        now.store(1_999, Ordering::SeqCst);
+        let task = mint.spawn_invoice_pruning();
+        let pruned = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+            loop {
+                let mut tx = mint.db.begin_tx().await?;
+                let remaining = mint.db.get_expired_invoices(&mut tx, NO_EXPIRY).await?;
+                tx.commit().await?;
+                if remaining.len() == 1 {
+                    return anyhow::Ok(remaining);
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            }
+        })
+        .await??;
+        task.abort();
+        assert_eq!("third", pruned[0].0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rotate_keyset() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let keyset_a = mint.keyset();
+        let inputs = sign_proofs(&keyset_a, &request.inputs)?;
+
+        let keyset_b = mint.rotate_keyset("0/0/0/1", 0).await?;
+        assert_ne!(keyset_a.keyset_id, keyset_b.keyset_id);
+        assert_eq!(keyset_b.keyset_id, mint.keyset().keyset_id);
+        let keysets = mint.keysets().keysets;
+        assert_eq!(2, keysets.len());
+        assert!(keysets
+            .iter()
+            .all(|k| k.active == (k.id == keyset_b.keyset_id)));
+
+        // proofs of the old keyset are still accepted, the outputs are signed with the new one
+        let result = mint.swap(&inputs, &request.outputs, &mint.keyset()).await?;
+        assert_eq!(64, result.total_amount());
+        assert!(result.iter().all(|s| s.id == keyset_b.keyset_id));
+
+        // the keysets survive a restart
+        let restarted = create_mint_from_mocks(mint.db.clone(), None).await?;
+        restarted.load_keysets().await?;
+        assert_eq!(mint.keysets(), restarted.keysets());
+        assert!(restarted.get_keyset(&keyset_a.keyset_id).is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_keyset_sunset() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mut mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+
+        let now = Arc::new(AtomicU64::new(1_000));
+        let mut clock = MockClock::new();
+        let clock_now = now.clone();
+        clock
+            .expect_now()
+            .returning(move || clock_now.load(Ordering::SeqCst));
+        mint.clock = Arc::new(clock);
+
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let keyset_a = mint.keyset();
+        let inputs = sign_proofs(&keyset_a, &request.inputs)?;
+        let other_proofs: Proofs = request
+            .inputs
+            .proofs()
+            .into_iter()
+            .map(|proof| Proof {
+                secret: format!("{}-other", proof.secret),
+                ..proof
+            })
+            .collect::<Vec<Proof>>()
+            .into();
+        let other_inputs = sign_proofs(&keyset_a, &other_proofs)?;
+        let keyset_b = mint.rotate_keyset("0/0/0/1", 0).await?;
+
+        // the old keyset doesn't sign anymore
+        let old_outputs = request
+            .outputs
+            .iter()
+            .map(|output| BlindedMessage {
+                id: keyset_a.keyset_id.clone(),
+                ..output.clone()
+            })
+            .collect::<Vec<_>>();
+        let result = mint.swap(&inputs, &old_outputs, &mint.keyset()).await;
+        assert!(
+            matches!(result, Err(MokshaMintError::KeysetInactive(id)) if id == keyset_a.keyset_id)
+        );
+
+        let result = mint.sunset_keyset(&keyset_b.keyset_id, Some(2_000)).await;
+        assert!(matches!(result, Err(MokshaMintError::KeysetActive(_))));
+        let result = mint.sunset_keyset("unknown", Some(2_000)).await;
+        assert!(matches!(result, Err(MokshaMintError::UnknownKeyset(_))));
+
+        mint.sunset_keyset(&keyset_a.keyset_id, Some(2_000)).await?;
+        let keysets = mint.keysets().keysets;
+        assert!(keysets
+            .iter()
+            .all(|k| k.final_expiry == (k.id == keyset_a.keyset_id).then_some(2_000)));
+
+        // proofs of the old keyset are accepted during the grace period
+        now.store(1_999, Ordering::SeqCst);
+        let result = mint.swap(&inputs, &request.outputs, &mint.keyset()).await?;
+        assert_eq!(64, result.total_amount());
+
+        now.store(2_000, Ordering::SeqCst);
+        let result = mint
+            .swap(&other_inputs, &request.outputs, &mint.keyset())
+            .await;
+        assert!(
+            matches!(result, Err(MokshaMintError::KeysetExpired(id)) if id == keyset_a.keyset_id)
+        );
+
+        let liabilities = mint.keyset_liabilities().await?;
+        assert_eq!(2, liabilities.len());
+        let liability_a = liabilities
+            .iter()
+            .find(|l| l.keyset_id == keyset_a.keyset_id)
+            .expect("liability of keyset a");
+        assert_eq!(64, liability_a.redeemed);
+        let liability_b = liabilities
+            .iter()
+            .find(|l| l.keyset_id == keyset_b.keyset_id)
+            .expect("liability of keyset b");
+        assert_eq!(64, liability_b.issued);
+
+        // the sunset survives a restart
+        let restarted = create_mint_from_mocks(mint.db.clone(), None).await?;
+        restarted.load_keysets().await?;
+        assert_eq!(mint.keysets(), restarted.keysets());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_keysets_stores_config_keyset() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        mint.load_keysets().await?;
+
+        let mut tx = mint.db.begin_tx().await?;
+        let stored = mint.db.get_keysets(&mut tx).await?;
+        assert_eq!(1, stored.len());
+        assert_eq!(mint.keyset().keyset_id, stored[0].keyset_id);
+        assert_eq!("0/0/0/0", stored[0].derivation_path);
+        assert!(stored[0].active);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_timelocked() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mut mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+
+        let now = Arc::new(AtomicU64::new(999));
+        let mut clock = MockClock::new();
+        let clock_now = now.clone();
+        clock
+            .expect_now()
+            .returning(move || clock_now.load(Ordering::SeqCst));
+        mint.clock = Arc::new(clock);
+
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let locked_proofs: Proofs = request
+            .inputs
+            .proofs()
+            .into_iter()
+            .map(|proof| Proof {
+                secret: WellKnownSecret::p2pk_with_locktime(&proof.c, 1_000).to_string(),
+                ..proof
+            })
+            .collect::<Vec<Proof>>()
+            .into();
+        let locked_proofs = sign_proofs(&mint.keyset(), &locked_proofs)?;
+
+        let result = mint
+            .swap(&locked_proofs, &request.outputs, &mint.keyset())
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::ProofLocked(1_000))));
+
+        now.store(1_000, Ordering::SeqCst);
+        let result = mint
+            .swap(&locked_proofs, &request.outputs, &mint.keyset())
+            .await?;
+        assert_eq!(64, result.total_amount());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_duplicate_key() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_duplicate_key.json")?;
+
+        let result = mint
+            .swap(&request.inputs, &request.outputs, &mint.keyset())
+            .await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// melt 20 sats with 60 tokens and receive 40 tokens as change
+    async fn test_melt_overpay() -> anyhow::Result<()> {
+        use lightning_invoice::Bolt11Invoice as LNInvoice;
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(
+                // 20 sat
+                LNInvoice::from_str("lnbc200n1pj9eanxsp5agdl4rd0twdljpcgmg67dwj9mseu5m4lwfhslkws4uh4m5f5pcrqpp5lvspx676rykr64l02s97wjztcxe355qck0naydrsvvkqw42cc35sdq2f38xy6t5wvxqzjccqpjrzjq027t9tsc6jn5ve2k6gnn689unn8h239juuf9s3ce09aty6ed73t5z7nqsqqsygqqyqqqqqqqqqqqqgq9q9qyysgqs5msn4j9v53fq000zhw0gulkcx2dlnfdt953v2ur7z765jj3m0fx6cppkpjwntq5nsqm273u4eevva508pvepg8mh27sqcd29sfjr4cq255a40").expect("invalid invoice")
+            )
+        });
+        lightning.expect_pay_invoice().returning(|_| {
+            Ok(PayInvoiceResult {
+                payment_hash: "hash".to_string(),
+                total_fees: 2,
+            })
+            .map_err(|_err: LightningError| MokshaMintError::InvoiceNotFound("".to_string()))
+        });
+
+        let db = create_mock_db_empty(node.get_host_port_ipv4(5432)).await?;
+
+        let mint = Mint::new(
+            // "TEST_PRIVATE_KEY".to_string(),
+            // "0/0/0/0".to_string(),
+            Arc::new(lightning),
+            LightningType::Lnbits(Default::default()),
+            db,
+            Default::default(),
+            Default::default(),
+            Some(Arc::new(MockBtcOnchain::default())),
+        );
+
+        let tokens = create_token_from_fixture("token_60.cashu").expect("can not read fixture");
+        let proofs = sign_proofs(&mint.keyset(), &tokens.proofs())?;
+        let invoice = "some invoice".to_string();
+        let change = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_blank_4000.json")?;
+
+        let mut tx = mint.db.begin_tx().await?;
+        let MeltResult { paid, change, .. } = mint
+            .melt_bolt11(&mut tx, invoice, 20, 4, &proofs, &change, &mint.keyset())
+            .await?;
+
+        assert!(paid);
+        // 60 (proofs) - 20 (invoice) - 2 (fees)
+        assert_eq!(38, change.total_amount());
+        Ok(())
+    }
+
+    #[tokio::test]
+    /// overpay by 7 sats and receive change for 4 + 2 + 1, or only for the largest amounts if
+    /// there are not enough outputs
+    async fn test_melt_change_outputs() -> anyhow::Result<()> {
+        use lightning_invoice::Bolt11Invoice as LNInvoice;
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(
+                // 20 sat
+                LNInvoice::from_str("lnbc200n1pj9eanxsp5agdl4rd0twdljpcgmg67dwj9mseu5m4lwfhslkws4uh4m5f5pcrqpp5lvspx676rykr64l02s97wjztcxe355qck0naydrsvvkqw42cc35sdq2f38xy6t5wvxqzjccqpjrzjq027t9tsc6jn5ve2k6gnn689unn8h239juuf9s3ce09aty6ed73t5z7nqsqqsygqqyqqqqqqqqqqqqgq9q9qyysgqs5msn4j9v53fq000zhw0gulkcx2dlnfdt953v2ur7z765jj3m0fx6cppkpjwntq5nsqm273u4eevva508pvepg8mh27sqcd29sfjr4cq255a40").expect("invalid invoice")
+            )
+        });
+        lightning.expect_pay_invoice().returning(|_| {
+            Ok(PayInvoiceResult {
+                payment_hash: "hash".to_string(),
+                total_fees: 1,
+            })
+        });
+
+        let db = create_mock_db_empty(node.get_host_port_ipv4(5432)).await?;
+        let mint = Mint::new(
+            Arc::new(lightning),
+            LightningType::Lnbits(Default::default()),
+            db,
+            Default::default(),
+            Default::default(),
+            Some(Arc::new(MockBtcOnchain::default())),
+        );
+
+        // 4 + 8 + 16 and 32
+        let tokens = create_token_from_fixture("token_60.cashu").expect("can not read fixture");
+        let (small, large): (Vec<_>, Vec<_>) = sign_proofs(&mint.keyset(), &tokens.proofs())?
+            .proofs()
+            .into_iter()
+            .partition(|p| p.amount < 32);
+        let outputs = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_blank_4000.json")?;
+
+        // 28 (proofs) - 20 (invoice) - 1 (fees)
+        let mut tx = mint.db.begin_tx().await?;
+        let MeltResult { paid, change, .. } = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &small.into(),
+                &outputs[0..3],
+                &mint.keyset(),
+            )
+            .await?;
+        tx.commit().await?;
+        assert!(paid);
+        assert_eq!(
+            vec![4, 2, 1],
+            change.iter().map(|s| s.amount).collect::<Vec<_>>()
+        );
+
+        // 32 (proofs) - 20 (invoice) - 1 (fees) = 8 + 2 + 1, but there are only two outputs
+        let mut tx = mint.db.begin_tx().await?;
+        let MeltResult { change, .. } = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &large.into(),
+                &outputs[3..5],
+                &mint.keyset(),
+            )
+            .await?;
+        tx.commit().await?;
+        assert_eq!(
+            vec![8, 2],
+            change.iter().map(|s| s.amount).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_melts_with_same_proofs() -> anyhow::Result<()> {
+        use lightning_invoice::Bolt11Invoice as LNInvoice;
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let payments = Arc::new(AtomicU64::new(0));
+        let counter = payments.clone();
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(
+                // 20 sat
+                LNInvoice::from_str("lnbc200n1pj9eanxsp5agdl4rd0twdljpcgmg67dwj9mseu5m4lwfhslkws4uh4m5f5pcrqpp5lvspx676rykr64l02s97wjztcxe355qck0naydrsvvkqw42cc35sdq2f38xy6t5wvxqzjccqpjrzjq027t9tsc6jn5ve2k6gnn689unn8h239juuf9s3ce09aty6ed73t5z7nqsqqsygqqyqqqqqqqqqqqqgq9q9qyysgqs5msn4j9v53fq000zhw0gulkcx2dlnfdt953v2ur7z765jj3m0fx6cppkpjwntq5nsqm273u4eevva508pvepg8mh27sqcd29sfjr4cq255a40").expect("invalid invoice")
+            )
+        });
+        lightning.expect_pay_invoice().returning(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Ok(PayInvoiceResult {
+                payment_hash: "hash".to_string(),
+                total_fees: 2,
+            })
+        });
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+
+        let tokens = create_token_from_fixture("token_60.cashu")?;
+        let proofs = sign_proofs(&mint.keyset(), &tokens.proofs())?;
+        let melt = || async {
+            let mut tx = mint.db.begin_tx().await?;
+            let result = mint
+                .melt_bolt11(
+                    &mut tx,
+                    "some invoice".to_string(),
+                    20,
+                    4,
+                    &proofs,
+                    &[],
+                    &mint.keyset(),
+                )
+                .await;
+            tx.commit().await?;
+            anyhow::Ok(result)
+        };
+
+        let (first, second) = tokio::join!(melt(), melt());
+        let results = [first?, second?];
+        assert_eq!(1, results.iter().filter(|r| r.is_ok()).count());
+        assert!(results
+            .iter()
+            .any(|r| matches!(r, Err(MokshaMintError::ProofAlreadyUsed(_)))));
+        assert_eq!(1, payments.load(Ordering::SeqCst));
+
+        let mut tx = mint.db.begin_tx().await?;
+        assert_eq!(4, mint.db.get_used_proofs(&mut tx).await?.len());
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_melt_fee_reserve() -> anyhow::Result<()> {
+        use lightning_invoice::Bolt11Invoice as LNInvoice;
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(
+                // 100 sat
+                LNInvoice::from_str("lnbcrt1u1pn3vymqdqqpp5wkrhhdqa8ya4lwz9tnnqanvdmgqp6p33vjttzn0607y4v4hwef9qsp5pqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyq9qrsgqcqzysxq8zals8sqwakuneqh6g8dagfjmplahvunea7nzm4vclacey9307cllrcfwzrs5smnzaec58mrrwscev68mzglvrqzvj3cwsxau6gtanye9pgn4nqqyg0je8").expect("invalid invoice")
+            )
+        });
+        lightning.expect_pay_invoice().returning(|_| {
+            Ok(PayInvoiceResult {
+                payment_hash: "hash".to_string(),
+                total_fees: 1,
+            })
+        });
+        let mut mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+        mint.config.lightning_fee = LightningFeeConfig::new(2.0, 0);
+        let fee_reserve = mint.fee_reserve_sat(100);
+        assert_eq!(2, fee_reserve);
+
+        let c = dhke::public_key_from_hex(
+            "02634a2c2b34bec9e8a4aba4361f6bf202d7fa2365379b0840afe249a7a9d71239",
+        );
+        let proofs_of = |amounts: &[u64]| {
+            let proofs: Proofs = amounts
+                .iter()
+                .map(|amount| Proof::new(*amount, format!("secret {amount}"), c, String::new()))
+                .collect::<Vec<_>>()
+                .into();
+            sign_proofs(&mint.keyset(), &proofs)
+        };
+        let change = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_blank_4000.json")?;
+        let invoice = "invoice".to_string();
+
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .melt_bolt11(
+                &mut tx,
+                invoice.clone(),
+                100,
+                fee_reserve,
+                &proofs_of(&[64, 32, 4])?,
+                &change,
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::NotEnoughTokens(102))));
+
+        let result = mint
+            .melt_bolt11(
+                &mut tx,
+                invoice,
+                100,
+                fee_reserve,
+                &proofs_of(&[64, 32, 4, 2])?,
+                &change,
+                &mint.keyset(),
+            )
+            .await?;
+        assert!(result.paid);
+        assert_eq!(1, result.fee_paid);
+        // 102 (proofs) - 100 (invoice) - 1 (routing fee)
+        assert_eq!(1, result.change.total_amount());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_melt_failed_payment_keeps_proofs_spendable() -> anyhow::Result<()> {
+        use lightning_invoice::Bolt11Invoice as LNInvoice;
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(
+                // 20 sat
+                LNInvoice::from_str("lnbc200n1pj9eanxsp5agdl4rd0twdljpcgmg67dwj9mseu5m4lwfhslkws4uh4m5f5pcrqpp5lvspx676rykr64l02s97wjztcxe355qck0naydrsvvkqw42cc35sdq2f38xy6t5wvxqzjccqpjrzjq027t9tsc6jn5ve2k6gnn689unn8h239juuf9s3ce09aty6ed73t5z7nqsqqsygqqyqqqqqqqqqqqqgq9q9qyysgqs5msn4j9v53fq000zhw0gulkcx2dlnfdt953v2ur7z765jj3m0fx6cppkpjwntq5nsqm273u4eevva508pvepg8mh27sqcd29sfjr4cq255a40").expect("invalid invoice")
+            )
+        });
+        lightning.expect_pay_invoice().returning(|invoice| {
+            Err(MokshaMintError::PayInvoice(
+                invoice,
+                LightningError::PaymentFailed,
+            ))
+        });
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+
+        let tokens = create_token_from_fixture("token_60.cashu")?;
+        let proofs = sign_proofs(&mint.keyset(), &tokens.proofs())?;
+        let change = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_blank_4000.json")?;
+
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &proofs,
+                &change,
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::PayInvoice(..))));
+        // even if the caller commits, nothing was written for the failed payment
+        tx.commit().await?;
+
+        let mut tx = mint.db.begin_tx().await?;
+        assert!(mint.db.get_used_proofs(&mut tx).await?.is_empty());
+        mint.check_used_proofs(&mut tx, &proofs).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_swap_keeps_proofs_spendable() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let inputs = sign_proofs(&mint.keyset(), &request.inputs)?;
+
+        // the outputs are signed before the amounts are compared
+        let result = mint
+            .swap(&inputs, &request.outputs[1..], &mint.keyset())
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::SwapAmountMismatch(_))
+        ));
+
+        let mut tx = mint.db.begin_tx().await?;
+        assert!(mint.db.get_used_proofs(&mut tx).await?.is_empty());
+        let b_ = request.outputs.iter().map(|o| o.b_).collect::<Vec<_>>();
+        assert!(mint.db.get_blind_signatures(&mut tx, &b_).await?.is_empty());
+        tx.commit().await?;
+
+        mint.swap(&inputs, &request.outputs, &mint.keyset()).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_rejects_invalid_outputs() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let inputs = sign_proofs(&mint.keyset(), &request.inputs)?;
+
+        // outputs of 100 sats for 64 sats of proofs
+        let mut too_high = request.outputs.clone();
+        too_high[0].amount = 8;
+        too_high[2].amount = 64;
+        let result = mint.swap(&inputs, &too_high, &mint.keyset()).await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::SwapAmountTooHigh {
+                inputs: 64,
+                outputs: 100
+            })
+        ));
+
+        // the sum of the outputs would overflow
+        let mut overflow = request.outputs[..2].to_vec();
+        overflow[0].amount = 1 << 63;
+        overflow[1].amount = 1 << 63;
+        let result = mint.swap(&inputs, &overflow, &mint.keyset()).await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::SwapAmountTooHigh {
+                inputs: 64,
+                outputs: u64::MAX
+            })
+        ));
+
+        let mut duplicates = request.outputs.clone();
+        duplicates[1].b_ = duplicates[0].b_;
+        duplicates[1].amount = 4;
+        duplicates[3].amount = 8;
+        let result = mint.swap(&inputs, &duplicates, &mint.keyset()).await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::SwapHasDuplicatePromises)
+        ));
+
+        let mut not_a_denomination = request.outputs.clone();
+        not_a_denomination[0].amount = 3;
+        not_a_denomination[3].amount = 5;
+        let result = mint
+            .swap(&inputs, &not_a_denomination, &mint.keyset())
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::InvalidOutputDecomposition(3))
+        ));
+
+        // a swap without outputs is only balanced if the fee takes the whole input
+        let result = mint.swap(&inputs, &[], &mint.keyset()).await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::SwapAmountMismatch(_))
+        ));
+
+        // nothing was signed or spent for the rejected swaps
+        let mut tx = mint.db.begin_tx().await?;
+        assert!(mint.db.get_used_proofs(&mut tx).await?.is_empty());
+        let b_ = request.outputs.iter().map(|o| o.b_).collect::<Vec<_>>();
+        assert!(mint.db.get_blind_signatures(&mut tx, &b_).await?.is_empty());
+        tx.commit().await?;
+
+        mint.swap(&inputs, &request.outputs, &mint.keyset()).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_melt_proofs_of_multiple_keysets() -> anyhow::Result<()> {
+        use lightning_invoice::Bolt11Invoice as LNInvoice;
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(
+                // 20 sat
+                LNInvoice::from_str("lnbc200n1pj9eanxsp5agdl4rd0twdljpcgmg67dwj9mseu5m4lwfhslkws4uh4m5f5pcrqpp5lvspx676rykr64l02s97wjztcxe355qck0naydrsvvkqw42cc35sdq2f38xy6t5wvxqzjccqpjrzjq027t9tsc6jn5ve2k6gnn689unn8h239juuf9s3ce09aty6ed73t5z7nqsqqsygqqyqqqqqqqqqqqqgq9q9qyysgqs5msn4j9v53fq000zhw0gulkcx2dlnfdt953v2ur7z765jj3m0fx6cppkpjwntq5nsqm273u4eevva508pvepg8mh27sqcd29sfjr4cq255a40").expect("invalid invoice")
+            )
+        });
+        lightning.expect_pay_invoice().returning(|_| {
+            Ok(PayInvoiceResult {
+                payment_hash: "hash".to_string(),
+                total_fees: 2,
+            })
+        });
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+
+        // proofs of the old and the new keyset are combined in one melt
+        let old_keyset = mint.keyset();
+        let new_keyset = mint.rotate_keyset("0/0/0/1", 0).await?;
+        let tokens = create_token_from_fixture("token_60.cashu")?
+            .proofs()
+            .proofs();
+        let (old, new) = tokens.split_at(2);
+        let proofs: Proofs = [
+            sign_proofs(&old_keyset, &old.to_vec().into())?.proofs(),
+            sign_proofs(&new_keyset, &new.to_vec().into())?.proofs(),
+        ]
+        .concat()
+        .into();
+        let change = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_blank_4000.json")?;
+
+        let mut tx = mint.db.begin_tx().await?;
+        let MeltResult { paid, change, .. } = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &proofs,
+                &change,
+                &mint.keyset(),
+            )
+            .await?;
+        tx.commit().await?;
+
+        assert!(paid);
+        assert_eq!(60 - 20 - 2, change.total_amount());
+        assert!(change.iter().all(|s| s.id == new_keyset.keyset_id));
+        let mut tx = mint.db.begin_tx().await?;
+        assert!(matches!(
+            mint.check_used_proofs(&mut tx, &proofs).await,
+            Err(MokshaMintError::ProofAlreadyUsed(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_input_fee_of_multiple_keysets() -> anyhow::Result<()> {
+        use lightning_invoice::Bolt11Invoice as LNInvoice;
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(
+                // 20 sat
+                LNInvoice::from_str("lnbc200n1pj9eanxsp5agdl4rd0twdljpcgmg67dwj9mseu5m4lwfhslkws4uh4m5f5pcrqpp5lvspx676rykr64l02s97wjztcxe355qck0naydrsvvkqw42cc35sdq2f38xy6t5wvxqzjccqpjrzjq027t9tsc6jn5ve2k6gnn689unn8h239juuf9s3ce09aty6ed73t5z7nqsqqsygqqyqqqqqqqqqqqqgq9q9qyysgqs5msn4j9v53fq000zhw0gulkcx2dlnfdt953v2ur7z765jj3m0fx6cppkpjwntq5nsqm273u4eevva508pvepg8mh27sqcd29sfjr4cq255a40").expect("invalid invoice")
+            )
+        });
+        lightning.expect_pay_invoice().returning(|_| {
+            Ok(PayInvoiceResult {
+                payment_hash: "hash".to_string(),
+                total_fees: 2,
+            })
+        });
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+
+        let cheap_keyset = mint.rotate_keyset("0/0/0/1", 300).await?;
+        let expensive_keyset = mint.rotate_keyset("0/0/0/2", 600).await?;
+        let fees = mint
+            .keysets()
+            .keysets
+            .into_iter()
+            .map(|k| (k.id, k.input_fee_ppk))
+            .collect::<HashMap<_, _>>();
+        assert_eq!(Some(&300), fees.get(&cheap_keyset.keyset_id));
+        assert_eq!(Some(&600), fees.get(&expensive_keyset.keyset_id));
+
+        let tokens = create_token_from_fixture("token_60.cashu")?
+            .proofs()
+            .proofs();
+        let (cheap, expensive) = tokens.split_at(2);
+        let proofs: Proofs = [
+            sign_proofs(&cheap_keyset, &cheap.to_vec().into())?.proofs(),
+            sign_proofs(&expensive_keyset, &expensive.to_vec().into())?.proofs(),
+        ]
+        .concat()
+        .into();
+        // 2 * 300 + 2 * 600 ppk are rounded up once for all inputs
+        assert_eq!(2, mint.input_fee(&proofs)?);
+
+        // outputs of the full input amount leave nothing for the fee of a swap
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let inputs = sign_proofs(&expensive_keyset, &request.inputs)?;
+        assert_eq!(1, mint.input_fee(&inputs)?);
+        let result = mint.swap(&inputs, &request.outputs, &mint.keyset()).await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::SwapAmountMismatch(_))
+        ));
+
+        let change = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_blank_4000.json")?;
+        let mut tx = mint.db.begin_tx().await?;
+        let MeltResult { paid, change, .. } = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &proofs,
+                &change,
+                &mint.keyset(),
+            )
+            .await?;
+        tx.commit().await?;
+
+        assert!(paid);
+        // 60 (proofs) - 20 (invoice) - 2 (lightning fees) - 2 (input fee)
+        assert_eq!(36, change.total_amount());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_forged_proof() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let forged: Proofs = request
+            .inputs
+            .proofs()
+            .into_iter()
+            .map(|proof| Proof {
+                keyset_id: mint.keyset().keyset_id.clone(),
+                ..proof
+            })
+            .collect::<Vec<Proof>>()
+            .into();
+
+        let result = mint.swap(&forged, &request.outputs, &mint.keyset()).await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::InvalidProof(ref secret)) if secret == &forged.proofs()[0].secret
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_proof_from_other_keyset() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            None,
+        )
+        .await?;
+        let request = read_fixture_as::<PostSwapRequest>("post_swap_request_64_20.json")?;
+        let other_keyset = MintKeyset::new("OTHER_PRIVATE_KEY", "0/0/0/0");
+        let foreign = sign_proofs(&other_keyset, &request.inputs)?;
+
+        let result = mint.swap(&foreign, &request.outputs, &mint.keyset()).await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::UnknownKeyset(ref id)) if *id == other_keyset.keyset_id
+        ));
+
+        // signed with the key of another keyset, but claims to be from the mint's keyset
+        let forged: Proofs = foreign
+            .proofs()
+            .into_iter()
+            .map(|proof| Proof {
+                keyset_id: mint.keyset().keyset_id.clone(),
+                ..proof
+            })
+            .collect::<Vec<Proof>>()
+            .into();
+        let result = mint.swap(&forged, &request.outputs, &mint.keyset()).await;
+        assert!(matches!(result, Err(MokshaMintError::InvalidProof(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_melt_unknown_keyset() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().never();
+        lightning.expect_pay_invoice().never();
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+
+        // proofs of a retired keyset of the same mint mixed with valid proofs
+        let retired_keyset = MintKeyset::new("TEST_PRIVATE_KEY", "0/0/0/1");
+        let tokens = create_token_from_fixture("token_60.cashu")?
+            .proofs()
+            .proofs();
+        let (valid, retired) = tokens.split_at(2);
+        let proofs: Proofs = [
+            sign_proofs(&mint.keyset(), &valid.to_vec().into())?.proofs(),
+            sign_proofs(&retired_keyset, &retired.to_vec().into())?.proofs(),
+        ]
+        .concat()
+        .into();
+
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &proofs,
+                &[],
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::UnknownKeyset(ref id)) if *id == retired_keyset.keyset_id
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_melt_change_of_inactive_keyset() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().never();
+        lightning.expect_pay_invoice().never();
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+        let inactive = mint.keyset();
+        mint.rotate_keyset("0/0/0/1", 0).await?;
+
+        let proofs = sign_proofs(
+            &mint.keyset(),
+            &create_token_from_fixture("token_60.cashu")?.proofs(),
+        )?;
+        let outputs = read_fixture_as::<Vec<BlindedMessage>>("blinded_messages_blank_4000.json")?
+            .into_iter()
+            .take(3)
+            .map(|output| BlindedMessage {
+                id: inactive.keyset_id.clone(),
+                ..output
+            })
+            .collect::<Vec<_>>();
+
+        // rejected before the invoice is paid, the proofs stay spendable
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &proofs,
+                &outputs,
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaMintError::KeysetInactive(ref id)) if *id == inactive.keyset_id
+        ));
+        tx.commit().await?;
+        let mut tx = mint.db.begin_tx().await?;
+        mint.check_used_proofs(&mut tx, &proofs).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_melt_forged_proof() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mut lightning = MockLightning::new();
+        lightning.expect_decode_invoice().returning(|_| {
+            Ok(lightning_invoice::Bolt11Invoice::from_str("lnbc200n1pj9eanxsp5agdl4rd0twdljpcgmg67dwj9mseu5m4lwfhslkws4uh4m5f5pcrqpp5lvspx676rykr64l02s97wjztcxe355qck0naydrsvvkqw42cc35sdq2f38xy6t5wvxqzjccqpjrzjq027t9tsc6jn5ve2k6gnn689unn8h239juuf9s3ce09aty6ed73t5z7nqsqqsygqqyqqqqqqqqqqqqgq9q9qyysgqs5msn4j9v53fq000zhw0gulkcx2dlnfdt953v2ur7z765jj3m0fx6cppkpjwntq5nsqm273u4eevva508pvepg8mh27sqcd29sfjr4cq255a40").expect("invalid invoice"))
+        });
+        lightning.expect_pay_invoice().never();
+        let mint = create_mint_from_mocks(
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            Some(lightning),
+        )
+        .await?;
+
+        let tokens = create_token_from_fixture("token_60.cashu")?;
+        let forged: Proofs = tokens
+            .proofs()
+            .proofs()
+            .into_iter()
+            .map(|proof| Proof {
+                keyset_id: mint.keyset().keyset_id.clone(),
+                ..proof
+            })
+            .collect::<Vec<Proof>>()
+            .into();
+
+        let mut tx = mint.db.begin_tx().await?;
+        let result = mint
+            .melt_bolt11(
+                &mut tx,
+                "some invoice".to_string(),
+                20,
+                4,
+                &forged,
+                &[],
+                &mint.keyset(),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaMintError::InvalidProof(_))));
+        Ok(())
+    }
+
+    /// Replaces the signatures of the proofs with valid signatures of the given keyset
+    fn sign_proofs(keyset: &MintKeyset, proofs: &Proofs) -> anyhow::Result<Proofs> {
+        let dhke = Dhke::new();
+        Ok(proofs
+            .proofs()
+            .into_iter()
+            .map(|proof| {
+                let y = Dhke::hash_to_curve(proof.secret.as_bytes())?;
+                let private_key = keyset
+                    .private_keys
+                    .get(&proof.amount)
+                    .expect("no key for amount");
+                Ok(Proof {
+                    c: dhke.step2_bob(y, private_key)?,
+                    keyset_id: keyset.keyset_id.clone(),
+                    ..proof
+                })
+            })
+            .collect::<anyhow::Result<Vec<Proof>>>()?
+            .into())
+    }
+
+    fn create_token_from_fixture(fixture: &str) -> Result<TokenV3, anyhow::Error> {
+        let base_dir = std::env::var("CARGO_MANIFEST_DIR")?;
+        let raw_token = std::fs::read_to_string(format!("{base_dir}/src/fixtures/{fixture}"))?;
+        Ok(raw_token.trim().to_string().try_into()?)
+    }
+
+    async fn create_mint_from_mocks(
+        mock_db: PostgresDB,
+        mock_ln: Option<MockLightning>,
+    ) -> anyhow::Result<Mint> {
+        let lightning = match mock_ln {
+            Some(ln) => Arc::new(ln),
+            None => Arc::new(MockLightning::new()),
+        };
+
+        let mut mint = Mint::new(
+            lightning,
+            LightningType::Lnbits(Default::default()),
+            mock_db,
+            MintConfig {
+                privatekey: "TEST_PRIVATE_KEY".to_string(),
+                derivation_path: Some("0/0/0/0".to_string()),
+                ..Default::default()
+            },
+            Default::default(),
+            Some(Arc::new(MockBtcOnchain::default())),
+        );
+        // before the fixture invoices expire
+        let mut clock = MockClock::new();
+        clock.expect_now().returning(|| FIXTURE_INVOICE_TIME);
+        mint.clock = Arc::new(clock);
+        Ok(mint)
     }
 
     async fn create_mock_db_empty(port: u16) -> anyhow::Result<PostgresDB> {
@@ -728,13 +3359,15 @@ mod tests {
         Ok(db)
     }
 
-    async fn create_mock_db_pending_invoice(port: u16) -> anyhow::Result<PostgresDB> {
+    async fn create_mock_db_pending_invoice(port: u16, amount: u64) -> anyhow::Result<PostgresDB> {
         let db = create_mock_db_empty(port).await?;
 
         let mut tx = db.begin_tx().await?;
         let invoice = Invoice{
-            amount: 100,
+            amount,
             payment_request: "lnbcrt1u1pjgamjepp5cr2dzhcuy9tjwl7u45kxa9h02khvsd2a7f2x9yjxgst8trduld4sdqqcqzzsxqyz5vqsp5kaclwkq79ylef295qj7x6c9kvhaq6272ge4tgz7stlzv46csrzks9qyyssq9szxlvhh0uen2jmh07hp242nj5529wje3x5e434kepjzeqaq5hnsje8rzrl97s0j8cxxt3kgz5gfswrrchr45u8fq3twz2jjc029klqpd6jmgv".to_string(),
+            created_at: 0,
+            expires_at: NO_EXPIRY,
         };
         db.add_pending_invoice(&mut tx, "somehash".to_string(), &invoice)
             .await?;
@@ -1,18 +1,108 @@
+use moksha_core::{blind::BlindedSignature, page::PageCursor};
 use serde::{Deserialize, Serialize};
 
+/// A keyset that is derived from the private key of the mint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeysetInfo {
+    pub keyset_id: String,
+    pub derivation_path: String,
+    pub active: bool,
+    /// fee in parts per thousand per input proof (NUT-02)
+    pub input_fee_ppk: u64,
+    /// unix timestamp (seconds) after which proofs of the keyset are rejected
+    pub sunset_at: Option<u64>,
+}
+
+/// Amounts of a keyset the mint has signed and redeemed. Signatures are only counted since they are
+/// stored for restoring them (NUT-09).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeysetLiability {
+    pub keyset_id: String,
+    pub issued: u64,
+    pub redeemed: u64,
+}
+
+/// A keyset in the admin api with the amount of its proofs that can still be redeemed
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdminKeyset {
+    pub id: String,
+    pub active: bool,
+    /// unix timestamp (seconds) after which proofs of the keyset are rejected
+    pub final_expiry: Option<u64>,
+    pub issued: u64,
+    pub redeemed: u64,
+    pub outstanding: u64,
+}
+
+/// A proof the mint has redeemed, for the audit of the redeemed proofs in the admin api
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RedeemedProof {
+    pub id: u64,
+    pub amount: u64,
+    pub keyset_id: String,
+    pub secret: String,
+    /// unix timestamp (seconds) when the proof was redeemed
+    pub redeemed_at: u64,
+}
+
+/// Redeemed proofs, the newest first
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdminRedeemedProofs {
+    pub proofs: Vec<RedeemedProof>,
+    /// continues after the last proof, `None` on the last page
+    pub next: Option<PageCursor>,
+}
+
+/// Query of a paged list in the admin api
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AdminPageQuery {
+    /// number of records, see [`crate::routes::admin::ADMIN_PAGE_MAX_LIMIT`]
+    pub limit: Option<u64>,
+    /// continues after this cursor, starts at the newest record by default
+    pub before: Option<PageCursor>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PostKeysetSunsetRequest {
+    /// unix timestamp (seconds), `None` cancels the sunset
+    pub sunset_at: Option<u64>,
+}
+
+impl KeysetLiability {
+    /// The amount of the proofs that can still be redeemed
+    pub fn outstanding(&self) -> u64 {
+        self.issued.saturating_sub(self.redeemed)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Invoice {
     pub amount: u64,
     pub payment_request: String,
+    /// unix timestamp (seconds) when the invoice was created
+    pub created_at: u64,
+    /// unix timestamp (seconds) from which the invoice can't be minted anymore and is pruned
+    pub expires_at: u64,
 }
 
 impl Invoice {
-    pub const fn new(amount: u64, payment_request: String) -> Self {
+    pub const fn new(
+        amount: u64,
+        payment_request: String,
+        created_at: u64,
+        expires_at: u64,
+    ) -> Self {
         Self {
             amount,
             payment_request,
+            created_at,
+            expires_at,
         }
     }
+
+    pub const fn is_expired(&self, now: u64) -> bool {
+        now >= self.expires_at
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -28,6 +118,16 @@ pub struct PayInvoiceResult {
     pub total_fees: u64,
 }
 
+/// Outcome of [`crate::mint::Mint::melt_bolt11`]
+#[derive(Debug, Clone)]
+pub struct MeltResult {
+    pub paid: bool,
+    pub payment_hash: String,
+    /// routing fee in sats the lightning backend paid
+    pub fee_paid: u64,
+    pub change: Vec<BlindedSignature>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateInvoiceParams {
     pub amount: u64,
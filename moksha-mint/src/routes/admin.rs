@@ -0,0 +1,98 @@
+use axum::{
+    extract::{Path, Query, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use tracing::instrument;
+
+use crate::{
+    error::MokshaMintError,
+    mint::Mint,
+    model::{AdminKeyset, AdminPageQuery, AdminRedeemedProofs, PostKeysetSunsetRequest},
+};
+
+/// Number of records of a page in the admin api if the query doesn't set a limit
+pub const ADMIN_PAGE_DEFAULT_LIMIT: u64 = 100;
+
+/// Largest page in the admin api, larger limits are reduced to it
+pub const ADMIN_PAGE_MAX_LIMIT: u64 = 1_000;
+
+/// Rejects requests that don't have the admin token of the mint as bearer token
+pub async fn require_admin_token(State(mint): State<Mint>, req: Request, next: Next) -> Response {
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match (mint.config.server.admin_token.as_deref(), provided) {
+        (Some(expected), Some(provided)) if tokens_match(expected, provided) => next.run(req).await,
+        _ => StatusCode::UNAUTHORIZED.into_response(),
+    }
+}
+
+/// Compares the tokens in constant time
+fn tokens_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected
+            .bytes()
+            .zip(provided.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Returns all keysets with their outstanding liability, to decide on the sunset of a keyset
+#[instrument(name = "get_admin_keysets", skip(mint), err)]
+pub async fn get_admin_keysets(
+    State(mint): State<Mint>,
+) -> Result<Json<Vec<AdminKeyset>>, MokshaMintError> {
+    let liabilities = mint.keyset_liabilities().await?;
+    Ok(Json(
+        mint.keysets()
+            .keysets
+            .into_iter()
+            .map(|keyset| {
+                let liability = liabilities
+                    .iter()
+                    .find(|liability| liability.keyset_id == keyset.id)
+                    .cloned()
+                    .unwrap_or_default();
+                AdminKeyset {
+                    id: keyset.id,
+                    active: keyset.active,
+                    final_expiry: keyset.final_expiry,
+                    issued: liability.issued,
+                    redeemed: liability.redeemed,
+                    outstanding: liability.outstanding(),
+                }
+            })
+            .collect(),
+    ))
+}
+
+/// Schedules the sunset of an inactive keyset
+#[instrument(name = "post_admin_keyset_sunset", skip(mint), err)]
+pub async fn post_admin_keyset_sunset(
+    State(mint): State<Mint>,
+    Path(id): Path<String>,
+    Json(request): Json<PostKeysetSunsetRequest>,
+) -> Result<StatusCode, MokshaMintError> {
+    mint.sunset_keyset(&id, request.sunset_at).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Returns a page of the redeemed proofs, the newest first, to audit the redemptions of the mint
+#[instrument(name = "get_admin_redeemed_proofs", skip(mint), err)]
+pub async fn get_admin_redeemed_proofs(
+    State(mint): State<Mint>,
+    Query(query): Query<AdminPageQuery>,
+) -> Result<Json<AdminRedeemedProofs>, MokshaMintError> {
+    let limit = query
+        .limit
+        .unwrap_or(ADMIN_PAGE_DEFAULT_LIMIT)
+        .min(ADMIN_PAGE_MAX_LIMIT);
+    Ok(Json(
+        mint.redeemed_proofs(query.before.as_ref(), limit).await?,
+    ))
+}
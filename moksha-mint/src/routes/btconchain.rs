@@ -132,8 +132,7 @@ pub async fn post_mint_btconchain(
             PaymentMethod::BtcOnchain,
             request.quote.clone(),
             &request.outputs,
-            &mint.keyset,
-            false,
+            &mint.keyset(),
         )
         .await?;
 
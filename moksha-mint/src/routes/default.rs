@@ -6,10 +6,12 @@ use moksha_core::{
     keyset::Keysets,
     primitives::{
         Bolt11MeltQuote, Bolt11MintQuote, CurrencyUnit, KeyResponse, KeysResponse,
-        MintInfoResponse, Nuts, PaymentMethod, PostMeltBolt11Request, PostMeltBolt11Response,
-        PostMeltQuoteBolt11Request, PostMeltQuoteBolt11Response, PostMintBolt11Request,
-        PostMintBolt11Response, PostMintQuoteBolt11Request, PostMintQuoteBolt11Response,
-        PostSwapRequest, PostSwapResponse,
+        MintInfoResponse, Nut5, Nut7, Nut9, Nuts, PaymentMethod, PaymentMethodConfig,
+        PostCheckStateRequest, PostCheckStateResponse, PostMeltBolt11Request,
+        PostMeltBolt11Response, PostMeltQuoteBolt11Request, PostMeltQuoteBolt11Response,
+        PostMintBolt11Request, PostMintBolt11Response, PostMintQuoteBolt11Request,
+        PostMintQuoteBolt11Response, PostRestoreRequest, PostRestoreResponse, PostSwapRequest,
+        PostSwapResponse,
     },
 };
 use tracing::{debug, instrument};
@@ -28,7 +30,6 @@ use moksha_core::primitives::{
     PostRequestToMintBitcredit, PostRequestToMintBitcreditResponse,
 };
 use std::str::FromStr;
-use tonic::codegen::ok;
 
 #[utoipa::path(
         post,
@@ -44,11 +45,50 @@ pub async fn post_swap(
     Json(swap_request): Json<PostSwapRequest>,
 ) -> Result<Json<PostSwapResponse>, MokshaMintError> {
     let response = mint
-        .swap(&swap_request.inputs, &swap_request.outputs, &mint.keyset)
+        .swap(&swap_request.inputs, &swap_request.outputs, &mint.keyset())
         .await?;
+    let input_fee = mint.input_fee(&swap_request.inputs)?;
 
     Ok(Json(PostSwapResponse {
         signatures: response,
+        input_fee: Some(input_fee),
+    }))
+}
+
+#[utoipa::path(
+        post,
+        path = "/v1/checkstate",
+        request_body = PostCheckStateRequest,
+        responses(
+            (status = 200, description = "post checkstate", body = [PostCheckStateResponse])
+        ),
+    )]
+#[instrument(name = "post_checkstate", skip(mint), err)]
+pub async fn post_checkstate(
+    State(mint): State<Mint>,
+    Json(request): Json<PostCheckStateRequest>,
+) -> Result<Json<PostCheckStateResponse>, MokshaMintError> {
+    let states = mint.check_state(&request.ys).await?;
+    Ok(Json(PostCheckStateResponse { states }))
+}
+
+#[utoipa::path(
+        post,
+        path = "/v1/restore",
+        request_body = PostRestoreRequest,
+        responses(
+            (status = 200, description = "post restore", body = [PostRestoreResponse])
+        ),
+    )]
+#[instrument(name = "post_restore", skip(mint), err)]
+pub async fn post_restore(
+    State(mint): State<Mint>,
+    Json(request): Json<PostRestoreRequest>,
+) -> Result<Json<PostRestoreResponse>, MokshaMintError> {
+    let (outputs, signatures) = mint.restore(&request.outputs).await?;
+    Ok(Json(PostRestoreResponse {
+        outputs,
+        signatures,
     }))
 }
 
@@ -61,11 +101,12 @@ pub async fn post_swap(
     )]
 #[instrument(skip(mint), err)]
 pub async fn get_keys(State(mint): State<Mint>) -> Result<Json<KeysResponse>, MokshaMintError> {
+    let keyset = mint.keyset();
     Ok(Json(KeysResponse {
         keysets: vec![KeyResponse {
-            id: mint.keyset.keyset_id.clone(),
+            id: keyset.keyset_id,
             unit: CurrencyUnit::Sat,
-            keys: mint.keyset.public_keys,
+            keys: keyset.public_keys,
         }],
     }))
 }
@@ -85,15 +126,15 @@ pub async fn get_keys_by_id(
     Path(id): Path<String>,
     State(mint): State<Mint>,
 ) -> Result<Json<KeysResponse>, MokshaMintError> {
-    if id != mint.keyset.keyset_id {
-        return Err(MokshaMintError::KeysetNotFound(id));
-    }
+    let keyset = mint
+        .get_keyset(&id)
+        .ok_or(MokshaMintError::KeysetNotFound(id))?;
 
     Ok(Json(KeysResponse {
         keysets: vec![KeyResponse {
-            id: mint.keyset.keyset_id.clone(),
+            id: keyset.keyset_id,
             unit: CurrencyUnit::Sat,
-            keys: mint.keyset.public_keys,
+            keys: keyset.public_keys,
         }],
     }))
 }
@@ -107,11 +148,7 @@ pub async fn get_keys_by_id(
     )]
 #[instrument(skip(mint), err)]
 pub async fn get_keysets(State(mint): State<Mint>) -> Result<Json<Keysets>, MokshaMintError> {
-    Ok(Json(Keysets::new(
-        mint.keyset.keyset_id,
-        CurrencyUnit::Sat,
-        true,
-    )))
+    Ok(Json(mint.keysets()))
 }
 
 #[utoipa::path(
@@ -224,8 +261,7 @@ pub async fn post_mint_bolt11(
             PaymentMethod::Bolt11,
             request.quote.clone(),
             &request.outputs,
-            &mint.keyset,
-            false,
+            &mint.keyset(),
         )
         .await?;
 
@@ -264,13 +300,10 @@ pub async fn post_melt_quote_bolt11(
         .lightning
         .decode_invoice(melt_request.request.clone())
         .await?;
-    let amount = invoice.amount_milli_satoshis().ok_or_else(|| {
-        crate::error::MokshaMintError::InvalidAmount("invalid invoice".to_owned())
-    })?;
-    let fee_reserve = mint.fee_reserve(amount) / 1_000; // FIXME check if this is correct
+    let amount_sat = mint.melt_amount(invoice.amount_milli_satoshis(), melt_request.amount)?;
+    let fee_reserve = mint.fee_reserve_sat(amount_sat);
     debug!("fee_reserve: {}", fee_reserve);
 
-    let amount_sat = amount / 1_000;
     let key = Uuid::new_v4();
     let quote = Bolt11MeltQuote {
         quote_id: key,
@@ -321,7 +354,7 @@ pub async fn post_melt_quote_bitcredit(
     let response = PostMeltQuoteResponseBitcredit {
         quote: quote.quote_id.to_string(),
         amount: melt_request.quote_amount,
-        fee_reserve: fee_reserve,
+        fee_reserve,
         bill_id: melt_request.bill_id,
         expiry: Option::from(quote.expiry),
     };
@@ -361,25 +394,35 @@ pub async fn post_melt_bolt11(
 
     debug!("post_melt_bolt11 fee_reserve: {:#?}", &quote);
 
-    let (paid, payment_preimage, change) = mint
+    let result = mint
         .melt_bolt11(
             &mut tx,
             quote.payment_request.to_owned(),
+            quote.amount,
             quote.fee_reserve,
             &melt_request.inputs,
             &melt_request.outputs,
-            &mint.keyset,
+            &mint.keyset(),
         )
         .await?;
     mint.db
-        .update_bolt11_melt_quote(&mut tx, &Bolt11MeltQuote { paid, ..quote })
+        .update_bolt11_melt_quote(
+            &mut tx,
+            &Bolt11MeltQuote {
+                paid: result.paid,
+                ..quote
+            },
+        )
         .await?;
     tx.commit().await?;
+    let input_fee = mint.input_fee(&melt_request.inputs)?;
 
     Ok(Json(PostMeltBolt11Response {
-        paid,
-        payment_preimage: Some(payment_preimage),
-        change,
+        paid: result.paid,
+        payment_preimage: Some(result.payment_hash),
+        change: result.change,
+        input_fee: Some(input_fee),
+        fee_paid: Some(result.fee_paid),
     }))
 }
 
@@ -471,10 +514,11 @@ pub async fn get_info(State(mint): State<Mint>) -> Result<Json<MintInfoResponse>
         .collect::<Vec<Vec<String>>>(),
     );
 
+    let pubkey = mint.keyset().mint_pubkey;
     let mint_info = MintInfoResponse {
         nuts: get_nuts(&mint.config),
         name: mint.config.info.name,
-        pubkey: mint.keyset.mint_pubkey,
+        pubkey,
         version: match mint.config.info.version {
             true => Some(mint.build_params.full_version()),
             _ => None,
@@ -491,6 +535,17 @@ fn get_nuts(cfg: &MintConfig) -> Nuts {
     let default_config = BtcOnchainConfig::default();
     let config = cfg.btconchain_backend.as_ref().unwrap_or(&default_config);
     Nuts {
+        nut5: Nut5 {
+            payment_methods: vec![PaymentMethodConfig {
+                payment_method: PaymentMethod::Bolt11,
+                unit: CurrencyUnit::Sat,
+                min_amount: Some(cfg.lightning_fee.min_melt),
+                max_amount: Some(cfg.lightning_fee.max_melt),
+            }],
+            disabled: false,
+        },
+        nut7: Some(Nut7 { supported: true }),
+        nut9: Some(Nut9 { supported: true }),
         nut17: Some(config.to_owned().into()),
         nut18: Some(config.to_owned().into()),
         ..Nuts::default()
@@ -1,3 +1,6 @@
+use crate::routes::admin::{
+    get_admin_keysets, get_admin_redeemed_proofs, post_admin_keyset_sunset, require_admin_token,
+};
 use crate::routes::btconchain::{
     get_melt_btconchain, get_melt_quote_btconchain, get_mint_quote_btconchain,
     post_melt_btconchain, post_melt_quote_btconchain, post_mint_btconchain,
@@ -5,8 +8,9 @@ use crate::routes::btconchain::{
 };
 use crate::routes::default::{
     get_info, get_keys, get_keys_by_id, get_keysets, get_melt_quote_bolt11, get_mint_quote_bolt11,
-    post_melt_bolt11, post_melt_quote_bitcredit, post_melt_quote_bolt11, post_mint_bolt11,
-    post_mint_quote_bitcredit, post_mint_quote_bolt11, post_request_to_mint_bitcredit, post_swap,
+    post_checkstate, post_melt_bolt11, post_melt_quote_bitcredit, post_melt_quote_bolt11,
+    post_mint_bolt11, post_mint_quote_bitcredit, post_mint_quote_bolt11,
+    post_request_to_mint_bitcredit, post_restore, post_swap,
 };
 use axum::extract::Request;
 use axum::http::{HeaderName, HeaderValue, StatusCode};
@@ -25,15 +29,16 @@ use crate::mint::Mint;
 
 use moksha_core::blind::BlindedMessage;
 use moksha_core::blind::BlindedSignature;
+use moksha_core::blind::DleqProof;
 use moksha_core::primitives::{
     CurrencyUnit, GetMeltBtcOnchainResponse, KeyResponse, KeysResponse, MintInfoResponse, Nut10,
     Nut11, Nut12, Nut17, Nut18, Nut4, Nut5, Nut7, Nut8, Nut9, Nuts, PaymentMethod,
-    PostMeltBolt11Request, PostMeltBolt11Response, PostMeltQuoteBolt11Request,
-    PostMeltQuoteBolt11Response, PostMeltQuoteBtcOnchainRequest, PostMeltQuoteBtcOnchainResponse,
-    PostMeltQuoteRequestBitcredit, PostMeltQuoteResponseBitcredit, PostMintBolt11Request,
-    PostMintBolt11Response, PostMintQuoteBolt11Request, PostMintQuoteBolt11Response,
-    PostMintQuoteBtcOnchainRequest, PostMintQuoteBtcOnchainResponse, PostSwapRequest,
-    PostSwapResponse,
+    PostCheckStateRequest, PostCheckStateResponse, PostMeltBolt11Request, PostMeltBolt11Response,
+    PostMeltQuoteBolt11Request, PostMeltQuoteBolt11Response, PostMeltQuoteBtcOnchainRequest,
+    PostMeltQuoteBtcOnchainResponse, PostMeltQuoteRequestBitcredit, PostMeltQuoteResponseBitcredit,
+    PostMintBolt11Request, PostMintBolt11Response, PostMintQuoteBolt11Request,
+    PostMintQuoteBolt11Response, PostMintQuoteBtcOnchainRequest, PostMintQuoteBtcOnchainResponse,
+    PostRestoreRequest, PostRestoreResponse, PostSwapRequest, PostSwapResponse, ProofState, State,
 };
 
 use tower_http::services::ServeDir;
@@ -71,6 +76,9 @@ pub async fn run_server(mint: Mint) -> anyhow::Result<()> {
     }
 
     info!("tracing jaeger-endpoint: {:?}", mint.config.tracing);
+    info!("pending invoice expiry: {:?}", mint.config.pending_invoice);
+
+    mint.spawn_invoice_pruning();
 
     let listener = tokio::net::TcpListener::bind(&mint.config.server.host_port).await?;
 
@@ -105,6 +113,8 @@ pub async fn run_server(mint: Mint) -> anyhow::Result<()> {
         crate::routes::default::post_melt_quote_bitcredit,
         crate::routes::default::get_melt_quote_bolt11,
         crate::routes::default::post_swap,
+        crate::routes::default::post_checkstate,
+        crate::routes::default::post_restore,
         crate::routes::default::get_info,
         get_health,
         crate::routes::btconchain::post_mint_quote_btconchain,
@@ -134,6 +144,7 @@ pub async fn run_server(mint: Mint) -> anyhow::Result<()> {
         Keyset,
         BlindedMessage,
         BlindedSignature,
+        DleqProof,
         Proof,
         Proofs,
         PostMintQuoteBolt11Request,
@@ -148,6 +159,12 @@ pub async fn run_server(mint: Mint) -> anyhow::Result<()> {
         PostMintBolt11Response,
         PostSwapRequest,
         PostSwapResponse,
+        PostCheckStateRequest,
+        PostCheckStateResponse,
+        PostRestoreRequest,
+        PostRestoreResponse,
+        ProofState,
+        State,
         P2SHScript,
         Nut17,
         Nut18,
@@ -160,7 +177,8 @@ pub async fn run_server(mint: Mint) -> anyhow::Result<()> {
 )]
 struct ApiDoc;
 
-fn app(mint: Mint) -> Router {
+/// Creates the router with all routes of the mint. [`run_server`] adds the cors layer.
+pub fn app(mint: Mint) -> Router {
     let default_routes = Router::new()
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
         .route("/v1/keys", get(get_keys))
@@ -168,7 +186,10 @@ fn app(mint: Mint) -> Router {
         .route("/v1/keysets", get(get_keysets))
         .route("/v1/mint/quote/bolt11", post(post_mint_quote_bolt11))
         .route("/v1/mint/quote/bitcredit", post(post_mint_quote_bitcredit))
-        .route("/v1/mint/request/bitcredit", post(post_request_to_mint_bitcredit), )
+        .route(
+            "/v1/mint/request/bitcredit",
+            post(post_request_to_mint_bitcredit),
+        )
         .route("/v1/mint/quote/bolt11/:quote", get(get_mint_quote_bolt11))
         .route("/v1/mint/bolt11", post(post_mint_bolt11))
         .route("/v1/melt/quote/bolt11", post(post_melt_quote_bolt11))
@@ -176,6 +197,8 @@ fn app(mint: Mint) -> Router {
         .route("/v1/melt/quote/bolt11/:quote", get(get_melt_quote_bolt11))
         .route("/v1/melt/bolt11", post(post_melt_bolt11))
         .route("/v1/swap", post(post_swap))
+        .route("/v1/checkstate", post(post_checkstate))
+        .route("/v1/restore", post(post_restore))
         .route("/v1/info", get(get_info));
 
     let btconchain_routes = if mint.onchain.is_some() {
@@ -203,6 +226,20 @@ fn app(mint: Mint) -> Router {
         Router::new()
     };
 
+    // the admin routes are only served with a token
+    let admin_routes = if mint.config.server.admin_token.is_some() {
+        Router::new()
+            .route("/admin/keysets", get(get_admin_keysets))
+            .route("/admin/keysets/:id/sunset", post(post_admin_keyset_sunset))
+            .route("/admin/proofs/redeemed", get(get_admin_redeemed_proofs))
+            .route_layer(middleware::from_fn_with_state(
+                mint.clone(),
+                require_admin_token,
+            ))
+    } else {
+        Router::new()
+    };
+
     let general_routes = Router::new().route("/health", get(get_health));
 
     let server_config = mint.config.server.clone();
@@ -211,6 +248,7 @@ fn app(mint: Mint) -> Router {
     let router = Router::new()
         .nest(&prefix, default_routes)
         .nest(&prefix, btconchain_routes)
+        .nest(&prefix, admin_routes)
         .nest("", general_routes)
         .with_state(mint);
 
@@ -276,8 +314,9 @@ mod tests {
 
     use crate::{
         btconchain::MockBtcOnchain,
-        config::{DatabaseConfig, MintConfig},
+        config::{DatabaseConfig, MintConfig, ServerConfig},
         database::postgres::PostgresDB,
+        model::{AdminKeyset, AdminRedeemedProofs},
         server::app,
     };
     use axum::{
@@ -287,7 +326,9 @@ mod tests {
     use http_body_util::BodyExt;
     use moksha_core::{
         keyset::Keysets,
-        primitives::{CurrencyUnit, KeysResponse, MintInfoResponse},
+        primitives::{
+            CashuErrorCode, CashuErrorResponse, CurrencyUnit, KeysResponse, MintInfoResponse,
+        },
     };
 
     use testcontainers::{clients::Cli, RunnableImage};
@@ -456,6 +497,10 @@ mod tests {
             .await?;
 
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let body = response.into_body().collect().await?.to_bytes();
+        let error: CashuErrorResponse = serde_json::from_slice(&body)?;
+        assert_eq!(Some(CashuErrorCode::KeysetNotFound), error.error_code());
+        assert_eq!("Keyset not found unknownkeyset", error.error);
         Ok(())
     }
 
@@ -553,6 +598,141 @@ mod tests {
             info.description_long,
             Some("A mint for testing long".to_string())
         );
+        let melt_limits = &info.nuts.nut5.payment_methods[0];
+        assert_eq!(Some(1), melt_limits.min_amount);
+        assert_eq!(Some(10_000_000), melt_limits.max_amount);
+        Ok(())
+    }
+    #[tokio::test]
+    async fn test_admin_keysets() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mint = Mint::new(
+            Arc::new(MockLightning::new()),
+            LightningType::Lnbits(Default::default()),
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            MintConfig {
+                privatekey: "mytestsecret".to_string(),
+                server: ServerConfig {
+                    admin_token: Some("admintoken".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Default::default(),
+            Some(Arc::new(MockBtcOnchain::default())),
+        );
+        let app = app(mint);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/keysets")
+                    .header("Authorization", "Bearer wrongtoken")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/keysets")
+                    .header("Authorization", "Bearer admintoken")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let keysets = serde_json::from_slice::<Vec<AdminKeyset>>(&body)?;
+        assert_eq!(1, keysets.len());
+        assert!(keysets[0].active);
+        assert_eq!(0, keysets[0].outstanding);
+
+        // the active keyset can't be sunset
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri(format!("/admin/keysets/{}/sunset", keysets[0].id))
+                    .header("Authorization", "Bearer admintoken")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(r#"{"sunset_at":2000}"#))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_admin_redeemed_proofs() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let mint = Mint::new(
+            Arc::new(MockLightning::new()),
+            LightningType::Lnbits(Default::default()),
+            create_mock_db_empty(node.get_host_port_ipv4(5432)).await?,
+            MintConfig {
+                privatekey: "mytestsecret".to_string(),
+                server: ServerConfig {
+                    admin_token: Some("admintoken".to_string()),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            Default::default(),
+            Some(Arc::new(MockBtcOnchain::default())),
+        );
+        let app = app(mint);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/proofs/redeemed?limit=10")
+                    .header("Authorization", "Bearer admintoken")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = response.into_body().collect().await?.to_bytes();
+        let page = serde_json::from_slice::<AdminRedeemedProofs>(&body)?;
+        assert!(page.proofs.is_empty());
+        assert_eq!(None, page.next);
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/proofs/redeemed?before=invalid")
+                    .header("Authorization", "Bearer admintoken")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_admin_keysets_disabled() -> anyhow::Result<()> {
+        let docker = Cli::default();
+        let image = create_postgres_image();
+        let node = docker.run(image);
+
+        let app = app(create_mock_mint(Default::default(), node.get_host_port_ipv4(5432)).await?);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/admin/keysets")
+                    .body(Body::empty())?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
         Ok(())
     }
 }
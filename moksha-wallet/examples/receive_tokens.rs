@@ -1,9 +1,7 @@
 use std::env::temp_dir;
 
 use moksha_core::token::TokenV3;
-use moksha_wallet::{
-    http::CrossPlatformHttpClient, localstore::sqlite::SqliteLocalStore, wallet::Wallet,
-};
+use moksha_wallet::{localstore::sqlite::SqliteLocalStore, wallet::Wallet};
 use std::str::FromStr;
 use url::Url;
 
@@ -12,18 +10,16 @@ async fn main() -> anyhow::Result<()> {
     let db_path = temp_dir().join("wallet.db").to_str().unwrap().to_string();
     let localstore = SqliteLocalStore::with_path(db_path).await?;
 
-    let wallet: Wallet<_, CrossPlatformHttpClient> = Wallet::builder()
+    let wallet: Wallet<_> = Wallet::builder()
         .with_localstore(localstore)
         .build()
         .await?;
-    let wallet_keysets = wallet
-        .add_mint_keysets(&Url::parse("https://mint.mutinynet.moksha.cash")?)
-        .await?;
-    let wallet_keyset = wallet_keysets.first().unwrap();
-    // FIXME add better filtering by CurrencyUnit
+    let mint_url = Url::parse("https://mint.mutinynet.moksha.cash")?;
+    wallet.add_mint_keysets(&mint_url).await?;
+    let wallet_keyset = wallet.get_active_keyset(&mint_url).await?;
 
     let tokens = TokenV3::from_str("cashuAeyJ0b2tlbiI6IFt7InByb29mcyI6IFt7ImlkIjogIjAwOTkxZjRmMjc3MzMzOGMiLCAiYW1vdW50IjogMiwgInNlY3JldCI6ICI5ZmFjZWE0Y2QzN2I3ZWRlOGE4NmQzYWY1ZWIxZTczNzIxMDNmZDE2YTQ1M2E5NDQ5YjE0MDFkZDhhMzAzMWJiIiwgIkMiOiAiMDM2ZTVhOWJhOWE1ZjYxZmQ5MTk3YzM2OTgzZjc1YzAzYTUyYzc0YTJmZmM2NTBmNzg5MjJlMDcyZWY1MTI0YjZlIn1dLCAibWludCI6ICJodHRwczovL21pbnQubXV0aW55bmV0Lm1va3NoYS5jYXNoOjMzMzgifV19")?;
-    wallet.receive_tokens(wallet_keyset, &tokens).await?;
+    wallet.receive_tokens(&wallet_keyset, &tokens).await?;
     let balance = wallet.get_balance().await?;
     println!("New balance: {} sats", balance);
     Ok(())
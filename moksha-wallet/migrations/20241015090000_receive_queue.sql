@@ -0,0 +1,10 @@
+-- tokens that could not be redeemed yet and are retried in the background
+CREATE TABLE IF NOT EXISTS receive_queue (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token TEXT NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt INTEGER NOT NULL DEFAULT 0,
+    status TEXT NOT NULL,
+    last_error TEXT,
+    UNIQUE (token)
+);
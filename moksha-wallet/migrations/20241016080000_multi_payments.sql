@@ -0,0 +1,13 @@
+-- journal of payments that transfer funds between mints before paying an invoice
+CREATE TABLE IF NOT EXISTS multi_payments (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    invoice TEXT NOT NULL,
+    source_mint_url TEXT NOT NULL,
+    target_mint_url TEXT NOT NULL,
+    transfer_amount INTEGER NOT NULL,
+    transfer_quote TEXT,
+    transfer_invoice TEXT,
+    transfer_fees INTEGER NOT NULL DEFAULT 0,
+    payment_fees INTEGER NOT NULL DEFAULT 0,
+    stage TEXT NOT NULL
+);
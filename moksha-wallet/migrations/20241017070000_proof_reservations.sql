@@ -0,0 +1,2 @@
+-- unix timestamp of when the proof was reserved for a pending operation
+ALTER TABLE proofs ADD COLUMN reserved_at INTEGER;
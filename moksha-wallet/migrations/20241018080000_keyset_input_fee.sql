@@ -0,0 +1,2 @@
+-- input fee of the keyset in parts per thousand per proof (NUT-02)
+ALTER TABLE keysets ADD COLUMN input_fee_ppk INTEGER NOT NULL DEFAULT 0;
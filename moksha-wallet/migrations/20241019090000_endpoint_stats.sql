@@ -0,0 +1,9 @@
+-- request counters and the latest response times (json array in ms) per mint endpoint
+CREATE TABLE IF NOT EXISTS endpoint_stats (
+    mint_url TEXT NOT NULL,
+    endpoint TEXT NOT NULL,
+    requests INTEGER NOT NULL,
+    errors INTEGER NOT NULL,
+    latencies TEXT NOT NULL,
+    PRIMARY KEY (mint_url, endpoint)
+);
@@ -0,0 +1,9 @@
+-- outputs and outcome of minting a quote, so only one attempt runs and an interrupted one can be finished
+CREATE TABLE IF NOT EXISTS pending_mints (
+    quote_id TEXT PRIMARY KEY,
+    mint_url TEXT NOT NULL,
+    keyset_id TEXT NOT NULL,
+    outputs TEXT NOT NULL,
+    claimed_at INTEGER,
+    proofs TEXT
+);
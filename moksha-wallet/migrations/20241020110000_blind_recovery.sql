@@ -0,0 +1,9 @@
+-- outputs of requests that are in flight, so their proofs can be restored after a crash (NUT-09)
+CREATE TABLE IF NOT EXISTS blind_recovery (
+    b_ TEXT PRIMARY KEY NOT NULL,
+    mint_url TEXT NOT NULL,
+    keyset_id TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    secret TEXT NOT NULL,
+    blinding_factor TEXT NOT NULL
+);
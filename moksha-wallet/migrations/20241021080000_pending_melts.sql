@@ -0,0 +1,9 @@
+-- proofs of melts whose outcome is not known yet, so they can be restored or removed after a crash
+CREATE TABLE IF NOT EXISTS pending_melts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    mint_url TEXT NOT NULL,
+    quote_id TEXT NOT NULL,
+    invoice TEXT NOT NULL,
+    proofs TEXT NOT NULL,
+    created_at INTEGER NOT NULL
+);
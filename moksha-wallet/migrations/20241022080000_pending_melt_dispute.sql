@@ -0,0 +1,2 @@
+-- reason why the change of a melt was not accepted, the melt is kept for a manual check
+ALTER TABLE pending_melts ADD COLUMN dispute TEXT;
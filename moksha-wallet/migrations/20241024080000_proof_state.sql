@@ -0,0 +1,5 @@
+-- lifecycle of a proof: spendable, reserved, pending_melt, pending_send, frozen or spent
+ALTER TABLE proofs ADD COLUMN state TEXT NOT NULL DEFAULT 'spendable';
+-- proofs were only reserved for melts
+UPDATE proofs SET state = 'pending_melt' WHERE reserved_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS proofs_state ON proofs (state);
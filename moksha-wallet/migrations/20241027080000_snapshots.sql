@@ -0,0 +1,7 @@
+-- copies of the spendable proofs, taken before operations that remove proofs
+CREATE TABLE IF NOT EXISTS snapshots (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    reason TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    proofs TEXT NOT NULL
+);
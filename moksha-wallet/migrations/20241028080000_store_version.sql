@@ -0,0 +1,7 @@
+-- version of the schema and the oldest version of the wallet that can still use it, so an older
+-- wallet doesn't open a store that was migrated by a newer one
+CREATE TABLE IF NOT EXISTS store_version (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    version INTEGER NOT NULL,
+    min_compatible_version INTEGER NOT NULL
+);
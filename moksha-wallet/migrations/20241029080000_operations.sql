@@ -0,0 +1,15 @@
+-- top-level operations of the wallet and the records they wrote
+CREATE TABLE IF NOT EXISTS operations (
+    id TEXT PRIMARY KEY NOT NULL,
+    kind TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    finished_at INTEGER,
+    error TEXT
+);
+
+CREATE TABLE IF NOT EXISTS operation_records (
+    operation_id TEXT NOT NULL REFERENCES operations(id),
+    record_type TEXT NOT NULL,
+    record_key TEXT NOT NULL,
+    PRIMARY KEY (operation_id, record_type, record_key)
+);
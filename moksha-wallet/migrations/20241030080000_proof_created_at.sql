@@ -0,0 +1,3 @@
+-- unix timestamp (seconds) when the proof was added, taken from time_created for existing proofs
+ALTER TABLE proofs ADD COLUMN created_at INTEGER NOT NULL DEFAULT 0;
+UPDATE proofs SET created_at = COALESCE(CAST(strftime('%s', time_created) AS INTEGER), 0);
@@ -0,0 +1,11 @@
+-- mints, sends, receives and payments that changed the balance
+CREATE TABLE IF NOT EXISTS history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    operation_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    amount INTEGER NOT NULL,
+    mint_url TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    invoice TEXT,
+    memo TEXT
+);
@@ -0,0 +1,2 @@
+-- pages of the history are ordered by the creation time and the id, the newest first
+CREATE INDEX IF NOT EXISTS history_created_at_id ON history (created_at, id);
@@ -0,0 +1,230 @@
+//! Best-effort proof that a wallet holds ecash at a mint, see
+//! [`crate::wallet::Wallet::export_balance_attestation`].
+//!
+//! An attestation lists the amounts, the signatures `C` and the `Y` values of proofs, but not
+//! their secrets, so it can't be redeemed. A verifier asks the mint (NUT-07) whether the `Y` values
+//! are unspent. The mint doesn't reveal the amounts of the proofs, so they are taken from the
+//! attestation. The document is signed with an ephemeral key, which only shows that it was not
+//! changed after it was created. The nonce and the creation time keep a verifier from accepting
+//! the same attestation twice or an old one.
+
+use std::collections::HashSet;
+
+use bitcoin_hashes::{sha256, Hash};
+use moksha_core::proof::Proofs;
+use rand::Rng;
+use secp256k1::{ecdsa::Signature, Message, PublicKey, Secp256k1, SecretKey};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::MokshaWalletError;
+
+/// Marks an attestation, it is not a token and has no value
+pub const ATTESTATION_PURPOSE: &str = "moksha balance attestation, not redeemable";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceAttestation {
+    /// always [`ATTESTATION_PURPOSE`]
+    pub purpose: String,
+    pub mint_url: Url,
+    pub proofs: Vec<AttestedProof>,
+    /// 32 random bytes as hex
+    pub nonce: String,
+    /// unix timestamp (seconds)
+    pub created_at: u64,
+    /// the ephemeral key the attestation is signed with
+    pub pubkey: PublicKey,
+    /// compact ECDSA signature over the other fields as hex
+    pub signature: String,
+}
+
+/// A proof without its secret
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AttestedProof {
+    pub amount: u64,
+    #[serde(rename = "C")]
+    pub c: PublicKey,
+    /// hash of the secret to the curve, the mint reports the state of the proof by it
+    #[serde(rename = "Y")]
+    pub y: PublicKey,
+}
+
+impl BalanceAttestation {
+    /// Creates an attestation for the proofs with a fresh nonce and signs it with a new key that
+    /// is discarded afterwards
+    pub fn create(
+        mint_url: &Url,
+        proofs: &Proofs,
+        created_at: u64,
+    ) -> Result<Self, MokshaWalletError> {
+        let mut rng = rand::thread_rng();
+        let secret_key = SecretKey::from_slice(&rng.gen::<[u8; 32]>())?;
+        let secp = Secp256k1::new();
+        let mut attestation = Self {
+            purpose: ATTESTATION_PURPOSE.to_owned(),
+            mint_url: mint_url.clone(),
+            proofs: proofs
+                .proofs()
+                .into_iter()
+                .map(|proof| {
+                    Ok(AttestedProof {
+                        amount: proof.amount,
+                        c: proof.c,
+                        y: proof.y()?,
+                    })
+                })
+                .collect::<Result<_, MokshaWalletError>>()?,
+            nonce: hex::encode(rng.gen::<[u8; 32]>()),
+            created_at,
+            pubkey: PublicKey::from_secret_key(&secp, &secret_key),
+            signature: String::new(),
+        };
+        let signature = secp.sign_ecdsa(&attestation.message()?, &secret_key);
+        attestation.signature = hex::encode(signature.serialize_compact());
+        Ok(attestation)
+    }
+
+    /// Sum of the amounts of the proofs
+    pub fn total_amount(&self) -> u64 {
+        self.proofs.iter().map(|proof| proof.amount).sum()
+    }
+
+    /// Hash of every field except the signature
+    fn message(&self) -> Result<Message, MokshaWalletError> {
+        let unsigned = serde_json::to_vec(&(
+            &self.purpose,
+            &self.mint_url,
+            &self.proofs,
+            &self.nonce,
+            self.created_at,
+            &self.pubkey,
+        ))?;
+        Ok(Message::from_digest(
+            sha256::Hash::hash(&unsigned).to_byte_array(),
+        ))
+    }
+
+    fn verify_signature(&self) -> Result<(), MokshaWalletError> {
+        let invalid = || MokshaWalletError::InvalidAttestation("invalid signature".to_owned());
+        let signature = hex::decode(&self.signature).map_err(|_| invalid())?;
+        let signature = Signature::from_compact(&signature).map_err(|_| invalid())?;
+        Secp256k1::verification_only()
+            .verify_ecdsa(&self.message()?, &signature, &self.pubkey)
+            .map_err(|_| invalid())
+    }
+}
+
+/// Checks attestations before the state of their proofs is checked with the mint, see
+/// [`crate::wallet::Wallet::verify_balance_attestation`]. Remembers the nonces it has seen, so
+/// a verifier should be kept for as long as attestations are accepted.
+#[derive(Debug, Clone, Default)]
+pub struct AttestationVerifier {
+    /// seconds an attestation is accepted after it was created
+    pub max_age: u64,
+    seen_nonces: HashSet<String>,
+}
+
+impl AttestationVerifier {
+    pub fn new(max_age: u64) -> Self {
+        Self {
+            max_age,
+            seen_nonces: HashSet::new(),
+        }
+    }
+
+    /// Fails if the attestation is not a balance attestation, was changed, is older than
+    /// [`Self::max_age`] or its nonce was seen before. The nonce of an attestation that passes is
+    /// remembered.
+    pub fn check(
+        &mut self,
+        attestation: &BalanceAttestation,
+        now: u64,
+    ) -> Result<(), MokshaWalletError> {
+        let invalid = |reason: &str| Err(MokshaWalletError::InvalidAttestation(reason.to_owned()));
+        if attestation.purpose != ATTESTATION_PURPOSE {
+            return invalid("not a balance attestation");
+        }
+        if attestation.proofs.is_empty() {
+            return invalid("no proofs");
+        }
+        attestation.verify_signature()?;
+        if attestation.created_at > now.saturating_add(self.max_age)
+            || now.saturating_sub(attestation.created_at) > self.max_age
+        {
+            return invalid("expired");
+        }
+        if !self.seen_nonces.insert(attestation.nonce.clone()) {
+            return invalid("nonce was already used");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use moksha_core::{fixture::read_fixture, token::TokenV3};
+    use url::Url;
+
+    use super::{AttestationVerifier, BalanceAttestation};
+    use crate::error::MokshaWalletError;
+
+    fn create_attestation() -> anyhow::Result<BalanceAttestation> {
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?
+            .trim()
+            .to_string()
+            .try_into()?;
+        Ok(BalanceAttestation::create(
+            &Url::parse("https://mint.example.com/")?,
+            &tokens.proofs(),
+            1_000,
+        )?)
+    }
+
+    #[test]
+    fn test_attestation_has_no_secrets() -> anyhow::Result<()> {
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?
+            .trim()
+            .to_string()
+            .try_into()?;
+        let attestation = create_attestation()?;
+        assert_eq!(60, attestation.total_amount());
+
+        let json = serde_json::to_string(&attestation)?;
+        for secret in tokens.proofs().secrets() {
+            assert!(!json.contains(&secret));
+        }
+        // a token can't be parsed from it
+        assert!(TokenV3::try_from(json.clone()).is_err());
+        assert_eq!(attestation, serde_json::from_str(&json)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_attestation() -> anyhow::Result<()> {
+        let attestation = create_attestation()?;
+        let mut verifier = AttestationVerifier::new(600);
+        verifier.check(&attestation, 1_100)?;
+
+        // replayed
+        assert!(matches!(
+            verifier.check(&attestation, 1_100),
+            Err(MokshaWalletError::InvalidAttestation(_))
+        ));
+
+        // too old
+        let attestation = create_attestation()?;
+        assert!(matches!(
+            AttestationVerifier::new(600).check(&attestation, 1_601),
+            Err(MokshaWalletError::InvalidAttestation(_))
+        ));
+
+        // changed after it was signed
+        let mut changed = create_attestation()?;
+        changed.proofs[0].amount = 1_000;
+        assert!(matches!(
+            AttestationVerifier::new(600).check(&changed, 1_100),
+            Err(MokshaWalletError::InvalidAttestation(_))
+        ));
+        Ok(())
+    }
+}
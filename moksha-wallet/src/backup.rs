@@ -0,0 +1,191 @@
+//! Portable backup of the proofs of a wallet, see [`crate::wallet::Wallet::export_backup`]
+//!
+//! A backup file starts with [`BACKUP_MAGIC`] and the version, followed by the salt, the
+//! [`WalletBackup`] as json encrypted with the ChaCha20 keystream and an HMAC-SHA256 tag over
+//! everything before it (encrypt-then-MAC). Both keys are derived from the password and the salt
+//! with PBKDF2-HMAC-SHA256. Version 1 backups are unencrypted json.
+
+use hmac::{Hmac, Mac};
+use moksha_core::proof::Proofs;
+use rand::{Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::error::MokshaWalletError;
+
+/// version of the backups written by this wallet
+pub const BACKUP_VERSION: u16 = 2;
+
+/// first bytes of an encrypted backup file
+pub const BACKUP_MAGIC: &[u8; 8] = b"MOKSHABK";
+
+/// PBKDF2 iterations of the key derivation
+const KDF_ROUNDS: u32 = 600_000;
+const HEADER_LEN: usize = BACKUP_MAGIC.len() + 2;
+const SALT_LEN: usize = 16;
+const TAG_LEN: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletBackup {
+    pub version: u16,
+    pub mints: Vec<MintBackup>,
+}
+
+/// The proofs of a mint, grouped with the keysets they belong to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MintBackup {
+    pub mint_url: String,
+    pub keysets: Vec<String>,
+    pub proofs: Proofs,
+}
+
+impl WalletBackup {
+    /// Parses a backup and checks that every proof belongs to one of the keysets of its mint
+    pub fn parse(json: &str) -> Result<Self, MokshaWalletError> {
+        let backup: Self = serde_json::from_str(json)?;
+        if !(1..=BACKUP_VERSION).contains(&backup.version) {
+            return Err(MokshaWalletError::InvalidBackup(format!(
+                "unsupported version {}",
+                backup.version
+            )));
+        }
+        for mint in &backup.mints {
+            if let Some(proof) = mint
+                .proofs
+                .proofs()
+                .into_iter()
+                .find(|proof| !mint.keysets.contains(&proof.keyset_id))
+            {
+                return Err(MokshaWalletError::InvalidBackup(format!(
+                    "proof of keyset {} is not listed for mint {}",
+                    proof.keyset_id, mint.mint_url
+                )));
+            }
+        }
+        Ok(backup)
+    }
+
+    pub fn total_amount(&self) -> u64 {
+        self.mints
+            .iter()
+            .map(|mint| mint.proofs.total_amount())
+            .sum()
+    }
+}
+
+/// Encrypts the json of a backup with keys derived from `password`
+pub fn encrypt_backup(json: &str, password: &str) -> Result<Vec<u8>, MokshaWalletError> {
+    let salt = rand::thread_rng().gen::<[u8; SALT_LEN]>();
+    let (encryption_key, mac_key) = derive_keys(password, &salt);
+
+    let mut data = Vec::with_capacity(HEADER_LEN + SALT_LEN + json.len() + TAG_LEN);
+    data.extend_from_slice(BACKUP_MAGIC);
+    data.extend_from_slice(&BACKUP_VERSION.to_be_bytes());
+    data.extend_from_slice(&salt);
+    let mut ciphertext = json.as_bytes().to_vec();
+    apply_keystream(&encryption_key, &mut ciphertext);
+    data.extend_from_slice(&ciphertext);
+    let tag = mac(&mac_key, &data).finalize().into_bytes();
+    data.extend_from_slice(&tag);
+    Ok(data)
+}
+
+/// Decrypts a backup from [`encrypt_backup`]. A wrong password and a changed file can't be told
+/// apart, both fail with [`MokshaWalletError::InvalidBackup`].
+pub fn decrypt_backup(data: &[u8], password: &str) -> Result<String, MokshaWalletError> {
+    let invalid = |reason: &str| MokshaWalletError::InvalidBackup(reason.to_owned());
+    if data.len() < HEADER_LEN + SALT_LEN + TAG_LEN || !data.starts_with(BACKUP_MAGIC) {
+        return Err(invalid("not a moksha backup"));
+    }
+    let version = u16::from_be_bytes([data[BACKUP_MAGIC.len()], data[BACKUP_MAGIC.len() + 1]]);
+    if version != BACKUP_VERSION {
+        return Err(MokshaWalletError::InvalidBackup(format!(
+            "unsupported version {version}"
+        )));
+    }
+    let (authenticated, tag) = data.split_at(data.len() - TAG_LEN);
+    let salt = &authenticated[HEADER_LEN..HEADER_LEN + SALT_LEN];
+    let (encryption_key, mac_key) = derive_keys(password, salt);
+    mac(&mac_key, authenticated)
+        .verify_slice(tag)
+        .map_err(|_| invalid("wrong password or corrupted backup"))?;
+
+    let mut json = authenticated[HEADER_LEN + SALT_LEN..].to_vec();
+    apply_keystream(&encryption_key, &mut json);
+    Ok(String::from_utf8(json)?)
+}
+
+/// Returns the encryption key and the mac key
+fn derive_keys(password: &str, salt: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut keys = [0u8; 64];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, KDF_ROUNDS, &mut keys);
+    let (encryption_key, mac_key) = keys.split_at(32);
+    (
+        encryption_key.try_into().expect("32 bytes"),
+        mac_key.try_into().expect("32 bytes"),
+    )
+}
+
+/// XORs the data with the ChaCha20 keystream. Every backup has a fresh salt and thereby a fresh
+/// key, so the keystream is never reused.
+fn apply_keystream(key: &[u8; 32], data: &mut [u8]) {
+    let mut keystream = vec![0u8; data.len()];
+    ChaCha20Rng::from_seed(*key).fill_bytes(&mut keystream);
+    data.iter_mut()
+        .zip(keystream)
+        .for_each(|(byte, key)| *byte ^= key);
+}
+
+fn mac(key: &[u8; 32], data: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any length");
+    mac.update(data);
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decrypt_backup, encrypt_backup, BACKUP_MAGIC};
+    use crate::error::MokshaWalletError;
+
+    #[test]
+    fn test_encrypt_backup_roundtrip() -> anyhow::Result<()> {
+        let json = r#"{"version":2,"mints":[]}"#;
+        let data = encrypt_backup(json, "secret password")?;
+        assert!(data.starts_with(BACKUP_MAGIC));
+        assert!(!String::from_utf8_lossy(&data).contains("mints"));
+        assert_eq!(json, decrypt_backup(&data, "secret password")?);
+
+        // a fresh salt for every backup
+        assert_ne!(data, encrypt_backup(json, "secret password")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_backup_invalid() -> anyhow::Result<()> {
+        let data = encrypt_backup(r#"{"version":2,"mints":[]}"#, "secret password")?;
+
+        let mut corrupted_tag = data.clone();
+        let last = corrupted_tag.len() - 1;
+        corrupted_tag[last] ^= 1;
+        let mut corrupted = data.clone();
+        corrupted[30] ^= 1;
+        let mut changed_header = data.clone();
+        changed_header[BACKUP_MAGIC.len() + 1] ^= 1;
+
+        for (data, password) in [
+            (&data[..], "wrong password"),
+            (&corrupted[..], "secret password"),
+            (&corrupted_tag[..], "secret password"),
+            (&changed_header[..], "secret password"),
+            (&data[..20], "secret password"),
+            (b"MOKSHABK".as_slice(), "secret password"),
+        ] {
+            assert!(matches!(
+                decrypt_backup(data, password),
+                Err(MokshaWalletError::InvalidBackup(_))
+            ));
+        }
+        Ok(())
+    }
+}
@@ -5,12 +5,14 @@ use moksha_core::{
     keyset::Keysets,
     primitives::{
         CurrencyUnit, GetMeltBtcOnchainResponse, KeysResponse, MintInfoResponse,
-        PostMeltBolt11Request, PostMeltBolt11Response, PostMeltBtcOnchainRequest,
-        PostMeltBtcOnchainResponse, PostMeltQuoteBolt11Request, PostMeltQuoteBolt11Response,
-        PostMeltQuoteBtcOnchainRequest, PostMeltQuoteBtcOnchainResponse, PostMintBolt11Request,
-        PostMintBolt11Response, PostMintBtcOnchainRequest, PostMintBtcOnchainResponse,
-        PostMintQuoteBolt11Request, PostMintQuoteBolt11Response, PostMintQuoteBtcOnchainRequest,
-        PostMintQuoteBtcOnchainResponse, PostSwapRequest, PostSwapResponse,
+        PostCheckStateRequest, PostCheckStateResponse, PostMeltBolt11Request,
+        PostMeltBolt11Response, PostMeltBtcOnchainRequest, PostMeltBtcOnchainResponse,
+        PostMeltQuoteBolt11Request, PostMeltQuoteBolt11Response, PostMeltQuoteBtcOnchainRequest,
+        PostMeltQuoteBtcOnchainResponse, PostMintBolt11Request, PostMintBolt11Response,
+        PostMintBtcOnchainRequest, PostMintBtcOnchainResponse, PostMintQuoteBolt11Request,
+        PostMintQuoteBolt11Response, PostMintQuoteBtcOnchainRequest,
+        PostMintQuoteBtcOnchainResponse, PostRestoreRequest, PostRestoreResponse, PostSwapRequest,
+        PostSwapResponse,
     },
     proof::Proofs,
 };
@@ -19,9 +21,15 @@ use moksha_core::primitives::{
     PostMintQuoteBitcreditRequest, PostMintQuoteBitcreditResponse, PostRequestToMintBitcredit,
     PostRequestToMintBitcreditResponse,
 };
+use secp256k1::PublicKey;
 use url::Url;
 
-use crate::{error::MokshaWalletError, http::CrossPlatformHttpClient};
+use crate::{
+    error::MokshaWalletError,
+    http::CrossPlatformHttpClient,
+    lnurl::{LnurlInvoiceResponse, LnurlPayResponse},
+    stats::EndpointSamples,
+};
 
 use super::CashuClient;
 
@@ -55,6 +63,26 @@ impl CashuClient for CrossPlatformHttpClient {
         self.do_post(&mint_url.join("v1/swap")?, &body).await
     }
 
+    async fn post_checkstate(
+        &self,
+        mint_url: &Url,
+        ys: Vec<PublicKey>,
+    ) -> Result<PostCheckStateResponse, MokshaWalletError> {
+        let body = PostCheckStateRequest { ys };
+
+        self.do_post(&mint_url.join("v1/checkstate")?, &body).await
+    }
+
+    async fn post_restore(
+        &self,
+        mint_url: &Url,
+        outputs: Vec<BlindedMessage>,
+    ) -> Result<PostRestoreResponse, MokshaWalletError> {
+        let body = PostRestoreRequest { outputs };
+
+        self.do_post(&mint_url.join("v1/restore")?, &body).await
+    }
+
     async fn post_melt_bolt11(
         &self,
         mint_url: &Url,
@@ -80,6 +108,7 @@ impl CashuClient for CrossPlatformHttpClient {
         let body = PostMeltQuoteBolt11Request {
             request: payment_request,
             unit,
+            amount: None,
         };
 
         self.do_post(&mint_url.join("v1/melt/quote/bolt11")?, &body)
@@ -189,12 +218,16 @@ impl CashuClient for CrossPlatformHttpClient {
             .await
     }
 
-    async fn get_info(&self, mint_url: &Url) -> Result<MintInfoResponse, MokshaWalletError> {
-        self.do_get(&mint_url.join("v1/info")?).await
+    async fn get_info(
+        &self,
+        mint_url: &Url,
+    ) -> Result<Option<MintInfoResponse>, MokshaWalletError> {
+        self.do_get_optional(&mint_url.join("v1/info")?).await
     }
 
     async fn is_v1_supported(&self, mint_url: &Url) -> Result<bool, MokshaWalletError> {
-        self.get_status(&mint_url.join("v1/info")?)
+        // the info endpoint is optional, every v1 mint serves its keysets
+        self.get_status(&mint_url.join("v1/keysets")?)
             .await
             .map(|s| s == 200)
     }
@@ -243,4 +276,26 @@ impl CashuClient for CrossPlatformHttpClient {
         self.do_get(&mint_url.join(&format!("/v1/melt/btconchain/{txid}"))?)
             .await
     }
+
+    async fn get_lnurl_pay_request(
+        &self,
+        url: &Url,
+    ) -> Result<LnurlPayResponse, MokshaWalletError> {
+        self.do_get(url).await
+    }
+
+    async fn get_lnurl_invoice(
+        &self,
+        callback: &Url,
+        amount_msat: u64,
+    ) -> Result<LnurlInvoiceResponse, MokshaWalletError> {
+        let mut url = callback.clone();
+        url.query_pairs_mut()
+            .append_pair("amount", &amount_msat.to_string());
+        self.do_get(&url).await
+    }
+
+    fn take_endpoint_samples(&self) -> Vec<(Url, EndpointSamples)> {
+        self.stats().take()
+    }
 }
@@ -4,16 +4,22 @@ use moksha_core::{
     keyset::Keysets,
     primitives::{
         CurrencyUnit, GetMeltBtcOnchainResponse, KeysResponse, MintInfoResponse,
-        PostMeltBolt11Response, PostMeltBtcOnchainResponse, PostMeltQuoteBolt11Response,
-        PostMeltQuoteBtcOnchainResponse, PostMintBolt11Response, PostMintBtcOnchainResponse,
-        PostMintQuoteBolt11Response, PostMintQuoteBtcOnchainResponse, PostSwapResponse,
+        PostCheckStateResponse, PostMeltBolt11Response, PostMeltBtcOnchainResponse,
+        PostMeltQuoteBolt11Response, PostMeltQuoteBtcOnchainResponse, PostMintBolt11Response,
+        PostMintBtcOnchainResponse, PostMintQuoteBolt11Response, PostMintQuoteBtcOnchainResponse,
+        PostRestoreResponse, PostSwapResponse,
     },
     proof::Proofs,
 };
 
+use secp256k1::PublicKey;
 use url::Url;
 
-use crate::error::MokshaWalletError;
+use crate::{
+    error::MokshaWalletError,
+    lnurl::{LnurlInvoiceResponse, LnurlPayResponse},
+    stats::EndpointSamples,
+};
 
 pub mod crossplatform;
 
@@ -41,6 +47,19 @@ pub trait CashuClient {
         output: Vec<BlindedMessage>,
     ) -> Result<PostSwapResponse, MokshaWalletError>;
 
+    async fn post_checkstate(
+        &self,
+        mint_url: &Url,
+        ys: Vec<PublicKey>,
+    ) -> Result<PostCheckStateResponse, MokshaWalletError>;
+
+    /// Returns the signatures the mint has issued for the outputs (NUT-09)
+    async fn post_restore(
+        &self,
+        mint_url: &Url,
+        outputs: Vec<BlindedMessage>,
+    ) -> Result<PostRestoreResponse, MokshaWalletError>;
+
     async fn post_melt_bolt11(
         &self,
         mint_url: &Url,
@@ -97,7 +116,9 @@ pub trait CashuClient {
         quote: String,
     ) -> Result<PostMintQuoteBolt11Response, MokshaWalletError>;
 
-    async fn get_info(&self, mint_url: &Url) -> Result<MintInfoResponse, MokshaWalletError>;
+    /// Returns `None` if the mint doesn't serve the info endpoint (NUT-06), like some older mints
+    async fn get_info(&self, mint_url: &Url)
+        -> Result<Option<MintInfoResponse>, MokshaWalletError>;
 
     async fn is_v1_supported(&self, mint_url: &Url) -> Result<bool, MokshaWalletError>;
 
@@ -147,4 +168,18 @@ pub trait CashuClient {
         mint_url: &Url,
         txid: String,
     ) -> Result<GetMeltBtcOnchainResponse, MokshaWalletError>;
+
+    /// Fetches the LNURL-pay request of a lightning address, see [`crate::lnurl`]
+    async fn get_lnurl_pay_request(&self, url: &Url)
+        -> Result<LnurlPayResponse, MokshaWalletError>;
+
+    /// Requests an invoice of `amount_msat` from the callback of a LNURL-pay request
+    async fn get_lnurl_invoice(
+        &self,
+        callback: &Url,
+        amount_msat: u64,
+    ) -> Result<LnurlInvoiceResponse, MokshaWalletError>;
+
+    /// Returns and clears the response times and errors recorded since the last call
+    fn take_endpoint_samples(&self) -> Vec<(Url, EndpointSamples)>;
 }
@@ -0,0 +1,28 @@
+#[cfg(test)]
+use mockall::automock;
+
+/// Source of the current time, so time dependent behaviour can be tested
+#[cfg_attr(test, automock)]
+pub trait Clock: Send + Sync {
+    /// Returns the current unix timestamp in seconds
+    fn now(&self) -> u64;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn now(&self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("system time is before unix epoch")
+            .as_secs()
+    }
+
+    // std::time is not available in the browser
+    #[cfg(target_arch = "wasm32")]
+    fn now(&self) -> u64 {
+        (js_sys::Date::now() / 1_000.0) as u64
+    }
+}
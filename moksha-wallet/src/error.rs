@@ -1,6 +1,9 @@
 use std::string::FromUtf8Error;
 
 use lightning_invoice::ParseOrSemanticError;
+use moksha_core::primitives::{CashuErrorCode, CashuErrorResponse};
+
+use crate::localstore::ProofStatus;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -19,8 +22,9 @@ pub enum MokshaWalletError {
     #[cfg(not(target_arch = "wasm32"))]
     #[error("InvalidHeaderValueError - {0}")]
     InvalidHeaderValue(#[from] reqwest::header::InvalidHeaderValue),
-    #[error("{0}")]
-    MintError(String),
+    /// error of the mint without a specific variant, with the NUT-00 error code
+    #[error("{1}")]
+    MintError(u64, String),
 
     #[error("{1}")]
     InvoiceNotPaidYet(u64, String),
@@ -49,8 +53,8 @@ pub enum MokshaWalletError {
     #[error("Invalid Proofs")]
     InvalidProofs,
 
-    #[error("Not enough tokens")]
-    NotEnoughTokens,
+    #[error("Not enough tokens: need {needed}, have {available}")]
+    NotEnoughTokens { needed: u64, available: u64 },
 
     #[error("Failed to decode payment request {0} - Error {1}")]
     DecodeInvoice(String, ParseOrSemanticError),
@@ -58,9 +62,24 @@ pub enum MokshaWalletError {
     #[error("Invalid invoice {0}")]
     InvalidInvoice(String),
 
+    #[error("Invoice is expired {0}")]
+    InvoiceExpired(String),
+
     #[error("URLParseError - {0}")]
     Url(#[from] url::ParseError),
 
+    #[error("Invalid operation id {0}")]
+    InvalidOperationId(String),
+
+    #[error("Operation {0} not found")]
+    OperationNotFound(String),
+
+    #[error("Invalid mint url {0}: {1}")]
+    InvalidMintUrl(String, String),
+
+    #[error("Mint url {0} uses plain http on a public host, which exposes the proofs to a man in the middle")]
+    InsecureMintUrl(String),
+
     #[error("Unsupported version: Only mints with /v1 api are supported")]
     UnsupportedApiVersion,
 
@@ -76,9 +95,18 @@ pub enum MokshaWalletError {
     #[error("Primarykey not set for keyset")]
     IdNotSet,
 
+    #[error("Token is from mint {0}, which is not the mint of the keyset")]
+    UnsupportedMint(String),
+
+    #[error("Invalid secret: {0}")]
+    InvalidSecret(String),
+
     #[error("Found multiple seeds in the database. This is not supported.")]
     MultipleSeeds,
 
+    #[error("The seed words don't match the seed in the database")]
+    SeedMismatch,
+
     #[error("Not valid hex string")]
     Hex(#[from] hex::FromHexError),
 
@@ -87,4 +115,172 @@ pub enum MokshaWalletError {
 
     #[error("Pubkey not found")]
     PubkeyNotFound,
+
+    #[error("Invalid signature from the mint for output {index} with amount {amount}")]
+    InvalidSignature { amount: u64, index: usize },
+
+    #[error("Invalid DLEQ proof from the mint for output {index} with amount {amount}")]
+    InvalidDleq { amount: u64, index: usize },
+
+    #[error("Invalid change from the mint: {0}")]
+    InvalidChange(String),
+
+    #[error("Swap has {actual} outputs, but the amounts need {expected}")]
+    OutputCountMismatch { expected: usize, actual: usize },
+
+    #[error("Mint returned {got} signatures for {expected} outputs")]
+    MintResponseLengthMismatch { expected: usize, got: usize },
+
+    #[error("Mint did not return a state for proof {0}")]
+    MissingProofState(String),
+
+    #[error("Invalid token: {0}")]
+    InvalidToken(String),
+
+    #[error("Failed to decode token {0} - Error {1}")]
+    DecodeToken(String, moksha_core::error::MokshaCoreError),
+
+    #[error("No active keyset found for mint {0}")]
+    UnknownMint(String),
+
+    #[error("Queued receive not found {0}")]
+    QueuedReceiveNotFound(u64),
+
+    #[error("Lightning payment failed: {0}")]
+    PaymentFailed(String),
+
+    #[error("Multi payment not found {0}")]
+    MultiPaymentNotFound(u64),
+
+    #[error("Quote {0} is being minted by another process")]
+    MintInProgress(String),
+
+    #[error("Mint is unreachable, request to {0} timed out")]
+    Timeout(String),
+
+    #[error("Quote {0} was not paid before the timeout")]
+    MintTimeout(String),
+
+    #[error("The connection to the mint was closed while reading the response of {operation}, the mint may have executed the request")]
+    ResponseTruncated { operation: String },
+
+    #[error("Amount {amount} is below the minimum of {min} sats the mint pays")]
+    MeltAmountTooLow { amount: u64, min: u64 },
+
+    #[error("Amount {amount} is above the maximum of {max} sats the mint pays")]
+    MeltAmountTooHigh { amount: u64, max: u64 },
+
+    #[error("IoError - {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Replay of recorded session failed: {0}")]
+    Replay(String),
+
+    #[error("{0}")]
+    ProofAlreadyUsed(String),
+
+    #[error("{0}")]
+    InsufficientFee(String),
+
+    #[error("{0}")]
+    KeysetNotFound(String),
+
+    #[error("{count} proofs are not {from} and can't become {to}")]
+    InvalidProofTransition {
+        from: ProofStatus,
+        to: ProofStatus,
+        count: usize,
+    },
+
+    #[error("Mint {mint_url} has {balance} of the {needed} sats, the rest of the balance is at other mints")]
+    BalanceAtOtherMints {
+        mint_url: String,
+        balance: u64,
+        needed: u64,
+    },
+
+    #[error("Duplicate entry in localstore: {0}")]
+    DuplicateEntry(String),
+
+    #[error("Snapshot {0} not found")]
+    SnapshotNotFound(u64),
+
+    #[error("Stored value has version {0}, this wallet only reads up to version {1}")]
+    UnsupportedBlobVersion(u16, u16),
+
+    #[error("Not supported by the localstore: {0}")]
+    UnsupportedByLocalStore(&'static str),
+
+    #[error("Invalid {column} in localstore: {value}")]
+    InvalidStoredValue { column: &'static str, value: String },
+
+    #[error("Operation did not finish within {0:?}")]
+    OperationTimeout(std::time::Duration),
+
+    #[error("Invalid denominations: {0}")]
+    InvalidDenominations(String),
+
+    #[error("The localstore has version {store_version} and needs a newer wallet, this wallet supports version {supported}")]
+    StoreRequiresNewerVersion { store_version: u32, supported: u32 },
+
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+
+    #[error("The backup contains proofs of {0}, which is not a mint of this wallet. Import it with force to add the mint")]
+    BackupFromOtherMint(String),
+
+    #[error("Invalid lightning address {0}")]
+    InvalidLightningAddress(String),
+
+    #[error("LNURL error: {0}")]
+    Lnurl(String),
+
+    #[error("Invalid balance attestation: {0}")]
+    InvalidAttestation(String),
+}
+
+impl From<CashuErrorResponse> for MokshaWalletError {
+    fn from(mut response: CashuErrorResponse) -> Self {
+        let code = response.error_code();
+        if let (Some(code), true) = (code, response.error.trim().is_empty()) {
+            response.error = code.message().to_owned();
+        }
+        match code {
+            Some(CashuErrorCode::InvoiceNotPaid) => {
+                Self::InvoiceNotPaidYet(response.code, response.error)
+            }
+            Some(CashuErrorCode::ProofAlreadyUsed) => Self::ProofAlreadyUsed(response.error),
+            Some(CashuErrorCode::InsufficientFee) => Self::InsufficientFee(response.error),
+            Some(CashuErrorCode::KeysetNotFound) => Self::KeysetNotFound(response.error),
+            _ => Self::MintError(response.code, response.error),
+        }
+    }
+}
+
+impl MokshaWalletError {
+    /// Whether a request failed before it reached the mint, e.g. because the mint is offline
+    pub fn is_not_sent(&self) -> bool {
+        match self {
+            #[cfg(not(target_arch = "wasm32"))]
+            Self::Reqwest(e) => e.is_connect() || e.is_builder(),
+            _ => false,
+        }
+    }
+
+    /// Whether the mint may have executed the request although its response was lost, so the
+    /// outputs of the request have to be recovered from the mint (NUT-09)
+    pub fn is_funds_at_risk(&self) -> bool {
+        matches!(self, Self::ResponseTruncated { .. })
+    }
+
+    /// Whether the mint answered the request with an error, so it was not executed
+    pub fn is_rejected(&self) -> bool {
+        matches!(
+            self,
+            Self::MintError(..)
+                | Self::ProofAlreadyUsed(_)
+                | Self::InsufficientFee(_)
+                | Self::KeysetNotFound(_)
+        )
+    }
 }
@@ -0,0 +1,39 @@
+//! Progress of the wallet operations for user interfaces, see [`crate::wallet::Wallet::subscribe`].
+//! Every operation that is recorded in the localstore starts with
+//! [`WalletEvent::OperationStarted`] and ends with [`WalletEvent::OperationFinished`], also if it
+//! fails. The events in between depend on the kind of the operation.
+
+use crate::{localstore::OperationKind, operation::OperationId};
+
+/// Number of events a subscriber can fall behind. A slower subscriber misses the oldest events and
+/// gets [`tokio::sync::broadcast::error::RecvError::Lagged`] instead.
+pub const EVENT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WalletEvent {
+    OperationStarted {
+        id: OperationId,
+        kind: OperationKind,
+    },
+    /// the amount of an invoice and the fee reserve of its melt quote are known
+    FeeEstimated { amount: u64, fee_reserve: u64 },
+    /// proofs of the balance were selected as inputs of a payment
+    ProofsSelected { amount: u64, count: usize },
+    /// the mint swapped proofs of `amount` sats for new ones
+    SplitCompleted { amount: u64 },
+    /// the proofs of a payment were sent to the mint
+    MeltSubmitted { quote_id: String },
+    /// the outputs of a paid quote were sent to the mint
+    MintSubmitted { quote_id: String },
+    /// the balance of all mints differs from the balance before the operation
+    BalanceChanged { new_balance: u64 },
+    OperationFinished {
+        id: OperationId,
+        kind: OperationKind,
+        /// error of a failed operation
+        error: Option<String>,
+    },
+    /// paying the balance to the lightning address failed, it is retried at `retry_at`, see
+    /// [`crate::wallet::Wallet::auto_withdraw`]
+    AutoWithdrawFailed { error: String, retry_at: u64 },
+}
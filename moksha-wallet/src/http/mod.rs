@@ -1,13 +1,93 @@
 #[cfg(not(target_arch = "wasm32"))]
 pub mod reqwest;
 
+#[cfg(not(target_arch = "wasm32"))]
+pub mod session;
+
 #[cfg(target_arch = "wasm32")]
 pub mod wasm;
 
+use std::{sync::Arc, time::Duration};
+
+use moksha_core::primitives::CashuErrorResponse;
+use url::Url;
+
+use crate::{
+    error::MokshaWalletError,
+    stats::{EndpointStats, RequestTimer},
+};
+
+/// Timeouts, retries and proxy of a [`CrossPlatformHttpClient`]. Only used on native targets,
+/// the browser handles them for wasm.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpClientConfig {
+    pub connect_timeout: Duration,
+    /// timeout of a whole request. Paying an invoice waits for the lightning payment of the mint,
+    /// so this should not be too short.
+    pub request_timeout: Duration,
+    /// number of retries of GET requests after a timeout, connection error or a 502, 503 or 504
+    /// status. POST requests are never retried, since they may not be idempotent.
+    pub retries: u32,
+    /// delay before the first retry, doubles with every retry
+    pub retry_base_delay: Duration,
+    pub proxy: Option<Url>,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            request_timeout: Duration::from_secs(60),
+            retries: 3,
+            retry_base_delay: Duration::from_millis(200),
+            proxy: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct CrossPlatformHttpClient {
     #[cfg(not(target_arch = "wasm32"))]
     client: ::reqwest::Client,
+    #[cfg(not(target_arch = "wasm32"))]
+    config: HttpClientConfig,
+    #[cfg(not(target_arch = "wasm32"))]
+    session: Option<Arc<session::Session>>,
+    stats: Arc<EndpointStats>,
+}
+
+impl CrossPlatformHttpClient {
+    pub fn stats(&self) -> &EndpointStats {
+        &self.stats
+    }
+
+    fn record<T>(&self, url: &Url, timer: &RequestTimer, result: &Result<T, MokshaWalletError>) {
+        // an unpaid invoice is the expected answer while polling a quote
+        let success = matches!(
+            result,
+            Ok(_) | Err(MokshaWalletError::InvoiceNotPaidYet(_, _))
+        );
+        self.stats.record(url, timer.elapsed(), success);
+    }
+
+    /// Parses the body of a successful response, or the error the mint sent instead
+    fn parse_response<T: serde::de::DeserializeOwned>(
+        is_ok: bool,
+        response_text: String,
+    ) -> Result<T, MokshaWalletError> {
+        if is_ok {
+            if let Ok(data) = serde_json::from_str::<T>(&response_text) {
+                return Ok(data);
+            }
+        }
+        // e.g. the html error page of a reverse proxy
+        Err(
+            serde_json::from_str::<CashuErrorResponse>(&response_text).map_or(
+                MokshaWalletError::UnexpectedResponse(response_text),
+                Into::into,
+            ),
+        )
+    }
 }
 
 impl Default for CrossPlatformHttpClient {
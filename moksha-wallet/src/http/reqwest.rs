@@ -1,62 +1,143 @@
-use super::CrossPlatformHttpClient;
-use crate::error::MokshaWalletError;
+use std::{future::Future, path::Path, sync::Arc};
+
+use super::{
+    session::{Session, SessionRecorder, SessionReplay},
+    CrossPlatformHttpClient, HttpClientConfig,
+};
+use crate::{
+    error::MokshaWalletError,
+    operation::{OperationId, OPERATION_ID_HEADER},
+    stats::RequestTimer,
+};
 use reqwest::{
     header::{HeaderValue, CONTENT_TYPE},
-    Response, StatusCode,
+    Method, Proxy, RequestBuilder, Response, StatusCode,
 };
 use serde_json::Value;
 use url::Url;
 
 impl CrossPlatformHttpClient {
     pub fn new() -> Self {
-        Self {
-            client: reqwest::Client::new(),
+        Self::with_config(HttpClientConfig::default()).expect("invalid default http config")
+    }
+
+    pub fn with_config(config: HttpClientConfig) -> Result<Self, MokshaWalletError> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.request_timeout);
+        if let Some(proxy) = &config.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str())?);
         }
+        Ok(Self {
+            client: builder.build()?,
+            config,
+            session: None,
+            stats: Default::default(),
+        })
     }
 
-    async fn extract_response_data<T: serde::de::DeserializeOwned>(
-        response: Response,
+    /// Appends the requests of this client with their responses to the session file at `path`.
+    /// Secrets and tokens are redacted, see [`super::session`].
+    pub fn with_recorder(mut self, path: impl AsRef<Path>) -> Result<Self, MokshaWalletError> {
+        self.session = Some(Arc::new(Session::Record(SessionRecorder::create(path)?)));
+        Ok(self)
+    }
+
+    /// A client that answers all requests from the session recorded at `path`, without
+    /// connecting to the mint
+    pub fn replay(path: impl AsRef<Path>) -> Result<Self, MokshaWalletError> {
+        let mut client = Self::new();
+        client.session = Some(Arc::new(Session::Replay(SessionReplay::open(path)?)));
+        Ok(client)
+    }
+
+    /// A request that carries the id of the current operation, see [`OperationId::current`]
+    fn request(&self, method: Method, url: &Url) -> RequestBuilder {
+        let request = self.client.request(method, url.clone());
+        match OperationId::current() {
+            Some(id) => request.header(OPERATION_ID_HEADER, id.to_string()),
+            None => request,
+        }
+    }
+
+    /// Whether a GET request may succeed if it is sent again
+    fn is_transient(response: &Result<Response, reqwest::Error>) -> bool {
+        match response {
+            Ok(response) => matches!(
+                response.status(),
+                StatusCode::BAD_GATEWAY
+                    | StatusCode::SERVICE_UNAVAILABLE
+                    | StatusCode::GATEWAY_TIMEOUT
+            ),
+            Err(e) => e.is_timeout() || e.is_connect(),
+        }
+    }
+
+    fn map_timeout<T>(
+        url: &Url,
+        result: Result<T, MokshaWalletError>,
     ) -> Result<T, MokshaWalletError> {
-        match response.status() {
-            StatusCode::OK => {
-                let response_text = response.text().await?;
-                match serde_json::from_str::<T>(&response_text) {
-                    Ok(data) => Ok(data),
-                    Err(_) => {
-                        // FIXME cleanup code
-                        let data: Value = serde_json::from_str(&response_text)
-                            .map_err(|_| MokshaWalletError::UnexpectedResponse(response_text))
-                            .expect("invalid value");
-                        let detail = data["detail"].as_str().expect("detail not found");
-                        // let data = serde_json::from_str::<CashuErrorResponse>(&response_text)
-                        //     .map_err(|_| MokshaWalletError::UnexpectedResponse(response_text))
-                        //     .unwrap();
-
-                        // FIXME: use the error code to return a proper error
-                        match detail {
-                            "Lightning invoice not paid yet." => {
-                                Err(MokshaWalletError::InvoiceNotPaidYet(0, detail.to_owned()))
-                            }
-                            _ => Err(MokshaWalletError::MintError(detail.to_owned())),
-                        }
-                    }
-                }
+        match result {
+            Err(MokshaWalletError::Reqwest(e)) if e.is_timeout() => {
+                Err(MokshaWalletError::Timeout(url.to_string()))
             }
-            _ => {
-                let response_text = response.text().await?;
-                let data: Value = serde_json::from_str(&response_text)
-                    .map_err(|_| MokshaWalletError::UnexpectedResponse(response_text))
-                    .expect("invalid value");
-                let detail = data["detail"].as_str().expect("detail not found");
-
-                // FIXME: use the error code to return a proper error
-                match detail {
-                    "Lightning invoice not paid yet." => {
-                        Err(MokshaWalletError::InvoiceNotPaidYet(0, detail.to_owned()))
-                    }
-                    _ => Err(MokshaWalletError::MintError(detail.to_owned())),
-                }
+            result => result,
+        }
+    }
+
+    async fn read_response(
+        url: &Url,
+        response: Response,
+    ) -> Result<(StatusCode, String), MokshaWalletError> {
+        let status = response.status();
+        match response.text().await {
+            Ok(text) => Ok((status, text)),
+            // the mint answered, but the connection was closed before the whole body arrived,
+            // e.g. by a reverse proxy that limits the size of responses
+            Err(e) if !e.is_timeout() && Self::is_body_error(&e) => {
+                Err(MokshaWalletError::ResponseTruncated {
+                    operation: url.path().to_owned(),
+                })
             }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Whether reading the body failed. The decoder of the response reports the body errors as
+    /// decode errors, so the sources and the io errors they are wrapped in are searched too.
+    fn is_body_error(error: &(dyn std::error::Error + 'static)) -> bool {
+        if error
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(reqwest::Error::is_body)
+        {
+            return true;
+        }
+        if let Some(inner) = error
+            .downcast_ref::<std::io::Error>()
+            .and_then(std::io::Error::get_ref)
+        {
+            return Self::is_body_error(inner);
+        }
+        error.source().is_some_and(Self::is_body_error)
+    }
+
+    /// Sends a request, or answers it from the replayed session. Records the response if a
+    /// session is recorded.
+    async fn exchange(
+        &self,
+        method: &str,
+        url: &Url,
+        request: Option<&Value>,
+        send: impl Future<Output = Result<(StatusCode, String), MokshaWalletError>>,
+    ) -> Result<(StatusCode, String), MokshaWalletError> {
+        match self.session.as_deref() {
+            Some(Session::Replay(replay)) => replay.next(method, url, request),
+            Some(Session::Record(recorder)) => {
+                let result = send.await;
+                recorder.append(method, url, request, &result);
+                result
+            }
+            None => send.await,
         }
     }
 
@@ -64,8 +145,50 @@ impl CrossPlatformHttpClient {
         &self,
         url: &Url,
     ) -> Result<T, MokshaWalletError> {
-        let resp = self.client.get(url.clone()).send().await?;
-        Self::extract_response_data::<T>(resp).await
+        let timer = RequestTimer::start();
+        let result = async {
+            let (status, text) = self.get_with_retries(url).await?;
+            Self::parse_response::<T>(status == StatusCode::OK, text)
+        }
+        .await;
+        let result = Self::map_timeout(url, result);
+        self.record(url, &timer, &result);
+        result
+    }
+
+    /// Like [`Self::do_get`], but returns `None` if the mint doesn't serve the endpoint (404)
+    pub async fn do_get_optional<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &Url,
+    ) -> Result<Option<T>, MokshaWalletError> {
+        let timer = RequestTimer::start();
+        let result = async {
+            let (status, text) = self.get_with_retries(url).await?;
+            if status == StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            Self::parse_response::<T>(status == StatusCode::OK, text).map(Some)
+        }
+        .await;
+        let result = Self::map_timeout(url, result);
+        self.record(url, &timer, &result);
+        result
+    }
+
+    async fn get_with_retries(&self, url: &Url) -> Result<(StatusCode, String), MokshaWalletError> {
+        self.exchange("GET", url, None, async {
+            let mut attempt = 0;
+            let resp = loop {
+                let resp = self.request(Method::GET, url).send().await;
+                if attempt >= self.config.retries || !Self::is_transient(&resp) {
+                    break resp?;
+                }
+                tokio::time::sleep(self.config.retry_base_delay * 2u32.pow(attempt)).await;
+                attempt += 1;
+            };
+            Self::read_response(url, resp).await
+        })
+        .await
     }
 
     pub async fn do_post<T: serde::de::DeserializeOwned, B: serde::Serialize>(
@@ -73,18 +196,302 @@ impl CrossPlatformHttpClient {
         url: &Url,
         body: &B,
     ) -> Result<T, MokshaWalletError> {
-        let resp = self
-            .client
-            .post(url.clone())
-            .header(CONTENT_TYPE, HeaderValue::from_str("application/json")?)
-            .body(serde_json::to_string(body)?)
-            .send()
-            .await?;
-        Self::extract_response_data::<T>(resp).await
+        let timer = RequestTimer::start();
+        let result = async {
+            let body = serde_json::to_value(body)?;
+            let (status, text) = self
+                .exchange("POST", url, Some(&body), async {
+                    let resp = self
+                        .request(Method::POST, url)
+                        .header(CONTENT_TYPE, HeaderValue::from_str("application/json")?)
+                        .body(body.to_string())
+                        .send()
+                        .await?;
+                    Self::read_response(url, resp).await
+                })
+                .await?;
+            Self::parse_response::<T>(status == StatusCode::OK, text)
+        }
+        .await;
+        let result = Self::map_timeout(url, result);
+        self.record(url, &timer, &result);
+        result
     }
 
     pub async fn get_status(&self, url: &Url) -> Result<u16, MokshaWalletError> {
-        let resp = self.client.get(url.to_owned()).send().await?;
-        Ok(resp.status().as_u16())
+        let (status, _) = self
+            .exchange("GET", url, None, async {
+                let resp = self.request(Method::GET, url).send().await?;
+                Self::read_response(url, resp).await
+            })
+            .await?;
+        Ok(status.as_u16())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use moksha_core::primitives::{CashuErrorCode, CashuErrorResponse, KeysResponse};
+    use serde_json::Value;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use url::Url;
+
+    use crate::{
+        error::MokshaWalletError,
+        http::{CrossPlatformHttpClient, HttpClientConfig},
+        operation::OperationId,
+    };
+
+    /// Starts a mock mint that sends the responses in order, repeating the last one. Returns the
+    /// url and the number of received requests.
+    async fn serve(
+        responses: Vec<(Duration, u16, &'static str)>,
+    ) -> anyhow::Result<(Url, Arc<AtomicUsize>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let index = counter.fetch_add(1, Ordering::SeqCst);
+                let (delay, status, body) = responses[index.min(responses.len() - 1)];
+                tokio::spawn(async move {
+                    let mut buf = [0; 4096];
+                    let _ = stream.read(&mut buf).await;
+                    tokio::time::sleep(delay).await;
+                    let response = format!(
+                        "HTTP/1.1 {status} Status\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = stream.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+        Ok((url, hits))
+    }
+
+    fn client(retries: u32) -> anyhow::Result<CrossPlatformHttpClient> {
+        Ok(CrossPlatformHttpClient::with_config(HttpClientConfig {
+            request_timeout: Duration::from_millis(200),
+            retries,
+            retry_base_delay: Duration::from_millis(10),
+            ..Default::default()
+        })?)
+    }
+
+    #[tokio::test]
+    async fn test_get_retries_bad_gateway() -> anyhow::Result<()> {
+        let (url, hits) = serve(vec![
+            (Duration::ZERO, 502, "<html>Bad Gateway</html>"),
+            (Duration::ZERO, 200, r#"{"keysets":[]}"#),
+        ])
+        .await?;
+
+        let result = client(3)?.do_get::<Value>(&url.join("v1/keysets")?).await?;
+        assert_eq!(serde_json::json!({"keysets": []}), result);
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_gives_up_after_retries() -> anyhow::Result<()> {
+        let (url, hits) = serve(vec![(Duration::ZERO, 503, "unavailable")]).await?;
+
+        let result = client(2)?.do_get::<Value>(&url.join("v1/keys")?).await;
+        assert!(
+            matches!(result, Err(MokshaWalletError::UnexpectedResponse(body)) if body == "unavailable")
+        );
+        assert_eq!(3, hits.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_optional_not_found() -> anyhow::Result<()> {
+        let (url, hits) = serve(vec![(Duration::ZERO, 404, "Not Found")]).await?;
+
+        let result = client(3)?
+            .do_get_optional::<Value>(&url.join("v1/info")?)
+            .await?;
+        assert_eq!(None, result);
+        assert_eq!(1, hits.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_is_not_retried() -> anyhow::Result<()> {
+        let (url, hits) = serve(vec![
+            (Duration::ZERO, 502, "<html>Bad Gateway</html>"),
+            (Duration::ZERO, 200, "{}"),
+        ])
+        .await?;
+
+        let result = client(3)?
+            .do_post::<Value, _>(&url.join("v1/melt/bolt11")?, &serde_json::json!({}))
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::UnexpectedResponse(_))
+        ));
+        assert_eq!(1, hits.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_timeout() -> anyhow::Result<()> {
+        let (url, hits) = serve(vec![(Duration::from_secs(5), 200, "{}")]).await?;
+        let keys_url = url.join("v1/keys")?;
+
+        let result = client(1)?.do_get::<Value>(&keys_url).await;
+        assert!(
+            matches!(result, Err(MokshaWalletError::Timeout(ref u)) if *u == keys_url.to_string())
+        );
+        assert_eq!(2, hits.load(Ordering::SeqCst));
+
+        let result = client(1)?
+            .do_post::<Value, _>(&url.join("v1/swap")?, &serde_json::json!({}))
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::Timeout(_))));
+        assert_eq!(3, hits.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_response_truncated() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf).await;
+                let response = "HTTP/1.1 200 OK\r\ncontent-length: 100\r\nconnection: close\r\n\r\n{\"signatures\":[";
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        let result = client(0)?
+            .do_post::<Value, _>(&url.join("v1/swap")?, &serde_json::json!({}))
+            .await;
+        assert!(
+            matches!(result, Err(MokshaWalletError::ResponseTruncated { ref operation }) if operation == "/v1/swap")
+        );
+        assert!(result.err().is_some_and(|e| e.is_funds_at_risk()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_operation_id_header() -> anyhow::Result<()> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let (sender, mut requests) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0; 4096];
+                let read = stream.read(&mut buf).await.unwrap_or_default();
+                let _ = sender.send(String::from_utf8_lossy(&buf[..read]).to_lowercase());
+                let response =
+                    "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}";
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        let client = client(0)?;
+        let id = OperationId::from_parts(1_000, 1);
+
+        client.do_get::<Value>(&url.join("v1/keys")?).await?;
+        let request = requests.recv().await.expect("no request");
+        assert!(!request.contains("x-operation-id"));
+
+        id.clone()
+            .scope(client.do_post::<Value, _>(&url.join("v1/swap")?, &serde_json::json!({})))
+            .await?;
+        let request = requests.recv().await.expect("no request");
+        assert!(request.contains(&format!(
+            "x-operation-id: {}",
+            id.to_string().to_lowercase()
+        )));
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_codes() {
+        for code in CashuErrorCode::ALL {
+            let body = serde_json::to_string(&CashuErrorResponse::new(Some(code), "failed"))
+                .expect("invalid error response");
+            let result = CrossPlatformHttpClient::parse_response::<Value>(false, body);
+            let Err(err) = result else {
+                panic!("no error for code {code:?}");
+            };
+            let expected = match code {
+                CashuErrorCode::InvoiceNotPaid => {
+                    matches!(err, MokshaWalletError::InvoiceNotPaidYet(20001, _))
+                }
+                CashuErrorCode::ProofAlreadyUsed => {
+                    matches!(err, MokshaWalletError::ProofAlreadyUsed(_))
+                }
+                CashuErrorCode::InsufficientFee => {
+                    matches!(err, MokshaWalletError::InsufficientFee(_))
+                }
+                CashuErrorCode::KeysetNotFound => {
+                    matches!(err, MokshaWalletError::KeysetNotFound(_))
+                }
+                _ => matches!(err, MokshaWalletError::MintError(c, _) if c == code.code()),
+            };
+            assert!(expected, "unexpected error {err:?} for code {code:?}");
+            assert_eq!("failed", err.to_string());
+        }
+
+        // a known code without a message gets the message of the spec
+        let result = CrossPlatformHttpClient::parse_response::<Value>(
+            false,
+            r#"{"code":11001,"error":""}"#.to_owned(),
+        );
+        assert!(
+            matches!(result, Err(MokshaWalletError::ProofAlreadyUsed(ref msg)) if msg == "Token is already spent")
+        );
+
+        // unknown codes of newer mints and the `detail` of older mints
+        let result = CrossPlatformHttpClient::parse_response::<Value>(
+            false,
+            r#"{"code":31337,"detail":"new error"}"#.to_owned(),
+        );
+        assert!(
+            matches!(result, Err(MokshaWalletError::MintError(31337, ref msg)) if msg == "new error")
+        );
+    }
+
+    #[test]
+    fn test_malformed_error_body() {
+        for body in [
+            "<html>Bad Gateway</html>",
+            "",
+            r#"{"code":"x"}"#,
+            r#"{"foo":1}"#,
+        ] {
+            let result = CrossPlatformHttpClient::parse_response::<Value>(false, body.to_owned());
+            assert!(matches!(
+                result,
+                Err(MokshaWalletError::UnexpectedResponse(ref text)) if text == body
+            ));
+        }
+
+        // a successful status with a body that is neither the data nor an error
+        let result = CrossPlatformHttpClient::parse_response::<KeysResponse>(
+            true,
+            r#"{"foo":1}"#.to_owned(),
+        );
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::UnexpectedResponse(_))
+        ));
     }
 }
@@ -0,0 +1,240 @@
+//! Recording and replay of the requests of a [`CrossPlatformHttpClient`].
+//!
+//! A session file contains one [`SessionEntry`] per line. Secrets of proofs, witnesses,
+//! preimages and serialized tokens are replaced by their sha256 digest before they are written,
+//! so a session can be shared to debug a failed payment. Blinded messages and signatures are
+//! kept, they are needed to re-execute the wallet code and are useless without the blinding
+//! factors, which never leave the wallet.
+//!
+//! A replayed session answers the requests with the recorded responses and fails with
+//! [`MokshaWalletError::Replay`] as soon as the wallet sends a different request than the
+//! recorded one.
+
+use std::{
+    collections::VecDeque,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+use bitcoin_hashes::{sha256, Hash};
+use moksha_core::token::{TOKEN_PREFIX_V3, TOKEN_PREFIX_V4};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use crate::error::MokshaWalletError;
+
+/// Fields whose values are replaced by a digest
+const SECRET_FIELDS: &[&str] = &["secret", "C", "witness", "payment_preimage"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionEntry {
+    pub method: String,
+    /// path of the url, the mint url itself is not recorded
+    pub path: String,
+    /// redacted json body of a POST request
+    pub request: Option<Value>,
+    /// status code of the response, 0 if the request failed without a response
+    pub status: u16,
+    /// redacted body of the response, or the error if the request failed
+    pub response: String,
+}
+
+impl SessionEntry {
+    fn new(
+        method: &str,
+        url: &Url,
+        request: Option<&Value>,
+        result: &Result<(StatusCode, String), MokshaWalletError>,
+    ) -> Self {
+        let (status, response) = match result {
+            Ok((status, body)) => (
+                status.as_u16(),
+                serde_json::from_str::<Value>(body)
+                    .map_or_else(|_| body.to_owned(), |body| redact(body).to_string()),
+            ),
+            Err(e) => (0, e.to_string()),
+        };
+        Self {
+            method: method.to_owned(),
+            path: url.path().to_owned(),
+            request: request.cloned().map(redact),
+            status,
+            response,
+        }
+    }
+}
+
+/// Replaces the secrets in a json value by their digests
+pub fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| match SECRET_FIELDS.contains(&key.as_str()) {
+                    true => (key, digest(&value)),
+                    false => (key, redact(value)),
+                })
+                .collect(),
+        ),
+        Value::Array(values) => Value::Array(values.into_iter().map(redact).collect()),
+        Value::String(text)
+            if text.starts_with(TOKEN_PREFIX_V3) || text.starts_with(TOKEN_PREFIX_V4) =>
+        {
+            digest(&Value::String(text))
+        }
+        value => value,
+    }
+}
+
+fn digest(value: &Value) -> Value {
+    match value {
+        Value::Null => Value::Null,
+        value => Value::String(format!(
+            "sha256:{}",
+            sha256::Hash::hash(value.to_string().as_bytes())
+        )),
+    }
+}
+
+/// Appends the requests of a client to a session file
+#[derive(Debug)]
+pub struct SessionRecorder {
+    file: Mutex<File>,
+}
+
+impl SessionRecorder {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, MokshaWalletError> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Writing the session is best effort: a request the mint has executed must not fail because
+    /// the session file could not be written.
+    pub(crate) fn append(
+        &self,
+        method: &str,
+        url: &Url,
+        request: Option<&Value>,
+        result: &Result<(StatusCode, String), MokshaWalletError>,
+    ) {
+        let entry = SessionEntry::new(method, url, request, result);
+        if let Ok(line) = serde_json::to_string(&entry) {
+            let mut file = self.file.lock().expect("session file lock is poisoned");
+            let _ = writeln!(file, "{line}");
+        }
+    }
+}
+
+/// Answers requests with the responses of a recorded session
+#[derive(Debug)]
+pub struct SessionReplay {
+    entries: Mutex<VecDeque<SessionEntry>>,
+    recorded: usize,
+}
+
+impl SessionReplay {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, MokshaWalletError> {
+        let entries = BufReader::new(File::open(path)?)
+            .lines()
+            .map(|line| Ok(serde_json::from_str::<SessionEntry>(&line?)?))
+            .collect::<Result<VecDeque<_>, MokshaWalletError>>()?;
+        Ok(Self {
+            recorded: entries.len(),
+            entries: Mutex::new(entries),
+        })
+    }
+
+    /// Number of recorded requests that have not been replayed yet
+    pub fn remaining(&self) -> usize {
+        self.entries
+            .lock()
+            .expect("session replay lock is poisoned")
+            .len()
+    }
+
+    pub(crate) fn next(
+        &self,
+        method: &str,
+        url: &Url,
+        request: Option<&Value>,
+    ) -> Result<(StatusCode, String), MokshaWalletError> {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("session replay lock is poisoned");
+        let index = self.recorded - entries.len();
+        let actual = SessionEntry::new(method, url, request, &Ok((StatusCode::OK, String::new())));
+        let expected = entries.pop_front().ok_or_else(|| {
+            MokshaWalletError::Replay(format!(
+                "request {index} {} {} was not recorded",
+                actual.method, actual.path
+            ))
+        })?;
+
+        if (&expected.method, &expected.path, &expected.request)
+            != (&actual.method, &actual.path, &actual.request)
+        {
+            return Err(MokshaWalletError::Replay(format!(
+                "request {index} differs: recorded {} {} {:?}, sent {} {} {:?}",
+                expected.method,
+                expected.path,
+                expected.request,
+                actual.method,
+                actual.path,
+                actual.request
+            )));
+        }
+        match StatusCode::from_u16(expected.status) {
+            Ok(status) => Ok((status, expected.response)),
+            Err(_) => Err(MokshaWalletError::Replay(format!(
+                "request {index} failed: {}",
+                expected.response
+            ))),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Session {
+    Record(SessionRecorder),
+    Replay(SessionReplay),
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::redact;
+
+    #[test]
+    fn test_redact() {
+        let redacted = redact(json!({
+            "inputs": [{"amount": 2, "id": "00ad", "secret": "s3cr3t", "C": "02ab"}],
+            "outputs": [{"amount": 2, "B_": "03cd"}],
+            "token": "cashuAeyJ0b2tlbiI6W119",
+            "witness": null,
+        }));
+
+        let text = redacted.to_string();
+        assert!(!text.contains("s3cr3t"));
+        assert!(!text.contains("02ab"));
+        assert!(!text.contains("cashuA"));
+        assert!(redacted["inputs"][0]["secret"]
+            .as_str()
+            .is_some_and(|digest| digest.starts_with("sha256:")));
+        assert_eq!(json!([{"amount": 2, "B_": "03cd"}]), redacted["outputs"]);
+        assert_eq!(2, redacted["inputs"][0]["amount"]);
+        assert!(redacted["witness"].is_null());
+        // the same secret always has the same digest
+        assert_eq!(
+            redacted["inputs"][0]["secret"],
+            redact(json!({"secret": "s3cr3t"}))["secret"]
+        );
+    }
+}
@@ -1,22 +1,58 @@
-use moksha_core::primitives::CashuErrorResponse;
-
-use crate::error::MokshaWalletError;
+use crate::{
+    error::MokshaWalletError,
+    operation::{OperationId, OPERATION_ID_HEADER},
+    stats::RequestTimer,
+};
 use url::Url;
 
 use super::CrossPlatformHttpClient;
-use gloo_net::http::{Request, Response};
+use gloo_net::http::{Request, RequestBuilder, Response};
+
+/// Adds the id of the current operation to a request, see [`OperationId::current`]
+fn with_operation_id(request: RequestBuilder) -> RequestBuilder {
+    match OperationId::current() {
+        Some(id) => request.header(OPERATION_ID_HEADER, &id.to_string()),
+        None => request,
+    }
+}
 
 impl CrossPlatformHttpClient {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            stats: Default::default(),
+        }
     }
 
     pub async fn do_get<T: serde::de::DeserializeOwned>(
         &self,
         url: &Url,
     ) -> Result<T, MokshaWalletError> {
-        let resp = Request::get(url.as_str()).send().await?;
-        Self::extract_response_data::<T>(resp).await
+        let timer = RequestTimer::start();
+        let result = async {
+            let resp = with_operation_id(Request::get(url.as_str())).send().await?;
+            Self::extract_response_data::<T>(resp).await
+        }
+        .await;
+        self.record(url, &timer, &result);
+        result
+    }
+
+    /// Like [`Self::do_get`], but returns `None` if the mint doesn't serve the endpoint (404)
+    pub async fn do_get_optional<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &Url,
+    ) -> Result<Option<T>, MokshaWalletError> {
+        let timer = RequestTimer::start();
+        let result = async {
+            let resp = with_operation_id(Request::get(url.as_str())).send().await?;
+            if resp.status() == 404 {
+                return Ok(None);
+            }
+            Self::extract_response_data::<T>(resp).await.map(Some)
+        }
+        .await;
+        self.record(url, &timer, &result);
+        result
     }
 
     pub async fn do_post<T: serde::de::DeserializeOwned, B: serde::Serialize>(
@@ -24,16 +60,22 @@ impl CrossPlatformHttpClient {
         url: &Url,
         body: &B,
     ) -> Result<T, MokshaWalletError> {
-        let resp = Request::post(url.as_str())
-            .header("content-type", "application/json")
-            .json(body)?
-            .send()
-            .await?;
-        Self::extract_response_data::<T>(resp).await
+        let timer = RequestTimer::start();
+        let result = async {
+            let resp = with_operation_id(Request::post(url.as_str()))
+                .header("content-type", "application/json")
+                .json(body)?
+                .send()
+                .await?;
+            Self::extract_response_data::<T>(resp).await
+        }
+        .await;
+        self.record(url, &timer, &result);
+        result
     }
 
     pub async fn get_status(&self, url: &Url) -> Result<u16, MokshaWalletError> {
-        let resp = Request::get(url.as_str()).send().await?;
+        let resp = with_operation_id(Request::get(url.as_str())).send().await?;
 
         Ok(resp.status())
     }
@@ -41,40 +83,14 @@ impl CrossPlatformHttpClient {
     async fn extract_response_data<T: serde::de::DeserializeOwned>(
         response: Response,
     ) -> Result<T, MokshaWalletError> {
-        match response.status() {
-            200 => {
-                let response_text = response.text().await.unwrap(); // FIXME handle error
-                match serde_json::from_str::<T>(&response_text) {
-                    Ok(data) => Ok(data),
-                    Err(_) => {
-                        let data = serde_json::from_str::<CashuErrorResponse>(&response_text)
-                            .map_err(|_| MokshaWalletError::UnexpectedResponse(response_text))
-                            .unwrap();
-
-                        // FIXME: use the error code to return a proper error
-                        match data.detail.as_str() {
-                            "Lightning invoice not paid yet." => {
-                                Err(MokshaWalletError::InvoiceNotPaidYet(data.code, data.detail))
-                            }
-                            _ => Err(MokshaWalletError::MintError(data.detail)),
-                        }
-                    }
-                }
-            }
-            _ => {
-                let txt = response.text().await.unwrap(); // FIXME handle error
-                let data = serde_json::from_str::<CashuErrorResponse>(&txt)
-                    .map_err(|_| MokshaWalletError::UnexpectedResponse(txt))
-                    .unwrap();
-
-                // FIXME: use the error code to return a proper error
-                match data.detail.as_str() {
-                    "Lightning invoice not paid yet." => {
-                        Err(MokshaWalletError::InvoiceNotPaidYet(data.code, data.detail))
-                    }
-                    _ => Err(MokshaWalletError::MintError(data.detail)),
-                }
+        let is_ok = response.status() == 200;
+        // the mint answered, but the connection was closed before the whole body arrived
+        let response_text = response.text().await.map_err(|_| {
+            let url = response.url();
+            MokshaWalletError::ResponseTruncated {
+                operation: Url::parse(&url).map_or(url, |url| url.path().to_owned()),
             }
-        }
+        })?;
+        Self::parse_response::<T>(is_ok, response_text)
     }
 }
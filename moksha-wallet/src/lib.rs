@@ -1,7 +1,14 @@
+pub mod attestation;
+pub mod backup;
 pub mod client;
+pub mod clock;
 pub mod config_path;
 pub mod error;
+pub mod event;
 pub mod http;
+pub mod lnurl;
 pub mod localstore;
+pub mod operation;
 pub mod secret;
+pub mod stats;
 pub mod wallet;
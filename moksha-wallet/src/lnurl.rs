@@ -0,0 +1,106 @@
+//! Lightning addresses (LUD-16) and the LNURL-pay requests (LUD-06) that turn them into invoices,
+//! see [`crate::wallet::AutoWithdrawConfig`]
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::error::MokshaWalletError;
+
+/// `user@domain`, the invoices are requested from `https://domain/.well-known/lnurlp/user`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightningAddress {
+    user: String,
+    domain: String,
+}
+
+impl LightningAddress {
+    /// Url of the LNURL-pay request of the address
+    pub fn lnurlp_url(&self) -> Result<Url, MokshaWalletError> {
+        Ok(Url::parse(&format!(
+            "https://{}/.well-known/lnurlp/{}",
+            self.domain, self.user
+        ))?)
+    }
+}
+
+impl FromStr for LightningAddress {
+    type Err = MokshaWalletError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MokshaWalletError::InvalidLightningAddress(s.to_owned());
+        let (user, domain) = s.split_once('@').ok_or_else(invalid)?;
+        let valid_user = !user.is_empty()
+            && user
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.+".contains(c));
+        if !valid_user || domain.is_empty() || domain.contains(['/', '@', '?', '#']) {
+            return Err(invalid());
+        }
+        Url::parse(&format!("https://{domain}/")).map_err(|_| invalid())?;
+        Ok(Self {
+            user: user.to_owned(),
+            domain: domain.to_owned(),
+        })
+    }
+}
+
+impl Display for LightningAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.user, self.domain)
+    }
+}
+
+/// Response of the LNURL-pay request of a lightning address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LnurlPayResponse {
+    /// the invoices are requested from this url with the amount in msats
+    pub callback: Url,
+    /// msats
+    pub min_sendable: u64,
+    /// msats
+    pub max_sendable: u64,
+    /// always `payRequest`
+    pub tag: String,
+}
+
+/// Response of the callback of a [`LnurlPayResponse`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LnurlInvoiceResponse {
+    /// the bolt11 invoice
+    pub pr: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LightningAddress;
+    use crate::error::MokshaWalletError;
+
+    #[test]
+    fn test_lightning_address() -> anyhow::Result<()> {
+        let address: LightningAddress = "merchant@pay.example.com".parse()?;
+        assert_eq!("merchant@pay.example.com", address.to_string());
+        assert_eq!(
+            "https://pay.example.com/.well-known/lnurlp/merchant",
+            address.lnurlp_url()?.as_str()
+        );
+
+        for invalid in [
+            "",
+            "merchant",
+            "@pay.example.com",
+            "merchant@",
+            "Merchant@pay.example.com",
+            "merchant@pay.example.com/path",
+            "mer@chant@pay.example.com",
+        ] {
+            assert!(matches!(
+                invalid.parse::<LightningAddress>(),
+                Err(MokshaWalletError::InvalidLightningAddress(_))
+            ));
+        }
+        Ok(())
+    }
+}
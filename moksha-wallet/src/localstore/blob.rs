@@ -0,0 +1,159 @@
+//! Versioned encoding of the values the localstore keeps as json in a single column, e.g. the
+//! proofs of a pending melt. Every value is stored as `{"v": <version>, "data": <value>}`, so a
+//! change of a model only needs a new upgrade function instead of breaking existing stores.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::MokshaWalletError;
+
+/// version of the values written by this wallet
+pub const BLOB_VERSION: u16 = 1;
+
+type Upgrade = fn(Value) -> Result<Value, MokshaWalletError>;
+
+/// `UPGRADES[i]` converts the data of version `i` to version `i + 1`
+const UPGRADES: [Upgrade; BLOB_VERSION as usize] = [upgrade_unversioned];
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    v: u16,
+    data: T,
+}
+
+pub fn encode<T: Serialize>(value: &T) -> Result<String, MokshaWalletError> {
+    Ok(serde_json::to_string(&Envelope {
+        v: BLOB_VERSION,
+        data: value,
+    })?)
+}
+
+/// Decodes a stored value of any supported version
+pub fn decode<T: DeserializeOwned>(blob: &str) -> Result<T, MokshaWalletError> {
+    let (version, data) = parse(blob)?;
+    Ok(serde_json::from_value(upgrade_data(version, data)?)?)
+}
+
+/// Returns the value in the current version or `None` if it already has the current version
+pub fn upgrade(blob: &str) -> Result<Option<String>, MokshaWalletError> {
+    let (version, data) = parse(blob)?;
+    if version == BLOB_VERSION {
+        return Ok(None);
+    }
+    encode(&upgrade_data(version, data)?).map(Some)
+}
+
+fn parse(blob: &str) -> Result<(u16, Value), MokshaWalletError> {
+    let value: Value = serde_json::from_str(blob)?;
+    match value {
+        Value::Object(ref fields)
+            if fields.len() == 2 && fields.contains_key("v") && fields.contains_key("data") =>
+        {
+            let envelope: Envelope<Value> = serde_json::from_value(value)?;
+            Ok((envelope.v, envelope.data))
+        }
+        // written before values were versioned
+        value => Ok((0, value)),
+    }
+}
+
+fn upgrade_data(version: u16, mut data: Value) -> Result<Value, MokshaWalletError> {
+    if version > BLOB_VERSION {
+        return Err(MokshaWalletError::UnsupportedBlobVersion(
+            version,
+            BLOB_VERSION,
+        ));
+    }
+    for upgrade in &UPGRADES[version as usize..] {
+        data = upgrade(data)?;
+    }
+    Ok(data)
+}
+
+/// version 0 is the plain json of the models, version 1 only adds the envelope
+fn upgrade_unversioned(data: Value) -> Result<Value, MokshaWalletError> {
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use moksha_core::{fixture::read_fixture, proof::Proofs, token::TokenV3};
+
+    use crate::{error::MokshaWalletError, localstore::PendingMintOutput};
+
+    fn proofs() -> anyhow::Result<Proofs> {
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        Ok(tokens.proofs())
+    }
+
+    fn outputs() -> anyhow::Result<Vec<PendingMintOutput>> {
+        Ok(proofs()?
+            .proofs()
+            .into_iter()
+            .map(|proof| PendingMintOutput {
+                blinded_message: moksha_core::blind::BlindedMessage {
+                    amount: proof.amount,
+                    b_: proof.c,
+                    id: proof.keyset_id,
+                },
+                secret: proof.secret,
+                blinding_factor: "00".repeat(32),
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_roundtrip_current_version() -> anyhow::Result<()> {
+        let proofs = proofs()?;
+        for value in [Proofs::empty(), proofs.clone()] {
+            assert_eq!(value, super::decode::<Proofs>(&super::encode(&value)?)?);
+        }
+        for value in [None, Some(proofs)] {
+            assert_eq!(
+                value,
+                super::decode::<Option<Proofs>>(&super::encode(&value)?)?
+            );
+        }
+
+        let outputs = outputs()?;
+        let decoded: Vec<PendingMintOutput> = super::decode(&super::encode(&outputs)?)?;
+        assert_eq!(
+            serde_json::to_value(&outputs)?,
+            serde_json::to_value(decoded)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_envelope() -> anyhow::Result<()> {
+        let blob: serde_json::Value = serde_json::from_str(&super::encode(&proofs()?)?)?;
+        assert_eq!(super::BLOB_VERSION as u64, blob["v"]);
+        assert_eq!(serde_json::to_value(proofs()?)?, blob["data"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_unversioned() -> anyhow::Result<()> {
+        let proofs = proofs()?;
+        let legacy = serde_json::to_string(&proofs)?;
+        assert_eq!(proofs, super::decode::<Proofs>(&legacy)?);
+
+        let upgraded = super::upgrade(&legacy)?.expect("value is not upgraded");
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&super::encode(&proofs)?)?,
+            serde_json::from_str::<serde_json::Value>(&upgraded)?
+        );
+        assert_eq!(None, super::upgrade(&upgraded)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode_newer_version() -> anyhow::Result<()> {
+        let result = super::decode::<Proofs>(r#"{"v":2,"data":[]}"#);
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::UnsupportedBlobVersion(2, 1))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,731 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use moksha_core::{
+    page::PageCursor,
+    proof::{Proof, Proofs},
+};
+use tokio::sync::{OwnedRwLockWriteGuard, RwLock};
+use url::Url;
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::MokshaWalletError;
+use crate::localstore::{
+    BlindRecovery, HistoryEntry, HistoryFilter, HistoryPage, LocalStore, LocalStoreTransaction,
+    MultiPayment, Operation, OperationRecord, PendingMelt, PendingMint, ProofStatus,
+    ProofWithMetadata, QueuedReceive, ReservedProof, Snapshot, WalletKeyset,
+};
+use crate::operation::OperationId;
+use crate::stats::EndpointSamples;
+
+/// A [`LocalStore`] that keeps everything in memory, e.g. for tests or a wallet in the browser
+/// that doesn't need to persist its proofs. Clones share the same data.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryLocalStore {
+    state: Arc<RwLock<MemoryState>>,
+}
+
+impl MemoryLocalStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct MemoryState {
+    proofs: Vec<StoredProof>,
+    keysets: Vec<WalletKeyset>,
+    last_keyset_id: u64,
+    seed: Option<String>,
+    /// samples by mint url and endpoint
+    endpoint_stats: BTreeMap<(String, String), EndpointSamples>,
+    receive_queue: BTreeMap<u64, QueuedReceive>,
+    last_receive_id: u64,
+    blind_recovery: Vec<BlindRecovery>,
+    pending_mints: Vec<PendingMint>,
+    multi_payments: BTreeMap<u64, MultiPayment>,
+    last_multi_payment_id: u64,
+    pending_melts: BTreeMap<u64, PendingMelt>,
+    last_pending_melt_id: u64,
+    snapshots: BTreeMap<u64, Snapshot>,
+    last_snapshot_id: u64,
+    operations: BTreeMap<OperationId, Operation>,
+    /// links in the order they were added
+    operation_records: Vec<(OperationId, OperationRecord)>,
+    /// entries are never deleted, so the id is the position + 1
+    history: Vec<HistoryEntry>,
+}
+
+#[derive(Clone, Debug)]
+struct StoredProof {
+    proof: Proof,
+    state: ProofStatus,
+    reserved_at: Option<u64>,
+    created_at: u64,
+}
+
+/// Holds the store exclusively until it is committed or dropped, like a write transaction of
+/// sqlite. A dropped transaction restores the state from its begin.
+pub struct MemoryTransaction {
+    state: OwnedRwLockWriteGuard<MemoryState>,
+    rollback: Option<MemoryState>,
+}
+
+#[async_trait(?Send)]
+impl LocalStoreTransaction for MemoryTransaction {
+    async fn commit(mut self) -> Result<(), MokshaWalletError> {
+        self.rollback = None;
+        Ok(())
+    }
+}
+
+impl Drop for MemoryTransaction {
+    fn drop(&mut self) {
+        if let Some(rollback) = self.rollback.take() {
+            *self.state = rollback;
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl LocalStore for MemoryLocalStore {
+    type Tx = MemoryTransaction;
+
+    async fn begin_tx(&self) -> Result<Self::Tx, MokshaWalletError> {
+        let state = self.state.clone().write_owned().await;
+        let rollback = Some(state.clone());
+        Ok(MemoryTransaction { state, rollback })
+    }
+
+    async fn delete_proofs(
+        &self,
+        tx: &mut Self::Tx,
+        proofs: &Proofs,
+    ) -> Result<(), MokshaWalletError> {
+        let proofs = proofs
+            .proofs()
+            .into_iter()
+            .map(|proof| (proof.secret, proof.amount))
+            .collect::<HashSet<_>>();
+        tx.state
+            .proofs
+            .retain(|stored| !proofs.contains(&(stored.proof.secret.clone(), stored.proof.amount)));
+        Ok(())
+    }
+
+    async fn delete_proofs_by_secrets(
+        &self,
+        tx: &mut Self::Tx,
+        secrets: &[String],
+    ) -> Result<(), MokshaWalletError> {
+        let secrets = secrets.iter().collect::<HashSet<_>>();
+        tx.state
+            .proofs
+            .retain(|stored| !secrets.contains(&stored.proof.secret));
+        Ok(())
+    }
+
+    async fn add_proofs(
+        &self,
+        tx: &mut Self::Tx,
+        proofs: &Proofs,
+    ) -> Result<(), MokshaWalletError> {
+        let created_at = SystemClock.now();
+        for proof in proofs.proofs() {
+            if tx
+                .state
+                .proofs
+                .iter()
+                .any(|stored| stored.proof.secret == proof.secret)
+            {
+                return Err(MokshaWalletError::DuplicateEntry(proof.secret));
+            }
+            tx.state.proofs.push(StoredProof {
+                // the script is not stored, like in the other localstores
+                proof: Proof {
+                    script: None,
+                    ..proof
+                },
+                state: ProofStatus::Spendable,
+                reserved_at: None,
+                created_at,
+            });
+        }
+        Ok(())
+    }
+
+    async fn get_proofs(&self, tx: &mut Self::Tx) -> Result<Proofs, MokshaWalletError> {
+        self.get_proofs_by_state(tx, ProofStatus::Spendable).await
+    }
+
+    async fn get_proofs_by_state(
+        &self,
+        tx: &mut Self::Tx,
+        state: ProofStatus,
+    ) -> Result<Proofs, MokshaWalletError> {
+        Ok(tx
+            .state
+            .proofs
+            .iter()
+            .filter(|stored| stored.state == state)
+            .map(|stored| stored.proof.clone())
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    async fn get_proofs_with_metadata(
+        &self,
+        tx: &mut Self::Tx,
+        state: ProofStatus,
+    ) -> Result<Vec<ProofWithMetadata>, MokshaWalletError> {
+        let mut proofs = tx
+            .state
+            .proofs
+            .iter()
+            .filter(|stored| stored.state == state)
+            .map(|stored| ProofWithMetadata {
+                proof: stored.proof.clone(),
+                state,
+                created_at: stored.created_at,
+            })
+            .collect::<Vec<_>>();
+        // stable, so proofs of the same second stay in the order they were added
+        proofs.sort_by_key(|proof| proof.created_at);
+        Ok(proofs)
+    }
+
+    async fn transition_proofs(
+        &self,
+        tx: &mut Self::Tx,
+        secrets: &[String],
+        from: ProofStatus,
+        to: ProofStatus,
+        changed_at: u64,
+    ) -> Result<(), MokshaWalletError> {
+        let secrets = secrets.iter().collect::<HashSet<_>>();
+        let matching = tx
+            .state
+            .proofs
+            .iter()
+            .filter(|stored| stored.state == from && secrets.contains(&stored.proof.secret))
+            .count();
+        if matching != secrets.len() {
+            return Err(MokshaWalletError::InvalidProofTransition {
+                from,
+                to,
+                count: secrets.len() - matching,
+            });
+        }
+
+        // spendable proofs are not reserved
+        let reserved_at = (to != ProofStatus::Spendable).then_some(changed_at);
+        for stored in tx.state.proofs.iter_mut() {
+            if stored.state == from && secrets.contains(&stored.proof.secret) {
+                stored.state = to;
+                stored.reserved_at = reserved_at;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_reserved_proofs(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<ReservedProof>, MokshaWalletError> {
+        Ok(tx
+            .state
+            .proofs
+            .iter()
+            .filter(|stored| {
+                matches!(
+                    stored.state,
+                    ProofStatus::Reserved | ProofStatus::PendingMelt
+                )
+            })
+            .filter_map(|stored| {
+                stored.reserved_at.map(|reserved_at| ReservedProof {
+                    proof: stored.proof.clone(),
+                    state: stored.state,
+                    reserved_at,
+                })
+            })
+            .collect())
+    }
+
+    async fn get_endpoint_stats(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+    ) -> Result<Vec<EndpointSamples>, MokshaWalletError> {
+        Ok(tx
+            .state
+            .endpoint_stats
+            .iter()
+            .filter(|((url, _), _)| url == mint_url.as_str())
+            .map(|(_, samples)| samples.clone())
+            .collect())
+    }
+
+    async fn upsert_endpoint_stats(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+        samples: &EndpointSamples,
+    ) -> Result<(), MokshaWalletError> {
+        tx.state.endpoint_stats.insert(
+            (mint_url.to_string(), samples.endpoint.to_string()),
+            samples.clone(),
+        );
+        Ok(())
+    }
+
+    async fn get_keysets(&self, tx: &mut Self::Tx) -> Result<Vec<WalletKeyset>, MokshaWalletError> {
+        Ok(tx.state.keysets.clone())
+    }
+
+    async fn upsert_keyset(
+        &self,
+        tx: &mut Self::Tx,
+        keyset: &WalletKeyset,
+    ) -> Result<(), MokshaWalletError> {
+        let state = &mut *tx.state;
+        match state
+            .keysets
+            .iter_mut()
+            .find(|k| k.keyset_id == keyset.keyset_id && k.mint_url == keyset.mint_url)
+        {
+            // the last index is only changed with update_keyset_last_index
+            Some(existing) => {
+                existing.currency_unit = keyset.currency_unit.clone();
+                existing.public_keys = keyset.public_keys.clone();
+                existing.active = keyset.active;
+                existing.input_fee_ppk = keyset.input_fee_ppk;
+            }
+            None => {
+                state.last_keyset_id += 1;
+                state.keysets.push(WalletKeyset {
+                    id: Some(state.last_keyset_id),
+                    ..keyset.clone()
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn update_keyset_last_index(
+        &self,
+        tx: &mut Self::Tx,
+        keyset: &WalletKeyset,
+    ) -> Result<(), MokshaWalletError> {
+        let id = keyset.id.ok_or(MokshaWalletError::IdNotSet)?;
+        if let Some(existing) = tx.state.keysets.iter_mut().find(|k| k.id == Some(id)) {
+            existing.last_index = keyset.last_index;
+        }
+        Ok(())
+    }
+
+    async fn add_seed(&self, tx: &mut Self::Tx, seed_words: &str) -> Result<(), MokshaWalletError> {
+        if tx.state.seed.is_some() {
+            return Err(MokshaWalletError::MultipleSeeds);
+        }
+        tx.state.seed = Some(seed_words.to_owned());
+        Ok(())
+    }
+
+    async fn get_seed(&self, tx: &mut Self::Tx) -> Result<Option<String>, MokshaWalletError> {
+        Ok(tx.state.seed.clone())
+    }
+
+    async fn add_queued_receive(
+        &self,
+        tx: &mut Self::Tx,
+        queued: &QueuedReceive,
+    ) -> Result<u64, MokshaWalletError> {
+        let state = &mut *tx.state;
+        if state
+            .receive_queue
+            .values()
+            .any(|existing| existing.token == queued.token)
+        {
+            return Err(MokshaWalletError::DuplicateEntry(queued.token.clone()));
+        }
+        state.last_receive_id += 1;
+        let id = state.last_receive_id;
+        state.receive_queue.insert(
+            id,
+            QueuedReceive {
+                id: Some(id),
+                ..queued.clone()
+            },
+        );
+        Ok(id)
+    }
+
+    async fn get_queued_receives(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<QueuedReceive>, MokshaWalletError> {
+        Ok(tx.state.receive_queue.values().cloned().collect())
+    }
+
+    async fn update_queued_receive(
+        &self,
+        tx: &mut Self::Tx,
+        queued: &QueuedReceive,
+    ) -> Result<(), MokshaWalletError> {
+        let id = queued.id.ok_or(MokshaWalletError::IdNotSet)?;
+        if let Some(existing) = tx.state.receive_queue.get_mut(&id) {
+            existing.attempts = queued.attempts;
+            existing.next_attempt = queued.next_attempt;
+            existing.status = queued.status;
+            existing.last_error = queued.last_error.clone();
+        }
+        Ok(())
+    }
+
+    async fn delete_queued_receive(
+        &self,
+        tx: &mut Self::Tx,
+        id: u64,
+    ) -> Result<(), MokshaWalletError> {
+        tx.state.receive_queue.remove(&id);
+        Ok(())
+    }
+
+    async fn add_blind_recovery(
+        &self,
+        tx: &mut Self::Tx,
+        records: &[BlindRecovery],
+    ) -> Result<(), MokshaWalletError> {
+        for record in records {
+            let b_ = record.blinded_message.b_;
+            if tx
+                .state
+                .blind_recovery
+                .iter()
+                .any(|existing| existing.blinded_message.b_ == b_)
+            {
+                return Err(MokshaWalletError::DuplicateEntry(b_.to_string()));
+            }
+            tx.state.blind_recovery.push(record.clone());
+        }
+        Ok(())
+    }
+
+    async fn take_blind_recovery(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<BlindRecovery>, MokshaWalletError> {
+        Ok(std::mem::take(&mut tx.state.blind_recovery))
+    }
+
+    async fn delete_blind_recovery(
+        &self,
+        tx: &mut Self::Tx,
+        records: &[BlindRecovery],
+    ) -> Result<(), MokshaWalletError> {
+        let b_ = records
+            .iter()
+            .map(|record| record.blinded_message.b_)
+            .collect::<HashSet<_>>();
+        tx.state
+            .blind_recovery
+            .retain(|record| !b_.contains(&record.blinded_message.b_));
+        Ok(())
+    }
+
+    async fn add_pending_mint(
+        &self,
+        tx: &mut Self::Tx,
+        pending_mint: &PendingMint,
+    ) -> Result<(), MokshaWalletError> {
+        if tx
+            .state
+            .pending_mints
+            .iter()
+            .any(|existing| existing.quote_id == pending_mint.quote_id)
+        {
+            return Err(MokshaWalletError::DuplicateEntry(
+                pending_mint.quote_id.clone(),
+            ));
+        }
+        tx.state.pending_mints.push(pending_mint.clone());
+        Ok(())
+    }
+
+    async fn get_pending_mint(
+        &self,
+        tx: &mut Self::Tx,
+        quote_id: &str,
+    ) -> Result<Option<PendingMint>, MokshaWalletError> {
+        Ok(tx
+            .state
+            .pending_mints
+            .iter()
+            .find(|pending_mint| pending_mint.quote_id == quote_id)
+            .cloned())
+    }
+
+    async fn update_pending_mint(
+        &self,
+        tx: &mut Self::Tx,
+        pending_mint: &PendingMint,
+    ) -> Result<(), MokshaWalletError> {
+        if let Some(existing) = tx
+            .state
+            .pending_mints
+            .iter_mut()
+            .find(|existing| existing.quote_id == pending_mint.quote_id)
+        {
+            existing.claimed_at = pending_mint.claimed_at;
+            existing.proofs = pending_mint.proofs.clone();
+        }
+        Ok(())
+    }
+
+    async fn claim_mint_hash(
+        &self,
+        tx: &mut Self::Tx,
+        quote_id: &str,
+        claimed_at: u64,
+        stale_before: u64,
+    ) -> Result<bool, MokshaWalletError> {
+        let claimable = tx.state.pending_mints.iter_mut().find(|pending_mint| {
+            pending_mint.quote_id == quote_id
+                && pending_mint.proofs.is_none()
+                && pending_mint
+                    .claimed_at
+                    .map_or(true, |claimed| claimed < stale_before)
+        });
+        Ok(match claimable {
+            Some(pending_mint) => {
+                pending_mint.claimed_at = Some(claimed_at);
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn add_multi_payment(
+        &self,
+        tx: &mut Self::Tx,
+        payment: &MultiPayment,
+    ) -> Result<u64, MokshaWalletError> {
+        let state = &mut *tx.state;
+        state.last_multi_payment_id += 1;
+        let id = state.last_multi_payment_id;
+        state.multi_payments.insert(
+            id,
+            MultiPayment {
+                id: Some(id),
+                ..payment.clone()
+            },
+        );
+        Ok(id)
+    }
+
+    async fn get_multi_payments(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<MultiPayment>, MokshaWalletError> {
+        Ok(tx.state.multi_payments.values().cloned().collect())
+    }
+
+    async fn update_multi_payment(
+        &self,
+        tx: &mut Self::Tx,
+        payment: &MultiPayment,
+    ) -> Result<(), MokshaWalletError> {
+        let id = payment.id.ok_or(MokshaWalletError::IdNotSet)?;
+        if let Some(existing) = tx.state.multi_payments.get_mut(&id) {
+            existing.transfer_quote = payment.transfer_quote.clone();
+            existing.transfer_invoice = payment.transfer_invoice.clone();
+            existing.transfer_fees = payment.transfer_fees;
+            existing.payment_fees = payment.payment_fees;
+            existing.stage = payment.stage;
+        }
+        Ok(())
+    }
+
+    async fn add_pending_melt(
+        &self,
+        tx: &mut Self::Tx,
+        pending_melt: &PendingMelt,
+    ) -> Result<u64, MokshaWalletError> {
+        let state = &mut *tx.state;
+        state.last_pending_melt_id += 1;
+        let id = state.last_pending_melt_id;
+        state.pending_melts.insert(
+            id,
+            PendingMelt {
+                id: Some(id),
+                ..pending_melt.clone()
+            },
+        );
+        Ok(id)
+    }
+
+    async fn get_pending_melts(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<PendingMelt>, MokshaWalletError> {
+        Ok(tx.state.pending_melts.values().cloned().collect())
+    }
+
+    async fn update_pending_melt(
+        &self,
+        tx: &mut Self::Tx,
+        pending_melt: &PendingMelt,
+    ) -> Result<(), MokshaWalletError> {
+        let id = pending_melt.id.ok_or(MokshaWalletError::IdNotSet)?;
+        if let Some(existing) = tx.state.pending_melts.get_mut(&id) {
+            existing.dispute = pending_melt.dispute.clone();
+        }
+        Ok(())
+    }
+
+    async fn remove_pending_melt(
+        &self,
+        tx: &mut Self::Tx,
+        id: u64,
+    ) -> Result<(), MokshaWalletError> {
+        tx.state.pending_melts.remove(&id);
+        Ok(())
+    }
+
+    async fn add_snapshot(
+        &self,
+        tx: &mut Self::Tx,
+        snapshot: &Snapshot,
+    ) -> Result<u64, MokshaWalletError> {
+        let state = &mut *tx.state;
+        state.last_snapshot_id += 1;
+        let id = state.last_snapshot_id;
+        state.snapshots.insert(
+            id,
+            Snapshot {
+                id: Some(id),
+                ..snapshot.clone()
+            },
+        );
+        Ok(id)
+    }
+
+    async fn get_snapshots(&self, tx: &mut Self::Tx) -> Result<Vec<Snapshot>, MokshaWalletError> {
+        Ok(tx.state.snapshots.values().cloned().collect())
+    }
+
+    async fn delete_snapshot(&self, tx: &mut Self::Tx, id: u64) -> Result<(), MokshaWalletError> {
+        tx.state.snapshots.remove(&id);
+        Ok(())
+    }
+
+    async fn add_operation(
+        &self,
+        tx: &mut Self::Tx,
+        operation: &Operation,
+    ) -> Result<(), MokshaWalletError> {
+        tx.state
+            .operations
+            .insert(operation.id.clone(), operation.clone());
+        Ok(())
+    }
+
+    async fn update_operation(
+        &self,
+        tx: &mut Self::Tx,
+        operation: &Operation,
+    ) -> Result<(), MokshaWalletError> {
+        if let Some(existing) = tx.state.operations.get_mut(&operation.id) {
+            existing.finished_at = operation.finished_at;
+            existing.error = operation.error.clone();
+        }
+        Ok(())
+    }
+
+    async fn get_operation(
+        &self,
+        tx: &mut Self::Tx,
+        id: &OperationId,
+    ) -> Result<Option<Operation>, MokshaWalletError> {
+        Ok(tx.state.operations.get(id).cloned())
+    }
+
+    async fn get_operations(&self, tx: &mut Self::Tx) -> Result<Vec<Operation>, MokshaWalletError> {
+        Ok(tx.state.operations.values().cloned().collect())
+    }
+
+    async fn add_operation_records(
+        &self,
+        tx: &mut Self::Tx,
+        id: &OperationId,
+        records: &[OperationRecord],
+    ) -> Result<(), MokshaWalletError> {
+        for record in records {
+            let link = (id.clone(), record.clone());
+            if !tx.state.operation_records.contains(&link) {
+                tx.state.operation_records.push(link);
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_operation_records(
+        &self,
+        tx: &mut Self::Tx,
+        id: &OperationId,
+    ) -> Result<Vec<OperationRecord>, MokshaWalletError> {
+        Ok(tx
+            .state
+            .operation_records
+            .iter()
+            .filter(|(operation_id, _)| operation_id == id)
+            .map(|(_, record)| record.clone())
+            .collect())
+    }
+
+    async fn add_history_entry(
+        &self,
+        tx: &mut Self::Tx,
+        entry: &HistoryEntry,
+    ) -> Result<u64, MokshaWalletError> {
+        let id = tx.state.history.len() as u64 + 1;
+        tx.state.history.push(HistoryEntry {
+            id: Some(id),
+            ..entry.clone()
+        });
+        Ok(id)
+    }
+
+    async fn get_history(&self, tx: &mut Self::Tx) -> Result<Vec<HistoryEntry>, MokshaWalletError> {
+        Ok(tx.state.history.clone())
+    }
+
+    async fn get_history_page(
+        &self,
+        tx: &mut Self::Tx,
+        filter: &HistoryFilter,
+        before: Option<&PageCursor>,
+        limit: usize,
+    ) -> Result<HistoryPage, MokshaWalletError> {
+        let mut entries = tx
+            .state
+            .history
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .filter(|entry| {
+                before.map_or(true, |cursor| {
+                    cursor.is_before(entry.created_at, entry.id.unwrap_or_default())
+                })
+            })
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|entry| std::cmp::Reverse((entry.created_at, entry.id)));
+        let entries = entries
+            .into_iter()
+            .take(limit.saturating_add(1))
+            .cloned()
+            .collect();
+        Ok(HistoryPage::from_entries(entries, limit))
+    }
+}
@@ -1,11 +1,23 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display};
 
 use async_trait::async_trait;
-use moksha_core::{keyset::KeysetId, primitives::CurrencyUnit, proof::Proofs};
+use moksha_core::{
+    blind::BlindedMessage,
+    keyset::KeysetId,
+    page::PageCursor,
+    primitives::CurrencyUnit,
+    proof::{Proof, Proofs},
+};
 use secp256k1::PublicKey;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
-use crate::error::MokshaWalletError;
+use crate::{error::MokshaWalletError, operation::OperationId, stats::EndpointSamples};
+
+pub mod memory;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) mod blob;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod sqlite;
@@ -13,7 +25,7 @@ pub mod sqlite;
 #[cfg(target_arch = "wasm32")]
 pub mod rexie;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WalletKeyset {
     /// primary key
     pub id: Option<u64>,
@@ -24,6 +36,389 @@ pub struct WalletKeyset {
     pub last_index: u64,
     pub public_keys: HashMap<u64, PublicKey>,
     pub active: bool,
+    /// input fee in parts per thousand per proof (NUT-02)
+    pub input_fee_ppk: u64,
+}
+
+/// Lifecycle of a proof in the localstore. Only spendable proofs are part of the balance.
+///
+/// The state of a proof is only changed with [`LocalStore::transition_proofs`], which fails if a
+/// proof is not in the expected state, so two operations can't use the same proof.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProofStatus {
+    Spendable,
+    /// set aside for an operation that has not been sent to the mint yet
+    Reserved,
+    /// sent to the mint to pay an invoice, the outcome of the melt is not known yet
+    PendingMelt,
+    /// sent to someone else in a token that has not been redeemed yet
+    PendingSend,
+    /// excluded from the balance by the user, e.g. after exporting it
+    Frozen,
+    Spent,
+}
+
+impl ProofStatus {
+    pub const ALL: [Self; 6] = [
+        Self::Spendable,
+        Self::Reserved,
+        Self::PendingMelt,
+        Self::PendingSend,
+        Self::Frozen,
+        Self::Spent,
+    ];
+}
+
+impl Display for ProofStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Spendable => write!(f, "spendable"),
+            Self::Reserved => write!(f, "reserved"),
+            Self::PendingMelt => write!(f, "pending_melt"),
+            Self::PendingSend => write!(f, "pending_send"),
+            Self::Frozen => write!(f, "frozen"),
+            Self::Spent => write!(f, "spent"),
+        }
+    }
+}
+
+/// A proof that is set aside for a pending operation and not part of the balance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReservedProof {
+    pub proof: Proof,
+    /// [`ProofStatus::Reserved`] or [`ProofStatus::PendingMelt`]
+    pub state: ProofStatus,
+    /// unix timestamp (seconds) of the reservation
+    pub reserved_at: u64,
+}
+
+/// A proof with the time it was added to the localstore, see
+/// [`LocalStore::get_proofs_with_metadata`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofWithMetadata {
+    pub proof: Proof,
+    pub state: ProofStatus,
+    /// unix timestamp (seconds) when the proof was added
+    pub created_at: u64,
+}
+
+/// Outputs of a mint request for a quote. They are stored before the request is sent, so an
+/// interrupted attempt can be finished with the same outputs.
+#[derive(Debug, Clone)]
+pub struct PendingMint {
+    pub quote_id: String,
+    pub mint_url: Url,
+    pub keyset_id: String,
+    pub outputs: Vec<PendingMintOutput>,
+    /// unix timestamp (seconds) of the running attempt, `None` if no attempt is running
+    pub claimed_at: Option<u64>,
+    /// the minted proofs once the mint has signed the outputs
+    pub proofs: Option<Proofs>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingMintOutput {
+    pub blinded_message: BlindedMessage,
+    pub secret: String,
+    /// blinding factor as hex
+    pub blinding_factor: String,
+}
+
+/// An output of a request whose signature has not been processed yet. If the wallet crashes
+/// during the request, the proof can be recovered with the restore endpoint of the mint (NUT-09).
+#[derive(Debug, Clone)]
+pub struct BlindRecovery {
+    pub mint_url: Url,
+    pub blinded_message: BlindedMessage,
+    pub secret: String,
+    /// blinding factor as hex
+    pub blinding_factor: String,
+}
+
+/// A received token that could not be redeemed yet and is retried later
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedReceive {
+    /// primary key
+    pub id: Option<u64>,
+    /// the serialized token
+    pub token: String,
+    /// number of failed redemption attempts
+    pub attempts: u32,
+    /// unix timestamp (seconds) after which the next attempt is made
+    pub next_attempt: u64,
+    pub status: QueuedReceiveStatus,
+    pub last_error: Option<String>,
+}
+
+impl QueuedReceive {
+    pub fn new(token: String) -> Self {
+        Self {
+            id: None,
+            token,
+            attempts: 0,
+            next_attempt: 0,
+            status: QueuedReceiveStatus::Pending,
+            last_error: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuedReceiveStatus {
+    /// will be retried once `next_attempt` is reached
+    Pending,
+    /// gave up on this token; it stays in the queue until it is retried or removed manually
+    Failed,
+}
+
+impl Display for QueuedReceiveStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Pending => write!(f, "pending"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// Journal entry of a payment that moves funds from one mint to another before paying an invoice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiPayment {
+    /// primary key
+    pub id: Option<u64>,
+    pub invoice: String,
+    /// mint that transfers the missing amount to the paying mint
+    pub source_mint_url: Url,
+    /// mint that pays the invoice
+    pub target_mint_url: Url,
+    pub transfer_amount: u64,
+    /// mint quote at the target mint that is paid by the source mint
+    pub transfer_quote: Option<String>,
+    pub transfer_invoice: Option<String>,
+    pub transfer_fees: u64,
+    pub payment_fees: u64,
+    pub stage: MultiPaymentStage,
+}
+
+impl MultiPayment {
+    pub fn total_fees(&self) -> u64 {
+        self.transfer_fees + self.payment_fees
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MultiPaymentStage {
+    Created,
+    TransferQuoted,
+    TransferPaid,
+    TransferMinted,
+    Paid,
+}
+
+impl Display for MultiPaymentStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Created => write!(f, "created"),
+            Self::TransferQuoted => write!(f, "transfer_quoted"),
+            Self::TransferPaid => write!(f, "transfer_paid"),
+            Self::TransferMinted => write!(f, "transfer_minted"),
+            Self::Paid => write!(f, "paid"),
+        }
+    }
+}
+
+/// Proofs that were sent to the mint to pay an invoice. The record is kept until the outcome of
+/// the melt is known, so the proofs are not lost if the wallet crashes during the payment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingMelt {
+    /// primary key
+    pub id: Option<u64>,
+    pub mint_url: Url,
+    pub quote_id: String,
+    pub invoice: String,
+    pub proofs: Proofs,
+    /// unix timestamp (seconds) of the payment attempt
+    pub created_at: u64,
+    /// reason why the response of the mint was not accepted. The melt is not finalized and has to
+    /// be checked manually.
+    pub dispute: Option<String>,
+}
+
+/// A copy of the spendable proofs, taken before operations that remove proofs from the
+/// localstore, see [`crate::wallet::Wallet::rollback_to`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// primary key
+    pub id: Option<u64>,
+    /// the operation the snapshot was taken for
+    pub reason: String,
+    /// unix timestamp (seconds)
+    pub created_at: u64,
+    pub proofs: Proofs,
+}
+
+impl Snapshot {
+    /// Size of the stored proofs in bytes
+    pub fn size(&self) -> usize {
+        serde_json::to_string(&self.proofs).map_or(0, |proofs| proofs.len())
+    }
+}
+
+/// A top-level operation of the wallet, the records it writes are linked to its id with
+/// [`LocalStore::add_operation_records`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub id: OperationId,
+    pub kind: OperationKind,
+    /// unix timestamp (seconds)
+    pub created_at: u64,
+    /// unix timestamp (seconds), `None` while the operation runs or if the wallet stopped during
+    /// the operation
+    pub finished_at: Option<u64>,
+    /// error of a failed operation
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationKind {
+    Mint,
+    Send,
+    Receive,
+    PayInvoice,
+    PayInvoiceMulti,
+}
+
+impl Display for OperationKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mint => write!(f, "mint"),
+            Self::Send => write!(f, "send"),
+            Self::Receive => write!(f, "receive"),
+            Self::PayInvoice => write!(f, "pay_invoice"),
+            Self::PayInvoiceMulti => write!(f, "pay_invoice_multi"),
+        }
+    }
+}
+
+/// A record in the localstore that was written by an operation
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum OperationRecord {
+    /// a proof the operation added or whose state it changed, by its secret
+    Proof(String),
+    /// by its quote id
+    PendingMint(String),
+    PendingMelt(u64),
+    MultiPayment(u64),
+}
+
+impl OperationRecord {
+    /// Type and key of the record as they are stored
+    pub fn parts(&self) -> (&'static str, String) {
+        match self {
+            Self::Proof(secret) => ("proof", secret.clone()),
+            Self::PendingMint(quote_id) => ("pending_mint", quote_id.clone()),
+            Self::PendingMelt(id) => ("pending_melt", id.to_string()),
+            Self::MultiPayment(id) => ("multi_payment", id.to_string()),
+        }
+    }
+
+    /// Links every proof by its secret
+    pub fn proofs(proofs: &Proofs) -> Vec<Self> {
+        proofs.secrets().into_iter().map(Self::Proof).collect()
+    }
+}
+
+/// An entry of the transaction history, see [`crate::wallet::Wallet::get_history`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// primary key
+    pub id: Option<u64>,
+    /// the operation that moved the funds
+    pub operation_id: OperationId,
+    pub kind: HistoryKind,
+    /// sats that were added to the balance or left it, including the fees of a payment
+    pub amount: u64,
+    pub mint_url: Url,
+    /// unix timestamp (seconds)
+    pub created_at: u64,
+    /// the paid invoice
+    pub invoice: Option<String>,
+    /// memo of a sent or received token
+    pub memo: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryKind {
+    Mint,
+    Send,
+    Receive,
+    Melt,
+}
+
+impl HistoryKind {
+    pub fn direction(&self) -> TransferDirection {
+        match self {
+            Self::Mint | Self::Receive => TransferDirection::Incoming,
+            Self::Send | Self::Melt => TransferDirection::Outgoing,
+        }
+    }
+}
+
+impl Display for HistoryKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mint => write!(f, "mint"),
+            Self::Send => write!(f, "send"),
+            Self::Receive => write!(f, "receive"),
+            Self::Melt => write!(f, "melt"),
+        }
+    }
+}
+
+/// Selects entries of the history, the default selects all
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HistoryFilter {
+    pub kind: Option<HistoryKind>,
+    pub mint_url: Option<Url>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.kind.map_or(true, |kind| kind == entry.kind)
+            && self
+                .mint_url
+                .as_ref()
+                .map_or(true, |mint_url| mint_url == &entry.mint_url)
+    }
+}
+
+/// Entries of the history, the newest first, see [`LocalStore::get_history_page`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    /// continues after the last entry, `None` on the last page
+    pub next: Option<PageCursor>,
+}
+
+impl HistoryPage {
+    /// Takes the first `limit` entries, `entries` has one more if there is a following page
+    fn from_entries(mut entries: Vec<HistoryEntry>, limit: usize) -> Self {
+        let next = match entries.len() > limit {
+            true => {
+                entries.truncate(limit);
+                entries
+                    .last()
+                    .map(|entry| PageCursor::new(entry.created_at, entry.id.unwrap_or_default()))
+            }
+            false => None,
+        };
+        Self { entries, next }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    Incoming,
+    Outgoing,
 }
 
 impl WalletKeysetFilter for Vec<WalletKeyset> {
@@ -54,121 +449,646 @@ impl WalletKeyset {
             last_index,
             public_keys,
             active,
+            input_fee_ppk: 0,
         }
     }
 }
 
+/// A transaction of a [`LocalStore`]. The changes are discarded if it is dropped without a commit.
+#[async_trait(?Send)]
+pub trait LocalStoreTransaction {
+    async fn commit(self) -> Result<(), MokshaWalletError>;
+}
+
 #[cfg(not(target_arch = "wasm32"))]
+#[async_trait(?Send)]
+impl LocalStoreTransaction for sqlx::Transaction<'static, sqlx::Sqlite> {
+    async fn commit(self) -> Result<(), MokshaWalletError> {
+        Ok(sqlx::Transaction::commit(self).await?)
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+pub struct RexieTransaction {}
+
+#[cfg(target_arch = "wasm32")]
+#[async_trait(?Send)]
+impl LocalStoreTransaction for RexieTransaction {
+    async fn commit(self) -> Result<(), MokshaWalletError> {
+        Ok(())
+    }
+}
+
 #[async_trait(?Send)]
 pub trait LocalStore {
-    type DB: sqlx::Database;
-    async fn begin_tx(&self) -> Result<sqlx::Transaction<Self::DB>, MokshaWalletError>;
+    type Tx: LocalStoreTransaction;
+    async fn begin_tx(&self) -> Result<Self::Tx, MokshaWalletError>;
+    /// Deletes the stored proofs that match both the secret and the amount of one of `proofs`
     async fn delete_proofs(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         proofs: &Proofs,
     ) -> Result<(), MokshaWalletError>;
-    async fn add_proofs(
+    /// Deletes the proofs with the given secrets in a single batch, whatever their state is
+    async fn delete_proofs_by_secrets(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-        proofs: &Proofs,
+        tx: &mut Self::Tx,
+        secrets: &[String],
     ) -> Result<(), MokshaWalletError>;
-    async fn get_proofs(
+    async fn add_proofs(&self, tx: &mut Self::Tx, proofs: &Proofs)
+        -> Result<(), MokshaWalletError>;
+    /// Returns the spendable proofs
+    async fn get_proofs(&self, tx: &mut Self::Tx) -> Result<Proofs, MokshaWalletError>;
+
+    async fn get_proofs_by_state(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
+        state: ProofStatus,
     ) -> Result<Proofs, MokshaWalletError>;
 
-    async fn get_keysets(
+    /// Returns the proofs in the state with the time they were added, the oldest first. Proofs
+    /// that were added in the same second are returned in the order they were added.
+    async fn get_proofs_with_metadata(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-    ) -> Result<Vec<WalletKeyset>, MokshaWalletError>;
+        tx: &mut Self::Tx,
+        state: ProofStatus,
+    ) -> Result<Vec<ProofWithMetadata>, MokshaWalletError>;
+
+    /// Moves the proofs with the given secrets from `from` to `to`. Fails with
+    /// [`MokshaWalletError::InvalidProofTransition`] without changing any proof, if one of them
+    /// is not in the `from` state.
+    async fn transition_proofs(
+        &self,
+        tx: &mut Self::Tx,
+        secrets: &[String],
+        from: ProofStatus,
+        to: ProofStatus,
+        changed_at: u64,
+    ) -> Result<(), MokshaWalletError>;
+
+    /// Returns the reserved proofs and the proofs of pending melts
+    async fn get_reserved_proofs(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<ReservedProof>, MokshaWalletError>;
+
+    async fn get_endpoint_stats(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+    ) -> Result<Vec<EndpointSamples>, MokshaWalletError>;
+
+    async fn upsert_endpoint_stats(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+        samples: &EndpointSamples,
+    ) -> Result<(), MokshaWalletError>;
+
+    async fn get_keysets(&self, tx: &mut Self::Tx) -> Result<Vec<WalletKeyset>, MokshaWalletError>;
     async fn upsert_keyset(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         keyset: &WalletKeyset,
     ) -> Result<(), MokshaWalletError>;
 
     async fn update_keyset_last_index(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         keyset: &WalletKeyset,
     ) -> Result<(), MokshaWalletError>;
 
-    async fn add_seed(
+    async fn add_seed(&self, tx: &mut Self::Tx, seed_words: &str) -> Result<(), MokshaWalletError>;
+
+    async fn get_seed(&self, tx: &mut Self::Tx) -> Result<Option<String>, MokshaWalletError>;
+
+    async fn add_queued_receive(
+        &self,
+        tx: &mut Self::Tx,
+        queued: &QueuedReceive,
+    ) -> Result<u64, MokshaWalletError>;
+
+    async fn get_queued_receives(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-        seed_words: &str,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<QueuedReceive>, MokshaWalletError>;
+
+    async fn update_queued_receive(
+        &self,
+        tx: &mut Self::Tx,
+        queued: &QueuedReceive,
     ) -> Result<(), MokshaWalletError>;
 
-    async fn get_seed(
+    async fn delete_queued_receive(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-    ) -> Result<Option<String>, MokshaWalletError>;
-}
+        tx: &mut Self::Tx,
+        id: u64,
+    ) -> Result<(), MokshaWalletError>;
 
-#[cfg(target_arch = "wasm32")]
-pub struct RexieTransaction {}
+    /// Stores the outputs of a request before it is sent
+    async fn add_blind_recovery(
+        &self,
+        tx: &mut Self::Tx,
+        records: &[BlindRecovery],
+    ) -> Result<(), MokshaWalletError>;
 
-#[cfg(target_arch = "wasm32")]
-impl RexieTransaction {
-    pub async fn commit(&self) -> Result<(), MokshaWalletError> {
-        Ok(())
-    }
-}
+    /// Returns and removes all stored outputs
+    async fn take_blind_recovery(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<BlindRecovery>, MokshaWalletError>;
 
-#[cfg(target_arch = "wasm32")]
-#[async_trait(?Send)]
-pub trait LocalStore {
-    async fn begin_tx(&self) -> Result<RexieTransaction, MokshaWalletError> {
-        Ok(RexieTransaction {})
-    }
+    /// Removes the outputs of a request that completed
+    async fn delete_blind_recovery(
+        &self,
+        tx: &mut Self::Tx,
+        records: &[BlindRecovery],
+    ) -> Result<(), MokshaWalletError>;
 
-    async fn delete_proofs(
+    async fn add_pending_mint(
         &self,
-        tx: &mut RexieTransaction,
-        proofs: &Proofs,
+        tx: &mut Self::Tx,
+        pending_mint: &PendingMint,
     ) -> Result<(), MokshaWalletError>;
-    async fn add_proofs(
+
+    async fn get_pending_mint(
         &self,
-        tx: &mut RexieTransaction,
-        proofs: &Proofs,
+        tx: &mut Self::Tx,
+        quote_id: &str,
+    ) -> Result<Option<PendingMint>, MokshaWalletError>;
+
+    async fn update_pending_mint(
+        &self,
+        tx: &mut Self::Tx,
+        pending_mint: &PendingMint,
     ) -> Result<(), MokshaWalletError>;
-    async fn get_proofs(&self, tx: &mut RexieTransaction) -> Result<Proofs, MokshaWalletError>;
 
-    async fn get_keysets(
+    /// Claims an unfinished pending mint for a new attempt. Returns false if another attempt
+    /// claimed it at or after `stale_before`.
+    async fn claim_mint_hash(
         &self,
-        _tx: &mut RexieTransaction,
-    ) -> Result<Vec<WalletKeyset>, MokshaWalletError>;
+        tx: &mut Self::Tx,
+        quote_id: &str,
+        claimed_at: u64,
+        stale_before: u64,
+    ) -> Result<bool, MokshaWalletError>;
 
-    async fn upsert_keyset(
+    async fn add_multi_payment(
         &self,
-        _tx: &mut RexieTransaction,
-        keyset: &WalletKeyset,
+        tx: &mut Self::Tx,
+        payment: &MultiPayment,
+    ) -> Result<u64, MokshaWalletError>;
+
+    async fn get_multi_payments(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<MultiPayment>, MokshaWalletError>;
+
+    async fn update_multi_payment(
+        &self,
+        tx: &mut Self::Tx,
+        payment: &MultiPayment,
     ) -> Result<(), MokshaWalletError>;
 
-    async fn update_keyset_last_index(
+    async fn add_pending_melt(
         &self,
-        _tx: &mut RexieTransaction,
-        keyset: &WalletKeyset,
+        tx: &mut Self::Tx,
+        pending_melt: &PendingMelt,
+    ) -> Result<u64, MokshaWalletError>;
+
+    async fn get_pending_melts(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<PendingMelt>, MokshaWalletError>;
+
+    async fn update_pending_melt(
+        &self,
+        tx: &mut Self::Tx,
+        pending_melt: &PendingMelt,
+    ) -> Result<(), MokshaWalletError>;
+
+    async fn remove_pending_melt(
+        &self,
+        tx: &mut Self::Tx,
+        id: u64,
     ) -> Result<(), MokshaWalletError>;
 
-    async fn add_seed(
+    async fn add_snapshot(
+        &self,
+        tx: &mut Self::Tx,
+        snapshot: &Snapshot,
+    ) -> Result<u64, MokshaWalletError>;
+
+    /// Returns the snapshots, the oldest first
+    async fn get_snapshots(&self, tx: &mut Self::Tx) -> Result<Vec<Snapshot>, MokshaWalletError>;
+
+    async fn delete_snapshot(&self, tx: &mut Self::Tx, id: u64) -> Result<(), MokshaWalletError>;
+
+    async fn add_operation(
         &self,
-        _tx: &mut RexieTransaction,
-        seed_words: &str,
+        tx: &mut Self::Tx,
+        operation: &Operation,
     ) -> Result<(), MokshaWalletError>;
 
-    async fn get_seed(
+    /// Stores the outcome of an operation
+    async fn update_operation(
         &self,
-        _tx: &mut RexieTransaction,
-    ) -> Result<Option<String>, MokshaWalletError>;
+        tx: &mut Self::Tx,
+        operation: &Operation,
+    ) -> Result<(), MokshaWalletError>;
+
+    async fn get_operation(
+        &self,
+        tx: &mut Self::Tx,
+        id: &OperationId,
+    ) -> Result<Option<Operation>, MokshaWalletError>;
+
+    /// Returns the operations sorted by their id
+    async fn get_operations(&self, tx: &mut Self::Tx) -> Result<Vec<Operation>, MokshaWalletError>;
+
+    /// Links the records to the operation, records that are already linked are skipped
+    async fn add_operation_records(
+        &self,
+        tx: &mut Self::Tx,
+        id: &OperationId,
+        records: &[OperationRecord],
+    ) -> Result<(), MokshaWalletError>;
+
+    /// Returns the records of the operation in the order they were linked
+    async fn get_operation_records(
+        &self,
+        tx: &mut Self::Tx,
+        id: &OperationId,
+    ) -> Result<Vec<OperationRecord>, MokshaWalletError>;
+
+    async fn add_history_entry(
+        &self,
+        tx: &mut Self::Tx,
+        entry: &HistoryEntry,
+    ) -> Result<u64, MokshaWalletError>;
+
+    /// Returns the history, the oldest entry first
+    async fn get_history(&self, tx: &mut Self::Tx) -> Result<Vec<HistoryEntry>, MokshaWalletError>;
+
+    /// Returns up to `limit` entries that match the filter, ordered by `(created_at, id)` with
+    /// the newest first. Starts after the cursor `before`, or at the newest entry.
+    async fn get_history_page(
+        &self,
+        tx: &mut Self::Tx,
+        filter: &HistoryFilter,
+        before: Option<&PageCursor>,
+        limit: usize,
+    ) -> Result<HistoryPage, MokshaWalletError>;
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
 
+    use moksha_core::{
+        fixture::read_fixture,
+        keyset::KeysetId,
+        page::PageCursor,
+        primitives::CurrencyUnit,
+        proof::{Proof, Proofs},
+        token::TokenV3,
+    };
     use secp256k1::PublicKey;
+    use url::Url;
+
+    use super::{
+        memory::MemoryLocalStore, sqlite::SqliteLocalStore, HistoryEntry, HistoryFilter,
+        HistoryKind, LocalStore, LocalStoreTransaction, Operation, OperationKind, OperationRecord,
+        PendingMint, ProofStatus, QueuedReceive, Snapshot, WalletKeyset,
+    };
+    use crate::{
+        clock::{Clock, SystemClock},
+        error::MokshaWalletError,
+        operation::OperationId,
+    };
+
+    /// Runs a check against every native localstore, so they behave the same
+    macro_rules! localstore_suite {
+        ($($check:ident),* $(,)?) => {
+            $(
+                mod $check {
+                    #[tokio::test]
+                    async fn sqlite() -> anyhow::Result<()> {
+                        super::$check(super::SqliteLocalStore::with_in_memory().await?).await
+                    }
+
+                    #[tokio::test]
+                    async fn memory() -> anyhow::Result<()> {
+                        super::$check(super::MemoryLocalStore::new()).await
+                    }
+                }
+            )*
+        };
+    }
+
+    localstore_suite!(
+        check_proofs,
+        check_proofs_with_metadata,
+        check_transition_proofs,
+        check_rollback,
+        check_keysets,
+        check_seed,
+        check_queued_receives,
+        check_pending_mints,
+        check_snapshots,
+        check_operations,
+        check_history,
+        check_history_pages,
+    );
+
+    async fn check_proofs_with_metadata(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let proofs = tokens.proofs().proofs();
+        let before = SystemClock.now();
+        let mut tx = localstore.begin_tx().await?;
+        localstore
+            .add_proofs(&mut tx, &proofs[2..].to_vec().into())
+            .await?;
+        localstore
+            .add_proofs(&mut tx, &proofs[..2].to_vec().into())
+            .await?;
+        let stored = localstore
+            .get_proofs_with_metadata(&mut tx, ProofStatus::Spendable)
+            .await?;
+        tx.commit().await?;
+        let after = SystemClock.now();
+
+        // the oldest first, the second add is not older than the first one
+        assert_eq!(
+            [&proofs[2..], &proofs[..2]].concat(),
+            stored
+                .iter()
+                .map(|stored| stored.proof.clone())
+                .collect::<Vec<_>>()
+        );
+        assert!(stored
+            .windows(2)
+            .all(|pair| pair[0].created_at <= pair[1].created_at));
+        assert!(stored
+            .iter()
+            .all(|stored| (before..=after).contains(&stored.created_at)
+                && stored.state == ProofStatus::Spendable));
+        Ok(())
+    }
+
+    async fn check_proofs(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        localstore.add_proofs(&mut tx, &tokens.proofs()).await?;
+        assert_eq!(tokens.proofs(), localstore.get_proofs(&mut tx).await?);
+
+        // a proof can't be stored twice
+        assert!(localstore
+            .add_proofs(&mut tx, &tokens.proofs().proofs()[0].clone().into())
+            .await
+            .is_err());
+
+        // unknown secrets are ignored
+        let secrets = tokens.proofs().secrets(); // 4, 8, 16, 32
+        localstore
+            .delete_proofs_by_secrets(&mut tx, &[secrets[0].clone(), "unknown".to_owned()])
+            .await?;
+        localstore.delete_proofs_by_secrets(&mut tx, &[]).await?;
+        assert_eq!(56, localstore.get_proofs(&mut tx).await?.total_amount());
+
+        // a proof with the secret of a stored one but another amount is not deleted
+        let proof_8 = tokens.proofs().proofs()[1].clone();
+        let other_amount = Proof {
+            amount: 16,
+            ..proof_8.clone()
+        };
+        localstore
+            .delete_proofs(&mut tx, &other_amount.into())
+            .await?;
+        assert_eq!(56, localstore.get_proofs(&mut tx).await?.total_amount());
+        localstore.delete_proofs(&mut tx, &proof_8.into()).await?;
+        assert_eq!(48, localstore.get_proofs(&mut tx).await?.total_amount());
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn check_transition_proofs(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        localstore.add_proofs(&mut tx, &tokens.proofs()).await?;
+        let secrets = tokens.proofs().secrets(); // 4, 8, 16, 32
+
+        localstore
+            .transition_proofs(
+                &mut tx,
+                &secrets[0..2],
+                ProofStatus::Spendable,
+                ProofStatus::PendingMelt,
+                1_000,
+            )
+            .await?;
+        let reserved = localstore.get_reserved_proofs(&mut tx).await?;
+        assert_eq!(12, reserved.iter().map(|r| r.proof.amount).sum::<u64>());
+        assert!(reserved
+            .iter()
+            .all(|r| r.state == ProofStatus::PendingMelt && r.reserved_at == 1_000));
+
+        // 16 is spendable, so none of the proofs change
+        let result = localstore
+            .transition_proofs(
+                &mut tx,
+                &secrets[1..3],
+                ProofStatus::PendingMelt,
+                ProofStatus::Spent,
+                2_000,
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidProofTransition { count: 1, .. })
+        ));
+        assert_eq!(48, localstore.get_proofs(&mut tx).await?.total_amount());
+        assert!(localstore
+            .get_proofs_by_state(&mut tx, ProofStatus::Spent)
+            .await?
+            .is_empty());
+
+        localstore
+            .transition_proofs(
+                &mut tx,
+                &secrets[0..2],
+                ProofStatus::PendingMelt,
+                ProofStatus::Spendable,
+                3_000,
+            )
+            .await?;
+        assert!(localstore.get_reserved_proofs(&mut tx).await?.is_empty());
+        assert_eq!(60, localstore.get_proofs(&mut tx).await?.total_amount());
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn check_rollback(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.add_proofs(&mut tx, &tokens.proofs()).await?;
+        tx.commit().await?;
+
+        // a transaction that is dropped without a commit doesn't change anything
+        let mut tx = localstore.begin_tx().await?;
+        localstore.delete_proofs(&mut tx, &tokens.proofs()).await?;
+        localstore.add_seed(&mut tx, "seed words").await?;
+        drop(tx);
+
+        let mut tx = localstore.begin_tx().await?;
+        assert_eq!(60, localstore.get_proofs(&mut tx).await?.total_amount());
+        assert_eq!(None, localstore.get_seed(&mut tx).await?);
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn check_keysets(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let mut keyset = WalletKeyset::new(
+            &KeysetId::new("00d31cecf59d18c0")?,
+            &mint_url,
+            &CurrencyUnit::Sat,
+            0,
+            generate_test_map()
+                .into_iter()
+                .map(|(amount, key)| (amount as u64, key))
+                .collect(),
+            true,
+        );
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+
+        // the upsert doesn't change the last index
+        let mut stored = localstore.get_keysets(&mut tx).await?.remove(0);
+        assert!(stored.id.is_some());
+        stored.last_index = 10;
+        localstore
+            .update_keyset_last_index(&mut tx, &stored)
+            .await?;
+        keyset.active = false;
+        keyset.last_index = 5;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+
+        let keysets = localstore.get_keysets(&mut tx).await?;
+        assert_eq!(1, keysets.len());
+        assert!(!keysets[0].active);
+        assert_eq!(10, keysets[0].last_index);
+        assert_eq!(keyset.public_keys, keysets[0].public_keys);
+
+        assert!(matches!(
+            localstore.update_keyset_last_index(&mut tx, &keyset).await,
+            Err(MokshaWalletError::IdNotSet)
+        ));
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn check_seed(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        assert_eq!(None, localstore.get_seed(&mut tx).await?);
+        localstore.add_seed(&mut tx, "seed words").await?;
+        assert_eq!(
+            Some("seed words".to_owned()),
+            localstore.get_seed(&mut tx).await?
+        );
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn check_queued_receives(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        let first = localstore
+            .add_queued_receive(&mut tx, &QueuedReceive::new("first".to_owned()))
+            .await?;
+        let second = localstore
+            .add_queued_receive(&mut tx, &QueuedReceive::new("second".to_owned()))
+            .await?;
+        assert!(first < second);
+        assert!(localstore
+            .add_queued_receive(&mut tx, &QueuedReceive::new("first".to_owned()))
+            .await
+            .is_err());
+
+        let mut queued = localstore.get_queued_receives(&mut tx).await?;
+        assert_eq!(
+            vec![Some(first), Some(second)],
+            queued.iter().map(|q| q.id).collect::<Vec<_>>()
+        );
+        queued[1].attempts = 3;
+        queued[1].last_error = Some("offline".to_owned());
+        localstore
+            .update_queued_receive(&mut tx, &queued[1])
+            .await?;
+        localstore.delete_queued_receive(&mut tx, first).await?;
+
+        assert_eq!(
+            vec![queued[1].clone()],
+            localstore.get_queued_receives(&mut tx).await?
+        );
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn check_pending_mints(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        let mut pending_mint = PendingMint {
+            quote_id: "quote".to_owned(),
+            mint_url: Url::parse("http://127.0.0.1:3338")?,
+            keyset_id: "00d31cecf59d18c0".to_owned(),
+            outputs: vec![],
+            claimed_at: None,
+            proofs: None,
+        };
+        localstore.add_pending_mint(&mut tx, &pending_mint).await?;
+        assert!(localstore
+            .get_pending_mint(&mut tx, "unknown")
+            .await?
+            .is_none());
+
+        assert!(
+            localstore
+                .claim_mint_hash(&mut tx, "quote", 1_000, 0)
+                .await?
+        );
+        // the claim is not stale yet
+        assert!(
+            !localstore
+                .claim_mint_hash(&mut tx, "quote", 1_010, 1_000)
+                .await?
+        );
+        assert!(
+            localstore
+                .claim_mint_hash(&mut tx, "quote", 2_000, 1_001)
+                .await?
+        );
+
+        // a finished mint can't be claimed anymore
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        pending_mint.claimed_at = None;
+        pending_mint.proofs = Some(tokens.proofs());
+        localstore
+            .update_pending_mint(&mut tx, &pending_mint)
+            .await?;
+        assert!(
+            !localstore
+                .claim_mint_hash(&mut tx, "quote", 3_000, 3_000)
+                .await?
+        );
+
+        let stored = localstore
+            .get_pending_mint(&mut tx, "quote")
+            .await?
+            .expect("pending mint not found");
+        assert_eq!(None, stored.claimed_at);
+        assert_eq!(Some(tokens.proofs()), stored.proofs);
+        tx.commit().await?;
+        Ok(())
+    }
 
     fn generate_test_map() -> HashMap<u32, PublicKey> {
         let mut map = HashMap::new();
@@ -190,4 +1110,300 @@ mod tests {
         let json = serde_json::to_string(&data).unwrap();
         println!("{:?}", json);
     }
+
+    async fn check_snapshots(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let snapshot = Snapshot {
+            id: None,
+            reason: "first".to_owned(),
+            created_at: 1_000,
+            proofs: tokens.proofs(),
+        };
+        let first = localstore.add_snapshot(&mut tx, &snapshot).await?;
+        let second = localstore
+            .add_snapshot(
+                &mut tx,
+                &Snapshot {
+                    reason: "second".to_owned(),
+                    created_at: 2_000,
+                    proofs: Proofs::empty(),
+                    ..snapshot.clone()
+                },
+            )
+            .await?;
+
+        let snapshots = localstore.get_snapshots(&mut tx).await?;
+        assert_eq!(
+            vec![Some(first), Some(second)],
+            snapshots.iter().map(|s| s.id).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Snapshot {
+                id: Some(first),
+                ..snapshot
+            },
+            snapshots[0]
+        );
+
+        localstore.delete_snapshot(&mut tx, first).await?;
+        let snapshots = localstore.get_snapshots(&mut tx).await?;
+        assert_eq!(1, snapshots.len());
+        assert_eq!("second", snapshots[0].reason);
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn check_history(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        let mint = HistoryEntry {
+            id: None,
+            operation_id: OperationId::from_parts(1_000_000, 1),
+            kind: HistoryKind::Mint,
+            amount: 60,
+            mint_url: Url::parse("http://localhost:3338/")?,
+            created_at: 1_000,
+            invoice: None,
+            memo: None,
+        };
+        let melt = HistoryEntry {
+            operation_id: OperationId::from_parts(1_000_000, 2),
+            kind: HistoryKind::Melt,
+            amount: 23,
+            invoice: Some("lnbcrt210n1".to_owned()),
+            memo: Some("coffee".to_owned()),
+            ..mint.clone()
+        };
+        let mint_id = localstore.add_history_entry(&mut tx, &mint).await?;
+        let melt_id = localstore.add_history_entry(&mut tx, &melt).await?;
+        assert_ne!(mint_id, melt_id);
+        assert_eq!(
+            vec![
+                HistoryEntry {
+                    id: Some(mint_id),
+                    ..mint
+                },
+                HistoryEntry {
+                    id: Some(melt_id),
+                    ..melt
+                }
+            ],
+            localstore.get_history(&mut tx).await?
+        );
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Adds `count` entries, seven in every second from `created_at`
+    async fn add_history_entries(
+        localstore: &impl LocalStore,
+        count: u64,
+        created_at: u64,
+    ) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        for i in 0..count {
+            let entry = HistoryEntry {
+                id: None,
+                operation_id: OperationId::from_parts(1_000_000, i.into()),
+                kind: [
+                    HistoryKind::Mint,
+                    HistoryKind::Send,
+                    HistoryKind::Receive,
+                    HistoryKind::Melt,
+                ][i as usize % 4],
+                amount: i,
+                mint_url: Url::parse(
+                    ["http://localhost:3338/", "http://localhost:3339/"][i as usize % 3 % 2],
+                )?,
+                created_at: created_at + i / 7,
+                invoice: None,
+                memo: None,
+            };
+            localstore.add_history_entry(&mut tx, &entry).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Ids of the entries on the pages of `limit` entries, checks that every page but the last is
+    /// full and continues below the previous one
+    async fn collect_history_pages(
+        localstore: &impl LocalStore,
+        filter: &HistoryFilter,
+        limit: usize,
+    ) -> anyhow::Result<Vec<u64>> {
+        let mut ids = vec![];
+        let mut before: Option<PageCursor> = None;
+        loop {
+            let mut tx = localstore.begin_tx().await?;
+            let page = localstore
+                .get_history_page(&mut tx, filter, before.as_ref(), limit)
+                .await?;
+            tx.commit().await?;
+            assert!(page.entries.iter().all(|entry| filter.matches(entry)));
+            for entry in &page.entries {
+                if let Some(before) = before {
+                    assert!(before.is_before(entry.created_at, entry.id.unwrap()));
+                }
+                before = Some(PageCursor::new(entry.created_at, entry.id.unwrap()));
+                ids.push(entry.id.unwrap());
+            }
+            match page.next {
+                Some(next) => {
+                    assert_eq!(limit, page.entries.len());
+                    assert_eq!(before, Some(next));
+                }
+                None => {
+                    assert!(page.entries.len() <= limit);
+                    return Ok(ids);
+                }
+            }
+        }
+    }
+
+    async fn check_history_pages(localstore: impl LocalStore) -> anyhow::Result<()> {
+        add_history_entries(&localstore, 10_000, 1_000).await?;
+        let mut tx = localstore.begin_tx().await?;
+        let history = localstore.get_history(&mut tx).await?;
+        tx.commit().await?;
+
+        // newest first, without duplicates or omissions
+        let mut newest_first = history.clone();
+        newest_first.sort_by_key(|entry| std::cmp::Reverse((entry.created_at, entry.id)));
+        let expected = newest_first
+            .iter()
+            .map(|entry| entry.id.unwrap())
+            .collect::<Vec<_>>();
+        for limit in [7, 333, 1_000, 10_000, 20_000] {
+            assert_eq!(
+                expected,
+                collect_history_pages(&localstore, &HistoryFilter::default(), limit).await?
+            );
+        }
+
+        let filter = HistoryFilter {
+            kind: Some(HistoryKind::Melt),
+            mint_url: Some(Url::parse("http://localhost:3339/")?),
+        };
+        let filtered = newest_first
+            .iter()
+            .filter(|entry| filter.matches(entry))
+            .map(|entry| entry.id.unwrap())
+            .collect::<Vec<_>>();
+        assert!(!filtered.is_empty());
+        assert_eq!(
+            filtered,
+            collect_history_pages(&localstore, &filter, 100).await?
+        );
+
+        // entries that are added while paging are not returned, also those of the same second
+        let mut tx = localstore.begin_tx().await?;
+        let first = localstore
+            .get_history_page(&mut tx, &HistoryFilter::default(), None, 500)
+            .await?;
+        tx.commit().await?;
+        let newest = newest_first[0].created_at;
+        add_history_entries(&localstore, 7, newest).await?;
+        add_history_entries(&localstore, 100, newest + 1).await?;
+        let mut ids = first
+            .entries
+            .iter()
+            .map(|entry| entry.id.unwrap())
+            .collect::<Vec<_>>();
+        let mut before = first.next;
+        while let Some(cursor) = before {
+            let mut tx = localstore.begin_tx().await?;
+            let page = localstore
+                .get_history_page(&mut tx, &HistoryFilter::default(), Some(&cursor), 500)
+                .await?;
+            tx.commit().await?;
+            ids.extend(page.entries.iter().map(|entry| entry.id.unwrap()));
+            before = page.next;
+        }
+        assert_eq!(expected, ids);
+        assert_eq!(10_000, ids.iter().collect::<HashSet<_>>().len());
+        Ok(())
+    }
+
+    async fn check_operations(localstore: impl LocalStore) -> anyhow::Result<()> {
+        let mut tx = localstore.begin_tx().await?;
+        let operation = Operation {
+            id: OperationId::from_parts(1_000_000, 1),
+            kind: OperationKind::PayInvoice,
+            created_at: 1_000,
+            finished_at: None,
+            error: None,
+        };
+        let other = OperationId::from_parts(1_000_000, 2);
+        localstore.add_operation(&mut tx, &operation).await?;
+        localstore
+            .add_operation(
+                &mut tx,
+                &Operation {
+                    id: other.clone(),
+                    ..operation.clone()
+                },
+            )
+            .await?;
+        assert_eq!(
+            Some(operation.clone()),
+            localstore.get_operation(&mut tx, &operation.id).await?
+        );
+        assert_eq!(
+            None,
+            localstore
+                .get_operation(&mut tx, &OperationId::from_parts(1_000_000, 3))
+                .await?
+        );
+
+        let finished = Operation {
+            finished_at: Some(1_010),
+            error: Some("failed".to_owned()),
+            ..operation.clone()
+        };
+        localstore.update_operation(&mut tx, &finished).await?;
+        assert_eq!(
+            Some(finished.clone()),
+            localstore.get_operation(&mut tx, &operation.id).await?
+        );
+        assert_eq!(
+            vec![
+                finished,
+                Operation {
+                    id: other.clone(),
+                    ..operation.clone()
+                }
+            ],
+            localstore.get_operations(&mut tx).await?
+        );
+
+        let records = vec![
+            OperationRecord::Proof("secret".to_owned()),
+            OperationRecord::PendingMelt(7),
+            OperationRecord::PendingMint("quote".to_owned()),
+            OperationRecord::MultiPayment(3),
+        ];
+        localstore
+            .add_operation_records(&mut tx, &operation.id, &records[..2])
+            .await?;
+        localstore
+            .add_operation_records(&mut tx, &other, &records[..1])
+            .await?;
+        // linking a record twice keeps the first link
+        localstore
+            .add_operation_records(&mut tx, &operation.id, &records[1..])
+            .await?;
+        assert_eq!(
+            records,
+            localstore
+                .get_operation_records(&mut tx, &operation.id)
+                .await?
+        );
+        assert_eq!(
+            records[..1].to_vec(),
+            localstore.get_operation_records(&mut tx, &other).await?
+        );
+        tx.commit().await?;
+        Ok(())
+    }
 }
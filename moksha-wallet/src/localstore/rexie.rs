@@ -1,7 +1,14 @@
-use super::{LocalStore, RexieTransaction, WalletKeyset};
-use crate::error::MokshaWalletError;
+use super::{
+    BlindRecovery, HistoryEntry, HistoryFilter, HistoryPage, LocalStore, MultiPayment, Operation,
+    OperationRecord, PendingMelt, PendingMint, ProofStatus, ProofWithMetadata, QueuedReceive,
+    ReservedProof, RexieTransaction, Snapshot, WalletKeyset,
+};
+use crate::{error::MokshaWalletError, operation::OperationId, stats::EndpointSamples};
 use async_trait::async_trait;
-use moksha_core::proof::{Proof, Proofs};
+use moksha_core::{
+    page::PageCursor,
+    proof::{Proof, Proofs},
+};
 use rexie::*;
 use wasm_bindgen::JsValue;
 
@@ -27,8 +34,12 @@ impl RexieLocalStore {
     }
 
     fn get_key(proof: &Proof) -> JsValue {
+        Self::get_key_for_secret(&proof.secret)
+    }
+
+    fn get_key_for_secret(secret: &str) -> JsValue {
         let key = serde_json::json!({
-            "key": proof.secret,
+            "key": secret,
         });
         let key = serde_json::to_string(&key).unwrap();
         serde_wasm_bindgen::to_value(&key).unwrap()
@@ -38,6 +49,12 @@ impl RexieLocalStore {
 #[async_trait(?Send)]
 impl LocalStore for RexieLocalStore {
     // FIXME implement tx-handling for Rexie
+    type Tx = RexieTransaction;
+
+    async fn begin_tx(&self) -> Result<RexieTransaction, MokshaWalletError> {
+        Ok(RexieTransaction {})
+    }
+
     async fn add_proofs(
         &self,
         _tx: &mut RexieTransaction,
@@ -110,11 +127,31 @@ impl LocalStore for RexieLocalStore {
         Ok(())
     }
 
+    async fn delete_proofs_by_secrets(
+        &self,
+        _tx: &mut RexieTransaction,
+        secrets: &[String],
+    ) -> std::result::Result<(), MokshaWalletError> {
+        let db = Self::get_rexie().await;
+        let transaction = db
+            .transaction(&[STORE_NAME], rexie::TransactionMode::ReadWrite)
+            .expect("db error");
+        let store = transaction.store(STORE_NAME).expect("db error");
+        for secret in secrets {
+            store
+                .delete(&Self::get_key_for_secret(secret))
+                .await
+                .expect("db error");
+        }
+        transaction.done().await.expect("db error");
+        Ok(())
+    }
+
     async fn get_keysets(
         &self,
         _tx: &mut RexieTransaction,
     ) -> std::result::Result<Vec<WalletKeyset>, MokshaWalletError> {
-        todo!()
+        Err(MokshaWalletError::UnsupportedByLocalStore("get_keysets"))
     }
 
     async fn upsert_keyset(
@@ -122,7 +159,7 @@ impl LocalStore for RexieLocalStore {
         _tx: &mut RexieTransaction,
         _keyset: &WalletKeyset,
     ) -> std::result::Result<(), MokshaWalletError> {
-        todo!()
+        Err(MokshaWalletError::UnsupportedByLocalStore("upsert_keyset"))
     }
 
     async fn update_keyset_last_index(
@@ -130,7 +167,9 @@ impl LocalStore for RexieLocalStore {
         _tx: &mut RexieTransaction,
         _keyset: &WalletKeyset,
     ) -> std::result::Result<(), MokshaWalletError> {
-        todo!()
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "update_keyset_last_index",
+        ))
     }
 
     async fn add_seed(
@@ -138,13 +177,362 @@ impl LocalStore for RexieLocalStore {
         _tx: &mut RexieTransaction,
         _seed_words: &str,
     ) -> std::result::Result<(), MokshaWalletError> {
-        todo!()
+        Err(MokshaWalletError::UnsupportedByLocalStore("add_seed"))
     }
 
     async fn get_seed(
         &self,
         _tx: &mut RexieTransaction,
     ) -> std::result::Result<Option<String>, MokshaWalletError> {
-        todo!()
+        Err(MokshaWalletError::UnsupportedByLocalStore("get_seed"))
+    }
+
+    async fn add_queued_receive(
+        &self,
+        _tx: &mut RexieTransaction,
+        _queued: &QueuedReceive,
+    ) -> std::result::Result<u64, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "add_queued_receive",
+        ))
+    }
+
+    async fn get_queued_receives(
+        &self,
+        _tx: &mut RexieTransaction,
+    ) -> std::result::Result<Vec<QueuedReceive>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_queued_receives",
+        ))
+    }
+
+    async fn update_queued_receive(
+        &self,
+        _tx: &mut RexieTransaction,
+        _queued: &QueuedReceive,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "update_queued_receive",
+        ))
+    }
+
+    async fn delete_queued_receive(
+        &self,
+        _tx: &mut RexieTransaction,
+        _id: u64,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "delete_queued_receive",
+        ))
+    }
+
+    async fn add_blind_recovery(
+        &self,
+        _tx: &mut RexieTransaction,
+        _records: &[BlindRecovery],
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "add_blind_recovery",
+        ))
+    }
+
+    async fn take_blind_recovery(
+        &self,
+        _tx: &mut RexieTransaction,
+    ) -> std::result::Result<Vec<BlindRecovery>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "take_blind_recovery",
+        ))
+    }
+
+    async fn delete_blind_recovery(
+        &self,
+        _tx: &mut RexieTransaction,
+        _records: &[BlindRecovery],
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "delete_blind_recovery",
+        ))
+    }
+
+    async fn add_pending_mint(
+        &self,
+        _tx: &mut RexieTransaction,
+        _pending_mint: &PendingMint,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "add_pending_mint",
+        ))
+    }
+
+    async fn get_pending_mint(
+        &self,
+        _tx: &mut RexieTransaction,
+        _quote_id: &str,
+    ) -> std::result::Result<Option<PendingMint>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_pending_mint",
+        ))
+    }
+
+    async fn update_pending_mint(
+        &self,
+        _tx: &mut RexieTransaction,
+        _pending_mint: &PendingMint,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "update_pending_mint",
+        ))
+    }
+
+    async fn claim_mint_hash(
+        &self,
+        _tx: &mut RexieTransaction,
+        _quote_id: &str,
+        _claimed_at: u64,
+        _stale_before: u64,
+    ) -> std::result::Result<bool, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "claim_mint_hash",
+        ))
+    }
+
+    async fn add_multi_payment(
+        &self,
+        _tx: &mut RexieTransaction,
+        _payment: &MultiPayment,
+    ) -> std::result::Result<u64, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "add_multi_payment",
+        ))
+    }
+
+    async fn get_multi_payments(
+        &self,
+        _tx: &mut RexieTransaction,
+    ) -> std::result::Result<Vec<MultiPayment>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_multi_payments",
+        ))
+    }
+
+    async fn update_multi_payment(
+        &self,
+        _tx: &mut RexieTransaction,
+        _payment: &MultiPayment,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "update_multi_payment",
+        ))
+    }
+
+    async fn add_pending_melt(
+        &self,
+        _tx: &mut RexieTransaction,
+        _pending_melt: &PendingMelt,
+    ) -> std::result::Result<u64, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "add_pending_melt",
+        ))
+    }
+
+    async fn get_pending_melts(
+        &self,
+        _tx: &mut RexieTransaction,
+    ) -> std::result::Result<Vec<PendingMelt>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_pending_melts",
+        ))
+    }
+
+    async fn update_pending_melt(
+        &self,
+        _tx: &mut RexieTransaction,
+        _pending_melt: &PendingMelt,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "update_pending_melt",
+        ))
+    }
+
+    async fn remove_pending_melt(
+        &self,
+        _tx: &mut RexieTransaction,
+        _id: u64,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "remove_pending_melt",
+        ))
+    }
+
+    async fn add_snapshot(
+        &self,
+        _tx: &mut RexieTransaction,
+        _snapshot: &Snapshot,
+    ) -> std::result::Result<u64, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore("add_snapshot"))
+    }
+
+    async fn get_snapshots(
+        &self,
+        _tx: &mut RexieTransaction,
+    ) -> std::result::Result<Vec<Snapshot>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore("get_snapshots"))
+    }
+
+    async fn delete_snapshot(
+        &self,
+        _tx: &mut RexieTransaction,
+        _id: u64,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "delete_snapshot",
+        ))
+    }
+
+    async fn get_proofs_by_state(
+        &self,
+        _tx: &mut RexieTransaction,
+        _state: ProofStatus,
+    ) -> std::result::Result<Proofs, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_proofs_by_state",
+        ))
+    }
+
+    async fn get_proofs_with_metadata(
+        &self,
+        _tx: &mut RexieTransaction,
+        _state: ProofStatus,
+    ) -> std::result::Result<Vec<ProofWithMetadata>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_proofs_with_metadata",
+        ))
+    }
+
+    async fn transition_proofs(
+        &self,
+        _tx: &mut RexieTransaction,
+        _secrets: &[String],
+        _from: ProofStatus,
+        _to: ProofStatus,
+        _changed_at: u64,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "transition_proofs",
+        ))
+    }
+
+    async fn get_reserved_proofs(
+        &self,
+        _tx: &mut RexieTransaction,
+    ) -> std::result::Result<Vec<ReservedProof>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_reserved_proofs",
+        ))
+    }
+
+    async fn get_endpoint_stats(
+        &self,
+        _tx: &mut RexieTransaction,
+        _mint_url: &url::Url,
+    ) -> std::result::Result<Vec<EndpointSamples>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_endpoint_stats",
+        ))
+    }
+
+    async fn upsert_endpoint_stats(
+        &self,
+        _tx: &mut RexieTransaction,
+        _mint_url: &url::Url,
+        _samples: &EndpointSamples,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "upsert_endpoint_stats",
+        ))
+    }
+
+    async fn add_operation(
+        &self,
+        _tx: &mut RexieTransaction,
+        _operation: &Operation,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore("add_operation"))
+    }
+
+    async fn update_operation(
+        &self,
+        _tx: &mut RexieTransaction,
+        _operation: &Operation,
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "update_operation",
+        ))
+    }
+
+    async fn get_operation(
+        &self,
+        _tx: &mut RexieTransaction,
+        _id: &OperationId,
+    ) -> std::result::Result<Option<Operation>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore("get_operation"))
+    }
+
+    async fn get_operations(
+        &self,
+        _tx: &mut RexieTransaction,
+    ) -> std::result::Result<Vec<Operation>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore("get_operations"))
+    }
+
+    async fn add_operation_records(
+        &self,
+        _tx: &mut RexieTransaction,
+        _id: &OperationId,
+        _records: &[OperationRecord],
+    ) -> std::result::Result<(), MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "add_operation_records",
+        ))
+    }
+
+    async fn get_operation_records(
+        &self,
+        _tx: &mut RexieTransaction,
+        _id: &OperationId,
+    ) -> std::result::Result<Vec<OperationRecord>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_operation_records",
+        ))
+    }
+
+    async fn add_history_entry(
+        &self,
+        _tx: &mut RexieTransaction,
+        _entry: &HistoryEntry,
+    ) -> std::result::Result<u64, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "add_history_entry",
+        ))
+    }
+
+    async fn get_history(
+        &self,
+        _tx: &mut RexieTransaction,
+    ) -> std::result::Result<Vec<HistoryEntry>, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore("get_history"))
+    }
+
+    async fn get_history_page(
+        &self,
+        _tx: &mut RexieTransaction,
+        _filter: &HistoryFilter,
+        _before: Option<&PageCursor>,
+        _limit: usize,
+    ) -> std::result::Result<HistoryPage, MokshaWalletError> {
+        Err(MokshaWalletError::UnsupportedByLocalStore(
+            "get_history_page",
+        ))
     }
 }
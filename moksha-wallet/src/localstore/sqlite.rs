@@ -1,15 +1,32 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use async_trait::async_trait;
+use moksha_core::blind::BlindedMessage;
 use moksha_core::keyset::KeysetId;
+use moksha_core::page::PageCursor;
 use moksha_core::proof::{Proof, Proofs};
 use secp256k1::PublicKey;
 use url::Url;
 
+use crate::clock::{Clock, SystemClock};
 use crate::error::MokshaWalletError;
-use crate::localstore::{LocalStore, WalletKeyset};
+use crate::localstore::{
+    blob, BlindRecovery, HistoryEntry, HistoryFilter, HistoryKind, HistoryPage, LocalStore,
+    MultiPayment, MultiPaymentStage, Operation, OperationKind, OperationRecord, PendingMelt,
+    PendingMint, ProofStatus, ProofWithMetadata, QueuedReceive, QueuedReceiveStatus, ReservedProof,
+    Snapshot, WalletKeyset,
+};
+use crate::operation::OperationId;
+use crate::stats::EndpointSamples;
 
-use sqlx::sqlite::SqliteError;
+use sqlx::Connection;
+
+/// Version of the schema written by this wallet, increased with every migration
+pub const STORE_VERSION: u32 = 5;
+
+/// Oldest [`STORE_VERSION`] of a wallet that can still use a store migrated by this wallet.
+/// Only increased by migrations that older wallets can't work with.
+pub const STORE_MIN_COMPATIBLE_VERSION: u32 = 1;
 
 #[derive(Clone, Debug)]
 pub struct SqliteLocalStore {
@@ -18,66 +35,87 @@ pub struct SqliteLocalStore {
 
 #[async_trait(?Send)]
 impl LocalStore for SqliteLocalStore {
-    type DB = sqlx::Sqlite;
+    type Tx = sqlx::Transaction<'static, sqlx::Sqlite>;
 
-    async fn begin_tx(&self) -> Result<sqlx::Transaction<Self::DB>, MokshaWalletError> {
+    async fn begin_tx(&self) -> Result<Self::Tx, MokshaWalletError> {
         Ok(self.pool.begin().await?)
     }
 
     async fn delete_proofs(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         proofs: &Proofs,
     ) -> Result<(), MokshaWalletError> {
-        let proof_secrets = proofs
-            .proofs()
-            .iter()
-            .map(|p| p.secret.to_owned())
-            .collect::<Vec<_>>();
-
-        let placeholders: Vec<String> = (1..=proof_secrets.len())
-            .map(|i| format!("?{}", i))
-            .collect();
-
-        let sql = format!(
-            "DELETE FROM proofs WHERE secret IN ({})",
-            placeholders.join(",")
-        );
-        let mut query = sqlx::query(&sql);
-        for secret in &proof_secrets {
-            query = query.bind(secret);
+        if proofs.is_empty() {
+            return Ok(());
         }
-        query.execute(&mut **tx).await?;
+        let proofs = serde_json::to_string(
+            &proofs
+                .proofs()
+                .into_iter()
+                .map(|proof| (proof.secret, proof.amount))
+                .collect::<Vec<_>>(),
+        )?;
+        sqlx::query("DELETE FROM proofs WHERE EXISTS (SELECT 1 FROM json_each(?1) WHERE proofs.secret = json_extract(value, '$[0]') AND proofs.amount = json_extract(value, '$[1]'))")
+            .bind(proofs)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
 
+    async fn delete_proofs_by_secrets(
+        &self,
+        tx: &mut Self::Tx,
+        secrets: &[String],
+    ) -> Result<(), MokshaWalletError> {
+        if secrets.is_empty() {
+            return Ok(());
+        }
+        // a single json parameter instead of one per secret, sqlite limits the number of
+        // parameters of a query
+        let secrets = serde_json::to_string(secrets)?;
+        sqlx::query("DELETE FROM proofs WHERE secret IN (SELECT value FROM json_each(?1))")
+            .bind(secrets)
+            .execute(&mut **tx)
+            .await?;
         Ok(())
     }
 
     async fn add_proofs(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         proofs: &Proofs,
     ) -> Result<(), MokshaWalletError> {
+        let created_at = SystemClock.now() as i64;
         for proof in proofs.proofs() {
             let c = proof.c.to_string();
             let amount = proof.amount as i64;
             sqlx::query!(
-                "INSERT INTO proofs (keyset_id, amount, C, secret, time_created) VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP);",
-            proof.keyset_id,amount, c, proof.secret )
+                "INSERT INTO proofs (keyset_id, amount, C, secret, time_created, created_at) VALUES ($1, $2, $3, $4, CURRENT_TIMESTAMP, $5);",
+            proof.keyset_id,amount, c, proof.secret, created_at)
             .execute(&mut **tx)
             .await?;
         }
         Ok(())
     }
 
-    async fn get_proofs(
+    async fn get_proofs(&self, tx: &mut Self::Tx) -> Result<Proofs, MokshaWalletError> {
+        self.get_proofs_by_state(tx, ProofStatus::Spendable).await
+    }
+
+    async fn get_proofs_by_state(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
+        state: ProofStatus,
     ) -> Result<Proofs, MokshaWalletError> {
-        let rows = sqlx::query!("SELECT keyset_id, amount, C, secret FROM proofs;")
-            .fetch_all(&mut **tx)
-            .await?;
+        let state = state.to_string();
+        let rows = sqlx::query!(
+            "SELECT keyset_id, amount, C, secret FROM proofs WHERE state = $1;",
+            state
+        )
+        .fetch_all(&mut **tx)
+        .await?;
 
-        // FIXME read time_created
         Ok(rows
             .into_iter()
             .map(|row| Proof {
@@ -91,9 +129,163 @@ impl LocalStore for SqliteLocalStore {
             .into())
     }
 
+    async fn get_proofs_with_metadata(
+        &self,
+        tx: &mut Self::Tx,
+        state: ProofStatus,
+    ) -> Result<Vec<ProofWithMetadata>, MokshaWalletError> {
+        let state_name = state.to_string();
+        let rows = sqlx::query!(
+            "SELECT keyset_id, amount, C, secret, created_at FROM proofs WHERE state = $1 ORDER BY created_at, rowid;",
+            state_name
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ProofWithMetadata {
+                proof: Proof {
+                    keyset_id: row.keyset_id,
+                    amount: row.amount as u64,
+                    c: row.C.parse().expect("Invalid Pubkey"),
+                    secret: row.secret,
+                    script: None,
+                },
+                state,
+                created_at: row.created_at as u64,
+            })
+            .collect())
+    }
+
+    async fn transition_proofs(
+        &self,
+        tx: &mut Self::Tx,
+        secrets: &[String],
+        from: ProofStatus,
+        to: ProofStatus,
+        changed_at: u64,
+    ) -> Result<(), MokshaWalletError> {
+        let secrets = secrets.iter().collect::<HashSet<_>>();
+        if secrets.is_empty() {
+            return Ok(());
+        }
+
+        let placeholders = (4..secrets.len() + 4)
+            .map(|i| format!("?{i}"))
+            .collect::<Vec<_>>();
+        let sql = format!(
+            "UPDATE proofs SET state = ?1, reserved_at = ?2 WHERE state = ?3 AND secret IN ({})",
+            placeholders.join(",")
+        );
+        // spendable proofs are not reserved
+        let reserved_at = (to != ProofStatus::Spendable).then_some(changed_at as i64);
+        let mut query = sqlx::query(&sql)
+            .bind(to.to_string())
+            .bind(reserved_at)
+            .bind(from.to_string());
+        for secret in &secrets {
+            query = query.bind(secret);
+        }
+
+        // the savepoint undoes the partial update, if a proof is not in the expected state
+        let mut savepoint = (**tx).begin().await?;
+        let updated = query.execute(&mut *savepoint).await?.rows_affected() as usize;
+        if updated != secrets.len() {
+            savepoint.rollback().await?;
+            return Err(MokshaWalletError::InvalidProofTransition {
+                from,
+                to,
+                count: secrets.len() - updated,
+            });
+        }
+        savepoint.commit().await?;
+        Ok(())
+    }
+
+    async fn get_reserved_proofs(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<ReservedProof>, MokshaWalletError> {
+        let rows = sqlx::query!(
+            r#"SELECT keyset_id, amount, C, secret, state, reserved_at as "reserved_at!" FROM proofs WHERE state IN ('reserved', 'pending_melt') AND reserved_at IS NOT NULL;"#
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(ReservedProof {
+                    proof: Proof {
+                        keyset_id: row.keyset_id,
+                        amount: row.amount as u64,
+                        c: row.C.parse().expect("Invalid Pubkey"),
+                        secret: row.secret,
+                        script: None,
+                    },
+                    state: match row.state.as_str() {
+                        "reserved" => ProofStatus::Reserved,
+                        "pending_melt" => ProofStatus::PendingMelt,
+                        _ => return Err(invalid_value("proof state", &row.state)),
+                    },
+                    reserved_at: row.reserved_at as u64,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_endpoint_stats(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+    ) -> Result<Vec<EndpointSamples>, MokshaWalletError> {
+        let mint_url = mint_url.as_str();
+        let rows = sqlx::query!(
+            "SELECT endpoint, requests, errors, latencies FROM endpoint_stats WHERE mint_url = $1 ORDER BY endpoint;",
+            mint_url
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(EndpointSamples {
+                    endpoint: row
+                        .endpoint
+                        .parse()
+                        .map_err(|_| invalid_value("endpoint", &row.endpoint))?,
+                    requests: row.requests as u64,
+                    errors: row.errors as u64,
+                    latencies: serde_json::from_str(&row.latencies)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn upsert_endpoint_stats(
+        &self,
+        tx: &mut Self::Tx,
+        mint_url: &Url,
+        samples: &EndpointSamples,
+    ) -> Result<(), MokshaWalletError> {
+        let mint_url = mint_url.as_str();
+        let endpoint = samples.endpoint.to_string();
+        let requests = samples.requests as i64;
+        let errors = samples.errors as i64;
+        let latencies = serde_json::to_string(&samples.latencies)?;
+        sqlx::query!(
+            r#"INSERT INTO endpoint_stats (mint_url, endpoint, requests, errors, latencies) VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT(mint_url, endpoint) DO UPDATE SET requests = $3, errors = $4, latencies = $5;"#,
+            mint_url, endpoint, requests, errors, latencies
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
     async fn upsert_keyset(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         keyset: &WalletKeyset,
     ) -> Result<(), MokshaWalletError> {
         let keyset_id = keyset.keyset_id.to_string();
@@ -101,20 +293,18 @@ impl LocalStore for SqliteLocalStore {
         let currency_unit = keyset.currency_unit.to_string();
         let last_index = keyset.last_index as i64;
         let public_keys = serde_json::to_string(&keyset.public_keys)?;
+        let input_fee_ppk = keyset.input_fee_ppk as i64;
         sqlx::query!(
-            r#"INSERT INTO keysets (keyset_id, mint_url, currency_unit, last_index, public_keys, active) VALUES ($1, $2, $3, $4, $5, $6)
-            ON CONFLICT(keyset_id, mint_url) DO UPDATE SET currency_unit = $3, public_keys = $5, active = $6;
-            "#,keyset_id, mint_url, currency_unit, last_index, public_keys, keyset.active)
+            r#"INSERT INTO keysets (keyset_id, mint_url, currency_unit, last_index, public_keys, active, input_fee_ppk) VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT(keyset_id, mint_url) DO UPDATE SET currency_unit = $3, public_keys = $5, active = $6, input_fee_ppk = $7;
+            "#,keyset_id, mint_url, currency_unit, last_index, public_keys, keyset.active, input_fee_ppk)
         .execute(&mut **tx)
         .await?;
         Ok(())
     }
 
-    async fn get_keysets(
-        &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-    ) -> Result<Vec<WalletKeyset>, MokshaWalletError> {
-        let rows = sqlx::query!("SELECT id, mint_url, keyset_id, currency_unit, active, last_index, public_keys FROM keysets;")
+    async fn get_keysets(&self, tx: &mut Self::Tx) -> Result<Vec<WalletKeyset>, MokshaWalletError> {
+        let rows = sqlx::query!("SELECT id, mint_url, keyset_id, currency_unit, active, last_index, public_keys, input_fee_ppk FROM keysets;")
             .fetch_all(&mut **tx)
             .await?;
 
@@ -122,15 +312,14 @@ impl LocalStore for SqliteLocalStore {
             .iter()
             .map(|row| {
                 let id: i64 = row.id;
-                let mint_url: Url = Url::parse(&row.mint_url).expect("invalid URL in localstore");
+                let mint_url: Url = Url::parse(&row.mint_url)?;
                 let keyset_id: KeysetId =
                     KeysetId::new(&row.keyset_id).expect("invalid keyset_id in localstore");
                 let currency_unit: String = row.currency_unit.clone();
                 let active: bool = row.active;
                 let last_index: i64 = row.last_index;
                 let public_keys: String = row.public_keys.clone();
-                let public_keys: HashMap<u64, PublicKey> =
-                    serde_json::from_str(&public_keys).expect("invalid json in localstore");
+                let public_keys: HashMap<u64, PublicKey> = serde_json::from_str(&public_keys)?;
                 Ok(WalletKeyset {
                     id: Some(id as u64),
                     mint_url,
@@ -139,14 +328,15 @@ impl LocalStore for SqliteLocalStore {
                     active,
                     last_index: last_index as u64,
                     public_keys,
+                    input_fee_ppk: row.input_fee_ppk as u64,
                 })
             })
-            .collect::<Result<Vec<WalletKeyset>, SqliteError>>()?)
+            .collect::<Result<Vec<WalletKeyset>, MokshaWalletError>>()?)
     }
 
     async fn update_keyset_last_index(
         &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
+        tx: &mut Self::Tx,
         keyset: &WalletKeyset,
     ) -> Result<(), MokshaWalletError> {
         let id = match keyset.id {
@@ -165,21 +355,14 @@ impl LocalStore for SqliteLocalStore {
         Ok(())
     }
 
-    async fn add_seed(
-        &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-        seed_words: &str,
-    ) -> Result<(), MokshaWalletError> {
+    async fn add_seed(&self, tx: &mut Self::Tx, seed_words: &str) -> Result<(), MokshaWalletError> {
         sqlx::query!("INSERT INTO seed (seed_words) VALUES ($1);", seed_words)
             .execute(&mut **tx)
             .await?;
         Ok(())
     }
 
-    async fn get_seed(
-        &self,
-        tx: &mut sqlx::Transaction<Self::DB>,
-    ) -> Result<Option<String>, MokshaWalletError> {
+    async fn get_seed(&self, tx: &mut Self::Tx) -> Result<Option<String>, MokshaWalletError> {
         let row = sqlx::query!("SELECT seed_words FROM seed;")
             .fetch_all(&mut **tx)
             .await?;
@@ -189,6 +372,628 @@ impl LocalStore for SqliteLocalStore {
             _ => Err(MokshaWalletError::MultipleSeeds),
         }
     }
+
+    async fn add_queued_receive(
+        &self,
+        tx: &mut Self::Tx,
+        queued: &QueuedReceive,
+    ) -> Result<u64, MokshaWalletError> {
+        let attempts = queued.attempts as i64;
+        let next_attempt = queued.next_attempt as i64;
+        let status = queued.status.to_string();
+        let result = sqlx::query!(
+            "INSERT INTO receive_queue (token, attempts, next_attempt, status, last_error) VALUES ($1, $2, $3, $4, $5);",
+            queued.token, attempts, next_attempt, status, queued.last_error)
+        .execute(&mut **tx)
+        .await?;
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    async fn get_queued_receives(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<QueuedReceive>, MokshaWalletError> {
+        let rows = sqlx::query!(
+            "SELECT id, token, attempts, next_attempt, status, last_error FROM receive_queue ORDER BY id;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(QueuedReceive {
+                    id: Some(row.id as u64),
+                    token: row.token,
+                    attempts: row.attempts as u32,
+                    next_attempt: row.next_attempt as u64,
+                    status: match row.status.as_str() {
+                        "pending" => QueuedReceiveStatus::Pending,
+                        "failed" => QueuedReceiveStatus::Failed,
+                        _ => return Err(invalid_value("receive queue status", &row.status)),
+                    },
+                    last_error: row.last_error,
+                })
+            })
+            .collect()
+    }
+
+    async fn update_queued_receive(
+        &self,
+        tx: &mut Self::Tx,
+        queued: &QueuedReceive,
+    ) -> Result<(), MokshaWalletError> {
+        let id = match queued.id {
+            None => return Err(MokshaWalletError::IdNotSet),
+            Some(id) => id as i64,
+        };
+        let attempts = queued.attempts as i64;
+        let next_attempt = queued.next_attempt as i64;
+        let status = queued.status.to_string();
+
+        sqlx::query!(
+            "UPDATE receive_queue SET attempts = $1, next_attempt = $2, status = $3, last_error = $4 WHERE id = $5;",
+            attempts, next_attempt, status, queued.last_error, id)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn delete_queued_receive(
+        &self,
+        tx: &mut Self::Tx,
+        id: u64,
+    ) -> Result<(), MokshaWalletError> {
+        let id = id as i64;
+        sqlx::query!("DELETE FROM receive_queue WHERE id = $1;", id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_blind_recovery(
+        &self,
+        tx: &mut Self::Tx,
+        records: &[BlindRecovery],
+    ) -> Result<(), MokshaWalletError> {
+        for record in records {
+            let b_ = record.blinded_message.b_.to_string();
+            let mint_url = record.mint_url.as_str();
+            let amount = record.blinded_message.amount as i64;
+            sqlx::query!(
+                "INSERT INTO blind_recovery (b_, mint_url, keyset_id, amount, secret, blinding_factor) VALUES ($1, $2, $3, $4, $5, $6);",
+                b_, mint_url, record.blinded_message.id, amount, record.secret, record.blinding_factor)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn take_blind_recovery(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<BlindRecovery>, MokshaWalletError> {
+        let rows = sqlx::query!(
+            "SELECT b_, mint_url, keyset_id, amount, secret, blinding_factor FROM blind_recovery;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+        sqlx::query!("DELETE FROM blind_recovery;")
+            .execute(&mut **tx)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(BlindRecovery {
+                    mint_url: Url::parse(&row.mint_url)?,
+                    blinded_message: BlindedMessage {
+                        amount: row.amount as u64,
+                        b_: row.b_.parse().expect("Invalid Pubkey"),
+                        id: row.keyset_id,
+                    },
+                    secret: row.secret,
+                    blinding_factor: row.blinding_factor,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_blind_recovery(
+        &self,
+        tx: &mut Self::Tx,
+        records: &[BlindRecovery],
+    ) -> Result<(), MokshaWalletError> {
+        for record in records {
+            let b_ = record.blinded_message.b_.to_string();
+            sqlx::query!("DELETE FROM blind_recovery WHERE b_ = $1;", b_)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn add_pending_mint(
+        &self,
+        tx: &mut Self::Tx,
+        pending_mint: &PendingMint,
+    ) -> Result<(), MokshaWalletError> {
+        let mint_url = pending_mint.mint_url.as_str();
+        let outputs = blob::encode(&pending_mint.outputs)?;
+        let claimed_at = pending_mint.claimed_at.map(|t| t as i64);
+        let proofs = pending_mint.proofs.as_ref().map(blob::encode).transpose()?;
+        sqlx::query!(
+            "INSERT INTO pending_mints (quote_id, mint_url, keyset_id, outputs, claimed_at, proofs) VALUES ($1, $2, $3, $4, $5, $6);",
+            pending_mint.quote_id, mint_url, pending_mint.keyset_id, outputs, claimed_at, proofs)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_pending_mint(
+        &self,
+        tx: &mut Self::Tx,
+        quote_id: &str,
+    ) -> Result<Option<PendingMint>, MokshaWalletError> {
+        let row = sqlx::query!(
+            "SELECT quote_id as \"quote_id!\", mint_url, keyset_id, outputs, claimed_at, proofs FROM pending_mints WHERE quote_id = $1;",
+            quote_id
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        row.map(|row| {
+            Ok(PendingMint {
+                quote_id: row.quote_id,
+                mint_url: Url::parse(&row.mint_url)?,
+                keyset_id: row.keyset_id,
+                outputs: blob::decode(&row.outputs)?,
+                claimed_at: row.claimed_at.map(|t| t as u64),
+                proofs: row.proofs.map(|proofs| blob::decode(&proofs)).transpose()?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn update_pending_mint(
+        &self,
+        tx: &mut Self::Tx,
+        pending_mint: &PendingMint,
+    ) -> Result<(), MokshaWalletError> {
+        let claimed_at = pending_mint.claimed_at.map(|t| t as i64);
+        let proofs = pending_mint.proofs.as_ref().map(blob::encode).transpose()?;
+        sqlx::query!(
+            "UPDATE pending_mints SET claimed_at = $1, proofs = $2 WHERE quote_id = $3;",
+            claimed_at,
+            proofs,
+            pending_mint.quote_id
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn claim_mint_hash(
+        &self,
+        tx: &mut Self::Tx,
+        quote_id: &str,
+        claimed_at: u64,
+        stale_before: u64,
+    ) -> Result<bool, MokshaWalletError> {
+        let claimed_at = claimed_at as i64;
+        let stale_before = stale_before as i64;
+        let result = sqlx::query!(
+            "UPDATE pending_mints SET claimed_at = $1 WHERE quote_id = $2 AND proofs IS NULL AND (claimed_at IS NULL OR claimed_at < $3);",
+            claimed_at,
+            quote_id,
+            stale_before
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(result.rows_affected() == 1)
+    }
+
+    async fn add_multi_payment(
+        &self,
+        tx: &mut Self::Tx,
+        payment: &MultiPayment,
+    ) -> Result<u64, MokshaWalletError> {
+        let source_mint_url = payment.source_mint_url.as_str();
+        let target_mint_url = payment.target_mint_url.as_str();
+        let transfer_amount = payment.transfer_amount as i64;
+        let transfer_fees = payment.transfer_fees as i64;
+        let payment_fees = payment.payment_fees as i64;
+        let stage = payment.stage.to_string();
+        let result = sqlx::query!(
+            r#"INSERT INTO multi_payments (invoice, source_mint_url, target_mint_url, transfer_amount, transfer_quote, transfer_invoice, transfer_fees, payment_fees, stage)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9);"#,
+            payment.invoice, source_mint_url, target_mint_url, transfer_amount, payment.transfer_quote, payment.transfer_invoice, transfer_fees, payment_fees, stage)
+        .execute(&mut **tx)
+        .await?;
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    async fn get_multi_payments(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<MultiPayment>, MokshaWalletError> {
+        let rows = sqlx::query!(
+            "SELECT id, invoice, source_mint_url, target_mint_url, transfer_amount, transfer_quote, transfer_invoice, transfer_fees, payment_fees, stage FROM multi_payments ORDER BY id;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(MultiPayment {
+                    id: Some(row.id as u64),
+                    invoice: row.invoice,
+                    source_mint_url: Url::parse(&row.source_mint_url)?,
+                    target_mint_url: Url::parse(&row.target_mint_url)?,
+                    transfer_amount: row.transfer_amount as u64,
+                    transfer_quote: row.transfer_quote,
+                    transfer_invoice: row.transfer_invoice,
+                    transfer_fees: row.transfer_fees as u64,
+                    payment_fees: row.payment_fees as u64,
+                    stage: match row.stage.as_str() {
+                        "created" => MultiPaymentStage::Created,
+                        "transfer_quoted" => MultiPaymentStage::TransferQuoted,
+                        "transfer_paid" => MultiPaymentStage::TransferPaid,
+                        "transfer_minted" => MultiPaymentStage::TransferMinted,
+                        "paid" => MultiPaymentStage::Paid,
+                        _ => return Err(invalid_value("multi payment stage", &row.stage)),
+                    },
+                })
+            })
+            .collect()
+    }
+
+    async fn update_multi_payment(
+        &self,
+        tx: &mut Self::Tx,
+        payment: &MultiPayment,
+    ) -> Result<(), MokshaWalletError> {
+        let id = match payment.id {
+            None => return Err(MokshaWalletError::IdNotSet),
+            Some(id) => id as i64,
+        };
+        let transfer_fees = payment.transfer_fees as i64;
+        let payment_fees = payment.payment_fees as i64;
+        let stage = payment.stage.to_string();
+
+        sqlx::query!(
+            "UPDATE multi_payments SET transfer_quote = $1, transfer_invoice = $2, transfer_fees = $3, payment_fees = $4, stage = $5 WHERE id = $6;",
+            payment.transfer_quote, payment.transfer_invoice, transfer_fees, payment_fees, stage, id)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn add_pending_melt(
+        &self,
+        tx: &mut Self::Tx,
+        pending_melt: &PendingMelt,
+    ) -> Result<u64, MokshaWalletError> {
+        let mint_url = pending_melt.mint_url.as_str();
+        let proofs = blob::encode(&pending_melt.proofs)?;
+        let created_at = pending_melt.created_at as i64;
+        let result = sqlx::query!(
+            "INSERT INTO pending_melts (mint_url, quote_id, invoice, proofs, created_at, dispute) VALUES ($1, $2, $3, $4, $5, $6);",
+            mint_url, pending_melt.quote_id, pending_melt.invoice, proofs, created_at, pending_melt.dispute)
+        .execute(&mut **tx)
+        .await?;
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    async fn get_pending_melts(
+        &self,
+        tx: &mut Self::Tx,
+    ) -> Result<Vec<PendingMelt>, MokshaWalletError> {
+        let rows = sqlx::query!(
+            "SELECT id, mint_url, quote_id, invoice, proofs, created_at, dispute FROM pending_melts ORDER BY id;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(PendingMelt {
+                    id: Some(row.id as u64),
+                    mint_url: Url::parse(&row.mint_url)?,
+                    quote_id: row.quote_id,
+                    invoice: row.invoice,
+                    proofs: blob::decode(&row.proofs)?,
+                    created_at: row.created_at as u64,
+                    dispute: row.dispute,
+                })
+            })
+            .collect()
+    }
+
+    async fn update_pending_melt(
+        &self,
+        tx: &mut Self::Tx,
+        pending_melt: &PendingMelt,
+    ) -> Result<(), MokshaWalletError> {
+        let id = match pending_melt.id {
+            None => return Err(MokshaWalletError::IdNotSet),
+            Some(id) => id as i64,
+        };
+        sqlx::query!(
+            "UPDATE pending_melts SET dispute = $1 WHERE id = $2;",
+            pending_melt.dispute,
+            id
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_pending_melt(
+        &self,
+        tx: &mut Self::Tx,
+        id: u64,
+    ) -> Result<(), MokshaWalletError> {
+        let id = id as i64;
+        sqlx::query!("DELETE FROM pending_melts WHERE id = $1;", id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_snapshot(
+        &self,
+        tx: &mut Self::Tx,
+        snapshot: &Snapshot,
+    ) -> Result<u64, MokshaWalletError> {
+        let proofs = blob::encode(&snapshot.proofs)?;
+        let created_at = snapshot.created_at as i64;
+        let result = sqlx::query!(
+            "INSERT INTO snapshots (reason, created_at, proofs) VALUES ($1, $2, $3);",
+            snapshot.reason,
+            created_at,
+            proofs
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    async fn get_snapshots(&self, tx: &mut Self::Tx) -> Result<Vec<Snapshot>, MokshaWalletError> {
+        let rows =
+            sqlx::query!("SELECT id, reason, created_at, proofs FROM snapshots ORDER BY id;")
+                .fetch_all(&mut **tx)
+                .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(Snapshot {
+                    id: Some(row.id as u64),
+                    reason: row.reason,
+                    created_at: row.created_at as u64,
+                    proofs: blob::decode(&row.proofs)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn delete_snapshot(&self, tx: &mut Self::Tx, id: u64) -> Result<(), MokshaWalletError> {
+        let id = id as i64;
+        sqlx::query!("DELETE FROM snapshots WHERE id = $1;", id)
+            .execute(&mut **tx)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_operation(
+        &self,
+        tx: &mut Self::Tx,
+        operation: &Operation,
+    ) -> Result<(), MokshaWalletError> {
+        let id = operation.id.to_string();
+        let kind = operation.kind.to_string();
+        let created_at = operation.created_at as i64;
+        let finished_at = operation.finished_at.map(|finished_at| finished_at as i64);
+        sqlx::query!(
+            "INSERT INTO operations (id, kind, created_at, finished_at, error) VALUES ($1, $2, $3, $4, $5);",
+            id, kind, created_at, finished_at, operation.error)
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn update_operation(
+        &self,
+        tx: &mut Self::Tx,
+        operation: &Operation,
+    ) -> Result<(), MokshaWalletError> {
+        let id = operation.id.to_string();
+        let finished_at = operation.finished_at.map(|finished_at| finished_at as i64);
+        sqlx::query!(
+            "UPDATE operations SET finished_at = $1, error = $2 WHERE id = $3;",
+            finished_at,
+            operation.error,
+            id
+        )
+        .execute(&mut **tx)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_operation(
+        &self,
+        tx: &mut Self::Tx,
+        id: &OperationId,
+    ) -> Result<Option<Operation>, MokshaWalletError> {
+        let id = id.to_string();
+        let row = sqlx::query!(
+            "SELECT id, kind, created_at, finished_at, error FROM operations WHERE id = $1;",
+            id
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+
+        row.map(|row| operation(row.id, row.kind, row.created_at, row.finished_at, row.error))
+            .transpose()
+    }
+
+    async fn get_operations(&self, tx: &mut Self::Tx) -> Result<Vec<Operation>, MokshaWalletError> {
+        let rows = sqlx::query!(
+            "SELECT id, kind, created_at, finished_at, error FROM operations ORDER BY id;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| operation(row.id, row.kind, row.created_at, row.finished_at, row.error))
+            .collect()
+    }
+
+    async fn add_operation_records(
+        &self,
+        tx: &mut Self::Tx,
+        id: &OperationId,
+        records: &[OperationRecord],
+    ) -> Result<(), MokshaWalletError> {
+        let id = id.to_string();
+        for record in records {
+            let (record_type, record_key) = record.parts();
+            sqlx::query!(
+                "INSERT OR IGNORE INTO operation_records (operation_id, record_type, record_key) VALUES ($1, $2, $3);",
+                id, record_type, record_key)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_operation_records(
+        &self,
+        tx: &mut Self::Tx,
+        id: &OperationId,
+    ) -> Result<Vec<OperationRecord>, MokshaWalletError> {
+        let id = id.to_string();
+        let rows = sqlx::query!(
+            "SELECT record_type, record_key FROM operation_records WHERE operation_id = $1 ORDER BY rowid;",
+            id
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                let id = || {
+                    row.record_key
+                        .parse()
+                        .map_err(|_| invalid_value("record id", &row.record_key))
+                };
+                Ok(match row.record_type.as_str() {
+                    "proof" => OperationRecord::Proof(row.record_key.clone()),
+                    "pending_mint" => OperationRecord::PendingMint(row.record_key.clone()),
+                    "pending_melt" => OperationRecord::PendingMelt(id()?),
+                    "multi_payment" => OperationRecord::MultiPayment(id()?),
+                    _ => return Err(invalid_value("operation record", &row.record_type)),
+                })
+            })
+            .collect()
+    }
+
+    async fn add_history_entry(
+        &self,
+        tx: &mut Self::Tx,
+        entry: &HistoryEntry,
+    ) -> Result<u64, MokshaWalletError> {
+        let operation_id = entry.operation_id.to_string();
+        let kind = entry.kind.to_string();
+        let amount = entry.amount as i64;
+        let mint_url = entry.mint_url.to_string();
+        let created_at = entry.created_at as i64;
+        let result = sqlx::query!(
+            "INSERT INTO history (operation_id, kind, amount, mint_url, created_at, invoice, memo) VALUES ($1, $2, $3, $4, $5, $6, $7);",
+            operation_id, kind, amount, mint_url, created_at, entry.invoice, entry.memo)
+        .execute(&mut **tx)
+        .await?;
+        Ok(result.last_insert_rowid() as u64)
+    }
+
+    async fn get_history(&self, tx: &mut Self::Tx) -> Result<Vec<HistoryEntry>, MokshaWalletError> {
+        let rows = sqlx::query!(
+            "SELECT id, operation_id, kind, amount, mint_url, created_at, invoice, memo FROM history ORDER BY id;"
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(HistoryEntry {
+                    id: Some(row.id as u64),
+                    operation_id: row.operation_id.parse()?,
+                    kind: history_kind(&row.kind)?,
+                    amount: row.amount as u64,
+                    mint_url: Url::parse(&row.mint_url)?,
+                    created_at: row.created_at as u64,
+                    invoice: row.invoice,
+                    memo: row.memo,
+                })
+            })
+            .collect()
+    }
+
+    async fn get_history_page(
+        &self,
+        tx: &mut Self::Tx,
+        filter: &HistoryFilter,
+        before: Option<&PageCursor>,
+        limit: usize,
+    ) -> Result<HistoryPage, MokshaWalletError> {
+        let kind = filter.kind.map(|kind| kind.to_string());
+        let mint_url = filter
+            .mint_url
+            .as_ref()
+            .map(|mint_url| mint_url.to_string());
+        let before_created_at = before.map(|cursor| cursor.created_at as i64);
+        let before_id = before.map(|cursor| cursor.id as i64);
+        // one more entry shows whether there is a following page
+        let fetch = limit.saturating_add(1).min(i64::MAX as usize) as i64;
+        let rows = sqlx::query!(
+            "SELECT id, operation_id, kind, amount, mint_url, created_at, invoice, memo FROM history WHERE ($1 IS NULL OR kind = $1) AND ($2 IS NULL OR mint_url = $2) AND ($3 IS NULL OR created_at < $3 OR (created_at = $3 AND id < $4)) ORDER BY created_at DESC, id DESC LIMIT $5;",
+            kind, mint_url, before_created_at, before_id, fetch
+        )
+        .fetch_all(&mut **tx)
+        .await?;
+
+        let entries = rows
+            .into_iter()
+            .map(|row| {
+                Ok(HistoryEntry {
+                    id: Some(row.id as u64),
+                    operation_id: row.operation_id.parse()?,
+                    kind: history_kind(&row.kind)?,
+                    amount: row.amount as u64,
+                    mint_url: Url::parse(&row.mint_url)?,
+                    created_at: row.created_at as u64,
+                    invoice: row.invoice,
+                    memo: row.memo,
+                })
+            })
+            .collect::<Result<Vec<_>, MokshaWalletError>>()?;
+        Ok(HistoryPage::from_entries(entries, limit))
+    }
+}
+
+fn history_kind(kind: &str) -> Result<HistoryKind, MokshaWalletError> {
+    Ok(match kind {
+        "mint" => HistoryKind::Mint,
+        "send" => HistoryKind::Send,
+        "receive" => HistoryKind::Receive,
+        "melt" => HistoryKind::Melt,
+        _ => return Err(invalid_value("history kind", kind)),
+    })
+}
+
+fn invalid_value(column: &'static str, value: &str) -> MokshaWalletError {
+    MokshaWalletError::InvalidStoredValue {
+        column,
+        value: value.to_owned(),
+    }
 }
 
 impl SqliteLocalStore {
@@ -200,6 +1005,17 @@ impl SqliteLocalStore {
         Self::with_connection_string("sqlite::memory:").await
     }
 
+    /// Opens an existing store without migrating it, e.g. to show the balance of a store that
+    /// needs a newer wallet. All changes to the store fail.
+    pub async fn with_path_readonly(absolute_path: String) -> Result<Self, MokshaWalletError> {
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .acquire_timeout(std::time::Duration::from_secs(5))
+            .idle_timeout(std::time::Duration::from_secs(5))
+            .connect(&format!("sqlite://{absolute_path}?mode=ro"))
+            .await?;
+        Ok(Self { pool })
+    }
+
     async fn with_connection_string(connection_string: &str) -> Result<Self, MokshaWalletError> {
         // creates db-file if not already exists
         let pool = sqlx::sqlite::SqlitePoolOptions::new()
@@ -211,16 +1027,132 @@ impl SqliteLocalStore {
         sqlx::query("PRAGMA journal_mode=WAL")
             .execute(&pool)
             .await?;
-        sqlx::migrate!("./migrations").run(&pool).await?;
+        Self::migrate(&pool).await?;
         Ok(Self { pool })
     }
+
+    /// Runs the migrations of the schema and upgrades the stored values to the current
+    /// [`blob::BLOB_VERSION`]. Fails with [`MokshaWalletError::StoreRequiresNewerVersion`] if
+    /// the store was migrated by a newer wallet that this wallet is not compatible with.
+    async fn migrate(pool: &sqlx::SqlitePool) -> Result<(), MokshaWalletError> {
+        let mut migrator = sqlx::migrate!("./migrations");
+        match Self::store_version(pool).await? {
+            Some((store_version, min_compatible_version))
+                if min_compatible_version > STORE_VERSION =>
+            {
+                return Err(MokshaWalletError::StoreRequiresNewerVersion {
+                    store_version,
+                    supported: STORE_VERSION,
+                });
+            }
+            // the newer wallet has applied migrations this wallet doesn't know, the schema and
+            // the stored values are kept as they are
+            Some((store_version, _)) if store_version > STORE_VERSION => {
+                migrator.set_ignore_missing(true).run(pool).await?;
+                return Ok(());
+            }
+            _ => {}
+        }
+        migrator.run(pool).await?;
+
+        let mut tx = pool.begin().await?;
+        let version = STORE_VERSION as i64;
+        let min_compatible_version = STORE_MIN_COMPATIBLE_VERSION as i64;
+        sqlx::query("INSERT OR REPLACE INTO store_version (id, version, min_compatible_version) VALUES (1, ?1, ?2);")
+            .bind(version)
+            .bind(min_compatible_version)
+            .execute(&mut *tx)
+            .await?;
+        for (table, column) in BLOB_COLUMNS {
+            let rows: Vec<(i64, String)> = sqlx::query_as(&format!(
+                "SELECT rowid, {column} FROM {table} WHERE {column} IS NOT NULL;"
+            ))
+            .fetch_all(&mut *tx)
+            .await?;
+            for (rowid, value) in rows {
+                if let Some(value) = blob::upgrade(&value)? {
+                    sqlx::query(&format!(
+                        "UPDATE {table} SET {column} = ?1 WHERE rowid = ?2;"
+                    ))
+                    .bind(value)
+                    .bind(rowid)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns the version and the minimal compatible version of the store, `None` for a new
+    /// store or a store from before the version was recorded
+    async fn store_version(
+        pool: &sqlx::SqlitePool,
+    ) -> Result<Option<(u32, u32)>, MokshaWalletError> {
+        let exists: Option<(String,)> = sqlx::query_as(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'store_version';",
+        )
+        .fetch_optional(pool)
+        .await?;
+        if exists.is_none() {
+            return Ok(None);
+        }
+        let version: Option<(i64, i64)> = sqlx::query_as(
+            "SELECT version, min_compatible_version FROM store_version WHERE id = 1;",
+        )
+        .fetch_optional(pool)
+        .await?;
+        Ok(version.map(|(version, min_compatible_version)| {
+            (version as u32, min_compatible_version as u32)
+        }))
+    }
 }
 
+/// Reads a row of the operations table
+fn operation(
+    id: String,
+    kind: String,
+    created_at: i64,
+    finished_at: Option<i64>,
+    error: Option<String>,
+) -> Result<Operation, MokshaWalletError> {
+    Ok(Operation {
+        id: id.parse()?,
+        kind: match kind.as_str() {
+            "mint" => OperationKind::Mint,
+            "send" => OperationKind::Send,
+            "receive" => OperationKind::Receive,
+            "pay_invoice" => OperationKind::PayInvoice,
+            "pay_invoice_multi" => OperationKind::PayInvoiceMulti,
+            _ => return Err(invalid_value("operation kind", &kind)),
+        },
+        created_at: created_at as u64,
+        finished_at: finished_at.map(|finished_at| finished_at as u64),
+        error,
+    })
+}
+
+/// columns that store values encoded with [`blob::encode`]
+const BLOB_COLUMNS: [(&str, &str); 4] = [
+    ("pending_mints", "outputs"),
+    ("pending_mints", "proofs"),
+    ("pending_melts", "proofs"),
+    ("snapshots", "proofs"),
+];
+
 #[cfg(test)]
 mod tests {
-    use super::SqliteLocalStore;
-    use crate::localstore::LocalStore;
-    use moksha_core::{fixture::read_fixture, token::TokenV3};
+    use super::{SqliteLocalStore, STORE_VERSION};
+    use crate::{
+        error::MokshaWalletError,
+        localstore::{blob, LocalStore, ProofStatus},
+    };
+    use moksha_core::{
+        fixture::read_fixture,
+        proof::{Proof, Proofs},
+        token::TokenV3,
+    };
 
     #[tokio::test]
     async fn test_add_proofs() -> anyhow::Result<()> {
@@ -269,6 +1201,146 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_delete_proofs_by_secrets() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let proof = tokens.proofs().proofs()[0].clone(); // 4
+                                                         // more secrets than sqlite allows parameters in a query
+        let proofs: Proofs = (0..40_000)
+            .map(|i| Proof {
+                secret: format!("secret {i}"),
+                ..proof.clone()
+            })
+            .collect::<Vec<_>>()
+            .into();
+        localstore.add_proofs(&mut tx, &proofs).await?;
+        let secrets = proofs.secrets();
+        localstore
+            .transition_proofs(
+                &mut tx,
+                &secrets[0..1],
+                ProofStatus::Spendable,
+                ProofStatus::PendingSend,
+                1_000,
+            )
+            .await?;
+
+        let mut delete = secrets[0..39_999].to_vec();
+        delete.push("unknown secret".to_owned());
+        localstore
+            .delete_proofs_by_secrets(&mut tx, &delete)
+            .await?;
+
+        assert_eq!(
+            vec!["secret 39999".to_owned()],
+            localstore.get_proofs(&mut tx).await?.secrets()
+        );
+        assert!(localstore
+            .get_proofs_by_state(&mut tx, ProofStatus::PendingSend)
+            .await?
+            .is_empty());
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_transition_proofs() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        let mut tx = localstore.begin_tx().await?;
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        localstore.add_proofs(&mut tx, &tokens.proofs()).await?;
+        let secrets = tokens.proofs().secrets(); // 4, 8, 16, 32
+
+        localstore
+            .transition_proofs(
+                &mut tx,
+                &secrets[0..2],
+                ProofStatus::Spendable,
+                ProofStatus::Reserved,
+                1_000,
+            )
+            .await?;
+        assert_eq!(48, localstore.get_proofs(&mut tx).await?.total_amount());
+        let reserved = localstore.get_reserved_proofs(&mut tx).await?;
+        assert_eq!(2, reserved.len());
+        assert!(reserved
+            .iter()
+            .all(|r| r.state == ProofStatus::Reserved && r.reserved_at == 1_000));
+
+        // 16 is not reserved, so none of the proofs change
+        let result = localstore
+            .transition_proofs(
+                &mut tx,
+                &secrets[1..3],
+                ProofStatus::Reserved,
+                ProofStatus::PendingMelt,
+                2_000,
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidProofTransition {
+                from: ProofStatus::Reserved,
+                to: ProofStatus::PendingMelt,
+                count: 1
+            })
+        ));
+        assert_eq!(
+            12,
+            localstore
+                .get_proofs_by_state(&mut tx, ProofStatus::Reserved)
+                .await?
+                .total_amount()
+        );
+        assert_eq!(48, localstore.get_proofs(&mut tx).await?.total_amount());
+        assert!(localstore
+            .get_proofs_by_state(&mut tx, ProofStatus::PendingMelt)
+            .await?
+            .is_empty());
+
+        // unknown proofs can't change their state either
+        let result = localstore
+            .transition_proofs(
+                &mut tx,
+                &[secrets[3].clone(), "unknown".to_owned()],
+                ProofStatus::Spendable,
+                ProofStatus::Frozen,
+                2_000,
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidProofTransition { count: 1, .. })
+        ));
+        assert_eq!(48, localstore.get_proofs(&mut tx).await?.total_amount());
+
+        localstore
+            .transition_proofs(
+                &mut tx,
+                &secrets[0..2],
+                ProofStatus::Reserved,
+                ProofStatus::Spent,
+                3_000,
+            )
+            .await?;
+        tx.commit().await?;
+
+        let mut tx = localstore.begin_tx().await?;
+        assert!(localstore.get_reserved_proofs(&mut tx).await?.is_empty());
+        assert_eq!(
+            12,
+            localstore
+                .get_proofs_by_state(&mut tx, ProofStatus::Spent)
+                .await?
+                .total_amount()
+        );
+        assert_eq!(48, localstore.get_proofs(&mut tx).await?.total_amount());
+        tx.commit().await?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_delete_multiple_proofs() -> anyhow::Result<()> {
         let localstore = SqliteLocalStore::with_in_memory().await?;
@@ -301,4 +1373,166 @@ mod tests {
         tx.commit().await?;
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_proofs_persisted() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("wallet.db").to_string_lossy().to_string();
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+
+        let localstore = SqliteLocalStore::with_path(path.clone()).await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.add_proofs(&mut tx, &tokens.proofs()).await?;
+        localstore
+            .delete_proofs(&mut tx, &tokens.proofs().proofs()[0].clone().into())
+            .await?;
+        tx.commit().await?;
+        localstore.pool.close().await;
+
+        // the migrations run again when the file is opened, they must keep the data
+        let localstore = SqliteLocalStore::with_path(path).await?;
+        let mut tx = localstore.begin_tx().await?;
+        let proofs = localstore.get_proofs(&mut tx).await?;
+        assert_eq!(56, proofs.total_amount());
+        localstore.delete_proofs(&mut tx, &proofs).await?;
+        assert!(localstore.get_proofs(&mut tx).await?.is_empty());
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unknown_stored_values() -> anyhow::Result<()> {
+        let localstore = SqliteLocalStore::with_in_memory().await?;
+        // kinds a newer wallet may write
+        sqlx::query("INSERT INTO operations (id, kind, created_at) VALUES ('01ARYZ6S4104HMASW9NF6YY093', 'swap', 1000);")
+            .execute(&localstore.pool)
+            .await?;
+        sqlx::query("INSERT INTO history (operation_id, kind, amount, mint_url, created_at) VALUES ('01ARYZ6S4104HMASW9NF6YY093', 'swap', 21, 'http://localhost:3338', 1000);")
+            .execute(&localstore.pool)
+            .await?;
+
+        let mut tx = localstore.begin_tx().await?;
+        let result = localstore.get_operations(&mut tx).await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidStoredValue { column: "operation kind", ref value }) if value == "swap"
+        ));
+        let result = localstore.get_history(&mut tx).await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidStoredValue { column: "history kind", ref value }) if value == "swap"
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_migrate_unversioned_blobs() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("wallet.db").to_string_lossy().to_string();
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let proofs = serde_json::to_string(&tokens.proofs())?;
+
+        // values as they were written before blobs were versioned
+        let localstore = SqliteLocalStore::with_path(path.clone()).await?;
+        sqlx::query("INSERT INTO pending_mints (quote_id, mint_url, keyset_id, outputs, claimed_at, proofs) VALUES ('quote', 'http://localhost:3338', '00f545318e4fad2b', '[]', NULL, ?1);")
+            .bind(&proofs)
+            .execute(&localstore.pool)
+            .await?;
+        sqlx::query("INSERT INTO pending_melts (mint_url, quote_id, invoice, proofs, created_at) VALUES ('http://localhost:3338', 'quote', 'invoice', ?1, 1000);")
+            .bind(&proofs)
+            .execute(&localstore.pool)
+            .await?;
+        sqlx::query(
+            "INSERT INTO snapshots (reason, created_at, proofs) VALUES ('send', 1000, ?1);",
+        )
+        .bind(&proofs)
+        .execute(&localstore.pool)
+        .await?;
+        localstore.pool.close().await;
+
+        let localstore = SqliteLocalStore::with_path(path).await?;
+        let stored: Vec<String> = sqlx::query_scalar(
+            "SELECT outputs FROM pending_mints UNION ALL SELECT proofs FROM pending_mints UNION ALL SELECT proofs FROM pending_melts UNION ALL SELECT proofs FROM snapshots;",
+        )
+        .fetch_all(&localstore.pool)
+        .await?;
+        assert_eq!(4, stored.len());
+        for value in stored {
+            assert_eq!(None, blob::upgrade(&value)?);
+        }
+
+        let mut tx = localstore.begin_tx().await?;
+        let pending_mint = localstore
+            .get_pending_mint(&mut tx, "quote")
+            .await?
+            .expect("pending mint not found");
+        assert!(pending_mint.outputs.is_empty());
+        assert_eq!(Some(tokens.proofs()), pending_mint.proofs);
+        let pending_melts = localstore.get_pending_melts(&mut tx).await?;
+        assert_eq!(tokens.proofs(), pending_melts[0].proofs);
+        let snapshots = localstore.get_snapshots(&mut tx).await?;
+        assert_eq!(tokens.proofs(), snapshots[0].proofs);
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn create_store_with_version(
+        dir: &tempfile::TempDir,
+        version: u32,
+        min_compatible_version: u32,
+    ) -> anyhow::Result<(String, Proofs)> {
+        let path = dir.path().join("wallet.db").to_string_lossy().to_string();
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let localstore = SqliteLocalStore::with_path(path.clone()).await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.add_proofs(&mut tx, &tokens.proofs()).await?;
+        tx.commit().await?;
+        // stamped like a store that was migrated by a newer wallet
+        sqlx::query("UPDATE store_version SET version = ?1, min_compatible_version = ?2;")
+            .bind(version as i64)
+            .bind(min_compatible_version as i64)
+            .execute(&localstore.pool)
+            .await?;
+        localstore.pool.close().await;
+        Ok((path, tokens.proofs()))
+    }
+
+    #[tokio::test]
+    async fn test_store_requires_newer_version() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (path, proofs) = create_store_with_version(&dir, 99, 99).await?;
+
+        let result = SqliteLocalStore::with_path(path.clone()).await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::StoreRequiresNewerVersion {
+                store_version: 99,
+                supported: STORE_VERSION
+            })
+        ));
+
+        let localstore = SqliteLocalStore::with_path_readonly(path).await?;
+        let mut tx = localstore.begin_tx().await?;
+        assert_eq!(proofs, localstore.get_proofs(&mut tx).await?);
+        assert!(localstore.delete_proofs(&mut tx, &proofs).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_newer_compatible_version() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let (path, proofs) =
+            create_store_with_version(&dir, STORE_VERSION + 1, STORE_VERSION).await?;
+
+        let localstore = SqliteLocalStore::with_path(path).await?;
+        let mut tx = localstore.begin_tx().await?;
+        assert_eq!(proofs, localstore.get_proofs(&mut tx).await?);
+        tx.commit().await?;
+        // the version of the newer wallet is kept
+        let version: (i64,) = sqlx::query_as("SELECT version FROM store_version;")
+            .fetch_one(&localstore.pool)
+            .await?;
+        assert_eq!(STORE_VERSION as i64 + 1, version.0);
+        Ok(())
+    }
 }
@@ -0,0 +1,159 @@
+//! Identifiers of the top-level operations of the wallet, e.g. paying an invoice. The records an
+//! operation writes to the localstore are linked to its id, so everything that happened during
+//! the operation can be looked up with [`crate::wallet::Wallet::operation_details`].
+
+use std::{fmt::Display, future::Future, str::FromStr};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::error::MokshaWalletError;
+
+/// Crockford base32, the encoding of a ULID
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ENCODED_LEN: usize = 26;
+
+/// Header of the requests to the mint with the id of the operation that sent them, so the logs of
+/// the mint can be matched with the operations of the wallet
+pub const OPERATION_ID_HEADER: &str = "X-Operation-Id";
+
+tokio::task_local! {
+    static CURRENT_OPERATION: OperationId;
+}
+
+/// ULID of an operation: 48 bits of milliseconds since the unix epoch followed by 80 random
+/// bits, encoded as 26 characters of Crockford base32. Ids sort by their creation time.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct OperationId(u128);
+
+impl OperationId {
+    /// Creates a new id for an operation that starts at `timestamp_ms`
+    pub fn new(timestamp_ms: u64) -> Self {
+        Self::from_parts(timestamp_ms, rand::thread_rng().gen())
+    }
+
+    /// Only the lower 48 bits of the timestamp and the lower 80 bits of `random` are used
+    pub fn from_parts(timestamp_ms: u64, random: u128) -> Self {
+        let timestamp = u128::from(timestamp_ms) & ((1 << 48) - 1);
+        Self((timestamp << 80) | (random & ((1 << 80) - 1)))
+    }
+
+    /// Milliseconds since the unix epoch when the operation started
+    pub fn timestamp_ms(&self) -> u64 {
+        (self.0 >> 80) as u64
+    }
+
+    /// The id of the operation that is running in the current task, if any
+    pub fn current() -> Option<Self> {
+        CURRENT_OPERATION.try_with(Clone::clone).ok()
+    }
+
+    /// Runs `future` as the current operation, see [`Self::current`]
+    pub(crate) async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT_OPERATION.scope(self, future).await
+    }
+}
+
+impl Display for OperationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let encoded = (0..ENCODED_LEN)
+            .map(|i| ALPHABET[((self.0 >> (5 * (ENCODED_LEN - 1 - i))) & 31) as usize] as char)
+            .collect::<String>();
+        write!(f, "{encoded}")
+    }
+}
+
+impl FromStr for OperationId {
+    type Err = MokshaWalletError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || MokshaWalletError::InvalidOperationId(s.to_owned());
+        if s.len() != ENCODED_LEN {
+            return Err(invalid());
+        }
+        // the first character only holds 3 bits
+        s.bytes()
+            .enumerate()
+            .try_fold(0u128, |value, (i, c)| {
+                let digit = ALPHABET
+                    .iter()
+                    .position(|a| *a == c.to_ascii_uppercase())
+                    .ok_or_else(invalid)?;
+                if i == 0 && digit > 7 {
+                    return Err(invalid());
+                }
+                Ok((value << 5) | digit as u128)
+            })
+            .map(Self)
+    }
+}
+
+impl TryFrom<String> for OperationId {
+    type Error = MokshaWalletError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<OperationId> for String {
+    fn from(value: OperationId) -> Self {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OperationId;
+    use crate::error::MokshaWalletError;
+
+    #[test]
+    fn test_operation_id_roundtrip() -> anyhow::Result<()> {
+        let id = OperationId::from_parts(1_469_918_176_385, 0x0123_4567_89ab_cdef_0123);
+        assert_eq!("01ARYZ6S4104HMASW9NF6YY093", id.to_string());
+        assert_eq!(1_469_918_176_385, id.timestamp_ms());
+        assert_eq!(id, id.to_string().parse()?);
+        assert_eq!(id, id.to_string().to_lowercase().parse()?);
+
+        let random = OperationId::new(1_000);
+        assert_eq!(1_000, random.timestamp_ms());
+        assert_eq!(random, random.to_string().parse()?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_id_sorts_by_time() {
+        let earlier = OperationId::from_parts(1_000, u128::MAX);
+        let later = OperationId::from_parts(1_001, 0);
+        assert!(earlier < later);
+        assert!(earlier.to_string() < later.to_string());
+    }
+
+    #[tokio::test]
+    async fn test_current_operation() {
+        let id = OperationId::from_parts(1_000, 1);
+        assert_eq!(None, OperationId::current());
+        let current = id.clone().scope(async { OperationId::current() }).await;
+        assert_eq!(Some(id), current);
+        assert_eq!(None, OperationId::current());
+    }
+
+    #[test]
+    fn test_operation_id_invalid() {
+        for invalid in [
+            "",
+            "01ARYZ6S410028T5S4NF6YX04",
+            "01ARYZ6S410028T5S4NF6YX04SS",
+            // I, L, O and U are not part of the alphabet
+            "01ARYZ6S410028T5S4NF6YX04I",
+            // more than 128 bits
+            "81ARYZ6S410028T5S4NF6YX04S",
+        ] {
+            assert!(matches!(
+                invalid.parse::<OperationId>(),
+                Err(MokshaWalletError::InvalidOperationId(_))
+            ));
+        }
+    }
+}
@@ -1,17 +1,38 @@
 use crate::error::MokshaWalletError;
-use std::str::FromStr;
+use std::{collections::HashSet, str::FromStr, sync::Mutex};
 
 use bip32::{Seed, XPrv};
 use bip39::Mnemonic;
+use bitcoin_hashes::{sha256, Hash};
 use moksha_core::{blind::BlindingFactor, keyset::KeysetId};
-use rand::Rng;
+use rand::{rngs::OsRng, Rng, RngCore};
 use secp256k1::SecretKey;
 
+/// Minimum length of a secret. Corresponds to 16 bytes of entropy when hex encoded.
+pub const MIN_SECRET_LENGTH: usize = 32;
+
+/// Source of the secrets and blinding factors that are used for new outputs
+pub trait SecretGenerator: Send + Sync {
+    /// Returns `count` pairs of secret and blinding factor. `start` is the next unused counter
+    /// of the keyset, generators that don't derive their secrets can ignore it.
+    fn generate(
+        &self,
+        keyset_id: &KeysetId,
+        start: u32,
+        count: u32,
+    ) -> Result<Vec<(String, BlindingFactor)>, MokshaWalletError>;
+}
+
 enum DerivationType {
     Secret = 0,
     Blinding = 1,
 }
 
+/// Derives secrets and blinding factors from a BIP39 seed (NUT-13).
+///
+/// The keys are derived at `m/129372'/0'/{keyset_id}'/{counter}'/{0|1}`, where the last index
+/// selects the secret (0) or the blinding factor (1). The wallet persists the last used counter
+/// per keyset, so proofs can be restored from the seed words.
 pub struct DeterministicSecret {
     pub seed: Seed,
 }
@@ -59,6 +80,16 @@ impl DeterministicSecret {
         Ok(key.private_key().to_bytes().to_vec())
     }
 
+    /// Stable identifier of the seed, e.g. to key the data of a wallet on a sync server. It is the
+    /// sha256 hash of the public key at `m/129372'/1'/0'`, which is outside of the NUT-13 paths,
+    /// so it neither reveals the seed nor any secret of a proof.
+    pub fn wallet_id(&self) -> Result<String, MokshaWalletError> {
+        let derivation_path = bip32::DerivationPath::from_str("m/129372'/1'/0'")?;
+        let key = XPrv::derive_from_path(&self.seed, &derivation_path)?;
+        let public_key = key.public_key().to_bytes();
+        Ok(sha256::Hash::hash(&public_key).to_string())
+    }
+
     fn derive_secret(&self, keyset_id: u32, counter: u32) -> Result<String, MokshaWalletError> {
         let key = self.derive_private_key(keyset_id, counter, DerivationType::Secret)?;
         Ok(hex::encode(key))
@@ -90,6 +121,87 @@ impl DeterministicSecret {
     }
 }
 
+impl SecretGenerator for DeterministicSecret {
+    fn generate(
+        &self,
+        keyset_id: &KeysetId,
+        start: u32,
+        count: u32,
+    ) -> Result<Vec<(String, BlindingFactor)>, MokshaWalletError> {
+        self.derive_range(keyset_id, start, count)
+    }
+}
+
+/// Creates random secrets (32 bytes, hex encoded) and blinding factors from a rng.
+///
+/// Uses the OS rng by default. A seeded rng can be passed with [`RandomSecretGenerator::from_rng`]
+/// to get reproducible secrets e.g. in simulations.
+pub struct RandomSecretGenerator<R = OsRng> {
+    rng: Mutex<R>,
+}
+
+impl RandomSecretGenerator<OsRng> {
+    pub fn new() -> Self {
+        Self::from_rng(OsRng)
+    }
+}
+
+impl Default for RandomSecretGenerator<OsRng> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: RngCore + Send> RandomSecretGenerator<R> {
+    pub fn from_rng(rng: R) -> Self {
+        Self {
+            rng: Mutex::new(rng),
+        }
+    }
+}
+
+impl<R: RngCore + Send> SecretGenerator for RandomSecretGenerator<R> {
+    fn generate(
+        &self,
+        _keyset_id: &KeysetId,
+        _start: u32,
+        count: u32,
+    ) -> Result<Vec<(String, BlindingFactor)>, MokshaWalletError> {
+        let mut rng = self.rng.lock().expect("rng lock is poisoned");
+        (0..count)
+            .map(|_| {
+                let secret = hex::encode(rng.gen::<[u8; 32]>());
+                let blinding_factor = loop {
+                    // the chance to hit an invalid key is negligible, but it is not impossible
+                    if let Ok(key) = SecretKey::from_slice(&rng.gen::<[u8; 32]>()) {
+                        break key;
+                    }
+                };
+                Ok((secret, blinding_factor.into()))
+            })
+            .collect()
+    }
+}
+
+/// Checks that the secrets have the minimum length and are not reused within a batch
+pub fn validate_secrets(secrets: &[(String, BlindingFactor)]) -> Result<(), MokshaWalletError> {
+    let mut seen = HashSet::with_capacity(secrets.len());
+    for (secret, _) in secrets {
+        if secret.len() < MIN_SECRET_LENGTH {
+            return Err(MokshaWalletError::InvalidSecret(format!(
+                "secret has {} characters, at least {MIN_SECRET_LENGTH} are required",
+                secret.len()
+            )));
+        }
+        if !seen.insert(secret) {
+            return Err(MokshaWalletError::InvalidSecret(
+                "secret was generated more than once".to_owned(),
+            ));
+        }
+    }
+    Ok(())
+}
+
 pub fn convert_hex_to_int(keyset_id_hex: &str) -> Result<u32, MokshaWalletError> {
     let bytes = hex::decode(keyset_id_hex)?;
     let bytes_array: [u8; 8] = bytes[0..8].try_into()?;
@@ -101,8 +213,12 @@ pub fn convert_hex_to_int(keyset_id_hex: &str) -> Result<u32, MokshaWalletError>
 mod tests {
 
     use moksha_core::keyset::KeysetId;
+    use rand::{rngs::StdRng, SeedableRng};
 
-    use super::{convert_hex_to_int, DeterministicSecret};
+    use super::{
+        convert_hex_to_int, validate_secrets, DeterministicSecret, RandomSecretGenerator,
+        SecretGenerator,
+    };
 
     #[test]
     fn test_keyset_id_conversion() -> anyhow::Result<()> {
@@ -185,4 +301,51 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_random_secrets() -> anyhow::Result<()> {
+        let keyset_id = KeysetId::new("009a1f293253e41e")?;
+        let secrets = RandomSecretGenerator::new().generate(&keyset_id, 0, 5)?;
+        assert_eq!(5, secrets.len());
+        assert!(secrets
+            .iter()
+            .all(|(s, _)| s.len() == 64 && hex::decode(s).is_ok()));
+        validate_secrets(&secrets)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeded_random_secrets_are_reproducible() -> anyhow::Result<()> {
+        let keyset_id = KeysetId::new("009a1f293253e41e")?;
+        let first = RandomSecretGenerator::from_rng(StdRng::seed_from_u64(42))
+            .generate(&keyset_id, 0, 3)?;
+        let second = RandomSecretGenerator::from_rng(StdRng::seed_from_u64(42))
+            .generate(&keyset_id, 0, 3)?;
+        let other =
+            RandomSecretGenerator::from_rng(StdRng::seed_from_u64(7)).generate(&keyset_id, 0, 3)?;
+
+        let to_hex = |secrets: &[(String, moksha_core::blind::BlindingFactor)]| {
+            secrets
+                .iter()
+                .map(|(s, b)| (s.clone(), b.as_hex()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(to_hex(&first), to_hex(&second));
+        assert_ne!(to_hex(&first), to_hex(&other));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_secrets() -> anyhow::Result<()> {
+        let keyset_id = KeysetId::new("009a1f293253e41e")?;
+        let mut secrets = RandomSecretGenerator::new().generate(&keyset_id, 0, 2)?;
+
+        let mut short = secrets.clone();
+        short[0].0 = "too short".to_owned();
+        assert!(validate_secrets(&short).is_err());
+
+        secrets[1].0 = secrets[0].0.clone();
+        assert!(validate_secrets(&secrets).is_err());
+        Ok(())
+    }
 }
@@ -0,0 +1,351 @@
+//! Response times and error rates of the mint endpoints.
+//!
+//! The [`EndpointStats`] recorder is shared by the http client and keeps the last
+//! [`MAX_SAMPLES`] latencies per mint and endpoint. The wallet moves the samples into the
+//! localstore, so the statistics survive restarts of short-lived processes like the cli.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    str::FromStr,
+    sync::Mutex,
+    time::Duration,
+};
+
+use url::Url;
+
+/// Number of latencies that are kept per mint and endpoint
+pub const MAX_SAMPLES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Endpoint {
+    Info,
+    Keys,
+    Keysets,
+    Swap,
+    CheckState,
+    Restore,
+    MintQuote,
+    Mint,
+    MeltQuote,
+    Melt,
+}
+
+impl Endpoint {
+    /// Splits a request url into the url of the mint and the called endpoint
+    pub fn from_url(url: &Url) -> Option<(Url, Self)> {
+        let path = url.path();
+        let index = path.find("/v1/")?;
+        let endpoint = match path[index + 4..].split('/').collect::<Vec<_>>().as_slice() {
+            ["info"] => Self::Info,
+            ["keys", ..] => Self::Keys,
+            ["keysets"] => Self::Keysets,
+            ["swap"] => Self::Swap,
+            ["checkstate"] => Self::CheckState,
+            ["restore"] => Self::Restore,
+            ["mint", "quote", ..] => Self::MintQuote,
+            ["mint", ..] => Self::Mint,
+            ["melt", "quote", ..] => Self::MeltQuote,
+            ["melt", ..] => Self::Melt,
+            _ => return None,
+        };
+
+        let mut mint_url = url.clone();
+        mint_url.set_path(&path[..=index]);
+        mint_url.set_query(None);
+        Some((mint_url, endpoint))
+    }
+}
+
+impl Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Info => "info",
+            Self::Keys => "keys",
+            Self::Keysets => "keysets",
+            Self::Swap => "swap",
+            Self::CheckState => "checkstate",
+            Self::Restore => "restore",
+            Self::MintQuote => "mint_quote",
+            Self::Mint => "mint",
+            Self::MeltQuote => "melt_quote",
+            Self::Melt => "melt",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "info" => Self::Info,
+            "keys" => Self::Keys,
+            "keysets" => Self::Keysets,
+            "swap" => Self::Swap,
+            "checkstate" => Self::CheckState,
+            "restore" => Self::Restore,
+            "mint_quote" => Self::MintQuote,
+            "mint" => Self::Mint,
+            "melt_quote" => Self::MeltQuote,
+            "melt" => Self::Melt,
+            _ => return Err(format!("unknown endpoint {s}")),
+        })
+    }
+}
+
+/// Request counters and the most recent latencies (in ms) of an endpoint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointSamples {
+    pub endpoint: Endpoint,
+    pub requests: u64,
+    pub errors: u64,
+    pub latencies: VecDeque<u64>,
+}
+
+impl EndpointSamples {
+    pub fn new(endpoint: Endpoint) -> Self {
+        Self {
+            endpoint,
+            requests: 0,
+            errors: 0,
+            latencies: VecDeque::with_capacity(MAX_SAMPLES),
+        }
+    }
+
+    pub fn record(&mut self, latency: Duration, success: bool) {
+        self.requests += 1;
+        if !success {
+            self.errors += 1;
+        }
+        self.push_latency(latency.as_millis() as u64);
+    }
+
+    /// Adds the samples of `other`, which are expected to be more recent
+    pub fn merge(&mut self, other: Self) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        other
+            .latencies
+            .into_iter()
+            .for_each(|latency| self.push_latency(latency));
+    }
+
+    pub fn summary(&self) -> EndpointSummary {
+        let mut sorted = self.latencies.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        EndpointSummary {
+            endpoint: self.endpoint,
+            requests: self.requests,
+            errors: self.errors,
+            p50_ms: percentile(&sorted, 50),
+            p90_ms: percentile(&sorted, 90),
+            p99_ms: percentile(&sorted, 99),
+        }
+    }
+
+    fn push_latency(&mut self, latency: u64) {
+        if self.latencies.len() == MAX_SAMPLES {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointSummary {
+    pub endpoint: Endpoint,
+    pub requests: u64,
+    pub errors: u64,
+    pub p50_ms: Option<u64>,
+    pub p90_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+}
+
+impl EndpointSummary {
+    /// Share of failed requests between 0.0 and 1.0
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 {
+            return 0.0;
+        }
+        self.errors as f64 / self.requests as f64
+    }
+}
+
+/// Nearest-rank percentile of sorted values
+pub fn percentile(sorted: &[u64], percent: u8) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (percent as usize * sorted.len()).div_ceil(100).max(1);
+    Some(sorted[rank - 1])
+}
+
+/// Collects the samples of all requests until they are taken by the wallet
+#[derive(Debug, Default)]
+pub struct EndpointStats {
+    samples: Mutex<HashMap<(Url, Endpoint), EndpointSamples>>,
+}
+
+impl EndpointStats {
+    /// Records a request. Urls that don't belong to a known endpoint are ignored.
+    pub fn record(&self, url: &Url, latency: Duration, success: bool) {
+        let Some((mint_url, endpoint)) = Endpoint::from_url(url) else {
+            return;
+        };
+        self.samples
+            .lock()
+            .expect("stats lock is poisoned")
+            .entry((mint_url, endpoint))
+            .or_insert_with(|| EndpointSamples::new(endpoint))
+            .record(latency, success);
+    }
+
+    /// Returns and clears all samples recorded so far
+    pub fn take(&self) -> Vec<(Url, EndpointSamples)> {
+        self.samples
+            .lock()
+            .expect("stats lock is poisoned")
+            .drain()
+            .map(|((mint_url, _), samples)| (mint_url, samples))
+            .collect()
+    }
+}
+
+/// Measures the duration of a request. `std::time::Instant` is not available on wasm.
+pub(crate) struct RequestTimer {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    start: f64,
+}
+
+impl RequestTimer {
+    pub fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+            #[cfg(target_arch = "wasm32")]
+            start: js_sys::Date::now(),
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.start.elapsed();
+        #[cfg(target_arch = "wasm32")]
+        return Duration::from_millis((js_sys::Date::now() - self.start).max(0.0) as u64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use url::Url;
+
+    use super::{percentile, Endpoint, EndpointSamples, EndpointStats, MAX_SAMPLES};
+
+    #[test]
+    fn test_percentile() {
+        let values = (1..=10).map(|v| v * 10).collect::<Vec<u64>>();
+        assert_eq!(Some(50), percentile(&values, 50));
+        assert_eq!(Some(90), percentile(&values, 90));
+        assert_eq!(Some(100), percentile(&values, 99));
+        assert_eq!(Some(10), percentile(&values, 0));
+        assert_eq!(Some(7), percentile(&[7], 99));
+        assert_eq!(None, percentile(&[], 50));
+    }
+
+    #[test]
+    fn test_endpoint_from_url() -> anyhow::Result<()> {
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let cases = [
+            ("v1/info", Endpoint::Info),
+            ("v1/keys", Endpoint::Keys),
+            ("v1/keys/00d31cecf59d18c0", Endpoint::Keys),
+            ("v1/keysets", Endpoint::Keysets),
+            ("v1/swap", Endpoint::Swap),
+            ("v1/checkstate", Endpoint::CheckState),
+            ("v1/restore", Endpoint::Restore),
+            ("v1/mint/quote/bolt11", Endpoint::MintQuote),
+            ("v1/mint/quote/bolt11/some-quote", Endpoint::MintQuote),
+            ("v1/mint/bolt11", Endpoint::Mint),
+            ("v1/melt/quote/bolt11", Endpoint::MeltQuote),
+            ("v1/melt/bolt11", Endpoint::Melt),
+        ];
+        for (path, expected) in cases {
+            let (url, endpoint) = Endpoint::from_url(&mint_url.join(path)?).expect(path);
+            assert_eq!(mint_url, url);
+            assert_eq!(expected, endpoint);
+            assert_eq!(Ok(expected), endpoint.to_string().parse());
+        }
+        assert_eq!(None, Endpoint::from_url(&mint_url.join("v1/unknown")?));
+        assert_eq!(None, Endpoint::from_url(&mint_url.join("health")?));
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_per_endpoint() -> anyhow::Result<()> {
+        let mint_a = Url::parse("http://127.0.0.1:3338")?;
+        let mint_b = Url::parse("http://127.0.0.1:3339")?;
+        let stats = EndpointStats::default();
+        for latency in [10, 20, 30, 40] {
+            stats.record(
+                &mint_a.join("v1/swap")?,
+                Duration::from_millis(latency),
+                true,
+            );
+        }
+        stats.record(
+            &mint_a.join("v1/melt/bolt11")?,
+            Duration::from_millis(500),
+            false,
+        );
+        stats.record(&mint_b.join("v1/swap")?, Duration::from_millis(5), true);
+
+        let mut samples = stats.take();
+        samples.sort_by_key(|(url, s)| (url.to_string(), s.endpoint));
+        assert_eq!(3, samples.len());
+
+        let (url, swap) = &samples[0];
+        assert_eq!(&mint_a, url);
+        let swap = swap.summary();
+        assert_eq!(
+            (Endpoint::Swap, 4, 0),
+            (swap.endpoint, swap.requests, swap.errors)
+        );
+        assert_eq!(Some(20), swap.p50_ms);
+        assert_eq!(Some(40), swap.p99_ms);
+
+        let melt = samples[1].1.summary();
+        assert_eq!(
+            (Endpoint::Melt, 1, 1),
+            (melt.endpoint, melt.requests, melt.errors)
+        );
+        assert_eq!(1.0, melt.error_rate());
+
+        assert_eq!(&mint_b, &samples[2].0);
+        assert!(stats.take().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_samples_are_bounded() {
+        let mut samples = EndpointSamples::new(Endpoint::Keys);
+        for latency in 0..MAX_SAMPLES as u64 + 50 {
+            samples.record(Duration::from_millis(latency), latency % 2 == 0);
+        }
+        assert_eq!(MAX_SAMPLES, samples.latencies.len());
+        assert_eq!(Some(&50), samples.latencies.front());
+        assert_eq!(MAX_SAMPLES as u64 + 50, samples.requests);
+        assert_eq!(75, samples.errors);
+
+        let mut older = EndpointSamples::new(Endpoint::Keys);
+        older.record(Duration::from_millis(1), true);
+        older.merge(samples);
+        assert_eq!(MAX_SAMPLES, older.latencies.len());
+        assert_eq!(MAX_SAMPLES as u64 + 51, older.requests);
+    }
+}
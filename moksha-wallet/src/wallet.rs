@@ -1,37 +1,60 @@
 use moksha_core::{
-    amount::Amount,
+    amount::{Amount, SplitAmount},
     blind::{BlindedMessage, BlindedSignature, BlindingFactor, TotalAmount},
     dhke::Dhke,
-    keyset::KeysetId,
+    error::MokshaCoreError,
+    keyset::{KeysetId, Keysets},
+    page::PageCursor,
     primitives::{
         CurrencyUnit, MintInfoResponse, PaymentMethod, PostMeltBolt11Response,
         PostMeltBtcOnchainResponse, PostMeltQuoteBolt11Response, PostMeltQuoteBtcOnchainResponse,
-        PostMintQuoteBolt11Response, PostMintQuoteBtcOnchainResponse,
+        PostMintQuoteBolt11Response, PostMintQuoteBtcOnchainResponse, State,
     },
     proof::{Proof, Proofs},
-    token::TokenV3,
+    secret::WellKnownSecret,
+    token::{Token, TokenV3, TOKEN_PREFIX_V3, TOKEN_PREFIX_V4},
 };
 
-use secp256k1::PublicKey;
-use url::Url;
+use futures::{stream, Stream, TryStreamExt};
+use rand::Rng;
+use secp256k1::{PublicKey, Secp256k1, SecretKey};
+use tracing::Instrument;
+use url::{Host, Url};
 
 use crate::{
+    attestation::{AttestationVerifier, BalanceAttestation},
+    backup::{
+        decrypt_backup, encrypt_backup, MintBackup, WalletBackup, BACKUP_MAGIC, BACKUP_VERSION,
+    },
     client::CashuClient,
+    clock::{Clock, SystemClock},
     error::MokshaWalletError,
+    event::{WalletEvent, EVENT_CAPACITY},
     http::CrossPlatformHttpClient,
-    localstore::{LocalStore, WalletKeyset},
-    secret::DeterministicSecret,
+    lnurl::LightningAddress,
+    localstore::{
+        BlindRecovery, HistoryEntry, HistoryFilter, HistoryKind, HistoryPage, LocalStore,
+        LocalStoreTransaction, MultiPayment, MultiPaymentStage, Operation, OperationKind,
+        OperationRecord, PendingMelt, PendingMint, PendingMintOutput, ProofStatus, QueuedReceive,
+        QueuedReceiveStatus, ReservedProof, Snapshot, WalletKeyset, WalletKeysetFilter,
+    },
+    operation::OperationId,
+    secret::{validate_secrets, DeterministicSecret, SecretGenerator},
+    stats::{EndpointSamples, EndpointSummary},
 };
 use lightning_invoice::Bolt11Invoice as LNInvoice;
 use moksha_core::primitives::{PostMintQuoteBitcreditResponse, PostRequestToMintBitcreditResponse};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
+    iter::once,
     str::FromStr,
+    sync::Arc,
+    time::Duration,
     vec,
 };
 
 #[derive(Clone)]
-pub struct Wallet<L, C>
+pub struct Wallet<L, C = CrossPlatformHttpClient>
 where
     L: LocalStore,
     C: CashuClient,
@@ -39,7 +62,46 @@ where
     client: C,
     dhke: Dhke,
     localstore: L,
-    secret: DeterministicSecret,
+    secret_generator: Arc<dyn SecretGenerator>,
+    clock: Arc<dyn Clock>,
+    mint_locks: Arc<QuoteLocks>,
+    /// responses of [`Wallet::get_mint_info`], used to check requests before they are sent.
+    /// `None` if the mint doesn't serve its info.
+    mint_infos: Arc<std::sync::RwLock<HashMap<Url, Option<MintInfoResponse>>>>,
+    snapshot_config: SnapshotConfig,
+    wallet_id: String,
+    /// see [`Wallet::with_operation_timeout`], not supported on wasm
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    operation_timeout: Option<Duration>,
+    /// see [`WalletBuilder::with_allow_insecure_mints`]
+    allow_insecure_mints: bool,
+    /// see [`Wallet::subscribe`], clones of a wallet share the subscribers
+    events: tokio::sync::broadcast::Sender<WalletEvent>,
+    /// see [`WalletBuilder::with_auto_withdraw`]
+    auto_withdraw: Option<AutoWithdrawConfig>,
+    /// failed auto-withdrawals per mint. Held during a withdrawal, so concurrent receives don't
+    /// withdraw the same balance twice.
+    auto_withdraw_backoff: Arc<tokio::sync::Mutex<HashMap<Url, AutoWithdrawBackoff>>>,
+}
+
+/// Serializes the mint attempts for a quote within the process. Clones of a wallet share the locks.
+#[derive(Default)]
+struct QuoteLocks(std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>);
+
+impl QuoteLocks {
+    fn get(&self, quote_id: &str) -> Arc<tokio::sync::Mutex<()>> {
+        let mut locks = self.0.lock().expect("quote locks are poisoned");
+        // drop the locks nobody is waiting for anymore
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        locks.entry(quote_id.to_owned()).or_default().clone()
+    }
+}
+
+/// Derivation counters of a keyset that were reserved for new outputs
+#[derive(Debug, Clone, Copy, Default)]
+struct CounterRange {
+    start: u32,
+    count: u32,
 }
 
 pub struct WalletBuilder<L, C: CashuClient = CrossPlatformHttpClient>
@@ -49,6 +111,12 @@ where
 {
     client: Option<C>,
     localstore: Option<L>,
+    clock: Option<Arc<dyn Clock>>,
+    secret_generator: Option<Arc<dyn SecretGenerator>>,
+    seed_words: Option<String>,
+    snapshot_config: SnapshotConfig,
+    allow_insecure_mints: bool,
+    auto_withdraw: Option<AutoWithdrawConfig>,
 }
 
 impl<L, C> WalletBuilder<L, C>
@@ -60,6 +128,12 @@ where
         Self {
             client: Some(C::default()),
             localstore: None,
+            clock: None,
+            secret_generator: None,
+            seed_words: None,
+            snapshot_config: SnapshotConfig::default(),
+            allow_insecure_mints: false,
+            auto_withdraw: None,
         }
     }
 
@@ -73,16 +147,64 @@ where
         self
     }
 
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn with_snapshot_config(mut self, snapshot_config: SnapshotConfig) -> Self {
+        self.snapshot_config = snapshot_config;
+        self
+    }
+
+    /// Replaces the secrets derived from the seed (NUT-13) with a custom source. Proofs that
+    /// were created with a custom generator can't be restored from the seed words.
+    pub fn with_secret_generator(mut self, secret_generator: Arc<dyn SecretGenerator>) -> Self {
+        self.secret_generator = Some(secret_generator);
+        self
+    }
+
+    /// Derives the secrets from the given BIP39 seed words instead of a random seed, e.g. to
+    /// restore a wallet. The seed words are stored in the localstore on the first build.
+    pub fn with_seed_words(mut self, seed_words: impl Into<String>) -> Self {
+        self.seed_words = Some(seed_words.into());
+        self
+    }
+
+    /// Allows mints with plain http urls on public hosts, see [`is_secure_mint_url`]. By default
+    /// the wallet refuses to add such a mint or to receive its tokens.
+    pub fn with_allow_insecure_mints(mut self, allow_insecure_mints: bool) -> Self {
+        self.allow_insecure_mints = allow_insecure_mints;
+        self
+    }
+
+    /// Pays the balance of a mint to a lightning address after receiving tokens, see
+    /// [`Wallet::auto_withdraw`]
+    pub fn with_auto_withdraw(mut self, auto_withdraw: AutoWithdrawConfig) -> Self {
+        self.auto_withdraw = Some(auto_withdraw);
+        self
+    }
+
     pub async fn build(self) -> Result<Wallet<L, C>, MokshaWalletError> {
         let client = self.client.unwrap_or_default();
         let localstore = self.localstore.expect("localstore is required");
 
         let mut tx = localstore.begin_tx().await?;
         let seed_words = localstore.get_seed(&mut tx).await?;
-        let seed = match seed_words {
-            Some(seed) => seed,
-            None => {
-                let seed = DeterministicSecret::generate_random_seed_words()?;
+        let seed = match (seed_words, self.seed_words) {
+            (Some(stored), Some(seed)) if stored != seed => {
+                return Err(MokshaWalletError::SeedMismatch);
+            }
+            (Some(seed), _) => seed,
+            (None, seed) => {
+                let seed = match seed {
+                    Some(seed) => {
+                        // fail before the seed is stored
+                        DeterministicSecret::from_seed_words(&seed)?;
+                        seed
+                    }
+                    None => DeterministicSecret::generate_random_seed_words()?,
+                };
                 localstore.add_seed(&mut tx, &seed).await?;
                 seed
             }
@@ -90,14 +212,43 @@ where
 
         tx.commit().await?;
 
+        let deterministic_secret = DeterministicSecret::from_seed_words(&seed)?;
+        let wallet_id = deterministic_secret.wallet_id()?;
+        let secret_generator = match self.secret_generator {
+            Some(secret_generator) => secret_generator,
+            None => Arc::new(deterministic_secret),
+        };
+
         Ok(Wallet::new(
             client as C,
             localstore,
-            DeterministicSecret::from_seed_words(&seed)?,
+            secret_generator,
+            self.clock.unwrap_or_else(|| Arc::new(SystemClock)),
+            self.snapshot_config,
+            wallet_id,
+            self.allow_insecure_mints,
+            self.auto_withdraw,
         ))
     }
 }
 
+#[cfg(not(target_arch = "wasm32"))]
+impl<L> WalletBuilder<L, CrossPlatformHttpClient>
+where
+    L: LocalStore,
+{
+    /// Records the requests to the mints with their redacted responses in the session file at
+    /// `path`, so a failed operation can be replayed with [`CrossPlatformHttpClient::replay`]
+    pub fn with_recorder(
+        mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, MokshaWalletError> {
+        let client = self.client.take().unwrap_or_default();
+        self.client = Some(client.with_recorder(path)?);
+        Ok(self)
+    }
+}
+
 impl<L, C> Default for WalletBuilder<L, C>
 where
     C: CashuClient + Default,
@@ -108,17 +259,225 @@ where
     }
 }
 
+/// The records of an operation, see [`Wallet::operation_details`]
+#[derive(Debug, Clone)]
+pub struct OperationDetails {
+    pub operation: Operation,
+    /// every record the operation wrote in the order it was written, including records that were
+    /// removed since, e.g. the pending melt of a finished payment
+    pub records: Vec<OperationRecord>,
+    /// the linked proofs that are still in the localstore with their current state
+    pub proofs: Vec<(Proof, ProofStatus)>,
+    pub pending_mints: Vec<PendingMint>,
+    pub pending_melts: Vec<PendingMelt>,
+    pub multi_payments: Vec<MultiPayment>,
+}
+
+/// Result of comparing the proofs and keysets in the localstore with the state of a mint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditReport {
+    pub mint_url: Url,
+    /// total amount of the proofs the mint reports as unspent
+    pub confirmed_balance: u64,
+    /// proofs in the localstore that are already spent according to the mint
+    pub spent_proofs: Proofs,
+    /// proofs that are currently pending in the mint (e.g. used in an unfinished melt)
+    pub pending_proofs: Proofs,
+    /// keysets in the localstore that are unknown to the mint
+    pub unknown_keysets: Vec<WalletKeyset>,
+}
+
+impl AuditReport {
+    pub fn is_consistent(&self) -> bool {
+        self.spent_proofs.is_empty()
+            && self.pending_proofs.is_empty()
+            && self.unknown_keysets.is_empty()
+    }
+}
+
+/// Result of [`Wallet::release_stuck_reservations`] and [`Wallet::recover_pending`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReleasedReservations {
+    /// unspent proofs that are part of the balance again
+    pub released: Proofs,
+    /// proofs the mint reports as spent, they have been removed from the localstore
+    pub spent: Proofs,
+}
+
+/// Preview of a token before it is redeemed, see [`Wallet::inspect_token`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenSummary {
+    pub total_amount: u64,
+    /// distinct mint urls of the token entries in the order of the token
+    pub mint_urls: Vec<String>,
+    /// amount of every proof in the order of the token
+    pub denominations: Vec<u64>,
+    pub proof_count: usize,
+    pub memo: Option<String>,
+}
+
+/// Outcome of [`Wallet::receive_tokens_lenient`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReceiveReport {
+    pub redeemed_amount: u64,
+    pub redeemed_proofs: usize,
+    /// amount of the proofs that were already spent or pending
+    pub skipped_amount: u64,
+    pub skipped_proofs: usize,
+}
+
+/// Outputs of a swap, see [`Wallet::plan_split`]
+#[derive(Debug, Clone)]
+pub struct SplitPlan {
+    /// secrets of the outputs for the rest of the tokens
+    pub first_secrets: Vec<(String, BlindingFactor)>,
+    pub first_outputs: Vec<(BlindedMessage, BlindingFactor)>,
+    /// secrets of the outputs for the split amount
+    pub second_secrets: Vec<(String, BlindingFactor)>,
+    pub second_outputs: Vec<(BlindedMessage, BlindingFactor)>,
+}
+
+/// Retention of the snapshots of the proofs, see [`Wallet::take_snapshot`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotConfig {
+    /// number of snapshots that are kept, older ones are deleted
+    pub max_snapshots: usize,
+    /// total size of the stored proofs in bytes, the newest snapshot is always kept
+    pub max_size: usize,
+    /// seconds between the snapshots of [`Wallet::take_scheduled_snapshot`], `None` disables them
+    pub interval: Option<u64>,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            max_snapshots: 10,
+            max_size: 10_000_000,
+            interval: Some(24 * 60 * 60),
+        }
+    }
+}
+
+/// Pays the balance of a mint to a lightning address once it reaches `min_batch_sat`, see
+/// [`Wallet::auto_withdraw`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoWithdrawConfig {
+    pub ln_address: LightningAddress,
+    /// the balance of a mint is withdrawn once it reaches this amount
+    pub min_batch_sat: u64,
+    /// a withdrawal is skipped if the fee reserve of the melt quote exceeds this percentage of
+    /// the withdrawn amount. The same share is kept back from the balance for the fees.
+    pub max_fee_percent: f64,
+}
+
+/// Outcome of [`Wallet::auto_withdraw`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AutoWithdrawal {
+    /// no [`AutoWithdrawConfig`] is set
+    Disabled,
+    /// the balance of the mint is below `min_batch_sat`
+    BelowThreshold {
+        balance: u64,
+    },
+    /// a previous withdrawal failed, the next one is attempted at `retry_at` (unix timestamp)
+    BackingOff {
+        retry_at: u64,
+    },
+    /// the fee reserve of the melt quote exceeds `max_fee_percent`, the balance is kept
+    FeeTooHigh {
+        amount: u64,
+        fee_reserve: u64,
+    },
+    /// the mint hasn't settled the payment yet, see [`Wallet::recover_pending`]
+    Pending {
+        amount: u64,
+    },
+    Paid {
+        amount: u64,
+    },
+}
+
+/// Rounds the summed input fees of proofs in parts per thousand up to a full sat (NUT-02)
+fn input_fee_of_ppk(fee_ppk: u64) -> u64 {
+    fee_ppk.div_ceil(1000)
+}
+
+/// Delay in seconds before the first retry of a failed auto-withdrawal. Doubles with every
+/// failure up to [`AUTO_WITHDRAW_MAX_DELAY`].
+pub const AUTO_WITHDRAW_BASE_DELAY: u64 = 60;
+
+pub const AUTO_WITHDRAW_MAX_DELAY: u64 = 60 * 60;
+
+/// Failed auto-withdrawals of a mint in a row
+#[derive(Debug, Clone, Default)]
+struct AutoWithdrawBackoff {
+    failures: u32,
+    retry_at: u64,
+}
+
+/// Number of failed attempts after which a queued receive is marked as failed
+pub const RECEIVE_QUEUE_MAX_ATTEMPTS: u32 = 10;
+
+/// Delay in seconds before the first retry of a queued receive. Doubles with every attempt.
+pub const RECEIVE_QUEUE_BASE_DELAY: u64 = 30;
+
+/// Seconds after which the claim of an unfinished mint attempt is considered abandoned
+pub const MINT_CLAIM_TIMEOUT: u64 = 120;
+
+/// Number of consecutive batches without any signature after which [`Wallet::restore`] stops
+/// scanning a keyset
+pub const RESTORE_GAP_LIMIT: u32 = 3;
+
+/// Number of history entries that [`Wallet::transactions_stream`] loads at once
+pub const HISTORY_PAGE_SIZE: usize = 100;
+
+/// State transitions of the entries in the receive queue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReceiveQueueEvent {
+    /// the token was redeemed and removed from the queue
+    Received { id: u64, amount: u64 },
+    /// the attempt failed and will be retried at `next_attempt`
+    Retrying {
+        id: u64,
+        attempts: u32,
+        next_attempt: u64,
+        error: String,
+    },
+    /// the token is already spent or ran out of attempts
+    Failed { id: u64, reason: String },
+}
+
 impl<L, C> Wallet<L, C>
 where
     C: CashuClient + Default,
     L: LocalStore,
 {
-    fn new(client: C, localstore: L, secret: DeterministicSecret) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        client: C,
+        localstore: L,
+        secret_generator: Arc<dyn SecretGenerator>,
+        clock: Arc<dyn Clock>,
+        snapshot_config: SnapshotConfig,
+        wallet_id: String,
+        allow_insecure_mints: bool,
+        auto_withdraw: Option<AutoWithdrawConfig>,
+    ) -> Self {
         Self {
             client,
             dhke: Dhke::new(),
             localstore,
-            secret,
+            secret_generator,
+            clock,
+            mint_locks: Arc::default(),
+            mint_infos: Arc::default(),
+            snapshot_config,
+            wallet_id,
+            operation_timeout: None,
+            allow_insecure_mints,
+            events: tokio::sync::broadcast::channel(EVENT_CAPACITY).0,
+            auto_withdraw,
+            auto_withdraw_backoff: Arc::default(),
         }
     }
 
@@ -126,6 +485,262 @@ where
         WalletBuilder::default()
     }
 
+    /// Receives the progress of the operations that start after subscribing, see
+    /// [`crate::event`]
+    pub fn subscribe(&self) -> tokio::sync::broadcast::Receiver<WalletEvent> {
+        self.events.subscribe()
+    }
+
+    /// Sends an event to the subscribers, it is dropped if there are none
+    fn emit(&self, event: WalletEvent) {
+        let _ = self.events.send(event);
+    }
+
+    /// Fails with [`MokshaWalletError::InsecureMintUrl`] if the mint url is not secure, unless
+    /// insecure mints are allowed, see [`is_secure_mint_url`]
+    pub fn check_mint_url(&self, mint_url: &Url) -> Result<(), MokshaWalletError> {
+        if self.allow_insecure_mints || is_secure_mint_url(mint_url) {
+            return Ok(());
+        }
+        Err(MokshaWalletError::InsecureMintUrl(mint_url.to_string()))
+    }
+
+    /// Stable identifier derived from the seed, which doesn't reveal the seed. It stays the same
+    /// when the wallet is restored from the seed words on another device.
+    pub fn wallet_id(&self) -> String {
+        self.wallet_id.clone()
+    }
+
+    /// Fails the operations of the wallet, see [`Wallet::operations`], with
+    /// [`MokshaWalletError::OperationTimeout`] if they take longer than `timeout`. Proofs that are
+    /// already reserved for the operation stay reserved, [`Wallet::recover_pending`] settles them
+    /// with the mint.
+    pub fn with_operation_timeout(mut self, timeout: Duration) -> Self {
+        self.operation_timeout = Some(timeout);
+        self
+    }
+
+    /// Runs an operation with the timeout of [`Wallet::with_operation_timeout`]. A timed out
+    /// operation is dropped at the await point it is waiting on.
+    async fn with_timeout<T>(
+        &self,
+        operation: impl std::future::Future<Output = Result<T, MokshaWalletError>>,
+    ) -> Result<T, MokshaWalletError> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(timeout) = self.operation_timeout {
+            return tokio::time::timeout(timeout, operation)
+                .await
+                .map_err(|_| MokshaWalletError::OperationTimeout(timeout))?;
+        }
+        operation.await
+    }
+
+    /// Runs a top-level operation with a new [`OperationId`]. The operation is stored before it
+    /// starts and its outcome once it is done, the records it writes are linked to the id.
+    async fn run_operation<T, F>(
+        &self,
+        kind: OperationKind,
+        operation: impl FnOnce(OperationId) -> F,
+    ) -> Result<T, MokshaWalletError>
+    where
+        F: std::future::Future<Output = Result<T, MokshaWalletError>>,
+    {
+        let now = self.clock.now();
+        // the clock of the wallet has a resolution of seconds
+        let id = OperationId::new(now.saturating_mul(1_000));
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .add_operation(
+                &mut tx,
+                &Operation {
+                    id: id.clone(),
+                    kind,
+                    created_at: now,
+                    finished_at: None,
+                    error: None,
+                },
+            )
+            .await?;
+        tx.commit().await?;
+        let balance = self.get_balance().await?;
+        self.emit(WalletEvent::OperationStarted {
+            id: id.clone(),
+            kind,
+        });
+
+        let span = tracing::info_span!("operation", operation_id = %id, ?kind);
+        let result = id
+            .clone()
+            .scope(self.with_timeout(operation(id.clone())))
+            .instrument(span)
+            .await;
+
+        let error = result.as_ref().err().map(ToString::to_string);
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .update_operation(
+                &mut tx,
+                &Operation {
+                    id: id.clone(),
+                    kind,
+                    created_at: now,
+                    finished_at: Some(self.clock.now()),
+                    error: error.clone(),
+                },
+            )
+            .await?;
+        tx.commit().await?;
+        let new_balance = self.get_balance().await?;
+        if new_balance != balance {
+            self.emit(WalletEvent::BalanceChanged { new_balance });
+        }
+        self.emit(WalletEvent::OperationFinished { id, kind, error });
+        result
+    }
+
+    /// Returns the operations of the wallet sorted by their id, i.e. by the time they started
+    pub async fn operations(&self) -> Result<Vec<Operation>, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let operations = self.localstore.get_operations(&mut tx).await?;
+        tx.commit().await?;
+        Ok(operations)
+    }
+
+    /// Returns the mints, sends, receives and payments, the oldest first
+    pub async fn get_history(&self) -> Result<Vec<HistoryEntry>, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let history = self.localstore.get_history(&mut tx).await?;
+        tx.commit().await?;
+        Ok(history)
+    }
+
+    /// Returns up to `limit` entries of the history that match the filter, the newest first.
+    /// Starts after the cursor `before`, the cursor of the page continues with the older entries.
+    pub async fn get_history_page(
+        &self,
+        filter: &HistoryFilter,
+        before: Option<&PageCursor>,
+        limit: usize,
+    ) -> Result<HistoryPage, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let page = self
+            .localstore
+            .get_history_page(&mut tx, filter, before, limit)
+            .await?;
+        tx.commit().await?;
+        Ok(page)
+    }
+
+    /// Streams the entries of the history that match the filter, the newest first. Loads them in
+    /// pages of [`HISTORY_PAGE_SIZE`], entries that are added while streaming are not returned.
+    pub fn transactions_stream(
+        &self,
+        filter: HistoryFilter,
+    ) -> impl Stream<Item = Result<HistoryEntry, MokshaWalletError>> + '_ {
+        // `None` once the last page was loaded
+        let start: Option<Option<PageCursor>> = Some(None);
+        stream::try_unfold(start, move |before| {
+            let filter = filter.clone();
+            async move {
+                let Some(before) = before else {
+                    return Ok::<_, MokshaWalletError>(None);
+                };
+                let page = self
+                    .get_history_page(&filter, before.as_ref(), HISTORY_PAGE_SIZE)
+                    .await?;
+                Ok(Some((page.entries, page.next.map(Some))))
+            }
+        })
+        .map_ok(|entries| stream::iter(entries.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// An entry of the history without invoice and memo that is created now
+    fn history_entry(
+        &self,
+        operation_id: &OperationId,
+        kind: HistoryKind,
+        amount: u64,
+        mint_url: &Url,
+    ) -> HistoryEntry {
+        HistoryEntry {
+            id: None,
+            operation_id: operation_id.clone(),
+            kind,
+            amount,
+            mint_url: mint_url.clone(),
+            created_at: self.clock.now(),
+            invoice: None,
+            memo: None,
+        }
+    }
+
+    /// Collects the records of an operation from the localstore. Fails with
+    /// [`MokshaWalletError::OperationNotFound`] for an unknown id.
+    pub async fn operation_details(
+        &self,
+        id: &OperationId,
+    ) -> Result<OperationDetails, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let operation = self
+            .localstore
+            .get_operation(&mut tx, id)
+            .await?
+            .ok_or_else(|| MokshaWalletError::OperationNotFound(id.to_string()))?;
+        let records = self.localstore.get_operation_records(&mut tx, id).await?;
+
+        let mut proofs = vec![];
+        for state in ProofStatus::ALL {
+            for proof in self
+                .localstore
+                .get_proofs_by_state(&mut tx, state)
+                .await?
+                .proofs()
+            {
+                if records.contains(&OperationRecord::Proof(proof.secret.clone())) {
+                    proofs.push((proof, state));
+                }
+            }
+        }
+        let mut pending_mints = vec![];
+        for record in &records {
+            if let OperationRecord::PendingMint(quote_id) = record {
+                pending_mints.extend(self.localstore.get_pending_mint(&mut tx, quote_id).await?);
+            }
+        }
+        let pending_melts = self
+            .localstore
+            .get_pending_melts(&mut tx)
+            .await?
+            .into_iter()
+            .filter(|melt| {
+                melt.id
+                    .is_some_and(|id| records.contains(&OperationRecord::PendingMelt(id)))
+            })
+            .collect();
+        let multi_payments = self
+            .localstore
+            .get_multi_payments(&mut tx)
+            .await?
+            .into_iter()
+            .filter(|payment| {
+                payment
+                    .id
+                    .is_some_and(|id| records.contains(&OperationRecord::MultiPayment(id)))
+            })
+            .collect();
+        tx.commit().await?;
+
+        Ok(OperationDetails {
+            operation,
+            records,
+            proofs,
+            pending_mints,
+            pending_melts,
+            multi_payments,
+        })
+    }
+
     pub async fn create_quote_bolt11(
         &self,
         mint_url: &Url,
@@ -229,6 +844,8 @@ where
         &self,
         mint_url: &Url,
     ) -> Result<Vec<WalletKeyset>, MokshaWalletError> {
+        let mint_url = &normalize_mint_url(mint_url)?;
+        self.check_mint_url(mint_url)?;
         if !self.client.is_v1_supported(mint_url).await? {
             return Err(MokshaWalletError::UnsupportedApiVersion);
         }
@@ -266,14 +883,17 @@ where
                 }
             };
 
-            let wallet_keyset = WalletKeyset::new(
-                &keyset_id,
-                mint_url,
-                &keyset.unit,
-                0,
-                public_keys,
-                keyset.active,
-            );
+            let wallet_keyset = WalletKeyset {
+                input_fee_ppk: keyset.input_fee_ppk,
+                ..WalletKeyset::new(
+                    &keyset_id,
+                    mint_url,
+                    &keyset.unit,
+                    0,
+                    public_keys,
+                    keyset.active,
+                )
+            };
 
             result.push(wallet_keyset.clone());
             self.localstore
@@ -291,907 +911,8788 @@ where
         Ok(total_amount)
     }
 
-    pub async fn send_tokens(
-        &self,
-        wallet_keyset: &WalletKeyset,
-        amount: u64,
-    ) -> Result<TokenV3, MokshaWalletError> {
-        let balance = self.get_balance().await?;
-        if amount > balance {
-            return Err(MokshaWalletError::NotEnoughTokens);
-        }
+    /// Returns the balance of the proofs of all keysets of a mint
+    pub async fn get_balance_for_mint(&self, mint_url: &Url) -> Result<u64, MokshaWalletError> {
+        Ok(self.get_proofs_for_mint(mint_url).await?.total_amount())
+    }
 
+    /// Returns the balance grouped by the keyset id of the proofs
+    pub async fn get_balance_per_keyset(&self) -> Result<HashMap<String, u64>, MokshaWalletError> {
         let mut tx = self.localstore.begin_tx().await?;
-        let all_proofs = self
-            .localstore
-            .get_proofs(&mut tx)
-            .await?
-            .proofs_by_keyset(&wallet_keyset.keyset_id);
+        let proofs = self.localstore.get_proofs(&mut tx).await?;
         tx.commit().await?;
 
-        let selected_proofs = all_proofs.proofs_for_amount(amount)?;
-        let selected_tokens = (wallet_keyset.mint_url.to_owned(), selected_proofs.clone()).into();
+        let mut balances = HashMap::new();
+        for proof in proofs.proofs() {
+            *balances.entry(proof.keyset_id).or_insert(0) += proof.amount;
+        }
+        Ok(balances)
+    }
 
-        let (remaining_tokens, result) = self
-            .swap_tokens(wallet_keyset, &selected_tokens, amount.into())
-            .await?;
+    /// Returns the input fee (NUT-02) the recipient has to pay to the mint when swapping the proofs
+    /// of the token. Keysets that are unknown to the wallet are fetched from the mint of the token.
+    pub async fn redeem_fee(&self, tokens: &TokenV3) -> Result<u64, MokshaWalletError> {
+        let wallet_keysets = self.get_wallet_keysets().await?;
+
+        let mut total_fee = 0;
+        for token in tokens.tokens.iter() {
+            let mint_url = token
+                .mint
+                .as_ref()
+                .ok_or_else(|| MokshaWalletError::InvalidToken("missing mint url".to_owned()))?;
+
+            let mut mint_keysets: Option<Keysets> = None;
+            let mut fee_ppk = 0;
+            for proof in token.proofs.proofs() {
+                let known = wallet_keysets
+                    .iter()
+                    .find(|k| k.mint_url == *mint_url && k.keyset_id.to_string() == proof.keyset_id)
+                    .map(|k| k.input_fee_ppk);
+
+                let input_fee_ppk = match known {
+                    Some(input_fee_ppk) => input_fee_ppk,
+                    None => {
+                        if mint_keysets.is_none() {
+                            mint_keysets = Some(self.client.get_keysets(mint_url).await?);
+                        }
+                        mint_keysets
+                            .as_ref()
+                            .and_then(|k| k.keysets.iter().find(|k| k.id == proof.keyset_id))
+                            .map(|k| k.input_fee_ppk)
+                            .ok_or_else(|| {
+                                MokshaWalletError::InvalidToken(format!(
+                                    "unknown keyset {}",
+                                    proof.keyset_id
+                                ))
+                            })?
+                    }
+                };
+                fee_ppk += input_fee_ppk;
+            }
+            // every mint of the token is a separate swap, the fee is rounded up per swap
+            total_fee += input_fee_of_ppk(fee_ppk);
+        }
+        Ok(total_fee)
+    }
 
-        let mut tx = self.localstore.begin_tx().await?;
-        self.localstore
-            .delete_proofs(&mut tx, &selected_proofs)
-            .await?;
+    /// Returns the input fee (NUT-02) the mint charges for spending the proofs of the wallet.
+    /// Keysets the wallet doesn't know, e.g. legacy keysets, have no fee.
+    async fn input_fee(&self, mint_url: &Url, proofs: &Proofs) -> Result<u64, MokshaWalletError> {
+        let wallet_keysets = self.get_wallet_keysets().await?;
+        let fee_ppk = proofs
+            .proofs()
+            .iter()
+            .filter_map(|proof| {
+                wallet_keysets
+                    .iter()
+                    .find(|k| k.mint_url == *mint_url && k.keyset_id.to_string() == proof.keyset_id)
+                    .map(|k| k.input_fee_ppk)
+            })
+            .sum();
+        Ok(input_fee_of_ppk(fee_ppk))
+    }
 
-        self.localstore
-            .add_proofs(&mut tx, &remaining_tokens.proofs())
-            .await?;
-        tx.commit().await?;
-        Ok(result)
+    /// Selects proofs that cover the amount and the input fee of spending them in a swap
+    async fn proofs_for_amount_with_fee(
+        &self,
+        mint_url: &Url,
+        proofs: &Proofs,
+        amount: u64,
+    ) -> Result<Proofs, MokshaWalletError> {
+        let mut needed = amount;
+        loop {
+            let selected = proofs.proofs_for_amount(needed)?;
+            let fee = self.input_fee(mint_url, &selected).await?;
+            if selected.total_amount() >= amount + fee {
+                return Ok(selected);
+            }
+            // more proofs cost more fees, so the needed amount grows until the selection covers it
+            needed = amount + fee;
+        }
     }
 
-    pub async fn receive_tokens(
+    /// Amount the inputs of a melt of `amount` need, including the input fee of the proofs of the
+    /// keyset that a swap creates for it
+    fn melt_amount_with_fee(wallet_keyset: &WalletKeyset, amount: u64) -> u64 {
+        let mut melt_amount = amount;
+        loop {
+            let fee = input_fee_of_ppk(
+                Amount(melt_amount).split().len() as u64 * wallet_keyset.input_fee_ppk,
+            );
+            if melt_amount >= amount + fee {
+                return melt_amount;
+            }
+            melt_amount = amount + fee;
+        }
+    }
+
+    /// Returns proofs of the keyset that sum up to exactly the amount, so they can be sent
+    /// without a swap. `None` means a swap at the mint is required, the mint is not contacted.
+    pub async fn exact_proofs(
         &self,
         wallet_keyset: &WalletKeyset,
-        tokens: &TokenV3,
-    ) -> Result<(), MokshaWalletError> {
-        let total_amount = tokens.total_amount();
-        let (_, redeemed_tokens) = self
-            .swap_tokens(wallet_keyset, tokens, total_amount.into())
-            .await?;
+        amount: u64,
+    ) -> Result<Option<Proofs>, MokshaWalletError> {
         let mut tx = self.localstore.begin_tx().await?;
-        self.localstore
-            .add_proofs(&mut tx, &redeemed_tokens.proofs())
-            .await?;
+        let all_proofs = self
+            .localstore
+            .get_proofs(&mut tx)
+            .await?
+            .proofs_by_keyset(&wallet_keyset.keyset_id);
         tx.commit().await?;
-        Ok(())
+
+        Ok(all_proofs.exact_proofs_for_amount(amount))
     }
 
-    pub async fn get_mint_quote(
+    pub async fn send_tokens(
         &self,
-        mint_url: &Url,
-        amount: Amount,
-        currency: CurrencyUnit,
-    ) -> Result<PostMintQuoteBolt11Response, MokshaWalletError> {
-        self.client
-            .post_mint_quote_bolt11(mint_url, amount.0, currency)
+        wallet_keyset: &WalletKeyset,
+        amount: u64,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        self.send_tokens_with_locktime(wallet_keyset, amount, None, None, None)
             .await
     }
 
-    pub async fn get_melt_quote_bolt11(
+    /// Same as [`Wallet::send_tokens`], the memo is added to the token for the receiver
+    pub async fn send_tokens_with_memo(
         &self,
-        mint_url: &Url,
-        invoice: String,
-        currency: CurrencyUnit,
-    ) -> Result<PostMeltQuoteBolt11Response, MokshaWalletError> {
-        self.client
-            .post_melt_quote_bolt11(mint_url, invoice.clone(), currency)
+        wallet_keyset: &WalletKeyset,
+        amount: u64,
+        memo: Option<String>,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        self.send_tokens_with_locktime(wallet_keyset, amount, None, None, memo)
             .await
     }
 
-    pub async fn pay_invoice(
+    /// Same as [`Wallet::send_tokens`], but the token consists of proofs with the given
+    /// `denominations` instead of the powers of two of the amount, e.g. many small proofs so the
+    /// receiver can pay smaller amounts without a swap. The denominations must add up to `amount`
+    /// and the keyset must have a key for each of them.
+    pub async fn send_tokens_with_denominations(
         &self,
         wallet_keyset: &WalletKeyset,
-        melt_quote: &PostMeltQuoteBolt11Response,
-        invoice: String,
-    ) -> Result<(PostMeltBolt11Response, u64), MokshaWalletError> {
-        let mut tx = self.localstore.begin_tx().await?;
-        let all_proofs = self.localstore.get_proofs(&mut tx).await?;
-        tx.commit().await?;
+        amount: u64,
+        denominations: Vec<u64>,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        let total = denominations.iter().sum::<u64>();
+        if total != amount {
+            return Err(MokshaWalletError::InvalidDenominations(format!(
+                "denominations add up to {total} instead of {amount}"
+            )));
+        }
+        if let Some(unknown) = denominations
+            .iter()
+            .find(|denomination| !wallet_keyset.public_keys.contains_key(*denomination))
+        {
+            return Err(MokshaWalletError::InvalidDenominations(format!(
+                "keyset {} has no key for {unknown}",
+                wallet_keyset.keyset_id.to_string()
+            )));
+        }
 
-        let ln_amount = Self::get_invoice_amount(&invoice)? + melt_quote.fee_reserve;
+        self.send_tokens_with_locktime(
+            wallet_keyset,
+            amount,
+            None,
+            Some(denominations.into()),
+            None,
+        )
+        .await
+    }
 
-        if ln_amount > all_proofs.total_amount() {
-            return Err(MokshaWalletError::NotEnoughTokens);
-        }
-        let selected_proofs = all_proofs.proofs_for_amount(ln_amount)?;
+    /// Creates a token that the mint only accepts after the unix timestamp `redeemable_after`.
+    ///
+    /// The proofs are locked to a throwaway key with a P2PK secret (NUT-11), so they can't be
+    /// spent by anyone until the locktime has passed.
+    pub async fn send_timelocked(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        amount: u64,
+        redeemable_after: u64,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        self.send_tokens_with_locktime(wallet_keyset, amount, Some(redeemable_after), None, None)
+            .await
+    }
 
-        let total_proofs = {
-            let selected_tokens =
-                (wallet_keyset.mint_url.to_owned(), selected_proofs.clone()).into();
-            let swap_result = self
-                .swap_tokens(wallet_keyset, &selected_tokens, ln_amount.into())
-                .await?;
+    async fn send_tokens_with_locktime(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        amount: u64,
+        locktime: Option<u64>,
+        denominations: Option<SplitAmount>,
+        memo: Option<String>,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        self.run_operation(OperationKind::Send, |operation_id| async move {
+            let mut tx = self.localstore.begin_tx().await?;
+            let all_proofs = self
+                .localstore
+                .get_proofs(&mut tx)
+                .await?
+                .proofs_by_keyset(&wallet_keyset.keyset_id);
+            tx.commit().await?;
+
+            if amount > all_proofs.total_amount() {
+                return Err(MokshaWalletError::NotEnoughTokens {
+                    needed: amount,
+                    available: all_proofs.total_amount(),
+                });
+            }
+
+            let exact_proofs = match (locktime, &denominations) {
+                (None, None) => all_proofs.exact_proofs_for_amount(amount),
+                _ => None,
+            };
+            let selected_proofs = match exact_proofs {
+                Some(proofs) => proofs,
+                None => {
+                    self.proofs_for_amount_with_fee(&wallet_keyset.mint_url, &all_proofs, amount)
+                        .await?
+                }
+            };
+            let selected_tokens: TokenV3 = (
+                wallet_keyset.mint_url.to_owned(),
+                wallet_keyset.currency_unit.clone(),
+                selected_proofs.clone(),
+            )
+                .into();
+
+            // the proofs can be sent as they are, if they match the amount and don't need to be locked
+            // or split into other denominations
+            let send_as_is = locktime.is_none()
+                && denominations.is_none()
+                && selected_proofs.total_amount() == amount;
+            let (remaining_tokens, result) = if send_as_is {
+                (TokenV3::empty(), selected_tokens)
+            } else {
+                self.swap_tokens_with_locktime(
+                    wallet_keyset,
+                    &selected_tokens,
+                    amount.into(),
+                    locktime,
+                    denominations,
+                )
+                .await?
+            };
 
+            // the localstore is only changed after a successful swap
             let mut tx = self.localstore.begin_tx().await?;
             self.localstore
-                .delete_proofs(&mut tx, &selected_proofs)
+                .transition_proofs(
+                    &mut tx,
+                    &selected_proofs.secrets(),
+                    ProofStatus::Spendable,
+                    match send_as_is {
+                        true => ProofStatus::PendingSend,
+                        false => ProofStatus::Spent,
+                    },
+                    self.clock.now(),
+                )
                 .await?;
+
             self.localstore
-                .add_proofs(&mut tx, &swap_result.0.proofs())
+                .add_proofs(&mut tx, &remaining_tokens.proofs())
+                .await?;
+            self.localstore
+                .add_operation_records(
+                    &mut tx,
+                    &operation_id,
+                    &[
+                        OperationRecord::proofs(&selected_proofs),
+                        OperationRecord::proofs(&remaining_tokens.proofs()),
+                    ]
+                    .concat(),
+                )
+                .await?;
+            self.localstore
+                .add_history_entry(
+                    &mut tx,
+                    &HistoryEntry {
+                        memo: memo.clone(),
+                        ..self.history_entry(
+                            &operation_id,
+                            HistoryKind::Send,
+                            amount,
+                            &wallet_keyset.mint_url,
+                        )
+                    },
+                )
                 .await?;
             tx.commit().await?;
+            Ok(TokenV3 { memo, ..result })
+        })
+        .await
+    }
 
-            swap_result.1.proofs()
-        };
-
-        let fee_blind = self
-            .create_blank(melt_quote.fee_reserve.into(), &wallet_keyset.keyset_id)
+    /// Redeems the proofs of a token at its mint. Returns the summary of the token, including
+    /// the memo of the sender.
+    ///
+    /// `cashuB` tokens are normalized to a [`TokenV3`] when they are parsed, see
+    /// [`TokenV3::deserialize`].
+    pub async fn receive_tokens(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+    ) -> Result<TokenSummary, MokshaWalletError> {
+        let summary = self
+            .run_operation(OperationKind::Receive, |operation_id| async move {
+                self.check_token_mints(wallet_keyset, tokens).await?;
+                self.redeem_tokens(wallet_keyset, tokens, &operation_id)
+                    .await?;
+                Ok(self.inspect_token(tokens))
+            })
             .await?;
+        self.auto_withdraw_after_receive(wallet_keyset).await;
+        Ok(summary)
+    }
 
-        let msgs = fee_blind
-            .iter()
-            .map(|(msg, _, _)| msg.clone())
-            .collect::<Vec<BlindedMessage>>();
-
-        let secrets = fee_blind
-            .iter()
-            .map(|(_, _, secret)| secret.clone())
-            .collect::<Vec<String>>();
-
-        let outputs = fee_blind
-            .iter()
-            .map(|(msg, blinding_factor, _)| (msg.clone(), blinding_factor.clone()))
-            .collect::<Vec<(BlindedMessage, BlindingFactor)>>();
-
-        let mut tx = self.localstore.begin_tx().await?;
-        match self
-            .melt_token(
-                &wallet_keyset.mint_url,
-                melt_quote.to_owned().quote,
-                &total_proofs,
-                msgs,
-            )
-            .await
-        {
-            Ok(response) => {
-                if !response.paid {
-                    self.localstore.add_proofs(&mut tx, &total_proofs).await?;
+    /// Like [`Wallet::receive_tokens`], but only redeems the proofs the mint reports as unspent.
+    /// Spent and pending proofs are skipped, so the rest of a partially double-spent token can
+    /// still be received. Nothing is redeemed if no proof is unspent.
+    pub async fn receive_tokens_lenient(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+    ) -> Result<ReceiveReport, MokshaWalletError> {
+        let report = self
+            .run_operation(OperationKind::Receive, |operation_id| async move {
+                self.check_token_mints(wallet_keyset, tokens).await?;
+
+                let spendable = self.check_proofs_spendable(&tokens.proofs()).await?;
+                let mut spendable = spendable.into_iter();
+                let mut report = ReceiveReport::default();
+                let live = TokenV3 {
+                    tokens: tokens
+                        .tokens
+                        .iter()
+                        .map(|token| Token {
+                            mint: token.mint.clone(),
+                            proofs: token
+                                .proofs
+                                .proofs()
+                                .into_iter()
+                                .filter(|proof| {
+                                    let live = spendable.next().unwrap_or(false);
+                                    if live {
+                                        report.redeemed_proofs += 1;
+                                        report.redeemed_amount += proof.amount;
+                                    } else {
+                                        report.skipped_proofs += 1;
+                                        report.skipped_amount += proof.amount;
+                                    }
+                                    live
+                                })
+                                .collect::<Vec<_>>()
+                                .into(),
+                        })
+                        .filter(|token| !token.proofs.is_empty())
+                        .collect(),
+                    ..tokens.clone()
+                };
+
+                if report.redeemed_proofs > 0 {
+                    self.redeem_tokens(wallet_keyset, &live, &operation_id)
+                        .await?;
                 }
-                let change_proofs = self.create_proofs_from_blinded_signatures(
-                    &wallet_keyset.keyset_id,
-                    &wallet_keyset.public_keys,
-                    response.clone().change,
-                    secrets,
-                    outputs,
-                )?;
-                self.localstore.add_proofs(&mut tx, &change_proofs).await?;
-                tx.commit().await?;
+                Ok(report)
+            })
+            .await?;
+        if report.redeemed_proofs > 0 {
+            self.auto_withdraw_after_receive(wallet_keyset).await;
+        }
+        Ok(report)
+    }
 
-                Ok((response, change_proofs.total_amount()))
+    /// Fails if a token entry is not from the mint of `wallet_keyset` or from an insecure mint.
+    /// Mint urls are compared after [`normalize_mint_url`], so a missing trailing slash doesn't
+    /// matter. Proofs of keysets the wallet doesn't know yet must be of a keyset the mint lists.
+    async fn check_token_mints(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+    ) -> Result<(), MokshaWalletError> {
+        let expected = normalize_mint_url(&wallet_keyset.mint_url)?;
+        for token in tokens.tokens.iter() {
+            if let Some(mint_url) = &token.mint {
+                self.check_mint_url(mint_url)?;
             }
-            Err(e) => {
-                self.localstore.add_proofs(&mut tx, &total_proofs).await?;
-                tx.commit().await?;
-                Err(e)
+            let actual = token
+                .mint
+                .as_ref()
+                .and_then(|mint_url| normalize_mint_url(mint_url).ok());
+            if actual.as_ref() != Some(&expected) {
+                return Err(MokshaWalletError::UnsupportedMint(
+                    token
+                        .mint
+                        .as_ref()
+                        .map_or_else(|| "<none>".to_owned(), Url::to_string),
+                ));
             }
         }
-    }
 
-    pub async fn get_melt_quote_btconchain(
-        &self,
-        mint_url: &Url,
-        address: String,
-        amount: u64,
-    ) -> Result<Vec<PostMeltQuoteBtcOnchainResponse>, MokshaWalletError> {
-        self.client
-            .post_melt_quote_onchain(mint_url, address, amount, CurrencyUnit::Sat)
-            .await
+        let mut known = self
+            .get_wallet_keysets()
+            .await?
+            .into_iter()
+            .filter(|keyset| keyset.mint_url == wallet_keyset.mint_url)
+            .map(|keyset| keyset.keyset_id.to_string())
+            .collect::<HashSet<_>>();
+        known.insert(wallet_keyset.keyset_id.to_string());
+        let unknown = tokens
+            .proofs()
+            .proofs()
+            .into_iter()
+            .map(|proof| proof.keyset_id)
+            .filter(|keyset_id| !known.contains(keyset_id))
+            // legacy keysets aren't listed by the mint, it checks them when they are swapped
+            .filter(|keyset_id| keyset_id.len() == 16)
+            .collect::<HashSet<_>>();
+        if unknown.is_empty() {
+            return Ok(());
+        }
+
+        let mint_keysets = self.client.get_keysets(&wallet_keyset.mint_url).await?;
+        match unknown
+            .into_iter()
+            .find(|keyset_id| !mint_keysets.keysets.iter().any(|k| &k.id == keyset_id))
+        {
+            Some(keyset_id) => Err(MokshaWalletError::KeysetNotFound(format!(
+                "Keyset {keyset_id} of the token is not a keyset of mint {}",
+                wallet_keyset.mint_url
+            ))),
+            None => Ok(()),
+        }
     }
 
-    pub async fn pay_onchain(
+    /// Swaps all proofs of the token for new ones and stores them
+    async fn redeem_tokens(
         &self,
         wallet_keyset: &WalletKeyset,
-        melt_quote: &PostMeltQuoteBtcOnchainResponse,
-    ) -> Result<PostMeltBtcOnchainResponse, MokshaWalletError> {
+        tokens: &TokenV3,
+        operation_id: &OperationId,
+    ) -> Result<(), MokshaWalletError> {
+        // the mint keeps the input fee of the swap
+        let input_fee = self
+            .input_fee(&wallet_keyset.mint_url, &tokens.proofs())
+            .await?;
+        let total_amount = tokens.total_amount().checked_sub(input_fee).ok_or(
+            MokshaWalletError::NotEnoughTokens {
+                needed: input_fee,
+                available: tokens.total_amount(),
+            },
+        )?;
+        let (_, redeemed_tokens) = self
+            .swap_tokens(wallet_keyset, tokens, total_amount.into())
+            .await?;
         let mut tx = self.localstore.begin_tx().await?;
-        let all_proofs = self.localstore.get_proofs(&mut tx).await?;
+        self.localstore
+            .add_proofs(&mut tx, &redeemed_tokens.proofs())
+            .await?;
+        self.localstore
+            .add_operation_records(
+                &mut tx,
+                operation_id,
+                &OperationRecord::proofs(&redeemed_tokens.proofs()),
+            )
+            .await?;
+        self.localstore
+            .add_history_entry(
+                &mut tx,
+                &HistoryEntry {
+                    memo: tokens.memo.clone(),
+                    ..self.history_entry(
+                        operation_id,
+                        HistoryKind::Receive,
+                        redeemed_tokens.total_amount(),
+                        &wallet_keyset.mint_url,
+                    )
+                },
+            )
+            .await?;
         tx.commit().await?;
+        Ok(())
+    }
 
-        let ln_amount = melt_quote.amount + melt_quote.fee;
-
-        if ln_amount > all_proofs.total_amount() {
-            return Err(MokshaWalletError::NotEnoughTokens);
+    /// Summarizes a token without redeeming it. Doesn't contact the mint, so the proofs may
+    /// already be spent.
+    pub fn inspect_token(&self, token: &TokenV3) -> TokenSummary {
+        let mut mint_urls: Vec<String> = vec![];
+        for url in token.tokens.iter().filter_map(|token| token.mint.as_ref()) {
+            if !mint_urls.contains(&url.to_string()) {
+                mint_urls.push(url.to_string());
+            }
+        }
+        let denominations = token
+            .proofs()
+            .proofs()
+            .iter()
+            .map(|proof| proof.amount)
+            .collect::<Vec<_>>();
+        TokenSummary {
+            total_amount: token.total_amount(),
+            mint_urls,
+            proof_count: denominations.len(),
+            denominations,
+            memo: token.memo.clone(),
         }
-        let selected_proofs = all_proofs.proofs_for_amount(ln_amount)?;
+    }
 
-        let mut tx = self.localstore.begin_tx().await?;
-        let total_proofs = {
-            let selected_tokens =
-                (wallet_keyset.mint_url.to_owned(), selected_proofs.clone()).into();
-            let swap_result = self
-                .swap_tokens(wallet_keyset, &selected_tokens, ln_amount.into())
-                .await?;
-            self.localstore
-                .delete_proofs(&mut tx, &selected_proofs)
-                .await?;
-            self.localstore
-                .add_proofs(&mut tx, &swap_result.0.proofs())
-                .await?;
+    /// Decodes and redeems a serialized `cashuA` or `cashuB` token, see [`Wallet::decode_token`]
+    pub async fn receive_serialized_tokens(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        serialized: &str,
+    ) -> Result<TokenSummary, MokshaWalletError> {
+        let tokens = self.decode_token(serialized)?;
+        self.receive_tokens(wallet_keyset, &tokens).await
+    }
 
-            swap_result.1.proofs()
+    /// Parses a pasted token. Fails with the part of the token that could not be decoded, or if a
+    /// token entry has no mint url.
+    pub fn decode_token(&self, serialized: &str) -> Result<TokenV3, MokshaWalletError> {
+        let serialized = serialized.trim();
+        let prefix_len = TOKEN_PREFIX_V3.len();
+        let (prefix, payload) = match serialized.get(..prefix_len) {
+            Some(prefix) if prefix == TOKEN_PREFIX_V3 || prefix == TOKEN_PREFIX_V4 => {
+                (prefix, &serialized[prefix_len..])
+            }
+            _ => {
+                let prefix = serialized.chars().take(prefix_len).collect();
+                return Err(MokshaWalletError::DecodeToken(
+                    prefix,
+                    MokshaCoreError::InvalidTokenPrefix,
+                ));
+            }
         };
 
-        let melt_response = self
-            .client
-            .post_melt_onchain(
-                &wallet_keyset.mint_url,
-                total_proofs.clone(),
-                melt_quote.quote.clone(),
-            )
-            .await?;
+        let tokens = TokenV3::deserialize(serialized)
+            .map_err(|err| MokshaWalletError::DecodeToken(payload.to_owned(), err))?;
+        if tokens.tokens.is_empty() {
+            return Err(MokshaWalletError::InvalidToken(format!(
+                "{prefix} token contains no proofs"
+            )));
+        }
+        if let Some(index) = tokens.tokens.iter().position(|token| token.mint.is_none()) {
+            return Err(MokshaWalletError::InvalidToken(format!(
+                "token entry {index} has no mint url"
+            )));
+        }
+        Ok(tokens)
+    }
 
-        if melt_response.paid {
-            self.localstore
-                .delete_proofs(&mut tx, &total_proofs)
-                .await?;
+    /// Validates the token offline and stores it in the receive queue.
+    ///
+    /// The token is redeemed by [`Wallet::process_receive_queue`], which retries it until the
+    /// mint is reachable again. Returns the id of the queue entry.
+    pub async fn enqueue_receive(&self, tokens: &TokenV3) -> Result<u64, MokshaWalletError> {
+        if tokens.proofs().is_empty() || tokens.total_amount() == 0 {
+            return Err(MokshaWalletError::InvalidToken("token is empty".to_owned()));
+        }
+        self.get_keyset_for_token(tokens).await?;
+
+        let mut tx = self.localstore.begin_tx().await?;
+        // a token that shares proofs with a queued one is a replay and can't be redeemed twice
+        for queued in self.localstore.get_queued_receives(&mut tx).await? {
+            if queued.status == QueuedReceiveStatus::Pending
+                && self.tokens_overlap(tokens, &TokenV3::deserialize(queued.token)?)
+            {
+                return Err(MokshaWalletError::InvalidToken(format!(
+                    "token overlaps with queued receive {}",
+                    queued.id.unwrap_or_default()
+                )));
+            }
         }
+        let id = self
+            .localstore
+            .add_queued_receive(&mut tx, &QueuedReceive::new(tokens.serialize()?))
+            .await?;
         tx.commit().await?;
-        Ok(melt_response)
+        Ok(id)
     }
 
-    async fn create_secrets(
-        &self,
-        keyset_id: &KeysetId,
-        amount: u32,
-    ) -> Result<Vec<(String, BlindingFactor)>, MokshaWalletError> {
-        let mut tx = self.localstore.begin_tx().await?;
-        let all_keysets = self.localstore.get_keysets(&mut tx).await?;
-        let keyset = all_keysets
-            .iter()
-            .find(|k| k.keyset_id == *keyset_id)
-            .expect("keyset not found create-secrets");
+    /// Returns true if both tokens contain a proof with the same secret
+    pub fn tokens_overlap(&self, a: &TokenV3, b: &TokenV3) -> bool {
+        let secrets = a
+            .proofs()
+            .proofs()
+            .into_iter()
+            .map(|proof| proof.secret)
+            .collect::<HashSet<_>>();
+        b.proofs()
+            .proofs()
+            .into_iter()
+            .any(|proof| secrets.contains(&proof.secret))
+    }
 
-        let start_index = (keyset.last_index + 1) as u32;
-        let secret_range = self.secret.derive_range(keyset_id, start_index, amount)?;
+    pub async fn get_receive_queue(&self) -> Result<Vec<QueuedReceive>, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let queue = self.localstore.get_queued_receives(&mut tx).await?;
+        tx.commit().await?;
+        Ok(queue)
+    }
 
+    /// Resets the attempts of a queue entry, so it is redeemed on the next run of the processor
+    pub async fn retry_queued_receive(&self, id: u64) -> Result<(), MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let queued = self
+            .localstore
+            .get_queued_receives(&mut tx)
+            .await?
+            .into_iter()
+            .find(|q| q.id == Some(id))
+            .ok_or(MokshaWalletError::QueuedReceiveNotFound(id))?;
         self.localstore
-            .update_keyset_last_index(
+            .update_queued_receive(
                 &mut tx,
-                &WalletKeyset {
-                    last_index: (start_index + amount - 1) as u64,
-                    ..keyset.clone()
+                &QueuedReceive {
+                    attempts: 0,
+                    next_attempt: 0,
+                    status: QueuedReceiveStatus::Pending,
+                    ..queued
                 },
             )
             .await?;
         tx.commit().await?;
-        Ok(secret_range)
+        Ok(())
     }
 
-    pub async fn swap_tokens(
-        &self,
-        wallet_keyset: &WalletKeyset,
-        tokens: &TokenV3,
-        splt_amount: Amount,
-    ) -> Result<(TokenV3, TokenV3), MokshaWalletError> {
-        let total_token_amount = tokens.total_amount();
-        let first_amount: Amount = (total_token_amount - splt_amount.0).into();
-        let first_secrets = self
-            .create_secrets(&wallet_keyset.keyset_id, first_amount.split().len() as u32)
-            .await?;
-        let first_outputs = self.create_blinded_messages(
-            &wallet_keyset.keyset_id,
-            first_amount,
-            first_secrets.clone(),
-        )?;
+    pub async fn remove_queued_receive(&self, id: u64) -> Result<(), MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore.delete_queued_receive(&mut tx, id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
-        // ############################################################################
+    /// Tries to redeem all pending queue entries that are due.
+    ///
+    /// Failed attempts are retried with exponential backoff. An entry is marked as failed if the
+    /// mint reports the token as spent or after [`RECEIVE_QUEUE_MAX_ATTEMPTS`] attempts.
+    /// Meant to be called periodically by the service that owns the wallet.
+    pub async fn process_receive_queue(&self) -> Result<Vec<ReceiveQueueEvent>, MokshaWalletError> {
+        let now = self.clock.now();
+        let due = self
+            .get_receive_queue()
+            .await?
+            .into_iter()
+            .filter(|q| q.status == QueuedReceiveStatus::Pending && q.next_attempt <= now);
 
-        let second_amount = splt_amount.clone();
-        let second_secrets = self
-            .create_secrets(&wallet_keyset.keyset_id, second_amount.split().len() as u32)
-            .await?;
-        let second_outputs = self.create_blinded_messages(
-            &wallet_keyset.keyset_id,
-            second_amount,
-            second_secrets.clone(),
-        )?;
+        let mut events = vec![];
+        for mut queued in due {
+            let id = queued.id.ok_or(MokshaWalletError::IdNotSet)?;
+            let tokens = TokenV3::deserialize(queued.token.clone())?;
 
-        let mut total_outputs = vec![];
-        total_outputs.extend(get_blinded_msg(first_outputs.clone()));
-        total_outputs.extend(get_blinded_msg(second_outputs.clone()));
+            let result = match self.get_keyset_for_token(&tokens).await {
+                Ok(wallet_keyset) => self.receive_tokens(&wallet_keyset, &tokens).await,
+                Err(e) => Err(e),
+            };
 
-        if tokens.total_amount() != total_outputs.total_amount() {
-            return Err(MokshaWalletError::InvalidProofs);
+            let event = match result {
+                Ok(_) => {
+                    let mut tx = self.localstore.begin_tx().await?;
+                    self.localstore.delete_queued_receive(&mut tx, id).await?;
+                    tx.commit().await?;
+                    ReceiveQueueEvent::Received {
+                        id,
+                        amount: tokens.total_amount(),
+                    }
+                }
+                Err(e) => {
+                    queued.attempts += 1;
+                    queued.last_error = Some(e.to_string());
+
+                    // the mint might be unreachable, so a failed check counts as not spent
+                    let is_spent = self.is_token_spent(&tokens).await.unwrap_or(false);
+                    let event = if is_spent || queued.attempts >= RECEIVE_QUEUE_MAX_ATTEMPTS {
+                        queued.status = QueuedReceiveStatus::Failed;
+                        ReceiveQueueEvent::Failed {
+                            id,
+                            reason: if is_spent {
+                                "token is already spent".to_owned()
+                            } else {
+                                e.to_string()
+                            },
+                        }
+                    } else {
+                        queued.next_attempt = now + receive_queue_backoff(queued.attempts);
+                        ReceiveQueueEvent::Retrying {
+                            id,
+                            attempts: queued.attempts,
+                            next_attempt: queued.next_attempt,
+                            error: e.to_string(),
+                        }
+                    };
+
+                    let mut tx = self.localstore.begin_tx().await?;
+                    self.localstore
+                        .update_queued_receive(&mut tx, &queued)
+                        .await?;
+                    tx.commit().await?;
+                    event
+                }
+            };
+            events.push(event);
         }
+        Ok(events)
+    }
 
-        let split_result = self
-            .client
-            .post_swap(&wallet_keyset.mint_url, tokens.proofs(), total_outputs)
-            .await?;
+    async fn get_keyset_for_token(
+        &self,
+        tokens: &TokenV3,
+    ) -> Result<WalletKeyset, MokshaWalletError> {
+        let mint_url = tokens
+            .mint()
+            .ok_or_else(|| MokshaWalletError::InvalidToken("missing mint url".to_owned()))?;
+        let currency_unit = tokens.currency_unit.clone().unwrap_or(CurrencyUnit::Sat);
 
-        if split_result.signatures.is_empty() {
-            return Ok((TokenV3::empty(), TokenV3::empty()));
-        }
+        self.get_wallet_keysets()
+            .await?
+            .get_active(&mint_url, &currency_unit)
+            .cloned()
+            .ok_or_else(|| MokshaWalletError::UnknownMint(mint_url.to_string()))
+    }
 
-        let len_first = first_secrets.len();
-        let secrets = [first_secrets, second_secrets].concat();
-        let outputs = [first_outputs, second_outputs].concat();
+    async fn is_token_spent(&self, tokens: &TokenV3) -> Result<bool, MokshaWalletError> {
+        let mint_url = tokens
+            .mint()
+            .ok_or_else(|| MokshaWalletError::InvalidToken("missing mint url".to_owned()))?;
+        let ys = tokens
+            .proofs()
+            .proofs()
+            .iter()
+            .map(|p| p.y())
+            .collect::<Result<Vec<PublicKey>, _>>()?;
+        Ok(self
+            .client
+            .post_checkstate(&mint_url, ys)
+            .await?
+            .states
+            .iter()
+            .any(|s| s.state == State::Spent))
+    }
 
-        let secrets = secrets.into_iter().map(|(s, _)| s).collect::<Vec<String>>();
+    pub async fn get_mint_quote(
+        &self,
+        mint_url: &Url,
+        amount: Amount,
+        currency: CurrencyUnit,
+    ) -> Result<PostMintQuoteBolt11Response, MokshaWalletError> {
+        self.client
+            .post_mint_quote_bolt11(mint_url, amount.0, currency)
+            .await
+    }
 
-        let proofs = self
-            .create_proofs_from_blinded_signatures(
-                &wallet_keyset.keyset_id,
-                &wallet_keyset.public_keys,
-                split_result.signatures,
-                secrets,
-                outputs,
-            )?
-            .proofs();
+    pub async fn get_melt_quote_bolt11(
+        &self,
+        mint_url: &Url,
+        invoice: String,
+        currency: CurrencyUnit,
+    ) -> Result<PostMeltQuoteBolt11Response, MokshaWalletError> {
+        if let Some(amount_msat) = Self::decode_invoice(&invoice)
+            .ok()
+            .and_then(|invoice| invoice.amount_milli_satoshis())
+        {
+            self.check_melt_limits(mint_url, amount_msat / 1_000)?;
+        }
+        self.client
+            .post_melt_quote_bolt11(mint_url, invoice.clone(), currency)
+            .await
+    }
 
-        let first_tokens: TokenV3 = (
-            wallet_keyset.mint_url.to_owned(),
-            wallet_keyset.currency_unit.clone(),
-            proofs[0..len_first].to_vec().into(),
-        )
-            .into();
-        let second_tokens: TokenV3 = (
-            wallet_keyset.mint_url.to_owned(),
-            wallet_keyset.currency_unit.clone(),
-            proofs[len_first..proofs.len()].to_vec().into(),
-        )
-            .into();
+    /// Checks the amount of a lightning payment against the limits (NUT-05) of the mint info
+    /// that was fetched last. Passes if the info of the mint hasn't been fetched yet.
+    fn check_melt_limits(&self, mint_url: &Url, amount: u64) -> Result<(), MokshaWalletError> {
+        let mint_infos = self.mint_infos.read().expect("mint infos lock is poisoned");
+        let Some(limits) = mint_infos
+            .get(mint_url)
+            .and_then(Option::as_ref)
+            .and_then(|info| {
+                info.nuts.nut5.payment_methods.iter().find(|method| {
+                    method.payment_method == PaymentMethod::Bolt11
+                        && method.unit == CurrencyUnit::Sat
+                })
+            })
+        else {
+            return Ok(());
+        };
 
-        if tokens.total_amount() != first_tokens.total_amount() + second_tokens.total_amount() {
-            println!(
-                "Error in swap: input {:?} != output {:?} + {:?}",
-                tokens.total_amount(),
-                first_tokens.total_amount(),
-                second_tokens.total_amount()
-            );
+        match (limits.min_amount, limits.max_amount) {
+            (Some(min), _) if amount < min => {
+                Err(MokshaWalletError::MeltAmountTooLow { amount, min })
+            }
+            (_, Some(max)) if amount > max => {
+                Err(MokshaWalletError::MeltAmountTooHigh { amount, max })
+            }
+            _ => Ok(()),
         }
-
-        Ok((first_tokens, second_tokens))
     }
 
-    pub async fn get_mint_info(
+    /// Returns the amount of sats that paying the invoice at the mint takes from the balance: the
+    /// invoice amount plus the fee reserve of a melt quote. Unused fees are returned as change.
+    pub async fn estimate_total_pay_amount(
         &self,
         mint_url: &Url,
-    ) -> Result<MintInfoResponse, MokshaWalletError> {
-        self.client.get_info(mint_url).await
+        invoice: &str,
+    ) -> Result<u64, MokshaWalletError> {
+        let melt_quote = self
+            .get_melt_quote_bolt11(mint_url, invoice.to_owned(), CurrencyUnit::Sat)
+            .await?;
+        self.get_total_pay_amount(invoice, &melt_quote)
     }
 
-    async fn melt_token(
+    fn get_total_pay_amount(
+        &self,
+        invoice: &str,
+        melt_quote: &PostMeltQuoteBolt11Response,
+    ) -> Result<u64, MokshaWalletError> {
+        Ok(self.get_invoice_amount(invoice)? + melt_quote.fee_reserve)
+    }
+
+    pub async fn pay_invoice(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        melt_quote: &PostMeltQuoteBolt11Response,
+        invoice: String,
+    ) -> Result<(PostMeltBolt11Response, u64), MokshaWalletError> {
+        self.run_operation(OperationKind::PayInvoice, |operation_id| async move {
+            let amount = self.get_invoice_amount(&invoice)?;
+            self.check_melt_limits(&wallet_keyset.mint_url, amount)?;
+
+            // proofs of all keysets of the mint can be combined, they are swapped into the active
+            // keyset before the melt
+            let all_proofs = self.get_proofs_for_mint(&wallet_keyset.mint_url).await?;
+
+            // pruning spent proofs only lowers the balance, so an underfunded payment fails
+            // without contacting the mint
+            let needed = self.get_total_pay_amount(&invoice, melt_quote)?;
+            if needed > all_proofs.total_amount() {
+                return Err(self
+                    .not_enough_tokens(&wallet_keyset.mint_url, all_proofs.total_amount(), needed)
+                    .await?);
+            }
+
+            // proofs that were spent without the localstore noticing (e.g. a crash during a melt)
+            // would make the mint reject the whole request. Pending proofs may still come back,
+            // so they are only left out of this payment.
+            let states = self.get_proof_states(&all_proofs).await?;
+            let (unspent, spent): (Vec<_>, Vec<_>) = all_proofs
+                .proofs()
+                .into_iter()
+                .zip(states)
+                .filter(|(_, state)| *state != State::Pending)
+                .partition(|(_, state)| *state == State::Unspent);
+            if !spent.is_empty() {
+                let spent: Proofs = spent.into_iter().map(|(p, _)| p).collect::<Vec<_>>().into();
+                let mut tx = self.localstore.begin_tx().await?;
+                self.localstore
+                    .transition_proofs(
+                        &mut tx,
+                        &spent.secrets(),
+                        ProofStatus::Spendable,
+                        ProofStatus::Spent,
+                        self.clock.now(),
+                    )
+                    .await?;
+                self.localstore
+                    .add_operation_records(&mut tx, &operation_id, &OperationRecord::proofs(&spent))
+                    .await?;
+                tx.commit().await?;
+            }
+            let all_proofs: Proofs = unspent
+                .into_iter()
+                .map(|(p, _)| p)
+                .collect::<Vec<_>>()
+                .into();
+
+            if needed > all_proofs.total_amount() {
+                return Err(self
+                    .not_enough_tokens(&wallet_keyset.mint_url, all_proofs.total_amount(), needed)
+                    .await?);
+            }
+
+            self.pay_invoice_with_proofs(
+                wallet_keyset,
+                melt_quote,
+                invoice,
+                all_proofs,
+                &operation_id,
+            )
+            .await
+        })
+        .await
+    }
+
+    /// The error for a payment of `needed` sats with a `balance` at the mint that is too low. A
+    /// mint can only spend its own proofs, so the balance at other mints doesn't help.
+    async fn not_enough_tokens(
         &self,
         mint_url: &Url,
-        quote_id: String,
+        balance: u64,
+        needed: u64,
+    ) -> Result<MokshaWalletError, MokshaWalletError> {
+        Ok(if self.get_balance().await? >= needed {
+            MokshaWalletError::BalanceAtOtherMints {
+                mint_url: mint_url.to_string(),
+                balance,
+                needed,
+            }
+        } else {
+            MokshaWalletError::NotEnoughTokens {
+                needed,
+                available: balance,
+            }
+        })
+    }
+
+    /// Asks the mints of the proofs (NUT-07) whether the proofs can still be spent.
+    ///
+    /// Returns a flag for every proof in the same order. Pending proofs are not spendable.
+    pub async fn check_proofs_spendable(
+        &self,
         proofs: &Proofs,
-        fee_blinded_messages: Vec<BlindedMessage>,
-    ) -> Result<PostMeltBolt11Response, MokshaWalletError> {
-        let melt_response = self
-            .client
-            .post_melt_bolt11(mint_url, proofs.clone(), quote_id, fee_blinded_messages)
-            .await?;
+    ) -> Result<Vec<bool>, MokshaWalletError> {
+        Ok(self
+            .get_proof_states(proofs)
+            .await?
+            .into_iter()
+            .map(|state| state == State::Unspent)
+            .collect())
+    }
 
-        if melt_response.paid {
+    /// Returns the proofs in the localstore that were already spent, e.g. by another copy of the
+    /// wallet database. If `delete` is set, they are removed from the localstore in a single batch
+    /// instead of being kept as spent.
+    pub async fn find_spent_proofs(&self, delete: bool) -> Result<Proofs, MokshaWalletError> {
+        let proofs = self.get_proofs().await?;
+        let states = self.get_proof_states(&proofs).await?;
+        let spent: Proofs = proofs
+            .proofs()
+            .into_iter()
+            .zip(states)
+            .filter_map(|(proof, state)| (state == State::Spent).then_some(proof))
+            .collect::<Vec<_>>()
+            .into();
+
+        if delete && !spent.is_empty() {
+            self.take_snapshot("delete spent proofs").await?;
             let mut tx = self.localstore.begin_tx().await?;
-            self.localstore.delete_proofs(&mut tx, proofs).await?;
+            self.localstore
+                .delete_proofs_by_secrets(&mut tx, &spent.secrets())
+                .await?;
             tx.commit().await?;
         }
-        Ok(melt_response)
+        Ok(spent)
     }
 
-    fn decode_invoice(payment_request: &str) -> Result<LNInvoice, MokshaWalletError> {
-        LNInvoice::from_str(payment_request)
-            .map_err(|err| MokshaWalletError::DecodeInvoice(payment_request.to_owned(), err))
-    }
+    /// Returns the state of every proof in the same order, grouped into one request per mint
+    async fn get_proof_states(&self, proofs: &Proofs) -> Result<Vec<State>, MokshaWalletError> {
+        let keysets = self.get_wallet_keysets().await?;
 
-    fn get_invoice_amount(payment_request: &str) -> Result<u64, MokshaWalletError> {
-        let invoice = Self::decode_invoice(payment_request)?;
-        Ok(invoice
-            .amount_milli_satoshis()
-            .ok_or_else(|| MokshaWalletError::InvalidInvoice(payment_request.to_owned()))?
-            / 1000)
+        let mut ys_by_mint: HashMap<Url, Vec<PublicKey>> = HashMap::new();
+        let mut ys = Vec::with_capacity(proofs.len());
+        for proof in proofs.proofs() {
+            let keyset = keysets
+                .iter()
+                .find(|k| k.keyset_id.to_string() == proof.keyset_id)
+                .ok_or(MokshaWalletError::InvalidProofs)?;
+            let y = proof.y()?;
+            ys_by_mint
+                .entry(keyset.mint_url.clone())
+                .or_default()
+                .push(y);
+            ys.push((y, proof.secret));
+        }
+
+        let mut states = HashMap::new();
+        for (mint_url, mint_ys) in ys_by_mint {
+            states.extend(
+                self.client
+                    .post_checkstate(&mint_url, mint_ys)
+                    .await?
+                    .states
+                    .into_iter()
+                    .map(|s| (s.y, s.state)),
+            );
+        }
+
+        ys.into_iter()
+            .map(|(y, secret)| {
+                states
+                    .get(&y)
+                    .cloned()
+                    .ok_or(MokshaWalletError::MissingProofState(secret))
+            })
+            .collect()
     }
 
-    pub async fn mint_tokens(
+    async fn pay_invoice_with_proofs(
         &self,
         wallet_keyset: &WalletKeyset,
-        payment_method: &PaymentMethod,
-        amount: Amount,
-        quote_id: String,
-    ) -> Result<TokenV3, MokshaWalletError> {
-        let split_amount = amount.split();
+        melt_quote: &PostMeltQuoteBolt11Response,
+        invoice: String,
+        all_proofs: Proofs,
+        operation_id: &OperationId,
+    ) -> Result<(PostMeltBolt11Response, u64), MokshaWalletError> {
+        let invoice_amount = self.get_invoice_amount(&invoice)?;
+        let ln_amount = self.get_total_pay_amount(&invoice, melt_quote)?;
+        self.emit(WalletEvent::FeeEstimated {
+            amount: invoice_amount,
+            fee_reserve: melt_quote.fee_reserve,
+        });
 
-        let secret_range = self
-            .create_secrets(&wallet_keyset.keyset_id, split_amount.len() as u32)
+        // the mint charges the input fee of the melt inputs on top of the amount and fee reserve
+        let melt_amount = Self::melt_amount_with_fee(wallet_keyset, ln_amount);
+        if melt_amount > all_proofs.total_amount() {
+            return Err(MokshaWalletError::NotEnoughTokens {
+                needed: melt_amount,
+                available: all_proofs.total_amount(),
+            });
+        }
+        let selected_proofs = self
+            .proofs_for_amount_with_fee(&wallet_keyset.mint_url, &all_proofs, melt_amount)
             .await?;
+        self.emit(WalletEvent::ProofsSelected {
+            amount: selected_proofs.total_amount(),
+            count: selected_proofs.len(),
+        });
 
-        let blinded_messages = split_amount
-            .into_iter()
-            .zip(secret_range)
-            .map(|(amount, (secret, blinding_factor))| {
-                let b_ = self.dhke.step1_alice(&secret, &blinding_factor)?;
-                Ok((
-                    BlindedMessage {
-                        amount,
-                        b_,
-                        id: wallet_keyset.keyset_id.to_string(), // FIXME use keyset_id
-                    },
-                    blinding_factor,
-                    secret,
-                ))
-            })
-            .collect::<Result<Vec<(_, _, _)>, MokshaWalletError>>()?;
-
-        let signatures = match payment_method {
-            PaymentMethod::Bolt11 => {
-                let post_mint_resp = self
-                    .client
-                    .post_mint_bolt11(
-                        &wallet_keyset.mint_url,
-                        quote_id,
-                        blinded_messages
-                            .clone()
-                            .into_iter()
-                            .map(|(msg, _, _)| msg)
-                            .collect::<Vec<BlindedMessage>>(),
-                    )
-                    .await?;
-                post_mint_resp.signatures
-            }
-            PaymentMethod::BtcOnchain => {
-                let post_mint_resp = self
-                    .client
-                    .post_mint_onchain(
-                        &wallet_keyset.mint_url,
-                        quote_id,
-                        blinded_messages
-                            .clone()
-                            .into_iter()
-                            .map(|(msg, _, _)| msg)
-                            .collect::<Vec<BlindedMessage>>(),
-                    )
-                    .await?;
-                post_mint_resp.signatures
-            }
-        };
+        let (total_proofs, pending_melt) = {
+            let selected_tokens =
+                TokenV3::from_proofs(&wallet_keyset.mint_url, selected_proofs.clone())?;
+            let swap_result = self
+                .swap_tokens(wallet_keyset, &selected_tokens, melt_amount.into())
+                .await?;
+            let total_proofs = swap_result.1.proofs();
 
-        // step 3: unblind signatures
-        let current_keyset_id = wallet_keyset.keyset_id.to_string(); // FIXME
+            // the proofs for the melt stay reserved until the outcome of the melt is known
+            let now = self.clock.now();
+            let mut tx = self.localstore.begin_tx().await?;
+            self.localstore
+                .transition_proofs(
+                    &mut tx,
+                    &selected_proofs.secrets(),
+                    ProofStatus::Spendable,
+                    ProofStatus::Spent,
+                    now,
+                )
+                .await?;
+            self.localstore
+                .add_proofs(&mut tx, &swap_result.0.proofs())
+                .await?;
+            self.localstore.add_proofs(&mut tx, &total_proofs).await?;
+            self.localstore
+                .transition_proofs(
+                    &mut tx,
+                    &total_proofs.secrets(),
+                    ProofStatus::Spendable,
+                    ProofStatus::PendingMelt,
+                    now,
+                )
+                .await?;
+            let mut pending_melt = PendingMelt {
+                id: None,
+                mint_url: wallet_keyset.mint_url.clone(),
+                quote_id: melt_quote.quote.clone(),
+                invoice,
+                proofs: total_proofs.clone(),
+                created_at: now,
+                dispute: None,
+            };
+            let id = self
+                .localstore
+                .add_pending_melt(&mut tx, &pending_melt)
+                .await?;
+            pending_melt.id = Some(id);
+            self.localstore
+                .add_operation_records(
+                    &mut tx,
+                    operation_id,
+                    &[
+                        OperationRecord::proofs(&selected_proofs),
+                        OperationRecord::proofs(&swap_result.0.proofs()),
+                        OperationRecord::proofs(&total_proofs),
+                        vec![OperationRecord::PendingMelt(id)],
+                    ]
+                    .concat(),
+                )
+                .await?;
+            tx.commit().await?;
 
-        let proofs = signatures
-            .iter()
-            .zip(blinded_messages)
-            .map(|(p, (_, priv_key, secret))| {
-                let key = wallet_keyset
-                    .public_keys
-                    .get(&p.amount)
-                    .expect("msg amount not found in mint keys");
-                let pub_alice = self.dhke.step3_alice(p.c_, priv_key, *key).unwrap();
-                Proof::new(p.amount, secret, pub_alice, current_keyset_id.clone())
-            })
-            .collect::<Vec<Proof>>()
-            .into();
+            (total_proofs, pending_melt)
+        };
+        let pending_melt_id = pending_melt.id.ok_or(MokshaWalletError::IdNotSet)?;
 
-        let tokens: TokenV3 = (wallet_keyset.mint_url.to_owned(), proofs).into();
-        let mut tx = self.localstore.begin_tx().await?;
-        self.localstore
-            .add_proofs(&mut tx, &tokens.proofs())
+        // the mint returns everything that exceeds the invoice amount, the input fee and the actual
+        // fees as change
+        let melt_input_fee = self
+            .input_fee(&wallet_keyset.mint_url, &total_proofs)
+            .await?;
+        let overpaid = match self.supports_melt_change(&wallet_keyset.mint_url) {
+            true => total_proofs
+                .total_amount()
+                .saturating_sub(invoice_amount + melt_input_fee),
+            false => 0,
+        };
+        let (blank_range, fee_blind) = self
+            .reserve_blank(overpaid.into(), &wallet_keyset.keyset_id)
             .await?;
-        tx.commit().await?;
 
-        Ok(tokens)
-    }
+        let msgs = fee_blind
+            .iter()
+            .map(|(msg, _, _)| msg.clone())
+            .collect::<Vec<BlindedMessage>>();
 
-    pub async fn create_blank(
-        &self,
-        fee_reserve: Amount,
-        keyset_id: &KeysetId,
-    ) -> Result<Vec<(BlindedMessage, BlindingFactor, String)>, MokshaWalletError> {
-        if fee_reserve.0 == 0 {
-            return Ok(vec![]);
-        }
+        let secrets = fee_blind
+            .iter()
+            .map(|(_, _, secret)| secret.clone())
+            .collect::<Vec<String>>();
 
-        let fee_reserve_float = fee_reserve.0 as f64;
-        let count = (fee_reserve_float.log2().ceil() as u64).max(1);
+        let outputs = fee_blind
+            .iter()
+            .map(|(msg, blinding_factor, _)| (msg.clone(), blinding_factor.clone()))
+            .collect::<Vec<(BlindedMessage, BlindingFactor)>>();
 
-        let secret_range = self.create_secrets(keyset_id, count as u32).await?;
-        let blinded_messages = secret_range
-            .into_iter()
-            .map(|(secret, blinding_factor)| {
-                let b_ = self.dhke.step1_alice(secret.clone(), &blinding_factor)?;
-                Ok((
-                    BlindedMessage {
-                        amount: 1,
-                        b_,
-                        id: keyset_id.to_string(),
-                    },
-                    blinding_factor,
-                    secret,
-                ))
+        self.emit(WalletEvent::MeltSubmitted {
+            quote_id: melt_quote.quote.clone(),
+        });
+        let melt_result = self
+            .melt_token(
+                &wallet_keyset.mint_url,
+                melt_quote.to_owned().quote,
+                &total_proofs,
+                ln_amount,
+                msgs,
+            )
+            .await;
+        let keysets = match &melt_result {
+            Ok(response) => {
+                self.keysets_of_signatures(&wallet_keyset.mint_url, &response.change)
+                    .await
+            }
+            Err(_) => Ok(vec![]),
+        };
+        let mut tx = self.localstore.begin_tx().await?;
+        match melt_result {
+            Ok(response) => {
+                // the inputs are only finalized with valid change, otherwise the melt stays
+                // pending with a dispute
+                let keysets = keysets.map_err(|e| MokshaWalletError::InvalidChange(e.to_string()));
+                let change_proofs = match keysets.and_then(|keysets| {
+                    self.verify_melt_change(
+                        &keysets,
+                        response.clone().change,
+                        secrets,
+                        outputs,
+                        overpaid,
+                        melt_quote.fee_reserve,
+                    )
+                }) {
+                    Ok(change_proofs) => change_proofs,
+                    Err(e) => {
+                        self.localstore
+                            .update_pending_melt(
+                                &mut tx,
+                                &PendingMelt {
+                                    dispute: Some(e.to_string()),
+                                    ..pending_melt
+                                },
+                            )
+                            .await?;
+                        tx.commit().await?;
+                        return Err(e);
+                    }
+                };
+
+                self.localstore
+                    .transition_proofs(
+                        &mut tx,
+                        &total_proofs.secrets(),
+                        ProofStatus::PendingMelt,
+                        match response.paid {
+                            true => ProofStatus::Spent,
+                            false => ProofStatus::Spendable,
+                        },
+                        self.clock.now(),
+                    )
+                    .await?;
+                self.localstore
+                    .remove_pending_melt(&mut tx, pending_melt_id)
+                    .await?;
+                self.localstore.add_proofs(&mut tx, &change_proofs).await?;
+                self.localstore
+                    .add_operation_records(
+                        &mut tx,
+                        operation_id,
+                        &OperationRecord::proofs(&change_proofs),
+                    )
+                    .await?;
+                if response.paid {
+                    self.localstore
+                        .add_history_entry(
+                            &mut tx,
+                            &HistoryEntry {
+                                invoice: Some(pending_melt.invoice.clone()),
+                                ..self.history_entry(
+                                    operation_id,
+                                    HistoryKind::Melt,
+                                    total_proofs.total_amount() - change_proofs.total_amount(),
+                                    &wallet_keyset.mint_url,
+                                )
+                            },
+                        )
+                        .await?;
+                }
+                tx.commit().await?;
+
+                Ok((response, change_proofs.total_amount()))
+            }
+            Err(e) => {
+                // otherwise the mint may have spent the proofs, so the melt stays pending until
+                // [`Wallet::recover_pending`] checks them with the mint
+                if e.is_not_sent() || e.is_rejected() {
+                    self.localstore
+                        .transition_proofs(
+                            &mut tx,
+                            &total_proofs.secrets(),
+                            ProofStatus::PendingMelt,
+                            ProofStatus::Spendable,
+                            self.clock.now(),
+                        )
+                        .await?;
+                    self.localstore
+                        .remove_pending_melt(&mut tx, pending_melt_id)
+                        .await?;
+                    tx.commit().await?;
+                }
+                // the change outputs never reached the mint, so a retry can use their counters
+                if e.is_not_sent() {
+                    self.release_secrets(&wallet_keyset.keyset_id, blank_range)
+                        .await?;
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Pays an invoice that none of the mints can pay on its own.
+    ///
+    /// The missing amount is first moved from one mint to the other by paying a mint quote of the
+    /// paying mint, then the invoice is paid. The paying mint is chosen based on the fee estimates
+    /// of both mints. Every stage is journaled, so an interrupted payment can be finished with
+    /// [`Wallet::resume_multi_payment`].
+    pub async fn pay_invoice_multi(
+        &self,
+        invoice: String,
+    ) -> Result<MultiPayment, MokshaWalletError> {
+        self.run_operation(OperationKind::PayInvoiceMulti, |operation_id| async move {
+            let invoice_amount = self.get_invoice_amount(&invoice)?;
+
+            let mut balances = vec![];
+            for mint_url in self.get_mint_urls().await? {
+                let balance = self.get_proofs_for_mint(&mint_url).await?.total_amount();
+                let fee_reserve = self
+                    .get_melt_quote_bolt11(&mint_url, invoice.clone(), CurrencyUnit::Sat)
+                    .await?
+                    .fee_reserve;
+                balances.push((mint_url, balance, fee_reserve));
+            }
+
+            // (estimated fees, transfer amount, source, target)
+            let mut best: Option<(u64, u64, Url, Url)> = None;
+            for (target, target_balance, target_fee_reserve) in &balances {
+                let needed = invoice_amount + target_fee_reserve;
+                let shortfall = needed.saturating_sub(*target_balance);
+                let candidate = if shortfall == 0 {
+                    Some((*target_fee_reserve, 0, target.clone(), target.clone()))
+                } else {
+                    // the fee reserve for the invoice serves as estimate for the transfer fees
+                    balances
+                        .iter()
+                        .filter(|(source, balance, fee_reserve)| {
+                            source != target && *balance >= shortfall + fee_reserve
+                        })
+                        .map(|(source, _, fee_reserve)| {
+                            (
+                                target_fee_reserve + fee_reserve,
+                                shortfall,
+                                source.clone(),
+                                target.clone(),
+                            )
+                        })
+                        .min_by_key(|(fees, transfer_amount, _, _)| (*fees, *transfer_amount))
+                };
+
+                if let Some(candidate) = candidate {
+                    if best
+                        .as_ref()
+                        .map_or(true, |best| (candidate.0, candidate.1) < (best.0, best.1))
+                    {
+                        best = Some(candidate);
+                    }
+                }
+            }
+            let (_, transfer_amount, source_mint_url, target_mint_url) =
+                best.ok_or_else(|| MokshaWalletError::NotEnoughTokens {
+                    needed: invoice_amount,
+                    available: balances.iter().map(|(_, balance, _)| balance).sum(),
+                })?;
+
+            let mut payment = MultiPayment {
+                id: None,
+                invoice,
+                source_mint_url,
+                target_mint_url,
+                transfer_amount,
+                transfer_quote: None,
+                transfer_invoice: None,
+                transfer_fees: 0,
+                payment_fees: 0,
+                stage: if transfer_amount == 0 {
+                    MultiPaymentStage::TransferMinted
+                } else {
+                    MultiPaymentStage::Created
+                },
+            };
+            let mut tx = self.localstore.begin_tx().await?;
+            let id = self.localstore.add_multi_payment(&mut tx, &payment).await?;
+            payment.id = Some(id);
+            self.localstore
+                .add_operation_records(&mut tx, &operation_id, &[OperationRecord::MultiPayment(id)])
+                .await?;
+            tx.commit().await?;
+
+            self.continue_multi_payment(payment, &operation_id).await
+        })
+        .await
+    }
+
+    /// Continues a multi payment from the last journaled stage
+    pub async fn resume_multi_payment(&self, id: u64) -> Result<MultiPayment, MokshaWalletError> {
+        self.run_operation(OperationKind::PayInvoiceMulti, |operation_id| async move {
+            let payment = self
+                .get_multi_payments()
+                .await?
+                .into_iter()
+                .find(|p| p.id == Some(id))
+                .ok_or(MokshaWalletError::MultiPaymentNotFound(id))?;
+            let mut tx = self.localstore.begin_tx().await?;
+            self.localstore
+                .add_operation_records(&mut tx, &operation_id, &[OperationRecord::MultiPayment(id)])
+                .await?;
+            tx.commit().await?;
+            self.continue_multi_payment(payment, &operation_id).await
+        })
+        .await
+    }
+
+    pub async fn get_multi_payments(&self) -> Result<Vec<MultiPayment>, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let payments = self.localstore.get_multi_payments(&mut tx).await?;
+        tx.commit().await?;
+        Ok(payments)
+    }
+
+    async fn continue_multi_payment(
+        &self,
+        mut payment: MultiPayment,
+        operation_id: &OperationId,
+    ) -> Result<MultiPayment, MokshaWalletError> {
+        loop {
+            match payment.stage {
+                MultiPaymentStage::Created => {
+                    let quote = self
+                        .create_quote_bolt11(&payment.target_mint_url, payment.transfer_amount)
+                        .await?;
+                    payment.transfer_quote = Some(quote.quote);
+                    payment.transfer_invoice = Some(quote.payment_request);
+                    payment.stage = MultiPaymentStage::TransferQuoted;
+                }
+                MultiPaymentStage::TransferQuoted => {
+                    let transfer_quote = payment.transfer_quote.clone().ok_or_else(|| {
+                        MokshaWalletError::PaymentFailed("transfer quote is missing".to_owned())
+                    })?;
+                    let transfer_invoice = payment.transfer_invoice.clone().ok_or_else(|| {
+                        MokshaWalletError::PaymentFailed("transfer invoice is missing".to_owned())
+                    })?;
+
+                    // the transfer might have been paid before the journal was updated
+                    let already_paid = self
+                        .is_quote_paid(
+                            &payment.target_mint_url,
+                            &PaymentMethod::Bolt11,
+                            transfer_quote,
+                        )
+                        .await?;
+                    if !already_paid {
+                        payment.transfer_fees = self
+                            .pay_invoice_from_mint(
+                                &payment.source_mint_url,
+                                transfer_invoice,
+                                operation_id,
+                            )
+                            .await?;
+                    }
+                    payment.stage = MultiPaymentStage::TransferPaid;
+                }
+                MultiPaymentStage::TransferPaid => {
+                    let transfer_quote = payment.transfer_quote.clone().ok_or_else(|| {
+                        MokshaWalletError::PaymentFailed("transfer quote is missing".to_owned())
+                    })?;
+                    let wallet_keyset = self.get_active_keyset(&payment.target_mint_url).await?;
+                    self.mint_tokens_in_operation(
+                        &wallet_keyset,
+                        &PaymentMethod::Bolt11,
+                        payment.transfer_amount.into(),
+                        transfer_quote,
+                        operation_id,
+                    )
+                    .await?;
+                    payment.stage = MultiPaymentStage::TransferMinted;
+                }
+                MultiPaymentStage::TransferMinted => {
+                    payment.payment_fees = self
+                        .pay_invoice_from_mint(
+                            &payment.target_mint_url,
+                            payment.invoice.clone(),
+                            operation_id,
+                        )
+                        .await?;
+                    payment.stage = MultiPaymentStage::Paid;
+                }
+                MultiPaymentStage::Paid => return Ok(payment),
+            }
+
+            let mut tx = self.localstore.begin_tx().await?;
+            self.localstore
+                .update_multi_payment(&mut tx, &payment)
+                .await?;
+            tx.commit().await?;
+        }
+    }
+
+    /// Pays the invoice with the proofs of the given mint and returns the fees that were paid
+    async fn pay_invoice_from_mint(
+        &self,
+        mint_url: &Url,
+        invoice: String,
+        operation_id: &OperationId,
+    ) -> Result<u64, MokshaWalletError> {
+        let wallet_keyset = self.get_active_keyset(mint_url).await?;
+        let melt_quote = self
+            .get_melt_quote_bolt11(mint_url, invoice.clone(), CurrencyUnit::Sat)
+            .await?;
+        let proofs = self.get_proofs_for_mint(mint_url).await?;
+
+        let (response, change) = self
+            .pay_invoice_with_proofs(
+                &wallet_keyset,
+                &melt_quote,
+                invoice.clone(),
+                proofs,
+                operation_id,
+            )
+            .await?;
+        if !response.paid {
+            return Err(MokshaWalletError::PaymentFailed(invoice));
+        }
+        Ok(melt_quote.fee_reserve.saturating_sub(change))
+    }
+
+    /// Returns the active sat keyset of a mint that was added with [`Wallet::add_mint_keysets`]
+    pub async fn get_active_keyset(
+        &self,
+        mint_url: &Url,
+    ) -> Result<WalletKeyset, MokshaWalletError> {
+        let mint_url = &normalize_mint_url(mint_url)?;
+        self.get_wallet_keysets()
+            .await?
+            .get_active(mint_url, &CurrencyUnit::Sat)
+            .cloned()
+            .ok_or_else(|| MokshaWalletError::UnknownMint(mint_url.to_string()))
+    }
+
+    async fn get_keysets_for_mint(
+        &self,
+        mint_url: &Url,
+    ) -> Result<Vec<WalletKeyset>, MokshaWalletError> {
+        Ok(self
+            .get_wallet_keysets()
+            .await?
+            .into_iter()
+            .filter(|k| k.mint_url == *mint_url)
+            .collect())
+    }
+
+    /// Returns the keyset of the mint with the id. Keysets that are not in the localstore, e.g.
+    /// older keysets or a keyset the mint rotated to, are fetched from the mint and stored.
+    pub async fn keys_for_keyset(
+        &self,
+        mint_url: &Url,
+        keyset_id: &str,
+    ) -> Result<WalletKeyset, MokshaWalletError> {
+        if let Some(keyset) = self
+            .get_keysets_for_mint(mint_url)
+            .await?
+            .into_iter()
+            .find(|k| k.keyset_id.to_string() == keyset_id)
+        {
+            return Ok(keyset);
+        }
+
+        let keys = self
+            .client
+            .get_keys_by_id(mint_url, keyset_id.to_owned())
+            .await?
+            .keysets
+            .into_iter()
+            .find(|k| k.id == keyset_id)
+            .ok_or_else(|| MokshaWalletError::KeysetNotFound(keyset_id.to_owned()))?;
+        // the keyset becomes active with the next call of [`Wallet::add_mint_keysets`]
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(keyset_id)?,
+            mint_url,
+            &keys.unit,
+            0,
+            keys.keys,
+            false,
+        );
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+        Ok(keyset)
+    }
+
+    /// Returns the keysets of the signatures, see [`Wallet::keys_for_keyset`]
+    async fn keysets_of_signatures(
+        &self,
+        mint_url: &Url,
+        signatures: &[BlindedSignature],
+    ) -> Result<Vec<WalletKeyset>, MokshaWalletError> {
+        let mut keysets: Vec<WalletKeyset> = vec![];
+        for signature in signatures {
+            if !keysets
+                .iter()
+                .any(|k| k.keyset_id.to_string() == signature.id)
+            {
+                keysets.push(self.keys_for_keyset(mint_url, &signature.id).await?);
+            }
+        }
+        Ok(keysets)
+    }
+
+    async fn get_proofs_for_mint(&self, mint_url: &Url) -> Result<Proofs, MokshaWalletError> {
+        let keyset_ids = self
+            .get_keysets_for_mint(mint_url)
+            .await?
+            .into_iter()
+            .map(|k| k.keyset_id.to_string())
+            .collect::<HashSet<String>>();
+        Ok(self
+            .get_proofs()
+            .await?
+            .proofs()
+            .into_iter()
+            .filter(|p| keyset_ids.contains(&p.keyset_id))
+            .collect::<Vec<Proof>>()
+            .into())
+    }
+
+    pub async fn get_melt_quote_btconchain(
+        &self,
+        mint_url: &Url,
+        address: String,
+        amount: u64,
+    ) -> Result<Vec<PostMeltQuoteBtcOnchainResponse>, MokshaWalletError> {
+        self.client
+            .post_melt_quote_onchain(mint_url, address, amount, CurrencyUnit::Sat)
+            .await
+    }
+
+    pub async fn pay_onchain(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        melt_quote: &PostMeltQuoteBtcOnchainResponse,
+    ) -> Result<PostMeltBtcOnchainResponse, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let all_proofs = self.localstore.get_proofs(&mut tx).await?;
+        tx.commit().await?;
+
+        let ln_amount = melt_quote.amount + melt_quote.fee;
+
+        if ln_amount > all_proofs.total_amount() {
+            return Err(MokshaWalletError::NotEnoughTokens {
+                needed: ln_amount,
+                available: all_proofs.total_amount(),
+            });
+        }
+        let selected_proofs = self
+            .proofs_for_amount_with_fee(&wallet_keyset.mint_url, &all_proofs, ln_amount)
+            .await?;
+
+        let mut tx = self.localstore.begin_tx().await?;
+        let total_proofs = {
+            let selected_tokens =
+                TokenV3::from_proofs(&wallet_keyset.mint_url, selected_proofs.clone())?;
+            let swap_result = self
+                .swap_tokens(wallet_keyset, &selected_tokens, ln_amount.into())
+                .await?;
+            self.localstore
+                .transition_proofs(
+                    &mut tx,
+                    &selected_proofs.secrets(),
+                    ProofStatus::Spendable,
+                    ProofStatus::Spent,
+                    self.clock.now(),
+                )
+                .await?;
+            self.localstore
+                .add_proofs(&mut tx, &swap_result.0.proofs())
+                .await?;
+            let total_proofs = swap_result.1.proofs();
+            self.localstore.add_proofs(&mut tx, &total_proofs).await?;
+            self.localstore
+                .transition_proofs(
+                    &mut tx,
+                    &total_proofs.secrets(),
+                    ProofStatus::Spendable,
+                    ProofStatus::PendingMelt,
+                    self.clock.now(),
+                )
+                .await?;
+
+            total_proofs
+        };
+
+        let melt_response = self
+            .client
+            .post_melt_onchain(
+                &wallet_keyset.mint_url,
+                total_proofs.clone(),
+                melt_quote.quote.clone(),
+            )
+            .await?;
+
+        self.localstore
+            .transition_proofs(
+                &mut tx,
+                &total_proofs.secrets(),
+                ProofStatus::PendingMelt,
+                match melt_response.paid {
+                    true => ProofStatus::Spent,
+                    false => ProofStatus::Spendable,
+                },
+                self.clock.now(),
+            )
+            .await?;
+        tx.commit().await?;
+        Ok(melt_response)
+    }
+
+    async fn create_secrets(
+        &self,
+        keyset_id: &KeysetId,
+        amount: u32,
+    ) -> Result<Vec<(String, BlindingFactor)>, MokshaWalletError> {
+        Ok(self.reserve_secrets(keyset_id, amount).await?.1)
+    }
+
+    /// Generates the secrets for the next `amount` counters of the keyset and advances its
+    /// counter, so the secrets are never used twice.
+    async fn reserve_secrets(
+        &self,
+        keyset_id: &KeysetId,
+        amount: u32,
+    ) -> Result<(CounterRange, Vec<(String, BlindingFactor)>), MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let all_keysets = self.localstore.get_keysets(&mut tx).await?;
+        let keyset = all_keysets
+            .iter()
+            .find(|k| k.keyset_id == *keyset_id)
+            .expect("keyset not found create-secrets");
+
+        let start_index = (keyset.last_index + 1) as u32;
+        let secret_range = self
+            .secret_generator
+            .generate(keyset_id, start_index, amount)?;
+        validate_secrets(&secret_range)?;
+
+        self.localstore
+            .update_keyset_last_index(
+                &mut tx,
+                &WalletKeyset {
+                    last_index: (start_index + amount - 1) as u64,
+                    ..keyset.clone()
+                },
+            )
+            .await?;
+        tx.commit().await?;
+        Ok((
+            CounterRange {
+                start: start_index,
+                count: amount,
+            },
+            secret_range,
+        ))
+    }
+
+    /// Generates the secrets for the next `amount` counters of the keyset like
+    /// [`Wallet::reserve_secrets`], but leaves the counter as it is.
+    async fn peek_secrets(
+        &self,
+        keyset_id: &KeysetId,
+        amount: u32,
+    ) -> Result<Vec<(String, BlindingFactor)>, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let all_keysets = self.localstore.get_keysets(&mut tx).await?;
+        tx.commit().await?;
+        let keyset = all_keysets
+            .iter()
+            .find(|k| k.keyset_id == *keyset_id)
+            .ok_or_else(|| MokshaWalletError::KeysetNotFound(keyset_id.to_string()))?;
+
+        let secret_range =
+            self.secret_generator
+                .generate(keyset_id, (keyset.last_index + 1) as u32, amount)?;
+        validate_secrets(&secret_range)?;
+        Ok(secret_range)
+    }
+
+    /// Returns the counters of outputs that were never sent to the mint. This is only possible
+    /// as long as no counters were reserved after them.
+    async fn release_secrets(
+        &self,
+        keyset_id: &KeysetId,
+        range: CounterRange,
+    ) -> Result<(), MokshaWalletError> {
+        if range.count == 0 {
+            return Ok(());
+        }
+        let mut tx = self.localstore.begin_tx().await?;
+        let keysets = self.localstore.get_keysets(&mut tx).await?;
+        if let Some(keyset) = keysets.iter().find(|k| {
+            k.keyset_id == *keyset_id && k.last_index == (range.start + range.count - 1) as u64
+        }) {
+            self.localstore
+                .update_keyset_last_index(
+                    &mut tx,
+                    &WalletKeyset {
+                        last_index: (range.start - 1) as u64,
+                        ..keyset.clone()
+                    },
+                )
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn swap_tokens(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+        splt_amount: Amount,
+    ) -> Result<(TokenV3, TokenV3), MokshaWalletError> {
+        self.swap_tokens_with_locktime(wallet_keyset, tokens, splt_amount, None, None)
+            .await
+    }
+
+    /// Plans the outputs of [`Wallet::swap_tokens`] without contacting the mint. The second
+    /// outputs are for `splt_amount`, the first ones for the rest of the tokens.
+    ///
+    /// The secrets are not reserved, so with the secrets derived from the seed the next swap of
+    /// the keyset uses exactly these outputs, unless other outputs are created in between.
+    pub async fn plan_split(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+        splt_amount: u64,
+    ) -> Result<SplitPlan, MokshaWalletError> {
+        self.plan_swap(wallet_keyset, tokens, splt_amount.into(), None, None, false)
+            .await
+    }
+
+    /// Creates the outputs of a swap, see [`Wallet::swap_tokens_with_locktime`]. The secrets are
+    /// only reserved if `reserve` is set, which the swap itself needs.
+    async fn plan_swap(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+        splt_amount: Amount,
+        locktime: Option<u64>,
+        denominations: Option<SplitAmount>,
+        reserve: bool,
+    ) -> Result<SplitPlan, MokshaWalletError> {
+        let total_token_amount = tokens.total_amount();
+        // the outputs are the inputs without the input fee of the swap
+        let input_fee = self
+            .input_fee(&wallet_keyset.mint_url, &tokens.proofs())
+            .await?;
+        // zero amounts have no outputs, so sending nothing or everything creates a single group
+        let (first_amount, splt_amount) = splt_amount
+            .split_from(total_token_amount.saturating_sub(input_fee))
+            .map_err(|_| MokshaWalletError::NotEnoughTokens {
+                needed: splt_amount.0 + input_fee,
+                available: total_token_amount,
+            })?;
+        let first_split = first_amount.split();
+        let second_split = denominations.unwrap_or_else(|| splt_amount.split());
+        let count = (first_split.len() + second_split.len()) as u32;
+        let mut first_secrets = if reserve {
+            self.create_secrets(&wallet_keyset.keyset_id, count).await?
+        } else {
+            self.peek_secrets(&wallet_keyset.keyset_id, count).await?
+        };
+        let second_secrets = first_secrets.split_off(first_split.len());
+        let first_outputs = self.create_blinded_messages(
+            &wallet_keyset.keyset_id,
+            first_split.clone(),
+            first_secrets.clone(),
+        )?;
+
+        let second_secrets = match locktime {
+            Some(locktime) => {
+                let lock_key = create_throwaway_pubkey()?;
+                second_secrets
+                    .into_iter()
+                    .map(|(_, blinding_factor)| {
+                        (
+                            WellKnownSecret::p2pk_with_locktime(&lock_key, locktime).to_string(),
+                            blinding_factor,
+                        )
+                    })
+                    .collect()
+            }
+            None => second_secrets,
+        };
+        let second_outputs = self.create_blinded_messages(
+            &wallet_keyset.keyset_id,
+            second_split.clone(),
+            second_secrets.clone(),
+        )?;
+
+        let mut total_outputs = vec![];
+        total_outputs.extend(get_blinded_msg(first_outputs.clone()));
+        total_outputs.extend(get_blinded_msg(second_outputs.clone()));
+        // the mint rejects a swap whose outputs don't match the denominations of the amounts
+        check_output_count(&total_outputs, &[&first_split, &second_split])?;
+
+        if tokens.total_amount() != total_outputs.total_amount() + input_fee {
+            return Err(MokshaWalletError::InvalidProofs);
+        }
+
+        Ok(SplitPlan {
+            first_secrets,
+            first_outputs,
+            second_secrets,
+            second_outputs,
+        })
+    }
+
+    /// Swaps the tokens like [`Wallet::swap_tokens`]. If a locktime is given, the second
+    /// token is locked until then. The second token has the given denominations instead of the
+    /// powers of two of `splt_amount`, they must add up to `splt_amount`.
+    async fn swap_tokens_with_locktime(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        tokens: &TokenV3,
+        splt_amount: Amount,
+        locktime: Option<u64>,
+        denominations: Option<SplitAmount>,
+    ) -> Result<(TokenV3, TokenV3), MokshaWalletError> {
+        let SplitPlan {
+            first_secrets,
+            first_outputs,
+            second_secrets,
+            second_outputs,
+        } = self
+            .plan_swap(
+                wallet_keyset,
+                tokens,
+                splt_amount,
+                locktime,
+                denominations,
+                true,
+            )
+            .await?;
+        let mut total_outputs = vec![];
+        total_outputs.extend(get_blinded_msg(first_outputs.clone()));
+        total_outputs.extend(get_blinded_msg(second_outputs.clone()));
+
+        let len_first = first_secrets.len();
+        let secrets = [first_secrets, second_secrets].concat();
+        let outputs = [first_outputs, second_outputs].concat();
+
+        let secrets = secrets.into_iter().map(|(s, _)| s).collect::<Vec<String>>();
+
+        let recovery = blind_recovery_records(&wallet_keyset.mint_url, &outputs, &secrets);
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .add_blind_recovery(&mut tx, &recovery)
+            .await?;
+        tx.commit().await?;
+
+        let split_result = match self
+            .client
+            .post_swap(&wallet_keyset.mint_url, tokens.proofs(), total_outputs)
+            .await
+        {
+            Ok(split_result) => split_result,
+            Err(e) if e.is_funds_at_risk() => {
+                // the outputs stay stored for [`Wallet::recover`], if the mint can't be asked now
+                let _ = self.recover_swap(&recovery, &tokens.proofs()).await;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        self.emit(WalletEvent::SplitCompleted {
+            amount: tokens.total_amount(),
+        });
+
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .delete_blind_recovery(&mut tx, &recovery)
+            .await?;
+        tx.commit().await?;
+
+        if outputs.is_empty() {
+            return Ok((TokenV3::empty(), TokenV3::empty()));
+        }
+
+        let keysets = self
+            .keysets_of_signatures(&wallet_keyset.mint_url, &split_result.signatures)
+            .await?;
+        let proofs = self
+            .create_proofs_from_blinded_signatures(
+                &keysets,
+                split_result.signatures,
+                secrets,
+                outputs,
+                false,
+            )?
+            .proofs();
+        // the number of signatures matches the outputs, see create_proofs_from_blinded_signatures
+        let (first_proofs, second_proofs) = proofs.split_at(len_first);
+
+        let first_tokens: TokenV3 = (
+            wallet_keyset.mint_url.to_owned(),
+            wallet_keyset.currency_unit.clone(),
+            first_proofs.to_vec().into(),
+        )
+            .into();
+        let second_tokens: TokenV3 = (
+            wallet_keyset.mint_url.to_owned(),
+            wallet_keyset.currency_unit.clone(),
+            second_proofs.to_vec().into(),
+        )
+            .into();
+
+        if tokens.total_amount() < first_tokens.total_amount() + second_tokens.total_amount() {
+            println!(
+                "Error in swap: input {:?} < output {:?} + {:?}",
+                tokens.total_amount(),
+                first_tokens.total_amount(),
+                second_tokens.total_amount()
+            );
+        }
+
+        Ok((first_tokens, second_tokens))
+    }
+
+    /// Fetches the info of the mint (NUT-06). Returns `None` if the mint doesn't serve it, the
+    /// wallet then assumes that optional features like change for melts are not supported.
+    pub async fn get_mint_info(
+        &self,
+        mint_url: &Url,
+    ) -> Result<Option<MintInfoResponse>, MokshaWalletError> {
+        let info = self.client.get_info(mint_url).await?;
+        self.mint_infos
+            .write()
+            .expect("mint infos lock is poisoned")
+            .insert(mint_url.to_owned(), info.clone());
+        Ok(info)
+    }
+
+    /// Whether the mint returns the overpaid fees of a melt as change (NUT-08). Assumed for mints
+    /// whose info hasn't been fetched yet, but not for mints without info.
+    fn supports_melt_change(&self, mint_url: &Url) -> bool {
+        let mint_infos = self.mint_infos.read().expect("mint infos lock is poisoned");
+        match mint_infos.get(mint_url) {
+            Some(Some(info)) => info.nuts.nut8.as_ref().is_some_and(|nut8| nut8.supported),
+            Some(None) => false,
+            None => true,
+        }
+    }
+
+    /// Moves the response times and errors recorded by the client into the localstore
+    pub async fn flush_endpoint_stats(&self) -> Result<(), MokshaWalletError> {
+        let recorded = self.client.take_endpoint_samples();
+        if recorded.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = self.localstore.begin_tx().await?;
+        for (mint_url, samples) in recorded {
+            let mut stored = self
+                .localstore
+                .get_endpoint_stats(&mut tx, &mint_url)
+                .await?
+                .into_iter()
+                .find(|s| s.endpoint == samples.endpoint)
+                .unwrap_or_else(|| EndpointSamples::new(samples.endpoint));
+            stored.merge(samples);
+            self.localstore
+                .upsert_endpoint_stats(&mut tx, &mint_url, &stored)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Returns the latency percentiles and error counts of the endpoints of a mint
+    pub async fn endpoint_stats(
+        &self,
+        mint_url: &Url,
+    ) -> Result<Vec<EndpointSummary>, MokshaWalletError> {
+        self.flush_endpoint_stats().await?;
+
+        let mut tx = self.localstore.begin_tx().await?;
+        let mut samples = self
+            .localstore
+            .get_endpoint_stats(&mut tx, mint_url)
+            .await?;
+        tx.commit().await?;
+
+        samples.sort_by_key(|s| s.endpoint);
+        Ok(samples.iter().map(EndpointSamples::summary).collect())
+    }
+
+    /// Posts the melt, unless the proofs don't cover the `amount` of the invoice and the fee
+    /// reserve
+    async fn melt_token(
+        &self,
+        mint_url: &Url,
+        quote_id: String,
+        proofs: &Proofs,
+        amount: u64,
+        fee_blinded_messages: Vec<BlindedMessage>,
+    ) -> Result<PostMeltBolt11Response, MokshaWalletError> {
+        if proofs.total_amount() < amount {
+            return Err(MokshaWalletError::NotEnoughTokens {
+                needed: amount,
+                available: proofs.total_amount(),
+            });
+        }
+        self.client
+            .post_melt_bolt11(mint_url, proofs.clone(), quote_id, fee_blinded_messages)
+            .await
+    }
+
+    fn decode_invoice(payment_request: &str) -> Result<LNInvoice, MokshaWalletError> {
+        LNInvoice::from_str(payment_request)
+            .map_err(|err| MokshaWalletError::DecodeInvoice(payment_request.to_owned(), err))
+    }
+
+    /// Returns the amount of the invoice in sats. Fails for expired invoices, since the mint
+    /// would reject them anyway.
+    fn get_invoice_amount(&self, payment_request: &str) -> Result<u64, MokshaWalletError> {
+        let invoice = Self::decode_invoice(payment_request)?;
+        if invoice.would_expire(Duration::from_secs(self.clock.now())) {
+            return Err(MokshaWalletError::InvoiceExpired(
+                payment_request.to_owned(),
+            ));
+        }
+        Ok(invoice
+            .amount_milli_satoshis()
+            .ok_or_else(|| MokshaWalletError::InvalidInvoice(payment_request.to_owned()))?
+            / 1000)
+    }
+
+    /// Mints the tokens of a paid quote. Fails with [`MokshaWalletError::InvoiceNotPaidYet`] if the
+    /// quote is not paid yet, the same outputs are used by the next attempt.
+    pub async fn mint_tokens(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        payment_method: &PaymentMethod,
+        amount: Amount,
+        quote_id: String,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        // only one attempt per quote runs at a time, the others observe its outcome. The lock
+        // is taken before the operation is recorded, so the attempts don't write to the
+        // localstore concurrently.
+        let lock = self.mint_locks.get(&quote_id);
+        let _guard = lock.lock().await;
+        self.run_operation(OperationKind::Mint, |operation_id| async move {
+            self.mint_quote_locked(
+                wallet_keyset,
+                payment_method,
+                amount,
+                quote_id,
+                &operation_id,
+            )
+            .await
+        })
+        .await
+    }
+
+    /// Mints the tokens like [`Wallet::mint_tokens`] as part of a running operation
+    async fn mint_tokens_in_operation(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        payment_method: &PaymentMethod,
+        amount: Amount,
+        quote_id: String,
+        operation_id: &OperationId,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        let lock = self.mint_locks.get(&quote_id);
+        let _guard = lock.lock().await;
+        self.mint_quote_locked(
+            wallet_keyset,
+            payment_method,
+            amount,
+            quote_id,
+            operation_id,
+        )
+        .await
+    }
+
+    /// Mints the tokens of the quote, the caller holds the lock of the quote
+    async fn mint_quote_locked(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        payment_method: &PaymentMethod,
+        amount: Amount,
+        quote_id: String,
+        operation_id: &OperationId,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        let pending_mint = self
+            .claim_mint_hash(wallet_keyset, amount, &quote_id, operation_id)
+            .await?;
+        if let Some(proofs) = pending_mint.proofs {
+            return Ok(TokenV3::from_proofs(&pending_mint.mint_url, proofs)?);
+        }
+
+        let result = self
+            .finish_pending_mint(wallet_keyset, payment_method, pending_mint, operation_id)
+            .await;
+        if result.is_err() {
+            // keep the outputs, so the next attempt sends the same blinded messages
+            let mut tx = self.localstore.begin_tx().await?;
+            if let Some(mut pending_mint) =
+                self.localstore.get_pending_mint(&mut tx, &quote_id).await?
+            {
+                pending_mint.claimed_at = None;
+                self.localstore
+                    .update_pending_mint(&mut tx, &pending_mint)
+                    .await?;
+            }
+            tx.commit().await?;
+        }
+        result
+    }
+
+    /// Waits until the quote is paid and mints the tokens like [`Wallet::mint_tokens`]. The quote
+    /// is checked every `poll_interval`, after `timeout` the wallet gives up with
+    /// [`MokshaWalletError::MintTimeout`]. A running mint attempt is never interrupted. All
+    /// attempts send the same outputs, dropping the future stops the polling.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub async fn mint_tokens_when_paid(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        payment_method: &PaymentMethod,
+        amount: Amount,
+        quote_id: String,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if self
+                .is_quote_paid(&wallet_keyset.mint_url, payment_method, quote_id.clone())
+                .await?
+            {
+                match self
+                    .mint_tokens(
+                        wallet_keyset,
+                        payment_method,
+                        amount.clone(),
+                        quote_id.clone(),
+                    )
+                    .await
+                {
+                    Err(MokshaWalletError::InvoiceNotPaidYet(..)) => {}
+                    result => return result,
+                }
+            }
+
+            if tokio::time::Instant::now() + poll_interval > deadline {
+                return Err(MokshaWalletError::MintTimeout(quote_id));
+            }
+            tokio::time::sleep(poll_interval).await;
+        }
+    }
+
+    /// Claims the mint attempt for a quote. Returns the stored outputs of an earlier attempt or
+    /// creates and stores new ones. Fails if another process is minting the quote right now.
+    async fn claim_mint_hash(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        amount: Amount,
+        quote_id: &str,
+        operation_id: &OperationId,
+    ) -> Result<PendingMint, MokshaWalletError> {
+        let now = self.clock.now();
+        let mut tx = self.localstore.begin_tx().await?;
+        if let Some(mut pending_mint) = self.localstore.get_pending_mint(&mut tx, quote_id).await? {
+            if pending_mint.proofs.is_none() {
+                let stale_before = now.saturating_sub(MINT_CLAIM_TIMEOUT);
+                if !self
+                    .localstore
+                    .claim_mint_hash(&mut tx, quote_id, now, stale_before)
+                    .await?
+                {
+                    return Err(MokshaWalletError::MintInProgress(quote_id.to_owned()));
+                }
+                pending_mint.claimed_at = Some(now);
+            }
+            self.localstore
+                .add_operation_records(
+                    &mut tx,
+                    operation_id,
+                    &[OperationRecord::PendingMint(quote_id.to_owned())],
+                )
+                .await?;
+            tx.commit().await?;
+            return Ok(pending_mint);
+        }
+        tx.commit().await?;
+
+        let split_amount = amount.split();
+        let secret_range = self
+            .create_secrets(&wallet_keyset.keyset_id, split_amount.len() as u32)
+            .await?;
+
+        let outputs = split_amount
+            .into_iter()
+            .zip(secret_range)
+            .map(|(amount, (secret, blinding_factor))| {
+                let b_ = self.dhke.step1_alice(&secret, &blinding_factor)?;
+                Ok(PendingMintOutput {
+                    blinded_message: BlindedMessage {
+                        amount,
+                        b_,
+                        id: wallet_keyset.keyset_id.to_string(), // FIXME use keyset_id
+                    },
+                    secret,
+                    blinding_factor: blinding_factor.as_hex(),
+                })
+            })
+            .collect::<Result<Vec<_>, MokshaWalletError>>()?;
+
+        let pending_mint = PendingMint {
+            quote_id: quote_id.to_owned(),
+            mint_url: wallet_keyset.mint_url.clone(),
+            keyset_id: wallet_keyset.keyset_id.to_string(),
+            outputs,
+            claimed_at: Some(now),
+            proofs: None,
+        };
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .add_pending_mint(&mut tx, &pending_mint)
+            .await?;
+        self.localstore
+            .add_operation_records(
+                &mut tx,
+                operation_id,
+                &[OperationRecord::PendingMint(quote_id.to_owned())],
+            )
+            .await?;
+        tx.commit().await?;
+        Ok(pending_mint)
+    }
+
+    async fn finish_pending_mint(
+        &self,
+        wallet_keyset: &WalletKeyset,
+        payment_method: &PaymentMethod,
+        mut pending_mint: PendingMint,
+        operation_id: &OperationId,
+    ) -> Result<TokenV3, MokshaWalletError> {
+        let quote_id = pending_mint.quote_id.clone();
+        let blinded_messages = pending_mint
+            .outputs
+            .iter()
+            .map(|output| {
+                Ok((
+                    output.blinded_message.clone(),
+                    BlindingFactor::try_from(output.blinding_factor.as_str())?,
+                    output.secret.clone(),
+                ))
+            })
+            .collect::<Result<Vec<(_, _, _)>, MokshaWalletError>>()?;
+
+        let recovery = pending_mint
+            .outputs
+            .iter()
+            .map(|output| BlindRecovery {
+                mint_url: pending_mint.mint_url.clone(),
+                blinded_message: output.blinded_message.clone(),
+                secret: output.secret.clone(),
+                blinding_factor: output.blinding_factor.clone(),
+            })
+            .collect::<Vec<_>>();
+        let mut tx = self.localstore.begin_tx().await?;
+        // the records of an interrupted attempt may still exist
+        self.localstore
+            .delete_blind_recovery(&mut tx, &recovery)
+            .await?;
+        self.localstore
+            .add_blind_recovery(&mut tx, &recovery)
+            .await?;
+        tx.commit().await?;
+
+        self.emit(WalletEvent::MintSubmitted {
+            quote_id: quote_id.clone(),
+        });
+        let signatures = match payment_method {
+            PaymentMethod::Bolt11 => {
+                let post_mint_resp = self
+                    .client
+                    .post_mint_bolt11(
+                        &wallet_keyset.mint_url,
+                        quote_id,
+                        blinded_messages
+                            .clone()
+                            .into_iter()
+                            .map(|(msg, _, _)| msg)
+                            .collect::<Vec<BlindedMessage>>(),
+                    )
+                    .await?;
+                post_mint_resp.signatures
+            }
+            PaymentMethod::BtcOnchain => {
+                let post_mint_resp = self
+                    .client
+                    .post_mint_onchain(
+                        &wallet_keyset.mint_url,
+                        quote_id,
+                        blinded_messages
+                            .clone()
+                            .into_iter()
+                            .map(|(msg, _, _)| msg)
+                            .collect::<Vec<BlindedMessage>>(),
+                    )
+                    .await?;
+                post_mint_resp.signatures
+            }
+        };
+
+        // the outputs are kept for the next attempt, unless every output is signed
+        if signatures.len() != blinded_messages.len() {
+            return Err(MokshaWalletError::MintResponseLengthMismatch {
+                expected: blinded_messages.len(),
+                got: signatures.len(),
+            });
+        }
+
+        // step 3: unblind signatures, they have to be from the keyset of the outputs
+        let keysets = vec![
+            self.keys_for_keyset(&wallet_keyset.mint_url, &pending_mint.keyset_id)
+                .await?,
+        ];
+        let (outputs, secrets) = blinded_messages
+            .into_iter()
+            .map(|(msg, blinding_factor, secret)| ((msg, blinding_factor), secret))
+            .unzip();
+        let proofs = self
+            .create_proofs_from_blinded_signatures(&keysets, signatures, secrets, outputs, false)?;
+
+        let tokens = TokenV3::from_proofs(&wallet_keyset.mint_url, proofs)?;
+        pending_mint.claimed_at = None;
+        pending_mint.proofs = Some(tokens.proofs());
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .add_proofs(&mut tx, &tokens.proofs())
+            .await?;
+        self.localstore
+            .update_pending_mint(&mut tx, &pending_mint)
+            .await?;
+        self.localstore
+            .add_operation_records(
+                &mut tx,
+                operation_id,
+                &OperationRecord::proofs(&tokens.proofs()),
+            )
+            .await?;
+        self.localstore
+            .add_history_entry(
+                &mut tx,
+                &self.history_entry(
+                    operation_id,
+                    HistoryKind::Mint,
+                    tokens.total_amount(),
+                    &wallet_keyset.mint_url,
+                ),
+            )
+            .await?;
+        self.localstore
+            .delete_blind_recovery(&mut tx, &recovery)
+            .await?;
+        tx.commit().await?;
+
+        Ok(tokens)
+    }
+
+    pub async fn create_blank(
+        &self,
+        fee_reserve: Amount,
+        keyset_id: &KeysetId,
+    ) -> Result<Vec<(BlindedMessage, BlindingFactor, String)>, MokshaWalletError> {
+        Ok(self.reserve_blank(fee_reserve, keyset_id).await?.1)
+    }
+
+    /// Creates the blank outputs for the change of a melt (NUT-08). Their secrets are derived from
+    /// the counter of the keyset like all other outputs, so restoring finds the change.
+    async fn reserve_blank(
+        &self,
+        fee_reserve: Amount,
+        keyset_id: &KeysetId,
+    ) -> Result<(CounterRange, Vec<(BlindedMessage, BlindingFactor, String)>), MokshaWalletError>
+    {
+        if fee_reserve.0 == 0 {
+            return Ok((CounterRange::default(), vec![]));
+        }
+
+        let fee_reserve_float = fee_reserve.0 as f64;
+        let count = (fee_reserve_float.log2().ceil() as u64).max(1);
+
+        let (range, secret_range) = self.reserve_secrets(keyset_id, count as u32).await?;
+        let blinded_messages = secret_range
+            .into_iter()
+            .map(|(secret, blinding_factor)| {
+                let b_ = self.dhke.step1_alice(secret.clone(), &blinding_factor)?;
+                Ok((
+                    BlindedMessage {
+                        amount: 1,
+                        b_,
+                        id: keyset_id.to_string(),
+                    },
+                    blinding_factor,
+                    secret,
+                ))
+            })
+            .collect::<Result<Vec<(_, _, _)>, MokshaWalletError>>()?;
+
+        Ok((range, blinded_messages))
+    }
+
+    fn create_blinded_messages(
+        &self,
+        keyset_id: &KeysetId,
+        split_amount: SplitAmount,
+        secrets_factors: Vec<(String, BlindingFactor)>,
+    ) -> Result<Vec<(BlindedMessage, BlindingFactor)>, MokshaWalletError> {
+        split_amount
+            .into_iter()
+            .zip(secrets_factors)
+            .map(|(amount, (secret, blinding_factor))| {
+                let b_ = self.dhke.step1_alice(secret, &blinding_factor)?;
+                Ok((
+                    BlindedMessage {
+                        amount,
+                        b_,
+                        id: keyset_id.to_string(),
+                    },
+                    blinding_factor,
+                ))
+            })
+            .collect::<Result<Vec<(_, _)>, MokshaWalletError>>()
+    }
+
+    /// Unblinds the signatures of the mint.
+    ///
+    /// Every signature must belong to one of the outputs, have the amount of the output and be
+    /// signed by one of the given keysets. Mints should keep the order of the outputs, but some
+    /// sort the signatures by amount, see [`Self::sort_signatures`]. The mint signs only as many
+    /// blank outputs for the change of a melt (NUT-08) as needed and chooses their amounts, so
+    /// `blank_outputs` allows fewer signatures and other amounts. The DLEQ proof of a signature
+    /// (NUT-12) is verified if the mint sent one.
+    fn create_proofs_from_blinded_signatures(
+        &self,
+        keysets: &[WalletKeyset],
+        signatures: Vec<BlindedSignature>,
+        secrets: Vec<String>,
+        outputs: Vec<(BlindedMessage, BlindingFactor)>,
+        blank_outputs: bool,
+    ) -> Result<Proofs, MokshaWalletError> {
+        if signatures.len() > outputs.len() || (!blank_outputs && signatures.len() < outputs.len())
+        {
+            let index = signatures.len().min(outputs.len());
+            let amount = signatures
+                .get(index)
+                .map(|signature| signature.amount)
+                .or_else(|| outputs.get(index).map(|(output, _)| output.amount))
+                .unwrap_or_default();
+            return Err(MokshaWalletError::InvalidSignature { amount, index });
+        }
+        let signatures = match blank_outputs {
+            // blank outputs are signed in order, their amounts don't identify them
+            true => signatures,
+            false => self.sort_signatures(keysets, signatures, &outputs)?,
+        };
+
+        Ok(signatures
+            .into_iter()
+            .zip(outputs)
+            .zip(secrets)
+            .enumerate()
+            .map(
+                |(index, ((signature, (output, blinding_factor)), secret))| {
+                    let invalid = MokshaWalletError::InvalidSignature {
+                        amount: signature.amount,
+                        index,
+                    };
+                    if !blank_outputs && signature.amount != output.amount {
+                        return Err(invalid);
+                    }
+                    let keyset = keysets
+                        .iter()
+                        .find(|k| k.keyset_id.to_string() == signature.id)
+                        .ok_or(invalid)?;
+                    let key = keyset
+                        .public_keys
+                        .get(&signature.amount)
+                        .ok_or(MokshaWalletError::PubkeyNotFound)?;
+                    // mints without NUT-12 do not send a proof
+                    if let Some(dleq) = &signature.dleq {
+                        if !self.dhke.verify_dleq(dleq, output.b_, signature.c_, *key)? {
+                            return Err(MokshaWalletError::InvalidDleq {
+                                amount: signature.amount,
+                                index,
+                            });
+                        }
+                    }
+                    let pub_alice = self.dhke.step3_alice(signature.c_, blinding_factor, *key)?;
+                    Ok(Proof::new(
+                        signature.amount,
+                        secret,
+                        pub_alice,
+                        signature.id,
+                    ))
+                },
+            )
+            .collect::<Result<Vec<_>, MokshaWalletError>>()?
+            .into())
+    }
+
+    /// Sorts the signatures into the order of their outputs, so the proofs have the order of the
+    /// outputs.
+    ///
+    /// A signature belongs to an unmatched output with the same amount, preferably the one at the
+    /// same position. Of several outputs with the same amount, the one the DLEQ proof of the
+    /// signature was created for is chosen. Without a DLEQ proof only the position tells outputs
+    /// with the same amount apart.
+    fn sort_signatures(
+        &self,
+        keysets: &[WalletKeyset],
+        signatures: Vec<BlindedSignature>,
+        outputs: &[(BlindedMessage, BlindingFactor)],
+    ) -> Result<Vec<BlindedSignature>, MokshaWalletError> {
+        let mut sorted: Vec<Option<BlindedSignature>> = vec![None; outputs.len()];
+        for (index, signature) in signatures.into_iter().enumerate() {
+            let invalid = MokshaWalletError::InvalidSignature {
+                amount: signature.amount,
+                index,
+            };
+            let candidates = once(index)
+                .chain(0..outputs.len())
+                .filter(|&i| {
+                    matches!((outputs.get(i), sorted.get(i)), (Some((output, _)), Some(None)) if output.amount == signature.amount)
+                })
+                .collect::<Vec<_>>();
+            let key = keysets
+                .iter()
+                .find(|k| k.keyset_id.to_string() == signature.id)
+                .and_then(|keyset| keyset.public_keys.get(&signature.amount));
+            let proven = candidates
+                .iter()
+                .copied()
+                .find(|&i| match (&signature.dleq, key) {
+                    (Some(dleq), Some(key)) => self
+                        .dhke
+                        .verify_dleq(dleq, outputs[i].0.b_, signature.c_, *key)
+                        .unwrap_or(false),
+                    _ => false,
+                });
+            // an invalid proof is reported when the signature is unblinded
+            let position = proven
+                .or_else(|| candidates.first().copied())
+                .ok_or(invalid)?;
+            sorted[position] = Some(signature);
+        }
+        // every output has a signature, since there are as many signatures as outputs
+        Ok(sorted.into_iter().flatten().collect())
+    }
+
+    /// Unblinds the change of a melt. The mint may keep at most the fee reserve of the quote, so
+    /// the change has to be at least `overpaid - fee_reserve` and can't exceed `overpaid`.
+    fn verify_melt_change(
+        &self,
+        keysets: &[WalletKeyset],
+        signatures: Vec<BlindedSignature>,
+        secrets: Vec<String>,
+        outputs: Vec<(BlindedMessage, BlindingFactor)>,
+        overpaid: u64,
+        fee_reserve: u64,
+    ) -> Result<Proofs, MokshaWalletError> {
+        let change = self
+            .create_proofs_from_blinded_signatures(keysets, signatures, secrets, outputs, true)
+            .map_err(|e| MokshaWalletError::InvalidChange(e.to_string()))?;
+        let min_change = overpaid.saturating_sub(fee_reserve);
+        let amount = change.total_amount();
+        if amount < min_change || amount > overpaid {
+            return Err(MokshaWalletError::InvalidChange(format!(
+                "expected between {min_change} and {overpaid} sats, got {amount}"
+            )));
+        }
+        Ok(change)
+    }
+
+    /// Recovers the proofs of swaps and mints that were interrupted after the request was sent,
+    /// e.g. by a crash. Asks the mints for the signatures of the stored outputs (NUT-09) and
+    /// adds the unspent proofs to the localstore. Returns the recovered amount.
+    ///
+    /// Should be called while no other operation is running, because it also picks up the
+    /// outputs of requests that are still in flight.
+    pub async fn recover(&self) -> Result<u64, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let records = self.localstore.take_blind_recovery(&mut tx).await?;
+        tx.commit().await?;
+        if records.is_empty() {
+            return Ok(0);
+        }
+
+        match self.recover_records(&records).await {
+            Ok(amount) => Ok(amount),
+            Err(err) => {
+                // keep the records for the next attempt
+                let mut tx = self.localstore.begin_tx().await?;
+                self.localstore
+                    .add_blind_recovery(&mut tx, &records)
+                    .await?;
+                tx.commit().await?;
+                Err(err)
+            }
+        }
+    }
+
+    /// Restores the proofs of all keysets from the seed, e.g. after the localstore was lost.
+    ///
+    /// Derives the outputs for `batch_size` counters at a time beginning at `start_counter` and
+    /// asks the mint for their signatures (NUT-09). Scanning a keyset stops after
+    /// [`RESTORE_GAP_LIMIT`] consecutive batches without any signature. The unspent proofs are
+    /// added to the localstore. Returns the restored amount.
+    pub async fn restore(
+        &self,
+        start_counter: u32,
+        batch_size: u32,
+    ) -> Result<u64, MokshaWalletError> {
+        let mut restored_amount = 0;
+        for keyset in self.get_wallet_keysets().await? {
+            restored_amount += self
+                .restore_keyset(&keyset, start_counter, batch_size)
+                .await?;
+        }
+        Ok(restored_amount)
+    }
+
+    /// Restores the proofs of a single keyset like [`Wallet::restore`], e.g. for the keyset of a
+    /// mint that was added again. Returns the restored amount.
+    pub async fn restore_keyset(
+        &self,
+        keyset: &WalletKeyset,
+        start_counter: u32,
+        batch_size: u32,
+    ) -> Result<u64, MokshaWalletError> {
+        let known_secrets = self.get_known_secrets().await?;
+
+        let mut restored = vec![];
+        let mut last_counter = None;
+        let mut empty_batches = 0;
+        let mut counter = start_counter;
+        while empty_batches < RESTORE_GAP_LIMIT {
+            let secrets = self
+                .secret_generator
+                .generate(&keyset.keyset_id, counter, batch_size)?;
+            let outputs = secrets
+                .iter()
+                .map(|(secret, blinding_factor)| {
+                    Ok(BlindedMessage {
+                        // the mint returns the amount of the signed output
+                        amount: 1,
+                        b_: self.dhke.step1_alice(secret, blinding_factor)?,
+                        id: keyset.keyset_id.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<_>, MokshaWalletError>>()?;
+
+            let response = self
+                .client
+                .post_restore(&keyset.mint_url, outputs.clone())
+                .await?;
+            if response.signatures.is_empty() {
+                empty_batches += 1;
+            } else {
+                empty_batches = 0;
+            }
+
+            for (output, signature) in response.outputs.iter().zip(response.signatures) {
+                let Some(index) = outputs.iter().position(|o| o.b_ == output.b_) else {
+                    continue;
+                };
+                last_counter = Some(counter + index as u32);
+                let (secret, blinding_factor) = secrets[index].clone();
+                if known_secrets.contains(&secret) {
+                    continue;
+                }
+                let key = keyset
+                    .public_keys
+                    .get(&signature.amount)
+                    .ok_or(MokshaWalletError::PubkeyNotFound)?;
+                let c = self.dhke.step3_alice(signature.c_, blinding_factor, *key)?;
+                restored.push(Proof::new(
+                    signature.amount,
+                    secret,
+                    c,
+                    keyset.keyset_id.to_string(),
+                ));
+            }
+            counter += batch_size;
+        }
+
+        let restored: Proofs = restored.into();
+        let states = self.get_proof_states(&restored).await?;
+        let unspent: Proofs = restored
+            .proofs()
+            .into_iter()
+            .zip(states)
+            .filter_map(|(proof, state)| (state == State::Unspent).then_some(proof))
+            .collect::<Vec<_>>()
+            .into();
+
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore.add_proofs(&mut tx, &unspent).await?;
+        // new secrets must not reuse the restored counters
+        if let Some(last_counter) = last_counter {
+            if last_counter as u64 > keyset.last_index {
+                self.localstore
+                    .update_keyset_last_index(
+                        &mut tx,
+                        &WalletKeyset {
+                            last_index: last_counter as u64,
+                            ..keyset.clone()
+                        },
+                    )
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(unspent.total_amount())
+    }
+
+    /// Restores the outputs of a swap whose response was lost. If the mint signed them, the swap
+    /// was executed and the inputs in the localstore are marked as spent. Returns the recovered
+    /// amount.
+    async fn recover_swap(
+        &self,
+        recovery: &[BlindRecovery],
+        inputs: &Proofs,
+    ) -> Result<u64, MokshaWalletError> {
+        let recovered = self.recover_records(recovery).await?;
+
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .delete_blind_recovery(&mut tx, recovery)
+            .await?;
+        if recovered > 0 {
+            let inputs = inputs.secrets();
+            let spent = self
+                .localstore
+                .get_proofs(&mut tx)
+                .await?
+                .secrets()
+                .into_iter()
+                .filter(|secret| inputs.contains(secret))
+                .collect::<Vec<_>>();
+            self.localstore
+                .transition_proofs(
+                    &mut tx,
+                    &spent,
+                    ProofStatus::Spendable,
+                    ProofStatus::Spent,
+                    self.clock.now(),
+                )
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(recovered)
+    }
+
+    async fn recover_records(&self, records: &[BlindRecovery]) -> Result<u64, MokshaWalletError> {
+        let keysets = self.get_wallet_keysets().await?;
+        let known_secrets = self.get_known_secrets().await?;
+
+        let mut records_by_mint: HashMap<Url, Vec<&BlindRecovery>> = HashMap::new();
+        for record in records {
+            records_by_mint
+                .entry(record.mint_url.clone())
+                .or_default()
+                .push(record);
+        }
+
+        let mut restored = vec![];
+        for (mint_url, records) in records_by_mint {
+            let outputs = records
+                .iter()
+                .map(|record| record.blinded_message.clone())
+                .collect::<Vec<_>>();
+            let response = self.client.post_restore(&mint_url, outputs).await?;
+
+            for (output, signature) in response.outputs.iter().zip(response.signatures) {
+                let Some(record) = records
+                    .iter()
+                    .find(|record| record.blinded_message.b_ == output.b_)
+                else {
+                    continue;
+                };
+                if known_secrets.contains(&record.secret) {
+                    continue;
+                }
+                let key = keysets
+                    .iter()
+                    .find(|k| k.mint_url == mint_url && k.keyset_id.to_string() == signature.id)
+                    .and_then(|k| k.public_keys.get(&signature.amount))
+                    .ok_or(MokshaWalletError::PubkeyNotFound)?;
+                let blinding_factor = BlindingFactor::try_from(record.blinding_factor.as_str())?;
+                let c = self.dhke.step3_alice(signature.c_, blinding_factor, *key)?;
+                restored.push(Proof::new(
+                    signature.amount,
+                    record.secret.clone(),
+                    c,
+                    signature.id,
+                ));
+            }
+        }
+
+        let restored: Proofs = restored.into();
+        let states = self.get_proof_states(&restored).await?;
+        let unspent: Proofs = restored
+            .proofs()
+            .into_iter()
+            .zip(states)
+            .filter_map(|(proof, state)| (state == State::Unspent).then_some(proof))
+            .collect::<Vec<_>>()
+            .into();
+
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore.add_proofs(&mut tx, &unspent).await?;
+        tx.commit().await?;
+        Ok(unspent.total_amount())
+    }
+
+    pub async fn get_proofs(&self) -> Result<Proofs, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let proofs = self.localstore.get_proofs(&mut tx).await?;
+        tx.commit().await?;
+        Ok(proofs)
+    }
+
+    /// Exports the spendable proofs with their mints and keysets, see [`WalletBackup`]. The backup
+    /// is encrypted with a key derived from `password`.
+    pub async fn export_backup(&self, password: &str) -> Result<Vec<u8>, MokshaWalletError> {
+        let keysets = self.get_wallet_keysets().await?;
+        // keyset ids and proofs per mint
+        let mut mints: BTreeMap<String, (Vec<String>, Vec<Proof>)> = BTreeMap::new();
+        for proof in self.get_proofs().await?.proofs() {
+            let keyset = keysets
+                .iter()
+                .find(|k| k.keyset_id.to_string() == proof.keyset_id)
+                .ok_or_else(|| MokshaWalletError::KeysetNotFound(proof.keyset_id.clone()))?;
+            let (keyset_ids, proofs) = mints.entry(keyset.mint_url.to_string()).or_default();
+            if !keyset_ids.contains(&proof.keyset_id) {
+                keyset_ids.push(proof.keyset_id.clone());
+            }
+            proofs.push(proof);
+        }
+
+        let json = serde_json::to_string(&WalletBackup {
+            version: BACKUP_VERSION,
+            mints: mints
+                .into_iter()
+                .map(|(mint_url, (keysets, proofs))| MintBackup {
+                    mint_url,
+                    keysets,
+                    proofs: proofs.into(),
+                })
+                .collect(),
+        })?;
+        encrypt_backup(&json, password)
+    }
+
+    /// Adds the proofs of a backup from [`Wallet::export_backup`] to the localstore. Proofs that
+    /// the localstore already contains are skipped, so importing a backup twice changes nothing.
+    /// If the wallet already has mints, a backup with proofs of other mints is only imported with
+    /// `force`. The keysets of unknown mints are fetched from the mint. Returns the imported
+    /// amount.
+    pub async fn import_backup(
+        &self,
+        data: &[u8],
+        password: &str,
+        force: bool,
+    ) -> Result<u64, MokshaWalletError> {
+        let backup = if data.starts_with(BACKUP_MAGIC) {
+            WalletBackup::parse(&decrypt_backup(data, password)?)?
+        } else {
+            // backups of version 1 are unencrypted json
+            let backup = WalletBackup::parse(std::str::from_utf8(data).map_err(|_| {
+                MokshaWalletError::InvalidBackup("not a moksha backup".to_owned())
+            })?)?;
+            if backup.version != 1 {
+                return Err(MokshaWalletError::InvalidBackup(format!(
+                    "backups of version {} must be encrypted",
+                    backup.version
+                )));
+            }
+            backup
+        };
+
+        let mut keysets = self.get_wallet_keysets().await?;
+        if !force && !keysets.is_empty() {
+            for mint in &backup.mints {
+                let mint_url = normalize_mint_url(&Url::parse(&mint.mint_url)?)?;
+                if !keysets.iter().any(|k| k.mint_url == mint_url) {
+                    return Err(MokshaWalletError::BackupFromOtherMint(mint_url.to_string()));
+                }
+            }
+        }
+        for mint in &backup.mints {
+            let mint_url = normalize_mint_url(&Url::parse(&mint.mint_url)?)?;
+            let has_keysets = |keysets: &[WalletKeyset]| {
+                mint.keysets.iter().all(|id| {
+                    keysets
+                        .iter()
+                        .any(|k| k.mint_url == mint_url && k.keyset_id.to_string() == *id)
+                })
+            };
+            if has_keysets(&keysets) {
+                continue;
+            }
+            self.add_mint_keysets(&mint_url).await?;
+            keysets = self.get_wallet_keysets().await?;
+            if !has_keysets(&keysets) {
+                return Err(MokshaWalletError::InvalidBackup(format!(
+                    "mint {mint_url} doesn't know all keysets of the backup"
+                )));
+            }
+        }
+
+        let mut known = self.get_known_secrets().await?;
+        let proofs: Proofs = backup
+            .mints
+            .into_iter()
+            .flat_map(|mint| mint.proofs.proofs())
+            .filter(|proof| known.insert(proof.secret.clone()))
+            .collect::<Vec<_>>()
+            .into();
+
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore.add_proofs(&mut tx, &proofs).await?;
+        tx.commit().await?;
+        Ok(proofs.total_amount())
+    }
+
+    /// Pays the balance of the mint to the lightning address of the [`AutoWithdrawConfig`] once it
+    /// reaches `min_batch_sat`. Runs after every receive, apps can also call it periodically.
+    /// `max_fee_percent` of the balance is kept back for the fees, the payment is journaled like
+    /// one of [`Wallet::pay_invoice`]. A failed withdrawal leaves the balance in the wallet and is
+    /// retried after [`AUTO_WITHDRAW_BASE_DELAY`], which doubles with every failure.
+    pub async fn auto_withdraw(
+        &self,
+        wallet_keyset: &WalletKeyset,
+    ) -> Result<AutoWithdrawal, MokshaWalletError> {
+        let Some(config) = &self.auto_withdraw else {
+            return Ok(AutoWithdrawal::Disabled);
+        };
+        let mint_url = &wallet_keyset.mint_url;
+        let mut backoff = self.auto_withdraw_backoff.lock().await;
+        let now = self.clock.now();
+        if let Some(state) = backoff.get(mint_url).filter(|state| now < state.retry_at) {
+            return Ok(AutoWithdrawal::BackingOff {
+                retry_at: state.retry_at,
+            });
+        }
+
+        let result = self.withdraw_to_ln_address(config, wallet_keyset).await;
+        match &result {
+            Ok(_) => {
+                backoff.remove(mint_url);
+            }
+            Err(e) => {
+                let state = backoff.entry(mint_url.clone()).or_default();
+                state.failures += 1;
+                state.retry_at = now + auto_withdraw_backoff(state.failures);
+                self.emit(WalletEvent::AutoWithdrawFailed {
+                    error: e.to_string(),
+                    retry_at: state.retry_at,
+                });
+            }
+        }
+        result
+    }
+
+    /// Failures are reported as [`WalletEvent::AutoWithdrawFailed`], the receive itself succeeded
+    async fn auto_withdraw_after_receive(&self, wallet_keyset: &WalletKeyset) {
+        if self.auto_withdraw.is_some() {
+            let _ = self.auto_withdraw(wallet_keyset).await;
+        }
+    }
+
+    async fn withdraw_to_ln_address(
+        &self,
+        config: &AutoWithdrawConfig,
+        wallet_keyset: &WalletKeyset,
+    ) -> Result<AutoWithdrawal, MokshaWalletError> {
+        let mint_url = &wallet_keyset.mint_url;
+        let balance = self.get_proofs_for_mint(mint_url).await?.total_amount();
+        if balance < config.min_batch_sat {
+            return Ok(AutoWithdrawal::BelowThreshold { balance });
+        }
+
+        let pay_request = self
+            .client
+            .get_lnurl_pay_request(&config.ln_address.lnurlp_url()?)
+            .await?;
+        if pay_request.tag != "payRequest" {
+            return Err(MokshaWalletError::Lnurl(format!(
+                "{} returned a {} instead of a payRequest",
+                config.ln_address, pay_request.tag
+            )));
+        }
+        // the rest of the balance is kept for the fees
+        let amount = ((balance as f64 * 100.0 / (100.0 + config.max_fee_percent)) as u64)
+            .min(pay_request.max_sendable / 1_000);
+        if amount * 1_000 < pay_request.min_sendable {
+            return Err(MokshaWalletError::Lnurl(format!(
+                "{amount} sats are below the minimum of {}",
+                config.ln_address
+            )));
+        }
+
+        let invoice = self
+            .client
+            .get_lnurl_invoice(&pay_request.callback, amount * 1_000)
+            .await?
+            .pr;
+        // the invoice is paid without asking, so it must be for exactly the requested amount
+        if Self::decode_invoice(&invoice)?.amount_milli_satoshis() != Some(amount * 1_000) {
+            return Err(MokshaWalletError::Lnurl(format!(
+                "{} returned an invoice that is not for {amount} sats",
+                config.ln_address
+            )));
+        }
+
+        let melt_quote = self
+            .get_melt_quote_bolt11(mint_url, invoice.clone(), CurrencyUnit::Sat)
+            .await?;
+        if melt_quote.fee_reserve as f64 > amount as f64 * config.max_fee_percent / 100.0 {
+            return Ok(AutoWithdrawal::FeeTooHigh {
+                amount,
+                fee_reserve: melt_quote.fee_reserve,
+            });
+        }
+
+        let (response, _) = self
+            .pay_invoice(wallet_keyset, &melt_quote, invoice)
+            .await?;
+        Ok(match response.paid {
+            true => AutoWithdrawal::Paid { amount },
+            false => AutoWithdrawal::Pending { amount },
+        })
+    }
+
+    /// Creates a [`BalanceAttestation`] for proofs of at least `min_amount` sats at the mint. The
+    /// attestation contains no secrets, so it shows the proofs without giving them away.
+    pub async fn export_balance_attestation(
+        &self,
+        mint_url: &Url,
+        min_amount: u64,
+    ) -> Result<BalanceAttestation, MokshaWalletError> {
+        let proofs = self.get_proofs_for_mint(mint_url).await?;
+        if min_amount > proofs.total_amount() {
+            return Err(MokshaWalletError::NotEnoughTokens {
+                needed: min_amount,
+                available: proofs.total_amount(),
+            });
+        }
+        BalanceAttestation::create(
+            mint_url,
+            &proofs.proofs_for_amount(min_amount)?,
+            self.clock.now(),
+        )
+    }
+
+    /// Verifies an attestation from [`Wallet::export_balance_attestation`] with `verifier` and
+    /// asks its mint whether all proofs are unspent. Returns the attested amount.
+    pub async fn verify_balance_attestation(
+        &self,
+        verifier: &mut AttestationVerifier,
+        attestation: &BalanceAttestation,
+    ) -> Result<u64, MokshaWalletError> {
+        verifier.check(attestation, self.clock.now())?;
+        let ys = attestation.proofs.iter().map(|p| p.y).collect::<Vec<_>>();
+        let states = self
+            .client
+            .post_checkstate(&attestation.mint_url, ys.clone())
+            .await?
+            .states;
+        let unspent = ys.iter().all(|y| {
+            states
+                .iter()
+                .any(|s| &s.y == y && s.state == State::Unspent)
+        });
+        if !unspent {
+            return Err(MokshaWalletError::InvalidAttestation(
+                "proofs are not unspent".to_owned(),
+            ));
+        }
+        Ok(attestation.total_amount())
+    }
+
+    /// Returns the secrets of all proofs in the localstore, including sent and spent proofs
+    async fn get_known_secrets(&self) -> Result<HashSet<String>, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let mut secrets = HashSet::new();
+        for state in ProofStatus::ALL {
+            secrets.extend(
+                self.localstore
+                    .get_proofs_by_state(&mut tx, state)
+                    .await?
+                    .secrets(),
+            );
+        }
+        tx.commit().await?;
+        Ok(secrets)
+    }
+
+    /// Returns the proofs that have been reserved for longer than `older_than`, e.g. because the
+    /// wallet crashed during a melt
+    pub async fn stuck_reservations(
+        &self,
+        older_than: Duration,
+    ) -> Result<Vec<ReservedProof>, MokshaWalletError> {
+        let now = self.clock.now();
+        let mut tx = self.localstore.begin_tx().await?;
+        let reserved = self.localstore.get_reserved_proofs(&mut tx).await?;
+        tx.commit().await?;
+
+        Ok(reserved
+            .into_iter()
+            .filter(|r| r.reserved_at.saturating_add(older_than.as_secs()) <= now)
+            .collect())
+    }
+
+    /// Checks the stuck reservations with the mint. Unspent proofs are added to the balance again,
+    /// spent proofs are removed and pending proofs stay reserved.
+    pub async fn release_stuck_reservations(
+        &self,
+        older_than: Duration,
+    ) -> Result<ReleasedReservations, MokshaWalletError> {
+        let stuck = self.stuck_reservations(older_than).await?;
+        let keysets = self.get_wallet_keysets().await?;
+
+        let mut proofs_by_mint: HashMap<Url, Vec<Proof>> = HashMap::new();
+        let mut states = HashMap::new();
+        for reserved in stuck {
+            // proofs of unknown keysets can't be checked
+            if let Some(keyset) = keysets
+                .iter()
+                .find(|k| k.keyset_id.to_string() == reserved.proof.keyset_id)
+            {
+                states.insert(reserved.proof.secret.clone(), reserved.state);
+                proofs_by_mint
+                    .entry(keyset.mint_url.clone())
+                    .or_default()
+                    .push(reserved.proof);
+            }
+        }
+
+        let mut released = vec![];
+        let mut spent = vec![];
+        for (mint_url, proofs) in proofs_by_mint {
+            let ys = proofs
+                .iter()
+                .map(|p| p.y())
+                .collect::<Result<Vec<PublicKey>, _>>()?;
+            let states = self
+                .client
+                .post_checkstate(&mint_url, ys.clone())
+                .await?
+                .states
+                .into_iter()
+                .map(|s| (s.y, s.state))
+                .collect::<HashMap<PublicKey, State>>();
+
+            for (proof, y) in proofs.into_iter().zip(ys) {
+                match states.get(&y) {
+                    Some(State::Unspent) => released.push(proof),
+                    Some(State::Spent) => spent.push(proof),
+                    Some(State::Pending) => {}
+                    None => return Err(MokshaWalletError::MissingProofState(proof.secret)),
+                }
+            }
+        }
+
+        let result = ReleasedReservations {
+            released: released.into(),
+            spent: spent.into(),
+        };
+        let now = self.clock.now();
+        let mut tx = self.localstore.begin_tx().await?;
+        for from in [ProofStatus::Reserved, ProofStatus::PendingMelt] {
+            for (proofs, to) in [
+                (&result.released, ProofStatus::Spendable),
+                (&result.spent, ProofStatus::Spent),
+            ] {
+                let secrets = proofs
+                    .secrets()
+                    .into_iter()
+                    .filter(|secret| states.get(secret) == Some(&from))
+                    .collect::<Vec<_>>();
+                self.localstore
+                    .transition_proofs(&mut tx, &secrets, from, to, now)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    pub async fn get_pending_melts(&self) -> Result<Vec<PendingMelt>, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let pending_melts = self.localstore.get_pending_melts(&mut tx).await?;
+        tx.commit().await?;
+        Ok(pending_melts)
+    }
+
+    /// Checks the proofs of pending melts with the mint, e.g. after the wallet crashed during
+    /// [`Wallet::pay_invoice`]. Unspent proofs are added to the balance again and spent proofs are
+    /// removed. Melts with proofs the mint still reports as pending are checked again next time,
+    /// disputed melts are skipped.
+    pub async fn recover_pending(&self) -> Result<ReleasedReservations, MokshaWalletError> {
+        let mut released = vec![];
+        let mut spent = vec![];
+        for pending_melt in self.get_pending_melts().await? {
+            // disputed melts have to be checked manually
+            if pending_melt.dispute.is_some() {
+                continue;
+            }
+            let id = pending_melt.id.ok_or(MokshaWalletError::IdNotSet)?;
+            let states = self.get_proof_states(&pending_melt.proofs).await?;
+            if states.contains(&State::Pending) {
+                continue;
+            }
+
+            let (unspent_proofs, spent_proofs): (Vec<_>, Vec<_>) = pending_melt
+                .proofs
+                .proofs()
+                .into_iter()
+                .zip(states)
+                .partition(|(_, state)| *state == State::Unspent);
+            let unspent_proofs: Proofs = unspent_proofs
+                .into_iter()
+                .map(|(proof, _)| proof)
+                .collect::<Vec<_>>()
+                .into();
+            let spent_proofs: Proofs = spent_proofs
+                .into_iter()
+                .map(|(proof, _)| proof)
+                .collect::<Vec<_>>()
+                .into();
+
+            let now = self.clock.now();
+            let mut tx = self.localstore.begin_tx().await?;
+            self.localstore
+                .transition_proofs(
+                    &mut tx,
+                    &unspent_proofs.secrets(),
+                    ProofStatus::PendingMelt,
+                    ProofStatus::Spendable,
+                    now,
+                )
+                .await?;
+            self.localstore
+                .transition_proofs(
+                    &mut tx,
+                    &spent_proofs.secrets(),
+                    ProofStatus::PendingMelt,
+                    ProofStatus::Spent,
+                    now,
+                )
+                .await?;
+            self.localstore.remove_pending_melt(&mut tx, id).await?;
+            tx.commit().await?;
+
+            released.extend(unspent_proofs.proofs());
+            spent.extend(spent_proofs.proofs());
+        }
+        Ok(ReleasedReservations {
+            released: released.into(),
+            spent: spent.into(),
+        })
+    }
+
+    /// Compares the proofs and keysets in the localstore with the state of the mint.
+    ///
+    /// If `fix` is set, spent proofs are removed from the localstore and keysets the mint
+    /// doesn't know anymore are marked as inactive.
+    pub async fn audit_against_mint(
+        &self,
+        mint_url: &Url,
+        fix: bool,
+    ) -> Result<AuditReport, MokshaWalletError> {
+        let mint_keyset_ids = self
+            .client
+            .get_keysets(mint_url)
+            .await?
+            .keysets
+            .into_iter()
+            .map(|k| k.id)
+            .collect::<HashSet<String>>();
+
+        let mut tx = self.localstore.begin_tx().await?;
+        let wallet_keysets = self
+            .localstore
+            .get_keysets(&mut tx)
+            .await?
+            .into_iter()
+            .filter(|k| k.mint_url == *mint_url)
+            .collect::<Vec<WalletKeyset>>();
+        let all_proofs = self.localstore.get_proofs(&mut tx).await?;
+        tx.commit().await?;
+
+        let unknown_keysets = wallet_keysets
+            .iter()
+            .filter(|k| !mint_keyset_ids.contains(&k.keyset_id.to_string()))
+            .cloned()
+            .collect::<Vec<WalletKeyset>>();
+
+        let proofs = wallet_keysets
+            .iter()
+            .flat_map(|k| all_proofs.proofs_by_keyset(&k.keyset_id).proofs())
+            .collect::<Vec<Proof>>();
+
+        let ys = proofs
+            .iter()
+            .map(|p| p.y())
+            .collect::<Result<Vec<PublicKey>, _>>()?;
+        let states = if ys.is_empty() {
+            HashMap::new()
+        } else {
+            self.client
+                .post_checkstate(mint_url, ys.clone())
+                .await?
+                .states
+                .into_iter()
+                .map(|s| (s.y, s.state))
+                .collect::<HashMap<PublicKey, State>>()
+        };
+
+        let mut confirmed_balance = 0;
+        let mut spent_proofs = vec![];
+        let mut pending_proofs = vec![];
+        for (proof, y) in proofs.into_iter().zip(ys) {
+            match states.get(&y) {
+                Some(State::Unspent) => confirmed_balance += proof.amount,
+                Some(State::Spent) => spent_proofs.push(proof),
+                Some(State::Pending) => pending_proofs.push(proof),
+                None => return Err(MokshaWalletError::MissingProofState(proof.secret)),
+            }
+        }
+        let spent_proofs: Proofs = spent_proofs.into();
+
+        if fix {
+            self.take_snapshot("audit").await?;
+            let mut tx = self.localstore.begin_tx().await?;
+            self.localstore
+                .transition_proofs(
+                    &mut tx,
+                    &spent_proofs.secrets(),
+                    ProofStatus::Spendable,
+                    ProofStatus::Spent,
+                    self.clock.now(),
+                )
+                .await?;
+            for keyset in unknown_keysets.iter().filter(|k| k.active) {
+                self.localstore
+                    .upsert_keyset(
+                        &mut tx,
+                        &WalletKeyset {
+                            active: false,
+                            ..keyset.clone()
+                        },
+                    )
+                    .await?;
+            }
+            tx.commit().await?;
+        }
+
+        Ok(AuditReport {
+            mint_url: mint_url.to_owned(),
+            confirmed_balance,
+            spent_proofs,
+            pending_proofs: pending_proofs.into(),
+            unknown_keysets,
+        })
+    }
+
+    /// Stores a copy of the spendable proofs, so they can be restored with
+    /// [`Wallet::rollback_to`]. Taken automatically before proofs are removed in a batch. Deletes
+    /// the oldest snapshots beyond the limits of the [`SnapshotConfig`]. Returns the id.
+    pub async fn take_snapshot(&self, reason: &str) -> Result<u64, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let snapshot = Snapshot {
+            id: None,
+            reason: reason.to_owned(),
+            created_at: self.clock.now(),
+            proofs: self.localstore.get_proofs(&mut tx).await?,
+        };
+        let id = self.localstore.add_snapshot(&mut tx, &snapshot).await?;
+
+        // the newest snapshots are kept
+        let mut snapshots = self.localstore.get_snapshots(&mut tx).await?;
+        snapshots.sort_by_key(|snapshot| snapshot.id);
+        let mut count = 0;
+        let mut size = 0;
+        for snapshot in snapshots.iter().rev() {
+            count += 1;
+            size += snapshot.size();
+            let keep = snapshot.id == Some(id)
+                || (count <= self.snapshot_config.max_snapshots
+                    && size <= self.snapshot_config.max_size);
+            if !keep {
+                self.localstore
+                    .delete_snapshot(&mut tx, snapshot.id.ok_or(MokshaWalletError::IdNotSet)?)
+                    .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(id)
+    }
+
+    /// Takes a snapshot if the last one is older than the interval of the [`SnapshotConfig`].
+    /// Meant to be called regularly, e.g. when the wallet is opened.
+    pub async fn take_scheduled_snapshot(&self) -> Result<Option<u64>, MokshaWalletError> {
+        let Some(interval) = self.snapshot_config.interval else {
+            return Ok(None);
+        };
+        let last = self
+            .list_snapshots()
+            .await?
+            .iter()
+            .map(|snapshot| snapshot.created_at)
+            .max();
+        match last {
+            Some(created_at) if created_at + interval > self.clock.now() => Ok(None),
+            _ => Ok(Some(self.take_snapshot("scheduled").await?)),
+        }
+    }
+
+    /// Returns the snapshots, the oldest first
+    pub async fn list_snapshots(&self) -> Result<Vec<Snapshot>, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let snapshots = self.localstore.get_snapshots(&mut tx).await?;
+        tx.commit().await?;
+        Ok(snapshots)
+    }
+
+    /// Restores the proofs of a snapshot that were removed from the localstore or marked as
+    /// spent since. Only proofs the mints report as unspent are restored, proofs that are
+    /// reserved or pending are not touched. Returns the restored proofs.
+    pub async fn rollback_to(&self, snapshot_id: u64) -> Result<Proofs, MokshaWalletError> {
+        let mut tx = self.localstore.begin_tx().await?;
+        let snapshot = self
+            .localstore
+            .get_snapshots(&mut tx)
+            .await?
+            .into_iter()
+            .find(|snapshot| snapshot.id == Some(snapshot_id))
+            .ok_or(MokshaWalletError::SnapshotNotFound(snapshot_id))?;
+        let mut stored = HashMap::new();
+        for status in ProofStatus::ALL {
+            for proof in self
+                .localstore
+                .get_proofs_by_state(&mut tx, status)
+                .await?
+                .proofs()
+            {
+                stored.insert(proof.secret, status);
+            }
+        }
+        tx.commit().await?;
+
+        let candidates: Proofs = snapshot
+            .proofs
+            .proofs()
+            .into_iter()
+            .filter(|proof| matches!(stored.get(&proof.secret), None | Some(ProofStatus::Spent)))
+            .collect::<Vec<_>>()
+            .into();
+        if candidates.is_empty() {
+            return Ok(Proofs::empty());
+        }
+        let states = self.get_proof_states(&candidates).await?;
+        let (spent, missing): (Vec<Proof>, Vec<Proof>) = candidates
+            .proofs()
+            .into_iter()
+            .zip(states)
+            .filter_map(|(proof, state)| (state == State::Unspent).then_some(proof))
+            .partition(|proof| stored.contains_key(&proof.secret));
+
+        let spent: Proofs = spent.into();
+        let missing: Proofs = missing.into();
+        let mut tx = self.localstore.begin_tx().await?;
+        self.localstore
+            .transition_proofs(
+                &mut tx,
+                &spent.secrets(),
+                ProofStatus::Spent,
+                ProofStatus::Spendable,
+                self.clock.now(),
+            )
+            .await?;
+        self.localstore.add_proofs(&mut tx, &missing).await?;
+        tx.commit().await?;
+
+        Ok(spent
+            .proofs()
+            .into_iter()
+            .chain(missing.proofs())
+            .collect::<Vec<_>>()
+            .into())
+    }
+}
+
+/// Returns a public key whose private key is discarded immediately
+fn create_throwaway_pubkey() -> Result<PublicKey, MokshaWalletError> {
+    let secret_key = SecretKey::from_slice(&rand::thread_rng().gen::<[u8; 32]>())?;
+    Ok(PublicKey::from_secret_key(&Secp256k1::new(), &secret_key))
+}
+
+fn receive_queue_backoff(attempts: u32) -> u64 {
+    RECEIVE_QUEUE_BASE_DELAY.saturating_mul(1 << attempts.saturating_sub(1).min(32))
+}
+
+fn auto_withdraw_backoff(failures: u32) -> u64 {
+    AUTO_WITHDRAW_BASE_DELAY
+        .saturating_mul(1 << failures.saturating_sub(1).min(32))
+        .min(AUTO_WITHDRAW_MAX_DELAY)
+}
+
+/// Checks that the url of a mint is http(s) without a query or fragment and adds a trailing slash
+/// to its path, so the api endpoints can be joined to it.
+pub fn normalize_mint_url(mint_url: &Url) -> Result<Url, MokshaWalletError> {
+    if !matches!(mint_url.scheme(), "http" | "https") {
+        return Err(MokshaWalletError::InvalidMintUrl(
+            mint_url.to_string(),
+            "scheme must be http or https".to_owned(),
+        ));
+    }
+    if mint_url.query().is_some() || mint_url.fragment().is_some() {
+        return Err(MokshaWalletError::InvalidMintUrl(
+            mint_url.to_string(),
+            "must not have a query or fragment".to_owned(),
+        ));
+    }
+    let mut normalized = mint_url.clone();
+    if !normalized.path().ends_with('/') {
+        normalized.set_path(&format!("{}/", mint_url.path()));
+    }
+    Ok(normalized)
+}
+
+/// Whether the requests to the mint are safe from a man in the middle: the url is https, or plain
+/// http to localhost, a private network (RFC 1918) or a tor onion service
+pub fn is_secure_mint_url(mint_url: &Url) -> bool {
+    match mint_url.scheme() {
+        "https" => true,
+        "http" => match mint_url.host() {
+            Some(Host::Domain(domain)) => {
+                let domain = domain.trim_end_matches('.');
+                domain == "localhost"
+                    || domain.ends_with(".localhost")
+                    || domain.ends_with(".onion")
+            }
+            Some(Host::Ipv4(ip)) => ip.is_loopback() || ip.is_private(),
+            Some(Host::Ipv6(ip)) => ip.is_loopback(),
+            None => false,
+        },
+        _ => false,
+    }
+}
+
+fn check_output_count(
+    outputs: &[BlindedMessage],
+    amounts: &[&SplitAmount],
+) -> Result<(), MokshaWalletError> {
+    let expected = amounts.iter().map(|amount| amount.len()).sum();
+    if outputs.len() != expected {
+        return Err(MokshaWalletError::OutputCountMismatch {
+            expected,
+            actual: outputs.len(),
+        });
+    }
+    Ok(())
+}
+
+// FIXME implement for Vec<BlindedMessage, Secretkey>
+fn blind_recovery_records(
+    mint_url: &Url,
+    outputs: &[(BlindedMessage, BlindingFactor)],
+    secrets: &[String],
+) -> Vec<BlindRecovery> {
+    outputs
+        .iter()
+        .zip(secrets)
+        .map(
+            |((blinded_message, blinding_factor), secret)| BlindRecovery {
+                mint_url: mint_url.clone(),
+                blinded_message: blinded_message.clone(),
+                secret: secret.clone(),
+                blinding_factor: blinding_factor.as_hex(),
+            },
+        )
+        .collect()
+}
+
+fn get_blinded_msg(blinded_messages: Vec<(BlindedMessage, BlindingFactor)>) -> Vec<BlindedMessage> {
+    blinded_messages
+        .into_iter()
+        .map(|(msg, _)| msg)
+        .collect::<Vec<BlindedMessage>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::collections::{HashMap, VecDeque};
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::attestation::AttestationVerifier;
+    use crate::backup::{decrypt_backup, encrypt_backup, WalletBackup, BACKUP_VERSION};
+    use crate::client::MockCashuClient;
+    use crate::clock::{Clock, MockClock};
+    use crate::error::MokshaWalletError;
+    use crate::event::WalletEvent;
+    use crate::http::CrossPlatformHttpClient;
+    use crate::lnurl::{LnurlInvoiceResponse, LnurlPayResponse};
+    use crate::localstore::memory::MemoryLocalStore;
+    use crate::localstore::sqlite::SqliteLocalStore;
+    use crate::localstore::{
+        HistoryEntry, HistoryFilter, HistoryKind, LocalStore, LocalStoreTransaction,
+        MultiPaymentStage, OperationKind, OperationRecord, PendingMint, ProofStatus,
+        QueuedReceiveStatus, TransferDirection, WalletKeyset,
+    };
+    use crate::operation::OperationId;
+    use crate::secret::{DeterministicSecret, RandomSecretGenerator, SecretGenerator};
+    use crate::stats::{Endpoint, EndpointSamples};
+    use crate::wallet::{
+        AutoWithdrawConfig, AutoWithdrawal, ReceiveQueueEvent, ReceiveReport, SnapshotConfig,
+        TokenSummary, Wallet, WalletBuilder, AUTO_WITHDRAW_BASE_DELAY, HISTORY_PAGE_SIZE,
+        MINT_CLAIM_TIMEOUT, RESTORE_GAP_LIMIT,
+    };
+
+    use futures::TryStreamExt;
+    use lightning_invoice::Bolt11Invoice as LNInvoice;
+    use moksha_core::amount::Amount;
+    use moksha_core::blind::{BlindedMessage, BlindedSignature, BlindingFactor, TotalAmount};
+    use moksha_core::dhke::Dhke;
+    use moksha_core::fixture::{read_fixture, read_fixture_as};
+    use moksha_core::keyset::{Keyset, KeysetId, Keysets, MintKeyset};
+    use moksha_core::primitives::{
+        CurrencyUnit, KeyResponse, KeysResponse, MintInfoResponse, Nuts, PaymentMethod,
+        PostCheckStateRequest, PostCheckStateResponse, PostMeltBolt11Response,
+        PostMeltQuoteBolt11Response, PostMintBolt11Response, PostMintQuoteBolt11Response,
+        PostRestoreRequest, PostRestoreResponse, PostSwapRequest, PostSwapResponse, ProofState,
+        State,
+    };
+
+    use moksha_core::error::MokshaCoreError;
+    use moksha_core::proof::{Proof, Proofs};
+    use moksha_core::token::{Token, TokenV3};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use secp256k1::{PublicKey, SecretKey};
+    use url::Url;
+
+    fn create_mock() -> MockCashuClient {
+        let keys = MintKeyset::new("mykey", "");
+        let key_response = KeyResponse {
+            keys: keys.public_keys.clone(),
+            id: keys.keyset_id.clone(),
+            unit: CurrencyUnit::Sat,
+        };
+        let keys_response = KeysResponse::new(key_response.clone());
+        let keys_by_id_response = keys_response.clone();
+        let keysets = Keysets::new(keys.keyset_id, CurrencyUnit::Sat, true);
+
+        let mut client = MockCashuClient::default();
+        client
+            .expect_get_keys()
+            .returning(move |_| Ok(keys_response.clone()));
+        client
+            .expect_get_keysets()
+            .returning(move |_| Ok(keysets.clone()));
+        client
+            .expect_get_keys_by_id()
+            .returning(move |_, _| Ok(keys_by_id_response.clone()));
+        client.expect_is_v1_supported().returning(move |_| Ok(true));
+        client
+    }
+
+    #[tokio::test]
+    async fn test_blank_blinded_messages_1000_sats() -> anyhow::Result<()> {
+        let localstore = MemoryLocalStore::new();
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let client = create_mock();
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        let result = wallet
+            .create_blank(1000.into(), &KeysetId::new("00d31cecf59d18c0")?)
+            .await;
+        assert!(result.is_ok());
+        let result = result.unwrap();
+        assert!(result.len() == 10);
+        assert!(result.first().unwrap().0.amount == 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blank_blinded_messages_zero_sats() -> anyhow::Result<()> {
+        let localstore = MemoryLocalStore::new();
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let client = create_mock();
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        let result = wallet
+            .create_blank(0.into(), &KeysetId::new("00d31cecf59d18c0")?)
+            .await;
+        assert!(result.is_ok());
+        assert!(result.unwrap().is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_blank_blinded_messages_serialize() -> anyhow::Result<()> {
+        let localstore = MemoryLocalStore::new();
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let client = create_mock();
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let result = wallet
+            .create_blank(4000.into(), &KeysetId::new("00d31cecf59d18c0")?)
+            .await?;
+        for (blinded_message, _, _) in result {
+            let out = serde_json::to_string(&blinded_message)?;
+            assert!(!out.is_empty());
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens() -> anyhow::Result<()> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
+
+        let mut client = create_mock();
+        client
+            .expect_post_mint_bolt11()
+            .returning(move |_, _, _| Ok(mint_response.clone()));
+
+        let localstore = MemoryLocalStore::new();
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let result = wallet
+            .mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+            .await?;
+        assert_eq!(20, result.total_amount());
+        result.tokens.into_iter().for_each(|t| {
+            assert_eq!(wallet_keyset.mint_url, t.mint.expect("mint is empty"));
+        });
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_not_paid_yet() -> anyhow::Result<()> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
+
+        let mut client = create_mock();
+        let attempts = Arc::new(AtomicU64::new(0));
+        let counter = attempts.clone();
+        client
+            .expect_post_mint_bolt11()
+            .times(2)
+            .returning(move |_, _, _| {
+                // the invoice is paid before the second attempt
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(MokshaWalletError::InvoiceNotPaidYet(
+                        20001,
+                        "Lightning invoice not paid yet.".to_owned(),
+                    ))
+                } else {
+                    Ok(mint_response.clone())
+                }
+            });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let result = wallet
+            .mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvoiceNotPaidYet(20001, _))
+        ));
+        assert_eq!(60, wallet.get_balance().await?);
+
+        let result = wallet
+            .mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+            .await?;
+        assert_eq!(20, result.total_amount());
+        assert_eq!(80, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_when_paid() -> anyhow::Result<()> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
+
+        let mut client = create_mock();
+        let polls = Arc::new(AtomicU64::new(0));
+        let counter = polls.clone();
+        client
+            .expect_get_mint_quote_bolt11()
+            .times(3)
+            .returning(move |_, quote| {
+                // unpaid twice, then paid
+                let paid = counter.fetch_add(1, Ordering::SeqCst) >= 2;
+                Ok(PostMintQuoteBolt11Response {
+                    quote,
+                    payment_request: INVOICE_21_SATS.to_owned(),
+                    paid,
+                    expiry: None,
+                })
+            });
+        client
+            .expect_post_mint_bolt11()
+            .times(1)
+            .returning(move |_, _, _| Ok(mint_response.clone()));
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let result = wallet
+            .mint_tokens_when_paid(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+                Duration::from_millis(10),
+                Duration::from_secs(10),
+            )
+            .await?;
+        assert_eq!(20, result.total_amount());
+        assert_eq!(3, polls.load(Ordering::SeqCst));
+        assert_eq!(80, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_when_paid_timeout() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client
+            .expect_get_mint_quote_bolt11()
+            .returning(move |_, quote| {
+                Ok(PostMintQuoteBolt11Response {
+                    quote,
+                    payment_request: INVOICE_21_SATS.to_owned(),
+                    paid: false,
+                    expiry: None,
+                })
+            });
+        client.expect_post_mint_bolt11().never();
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let result = wallet
+            .mint_tokens_when_paid(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+                Duration::from_millis(10),
+                Duration::from_millis(50),
+            )
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::MintTimeout(q)) if q == "hash"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_when_paid_reuses_outputs() -> anyhow::Result<()> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
+
+        let mut client = create_mock();
+        client
+            .expect_get_mint_quote_bolt11()
+            .returning(move |_, quote| {
+                Ok(PostMintQuoteBolt11Response {
+                    quote,
+                    payment_request: INVOICE_21_SATS.to_owned(),
+                    paid: true,
+                    expiry: None,
+                })
+            });
+        // the quote is reported as paid before the mint accepts the mint request
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        client
+            .expect_post_mint_bolt11()
+            .times(3)
+            .returning(move |_, _, outputs| {
+                let mut requests = recorded.lock().expect("poisoned");
+                requests.push(outputs.iter().map(|output| output.b_).collect::<Vec<_>>());
+                if requests.len() < 3 {
+                    Err(MokshaWalletError::InvoiceNotPaidYet(
+                        20001,
+                        "Lightning invoice not paid yet.".to_owned(),
+                    ))
+                } else {
+                    Ok(mint_response.clone())
+                }
+            });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let result = wallet
+            .mint_tokens_when_paid(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+                Duration::from_millis(10),
+                Duration::from_secs(10),
+            )
+            .await?;
+        assert_eq!(20, result.total_amount());
+        assert_eq!(80, wallet.get_balance().await?);
+        let requests = requests.lock().expect("poisoned");
+        assert!(requests.iter().all(|outputs| *outputs == requests[0]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_when_paid_dropped() -> anyhow::Result<()> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
+
+        let mut client = create_mock();
+        let paid = Arc::new(AtomicBool::new(false));
+        let quote_paid = paid.clone();
+        client
+            .expect_get_mint_quote_bolt11()
+            .returning(move |_, quote| {
+                Ok(PostMintQuoteBolt11Response {
+                    quote,
+                    payment_request: INVOICE_21_SATS.to_owned(),
+                    paid: quote_paid.load(Ordering::SeqCst),
+                    expiry: None,
+                })
+            });
+        client
+            .expect_post_mint_bolt11()
+            .times(1)
+            .returning(move |_, _, _| Ok(mint_response.clone()));
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        // the caller gives up while the quote is unpaid
+        let polling = wallet.mint_tokens_when_paid(
+            &wallet_keyset,
+            &PaymentMethod::Bolt11,
+            20.into(),
+            "hash".to_string(),
+            Duration::from_millis(10),
+            Duration::from_secs(10),
+        );
+        assert!(tokio::time::timeout(Duration::from_millis(50), polling)
+            .await
+            .is_err());
+
+        paid.store(true, Ordering::SeqCst);
+        let result = wallet
+            .mint_tokens_when_paid(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+                Duration::from_millis(10),
+                Duration::from_secs(10),
+            )
+            .await?;
+        assert_eq!(20, result.total_amount());
+        assert_eq!(80, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_invalid_signatures() -> anyhow::Result<()> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?; // 4, 16
+        let tamper = |f: fn(&mut Vec<BlindedSignature>)| {
+            let mut response = mint_response.clone();
+            f(&mut response.signatures);
+            response
+        };
+        let cases = [
+            // amount of the first output is 4
+            (tamper(|s| s[0].amount = 8), 8, 0),
+            (tamper(|s| s[1].id = "00ffffffffffffff".to_owned()), 16, 1),
+        ];
+
+        for (response, expected_amount, expected_index) in cases {
+            let mut client = create_mock();
+            client
+                .expect_post_mint_bolt11()
+                .returning(move |_, _, _| Ok(response.clone()));
+            let localstore = MemoryLocalStore::new();
+            let wallet_keyset = create_test_wallet_keyset()?;
+            let mut tx = localstore.begin_tx().await?;
+            localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+            tx.commit().await?;
+            let wallet = WalletBuilder::new()
+                .with_client(client)
+                .with_localstore(localstore)
+                .build()
+                .await?;
+
+            let result = wallet
+                .mint_tokens(
+                    &wallet_keyset,
+                    &PaymentMethod::Bolt11,
+                    20.into(),
+                    "hash".to_string(),
+                )
+                .await;
+            assert!(
+                matches!(
+                    result,
+                    Err(MokshaWalletError::InvalidSignature { amount, index })
+                        if amount == expected_amount && index == expected_index
+                ),
+                "unexpected result {result:?}"
+            );
+            assert_eq!(0, wallet.get_balance().await?);
+        }
+        Ok(())
+    }
+
+    /// Mints 20 sats from a mock mint that signs with the keyset of [`create_mock`] and attaches
+    /// DLEQ proofs, which are changed by `tamper` before the wallet receives them
+    async fn mint_20_sats_with_dleq(
+        tamper: fn(&mut Vec<BlindedSignature>),
+    ) -> anyhow::Result<TokenV3> {
+        let keys = MintKeyset::new("mykey", "");
+        let wallet_keyset = WalletKeyset::new(
+            &KeysetId::new(&keys.keyset_id)?,
+            &Url::parse("http://127.0.0.1:3338")?,
+            &CurrencyUnit::Sat,
+            0,
+            keys.public_keys.clone(),
+            true,
+        );
+        let mut client = create_mock();
+        client
+            .expect_post_mint_bolt11()
+            .returning(move |_, _, outputs| {
+                let mut signatures = sign_outputs(&keys, &outputs, true)?;
+                tamper(&mut signatures);
+                Ok(PostMintBolt11Response { signatures })
+            });
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        Ok(wallet
+            .mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+            .await?)
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_into_chosen_keyset() -> anyhow::Result<()> {
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let active = MintKeyset::new("mykey", "");
+        let chosen = MintKeyset::new("mykey", "0/0/0/1");
+        let wallet_keyset = |keys: &MintKeyset, active| -> anyhow::Result<WalletKeyset> {
+            Ok(WalletKeyset::new(
+                &KeysetId::new(&keys.keyset_id)?,
+                &mint_url,
+                &CurrencyUnit::Sat,
+                0,
+                keys.public_keys.clone(),
+                active,
+            ))
+        };
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore
+            .upsert_keyset(&mut tx, &wallet_keyset(&active, true)?)
+            .await?;
+        localstore
+            .upsert_keyset(&mut tx, &wallet_keyset(&chosen, false)?)
+            .await?;
+        tx.commit().await?;
+
+        // the first quote is signed with the chosen keyset, the second one with the active keyset
+        let mut client = create_mock();
+        let keys = chosen.clone();
+        client
+            .expect_post_mint_bolt11()
+            .withf(|_, quote, _| quote == "chosen")
+            .returning(move |_, _, outputs| {
+                Ok(PostMintBolt11Response {
+                    signatures: sign_outputs(&keys, &outputs, true)?,
+                })
+            });
+        let keys = active.clone();
+        client
+            .expect_post_mint_bolt11()
+            .withf(|_, quote, _| quote == "active")
+            .returning(move |_, _, outputs| {
+                let outputs = outputs
+                    .into_iter()
+                    .map(|output| BlindedMessage {
+                        id: keys.keyset_id.clone(),
+                        ..output
+                    })
+                    .collect::<Vec<_>>();
+                Ok(PostMintBolt11Response {
+                    signatures: sign_outputs(&keys, &outputs, true)?,
+                })
+            });
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        let chosen_keyset = wallet
+            .get_wallet_keysets()
+            .await?
+            .into_iter()
+            .find(|k| k.keyset_id.to_string() == chosen.keyset_id)
+            .expect("chosen keyset not found");
+
+        let token = wallet
+            .mint_tokens(
+                &chosen_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "chosen".to_owned(),
+            )
+            .await?;
+        assert_eq!(20, token.total_amount());
+        for proof in token.proofs().proofs() {
+            assert_eq!(chosen.keyset_id, proof.keyset_id);
+            assert!(Dhke::new().verify(
+                chosen.private_keys[&proof.amount],
+                proof.c,
+                proof.secret
+            )?);
+        }
+
+        let result = wallet
+            .mint_tokens(
+                &chosen_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "active".to_owned(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidSignature { .. })
+        ));
+        assert_eq!(20, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_dleq() -> anyhow::Result<()> {
+        let token = mint_20_sats_with_dleq(|_| {}).await?;
+        assert_eq!(20, token.total_amount());
+
+        // 4 and 16 are told apart by their amounts
+        let token = mint_20_sats_with_dleq(|signatures| {
+            signatures.reverse();
+            signatures.iter_mut().for_each(|s| s.dleq = None);
+        })
+        .await?;
+        let keys = MintKeyset::new("mykey", "");
+        for proof in token.proofs().proofs() {
+            assert!(Dhke::new().verify(keys.private_keys[&proof.amount], proof.c, proof.secret)?);
+        }
+
+        // the proofs of the signatures are swapped, they are valid, but not for these outputs
+        let result = mint_20_sats_with_dleq(|signatures| {
+            let dleq = signatures[0].dleq;
+            signatures[0].dleq = signatures[1].dleq;
+            signatures[1].dleq = dleq;
+        })
+        .await;
+        assert!(
+            matches!(
+                result
+                    .as_ref()
+                    .map_err(|e| e.downcast_ref::<MokshaWalletError>()),
+                Err(Some(MokshaWalletError::InvalidDleq {
+                    amount: 4,
+                    index: 0
+                }))
+            ),
+            "unexpected result {result:?}"
+        );
+        Ok(())
+    }
+
+    async fn mint_20_sats_with(
+        secret_generator: Arc<dyn SecretGenerator>,
+    ) -> anyhow::Result<Vec<String>> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
+        let mut client = create_mock();
+        client
+            .expect_post_mint_bolt11()
+            .returning(move |_, _, _| Ok(mint_response.clone()));
+
+        let localstore = MemoryLocalStore::new();
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .with_secret_generator(secret_generator)
+            .build()
+            .await?;
+        let result = wallet
+            .mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+            .await?;
+        Ok(result
+            .proofs()
+            .proofs()
+            .into_iter()
+            .map(|p| p.secret)
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_seeded_secret_generator() -> anyhow::Result<()> {
+        let first = mint_20_sats_with(Arc::new(RandomSecretGenerator::from_rng(
+            StdRng::seed_from_u64(21),
+        )))
+        .await?;
+        let second = mint_20_sats_with(Arc::new(RandomSecretGenerator::from_rng(
+            StdRng::seed_from_u64(21),
+        )))
+        .await?;
+        assert_eq!(2, first.len());
+        assert_eq!(first, second);
+        Ok(())
+    }
+
+    async fn create_file_localstore(
+        dir: &tempfile::TempDir,
+        wallet_keyset: &WalletKeyset,
+    ) -> anyhow::Result<SqliteLocalStore> {
+        let path = dir.path().join("wallet.db").to_string_lossy().to_string();
+        let localstore = SqliteLocalStore::with_path(path).await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, wallet_keyset).await?;
+        tx.commit().await?;
+        Ok(localstore)
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_concurrent_same_quote() -> anyhow::Result<()> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
+        let mut client = create_mock();
+        client
+            .expect_post_mint_bolt11()
+            .times(1)
+            .returning(move |_, _, _| Ok(mint_response.clone()));
+
+        let dir = tempfile::tempdir()?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = create_file_localstore(&dir, &wallet_keyset).await?;
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let (first, second) = tokio::join!(
+            wallet.mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            ),
+            wallet.mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+        );
+        assert_eq!(first?, second?);
+        assert_eq!(20, wallet.get_balance().await?);
+        assert_eq!(2, wallet.get_proofs().await?.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_resumes_interrupted_attempt() -> anyhow::Result<()> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
+        let sent_outputs = Arc::new(std::sync::Mutex::new(Vec::<Vec<PublicKey>>::new()));
+        let mut client = create_mock();
+        let sent = sent_outputs.clone();
+        client
+            .expect_post_mint_bolt11()
+            .times(1)
+            .returning(move |_, _, outputs| {
+                sent.lock()
+                    .unwrap()
+                    .push(outputs.iter().map(|o| o.b_).collect());
+                Err(MokshaWalletError::UnexpectedResponse("timeout".to_owned()))
+            });
+        let sent = sent_outputs.clone();
+        client
+            .expect_post_mint_bolt11()
+            .times(1)
+            .returning(move |_, _, outputs| {
+                sent.lock()
+                    .unwrap()
+                    .push(outputs.iter().map(|o| o.b_).collect());
+                Ok(mint_response.clone())
+            });
+
+        let dir = tempfile::tempdir()?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = create_file_localstore(&dir, &wallet_keyset).await?;
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint = || {
+            wallet.mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+        };
+        assert!(mint().await.is_err());
+        assert_eq!(0, wallet.get_balance().await?);
+        assert_eq!(20, mint().await?.total_amount());
+        assert_eq!(20, wallet.get_balance().await?);
+
+        let sent_outputs = sent_outputs.lock().unwrap();
+        assert_eq!(2, sent_outputs.len());
+        assert_eq!(sent_outputs[0], sent_outputs[1]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_empty_signatures() -> anyhow::Result<()> {
+        let mint_response =
+            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
+        let mut client = create_mock();
+        client
+            .expect_post_mint_bolt11()
+            .times(1)
+            .returning(|_, _, _| Ok(PostMintBolt11Response { signatures: vec![] }));
+        client
+            .expect_post_mint_bolt11()
+            .times(1)
+            .returning(move |_, _, _| Ok(mint_response.clone()));
+
+        let dir = tempfile::tempdir()?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = create_file_localstore(&dir, &wallet_keyset).await?;
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let mint = || {
+            wallet.mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+        };
+        let result = mint().await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::MintResponseLengthMismatch {
+                expected: 2,
+                got: 0
+            })
+        ));
+        assert_eq!(0, wallet.get_balance().await?);
+
+        // the quote stays pending, so minting can be retried
+        let mut tx = wallet.localstore.begin_tx().await?;
+        let pending_mint = wallet.localstore.get_pending_mint(&mut tx, "hash").await?;
+        tx.commit().await?;
+        let pending_mint = pending_mint.expect("pending mint is kept");
+        assert_eq!(None, pending_mint.claimed_at);
+        assert!(pending_mint.proofs.is_none());
+
+        assert_eq!(20, mint().await?.total_amount());
+        assert_eq!(20, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_claimed_by_other_process() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = create_file_localstore(&dir, &wallet_keyset).await?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore
+            .add_pending_mint(
+                &mut tx,
+                &PendingMint {
+                    quote_id: "hash".to_owned(),
+                    mint_url: wallet_keyset.mint_url.clone(),
+                    keyset_id: wallet_keyset.keyset_id.to_string(),
+                    outputs: vec![],
+                    claimed_at: Some(1_000),
+                    proofs: None,
+                },
+            )
+            .await?;
+        tx.commit().await?;
+
+        let now = Arc::new(AtomicU64::new(1_000 + MINT_CLAIM_TIMEOUT));
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .with_clock(create_mock_clock(now))
+            .build()
+            .await?;
+
+        let result = wallet
+            .mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::MintInProgress(quote)) if quote == "hash"
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_seed_words_derive_same_secrets() -> anyhow::Result<()> {
+        let seed_words =
+            "half depart obvious quality work element tank gorilla view sugar picture humble";
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut sequences = vec![];
+        for _ in 0..2 {
+            let localstore = MemoryLocalStore::new();
+            let mut tx = localstore.begin_tx().await?;
+            localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+            tx.commit().await?;
+            let wallet = WalletBuilder::new()
+                .with_client(create_mock())
+                .with_localstore(localstore)
+                .with_seed_words(seed_words)
+                .build()
+                .await?;
+
+            let mut secrets = wallet.create_secrets(&wallet_keyset.keyset_id, 2).await?;
+            secrets.extend(wallet.create_secrets(&wallet_keyset.keyset_id, 3).await?);
+            sequences.push(
+                secrets
+                    .into_iter()
+                    .map(|(secret, blinding_factor)| (secret, blinding_factor.as_hex()))
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(5, sequences[0].len());
+        assert_eq!(sequences[0], sequences[1]);
+        // the counter is bumped with every issuance
+        assert_eq!(5, HashSet::<_>::from_iter(&sequences[0]).len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_seed_words_mismatch() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = create_file_localstore(&dir, &wallet_keyset).await?;
+        let _ = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore.clone())
+            .build()
+            .await?;
+
+        let result = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .with_seed_words(DeterministicSecret::generate_random_seed_words()?)
+            .build()
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::SeedMismatch)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wallet_id() -> anyhow::Result<()> {
+        let seed_words =
+            "half depart obvious quality work element tank gorilla view sugar picture humble";
+        let mut ids = vec![];
+        for seed_words in [
+            seed_words.to_owned(),
+            seed_words.to_owned(),
+            DeterministicSecret::generate_random_seed_words()?,
+        ] {
+            let wallet = WalletBuilder::new()
+                .with_client(create_mock())
+                .with_localstore(MemoryLocalStore::new())
+                .with_seed_words(seed_words)
+                .build()
+                .await?;
+            ids.push(wallet.wallet_id());
+        }
+        assert_eq!(ids[0], ids[1]);
+        assert_ne!(ids[0], ids[2]);
+        assert_eq!(64, ids[0].len());
+        assert!(!ids[0].contains(seed_words));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_tokens_rejects_weak_secrets() -> anyhow::Result<()> {
+        struct ShortSecrets;
+        impl SecretGenerator for ShortSecrets {
+            fn generate(
+                &self,
+                _keyset_id: &KeysetId,
+                start: u32,
+                count: u32,
+            ) -> Result<Vec<(String, BlindingFactor)>, MokshaWalletError> {
+                Ok((start..start + count)
+                    .map(|i| {
+                        let key = SecretKey::from_slice(&[i as u8 + 1; 32]).expect("valid key");
+                        (format!("secret{i}"), key.into())
+                    })
+                    .collect())
+            }
+        }
+
+        let result = mint_20_sats_with(Arc::new(ShortSecrets)).await;
+        assert!(matches!(
+            result
+                .expect_err("weak secrets must be rejected")
+                .downcast_ref::<MokshaWalletError>(),
+            Some(MokshaWalletError::InvalidSecret(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_is_secure_mint_url() -> anyhow::Result<()> {
+        let cases = [
+            ("https://mint.example.com", true),
+            ("https://8.8.8.8:3338", true),
+            ("http://localhost:3338", true),
+            ("http://mint.localhost", true),
+            ("http://127.0.0.1:3338", true),
+            ("http://[::1]:3338", true),
+            ("http://10.0.0.5", true),
+            ("http://172.16.1.1", true),
+            ("http://192.168.1.10:3338", true),
+            ("http://mintabcdefghijklmnop.onion", true),
+            ("http://mint.example.com", false),
+            ("http://8.8.8.8:3338", false),
+            ("http://172.32.0.1", false),
+            ("http://[2001:db8::1]", false),
+            ("http://onion.example.com", false),
+            ("ftp://127.0.0.1", false),
+        ];
+        for (url, secure) in cases {
+            assert_eq!(
+                secure,
+                super::is_secure_mint_url(&Url::parse(url)?),
+                "{url}"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_mint_url() -> anyhow::Result<()> {
+        let normalize =
+            |url: &str| -> anyhow::Result<_> { Ok(super::normalize_mint_url(&Url::parse(url)?)) };
+
+        assert_eq!(
+            "https://mint.example.com/api/",
+            normalize("https://mint.example.com/api")??.as_str()
+        );
+        assert_eq!(
+            "http://127.0.0.1:3338/",
+            normalize("http://127.0.0.1:3338")??.as_str()
+        );
+        assert_eq!(
+            "https://mint.example.com/api/",
+            normalize("https://mint.example.com/api/")??.as_str()
+        );
+        assert!(matches!(
+            normalize("ftp://mint.example.com/")?,
+            Err(MokshaWalletError::InvalidMintUrl(_, _))
+        ));
+        assert!(matches!(
+            normalize("https://mint.example.com/api?token=1")?,
+            Err(MokshaWalletError::InvalidMintUrl(_, _))
+        ));
+        assert!(matches!(
+            normalize("https://mint.example.com/api#keys")?,
+            Err(MokshaWalletError::InvalidMintUrl(_, _))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_output_count() -> anyhow::Result<()> {
+        let b_ = Dhke::hash_to_curve(b"output")?;
+        let outputs = [8, 16, 8]
+            .into_iter()
+            .map(|amount| BlindedMessage {
+                amount,
+                b_,
+                id: "00d31cecf59d18c0".to_owned(),
+            })
+            .collect::<Vec<_>>();
+
+        // 24 (8 + 16) and 40 (8 + 32) need four outputs
+        let result =
+            super::check_output_count(&outputs, &[&Amount(24).split(), &Amount(40).split()]);
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::OutputCountMismatch {
+                expected: 4,
+                actual: 3
+            })
+        ));
+        assert!(
+            super::check_output_count(&outputs, &[&Amount(24).split(), &Amount(8).split()]).is_ok()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap() -> anyhow::Result<()> {
+        let split_response = read_fixture_as::<PostSwapResponse>("post_swap_response_24_40.json")?;
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(move |_, _, _| Ok(split_response.clone()));
+        let localstore = MemoryLocalStore::new();
+        let keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens = read_fixture("token_64.cashu")?.try_into()?;
+        let result = wallet.swap_tokens(&keyset, &tokens, 40.into()).await?;
+
+        let first = result.0;
+
+        assert_eq!(CurrencyUnit::Sat, first.clone().currency_unit.unwrap());
+        assert_eq!(24, first.total_amount());
+
+        let second = result.1;
+
+        assert_eq!(CurrencyUnit::Sat, second.clone().currency_unit.unwrap());
+        assert_eq!(40, second.total_amount());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_zero_and_full_amount() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .times(2)
+            .returning(|_, _, outputs| {
+                // a single output of 64, never an output group of zero
+                assert_eq!(
+                    vec![64],
+                    outputs.iter().map(|o| o.amount).collect::<Vec<_>>()
+                );
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        let (wallet, keyset) = create_wallet_with_proofs(client).await?;
+        let tokens: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+
+        let (first, second) = wallet.swap_tokens(&keyset, &tokens, 0.into()).await?;
+        assert_eq!(64, first.total_amount());
+        assert!(second.proofs().is_empty());
+
+        let (first, second) = wallet.swap_tokens(&keyset, &tokens, 64.into()).await?;
+        assert!(first.proofs().is_empty());
+        assert_eq!(64, second.total_amount());
+
+        // the amount is checked before anything is sent to the mint
+        let result = wallet.swap_tokens(&keyset, &tokens, 100.into()).await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::NotEnoughTokens {
+                needed: 100,
+                available: 64
+            })
+        ));
+        Ok(())
+    }
+
+    /// Signs the outputs with the keys of `keys` and attaches DLEQ proofs if `with_dleq` is set
+    fn sign_outputs(
+        keys: &MintKeyset,
+        outputs: &[BlindedMessage],
+        with_dleq: bool,
+    ) -> Result<Vec<BlindedSignature>, MokshaCoreError> {
+        let dhke = Dhke::new();
+        outputs
+            .iter()
+            .map(|output| {
+                let key = &keys.private_keys[&output.amount];
+                let c_ = dhke.step2_bob(output.b_, key)?;
+                Ok(BlindedSignature {
+                    amount: output.amount,
+                    c_,
+                    id: output.id.clone(),
+                    dleq: with_dleq
+                        .then(|| dhke.create_dleq(output.b_, c_, key))
+                        .transpose()?,
+                })
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_swap_signatures_in_reversed_order() -> anyhow::Result<()> {
+        let keys = MintKeyset::new("mykey", "");
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&keys.keyset_id)?,
+            &Url::parse("http://127.0.0.1:3338")?,
+            &CurrencyUnit::Sat,
+            0,
+            keys.public_keys.clone(),
+            true,
+        );
+        let mut client = create_mock();
+        let signing_keys = keys.clone();
+        client.expect_post_swap().returning(move |_, _, outputs| {
+            let mut signatures = sign_outputs(&signing_keys, &outputs, true)?;
+            signatures.reverse();
+            Ok(PostSwapResponse {
+                signatures,
+                input_fee: None,
+            })
+        });
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens = read_fixture("token_64.cashu")?.try_into()?;
+        // 8 + 16 and 8 + 32, the DLEQ proofs tell the outputs of 8 apart
+        let (first, second) = wallet.swap_tokens(&keyset, &tokens, 40.into()).await?;
+        assert_eq!(24, first.total_amount());
+        assert_eq!(40, second.total_amount());
+
+        let dhke = Dhke::new();
+        for proof in first
+            .proofs()
+            .proofs()
+            .into_iter()
+            .chain(second.proofs().proofs())
+        {
+            assert!(dhke.verify(keys.private_keys[&proof.amount], proof.c, proof.secret)?);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_fetches_keys_of_older_keyset() -> anyhow::Result<()> {
+        let old_keys = MintKeyset::new("mykey", "0/0/0/1");
+        let keys = MintKeyset::new("mykey", "");
+        let mint_url = Url::parse("http://127.0.0.1:3338")?;
+        let keyset = WalletKeyset::new(
+            &KeysetId::new(&keys.keyset_id)?,
+            &mint_url,
+            &CurrencyUnit::Sat,
+            0,
+            keys.public_keys.clone(),
+            true,
+        );
+        let mut client = MockCashuClient::default();
+        let signing_keys = old_keys.clone();
+        client.expect_post_swap().returning(move |_, _, outputs| {
+            // the mint signs with its older keyset
+            let outputs = outputs
+                .into_iter()
+                .map(|output| BlindedMessage {
+                    id: signing_keys.keyset_id.clone(),
+                    ..output
+                })
+                .collect::<Vec<_>>();
+            Ok(PostSwapResponse {
+                signatures: sign_outputs(&signing_keys, &outputs, true)?,
+                input_fee: None,
+            })
+        });
+        let old_public_keys = old_keys.public_keys.clone();
+        let old_keyset_id = old_keys.keyset_id.clone();
+        client
+            .expect_get_keys_by_id()
+            .times(1)
+            .withf(move |_, keyset_id| keyset_id == &old_keyset_id)
+            .returning(move |_, keyset_id| {
+                Ok(KeysResponse::new(KeyResponse {
+                    keys: old_public_keys.clone(),
+                    id: keyset_id,
+                    unit: CurrencyUnit::Sat,
+                }))
+            });
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset).await?;
+        tx.commit().await?;
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let tokens = read_fixture("token_64.cashu")?.try_into()?;
+        let (first, second) = wallet.swap_tokens(&keyset, &tokens, 40.into()).await?;
+        assert_eq!(64, first.total_amount() + second.total_amount());
+        let dhke = Dhke::new();
+        for proof in first
+            .proofs()
+            .proofs()
+            .into_iter()
+            .chain(second.proofs().proofs())
+        {
+            assert_eq!(old_keys.keyset_id, proof.keyset_id);
+            assert!(dhke.verify(old_keys.private_keys[&proof.amount], proof.c, proof.secret)?);
+        }
+
+        // the fetched keyset is stored, the keys are not fetched again
+        let old_keyset = wallet
+            .keys_for_keyset(&mint_url, &old_keys.keyset_id)
+            .await?;
+        assert_eq!(old_keys.public_keys, old_keyset.public_keys);
+        assert!(!old_keyset.active);
+        let current_keyset = wallet.keys_for_keyset(&mint_url, &keys.keyset_id).await?;
+        assert_eq!(keys.public_keys, current_keyset.public_keys);
+        assert!(current_keyset.active);
+        assert_eq!(2, wallet.get_wallet_keysets().await?.len());
+        Ok(())
+    }
+
+    fn proofs_for_keyset(wallet_keyset: &WalletKeyset) -> anyhow::Result<Proofs> {
+        let fixture: TokenV3 = read_fixture("token_60.cashu")?.try_into()?; // 60 tokens (4,8,16,32)
+        Ok(rekey_proofs(&fixture.proofs(), wallet_keyset))
+    }
+
+    fn rekey_proofs(proofs: &Proofs, wallet_keyset: &WalletKeyset) -> Proofs {
+        proofs
+            .proofs()
+            .into_iter()
+            .map(|p| Proof {
+                keyset_id: wallet_keyset.keyset_id.to_string(),
+                ..p
+            })
+            .collect::<Vec<Proof>>()
+            .into()
+    }
+
+    fn expect_all_unspent(client: &mut MockCashuClient) {
+        client.expect_post_checkstate().returning(|_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: State::Unspent,
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+    }
+
+    #[tokio::test]
+    async fn test_plan_split() -> anyhow::Result<()> {
+        let (wallet, keyset) = create_wallet_with_proofs(create_mock()).await?;
+        let tokens: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+
+        let plan = wallet.plan_split(&keyset, &tokens, 20).await?;
+        let first = super::get_blinded_msg(plan.first_outputs.clone());
+        let second = super::get_blinded_msg(plan.second_outputs.clone());
+        assert_eq!(44, first.total_amount());
+        assert_eq!(20, second.total_amount());
+        assert_eq!(first.len(), plan.first_secrets.len());
+        assert_eq!(second.len(), plan.second_secrets.len());
+
+        // planning doesn't reserve the secrets
+        let again = wallet.plan_split(&keyset, &tokens, 20).await?;
+        assert_eq!(
+            plan.second_secrets
+                .iter()
+                .map(|(secret, _)| secret)
+                .collect::<Vec<_>>(),
+            again
+                .second_secrets
+                .iter()
+                .map(|(secret, _)| secret)
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_uses_plan() -> anyhow::Result<()> {
+        let sent_outputs = Arc::new(std::sync::Mutex::new(Vec::<PublicKey>::new()));
+        let mut client = create_mock();
+        let sent = sent_outputs.clone();
+        client
+            .expect_post_swap()
+            .times(1)
+            .returning(move |_, _, outputs| {
+                sent.lock().unwrap().extend(outputs.iter().map(|o| o.b_));
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        let (wallet, keyset) = create_wallet_with_proofs(client).await?;
+        let tokens: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+
+        let plan = wallet.plan_split(&keyset, &tokens, 20).await?;
+        let (first, second) = wallet.swap_tokens(&keyset, &tokens, 20.into()).await?;
+        assert_eq!(44, first.total_amount());
+        assert_eq!(20, second.total_amount());
+        assert_eq!(
+            [plan.first_outputs, plan.second_outputs]
+                .concat()
+                .iter()
+                .map(|(msg, _)| msg.b_)
+                .collect::<Vec<_>>(),
+            *sent_outputs.lock().unwrap()
+        );
+        Ok(())
+    }
+
+    async fn create_wallet_with_proofs(
+        client: MockCashuClient,
+    ) -> anyhow::Result<(Wallet<MemoryLocalStore, MockCashuClient>, WalletKeyset)> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&wallet_keyset)?)
+            .await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        Ok((wallet, wallet_keyset))
+    }
+
+    #[tokio::test]
+    async fn test_get_active_keyset() -> anyhow::Result<()> {
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+        let active = wallet
+            .get_active_keyset(&Url::parse("http://127.0.0.1:3338")?)
+            .await?;
+        assert_eq!(wallet_keyset.keyset_id, active.keyset_id);
+        assert_eq!(wallet_keyset.mint_url, active.mint_url);
+
+        let result = wallet
+            .get_active_keyset(&Url::parse("http://127.0.0.1:3339")?)
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::UnknownMint(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_backup_roundtrip() -> anyhow::Result<()> {
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+        let backup = wallet.export_backup("secret password").await?;
+
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+        let restored = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        assert_eq!(
+            60,
+            restored
+                .import_backup(&backup, "secret password", false)
+                .await?
+        );
+        assert_eq!(60, restored.get_balance().await?);
+        assert_eq!(wallet.get_proofs().await?, restored.get_proofs().await?);
+
+        // importing the same backup again doesn't add any proofs
+        assert_eq!(
+            0,
+            restored
+                .import_backup(&backup, "secret password", false)
+                .await?
+        );
+        assert_eq!(60, restored.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_backup_invalid() -> anyhow::Result<()> {
+        let (wallet, _) = create_wallet_with_proofs(create_mock()).await?;
+        let data = wallet.export_backup("secret password").await?;
+        let mut backup: WalletBackup =
+            serde_json::from_str(&decrypt_backup(&data, "secret password")?)?;
+
+        backup.version = BACKUP_VERSION + 1;
+        let data = encrypt_backup(&serde_json::to_string(&backup)?, "secret password")?;
+        let result = wallet.import_backup(&data, "secret password", false).await;
+        assert!(matches!(result, Err(MokshaWalletError::InvalidBackup(_))));
+
+        backup.version = BACKUP_VERSION;
+        backup.mints[0].keysets.clear();
+        let data = encrypt_backup(&serde_json::to_string(&backup)?, "secret password")?;
+        let result = wallet.import_backup(&data, "secret password", false).await;
+        assert!(matches!(result, Err(MokshaWalletError::InvalidBackup(_))));
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_backup_unencrypted_v1() -> anyhow::Result<()> {
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+        let data = wallet.export_backup("secret password").await?;
+        let mut backup: WalletBackup =
+            serde_json::from_str(&decrypt_backup(&data, "secret password")?)?;
+        backup.version = 1;
+        let v1 = serde_json::to_string(&backup)?;
+
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+        let restored = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        // the password is not needed
+        assert_eq!(60, restored.import_backup(v1.as_bytes(), "", false).await?);
+        assert_eq!(60, restored.get_balance().await?);
+
+        // newer versions are always encrypted
+        backup.version = BACKUP_VERSION;
+        let result = restored
+            .import_backup(serde_json::to_string(&backup)?.as_bytes(), "", false)
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::InvalidBackup(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_backup_wrong_password() -> anyhow::Result<()> {
+        let (wallet, _) = create_wallet_with_proofs(create_mock()).await?;
+        let backup = wallet.export_backup("secret password").await?;
+
+        let result = wallet.import_backup(&backup, "wrong password", false).await;
+        assert!(matches!(result, Err(MokshaWalletError::InvalidBackup(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_backup_corrupted() -> anyhow::Result<()> {
+        let (wallet, _) = create_wallet_with_proofs(create_mock()).await?;
+        let mut backup = wallet.export_backup("secret password").await?;
+        let middle = backup.len() / 2;
+        backup[middle] ^= 0xff;
+
+        let result = wallet
+            .import_backup(&backup, "secret password", false)
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::InvalidBackup(_))));
+
+        let result = wallet
+            .import_backup(&backup[..middle], "secret password", false)
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::InvalidBackup(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_backup_other_mint() -> anyhow::Result<()> {
+        // proofs of the keyset that the mock client returns for every mint
+        let mint_keyset = WalletKeyset {
+            keyset_id: KeysetId::new(&MintKeyset::new("mykey", "").keyset_id)?,
+            ..create_test_wallet_keyset()?
+        };
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &mint_keyset).await?;
+        localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&mint_keyset)?)
+            .await?;
+        tx.commit().await?;
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        let backup = wallet.export_backup("secret password").await?;
+
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore
+            .upsert_keyset(
+                &mut tx,
+                &WalletKeyset {
+                    mint_url: Url::parse("https://other-mint.example.com")?,
+                    ..create_test_wallet_keyset()?
+                },
+            )
+            .await?;
+        tx.commit().await?;
+        let other = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let result = other.import_backup(&backup, "secret password", false).await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::BackupFromOtherMint(_))
+        ));
+        assert_eq!(0, other.get_balance().await?);
+
+        assert_eq!(
+            60,
+            other
+                .import_backup(&backup, "secret password", true)
+                .await?
+        );
+        assert_eq!(60, other.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_balance_attestation() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        expect_all_unspent(&mut client);
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let attestation = wallet
+            .export_balance_attestation(&wallet_keyset.mint_url, 20)
+            .await?;
+        assert!(attestation.total_amount() >= 20);
+        // the proofs stay in the wallet
+        assert_eq!(60, wallet.get_balance().await?);
+
+        let mut verifier = AttestationVerifier::new(600);
+        assert_eq!(
+            attestation.total_amount(),
+            wallet
+                .verify_balance_attestation(&mut verifier, &attestation)
+                .await?
+        );
+
+        // the same attestation is only accepted once
+        let result = wallet
+            .verify_balance_attestation(&mut verifier, &attestation)
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidAttestation(_))
+        ));
+
+        let result = wallet
+            .export_balance_attestation(&wallet_keyset.mint_url, 61)
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::NotEnoughTokens {
+                needed: 61,
+                available: 60
+            })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_balance_attestation_spent() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_checkstate().returning(|_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, y)| ProofState {
+                        y,
+                        state: if i == 0 { State::Spent } else { State::Unspent },
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let attestation = wallet
+            .export_balance_attestation(&wallet_keyset.mint_url, 60)
+            .await?;
+        let result = wallet
+            .verify_balance_attestation(&mut AttestationVerifier::new(600), &attestation)
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidAttestation(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_mint_mismatch() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_swap().never();
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let foreign_token = TokenV3::new(Token {
+            mint: Some(Url::parse("https://other-mint.example.com")?),
+            proofs: proofs_for_keyset(&wallet_keyset)?,
+        });
+        let result = wallet.receive_tokens(&wallet_keyset, &foreign_token).await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::UnsupportedMint(ref url))
+                if url == "https://other-mint.example.com/"
+        ));
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_trailing_slash() -> anyhow::Result<()> {
+        let wallet_keyset = WalletKeyset {
+            mint_url: Url::parse("https://mint.example.com/cashu/")?,
+            ..create_test_wallet_keyset()?
+        };
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .times(1)
+            .returning(|_, _, outputs| {
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let token = TokenV3::new(Token {
+            mint: Some(Url::parse("https://mint.example.com/cashu")?),
+            proofs: proofs_for_keyset(&wallet_keyset)?,
+        });
+        assert_eq!(
+            60,
+            wallet
+                .receive_tokens(&wallet_keyset, &token)
+                .await?
+                .total_amount
+        );
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_unknown_keyset() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .times(1)
+            .returning(|_, _, outputs| {
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+        let token_of_keyset = |keyset_id: &str| -> anyhow::Result<TokenV3> {
+            Ok(TokenV3::new(Token {
+                mint: Some(wallet_keyset.mint_url.clone()),
+                proofs: proofs_for_keyset(&WalletKeyset {
+                    keyset_id: KeysetId::new(keyset_id)?,
+                    ..wallet_keyset.clone()
+                })?,
+            }))
+        };
+
+        // neither known to the wallet nor listed by the mint
+        let result = wallet
+            .receive_tokens(&wallet_keyset, &token_of_keyset("00ffffffffffffff")?)
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::KeysetNotFound(_))));
+        assert_eq!(60, wallet.get_balance().await?);
+
+        // not known to the wallet yet, but listed by the mint
+        let mint_keyset_id = MintKeyset::new("mykey", "").keyset_id;
+        let summary = wallet
+            .receive_tokens(&wallet_keyset, &token_of_keyset(&mint_keyset_id)?)
+            .await?;
+        assert_eq!(60, summary.total_amount);
+        assert_eq!(120, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_tokens_insecure_mint() -> anyhow::Result<()> {
+        let wallet_keyset = WalletKeyset {
+            mint_url: Url::parse("http://mint.example.com/")?,
+            ..create_test_wallet_keyset()?
+        };
+        let token = TokenV3::new(Token {
+            mint: Some(wallet_keyset.mint_url.clone()),
+            proofs: proofs_for_keyset(&wallet_keyset)?,
+        });
+
+        for allow_insecure_mints in [false, true] {
+            let mut client = create_mock();
+            client
+                .expect_post_swap()
+                .times(usize::from(allow_insecure_mints))
+                .returning(|_, _, outputs| {
+                    Ok(PostSwapResponse {
+                        signatures: outputs.iter().map(sign_blinded_message).collect(),
+                        input_fee: None,
+                    })
+                });
+            let localstore = MemoryLocalStore::new();
+            let mut tx = localstore.begin_tx().await?;
+            localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+            tx.commit().await?;
+            let wallet = WalletBuilder::new()
+                .with_client(client)
+                .with_localstore(localstore)
+                .with_allow_insecure_mints(allow_insecure_mints)
+                .build()
+                .await?;
+
+            let result = wallet.receive_tokens(&wallet_keyset, &token).await;
+            if allow_insecure_mints {
+                assert_eq!(60, result?.total_amount);
+                assert_eq!(60, wallet.get_balance().await?);
+            } else {
+                assert!(matches!(
+                    result,
+                    Err(MokshaWalletError::InsecureMintUrl(ref url))
+                        if url == "http://mint.example.com/"
+                ));
+                assert_eq!(0, wallet.get_balance().await?);
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_add_mint_keysets_insecure_mint() -> anyhow::Result<()> {
+        let mint_url = Url::parse("http://mint.example.com")?;
+        for allow_insecure_mints in [false, true] {
+            let mut client = MockCashuClient::default();
+            client
+                .expect_is_v1_supported()
+                .times(usize::from(allow_insecure_mints))
+                .returning(|_| Ok(false));
+            let wallet = WalletBuilder::new()
+                .with_client(client)
+                .with_localstore(MemoryLocalStore::new())
+                .with_allow_insecure_mints(allow_insecure_mints)
+                .build()
+                .await?;
+
+            let result = wallet.add_mint_keysets(&mint_url).await;
+            if allow_insecure_mints {
+                // the url is accepted, the request to the mint fails
+                assert!(matches!(
+                    result,
+                    Err(MokshaWalletError::UnsupportedApiVersion)
+                ));
+            } else {
+                assert!(matches!(result, Err(MokshaWalletError::InsecureMintUrl(_))));
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_redeem_fee() -> anyhow::Result<()> {
+        let wallet_keyset = WalletKeyset {
+            input_fee_ppk: 300,
+            ..create_test_wallet_keyset()?
+        };
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let mut client = MockCashuClient::default();
+        client.expect_get_keysets().returning(|_| {
+            Ok(Keysets {
+                keysets: vec![Keyset {
+                    id: "00ffd48b8f5ecf80".to_owned(),
+                    unit: CurrencyUnit::Sat,
+                    active: true,
+                    input_fee_ppk: 100,
+                    final_expiry: None,
+                }],
+            })
+        });
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        // 4 proofs * 300 ppk = 1.2 sats, rounded up
+        let token = TokenV3::new(Token {
+            mint: Some(wallet_keyset.mint_url.clone()),
+            proofs: proofs_for_keyset(&wallet_keyset)?,
+        });
+        assert_eq!(2, wallet.redeem_fee(&token).await?);
+
+        // the fee of the unknown keyset is fetched from the mint: 4 proofs * 100 ppk
+        let unknown_keyset = WalletKeyset::new(
+            &KeysetId::new("00ffd48b8f5ecf80")?,
+            &wallet_keyset.mint_url,
+            &CurrencyUnit::Sat,
+            0,
+            HashMap::new(),
+            true,
+        );
+        let token = TokenV3::new(Token {
+            mint: Some(wallet_keyset.mint_url.clone()),
+            proofs: proofs_for_keyset(&unknown_keyset)?,
+        });
+        assert_eq!(1, wallet.redeem_fee(&token).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_exact_amount() -> anyhow::Result<()> {
+        // no swap is needed, so the mock doesn't expect any call to the mint
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+
+        let token = wallet.send_tokens(&wallet_keyset, 28).await?;
+        assert_eq!(28, token.total_amount());
+        assert_eq!(Some(wallet_keyset.mint_url.clone()), token.mint());
+        assert_eq!(32, wallet.get_balance().await?);
+
+        // the proofs are kept until the receiver has redeemed them
+        let mut tx = wallet.localstore.begin_tx().await?;
+        let pending = wallet
+            .localstore
+            .get_proofs_by_state(&mut tx, ProofStatus::PendingSend)
+            .await?;
+        tx.commit().await?;
+        assert_eq!(
+            HashSet::<String>::from_iter(token.proofs().secrets()),
+            HashSet::from_iter(pending.secrets())
+        );
+
+        let serialized = token.serialize()?;
+        assert_eq!(token.canonicalize(), TokenV3::deserialize(serialized)?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_serialization_independent_of_selection() -> anyhow::Result<()> {
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+
+        // same proofs, stored in reverse order
+        let localstore = MemoryLocalStore::new();
+        let mut proofs = proofs_for_keyset(&wallet_keyset)?.proofs();
+        proofs.reverse();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        localstore.add_proofs(&mut tx, &proofs.into()).await?;
+        tx.commit().await?;
+        let other_wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let token = wallet.send_tokens(&wallet_keyset, 60).await?;
+        let other_token = other_wallet.send_tokens(&wallet_keyset, 60).await?;
+        assert_eq!(token.serialize()?, other_token.serialize()?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_exact_proofs() -> anyhow::Result<()> {
+        // the mock doesn't expect any call to the mint
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+
+        let proofs = wallet
+            .exact_proofs(&wallet_keyset, 44)
+            .await?
+            .expect("proofs not found");
+        assert_eq!(44, proofs.total_amount());
+        assert_eq!(
+            HashSet::from([32, 8, 4]),
+            proofs
+                .proofs()
+                .iter()
+                .map(|p| p.amount)
+                .collect::<HashSet<_>>()
+        );
+
+        // the proofs are only selected, not sent
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_exact_proofs_needs_swap() -> anyhow::Result<()> {
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+
+        assert!(wallet.exact_proofs(&wallet_keyset, 21).await?.is_none());
+        assert!(wallet.exact_proofs(&wallet_keyset, 61).await?.is_none());
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_with_change() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let token = wallet.send_tokens(&wallet_keyset, 21).await?;
+        assert_eq!(21, token.total_amount());
+        assert_eq!(39, wallet.get_balance().await?);
+
+        // the swapped inputs are spent, the sent outputs are not stored
+        let mut tx = wallet.localstore.begin_tx().await?;
+        let spent = wallet
+            .localstore
+            .get_proofs_by_state(&mut tx, ProofStatus::Spent)
+            .await?;
+        let pending = wallet
+            .localstore
+            .get_proofs_by_state(&mut tx, ProofStatus::PendingSend)
+            .await?;
+        tx.commit().await?;
+        assert_eq!(24, spent.total_amount());
+        assert!(pending.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_failed_swap_keeps_proofs() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client
+            .expect_post_swap()
+            .returning(|_, _, _| Err(MokshaWalletError::MintError(0, "mint offline".to_owned())));
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let result = wallet.send_tokens(&wallet_keyset, 21).await;
+        assert!(matches!(result, Err(MokshaWalletError::MintError(..))));
+        assert_eq!(60, wallet.get_balance().await?);
+
+        let result = wallet.send_tokens(&wallet_keyset, 61).await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::NotEnoughTokens {
+                needed: 61,
+                available: 60
+            })
+        ));
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recover_interrupted_swap() -> anyhow::Result<()> {
+        // the mint signs the outputs, but the response never reaches the wallet
+        let signed = Arc::new(std::sync::Mutex::new(Vec::<BlindedMessage>::new()));
+        let mut client = create_mock();
+        let swap_outputs = signed.clone();
+        client
+            .expect_post_swap()
+            .times(1)
+            .returning(move |_, _, outputs| {
+                swap_outputs.lock().unwrap().extend(outputs);
+                Err(MokshaWalletError::MintError(
+                    0,
+                    "connection reset".to_owned(),
+                ))
+            });
+        let signed_outputs = signed.clone();
+        client
+            .expect_post_restore()
+            .times(1)
+            .returning(move |_, outputs| {
+                let signed = signed_outputs.lock().unwrap();
+                let outputs = outputs
+                    .into_iter()
+                    .filter(|output| signed.iter().any(|s| s.b_ == output.b_))
+                    .collect::<Vec<_>>();
+                Ok(PostRestoreResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    outputs,
+                })
+            });
+        expect_all_unspent(&mut client);
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        assert!(wallet.send_tokens(&wallet_keyset, 21).await.is_err());
+        assert_eq!(60, wallet.get_balance().await?);
+        let swapped = signed.lock().unwrap().iter().map(|o| o.amount).sum::<u64>();
+        assert!(swapped >= 21);
+
+        assert_eq!(swapped, wallet.recover().await?);
+        assert_eq!(60 + swapped, wallet.get_balance().await?);
+        // the records are gone after a successful recovery
+        assert_eq!(0, wallet.recover().await?);
+        Ok(())
+    }
+
+    const RESTORE_SEED_WORDS: &str =
+        "half depart obvious quality work element tank gorilla view sugar picture humble";
+
+    /// Mock of a mint that has signed the deterministic outputs of the given counters
+    fn create_restore_mock(
+        wallet_keyset: &WalletKeyset,
+        signed_counters: &[u32],
+        spent_counters: &[u32],
+        restore_calls: Arc<AtomicU64>,
+    ) -> anyhow::Result<MockCashuClient> {
+        let secrets = DeterministicSecret::from_seed_words(RESTORE_SEED_WORDS)?;
+        let dhke = Dhke::new();
+        let derive = |counter: u32| -> anyhow::Result<(String, PublicKey)> {
+            let (secret, blinding_factor) = secrets
+                .derive_range(&wallet_keyset.keyset_id, counter, 1)?
+                .remove(0);
+            let b_ = dhke.step1_alice(&secret, &blinding_factor)?;
+            Ok((secret, b_))
+        };
+        let signed = signed_counters
+            .iter()
+            .map(|counter| Ok((derive(*counter)?.1, 1 << (counter % 4))))
+            .collect::<anyhow::Result<HashMap<PublicKey, u64>>>()?;
+        let spent = spent_counters
+            .iter()
+            .map(|counter| Ok(Dhke::hash_to_curve(derive(*counter)?.0.as_bytes())?))
+            .collect::<anyhow::Result<HashSet<PublicKey>>>()?;
+
+        let mut client = create_mock();
+        client.expect_post_restore().returning(move |_, outputs| {
+            restore_calls.fetch_add(1, Ordering::SeqCst);
+            let outputs = outputs
+                .into_iter()
+                .filter_map(|output| {
+                    signed.get(&output.b_).map(|amount| BlindedMessage {
+                        amount: *amount,
+                        ..output
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(PostRestoreResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                outputs,
+            })
+        });
+        client.expect_post_checkstate().returning(move |_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: if spent.contains(&y) {
+                            State::Spent
+                        } else {
+                            State::Unspent
+                        },
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+        Ok(client)
+    }
+
+    async fn create_restore_wallet(
+        client: MockCashuClient,
+        wallet_keyset: &WalletKeyset,
+    ) -> anyhow::Result<Wallet<MemoryLocalStore, MockCashuClient>> {
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, wallet_keyset).await?;
+        tx.commit().await?;
+        Ok(WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .with_seed_words(RESTORE_SEED_WORDS)
+            .build()
+            .await?)
+    }
+
+    #[tokio::test]
+    async fn test_restore() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let restore_calls = Arc::new(AtomicU64::new(0));
+        // counter 1 was spent, counters 12 and 13 are behind an empty batch
+        let client = create_restore_mock(
+            &wallet_keyset,
+            &[0, 1, 2, 3, 12, 13],
+            &[1],
+            restore_calls.clone(),
+        )?;
+        let wallet = create_restore_wallet(client, &wallet_keyset).await?;
+
+        let restored = wallet.restore(0, 5).await?;
+        // amounts are 1 << (counter % 4): 1 + 4 + 8 + 1 + 2 without the spent 2
+        assert_eq!(16, restored);
+        assert_eq!(16, wallet.get_balance().await?);
+        assert_eq!(5, wallet.get_proofs().await?.len());
+        // batches 0, 5, 10 and three empty batches 15, 20, 25
+        assert_eq!(6, restore_calls.load(Ordering::SeqCst));
+
+        let keyset = wallet.get_wallet_keysets().await?.remove(0);
+        assert_eq!(13, keyset.last_index);
+
+        // restoring again doesn't add the proofs twice
+        assert_eq!(0, wallet.restore(0, 5).await?);
+        assert_eq!(16, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_keyset() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let other_keyset = WalletKeyset::new(
+            &KeysetId::new("00ffd48b8f5ecf80")?,
+            &wallet_keyset.mint_url,
+            &CurrencyUnit::Sat,
+            0,
+            wallet_keyset.public_keys.clone(),
+            false,
+        );
+        let restore_calls = Arc::new(AtomicU64::new(0));
+        let client = create_restore_mock(&wallet_keyset, &[0, 1, 2], &[], restore_calls.clone())?;
+        let wallet = create_restore_wallet(client, &wallet_keyset).await?;
+        let mut tx = wallet.localstore.begin_tx().await?;
+        wallet
+            .localstore
+            .upsert_keyset(&mut tx, &other_keyset)
+            .await?;
+        tx.commit().await?;
+
+        let keyset = wallet
+            .get_wallet_keysets()
+            .await?
+            .into_iter()
+            .find(|k| k.keyset_id == wallet_keyset.keyset_id)
+            .expect("keyset not found");
+
+        // only the given keyset is scanned: one batch with signatures and the empty batches
+        assert_eq!(7, wallet.restore_keyset(&keyset, 0, 5).await?);
+        assert_eq!(
+            1 + RESTORE_GAP_LIMIT as u64,
+            restore_calls.load(Ordering::SeqCst)
+        );
+        assert_eq!(7, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_restore_stops_at_gap_limit() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let restore_calls = Arc::new(AtomicU64::new(0));
+        // only found when scanning across more than the gap limit
+        let client = create_restore_mock(&wallet_keyset, &[40], &[], restore_calls.clone())?;
+        let wallet = create_restore_wallet(client, &wallet_keyset).await?;
+
+        assert_eq!(0, wallet.restore(0, 10).await?);
+        assert_eq!(
+            RESTORE_GAP_LIMIT as u64,
+            restore_calls.load(Ordering::SeqCst)
+        );
+        assert_eq!(0, wallet.get_balance().await?);
+        assert_eq!(0, wallet.get_wallet_keysets().await?[0].last_index);
+        Ok(())
+    }
+
+    /// Mock mint that remembers the amounts of the outputs it signed and the spent proofs, so the
+    /// change of a melt can be restored
+    fn create_melt_ledger_mock(
+        signed: Arc<std::sync::Mutex<HashMap<PublicKey, u64>>>,
+        spent: Arc<std::sync::Mutex<HashSet<PublicKey>>>,
+    ) -> MockCashuClient {
+        let mut client = create_mock();
+        let (swap_signed, swap_spent) = (signed.clone(), spent.clone());
+        client
+            .expect_post_swap()
+            .returning(move |_, inputs, outputs| {
+                for proof in inputs.proofs() {
+                    swap_spent.lock().expect("poisoned").insert(proof.y()?);
+                }
+                let mut signed = swap_signed.lock().expect("poisoned");
+                signed.extend(outputs.iter().map(|o| (o.b_, o.amount)));
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        let (melt_signed, melt_spent) = (signed.clone(), spent.clone());
+        client
+            .expect_post_melt_bolt11()
+            .returning(move |_, proofs, _, outputs| {
+                for proof in proofs.proofs() {
+                    melt_spent.lock().expect("poisoned").insert(proof.y()?);
+                }
+                // 21 sats invoice and 2 sats fees
+                let change = outputs
+                    .iter()
+                    .zip(Amount(proofs.total_amount() - 21 - 2).split())
+                    .map(|(msg, amount)| BlindedMessage {
+                        amount,
+                        ..msg.clone()
+                    })
+                    .collect::<Vec<_>>();
+                let mut signed = melt_signed.lock().expect("poisoned");
+                signed.extend(change.iter().map(|o| (o.b_, o.amount)));
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: change.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+        client.expect_post_restore().returning(move |_, outputs| {
+            let signed = signed.lock().expect("poisoned");
+            let outputs = outputs
+                .into_iter()
+                .filter_map(|output| {
+                    signed.get(&output.b_).map(|amount| BlindedMessage {
+                        amount: *amount,
+                        ..output
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(PostRestoreResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                outputs,
+            })
+        });
+        client.expect_post_checkstate().returning(move |_, ys| {
+            let spent = spent.lock().expect("poisoned");
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: if spent.contains(&y) {
+                            State::Spent
+                        } else {
+                            State::Unspent
+                        },
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+        client
+    }
+
+    #[tokio::test]
+    async fn test_restore_finds_melt_change() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let signed = Arc::new(std::sync::Mutex::new(HashMap::new()));
+        let spent = Arc::new(std::sync::Mutex::new(HashSet::new()));
+        let wallet = create_restore_wallet(
+            create_melt_ledger_mock(signed.clone(), spent.clone()),
+            &wallet_keyset,
+        )
+        .await?;
+        let proofs = proofs_for_keyset(&wallet_keyset)?.proofs(); // 4, 8, 16, 32
+        let mut tx = wallet.localstore.begin_tx().await?;
+        wallet
+            .localstore
+            .add_proofs(&mut tx, &proofs[0..3].to_vec().into())
+            .await?;
+        tx.commit().await?;
+
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+        let (_, change) = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await?;
+        assert_eq!(2, change);
+        // 3 sats from the swap before the melt and 2 sats change
+        assert_eq!(5, wallet.get_balance().await?);
+
+        let restored_wallet =
+            create_restore_wallet(create_melt_ledger_mock(signed, spent), &wallet_keyset).await?;
+        assert_eq!(5, restored_wallet.restore(0, 5).await?);
+        let secrets = |proofs: Proofs| {
+            proofs
+                .proofs()
+                .into_iter()
+                .map(|p| p.secret)
+                .collect::<HashSet<_>>()
+        };
+        assert_eq!(
+            secrets(wallet.get_proofs().await?),
+            secrets(restored_wallet.get_proofs().await?)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_estimate_total_pay_amount() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_melt_quote_bolt11().returning(|_, _, _| {
+            Ok(PostMeltQuoteBolt11Response {
+                quote: "quote".to_string(),
+                amount: 21,
+                fee_reserve: 4,
+                paid: false,
+                expiry: None,
+            })
+        });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let total = wallet
+            .estimate_total_pay_amount(&wallet_keyset.mint_url, INVOICE_21_SATS)
+            .await?;
+        assert_eq!(21 + 4, total);
+
+        let result = wallet
+            .estimate_total_pay_amount(&wallet_keyset.mint_url, EXPIRED_INVOICE_21_SATS)
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::InvoiceExpired(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice_expired() -> anyhow::Result<()> {
+        // the client has no expectations for swaps, checkstate or melts
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+
+        let result = wallet
+            .pay_invoice(&wallet_keyset, &quote, EXPIRED_INVOICE_21_SATS.to_string())
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::InvoiceExpired(_))));
+        assert_eq!(60, wallet.get_proofs().await?.total_amount());
+        assert_eq!(60, wallet.get_balance().await?);
+        assert!(wallet.stuck_reservations(Duration::ZERO).await?.is_empty());
+        assert!(wallet.get_pending_melts().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_melt_limits_of_mint_info() -> anyhow::Result<()> {
+        let limits = Arc::new(std::sync::Mutex::new((21, 21)));
+        let mut client = create_mock();
+        let mint_limits = limits.clone();
+        client.expect_get_info().returning(move |_| {
+            let (min, max) = *mint_limits.lock().expect("poisoned");
+            let mut nuts = Nuts::default();
+            nuts.nut5.payment_methods[0].min_amount = Some(min);
+            nuts.nut5.payment_methods[0].max_amount = Some(max);
+            Ok(Some(MintInfoResponse {
+                name: None,
+                pubkey: Dhke::hash_to_curve(b"mint")?,
+                version: None,
+                description: None,
+                description_long: None,
+                contact: None,
+                motd: None,
+                nuts,
+            }))
+        });
+        client
+            .expect_post_melt_quote_bolt11()
+            .times(1)
+            .returning(|_, _, _| {
+                Ok(PostMeltQuoteBolt11Response {
+                    quote: "quote".to_string(),
+                    amount: 21,
+                    fee_reserve: 4,
+                    paid: false,
+                    expiry: None,
+                })
+            });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+        let mint_url = wallet_keyset.mint_url.clone();
+
+        // exactly at both limits
+        wallet.get_mint_info(&mint_url).await?;
+        let quote = wallet
+            .get_melt_quote_bolt11(&mint_url, INVOICE_21_SATS.to_string(), CurrencyUnit::Sat)
+            .await?;
+
+        *limits.lock().expect("poisoned") = (22, 100);
+        wallet.get_mint_info(&mint_url).await?;
+        let result = wallet
+            .get_melt_quote_bolt11(&mint_url, INVOICE_21_SATS.to_string(), CurrencyUnit::Sat)
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::MeltAmountTooLow {
+                amount: 21,
+                min: 22
+            })
+        ));
+
+        *limits.lock().expect("poisoned") = (1, 20);
+        wallet.get_mint_info(&mint_url).await?;
+        let result = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::MeltAmountTooHigh {
+                amount: 21,
+                max: 20
+            })
+        ));
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recover_pending_melt_after_client_error() -> anyhow::Result<()> {
+        let state = Arc::new(std::sync::Mutex::new(State::Unspent));
+        let mut client = create_mock();
+        let mint_state = state.clone();
+        client.expect_post_checkstate().returning(move |_, ys| {
+            let state = mint_state.lock().expect("poisoned").clone();
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: state.clone(),
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+        client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        // the connection breaks after the mint received the melt
+        client
+            .expect_post_melt_bolt11()
+            .returning(|_, _, _, _| Err(MokshaWalletError::UnexpectedResponse("".to_owned())));
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+
+        let result = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await;
+        assert!(result.is_err());
+        assert_eq!(35, wallet.get_balance().await?);
+        let pending_melts = wallet.get_pending_melts().await?;
+        assert_eq!(1, pending_melts.len());
+        assert_eq!("quote", pending_melts[0].quote_id);
+        assert_eq!(INVOICE_21_SATS, pending_melts[0].invoice);
+        assert_eq!(25, pending_melts[0].proofs.total_amount());
+
+        // the mint is still paying the invoice
+        *state.lock().expect("poisoned") = State::Pending;
+        let result = wallet.recover_pending().await?;
+        assert!(result.released.is_empty() && result.spent.is_empty());
+        assert_eq!(1, wallet.get_pending_melts().await?.len());
+
+        // the payment failed, the proofs are part of the balance again
+        *state.lock().expect("poisoned") = State::Unspent;
+        let result = wallet.recover_pending().await?;
+        assert_eq!(25, result.released.total_amount());
+        assert_eq!(60, wallet.get_balance().await?);
+        assert!(wallet.get_pending_melts().await?.is_empty());
+
+        // the invoice was paid, the proofs are removed
+        let result = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await;
+        assert!(result.is_err());
+        *state.lock().expect("poisoned") = State::Spent;
+        let result = wallet.recover_pending().await?;
+        assert_eq!(25, result.spent.total_amount());
+        assert_eq!(35, wallet.get_balance().await?);
+        assert_eq!(35, wallet.get_proofs().await?.total_amount());
+        assert!(wallet.get_pending_melts().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice_unknown_keyset_change() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        expect_all_unspent(&mut client);
+        client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        client
+            .expect_post_melt_bolt11()
+            .returning(|_, _, _, outputs| {
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: vec![BlindedSignature {
+                        amount: 2,
+                        c_: outputs[0].b_,
+                        id: "00ffffffffffffff".to_owned(),
+                        dleq: None,
+                    }],
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+
+        let result = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::InvalidChange(_))));
+
+        // the inputs are not finalized and the melt is kept with the dispute
+        assert_eq!(35, wallet.get_balance().await?);
+        let reserved = wallet.stuck_reservations(Duration::ZERO).await?;
+        assert_eq!(25, reserved.iter().map(|r| r.proof.amount).sum::<u64>());
+        let pending_melts = wallet.get_pending_melts().await?;
+        assert_eq!(1, pending_melts.len());
+        assert!(pending_melts[0].dispute.is_some());
+
+        let result = wallet.recover_pending().await?;
+        assert!(result.released.is_empty() && result.spent.is_empty());
+        assert_eq!(1, wallet.get_pending_melts().await?.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_verify_melt_change_undersized() -> anyhow::Result<()> {
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+        let keysets = wallet.get_wallet_keysets().await?;
+        let (wallet, keysets, keyset_id) = (&wallet, &keysets, &wallet_keyset.keyset_id);
+        let change = |amounts: Vec<u64>| async move {
+            let blanks = wallet.create_blank(43.into(), keyset_id).await?;
+            let signatures = blanks
+                .iter()
+                .zip(amounts)
+                .map(|((msg, _, _), amount)| BlindedSignature {
+                    amount,
+                    ..sign_blinded_message(msg)
+                })
+                .collect::<Vec<_>>();
+            let secrets = blanks.iter().map(|(_, _, secret)| secret.clone()).collect();
+            let outputs = blanks
+                .into_iter()
+                .map(|(msg, blinding_factor, _)| (msg, blinding_factor))
+                .collect();
+            // 43 sats were paid for the invoice and fees, the mint can keep 4 of them
+            anyhow::Ok(wallet.verify_melt_change(keysets, signatures, secrets, outputs, 43, 4))
+        };
+
+        let result = change(vec![1]).await?;
+        assert!(matches!(result, Err(MokshaWalletError::InvalidChange(_))));
+        let result = change(vec![32, 8, 4]).await?;
+        assert!(matches!(result, Err(MokshaWalletError::InvalidChange(_))));
+        assert_eq!(42, change(vec![32, 8, 2]).await??.total_amount());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice_counter_after_failed_melt() -> anyhow::Result<()> {
+        // a request to a closed port fails before it reaches the mint
+        let not_sent = reqwest::get("http://127.0.0.1:1")
+            .await
+            .expect_err("port 1 is open");
+        let not_sent = Arc::new(std::sync::Mutex::new(Some(not_sent)));
+        let swap_outputs = Arc::new(AtomicU64::new(0));
+        let blanks = Arc::new(std::sync::Mutex::new(Vec::<HashSet<PublicKey>>::new()));
+
+        let mut client = create_mock();
+        expect_all_unspent(&mut client);
+        let outputs_count = swap_outputs.clone();
+        client.expect_post_swap().returning(move |_, _, outputs| {
+            outputs_count.fetch_add(outputs.len() as u64, Ordering::SeqCst);
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        let melt_blanks = blanks.clone();
+        client
+            .expect_post_melt_bolt11()
+            .returning(move |_, _, _, outputs| {
+                let mut blanks = melt_blanks.lock().expect("poisoned");
+                blanks.push(outputs.iter().map(|o| o.b_).collect());
+                match blanks.len() {
+                    1 => Err(not_sent
+                        .lock()
+                        .expect("poisoned")
+                        .take()
+                        .expect("error is taken")
+                        .into()),
+                    2 => Err(MokshaWalletError::MintError(
+                        0,
+                        "quote is pending".to_owned(),
+                    )),
+                    _ => Ok(PostMeltBolt11Response {
+                        paid: true,
+                        payment_preimage: None,
+                        change: vec![],
+                        input_fee: None,
+                        fee_paid: None,
+                    }),
+                }
+            });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+        let last_index = || async { anyhow::Ok(wallet.get_wallet_keysets().await?[0].last_index) };
+
+        // the melt was never sent, so the counters of its change outputs are free again
+        let result = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await;
+        assert!(matches!(result, Err(ref e) if e.is_not_sent()));
+        assert_eq!(swap_outputs.load(Ordering::SeqCst), last_index().await?);
+
+        // the mint saw the outputs of the second attempt, the retry must not use them again
+        let result = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::MintError(..))));
+        let blank_count = blanks.lock().expect("poisoned")[1].len() as u64;
+        assert_eq!(2, blank_count);
+        assert_eq!(
+            swap_outputs.load(Ordering::SeqCst) + blank_count,
+            last_index().await?
+        );
+
+        let (response, _) = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await?;
+        assert!(response.paid);
+        let blanks = blanks.lock().expect("poisoned");
+        assert!(blanks[1].is_disjoint(&blanks[2]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_timelocked() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let redeemable_after = 1_700_000_000;
+        let token = wallet
+            .send_timelocked(&wallet_keyset, 20, redeemable_after)
+            .await?;
+        assert_eq!(20, token.total_amount());
+        for proof in token.proofs().proofs() {
+            let secret = proof.well_known_secret().expect("secret is not locked");
+            assert_eq!(Some(redeemable_after), secret.locktime());
+        }
+
+        let remaining = wallet.get_proofs().await?;
+        assert_eq!(40, remaining.total_amount());
+        assert!(remaining
+            .proofs()
+            .iter()
+            .all(|p| p.well_known_secret().is_none()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_with_denominations() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        let sent = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = sent.clone();
+        client
+            .expect_post_swap()
+            .times(1)
+            .returning(move |_, _, outputs| {
+                *recorded.lock().expect("poisoned") =
+                    outputs.iter().map(|output| output.amount).collect();
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let token = wallet
+            .send_tokens_with_denominations(&wallet_keyset, 12, vec![1, 1, 2, 4, 4])
+            .await?;
+        let amounts = token
+            .proofs()
+            .proofs()
+            .iter()
+            .map(|proof| proof.amount)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![1, 1, 2, 4, 4], amounts);
+        // the outputs for the change come first
+        assert!(sent.lock().expect("poisoned").ends_with(&[1, 1, 2, 4, 4]));
+        assert_eq!(48, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_send_tokens_with_invalid_denominations() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_swap().never();
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let result = wallet
+            .send_tokens_with_denominations(&wallet_keyset, 12, vec![4, 4])
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidDenominations(_))
+        ));
+
+        let result = wallet
+            .send_tokens_with_denominations(&wallet_keyset, 12, vec![3, 9])
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::InvalidDenominations(_))
+        ));
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_balance() -> anyhow::Result<()> {
+        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
+        let fixture: TokenV3 = fixture.try_into()?;
+        let local_store = MemoryLocalStore::new();
+        let mut tx = local_store.begin_tx().await?;
+        local_store.add_proofs(&mut tx, &fixture.proofs()).await?;
+        tx.commit().await?;
+
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(local_store)
+            .build()
+            .await?;
+
+        let result = wallet.get_balance().await?;
+        assert_eq!(60, result);
+        Ok(())
+    }
+    // FIXME
+
+    #[tokio::test]
+    async fn test_get_balance_per_keyset() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let (wallet, _) = create_wallet_with_proofs(create_mock()).await?;
+
+        let other_keyset: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+        let mut tx = wallet.localstore.begin_tx().await?;
+        wallet
+            .localstore
+            .add_proofs(&mut tx, &other_keyset.proofs())
+            .await?;
+        tx.commit().await?;
+
+        let balances = wallet.get_balance_per_keyset().await?;
+        assert_eq!(2, balances.len());
+        assert_eq!(
+            Some(&60),
+            balances.get(&wallet_keyset.keyset_id.to_string())
+        );
+        assert_eq!(Some(&64), balances.get("paFbO142_sui"));
+        assert_eq!(124, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice() -> anyhow::Result<()> {
+        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
+
+        let local_store = MemoryLocalStore::new();
+        let fixture: TokenV3 = fixture.try_into()?;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = local_store.begin_tx().await?;
+        local_store
+            .add_proofs(&mut tx, &rekey_proofs(&fixture.proofs(), &wallet_keyset))
+            .await?;
+        local_store.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let melt_response =
+            read_fixture_as::<PostMeltBolt11Response>("post_melt_response_21.json")?; // 60 tokens (4,8,16,32)
+        let mut mock_client = create_mock();
+        expect_all_unspent(&mut mock_client);
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(move |_, _, _, _| Ok(melt_response.clone()));
+
+        let quote_response =
+            read_fixture_as::<PostMeltQuoteBolt11Response>("post_melt_quote_response.json")?;
+        mock_client
+            .expect_post_melt_quote_bolt11()
+            .returning(move |_, _, _| Ok(quote_response.clone()));
+
+        mock_client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+
+        let mint_url = Url::parse("http://localhost:8080/").expect("invalid url");
+        let wallet = WalletBuilder::new()
+            .with_client(mock_client)
+            .with_localstore(local_store)
+            .build()
+            .await?;
+
+        // 21 sats
+        let invoice = "lnbcrt210n1pn3vymqdqqpp5wtxkappzcsrlkmgfs6g0zyct0hkhashh7hsaxz7e65slq9fkx7fssp5qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs9qrsgqcqzysxq8zals8sqtt635dgzh35y2cgx5rh2vw77570u8w8q8pj37t4ymdzrar62wwssv8h83cdk4j2v7q3a7688dku0mwtmvyxfgr79j8k5xv56ed8wvespn60te4".to_string();
+
+        let quote = wallet
+            .get_melt_quote_bolt11(&mint_url, invoice.clone(), CurrencyUnit::Sat)
+            .await?;
+        let result = wallet.pay_invoice(&wallet_keyset, &quote, invoice).await?;
+        assert!(result.0.paid);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice_returns_change() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&wallet_keyset)?)
+            .await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        // the mock mint signs the blinded messages by returning them unchanged
+        let mut mock_client = create_mock();
+        expect_all_unspent(&mut mock_client);
+        mock_client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        let fee_paid = 2;
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(move |_, proofs, _, outputs| {
+                let change = proofs.total_amount() - 21 - fee_paid;
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: outputs
+                        .iter()
+                        .zip(Amount(change).split())
+                        .map(|(msg, amount)| BlindedSignature {
+                            amount,
+                            ..sign_blinded_message(msg)
+                        })
+                        .collect(),
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(mock_client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        // 21 sats
+        let invoice = "lnbcrt210n1pn3vymqdqqpp5wtxkappzcsrlkmgfs6g0zyct0hkhashh7hsaxz7e65slq9fkx7fssp5qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs9qrsgqcqzysxq8zals8sqtt635dgzh35y2cgx5rh2vw77570u8w8q8pj37t4ymdzrar62wwssv8h83cdk4j2v7q3a7688dku0mwtmvyxfgr79j8k5xv56ed8wvespn60te4".to_string();
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+
+        let (response, change) = wallet.pay_invoice(&wallet_keyset, &quote, invoice).await?;
+        assert!(response.paid);
+        assert_eq!(2, change);
+        assert_eq!(60 - 21 - fee_paid, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice_events() -> anyhow::Result<()> {
+        let mut mock_client = create_mock();
+        expect_all_unspent(&mut mock_client);
+        mock_client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(move |_, proofs, _, outputs| {
+                let change = proofs.total_amount() - 21 - 2;
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: outputs
+                        .iter()
+                        .zip(Amount(change).split())
+                        .map(|(msg, amount)| BlindedSignature {
+                            amount,
+                            ..sign_blinded_message(msg)
+                        })
+                        .collect(),
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(mock_client).await?;
+        let mut events = wallet.subscribe();
+
+        // 21 sats
+        let invoice = "lnbcrt210n1pn3vymqdqqpp5wtxkappzcsrlkmgfs6g0zyct0hkhashh7hsaxz7e65slq9fkx7fssp5qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs9qrsgqcqzysxq8zals8sqtt635dgzh35y2cgx5rh2vw77570u8w8q8pj37t4ymdzrar62wwssv8h83cdk4j2v7q3a7688dku0mwtmvyxfgr79j8k5xv56ed8wvespn60te4".to_string();
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+        wallet.pay_invoice(&wallet_keyset, &quote, invoice).await?;
+        assert!(wallet
+            .pay_invoice(&wallet_keyset, &quote, "invalid".to_string())
+            .await
+            .is_err());
+
+        let mut received = vec![];
+        while let Ok(event) = events.try_recv() {
+            received.push(event);
+        }
+        let [WalletEvent::OperationStarted {
+            id,
+            kind: OperationKind::PayInvoice,
+        }, WalletEvent::FeeEstimated {
+            amount: 21,
+            fee_reserve: 4,
+        }, WalletEvent::ProofsSelected { amount, .. }, WalletEvent::SplitCompleted {
+            amount: split_amount,
+        }, WalletEvent::MeltSubmitted { quote_id }, WalletEvent::BalanceChanged { new_balance: 37 }, WalletEvent::OperationFinished {
+            id: finished_id,
+            kind: OperationKind::PayInvoice,
+            error: None,
+        }, WalletEvent::OperationStarted { .. }, WalletEvent::OperationFinished { error: Some(_), .. }] =
+            received.as_slice()
+        else {
+            panic!("unexpected events {received:?}");
+        };
+        assert_eq!(id, finished_id);
+        assert!(*amount >= 25);
+        assert_eq!(amount, split_amount);
+        assert_eq!("quote", quote_id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_swap_and_melt_with_input_fee() -> anyhow::Result<()> {
+        // 1 sat per proof, everything the wallet pays in fees is added up here
+        let fees = Arc::new(AtomicU64::new(0));
+        let mut mock_client = create_mock();
+        expect_all_unspent(&mut mock_client);
+        let swap_fees = fees.clone();
+        mock_client
+            .expect_post_swap()
+            .returning(move |_, inputs, outputs| {
+                let input_fee = inputs.len() as u64;
+                assert_eq!(inputs.total_amount(), outputs.total_amount() + input_fee);
+                swap_fees.fetch_add(input_fee, Ordering::SeqCst);
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: Some(input_fee),
+                })
+            });
+        let melt_fees = fees.clone();
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(move |_, inputs, _, outputs| {
+                let input_fee = inputs.len() as u64;
+                // 21 sats, a fee reserve of 4 and a routing fee of 2
+                assert!(inputs.total_amount() >= 21 + 4 + input_fee);
+                melt_fees.fetch_add(input_fee + 2, Ordering::SeqCst);
+                let change = inputs.total_amount() - 21 - 2 - input_fee;
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: outputs
+                        .iter()
+                        .zip(Amount(change).split())
+                        .map(|(msg, amount)| BlindedSignature {
+                            amount,
+                            ..sign_blinded_message(msg)
+                        })
+                        .collect(),
+                    input_fee: Some(input_fee),
+                    fee_paid: Some(2),
+                })
+            });
+        let wallet_keyset = WalletKeyset {
+            input_fee_ppk: 1_000,
+            ..create_test_wallet_keyset()?
+        };
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&wallet_keyset)?)
+            .await?;
+        tx.commit().await?;
+        let wallet = WalletBuilder::new()
+            .with_client(mock_client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let token = wallet.send_tokens(&wallet_keyset, 10).await?;
+        assert_eq!(10, token.total_amount());
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+        let (response, _) = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await?;
+        assert!(response.paid);
+
+        let fees = fees.load(Ordering::SeqCst);
+        assert!(fees > 2);
+        assert_eq!(60 - 10 - 21 - fees, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_history_mint_and_pay() -> anyhow::Result<()> {
+        let mut mock_client = create_mock();
+        expect_all_unspent(&mut mock_client);
+        mock_client
+            .expect_post_mint_bolt11()
+            .returning(|_, _, outputs| {
+                Ok(PostMintBolt11Response {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                })
+            });
+        mock_client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(move |_, proofs, _, outputs| {
+                let change = proofs.total_amount() - 21 - 2;
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: outputs
+                        .iter()
+                        .zip(Amount(change).split())
+                        .map(|(msg, amount)| BlindedSignature {
+                            amount,
+                            ..sign_blinded_message(msg)
+                        })
+                        .collect(),
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+        let localstore = MemoryLocalStore::new();
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+        let wallet = WalletBuilder::new()
+            .with_client(mock_client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        wallet
+            .mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                40.into(),
+                "hash".to_string(),
+            )
+            .await?;
+        // 21 sats
+        let invoice = "lnbcrt210n1pn3vymqdqqpp5wtxkappzcsrlkmgfs6g0zyct0hkhashh7hsaxz7e65slq9fkx7fssp5qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs9qrsgqcqzysxq8zals8sqtt635dgzh35y2cgx5rh2vw77570u8w8q8pj37t4ymdzrar62wwssv8h83cdk4j2v7q3a7688dku0mwtmvyxfgr79j8k5xv56ed8wvespn60te4".to_string();
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+        wallet
+            .pay_invoice(&wallet_keyset, &quote, invoice.clone())
+            .await?;
+
+        let history = wallet.get_history().await?;
+        assert_eq!(2, history.len());
+        let (mint, melt) = (&history[0], &history[1]);
+        assert_eq!(HistoryKind::Mint, mint.kind);
+        assert_eq!(TransferDirection::Incoming, mint.kind.direction());
+        assert_eq!(40, mint.amount);
+        assert_eq!(wallet_keyset.mint_url, mint.mint_url);
+        assert_eq!(None, mint.invoice);
+        assert_eq!(HistoryKind::Melt, melt.kind);
+        assert_eq!(TransferDirection::Outgoing, melt.kind.direction());
+        // the invoice and the fees the mint charged
+        assert_eq!(21 + 2, melt.amount);
+        assert_eq!(Some(invoice), melt.invoice);
+        assert_ne!(mint.operation_id, melt.operation_id);
+        assert_eq!(40 - 21 - 2, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_transactions_stream() -> anyhow::Result<()> {
+        let localstore = MemoryLocalStore::new();
+        let add_entries = |count: u64, created_at: u64| {
+            let localstore = localstore.clone();
+            async move {
+                let mut tx = localstore.begin_tx().await?;
+                for i in 0..count {
+                    let entry = HistoryEntry {
+                        id: None,
+                        operation_id: OperationId::from_parts(1_000_000, i.into()),
+                        kind: [HistoryKind::Mint, HistoryKind::Melt][i as usize % 2],
+                        amount: i,
+                        mint_url: Url::parse("http://localhost:3338/")?,
+                        created_at: created_at + i / 10,
+                        invoice: None,
+                        memo: None,
+                    };
+                    localstore.add_history_entry(&mut tx, &entry).await?;
+                }
+                tx.commit().await?;
+                anyhow::Ok(())
+            }
+        };
+        add_entries(10_000, 1_000).await?;
+        let wallet = WalletBuilder::new()
+            .with_client(MockCashuClient::default())
+            .with_localstore(localstore.clone())
+            .build()
+            .await?;
+        let mut expected = wallet.get_history().await?;
+        expected.reverse();
+
+        let entries = wallet
+            .transactions_stream(HistoryFilter::default())
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(expected, entries);
+
+        let melts = wallet
+            .transactions_stream(HistoryFilter {
+                kind: Some(HistoryKind::Melt),
+                ..Default::default()
+            })
+            .try_collect::<Vec<_>>()
+            .await?;
+        assert_eq!(5_000, melts.len());
+        assert!(melts.iter().all(|entry| entry.kind == HistoryKind::Melt));
+
+        // entries that are added after the first pages were loaded are not streamed
+        let mut stream = Box::pin(wallet.transactions_stream(HistoryFilter::default()));
+        let mut entries = vec![];
+        for _ in 0..HISTORY_PAGE_SIZE + 1 {
+            entries.push(stream.try_next().await?.expect("entry"));
+        }
+        add_entries(100, expected[0].created_at).await?;
+        while let Some(entry) = stream.try_next().await? {
+            entries.push(entry);
+        }
+        assert_eq!(expected, entries);
+        Ok(())
+    }
+
+    /// Wallet with an empty balance that withdraws to merchant@pay.example.com. The lightning
+    /// address returns an invoice of 21 sats and the mint a fee reserve of 4 sats.
+    async fn create_auto_withdraw_wallet(
+        mut client: MockCashuClient,
+        max_fee_percent: f64,
+    ) -> anyhow::Result<(Wallet<MemoryLocalStore, MockCashuClient>, WalletKeyset)> {
+        client.expect_get_lnurl_pay_request().returning(|url| {
+            assert_eq!(
+                "https://pay.example.com/.well-known/lnurlp/merchant",
+                url.as_str()
+            );
+            Ok(LnurlPayResponse {
+                callback: Url::parse("https://pay.example.com/callback")?,
+                min_sendable: 1_000,
+                max_sendable: 21_000,
+                tag: "payRequest".to_owned(),
+            })
+        });
+        client
+            .expect_get_lnurl_invoice()
+            .withf(|_, amount_msat| *amount_msat == 21_000)
+            .returning(|_, _| {
+                Ok(LnurlInvoiceResponse {
+                    // 21 sats
+                    pr: "lnbcrt210n1pn3vymqdqqpp5wtxkappzcsrlkmgfs6g0zyct0hkhashh7hsaxz7e65slq9fkx7fssp5qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs9qrsgqcqzysxq8zals8sqtt635dgzh35y2cgx5rh2vw77570u8w8q8pj37t4ymdzrar62wwssv8h83cdk4j2v7q3a7688dku0mwtmvyxfgr79j8k5xv56ed8wvespn60te4".to_owned(),
+                })
+            });
+        client.expect_post_melt_quote_bolt11().returning(|_, _, _| {
+            Ok(PostMeltQuoteBolt11Response {
+                quote: "quote".to_string(),
+                amount: 21,
+                fee_reserve: 4,
+                paid: false,
+                expiry: None,
+            })
+        });
+        client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        expect_all_unspent(&mut client);
+
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .with_auto_withdraw(AutoWithdrawConfig {
+                ln_address: "merchant@pay.example.com".parse()?,
+                min_batch_sat: 50,
+                max_fee_percent,
+            })
+            .build()
+            .await?;
+        Ok((wallet, wallet_keyset))
+    }
+
+    #[tokio::test]
+    async fn test_receive_auto_withdraw() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client
+            .expect_post_melt_bolt11()
+            .times(1)
+            .returning(|_, proofs, _, outputs| {
+                let change = proofs.total_amount() - 21 - 2;
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: outputs
+                        .iter()
+                        .zip(Amount(change).split())
+                        .map(|(msg, amount)| BlindedSignature {
+                            amount,
+                            ..sign_blinded_message(msg)
+                        })
+                        .collect(),
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+        // 4 sats are 19% of 21 sats
+        let (wallet, wallet_keyset) = create_auto_withdraw_wallet(client, 20.0).await?;
+
+        let token = TokenV3::new(Token {
+            mint: Some(wallet_keyset.mint_url.clone()),
+            proofs: proofs_for_keyset(&wallet_keyset)?,
+        });
+        wallet.receive_tokens(&wallet_keyset, &token).await?;
+
+        // 60 received, 21 withdrawn and 2 of the fee reserve spent
+        assert_eq!(60 - 21 - 2, wallet.get_balance().await?);
+        let history = wallet.get_history().await?;
+        assert_eq!(
+            vec![(HistoryKind::Receive, 60), (HistoryKind::Melt, 21 + 2)],
+            history
+                .iter()
+                .map(|entry| (entry.kind, entry.amount))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            AutoWithdrawal::BelowThreshold { balance: 37 },
+            wallet.auto_withdraw(&wallet_keyset).await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_receive_auto_withdraw_fee_too_high() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_melt_bolt11().never();
+        // 4 sats are more than 10% of 21 sats
+        let (wallet, wallet_keyset) = create_auto_withdraw_wallet(client, 10.0).await?;
+
+        let token = TokenV3::new(Token {
+            mint: Some(wallet_keyset.mint_url.clone()),
+            proofs: proofs_for_keyset(&wallet_keyset)?,
+        });
+        wallet.receive_tokens(&wallet_keyset, &token).await?;
+
+        assert_eq!(60, wallet.get_balance().await?);
+        assert_eq!(
+            AutoWithdrawal::FeeTooHigh {
+                amount: 21,
+                fee_reserve: 4
+            },
+            wallet.auto_withdraw(&wallet_keyset).await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_auto_withdraw_backoff() -> anyhow::Result<()> {
+        let now = Arc::new(AtomicU64::new(1_000));
+        let mut client = create_mock();
+        client
+            .expect_get_lnurl_pay_request()
+            .times(2)
+            .returning(|_| Err(MokshaWalletError::Lnurl("unreachable".to_owned())));
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&wallet_keyset)?)
+            .await?;
+        tx.commit().await?;
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .with_clock(create_mock_clock(now.clone()))
+            .with_auto_withdraw(AutoWithdrawConfig {
+                ln_address: "merchant@pay.example.com".parse()?,
+                min_batch_sat: 50,
+                max_fee_percent: 1.0,
+            })
+            .build()
+            .await?;
+        let mut events = wallet.subscribe();
+
+        assert!(wallet.auto_withdraw(&wallet_keyset).await.is_err());
+        assert_eq!(
+            WalletEvent::AutoWithdrawFailed {
+                error: "LNURL error: unreachable".to_owned(),
+                retry_at: 1_000 + AUTO_WITHDRAW_BASE_DELAY,
+            },
+            events.try_recv()?
+        );
+        assert_eq!(
+            AutoWithdrawal::BackingOff {
+                retry_at: 1_000 + AUTO_WITHDRAW_BASE_DELAY
+            },
+            wallet.auto_withdraw(&wallet_keyset).await?
+        );
+        assert_eq!(60, wallet.get_balance().await?);
+
+        // the delay doubles after the second failure
+        now.store(1_000 + AUTO_WITHDRAW_BASE_DELAY, Ordering::SeqCst);
+        assert!(wallet.auto_withdraw(&wallet_keyset).await.is_err());
+        assert_eq!(
+            AutoWithdrawal::BackingOff {
+                retry_at: 1_000 + 3 * AUTO_WITHDRAW_BASE_DELAY
+            },
+            wallet.auto_withdraw(&wallet_keyset).await?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice_operation_details() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = MemoryLocalStore::new();
+        let fixture_proofs = proofs_for_keyset(&wallet_keyset)?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore.add_proofs(&mut tx, &fixture_proofs).await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let mut mock_client = create_mock();
+        expect_all_unspent(&mut mock_client);
+        mock_client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(move |_, proofs, _, outputs| {
+                let change = proofs.total_amount() - 21 - 2;
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: outputs
+                        .iter()
+                        .zip(Amount(change).split())
+                        .map(|(msg, amount)| BlindedSignature {
+                            amount,
+                            ..sign_blinded_message(msg)
+                        })
+                        .collect(),
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(mock_client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        // 21 sats
+        let invoice = "lnbcrt210n1pn3vymqdqqpp5wtxkappzcsrlkmgfs6g0zyct0hkhashh7hsaxz7e65slq9fkx7fssp5qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs9qrsgqcqzysxq8zals8sqtt635dgzh35y2cgx5rh2vw77570u8w8q8pj37t4ymdzrar62wwssv8h83cdk4j2v7q3a7688dku0mwtmvyxfgr79j8k5xv56ed8wvespn60te4".to_string();
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+        wallet.pay_invoice(&wallet_keyset, &quote, invoice).await?;
+        assert!(wallet
+            .pay_invoice(&wallet_keyset, &quote, "invalid".to_string())
+            .await
+            .is_err());
+
+        let operations = wallet.operations().await?;
+        assert_eq!(2, operations.len());
+        // both operations started within the same second, so their order is random
+        let (failed, paid): (Vec<_>, Vec<_>) = operations.iter().partition(|op| op.error.is_some());
+        let (paid, failed) = (paid[0], failed[0]);
+        assert_eq!(OperationKind::PayInvoice, paid.kind);
+        assert!(paid.finished_at.is_some());
+        assert_eq!(None, paid.error);
+        assert_eq!(OperationKind::PayInvoice, failed.kind);
+        assert!(wallet
+            .operation_details(&failed.id)
+            .await?
+            .records
+            .is_empty());
+
+        let details = wallet.operation_details(&paid.id).await?;
+        assert_eq!(paid, &details.operation);
+        assert!(details
+            .records
+            .iter()
+            .any(|record| matches!(record, OperationRecord::PendingMelt(_))));
+        // the pending melt is removed once the payment succeeded
+        assert!(details.pending_melts.is_empty());
+        // every linked proof is still in the localstore
+        let proof_records = details
+            .records
+            .iter()
+            .filter(|record| matches!(record, OperationRecord::Proof(_)))
+            .count();
+        assert_eq!(proof_records, details.proofs.len());
+
+        // the fixture proofs that were spent paid for the 25 sats sent to the mint, the swap
+        // change and the 2 sats returned by the melt are spendable again
+        let fixture_secrets = fixture_proofs.secrets();
+        let spent_inputs = details
+            .proofs
+            .iter()
+            .filter(|(proof, status)| {
+                *status == ProofStatus::Spent && fixture_secrets.contains(&proof.secret)
+            })
+            .map(|(proof, _)| proof.amount)
+            .sum::<u64>();
+        let melted = details
+            .proofs
+            .iter()
+            .filter(|(proof, status)| {
+                *status == ProofStatus::Spent && !fixture_secrets.contains(&proof.secret)
+            })
+            .map(|(proof, _)| proof.amount)
+            .sum::<u64>();
+        let change = details
+            .proofs
+            .iter()
+            .filter(|(_, status)| *status == ProofStatus::Spendable)
+            .map(|(proof, _)| proof.amount)
+            .sum::<u64>();
+        assert_eq!(25, melted);
+        assert_eq!(spent_inputs - 25 + 2, change);
+        assert_eq!(60 - spent_inputs + change, wallet.get_balance().await?);
+
+        assert!(matches!(
+            wallet
+                .operation_details(&OperationId::from_parts(0, 0))
+                .await,
+            Err(MokshaWalletError::OperationNotFound(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mint_and_pay_invoice_without_mint_info() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&wallet_keyset)?)
+            .await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let mut mock_client = create_mock();
+        expect_all_unspent(&mut mock_client);
+        mock_client.expect_get_info().returning(|_| Ok(None));
+        mock_client
+            .expect_post_mint_bolt11()
+            .returning(|_, _, outputs| {
+                Ok(PostMintBolt11Response {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                })
+            });
+        mock_client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        // without info the mint is not assumed to return change
+        mock_client
+            .expect_post_melt_bolt11()
+            .withf(|_, _, _, outputs| outputs.is_empty())
+            .returning(|_, _, _, _| {
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: vec![],
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(mock_client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        assert_eq!(None, wallet.get_mint_info(&wallet_keyset.mint_url).await?);
+
+        let minted = wallet
+            .mint_tokens(
+                &wallet_keyset,
+                &PaymentMethod::Bolt11,
+                20.into(),
+                "hash".to_string(),
+            )
+            .await?;
+        assert_eq!(20, minted.total_amount());
+
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+        let (response, change) = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await?;
+        assert!(response.paid);
+        assert_eq!(0, change);
+        assert_eq!(60 + 20 - 21 - 4, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice_with_proofs_of_multiple_keysets() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let old_keyset = WalletKeyset {
+            keyset_id: KeysetId::new("00aaaaaaaaaaaaaa")?,
+            active: false,
+            ..wallet_keyset.clone()
+        };
+        let other_mint_keyset = WalletKeyset {
+            keyset_id: KeysetId::new("00bbbbbbbbbbbbbb")?,
+            mint_url: Url::parse("http://127.0.0.1:3339")?,
+            ..wallet_keyset.clone()
+        };
+        let proofs = proofs_for_keyset(&wallet_keyset)?.proofs(); // 4, 8, 16, 32
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        for keyset in [&wallet_keyset, &old_keyset, &other_mint_keyset] {
+            localstore.upsert_keyset(&mut tx, keyset).await?;
+        }
+        localstore
+            .add_proofs(&mut tx, &proofs[0..2].to_vec().into())
+            .await?;
+        localstore
+            .add_proofs(
+                &mut tx,
+                &rekey_proofs(&proofs[2..3].to_vec().into(), &old_keyset),
+            )
+            .await?;
+        localstore
+            .add_proofs(
+                &mut tx,
+                &rekey_proofs(&proofs[3..4].to_vec().into(), &other_mint_keyset),
+            )
+            .await?;
+        tx.commit().await?;
+
+        let mut mock_client = create_mock();
+        expect_all_unspent(&mut mock_client);
+        let (active_id, old_id) = (
+            wallet_keyset.keyset_id.to_string(),
+            old_keyset.keyset_id.to_string(),
+        );
+        mock_client
+            .expect_post_swap()
+            .times(1)
+            .returning(move |mint_url, inputs, outputs| {
+                // the 32 sats of the other mint are not used
+                assert_eq!("http://127.0.0.1:3338/", mint_url.as_str());
+                assert_eq!(28, inputs.total_amount());
+                let keyset_ids = inputs
+                    .proofs()
+                    .into_iter()
+                    .map(|p| p.keyset_id)
+                    .collect::<HashSet<_>>();
+                assert_eq!(
+                    HashSet::from([active_id.clone(), old_id.clone()]),
+                    keyset_ids
+                );
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        let fee_paid = 2;
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(move |_, proofs, _, outputs| {
+                let change = proofs.total_amount() - 21 - fee_paid;
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: outputs
+                        .iter()
+                        .zip(Amount(change).split())
+                        .map(|(msg, amount)| BlindedSignature {
+                            amount,
+                            ..sign_blinded_message(msg)
+                        })
+                        .collect(),
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(mock_client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+
+        let (response, change) = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_string())
+            .await?;
+        assert!(response.paid);
+        assert_eq!(2, change);
+        let balances = wallet.get_balance_per_keyset().await?;
+        assert_eq!(Some(&32), balances.get("00bbbbbbbbbbbbbb"));
+        assert_eq!(None, balances.get("00aaaaaaaaaaaaaa"));
+        assert_eq!(32 + 28 - 21 - fee_paid, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice_prunes_spent_proofs() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let proofs = proofs_for_keyset(&wallet_keyset)?; // 4, 8, 16, 32
+        let spent_y = proofs.proofs()[3].y()?;
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.add_proofs(&mut tx, &proofs).await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let mut mock_client = create_mock();
+        mock_client
+            .expect_post_checkstate()
+            .returning(move |_, ys| {
+                Ok(PostCheckStateResponse {
+                    states: ys
+                        .into_iter()
+                        .map(|y| ProofState {
+                            y,
+                            state: if y == spent_y {
+                                State::Spent
+                            } else {
+                                State::Unspent
+                            },
+                            witness: None,
+                        })
+                        .collect(),
+                })
+            });
+        mock_client
+            .expect_post_swap()
+            .returning(|_, inputs, outputs| {
+                assert!(inputs.proofs().iter().all(|p| p.amount != 32));
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(|_, _, _, _| {
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: vec![],
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(mock_client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        assert_eq!(
+            vec![true, true, true, false],
+            wallet.check_proofs_spendable(&proofs).await?
+        );
+
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+        let (response, _) = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_owned())
+            .await?;
+        assert!(response.paid);
+        // the spent 32 was removed, 4 + 8 + 16 were swapped into 25 for the melt and 3 change
+        assert_eq!(3, wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pay_invoice_keeps_pending_proofs() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let proofs = proofs_for_keyset(&wallet_keyset)?; // 4, 8, 16, 32
+        let pending_y = proofs.proofs()[2].y()?;
+        let pending_secret = proofs.proofs()[2].secret.clone();
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.add_proofs(&mut tx, &proofs).await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
+
+        let mut mock_client = create_mock();
+        mock_client
+            .expect_post_checkstate()
+            .returning(move |_, ys| {
+                Ok(PostCheckStateResponse {
+                    states: ys
+                        .into_iter()
+                        .map(|y| ProofState {
+                            y,
+                            state: if y == pending_y {
+                                State::Pending
+                            } else {
+                                State::Unspent
+                            },
+                            witness: None,
+                        })
+                        .collect(),
+                })
+            });
+        let secret = pending_secret.clone();
+        mock_client
+            .expect_post_swap()
+            .returning(move |_, inputs, outputs| {
+                assert!(inputs.proofs().iter().all(|p| p.secret != secret));
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        let secret = pending_secret.clone();
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(move |_, inputs, _, _| {
+                assert!(inputs.proofs().iter().all(|p| p.secret != secret));
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: vec![],
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+
+        let wallet = WalletBuilder::new()
+            .with_client(mock_client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+        let (response, _) = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_21_SATS.to_owned())
+            .await?;
+        assert!(response.paid);
+        // the pending 16 is neither used nor marked as spent
+        assert_eq!(60 - 25, wallet.get_balance().await?);
+        assert!(wallet
+            .get_proofs()
+            .await?
+            .proofs()
+            .iter()
+            .any(|p| p.secret == pending_secret));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_spent_proofs() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_checkstate().returning(|_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, y)| ProofState {
+                        y,
+                        state: match i {
+                            0 | 2 => State::Spent,
+                            1 => State::Pending,
+                            _ => State::Unspent,
+                        },
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+        let (wallet, _) = create_wallet_with_proofs(client).await?;
+        let proofs = wallet.get_proofs().await?;
+        let expected_spent = [proofs.proofs()[0].clone(), proofs.proofs()[2].clone()];
+
+        let spent = wallet.find_spent_proofs(false).await?;
+        assert_eq!(expected_spent.to_vec(), spent.proofs());
+        assert_eq!(60, wallet.get_balance().await?);
+
+        let spent = wallet.find_spent_proofs(true).await?;
+        assert_eq!(expected_spent.to_vec(), spent.proofs());
+        // the pending proof is kept
+        assert_eq!(60 - spent.total_amount(), wallet.get_balance().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_find_spent_proofs_deletes_many() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_checkstate().returning(|_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: State::Spent,
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+        let (wallet, _) = create_wallet_with_proofs(client).await?;
+        let proof = wallet.get_proofs().await?.proofs()[0].clone();
+        let many: Proofs = (0..1_000)
+            .map(|i| Proof {
+                secret: format!("spent {i}"),
+                ..proof.clone()
+            })
+            .collect::<Vec<_>>()
+            .into();
+        let mut tx = wallet.localstore.begin_tx().await?;
+        wallet.localstore.add_proofs(&mut tx, &many).await?;
+        tx.commit().await?;
+
+        let spent = wallet.find_spent_proofs(true).await?;
+        assert_eq!(1_004, spent.len());
+        assert_eq!(0, wallet.get_balance().await?);
+        let mut tx = wallet.localstore.begin_tx().await?;
+        for state in ProofStatus::ALL {
+            assert!(wallet
+                .localstore
+                .get_proofs_by_state(&mut tx, state)
+                .await?
+                .is_empty());
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    fn sign_blinded_message(msg: &BlindedMessage) -> BlindedSignature {
+        BlindedSignature {
+            amount: msg.amount,
+            c_: msg.b_,
+            id: msg.id.clone(),
+            dleq: None,
+        }
+    }
+
+    const INVOICE_100_SATS: &str = "lnbcrt1u1pn3vymqdqqpp5wkrhhdqa8ya4lwz9tnnqanvdmgqp6p33vjttzn0607y4v4hwef9qsp5pqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyqszqgpqyq9qrsgqcqzysxq8zals8sqwakuneqh6g8dagfjmplahvunea7nzm4vclacey9307cllrcfwzrs5smnzaec58mrrwscev68mzglvrqzvj3cwsxau6gtanye9pgn4nqqyg0je8";
+    const INVOICE_21_SATS: &str = "lnbcrt210n1pn3vymqdqqpp5wtxkappzcsrlkmgfs6g0zyct0hkhashh7hsaxz7e65slq9fkx7fssp5qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs9qrsgqcqzysxq8zals8sqtt635dgzh35y2cgx5rh2vw77570u8w8q8pj37t4ymdzrar62wwssv8h83cdk4j2v7q3a7688dku0mwtmvyxfgr79j8k5xv56ed8wvespn60te4";
+    /// expired one day after it was created in 2023
+    const EXPIRED_INVOICE_21_SATS: &str = "lnbcrt210n1pjg6mqhpp5pza5wzh0csjjuvfpjpv4zdjmg30vedj9ycv5tyfes9x7dp8axy0sdqqcqzzsxqyz5vqsp5vtxg4c5tw2s2zxxya2a7an0psn9mcfmlqctxzntm3sngnpyk3muq9qyyssqf8z5f90yu3wrmsufnnza25qjlnvc6ukdr094ckzn63ktcy6z5fw5mxf9skndpg2p4648gfjfvvx4qg2lqvlryyycg5k7x9h4dw70t4qq37pegm";
+
+    /// Mock for two mints that both have a fee reserve of 2 sats and charge 1 sat in fees
+    fn create_multi_mint_mock(target_mint_online: Arc<AtomicBool>) -> MockCashuClient {
+        let mut client = create_mock();
+        client
+            .expect_post_melt_quote_bolt11()
+            .returning(|mint_url, invoice, _| {
+                let amount = LNInvoice::from_str(&invoice)
+                    .expect("invalid invoice")
+                    .amount_milli_satoshis()
+                    .expect("amount is missing")
+                    / 1_000;
+                Ok(PostMeltQuoteBolt11Response {
+                    quote: format!("melt {mint_url}"),
+                    amount,
+                    fee_reserve: 2,
+                    paid: false,
+                    expiry: None,
+                })
+            });
+        client.expect_post_mint_quote_bolt11().returning(|_, _, _| {
+            Ok(PostMintQuoteBolt11Response {
+                quote: "transfer".to_owned(),
+                payment_request: INVOICE_21_SATS.to_owned(),
+                paid: false,
+                expiry: None,
+            })
+        });
+        client.expect_get_mint_quote_bolt11().returning(|_, quote| {
+            Ok(PostMintQuoteBolt11Response {
+                quote,
+                payment_request: INVOICE_21_SATS.to_owned(),
+                paid: false,
+                expiry: None,
+            })
+        });
+        client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
             })
-            .collect::<Result<Vec<(_, _, _)>, MokshaWalletError>>()?;
-
-        Ok(blinded_messages)
+        });
+        client.expect_post_mint_bolt11().returning(|_, _, outputs| {
+            Ok(PostMintBolt11Response {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+            })
+        });
+        client
+            .expect_post_melt_bolt11()
+            .returning(move |mint_url, proofs, _, outputs| {
+                let invoice_amount = if mint_url.port() == Some(3339) {
+                    if !target_mint_online.load(Ordering::SeqCst) {
+                        return Err(MokshaWalletError::MintError(0, "mint offline".to_owned()));
+                    }
+                    100
+                } else {
+                    21
+                };
+                let change = proofs.total_amount() - invoice_amount - 1;
+                Ok(PostMeltBolt11Response {
+                    paid: true,
+                    payment_preimage: None,
+                    change: outputs
+                        .iter()
+                        .zip(Amount(change).split())
+                        .map(|(msg, amount)| BlindedSignature {
+                            amount,
+                            ..sign_blinded_message(msg)
+                        })
+                        .collect(),
+                    input_fee: None,
+                    fee_paid: None,
+                })
+            });
+        client
     }
 
-    fn create_blinded_messages(
-        &self,
-        keyset_id: &KeysetId,
-        amount: Amount,
-        secrets_factors: Vec<(String, BlindingFactor)>,
-    ) -> Result<Vec<(BlindedMessage, BlindingFactor)>, MokshaWalletError> {
-        let split_amount = amount.split();
+    /// mint A (port 3338) has 60 sats, mint B (port 3339) has 81 sats
+    async fn create_multi_mint_wallet(
+        client: MockCashuClient,
+    ) -> anyhow::Result<Wallet<MemoryLocalStore, MockCashuClient>> {
+        let keyset_a = create_test_wallet_keyset()?;
+        let keyset_b = WalletKeyset {
+            keyset_id: KeysetId::new("00ffd48b8f5ecf80")?,
+            mint_url: Url::parse("http://127.0.0.1:3339")?,
+            ..keyset_a.clone()
+        };
 
-        split_amount
+        let token_64: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+        let c = token_64.proofs().proofs()[0].c;
+        let mut proofs_b = token_64.proofs().proofs();
+        proofs_b.push(Proof::new(16, "secret16".to_owned(), c, String::new()));
+        proofs_b.push(Proof::new(1, "secret1".to_owned(), c, String::new()));
+        let proofs_b: Proofs = proofs_b
             .into_iter()
-            .zip(secrets_factors)
-            .map(|(amount, (secret, blinding_factor))| {
-                let b_ = self.dhke.step1_alice(secret, &blinding_factor)?;
-                Ok((
-                    BlindedMessage {
-                        amount,
-                        b_,
-                        id: keyset_id.to_string(),
-                    },
-                    blinding_factor,
-                ))
+            .map(|p| Proof {
+                keyset_id: keyset_b.keyset_id.to_string(),
+                ..p
             })
-            .collect::<Result<Vec<(_, _)>, MokshaWalletError>>()
+            .collect::<Vec<Proof>>()
+            .into();
+
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &keyset_a).await?;
+        localstore.upsert_keyset(&mut tx, &keyset_b).await?;
+        localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&keyset_a)?)
+            .await?;
+        localstore.add_proofs(&mut tx, &proofs_b).await?;
+        tx.commit().await?;
+
+        Ok(WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?)
     }
 
-    fn create_proofs_from_blinded_signatures(
-        &self,
-        keyset_id: &KeysetId,
-        pub_keys: &HashMap<u64, PublicKey>,
-        signatures: Vec<BlindedSignature>,
-        secrets: Vec<String>,
-        outputs: Vec<(BlindedMessage, BlindingFactor)>,
-    ) -> Result<Proofs, MokshaWalletError> {
-        let current_keyset_id = keyset_id.to_string(); // FIXME
+    #[tokio::test]
+    async fn test_pay_invoice_multi_transfer_then_pay() -> anyhow::Result<()> {
+        let client = create_multi_mint_mock(Arc::new(AtomicBool::new(true)));
+        let wallet = create_multi_mint_wallet(client).await?;
+        let mint_a = Url::parse("http://127.0.0.1:3338")?;
+        let mint_b = Url::parse("http://127.0.0.1:3339")?;
+
+        let payment = wallet
+            .pay_invoice_multi(INVOICE_100_SATS.to_owned())
+            .await?;
 
-        let blinding_factors = outputs
-            .into_iter()
-            .map(|(_, secret)| secret)
-            .collect::<Vec<BlindingFactor>>();
+        // mint B needs the smaller transfer: 100 + 2 fee reserve - 81
+        assert_eq!(MultiPaymentStage::Paid, payment.stage);
+        assert_eq!(mint_a, payment.source_mint_url);
+        assert_eq!(mint_b, payment.target_mint_url);
+        assert_eq!(21, payment.transfer_amount);
+        assert_eq!(1, payment.transfer_fees);
+        assert_eq!(1, payment.payment_fees);
+        assert_eq!(2, payment.total_fees());
+
+        assert_eq!(
+            38,
+            wallet.get_proofs_for_mint(&mint_a).await?.total_amount()
+        );
+        assert_eq!(1, wallet.get_proofs_for_mint(&mint_b).await?.total_amount());
+        assert_eq!(vec![payment], wallet.get_multi_payments().await?);
+        Ok(())
+    }
 
-        Ok(signatures
+    #[tokio::test]
+    async fn test_pay_invoice_from_other_mints_balance() -> anyhow::Result<()> {
+        let mut client = create_multi_mint_mock(Arc::new(AtomicBool::new(true)));
+        expect_all_unspent(&mut client);
+        let wallet = create_multi_mint_wallet(client).await?;
+        let keysets = wallet.get_wallet_keysets().await?;
+        let mint_a = Url::parse("http://127.0.0.1:3338")?;
+        let keyset_a = keysets
             .iter()
-            .zip(blinding_factors)
-            .zip(secrets)
-            .map(|((p, blinding_factor), secret)| {
-                let key = pub_keys
-                    .get(&p.amount)
-                    .ok_or(MokshaWalletError::PubkeyNotFound)?;
-                let pub_alice = self
-                    .dhke
-                    .step3_alice(p.c_, blinding_factor.to_owned(), *key)?;
-                Ok(Proof::new(
-                    p.amount,
-                    secret,
-                    pub_alice,
-                    current_keyset_id.clone(),
-                ))
+            .find(|k| k.mint_url == mint_a)
+            .expect("keyset of mint A not found");
+
+        assert_eq!(141, wallet.get_balance().await?);
+        assert_eq!(60, wallet.get_balance_for_mint(&mint_a).await?);
+        assert_eq!(
+            81,
+            wallet
+                .get_balance_for_mint(&Url::parse("http://127.0.0.1:3339")?)
+                .await?
+        );
+
+        // 100 sats + 2 fee reserve, mint A can't use the proofs of mint B
+        let quote = wallet
+            .get_melt_quote_bolt11(&mint_a, INVOICE_100_SATS.to_owned(), CurrencyUnit::Sat)
+            .await?;
+        let result = wallet
+            .pay_invoice(keyset_a, &quote, INVOICE_100_SATS.to_owned())
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::BalanceAtOtherMints {
+                balance: 60,
+                needed: 102,
+                ..
             })
-            .collect::<Result<Vec<_>, MokshaWalletError>>()?
-            .into())
+        ));
+        assert_eq!(141, wallet.get_balance().await?);
+        Ok(())
     }
 
-    pub async fn get_proofs(&self) -> Result<Proofs, MokshaWalletError> {
-        let mut tx = self.localstore.begin_tx().await?;
-        let proofs = self.localstore.get_proofs(&mut tx).await?;
-        tx.commit().await?;
-        Ok(proofs)
+    #[tokio::test]
+    async fn test_pay_invoice_not_enough_tokens() -> anyhow::Result<()> {
+        // an underfunded payment fails before anything is sent to the mint
+        let mut client = create_mock();
+        client.expect_post_checkstate().never();
+        client.expect_post_swap().never();
+        client.expect_post_melt_bolt11().never();
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        // 100 sats + 2 fee reserve
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_owned(),
+            amount: 100,
+            fee_reserve: 2,
+            paid: false,
+            expiry: None,
+        };
+        let result = wallet
+            .pay_invoice(&wallet_keyset, &quote, INVOICE_100_SATS.to_owned())
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::NotEnoughTokens {
+                needed: 102,
+                available: 60
+            })
+        ));
+
+        let result = wallet
+            .melt_token(
+                &wallet_keyset.mint_url,
+                "quote".to_owned(),
+                &proofs_for_keyset(&wallet_keyset)?,
+                102,
+                vec![],
+            )
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::NotEnoughTokens {
+                needed: 102,
+                available: 60
+            })
+        ));
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
     }
-}
 
-// FIXME implement for Vec<BlindedMessage, Secretkey>
-fn get_blinded_msg(blinded_messages: Vec<(BlindedMessage, BlindingFactor)>) -> Vec<BlindedMessage> {
-    blinded_messages
-        .into_iter()
-        .map(|(msg, _)| msg)
-        .collect::<Vec<BlindedMessage>>()
-}
+    #[tokio::test]
+    async fn test_pay_invoice_multi_resume_after_transfer() -> anyhow::Result<()> {
+        let target_mint_online = Arc::new(AtomicBool::new(false));
+        let client = create_multi_mint_mock(target_mint_online.clone());
+        let wallet = create_multi_mint_wallet(client).await?;
+        let mint_b = Url::parse("http://127.0.0.1:3339")?;
+
+        let result = wallet.pay_invoice_multi(INVOICE_100_SATS.to_owned()).await;
+        assert!(matches!(result, Err(MokshaWalletError::MintError(..))));
+
+        let payments = wallet.get_multi_payments().await?;
+        assert_eq!(1, payments.len());
+        assert_eq!(MultiPaymentStage::TransferMinted, payments[0].stage);
+        assert_eq!(
+            102,
+            wallet.get_proofs_for_mint(&mint_b).await?.total_amount()
+        );
 
-#[cfg(test)]
-mod tests {
-    use std::collections::HashMap;
+        target_mint_online.store(true, Ordering::SeqCst);
+        let id = payments[0].id.expect("id not set");
+        let payment = wallet.resume_multi_payment(id).await?;
+        assert_eq!(MultiPaymentStage::Paid, payment.stage);
+        assert_eq!(2, payment.total_fees());
+        assert_eq!(39, wallet.get_balance().await?);
+        Ok(())
+    }
 
-    use crate::client::MockCashuClient;
-    use crate::localstore::sqlite::SqliteLocalStore;
-    use crate::localstore::{LocalStore, WalletKeyset};
-    use crate::wallet::WalletBuilder;
+    #[tokio::test]
+    async fn test_pay_invoice_can_not_melt() -> anyhow::Result<()> {
+        let fixture = read_fixture("token_64.cashu")?; // 60 tokens (4,8,16,32)
+        let tokens: TokenV3 = fixture.try_into()?;
 
-    use moksha_core::fixture::{read_fixture, read_fixture_as};
-    use moksha_core::keyset::{KeysetId, Keysets, MintKeyset};
-    use moksha_core::primitives::{
-        CurrencyUnit, KeyResponse, KeysResponse, PaymentMethod, PostMeltBolt11Response,
-        PostMeltQuoteBolt11Response, PostMintBolt11Response, PostSwapResponse,
-    };
+        let localstore = MemoryLocalStore::new();
 
-    use moksha_core::token::TokenV3;
-    use secp256k1::PublicKey;
-    use url::Url;
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let mut tx = localstore.begin_tx().await?;
+        localstore
+            .add_proofs(&mut tx, &rekey_proofs(&tokens.proofs(), &wallet_keyset))
+            .await?;
+        assert_eq!(64, localstore.get_proofs(&mut tx).await?.total_amount());
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        tx.commit().await?;
 
-    fn create_mock() -> MockCashuClient {
-        let keys = MintKeyset::new("mykey", "");
-        let key_response = KeyResponse {
-            keys: keys.public_keys.clone(),
-            id: keys.keyset_id.clone(),
-            unit: CurrencyUnit::Sat,
+        let melt_response =
+            read_fixture_as::<PostMeltBolt11Response>("post_melt_response_not_paid.json")?;
+
+        let mut mock_client = create_mock();
+        expect_all_unspent(&mut mock_client);
+        mock_client
+            .expect_post_melt_bolt11()
+            .returning(move |_, _, _, _| Ok(melt_response.clone()));
+
+        let quote_response =
+            read_fixture_as::<PostMeltQuoteBolt11Response>("post_melt_quote_response.json")?;
+        mock_client
+            .expect_post_melt_quote_bolt11()
+            .returning(move |_, _, _| Ok(quote_response.clone()));
+        mock_client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+
+        let wallet = WalletBuilder::default()
+            .with_client(mock_client)
+            .with_localstore(localstore.clone())
+            .build()
+            .await?;
+
+        // 21 sats
+        let invoice = "lnbcrt210n1pn3vymqdqqpp5wtxkappzcsrlkmgfs6g0zyct0hkhashh7hsaxz7e65slq9fkx7fssp5qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurswpc8qurs9qrsgqcqzysxq8zals8sqtt635dgzh35y2cgx5rh2vw77570u8w8q8pj37t4ymdzrar62wwssv8h83cdk4j2v7q3a7688dku0mwtmvyxfgr79j8k5xv56ed8wvespn60te4".to_string();
+
+        let quote = wallet
+            .get_melt_quote_bolt11(&wallet_keyset.mint_url, invoice.clone(), CurrencyUnit::Sat)
+            .await?;
+
+        let wallet_keyset = create_test_wallet_keyset()?;
+
+        let result = wallet.pay_invoice(&wallet_keyset, &quote, invoice).await?;
+        assert!(!result.0.paid);
+        let mut tx = localstore.begin_tx().await?;
+        assert_eq!(64, localstore.get_proofs(&mut tx).await?.total_amount());
+        assert!(!result.0.paid);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_audit_against_mint() -> anyhow::Result<()> {
+        let wallet_keyset = create_test_wallet_keyset()?;
+        let retired_keyset = WalletKeyset {
+            keyset_id: KeysetId::new("00ffd48b8f5ecf80")?,
+            ..wallet_keyset.clone()
         };
-        let keys_response = KeysResponse::new(key_response.clone());
-        let keys_by_id_response = keys_response.clone();
-        let keysets = Keysets::new(keys.keyset_id, CurrencyUnit::Sat, true);
+        let fixture: TokenV3 = read_fixture("token_60.cashu")?.try_into()?; // 60 tokens (4,8,16,32)
+        let proofs: Proofs = fixture
+            .proofs()
+            .proofs()
+            .into_iter()
+            .map(|p| Proof {
+                keyset_id: wallet_keyset.keyset_id.to_string(),
+                ..p
+            })
+            .collect::<Vec<Proof>>()
+            .into();
+
+        let localstore = MemoryLocalStore::new();
+        let mut tx = localstore.begin_tx().await?;
+        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        localstore.upsert_keyset(&mut tx, &retired_keyset).await?;
+        localstore.add_proofs(&mut tx, &proofs).await?;
+        tx.commit().await?;
 
+        let spent_y = proofs.proofs()[1].y()?; // 8
+        let pending_y = proofs.proofs()[2].y()?; // 16
+        let keysets = Keysets::new(wallet_keyset.keyset_id.to_string(), CurrencyUnit::Sat, true);
         let mut client = MockCashuClient::default();
-        client
-            .expect_get_keys()
-            .returning(move |_| Ok(keys_response.clone()));
         client
             .expect_get_keysets()
             .returning(move |_| Ok(keysets.clone()));
-        client
-            .expect_get_keys_by_id()
-            .returning(move |_, _| Ok(keys_by_id_response.clone()));
-        client.expect_is_v1_supported().returning(move |_| Ok(true));
-        client
+        client.expect_post_checkstate().returning(move |_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: match y {
+                            y if y == spent_y => State::Spent,
+                            y if y == pending_y => State::Pending,
+                            _ => State::Unspent,
+                        },
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+
+        let wallet = WalletBuilder::new()
+            .with_client(client)
+            .with_localstore(localstore)
+            .build()
+            .await?;
+
+        let report = wallet
+            .audit_against_mint(&wallet_keyset.mint_url, false)
+            .await?;
+        assert!(!report.is_consistent());
+        assert_eq!(36, report.confirmed_balance);
+        assert_eq!(8, report.spent_proofs.total_amount());
+        assert_eq!(16, report.pending_proofs.total_amount());
+        assert_eq!(1, report.unknown_keysets.len());
+        assert_eq!(
+            retired_keyset.keyset_id,
+            report.unknown_keysets[0].keyset_id
+        );
+        assert_eq!(60, wallet.get_balance().await?);
+
+        let report = wallet
+            .audit_against_mint(&wallet_keyset.mint_url, true)
+            .await?;
+        assert_eq!(8, report.spent_proofs.total_amount());
+        assert_eq!(52, wallet.get_balance().await?);
+        let keysets = wallet.get_wallet_keysets().await?;
+        let retired = keysets
+            .iter()
+            .find(|k| k.keyset_id == retired_keyset.keyset_id)
+            .expect("keyset not found");
+        assert!(!retired.active);
+
+        let report = wallet
+            .audit_against_mint(&wallet_keyset.mint_url, false)
+            .await?;
+        assert!(report.spent_proofs.is_empty());
+        assert_eq!(36, report.confirmed_balance);
+        Ok(())
     }
 
     #[tokio::test]
-    async fn test_blank_blinded_messages_1000_sats() -> anyhow::Result<()> {
-        let localstore = SqliteLocalStore::with_in_memory().await?;
+    async fn test_receive_queue_retries_while_mint_is_down() -> anyhow::Result<()> {
         let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = MemoryLocalStore::new();
         let mut tx = localstore.begin_tx().await?;
         localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
         tx.commit().await?;
 
-        let client = create_mock();
+        let mint_online = Arc::new(AtomicBool::new(false));
+        let mut mock_client = create_mock();
+        let online = mint_online.clone();
+        mock_client
+            .expect_post_swap()
+            .returning(move |_, _, outputs| {
+                if !online.load(Ordering::SeqCst) {
+                    return Err(MokshaWalletError::UnexpectedResponse(
+                        "connection refused".to_owned(),
+                    ));
+                }
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        mock_client.expect_post_checkstate().returning(|_, _| {
+            Err(MokshaWalletError::UnexpectedResponse(
+                "connection refused".to_owned(),
+            ))
+        });
 
+        let now = Arc::new(AtomicU64::new(1_000));
         let wallet = WalletBuilder::new()
-            .with_client(client)
+            .with_client(mock_client)
             .with_localstore(localstore)
+            .with_clock(create_mock_clock(now.clone()))
             .build()
             .await?;
-        let result = wallet
-            .create_blank(1000.into(), &KeysetId::new("00d31cecf59d18c0")?)
-            .await;
-        println!("{:?}", result);
-        assert!(result.is_ok());
-        let result = result.unwrap();
-        assert!(result.len() == 10);
-        assert!(result.first().unwrap().0.amount == 1);
 
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let id = wallet.enqueue_receive(&tokens).await?;
+
+        let events = wallet.process_receive_queue().await?;
+        assert!(matches!(
+            events.as_slice(),
+            [ReceiveQueueEvent::Retrying {
+                attempts: 1,
+                next_attempt: 1_030,
+                ..
+            }]
+        ));
+
+        // not due yet
+        now.store(1_020, Ordering::SeqCst);
+        assert!(wallet.process_receive_queue().await?.is_empty());
+
+        now.store(1_030, Ordering::SeqCst);
+        let events = wallet.process_receive_queue().await?;
+        assert!(matches!(
+            events.as_slice(),
+            [ReceiveQueueEvent::Retrying {
+                attempts: 2,
+                next_attempt: 1_090,
+                ..
+            }]
+        ));
+        assert_eq!(2, wallet.get_receive_queue().await?[0].attempts);
+
+        mint_online.store(true, Ordering::SeqCst);
+        now.store(1_090, Ordering::SeqCst);
+        let events = wallet.process_receive_queue().await?;
+        assert_eq!(vec![ReceiveQueueEvent::Received { id, amount: 60 }], events);
+        assert!(wallet.get_receive_queue().await?.is_empty());
+        assert_eq!(60, wallet.get_balance().await?);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_blank_blinded_messages_zero_sats() -> anyhow::Result<()> {
-        let localstore = SqliteLocalStore::with_in_memory().await?;
+    async fn test_receive_queue_spent_token() -> anyhow::Result<()> {
         let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = MemoryLocalStore::new();
         let mut tx = localstore.begin_tx().await?;
         localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
         tx.commit().await?;
 
-        let client = create_mock();
+        let mut mock_client = create_mock();
+        mock_client.expect_post_swap().returning(|_, _, _| {
+            Err(MokshaWalletError::ProofAlreadyUsed(
+                "Proof already used".into(),
+            ))
+        });
+        mock_client.expect_post_checkstate().returning(|_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: State::Spent,
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
 
+        let now = Arc::new(AtomicU64::new(1_000));
         let wallet = WalletBuilder::new()
-            .with_client(client)
+            .with_client(mock_client)
             .with_localstore(localstore)
+            .with_clock(create_mock_clock(now.clone()))
             .build()
             .await?;
-        let result = wallet
-            .create_blank(0.into(), &KeysetId::new("00d31cecf59d18c0")?)
-            .await;
-        println!("{:?}", result);
-        assert!(result.is_ok());
-        assert!(result.unwrap().is_empty());
 
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let id = wallet.enqueue_receive(&tokens).await?;
+
+        let events = wallet.process_receive_queue().await?;
+        assert!(matches!(
+            events.as_slice(),
+            [ReceiveQueueEvent::Failed { id: failed_id, .. }] if *failed_id == id
+        ));
+        let queue = wallet.get_receive_queue().await?;
+        assert_eq!(QueuedReceiveStatus::Failed, queue[0].status);
+
+        // failed entries are not retried automatically
+        now.store(10_000, Ordering::SeqCst);
+        assert!(wallet.process_receive_queue().await?.is_empty());
+
+        wallet.retry_queued_receive(id).await?;
+        assert_eq!(
+            QueuedReceiveStatus::Pending,
+            wallet.get_receive_queue().await?[0].status
+        );
+
+        wallet.remove_queued_receive(id).await?;
+        assert!(wallet.get_receive_queue().await?.is_empty());
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_blank_blinded_messages_serialize() -> anyhow::Result<()> {
-        let localstore = SqliteLocalStore::with_in_memory().await?;
+    async fn test_release_stuck_reservations() -> anyhow::Result<()> {
         let wallet_keyset = create_test_wallet_keyset()?;
+        let proofs = proofs_for_keyset(&wallet_keyset)?.proofs(); // 4, 8, 16, 32
+        let spent_y = proofs[1].y()?;
+
+        let localstore = MemoryLocalStore::new();
         let mut tx = localstore.begin_tx().await?;
         localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        localstore
+            .add_proofs(&mut tx, &proofs.clone().into())
+            .await?;
+        // 4 and 8 were reserved by a melt that never finished, 16 is reserved by a running melt
+        let secrets = Proofs::new(proofs.clone()).secrets();
+        localstore
+            .transition_proofs(
+                &mut tx,
+                &secrets[0..2],
+                ProofStatus::Spendable,
+                ProofStatus::PendingMelt,
+                1_000,
+            )
+            .await?;
+        localstore
+            .transition_proofs(
+                &mut tx,
+                &secrets[2..3],
+                ProofStatus::Spendable,
+                ProofStatus::PendingMelt,
+                4_500,
+            )
+            .await?;
         tx.commit().await?;
 
-        let client = create_mock();
+        let mut client = create_mock();
+        client.expect_post_checkstate().returning(move |_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: if y == spent_y {
+                            State::Spent
+                        } else {
+                            State::Unspent
+                        },
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
 
+        let now = Arc::new(AtomicU64::new(5_000));
         let wallet = WalletBuilder::new()
             .with_client(client)
             .with_localstore(localstore)
-            .build()
-            .await?;
-
-        let result = wallet
-            .create_blank(4000.into(), &KeysetId::new("00d31cecf59d18c0")?)
-            .await?;
-        for (blinded_message, _, _) in result {
-            let out = serde_json::to_string(&blinded_message)?;
-            assert!(!out.is_empty());
-        }
+            .with_clock(create_mock_clock(now.clone()))
+            .build()
+            .await?;
+        assert_eq!(32, wallet.get_balance().await?);
+
+        let older_than = Duration::from_secs(3_600);
+        let stuck = wallet.stuck_reservations(older_than).await?;
+        assert_eq!(2, stuck.len());
+        assert!(stuck.iter().all(|r| r.reserved_at == 1_000));
+
+        let result = wallet.release_stuck_reservations(older_than).await?;
+        assert_eq!(4, result.released.total_amount());
+        assert_eq!(8, result.spent.total_amount());
+        assert_eq!(36, wallet.get_balance().await?);
+        assert!(wallet.stuck_reservations(older_than).await?.is_empty());
+
+        // the reservation of the running melt is released once it is old enough
+        now.store(8_100, Ordering::SeqCst);
+        let result = wallet.release_stuck_reservations(older_than).await?;
+        assert_eq!(16, result.released.total_amount());
+        assert_eq!(52, wallet.get_balance().await?);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_mint_tokens() -> anyhow::Result<()> {
-        let mint_response =
-            read_fixture_as::<PostMintBolt11Response>("post_mint_response_20.json")?;
-
+    async fn test_rollback_after_destructive_delete() -> anyhow::Result<()> {
+        let buggy = Arc::new(AtomicBool::new(true));
         let mut client = create_mock();
-        client
-            .expect_post_mint_bolt11()
-            .returning(move |_, _, _| Ok(mint_response.clone()));
+        let mint_buggy = buggy.clone();
+        let spent_y = proofs_for_keyset(&create_test_wallet_keyset()?)?.proofs()[0].y()?;
+        client.expect_post_checkstate().returning(move |_, ys| {
+            let buggy = mint_buggy.load(Ordering::SeqCst);
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: match buggy || y == spent_y {
+                            true => State::Spent,
+                            false => State::Unspent,
+                        },
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+        let (wallet, _) = create_wallet_with_proofs(client).await?;
+        assert_eq!(60, wallet.get_balance().await?);
+
+        // a buggy mint reports all proofs as spent
+        assert_eq!(60, wallet.find_spent_proofs(true).await?.total_amount());
+        assert_eq!(0, wallet.get_balance().await?);
+        let snapshots = wallet.list_snapshots().await?;
+        assert_eq!(1, snapshots.len());
+        assert_eq!(60, snapshots[0].proofs.total_amount());
+        let snapshot_id = snapshots[0].id.expect("snapshot id");
+
+        // the proof of 4 sats was actually spent and is not restored
+        buggy.store(false, Ordering::SeqCst);
+        let restored = wallet.rollback_to(snapshot_id).await?;
+        assert_eq!(56, restored.total_amount());
+        assert_eq!(56, wallet.get_balance().await?);
+
+        // proofs that are in the localstore already are not restored twice
+        assert!(wallet.rollback_to(snapshot_id).await?.is_empty());
+        assert_eq!(56, wallet.get_balance().await?);
+
+        let result = wallet.rollback_to(snapshot_id + 1).await;
+        assert!(
+            matches!(result, Err(MokshaWalletError::SnapshotNotFound(id)) if id == snapshot_id + 1)
+        );
+        Ok(())
+    }
 
-        let localstore = SqliteLocalStore::with_in_memory().await?;
+    #[tokio::test]
+    async fn test_snapshot_retention_and_schedule() -> anyhow::Result<()> {
         let wallet_keyset = create_test_wallet_keyset()?;
+        let localstore = MemoryLocalStore::new();
         let mut tx = localstore.begin_tx().await?;
         localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
+        localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&wallet_keyset)?)
+            .await?;
         tx.commit().await?;
-
+        let now = Arc::new(AtomicU64::new(1_000));
         let wallet = WalletBuilder::new()
-            .with_client(client)
-            .with_localstore(localstore)
+            .with_client(create_mock())
+            .with_localstore(localstore.clone())
+            .with_clock(create_mock_clock(now.clone()))
+            .with_snapshot_config(SnapshotConfig {
+                max_snapshots: 2,
+                interval: Some(100),
+                ..Default::default()
+            })
             .build()
             .await?;
 
-        let result = wallet
-            .mint_tokens(
-                &wallet_keyset,
-                &PaymentMethod::Bolt11,
-                20.into(),
-                "hash".to_string(),
-            )
+        let first = wallet.take_scheduled_snapshot().await?;
+        assert!(first.is_some());
+        now.store(1_099, Ordering::SeqCst);
+        assert_eq!(None, wallet.take_scheduled_snapshot().await?);
+        now.store(1_100, Ordering::SeqCst);
+        let second = wallet.take_scheduled_snapshot().await?;
+        assert!(second.is_some());
+
+        // the oldest snapshot is deleted
+        let third = wallet.take_snapshot("manual").await?;
+        let snapshots = wallet.list_snapshots().await?;
+        assert_eq!(
+            vec![second, Some(third)],
+            snapshots.iter().map(|s| s.id).collect::<Vec<_>>()
+        );
+        assert_eq!("manual", snapshots[1].reason);
+
+        // the newest snapshot is kept even if it exceeds the size limit
+        let wallet = WalletBuilder::new()
+            .with_client(create_mock())
+            .with_localstore(localstore)
+            .with_snapshot_config(SnapshotConfig {
+                max_size: snapshots[0].size(),
+                ..Default::default()
+            })
+            .build()
             .await?;
-        assert_eq!(20, result.total_amount());
-        result.tokens.into_iter().for_each(|t| {
-            assert_eq!(wallet_keyset.mint_url, t.mint.expect("mint is empty"));
-        });
+        let fourth = wallet.take_snapshot("manual").await?;
+        let snapshots = wallet.list_snapshots().await?;
+        assert_eq!(
+            vec![Some(fourth)],
+            snapshots.iter().map(|s| s.id).collect::<Vec<_>>()
+        );
         Ok(())
     }
 
+    fn create_mock_clock(now: Arc<AtomicU64>) -> Arc<dyn Clock> {
+        let mut clock = MockClock::new();
+        clock
+            .expect_now()
+            .returning(move || now.load(Ordering::SeqCst));
+        Arc::new(clock)
+    }
+
     #[tokio::test]
-    async fn test_swap() -> anyhow::Result<()> {
-        let split_response = read_fixture_as::<PostSwapResponse>("post_swap_response_24_40.json")?;
+    async fn test_endpoint_stats() -> anyhow::Result<()> {
+        let mint_a = Url::parse("http://127.0.0.1:3338")?;
+        let mint_b = Url::parse("http://127.0.0.1:3339")?;
+        let samples = |endpoint, latencies: &[u64], errors: usize| {
+            let mut samples = EndpointSamples::new(endpoint);
+            latencies.iter().enumerate().for_each(|(i, latency)| {
+                samples.record(Duration::from_millis(*latency), i >= errors)
+            });
+            samples
+        };
+
+        // every call of the client returns the samples recorded since the previous call
+        let recorded = std::sync::Mutex::new(VecDeque::from([
+            vec![
+                (mint_a.clone(), samples(Endpoint::Swap, &[30, 10, 20], 1)),
+                (mint_a.clone(), samples(Endpoint::Keys, &[5], 0)),
+                (mint_b.clone(), samples(Endpoint::Swap, &[900], 0)),
+            ],
+            vec![(mint_a.clone(), samples(Endpoint::Swap, &[400], 0))],
+        ]));
         let mut client = create_mock();
         client
-            .expect_post_swap()
-            .returning(move |_, _, _| Ok(split_response.clone()));
-        let localstore = SqliteLocalStore::with_in_memory().await?;
-        let keyset = create_test_wallet_keyset()?;
-        let mut tx = localstore.begin_tx().await?;
-        localstore.upsert_keyset(&mut tx, &keyset).await?;
-        tx.commit().await?;
+            .expect_take_endpoint_samples()
+            .returning(move || recorded.lock().unwrap().pop_front().unwrap_or_default());
 
         let wallet = WalletBuilder::new()
             .with_client(client)
-            .with_localstore(localstore)
+            .with_localstore(MemoryLocalStore::new())
             .build()
             .await?;
 
-        let tokens = read_fixture("token_64.cashu")?.try_into()?;
-        let result = wallet.swap_tokens(&keyset, &tokens, 20.into()).await?;
-
-        let first = result.0;
-
-        assert_eq!(CurrencyUnit::Sat, first.clone().currency_unit.unwrap());
-        assert_eq!(24, first.total_amount());
-
-        let second = result.1;
-
-        assert_eq!(CurrencyUnit::Sat, second.clone().currency_unit.unwrap());
-        assert_eq!(40, second.total_amount());
+        let stats = wallet.endpoint_stats(&mint_a).await?;
+        assert_eq!(
+            vec![Endpoint::Keys, Endpoint::Swap],
+            stats.iter().map(|s| s.endpoint).collect::<Vec<_>>()
+        );
+        let swap = &stats[1];
+        assert_eq!((3, 1), (swap.requests, swap.errors));
+        assert_eq!((Some(20), Some(30)), (swap.p50_ms, swap.p99_ms));
+
+        // the new samples are added to the stored ones
+        let stats = wallet.endpoint_stats(&mint_a).await?;
+        let swap = &stats[1];
+        assert_eq!((4, 1), (swap.requests, swap.errors));
+        assert_eq!((Some(20), Some(400)), (swap.p50_ms, swap.p99_ms));
+        assert_eq!(0.25, swap.error_rate());
+
+        let stats = wallet.endpoint_stats(&mint_b).await?;
+        assert_eq!(1, stats.len());
+        assert_eq!(Some(900), stats[0].p50_ms);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_get_balance() -> anyhow::Result<()> {
-        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
-        let fixture: TokenV3 = fixture.try_into()?;
-        let local_store = SqliteLocalStore::with_in_memory().await?;
-        let mut tx = local_store.begin_tx().await?;
-        local_store.add_proofs(&mut tx, &fixture.proofs()).await?;
-        tx.commit().await?;
-
+    async fn test_enqueue_receive_unknown_mint() -> anyhow::Result<()> {
         let wallet = WalletBuilder::new()
             .with_client(create_mock())
-            .with_localstore(local_store)
+            .with_localstore(MemoryLocalStore::new())
             .build()
             .await?;
 
-        let result = wallet.get_balance().await?;
-        assert_eq!(60, result);
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let result = wallet.enqueue_receive(&tokens).await;
+        assert!(matches!(result, Err(MokshaWalletError::UnknownMint(_))));
+        assert!(wallet.get_receive_queue().await?.is_empty());
         Ok(())
     }
-    // FIXME
 
     #[tokio::test]
-    async fn test_pay_invoice() -> anyhow::Result<()> {
-        let fixture = read_fixture("token_60.cashu")?; // 60 tokens (4,8,16,32)
-
-        let local_store = SqliteLocalStore::with_in_memory().await?;
-        let fixture: TokenV3 = fixture.try_into()?;
-        let mut tx = local_store.begin_tx().await?;
-        local_store.add_proofs(&mut tx, &fixture.proofs()).await?;
-        let wallet_keyset = create_test_wallet_keyset()?;
-        local_store.upsert_keyset(&mut tx, &wallet_keyset).await?;
-        tx.commit().await?;
-
-        let melt_response =
-            read_fixture_as::<PostMeltBolt11Response>("post_melt_response_21.json")?; // 60 tokens (4,8,16,32)
-        let mut mock_client = create_mock();
-        mock_client
-            .expect_post_melt_bolt11()
-            .returning(move |_, _, _, _| Ok(melt_response.clone()));
-
-        let quote_response =
-            read_fixture_as::<PostMeltQuoteBolt11Response>("post_melt_quote_response.json")?;
-        mock_client
-            .expect_post_melt_quote_bolt11()
-            .returning(move |_, _, _| Ok(quote_response.clone()));
+    async fn test_send_tokens_with_memo() -> anyhow::Result<()> {
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
 
-        let swap_response = read_fixture_as::<PostSwapResponse>("post_swap_response_24_40.json")?;
-        mock_client
-            .expect_post_swap()
-            .returning(move |_, _, _| Ok(swap_response.clone()));
-
-        let mint_url = Url::parse("http://localhost:8080/").expect("invalid url");
-        let wallet = WalletBuilder::new()
-            .with_client(mock_client)
-            .with_localstore(local_store)
-            .build()
+        // 4 sats are sent without a swap
+        let token = wallet
+            .send_tokens_with_memo(&wallet_keyset, 4, Some("coffee".to_owned()))
             .await?;
+        let decoded = wallet.decode_token(&format!(" {}\n", token.serialize()?))?;
+        assert_eq!(Some("coffee".to_owned()), decoded.memo);
+        assert_eq!(4, decoded.total_amount());
+        assert_eq!(Some(wallet_keyset.mint_url), decoded.mint());
+        assert_eq!(56, wallet.get_balance().await?);
+        Ok(())
+    }
 
-        // FIXME fix mock response: Warning Error in swap: input 32 != output 24 + 40
-
-        // 21 sats
-        let invoice = "lnbcrt210n1pjg6mqhpp5pza5wzh0csjjuvfpjpv4zdjmg30vedj9ycv5tyfes9x7dp8axy0sdqqcqzzsxqyz5vqsp5vtxg4c5tw2s2zxxya2a7an0psn9mcfmlqctxzntm3sngnpyk3muq9qyyssqf8z5f90yu3wrmsufnnza25qjlnvc6ukdr094ckzn63ktcy6z5fw5mxf9skndpg2p4648gfjfvvx4qg2lqvlryyycg5k7x9h4dw70t4qq37pegm".to_string();
+    #[tokio::test]
+    async fn test_receive_tokens_with_memo() -> anyhow::Result<()> {
+        let mut client = create_mock();
+        client.expect_post_swap().returning(|_, _, outputs| {
+            Ok(PostSwapResponse {
+                signatures: outputs.iter().map(sign_blinded_message).collect(),
+                input_fee: None,
+            })
+        });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
 
-        let quote = wallet
-            .get_melt_quote_bolt11(&mint_url, invoice.clone(), CurrencyUnit::Sat)
+        let token = wallet
+            .send_tokens_with_memo(&wallet_keyset, 4, Some("coffee".to_owned()))
             .await?;
-        let result = wallet.pay_invoice(&wallet_keyset, &quote, invoice).await?;
-        assert!(result.0.paid);
+        let summary = wallet
+            .receive_serialized_tokens(&wallet_keyset, &token.serialize()?)
+            .await?;
+        assert_eq!(Some("coffee".to_owned()), summary.memo);
+        assert_eq!(4, summary.total_amount);
+        assert_eq!(60, wallet.get_balance().await?);
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_pay_invoice_can_not_melt() -> anyhow::Result<()> {
-        let fixture = read_fixture("token_64.cashu")?; // 60 tokens (4,8,16,32)
-        let tokens: TokenV3 = fixture.try_into()?;
-
-        let localstore = SqliteLocalStore::with_in_memory()
-            .await
-            .expect("Could not create localstore");
-
-        let mut tx = localstore.begin_tx().await?;
-        localstore.add_proofs(&mut tx, &tokens.proofs()).await?;
-        assert_eq!(64, localstore.get_proofs(&mut tx).await?.total_amount());
+    async fn test_receive_tokens_lenient() -> anyhow::Result<()> {
         let wallet_keyset = create_test_wallet_keyset()?;
-        localstore.upsert_keyset(&mut tx, &wallet_keyset).await?;
-        tx.commit().await?;
+        let proofs = proofs_for_keyset(&wallet_keyset)?;
+        let spent = proofs
+            .proofs()
+            .into_iter()
+            .find(|proof| proof.amount == 8)
+            .expect("fixture has a proof of 8")
+            .y()?;
 
-        let melt_response =
-            read_fixture_as::<PostMeltBolt11Response>("post_melt_response_not_paid.json")?;
+        let mut client = create_mock();
+        client.expect_post_checkstate().returning(move |_, ys| {
+            Ok(PostCheckStateResponse {
+                states: ys
+                    .into_iter()
+                    .map(|y| ProofState {
+                        y,
+                        state: if y == spent {
+                            State::Spent
+                        } else {
+                            State::Unspent
+                        },
+                        witness: None,
+                    })
+                    .collect(),
+            })
+        });
+        client
+            .expect_post_swap()
+            .times(1)
+            .returning(|_, inputs, outputs| {
+                // the spent proof is not sent to the mint
+                assert_eq!(52, inputs.total_amount());
+                Ok(PostSwapResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })
+            });
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(client).await?;
+
+        let token = TokenV3::new(Token {
+            mint: Some(wallet_keyset.mint_url.clone()),
+            proofs,
+        });
+        let report = wallet
+            .receive_tokens_lenient(&wallet_keyset, &token)
+            .await?;
+        assert_eq!(
+            ReceiveReport {
+                redeemed_amount: 52,
+                redeemed_proofs: 3,
+                skipped_amount: 8,
+                skipped_proofs: 1,
+            },
+            report
+        );
+        assert_eq!(60 + 52, wallet.get_balance().await?);
+        Ok(())
+    }
 
-        let mut mock_client = create_mock();
-        mock_client
-            .expect_post_melt_bolt11()
-            .returning(move |_, _, _, _| Ok(melt_response.clone()));
+    #[tokio::test]
+    async fn test_inspect_token() -> anyhow::Result<()> {
+        let (wallet, _) = create_wallet_with_proofs(create_mock()).await?;
+        let token: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+
+        let summary = wallet.inspect_token(&token);
+        assert_eq!(
+            TokenSummary {
+                total_amount: 64,
+                mint_urls: vec!["http://127.0.0.1:3338/".to_owned()],
+                denominations: vec![64],
+                proof_count: 1,
+                memo: None,
+            },
+            summary
+        );
+        // inspecting doesn't redeem the token
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
 
-        let quote_response =
-            read_fixture_as::<PostMeltQuoteBolt11Response>("post_melt_quote_response.json")?;
-        mock_client
-            .expect_post_melt_quote_bolt11()
-            .returning(move |_, _, _| Ok(quote_response.clone()));
-        let swap_response = read_fixture_as::<PostSwapResponse>("post_swap_response_24_40.json")?;
-        mock_client
-            .expect_post_swap()
-            .returning(move |_, _, _| Ok(swap_response.clone()));
+    #[tokio::test]
+    async fn test_decode_token_invalid() -> anyhow::Result<()> {
+        let (wallet, wallet_keyset) = create_wallet_with_proofs(create_mock()).await?;
+
+        let without_mint = TokenV3 {
+            tokens: vec![Token {
+                mint: None,
+                proofs: proofs_for_keyset(&wallet_keyset)?,
+            }],
+            currency_unit: None,
+            memo: None,
+        };
+        let result = wallet.decode_token(&without_mint.serialize()?);
+        assert!(
+            matches!(result, Err(MokshaWalletError::InvalidToken(ref msg)) if msg == "token entry 0 has no mint url")
+        );
 
-        let wallet = WalletBuilder::default()
-            .with_client(mock_client)
-            .with_localstore(localstore.clone())
-            .build()
-            .await?;
+        let result = wallet.decode_token("cashuAeyJ0b2tlbiI6W3sicHJvb2Zz!!");
+        assert!(
+            matches!(result, Err(MokshaWalletError::DecodeToken(ref segment, _)) if segment == "eyJ0b2tlbiI6W3sicHJvb2Zz!!")
+        );
 
-        // 21 sats
-        let invoice = "lnbcrt210n1pjg6mqhpp5pza5wzh0csjjuvfpjpv4zdjmg30vedj9ycv5tyfes9x7dp8axy0sdqqcqzzsxqyz5vqsp5vtxg4c5tw2s2zxxya2a7an0psn9mcfmlqctxzntm3sngnpyk3muq9qyyssqf8z5f90yu3wrmsufnnza25qjlnvc6ukdr094ckzn63ktcy6z5fw5mxf9skndpg2p4648gfjfvvx4qg2lqvlryyycg5k7x9h4dw70t4qq37pegm".to_string();
+        let result = wallet.decode_token("cashuCeyJ0b2tlbiI6W119");
+        assert!(
+            matches!(result, Err(MokshaWalletError::DecodeToken(ref segment, _)) if segment == "cashuC")
+        );
 
-        let quote = wallet
-            .get_melt_quote_bolt11(&wallet_keyset.mint_url, invoice.clone(), CurrencyUnit::Sat)
-            .await?;
+        // nothing was received
+        let result = wallet
+            .receive_serialized_tokens(&wallet_keyset, "cashuA")
+            .await;
+        assert!(matches!(result, Err(MokshaWalletError::DecodeToken(_, _))));
+        assert_eq!(60, wallet.get_balance().await?);
+        Ok(())
+    }
 
-        let wallet_keyset = create_test_wallet_keyset()?;
+    #[tokio::test]
+    async fn test_tokens_overlap() -> anyhow::Result<()> {
+        let (wallet, _) = create_wallet_with_proofs(create_mock()).await?;
+        let token_60: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let token_64: TokenV3 = read_fixture("token_64.cashu")?.try_into()?;
+        let proofs = token_60.proofs().proofs();
+        let mint_url = token_60.mint().expect("mint is missing");
+        let first: TokenV3 = (mint_url.clone(), proofs[0..2].to_vec().into()).into();
+        let second: TokenV3 = (mint_url.clone(), proofs[1..4].to_vec().into()).into();
+        let third: TokenV3 = (mint_url, proofs[2..4].to_vec().into()).into();
+
+        assert!(wallet.tokens_overlap(&token_60, &token_60));
+        assert!(wallet.tokens_overlap(&first, &second));
+        assert!(wallet.tokens_overlap(&second, &first));
+        assert!(wallet.tokens_overlap(&token_60, &third));
+        assert!(!wallet.tokens_overlap(&first, &third));
+        assert!(!wallet.tokens_overlap(&token_60, &token_64));
+        assert!(!wallet.tokens_overlap(&token_60, &TokenV3::empty()));
+        Ok(())
+    }
 
-        let result = wallet.pay_invoice(&wallet_keyset, &quote, invoice).await?;
-        assert!(!result.0.paid);
-        let mut tx = localstore.begin_tx().await?;
-        assert_eq!(64, localstore.get_proofs(&mut tx).await?.total_amount());
-        assert!(!result.0.paid);
+    #[tokio::test]
+    async fn test_enqueue_receive_rejects_overlapping_token() -> anyhow::Result<()> {
+        let (wallet, _) = create_wallet_with_proofs(create_mock()).await?;
+        let tokens: TokenV3 = read_fixture("token_60.cashu")?.try_into()?;
+        let proofs = tokens.proofs().proofs();
+        let mint_url = tokens.mint().expect("mint is missing");
+        let first: TokenV3 = (mint_url.clone(), proofs[0..2].to_vec().into()).into();
+        let replay: TokenV3 = (mint_url.clone(), proofs[1..3].to_vec().into()).into();
+        let disjoint: TokenV3 = (mint_url, proofs[2..4].to_vec().into()).into();
+
+        let id = wallet.enqueue_receive(&first).await?;
+        let result = wallet.enqueue_receive(&replay).await;
+        assert!(
+            matches!(result, Err(MokshaWalletError::InvalidToken(ref reason)) if reason.contains(&id.to_string()))
+        );
+        wallet.enqueue_receive(&disjoint).await?;
+        assert_eq!(2, wallet.get_receive_queue().await?.len());
         Ok(())
     }
 
@@ -1210,4 +9711,256 @@ mod tests {
         );
         Ok(wallet_keyset)
     }
+
+    /// Answers a request to the mock mint of [`serve_mint`]. The signed outputs are kept for
+    /// restoring them.
+    fn mint_response(
+        keys: &MintKeyset,
+        signed: &std::sync::Mutex<Vec<BlindedMessage>>,
+        path: &str,
+        body: &str,
+    ) -> anyhow::Result<String> {
+        Ok(match path {
+            "/v1/info" => "{}".to_owned(),
+            "/v1/keysets" => serde_json::to_string(&Keysets::new(
+                keys.keyset_id.clone(),
+                CurrencyUnit::Sat,
+                true,
+            ))?,
+            "/v1/swap" => {
+                let request: PostSwapRequest = serde_json::from_str(body)?;
+                signed
+                    .lock()
+                    .expect("poisoned")
+                    .extend(request.outputs.iter().cloned());
+                serde_json::to_string(&PostSwapResponse {
+                    signatures: request.outputs.iter().map(sign_blinded_message).collect(),
+                    input_fee: None,
+                })?
+            }
+            "/v1/restore" => {
+                let request: PostRestoreRequest = serde_json::from_str(body)?;
+                let signed = signed.lock().expect("poisoned");
+                let outputs = request
+                    .outputs
+                    .into_iter()
+                    .filter(|output| signed.iter().any(|s| s.b_ == output.b_))
+                    .collect::<Vec<_>>();
+                serde_json::to_string(&PostRestoreResponse {
+                    signatures: outputs.iter().map(sign_blinded_message).collect(),
+                    outputs,
+                })?
+            }
+            "/v1/checkstate" => {
+                let request: PostCheckStateRequest = serde_json::from_str(body)?;
+                serde_json::to_string(&PostCheckStateResponse {
+                    states: request
+                        .ys
+                        .into_iter()
+                        .map(|y| ProofState {
+                            y,
+                            state: State::Unspent,
+                            witness: None,
+                        })
+                        .collect(),
+                })?
+            }
+            _ => serde_json::to_string(&KeysResponse::new(KeyResponse {
+                keys: keys.public_keys.clone(),
+                id: keys.keyset_id.clone(),
+                unit: CurrencyUnit::Sat,
+            }))?,
+        })
+    }
+
+    /// Starts a mock mint with the keyset of [`create_mock`] over http
+    async fn serve_mint() -> anyhow::Result<Url> {
+        serve_mint_with(false).await
+    }
+
+    /// Like [`serve_mint`], but if `truncate_swaps` is set, the connection is closed after the
+    /// first half of the body of swap responses, like a reverse proxy that limits their size.
+    /// Melts are never answered, like a mint that hangs during the payment.
+    async fn serve_mint_with(truncate_swaps: bool) -> anyhow::Result<Url> {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+        let keys = MintKeyset::new("mykey", "");
+        let signed = std::sync::Mutex::new(vec![]);
+        tokio::spawn(async move {
+            let mut hanging = vec![];
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut stream = BufReader::new(stream);
+                let (mut request_line, mut content_length) = (String::new(), 0);
+                let _ = stream.read_line(&mut request_line).await;
+                loop {
+                    let mut header = String::new();
+                    let _ = stream.read_line(&mut header).await;
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some(length) = header.to_lowercase().strip_prefix("content-length:") {
+                        content_length = length.trim().parse().unwrap_or_default();
+                    }
+                }
+                let mut body = vec![0; content_length];
+                let _ = stream.read_exact(&mut body).await;
+
+                let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+                if path == "/v1/melt/bolt11" {
+                    hanging.push(stream);
+                    continue;
+                }
+                let body = mint_response(&keys, &signed, path, &String::from_utf8_lossy(&body))
+                    .expect("invalid request");
+                let sent = match truncate_swaps && path == "/v1/swap" {
+                    true => &body[..body.len() / 2],
+                    false => &body,
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{sent}",
+                    body.len()
+                );
+                let _ = stream.get_mut().write_all(response.as_bytes()).await;
+            }
+        });
+        Ok(url)
+    }
+
+    /// Adds the mint, receives the fixture proofs and sends 10 sats, which needs a swap
+    async fn run_session(
+        builder: WalletBuilder<MemoryLocalStore>,
+        mint_url: &Url,
+    ) -> anyhow::Result<(Proofs, Vec<WalletKeyset>)> {
+        let wallet = builder
+            .with_localstore(MemoryLocalStore::new())
+            .with_seed_words(
+                "half depart obvious quality work element tank gorilla view sugar picture humble",
+            )
+            .build()
+            .await?;
+        let keysets = wallet.add_mint_keysets(mint_url).await?;
+
+        let mut tx = wallet.localstore.begin_tx().await?;
+        wallet
+            .localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&keysets[0])?)
+            .await?;
+        tx.commit().await?;
+
+        let token = wallet.send_tokens(&keysets[0], 10).await?;
+        assert_eq!(10, token.total_amount());
+
+        let mut tx = wallet.localstore.begin_tx().await?;
+        let proofs = wallet.localstore.get_proofs(&mut tx).await?;
+        let keysets = wallet.localstore.get_keysets(&mut tx).await?;
+        tx.commit().await?;
+        Ok((proofs, keysets))
+    }
+
+    #[tokio::test]
+    async fn test_swap_response_truncated() -> anyhow::Result<()> {
+        let mint_url = serve_mint_with(true).await?;
+        let wallet = WalletBuilder::<MemoryLocalStore>::new()
+            .with_localstore(MemoryLocalStore::new())
+            .build()
+            .await?;
+        let keysets = wallet.add_mint_keysets(&mint_url).await?;
+        let mut tx = wallet.localstore.begin_tx().await?;
+        wallet
+            .localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&keysets[0])?)
+            .await?;
+        tx.commit().await?;
+
+        let result = wallet.send_tokens(&keysets[0], 10).await;
+        assert!(
+            matches!(result, Err(MokshaWalletError::ResponseTruncated { ref operation }) if operation == "/v1/swap")
+        );
+        assert!(result.err().is_some_and(|e| e.is_funds_at_risk()));
+
+        // the mint executed the swap, the outputs were restored and the inputs are spent
+        let mut tx = wallet.localstore.begin_tx().await?;
+        let spent = wallet
+            .localstore
+            .get_proofs_by_state(&mut tx, ProofStatus::Spent)
+            .await?;
+        tx.commit().await?;
+        assert!(spent.total_amount() >= 10);
+        assert_eq!(60, wallet.get_balance().await?);
+        assert_eq!(0, wallet.recover().await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_operation_timeout_keeps_melt_recoverable() -> anyhow::Result<()> {
+        let mint_url = serve_mint().await?;
+        let wallet = WalletBuilder::<MemoryLocalStore>::new()
+            .with_localstore(MemoryLocalStore::new())
+            .build()
+            .await?
+            .with_operation_timeout(Duration::from_millis(200));
+        let keysets = wallet.add_mint_keysets(&mint_url).await?;
+        let mut tx = wallet.localstore.begin_tx().await?;
+        wallet
+            .localstore
+            .add_proofs(&mut tx, &proofs_for_keyset(&keysets[0])?)
+            .await?;
+        tx.commit().await?;
+        let quote = PostMeltQuoteBolt11Response {
+            quote: "quote".to_string(),
+            amount: 21,
+            fee_reserve: 4,
+            paid: false,
+            expiry: None,
+        };
+
+        let result = wallet
+            .pay_invoice(&keysets[0], &quote, INVOICE_21_SATS.to_string())
+            .await;
+        assert!(matches!(
+            result,
+            Err(MokshaWalletError::OperationTimeout(timeout)) if timeout == Duration::from_millis(200)
+        ));
+
+        // the proofs of the melt are not lost, they stay reserved until the outcome is known
+        assert_eq!(35, wallet.get_balance().await?);
+        let pending_melts = wallet.get_pending_melts().await?;
+        assert_eq!(1, pending_melts.len());
+        assert_eq!(25, pending_melts[0].proofs.total_amount());
+
+        // the mint reports the proofs as unspent, the payment didn't happen
+        let result = wallet.recover_pending().await?;
+        assert_eq!(25, result.released.total_amount());
+        assert_eq!(60, wallet.get_balance().await?);
+        assert!(wallet.get_pending_melts().await?.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_replay_recorded_session() -> anyhow::Result<()> {
+        let mint_url = serve_mint().await?;
+        let dir = tempfile::tempdir()?;
+        let session = dir.path().join("session.jsonl");
+
+        let recording = WalletBuilder::new().with_recorder(&session)?;
+        let recorded = run_session(recording, &mint_url).await?;
+        assert_eq!(50, recorded.0.total_amount());
+
+        let replaying =
+            WalletBuilder::new().with_client(CrossPlatformHttpClient::replay(&session)?);
+        let replayed = run_session(replaying, &mint_url).await?;
+        assert_eq!(recorded, replayed);
+
+        let content = std::fs::read_to_string(&session)?;
+        assert!(content.contains("/v1/swap"));
+        for proof in proofs_for_keyset(&recorded.1[0])?.proofs() {
+            assert!(!content.contains(&proof.secret));
+        }
+        for proof in recorded.0.proofs() {
+            assert!(!content.contains(&proof.secret));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,17 @@
+//! Tokens, proofs and amounts that are shared by the wallet and the mint.
+//!
+//! ```
+//! use moksha::core::{Amount, TokenV3};
+//!
+//! let token: TokenV3 = "cashuBpGF0gaJhaUgArSaMTR9YJmFwgaNhYQFhc3hAOWE2ZGJiODQ3YmQyMzJiYTc2ZGIwZGYxOTcyMTZiMjlkM2I4Y2MxNDU1M2NkMjc4MjdmYzFjYzk0MmZlZGI0ZWFjWCEDhhhUP_trhpXfStS6vN6So0qWvc2X3O4NfM-Y1HISZ5JhZGlUaGFuayB5b3VhbXVodHRwOi8vbG9jYWxob3N0OjMzMzhhdWNzYXQ=".parse()?;
+//! assert_eq!(1, token.total_amount());
+//! assert_eq!(vec![4, 16], Amount(20).split().into_iter().collect::<Vec<_>>());
+//! # Ok::<(), moksha::core::Error>(())
+//! ```
+
+pub use moksha_core::amount::Amount;
+pub use moksha_core::error::MokshaCoreError as Error;
+pub use moksha_core::keyset::KeysetId;
+pub use moksha_core::primitives::CurrencyUnit;
+pub use moksha_core::proof::{Proof, Proofs};
+pub use moksha_core::token::{Token, TokenV3, TokenV4};
@@ -0,0 +1,15 @@
+//! The public api of moksha, a cashu wallet and mint.
+//!
+//! This crate re-exports the types of the `moksha-core`, `moksha-wallet` and `moksha-mint` crates
+//! that are meant to be used by applications. It follows semver, while the internal crates can
+//! change between minor versions.
+//!
+//! - [`core`] tokens, proofs and amounts
+//! - [`wallet`] the wallet (feature `wallet`)
+//! - [`mint`] the mint for embedding it into an application (feature `mint`)
+
+pub mod core;
+#[cfg(feature = "mint")]
+pub mod mint;
+#[cfg(feature = "wallet")]
+pub mod wallet;
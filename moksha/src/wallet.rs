@@ -0,0 +1,34 @@
+//! The cashu wallet.
+//!
+//! A wallet is created with the [`WalletBuilder`] and stores its proofs in a [`LocalStore`].
+//!
+//! ```no_run
+//! use moksha::{
+//!     core::TokenV3,
+//!     wallet::{CrossPlatformHttpClient, SqliteLocalStore, Wallet},
+//! };
+//!
+//! # async fn receive() -> Result<(), moksha::wallet::Error> {
+//! let localstore = SqliteLocalStore::with_path("wallet.db".to_owned()).await?;
+//! let wallet: Wallet<_, CrossPlatformHttpClient> = Wallet::builder()
+//!     .with_localstore(localstore)
+//!     .build()
+//!     .await?;
+//!
+//! let tokens: TokenV3 = "cashuA...".parse()?;
+//! let mint_url = tokens.mint().expect("token without mint");
+//! let keysets = wallet.add_mint_keysets(&mint_url).await?;
+//! wallet.receive_tokens(&keysets[0], &tokens).await?;
+//! println!("balance: {} sats", wallet.get_balance().await?);
+//! # Ok(())
+//! # }
+//! ```
+
+pub use moksha_wallet::client::CashuClient;
+pub use moksha_wallet::error::MokshaWalletError as Error;
+pub use moksha_wallet::http::CrossPlatformHttpClient;
+pub use moksha_wallet::localstore::memory::MemoryLocalStore;
+#[cfg(not(target_arch = "wasm32"))]
+pub use moksha_wallet::localstore::sqlite::SqliteLocalStore;
+pub use moksha_wallet::localstore::{LocalStore, LocalStoreTransaction, WalletKeyset};
+pub use moksha_wallet::wallet::{Wallet, WalletBuilder};
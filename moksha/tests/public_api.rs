@@ -0,0 +1,60 @@
+//! Fails to compile if an item of the public api is removed or renamed. Changes to this file are
+//! changes of the semver anchor of moksha.
+
+use moksha::core::{self, Amount, CurrencyUnit, KeysetId, Proof, Proofs, Token, TokenV3, TokenV4};
+use moksha::mint::{
+    self, DatabaseConfig, LightningFeeConfig, LightningType, LnbitsLightningSettings, Mint,
+    MintBuilder, MintInfoConfig, ServerConfig,
+};
+use moksha::wallet::{
+    self, CashuClient, CrossPlatformHttpClient, LocalStore, LocalStoreTransaction,
+    MemoryLocalStore, SqliteLocalStore, Wallet, WalletBuilder, WalletKeyset,
+};
+
+fn assert_type<T>() {}
+
+fn assert_client<C: CashuClient>() {}
+
+fn assert_localstore<L: LocalStore>() {}
+
+fn assert_transaction<T: LocalStoreTransaction>() {}
+
+#[test]
+fn test_core_api() {
+    assert_type::<Amount>();
+    assert_type::<CurrencyUnit>();
+    assert_type::<KeysetId>();
+    assert_type::<Proof>();
+    assert_type::<Proofs>();
+    assert_type::<Token>();
+    assert_type::<TokenV3>();
+    assert_type::<TokenV4>();
+    assert_type::<core::Error>();
+}
+
+#[test]
+fn test_wallet_api() {
+    assert_type::<Wallet<SqliteLocalStore, CrossPlatformHttpClient>>();
+    assert_type::<WalletBuilder<SqliteLocalStore, CrossPlatformHttpClient>>();
+    assert_type::<WalletKeyset>();
+    assert_client::<CrossPlatformHttpClient>();
+    assert_localstore::<SqliteLocalStore>();
+    assert_localstore::<MemoryLocalStore>();
+    assert_transaction::<<MemoryLocalStore as LocalStore>::Tx>();
+    let _: wallet::Error = core::Error::InvalidToken.into();
+}
+
+#[test]
+fn test_mint_api() {
+    assert_type::<Mint>();
+    assert_type::<MintBuilder>();
+    assert_type::<DatabaseConfig>();
+    assert_type::<LightningFeeConfig>();
+    assert_type::<MintInfoConfig>();
+    assert_type::<ServerConfig>();
+    assert_type::<LightningType>();
+    assert_type::<LnbitsLightningSettings>();
+    assert_type::<mint::Error>();
+    let _: fn(Mint) -> _ = mint::router;
+    let _ = mint::run_server;
+}
@@ -0,0 +1,195 @@
+//! Load test harness for the mint.
+//!
+//! Starts the mint in-process against postgres and the lnbits mock of the integrationtests (or
+//! uses the mint at `--mint-url`) and runs a weighted mix of operations with concurrent workers.
+//! Prints throughput, error rates and latency percentiles per operation and compares them with a
+//! baseline of an earlier run.
+
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+use anyhow::{bail, Context};
+use clap::Parser;
+use moksha_wallet::{client::CashuClient, http::CrossPlatformHttpClient};
+use mokshamint::{
+    config::DatabaseConfig,
+    lightning::{lnbits::LnbitsLightningSettings, LightningType},
+    mint::MintBuilder,
+};
+use url::Url;
+
+use crate::{
+    report::Report,
+    workload::{InvoiceSource, Mix, Worker},
+};
+
+mod report;
+mod workload;
+
+#[derive(Debug, Parser)]
+#[command(about = "Load test harness for the moksha mint")]
+struct Opts {
+    /// Postgres database of the in-process mint. Use an empty database, the run adds quotes and
+    /// used proofs.
+    #[clap(long, env = "MINT_DB_URL")]
+    db_url: Option<String>,
+
+    /// Connections of the in-process mint to the database
+    #[clap(long, default_value_t = 10)]
+    db_max_connections: u32,
+
+    /// Url of a running mint that is tested instead of the in-process mint. The mint must use a
+    /// fake lightning backend that pays all invoices.
+    #[clap(long)]
+    mint_url: Option<Url>,
+
+    /// Port of the lnbits mock that is started as the fake lightning backend
+    #[clap(long, default_value_t = 6110)]
+    lightning_port: u16,
+
+    /// Number of concurrent workers
+    #[clap(long, default_value_t = 8)]
+    concurrency: usize,
+
+    /// Duration of the run in seconds
+    #[clap(long, default_value_t = 30)]
+    duration: u64,
+
+    /// Weighted operations: mint, mint-status, swap and melt
+    #[clap(long, default_value = "swap=70,mint-status=20,melt=10")]
+    mix: Mix,
+
+    /// Results of an earlier run to compare with
+    #[clap(long)]
+    baseline: Option<PathBuf>,
+
+    /// Writes the results of this run as json
+    #[clap(long)]
+    output: Option<PathBuf>,
+
+    /// Allowed deviation from the baseline before a metric counts as regression
+    #[clap(long, default_value_t = 0.2)]
+    tolerance: f64,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opts = Opts::parse();
+
+    let lightning_url = Url::parse(&format!("http://127.0.0.1:{}/", opts.lightning_port))?;
+    let lightning_port = opts.lightning_port;
+    tokio::spawn(async move { itests::lnbitsmock::run_server(lightning_port).await });
+
+    let (target, mint_url) = match opts.mint_url {
+        Some(ref mint_url) => (mint_url.to_string(), mint_url.clone()),
+        None => {
+            let db_url = opts
+                .db_url
+                .clone()
+                .context("either --db-url or --mint-url is required")?;
+            let addr = start_mint(db_url, opts.db_max_connections, &lightning_url).await?;
+            (
+                "in-process".to_owned(),
+                Url::parse(&format!("http://{addr}/"))?,
+            )
+        }
+    };
+
+    let keyset = CrossPlatformHttpClient::new()
+        .get_keys(&mint_url)
+        .await?
+        .keysets
+        .into_iter()
+        .next()
+        .context("the mint has no keyset")?;
+
+    // the futures of the wallet client are not Send, so every worker gets its own thread
+    let duration = Duration::from_secs(opts.duration);
+    let deadline = tokio::time::Instant::now() + duration;
+    let workers = (0..opts.concurrency)
+        .map(|_| {
+            let (mint_url, keyset, lightning_url) =
+                (mint_url.clone(), keyset.clone(), lightning_url.clone());
+            let mix = opts.mix.clone();
+            std::thread::spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?;
+                runtime.block_on(async move {
+                    let mut worker =
+                        Worker::new(mint_url, keyset, InvoiceSource::new(lightning_url));
+                    while tokio::time::Instant::now() < deadline {
+                        let op = mix.pick(worker.rng());
+                        worker.run(op).await;
+                    }
+                    Ok::<_, anyhow::Error>(worker.into_samples())
+                })
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let mut samples = vec![];
+    for worker in workers {
+        let worker_samples = tokio::task::spawn_blocking(move || worker.join())
+            .await?
+            .map_err(|_| anyhow::anyhow!("worker panicked"))??;
+        samples.extend(worker_samples);
+    }
+
+    let report = Report::new(
+        target,
+        opts.mix.to_string(),
+        opts.concurrency,
+        duration,
+        samples,
+    );
+    println!("{report}");
+
+    if let Some(ref output) = opts.output {
+        report.write(output)?;
+        println!("results written to {}", output.display());
+    }
+
+    if let Some(ref baseline) = opts.baseline {
+        let regressions = report.compare(&Report::read(baseline)?, opts.tolerance);
+        if !regressions.is_empty() {
+            for regression in &regressions {
+                eprintln!("regression: {regression}");
+            }
+            bail!(
+                "{} metrics are worse than in {}",
+                regressions.len(),
+                baseline.display()
+            );
+        }
+        println!("no regressions compared to {}", baseline.display());
+    }
+    Ok(())
+}
+
+/// Serves the router of the mint on a random local port
+async fn start_mint(
+    db_url: String,
+    max_connections: u32,
+    lightning_url: &Url,
+) -> anyhow::Result<SocketAddr> {
+    let mint = MintBuilder::new()
+        .with_private_key("moksha-loadtest".to_owned())
+        .with_db(Some(DatabaseConfig {
+            db_url,
+            max_connections,
+        }))
+        .with_lightning(LightningType::Lnbits(LnbitsLightningSettings::new(
+            "loadtest",
+            lightning_url.as_str(),
+        )))
+        .with_fee(Some((0.0, 0).into()))
+        .build()
+        .await?;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        axum::serve(listener, mokshamint::server::app(mint).into_make_service()).await
+    });
+    Ok(addr)
+}
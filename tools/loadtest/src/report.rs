@@ -0,0 +1,276 @@
+//! Aggregated results of a load test run and the comparison against a baseline.
+//!
+//! Reports are stored as json, so the results of a run can be used as the baseline of later runs.
+
+use std::{collections::BTreeMap, fmt::Display, path::Path, time::Duration};
+
+use moksha_wallet::stats::percentile;
+use serde::{Deserialize, Serialize};
+
+use crate::workload::{Op, Sample};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    /// `in-process` or the url of the mint
+    pub target: String,
+    pub mix: String,
+    pub concurrency: usize,
+    pub duration_ms: u64,
+    pub ops_per_sec: f64,
+    pub ops: Vec<OpReport>,
+}
+
+/// Throughput, error rate and latency percentiles (in µs) of an operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpReport {
+    pub op: Op,
+    pub count: u64,
+    pub errors: u64,
+    pub ops_per_sec: f64,
+    pub error_rate: f64,
+    pub p50_us: Option<u64>,
+    pub p90_us: Option<u64>,
+    pub p99_us: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+impl Report {
+    pub fn new(
+        target: String,
+        mix: String,
+        concurrency: usize,
+        duration: Duration,
+        samples: Vec<Sample>,
+    ) -> Self {
+        let seconds = duration.as_secs_f64();
+        let total = samples.len();
+
+        let mut by_op = BTreeMap::<Op, Vec<Sample>>::new();
+        for sample in samples {
+            by_op.entry(sample.op).or_default().push(sample);
+        }
+        let ops = by_op
+            .into_iter()
+            .map(|(op, samples)| {
+                let mut latencies = samples
+                    .iter()
+                    .map(|sample| sample.latency.as_micros() as u64)
+                    .collect::<Vec<_>>();
+                latencies.sort_unstable();
+                let errors = samples.iter().filter(|s| s.error.is_some()).count() as u64;
+                let count = samples.len() as u64;
+                OpReport {
+                    op,
+                    count,
+                    errors,
+                    ops_per_sec: count as f64 / seconds,
+                    error_rate: errors as f64 / count as f64,
+                    p50_us: percentile(&latencies, 50),
+                    p90_us: percentile(&latencies, 90),
+                    p99_us: percentile(&latencies, 99),
+                    last_error: samples.into_iter().rev().find_map(|s| s.error),
+                }
+            })
+            .collect();
+
+        Self {
+            target,
+            mix,
+            concurrency,
+            duration_ms: duration.as_millis() as u64,
+            ops_per_sec: total as f64 / seconds,
+            ops,
+        }
+    }
+
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// Returns the metrics that are worse than in the baseline by more than `tolerance`
+    /// (e.g. 0.2 for 20%). Operations that are missing in one of the reports are ignored.
+    pub fn compare(&self, baseline: &Self, tolerance: f64) -> Vec<Regression> {
+        let mut regressions = vec![];
+        for current in &self.ops {
+            let Some(base) = baseline.ops.iter().find(|b| b.op == current.op) else {
+                continue;
+            };
+            if current.ops_per_sec < base.ops_per_sec * (1.0 - tolerance) {
+                regressions.push(Regression::new(
+                    current.op,
+                    "ops/s",
+                    base.ops_per_sec,
+                    current.ops_per_sec,
+                ));
+            }
+            if let (Some(base_p99), Some(p99)) = (base.p99_us, current.p99_us) {
+                if p99 as f64 > base_p99 as f64 * (1.0 + tolerance) {
+                    regressions.push(Regression::new(
+                        current.op,
+                        "p99 µs",
+                        base_p99 as f64,
+                        p99 as f64,
+                    ));
+                }
+            }
+            if current.error_rate > base.error_rate + tolerance / 10.0 {
+                regressions.push(Regression::new(
+                    current.op,
+                    "error rate",
+                    base.error_rate,
+                    current.error_rate,
+                ));
+            }
+        }
+        regressions
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "target: {}  mix: {}  concurrency: {}  duration: {}ms  total: {:.1} ops/s",
+            self.target, self.mix, self.concurrency, self.duration_ms, self.ops_per_sec
+        )?;
+        writeln!(
+            f,
+            "{:<12} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10}",
+            "op", "count", "errors", "ops/s", "p50 µs", "p90 µs", "p99 µs"
+        )?;
+        for op in &self.ops {
+            let us = |value: Option<u64>| value.map_or("-".to_owned(), |v| v.to_string());
+            writeln!(
+                f,
+                "{:<12} {:>8} {:>8} {:>10.1} {:>10} {:>10} {:>10}",
+                op.op.to_string(),
+                op.count,
+                op.errors,
+                op.ops_per_sec,
+                us(op.p50_us),
+                us(op.p90_us),
+                us(op.p99_us)
+            )?;
+            if let Some(ref error) = op.last_error {
+                writeln!(f, "{:<12} last error: {error}", "")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Regression {
+    pub op: Op,
+    pub metric: &'static str,
+    pub baseline: f64,
+    pub current: f64,
+}
+
+impl Regression {
+    fn new(op: Op, metric: &'static str, baseline: f64, current: f64) -> Self {
+        Self {
+            op,
+            metric,
+            baseline,
+            current,
+        }
+    }
+}
+
+impl Display for Regression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {}: {:.2} (baseline {:.2})",
+            self.op, self.metric, self.current, self.baseline
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::Report;
+    use crate::workload::{Op, Sample};
+
+    fn sample(op: Op, latency_ms: u64, error: Option<&str>) -> Sample {
+        Sample {
+            op,
+            latency: Duration::from_millis(latency_ms),
+            error: error.map(ToOwned::to_owned),
+        }
+    }
+
+    fn create_report(swap_latencies_ms: &[u64]) -> Report {
+        let mut samples = swap_latencies_ms
+            .iter()
+            .map(|latency| sample(Op::Swap, *latency, None))
+            .collect::<Vec<_>>();
+        samples.push(sample(Op::Melt, 40, Some("not enough proofs")));
+        samples.push(sample(Op::Melt, 20, None));
+        Report::new(
+            "in-process".to_owned(),
+            "swap=1,melt=1".to_owned(),
+            2,
+            Duration::from_secs(2),
+            samples,
+        )
+    }
+
+    #[test]
+    fn test_report_from_samples() {
+        let report = create_report(&[10, 20, 30, 40]);
+        assert_eq!(3.0, report.ops_per_sec);
+        assert_eq!(2, report.ops.len());
+
+        let swap = &report.ops[0];
+        assert_eq!((Op::Swap, 4, 0), (swap.op, swap.count, swap.errors));
+        assert_eq!(2.0, swap.ops_per_sec);
+        assert_eq!(Some(20_000), swap.p50_us);
+        assert_eq!(Some(40_000), swap.p99_us);
+
+        let melt = &report.ops[1];
+        assert_eq!((Op::Melt, 2, 1), (melt.op, melt.count, melt.errors));
+        assert_eq!(0.5, melt.error_rate);
+        assert_eq!(Some("not enough proofs".to_owned()), melt.last_error);
+    }
+
+    #[test]
+    fn test_report_roundtrip() -> anyhow::Result<()> {
+        let report = create_report(&[10, 20]);
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("results.json");
+        report.write(&path)?;
+        assert_eq!(report, Report::read(&path)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_compare() {
+        let baseline = create_report(&[10, 20, 30, 40]);
+        assert!(baseline.compare(&baseline, 0.2).is_empty());
+
+        // fewer and slower swaps
+        let current = create_report(&[10, 80]);
+        let regressions = current.compare(&baseline, 0.2);
+        let metrics = regressions
+            .iter()
+            .map(|r| (r.op, r.metric))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(Op::Swap, "ops/s"), (Op::Swap, "p99 µs")], metrics);
+        assert_eq!(2.0, regressions[0].baseline);
+        assert_eq!(1.0, regressions[0].current);
+
+        // within the tolerance
+        let current = create_report(&[10, 20, 30, 45]);
+        assert!(current.compare(&baseline, 0.2).is_empty());
+    }
+}
@@ -0,0 +1,397 @@
+//! The operations of the load test and the workers that run them.
+//!
+//! Every worker behaves like a wallet: it mints ecash from the fake lightning backend, splits its
+//! proofs, polls the state of its mint quotes and melts proofs to pay invoices.
+
+use std::{collections::HashMap, fmt::Display, str::FromStr, time::Duration};
+
+use anyhow::{bail, Context};
+use moksha_core::{
+    amount::Amount,
+    blind::{BlindedMessage, BlindedSignature, BlindingFactor},
+    dhke::Dhke,
+    primitives::{CurrencyUnit, KeyResponse},
+    proof::{Proof, Proofs},
+};
+use moksha_wallet::{client::CashuClient, http::CrossPlatformHttpClient};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use secp256k1::{PublicKey, SecretKey};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Amount of every mint operation in sats
+pub const MINT_AMOUNT: u64 = 64;
+
+/// Workers mint new ecash before swapping or melting if their balance drops below this amount
+pub const FUNDING_THRESHOLD: u64 = 32;
+
+/// Melted invoices are between 1 and this amount in sats
+pub const MAX_MELT_AMOUNT: u64 = 8;
+
+/// Number of mint quotes a worker keeps for the mint-status operation
+const MAX_QUOTES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Op {
+    Mint,
+    MintStatus,
+    Swap,
+    Melt,
+}
+
+impl Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Mint => "mint",
+            Self::MintStatus => "mint-status",
+            Self::Swap => "swap",
+            Self::Melt => "melt",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Op {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "mint" => Self::Mint,
+            "mint-status" => Self::MintStatus,
+            "swap" => Self::Swap,
+            "melt" => Self::Melt,
+            _ => bail!("unknown operation {s}"),
+        })
+    }
+}
+
+/// Weighted operations, e.g. `swap=70,mint-status=20,melt=10`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mix(Vec<(Op, u32)>);
+
+impl Mix {
+    pub fn pick(&self, rng: &mut impl Rng) -> Op {
+        let total = self.0.iter().map(|(_, weight)| weight).sum::<u32>();
+        let mut choice = rng.gen_range(0..total);
+        for (op, weight) in &self.0 {
+            if choice < *weight {
+                return *op;
+            }
+            choice -= weight;
+        }
+        unreachable!("choice is lower than the total weight")
+    }
+}
+
+impl Display for Mix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts = self
+            .0
+            .iter()
+            .map(|(op, weight)| format!("{op}={weight}"))
+            .collect::<Vec<_>>();
+        write!(f, "{}", parts.join(","))
+    }
+}
+
+impl FromStr for Mix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let ops = s
+            .split(',')
+            .map(|part| {
+                let (op, weight) = part
+                    .split_once('=')
+                    .with_context(|| format!("expected op=weight, got {part}"))?;
+                Ok((op.trim().parse()?, weight.trim().parse()?))
+            })
+            .collect::<anyhow::Result<Vec<(Op, u32)>>>()?;
+        if ops.iter().map(|(_, weight)| weight).sum::<u32>() == 0 {
+            bail!("the mix needs at least one operation with a weight > 0");
+        }
+        Ok(Self(ops))
+    }
+}
+
+/// Secrets and blinding factors of outputs, to unblind their signatures
+type Secrets = Vec<(String, BlindingFactor)>;
+
+/// Duration and outcome of a single operation
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub op: Op,
+    pub latency: Duration,
+    pub error: Option<String>,
+}
+
+/// Creates the invoices that are paid by the melt operations
+#[derive(Clone)]
+pub struct InvoiceSource {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl InvoiceSource {
+    /// Uses the lnbits api of the fake lightning backend at `url`
+    pub fn new(url: Url) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    async fn create_invoice(&self, amount_sat: u64) -> anyhow::Result<String> {
+        let response = self
+            .client
+            .post(self.url.join("api/v1/payments")?)
+            .json(&serde_json::json!({
+                "out": false,
+                "amount": amount_sat * 1_000,
+                "memo": "moksha-loadtest",
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<serde_json::Value>()
+            .await?;
+        response["payment_request"]
+            .as_str()
+            .map(ToOwned::to_owned)
+            .context("payment_request is missing")
+    }
+}
+
+pub struct Worker {
+    client: CrossPlatformHttpClient,
+    mint_url: Url,
+    keyset_id: String,
+    keys: HashMap<u64, PublicKey>,
+    invoices: InvoiceSource,
+    dhke: Dhke,
+    rng: StdRng,
+    proofs: Vec<Proof>,
+    quotes: Vec<String>,
+    samples: Vec<Sample>,
+}
+
+impl Worker {
+    /// Every worker has its own http client, so the workers don't share the stats of a client
+    pub fn new(mint_url: Url, keyset: KeyResponse, invoices: InvoiceSource) -> Self {
+        Self {
+            client: CrossPlatformHttpClient::new(),
+            mint_url,
+            keyset_id: keyset.id,
+            keys: keyset.keys,
+            invoices,
+            dhke: Dhke::new(),
+            rng: StdRng::from_entropy(),
+            proofs: vec![],
+            quotes: vec![],
+            samples: vec![],
+        }
+    }
+
+    pub fn rng(&mut self) -> &mut StdRng {
+        &mut self.rng
+    }
+
+    /// Runs the operation. Mints new ecash first, if the worker can't run it otherwise.
+    pub async fn run(&mut self, op: Op) {
+        let needs_funding = match op {
+            Op::Mint => false,
+            Op::MintStatus => self.quotes.is_empty(),
+            Op::Swap | Op::Melt => self.balance() < FUNDING_THRESHOLD,
+        };
+        if needs_funding {
+            self.execute(Op::Mint).await;
+        }
+        self.execute(op).await;
+    }
+
+    pub fn into_samples(self) -> Vec<Sample> {
+        self.samples
+    }
+
+    async fn execute(&mut self, op: Op) {
+        let start = tokio::time::Instant::now();
+        let result = match op {
+            Op::Mint => self.mint().await,
+            Op::MintStatus => self.mint_status().await,
+            Op::Swap => self.swap().await,
+            Op::Melt => self.melt().await,
+        };
+        self.samples.push(Sample {
+            op,
+            latency: start.elapsed(),
+            error: result.err().map(|err| err.to_string()),
+        });
+    }
+
+    fn balance(&self) -> u64 {
+        self.proofs.iter().map(|proof| proof.amount).sum()
+    }
+
+    async fn mint(&mut self) -> anyhow::Result<()> {
+        let quote = self
+            .client
+            .post_mint_quote_bolt11(&self.mint_url, MINT_AMOUNT, CurrencyUnit::Sat)
+            .await?
+            .quote;
+        let (outputs, secrets) =
+            self.create_outputs(&Amount(MINT_AMOUNT).split().into_iter().collect::<Vec<_>>())?;
+        let signatures = self
+            .client
+            .post_mint_bolt11(&self.mint_url, quote.clone(), outputs)
+            .await?
+            .signatures;
+        self.add_proofs(signatures, secrets)?;
+
+        if self.quotes.len() == MAX_QUOTES {
+            self.quotes.remove(0);
+        }
+        self.quotes.push(quote);
+        Ok(())
+    }
+
+    async fn mint_status(&mut self) -> anyhow::Result<()> {
+        let quote = self.quotes[self.rng.gen_range(0..self.quotes.len())].clone();
+        self.client
+            .get_mint_quote_bolt11(&self.mint_url, quote)
+            .await?;
+        Ok(())
+    }
+
+    /// Splits up to two proofs into a random amount and its change, like sending a part of them
+    async fn swap(&mut self) -> anyhow::Result<()> {
+        let inputs = self.take_proofs(2);
+        let total = inputs.iter().map(|proof| proof.amount).sum::<u64>();
+        let send = self.rng.gen_range(1..=total);
+        let amounts = Amount(send)
+            .split()
+            .into_iter()
+            .chain(Amount(total - send).split())
+            .collect::<Vec<_>>();
+
+        let (outputs, secrets) = self.create_outputs(&amounts)?;
+        let signatures = self
+            .client
+            .post_swap(&self.mint_url, Proofs::new(inputs), outputs)
+            .await?
+            .signatures;
+        self.add_proofs(signatures, secrets)
+    }
+
+    async fn melt(&mut self) -> anyhow::Result<()> {
+        let amount = self.rng.gen_range(1..=MAX_MELT_AMOUNT);
+        let invoice = self.invoices.create_invoice(amount).await?;
+        let quote = self
+            .client
+            .post_melt_quote_bolt11(&self.mint_url, invoice, CurrencyUnit::Sat)
+            .await?;
+
+        let needed = quote.amount + quote.fee_reserve;
+        let mut inputs = vec![];
+        self.proofs.sort_by_key(|proof| proof.amount);
+        while inputs.iter().map(|proof: &Proof| proof.amount).sum::<u64>() < needed {
+            inputs.push(self.proofs.pop().context("not enough proofs to melt")?);
+        }
+        let total = inputs.iter().map(|proof| proof.amount).sum::<u64>();
+
+        // blank outputs for the change, the mint sets their amounts
+        let blank_outputs = (u64::BITS - total.leading_zeros()) as usize;
+        let (outputs, secrets) = self.create_outputs(&vec![1; blank_outputs])?;
+        let change = self
+            .client
+            .post_melt_bolt11(&self.mint_url, Proofs::new(inputs), quote.quote, outputs)
+            .await?
+            .change;
+        self.add_proofs(change, secrets)
+    }
+
+    /// Removes the `count` largest proofs of the worker
+    fn take_proofs(&mut self, count: usize) -> Vec<Proof> {
+        self.proofs.sort_by_key(|proof| proof.amount);
+        let start = self.proofs.len().saturating_sub(count);
+        self.proofs.split_off(start)
+    }
+
+    fn create_outputs(
+        &mut self,
+        amounts: &[u64],
+    ) -> anyhow::Result<(Vec<BlindedMessage>, Secrets)> {
+        let secrets = amounts
+            .iter()
+            .map(|_| {
+                let secret = hex::encode(self.rng.gen::<[u8; 32]>());
+                let blinding_factor: BlindingFactor = SecretKey::new(&mut self.rng).into();
+                (secret, blinding_factor)
+            })
+            .collect::<Vec<_>>();
+        let outputs = amounts
+            .iter()
+            .zip(&secrets)
+            .map(|(amount, (secret, blinding_factor))| {
+                Ok(BlindedMessage {
+                    amount: *amount,
+                    b_: self.dhke.step1_alice(secret, blinding_factor)?,
+                    id: self.keyset_id.clone(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok((outputs, secrets))
+    }
+
+    /// Unblinds the signatures of the first outputs and keeps the resulting proofs
+    fn add_proofs(
+        &mut self,
+        signatures: Vec<BlindedSignature>,
+        secrets: Secrets,
+    ) -> anyhow::Result<()> {
+        for (signature, (secret, blinding_factor)) in signatures.into_iter().zip(secrets) {
+            let key = self
+                .keys
+                .get(&signature.amount)
+                .with_context(|| format!("no key for amount {}", signature.amount))?;
+            let c = self.dhke.step3_alice(signature.c_, blinding_factor, *key)?;
+            self.proofs
+                .push(Proof::new(signature.amount, secret, c, signature.id));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{Mix, Op};
+
+    #[test]
+    fn test_parse_mix() -> anyhow::Result<()> {
+        let mix = "swap=70, mint-status=20,melt=10".parse::<Mix>()?;
+        assert_eq!(
+            Mix(vec![(Op::Swap, 70), (Op::MintStatus, 20), (Op::Melt, 10)]),
+            mix
+        );
+        assert_eq!("swap=70,mint-status=20,melt=10", mix.to_string());
+
+        assert!("swap".parse::<Mix>().is_err());
+        assert!("split=10".parse::<Mix>().is_err());
+        assert!("swap=0".parse::<Mix>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pick_respects_weights() -> anyhow::Result<()> {
+        let mix = "swap=3,melt=1,mint=0".parse::<Mix>()?;
+        let mut rng = StdRng::seed_from_u64(42);
+        let picks = (0..4_000).map(|_| mix.pick(&mut rng)).collect::<Vec<_>>();
+
+        let swaps = picks.iter().filter(|op| **op == Op::Swap).count();
+        assert!((2_800..3_200).contains(&swaps), "{swaps} swaps");
+        assert!(!picks.contains(&Op::Mint));
+        Ok(())
+    }
+}